@@ -304,33 +304,41 @@ impl ComputeNode {
         let mut client = config.connect(NoTls)?;
         let pageserver_connect_micros = start_time.elapsed().as_micros() as u64;
 
-        let basebackup_cmd = match lsn {
+        let mut basebackup_cmd = match lsn {
             // HACK We don't use compression on first start (Lsn(0)) because there's no API for it
             Lsn(0) => format!("basebackup {} {}", spec.tenant_id, spec.timeline_id),
             _ => format!(
-                "basebackup {} {} {} --gzip",
+                "basebackup {} {} {} --zstd",
                 spec.tenant_id, spec.timeline_id, lsn
             ),
         };
+        // The compute keeps the catalogs in local files only as a primary, see
+        // `write_postgres_conf`.
+        if spec.spec.mode == ComputeMode::Primary && spec.spec.catalog_basebackup {
+            basebackup_cmd.push_str(" --catalog");
+        }
 
         let copyreader = client.copy_out(basebackup_cmd.as_str())?;
         let mut measured_reader = MeasuredReader::new(copyreader);
 
-        // Check the magic number to see if it's a gzip or not. Even though
-        // we might explicitly ask for gzip, an old pageserver with no implementation
-        // of gzip compression might send us uncompressed data. After some time
-        // passes we can assume all pageservers know how to compress and we can
-        // delete this check.
+        // Check the magic number to see how the data is compressed. Even though
+        // we explicitly ask for zstd, a pageserver that only implements gzip
+        // compression, or none at all, is also allowed to send us gzip or
+        // uncompressed data. After some time passes we can assume all
+        // pageservers know how to compress with zstd and we can delete this check.
         //
-        // If the data is not gzip, it will be tar. It will not be mistakenly
-        // recognized as gzip because tar starts with an ascii encoding of a filename,
-        // and 0x1f and 0x8b are unlikely first characters for any filename. Moreover,
-        // we send the "global" directory first from the pageserver, so it definitely
-        // won't be recognized as gzip.
+        // If the data is not compressed, it will be tar. It will not be mistakenly
+        // recognized as compressed because tar starts with an ascii encoding of a
+        // filename, and neither the gzip nor the zstd magic number are ascii.
+        // Moreover, we send the "global" directory first from the pageserver, so
+        // it definitely won't be recognized as compressed.
         let mut bufreader = std::io::BufReader::new(&mut measured_reader);
-        let gzip = {
-            let peek = bufreader.fill_buf().unwrap();
-            peek[0] == 0x1f && peek[1] == 0x8b
+        let (gzip, zstd) = {
+            let peek = bufreader.fill_buf()?;
+            (
+                peek.starts_with(&[0x1f, 0x8b]),
+                peek.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]),
+            )
         };
 
         // Read the archive directly from the `CopyOutReader`
@@ -342,6 +350,11 @@ impl ComputeNode {
             let mut ar = tar::Archive::new(flate2::read::GzDecoder::new(&mut bufreader));
             ar.set_ignore_zeros(true);
             ar.unpack(&self.pgdata)?;
+        } else if zstd {
+            let mut ar =
+                tar::Archive::new(zstd::stream::read::Decoder::with_buffer(&mut bufreader)?);
+            ar.set_ignore_zeros(true);
+            ar.unpack(&self.pgdata)?;
         } else {
             let mut ar = tar::Archive::new(&mut bufreader);
             ar.set_ignore_zeros(true);
@@ -72,7 +72,11 @@ pub fn write_postgres_conf(
     }
 
     match spec.mode {
-        ComputeMode::Primary => {}
+        ComputeMode::Primary => {
+            if spec.catalog_basebackup {
+                writeln!(file, "neon.catalog_basebackup=on")?;
+            }
+        }
         ComputeMode::Static(lsn) => {
             // hot_standby is 'on' by default, but let's be explicit
             writeln!(file, "hot_standby=on")?;
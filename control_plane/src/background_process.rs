@@ -18,8 +18,8 @@ use std::os::unix::prelude::AsRawFd;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
-use std::time::Duration;
-use std::{fs, io, thread};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io, thread};
 
 use anyhow::Context;
 use nix::errno::Errno;
@@ -136,14 +136,47 @@ where
     anyhow::bail!("{process_name} did not start in {RETRY_UNTIL_SECS} seconds");
 }
 
+/// How [`stop_process`] brought a process down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    /// The process was not running in the first place.
+    NotRunning,
+    /// The process shut down by itself after being asked to.
+    Graceful,
+    /// The process was asked to shut down immediately.
+    Immediate,
+    /// The process did not shut down within the timeout, and was killed.
+    Killed,
+}
+
+impl fmt::Display for StopOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopOutcome::NotRunning => "was not running",
+            StopOutcome::Graceful => "shut down gracefully",
+            StopOutcome::Immediate => "shut down immediately",
+            StopOutcome::Killed => "killed after the shutdown timeout",
+        })
+    }
+}
+
 /// Stops the process, using the pid file given. Returns Ok also if the process is already not running.
-pub fn stop_process(immediate: bool, process_name: &str, pid_file: &Path) -> anyhow::Result<()> {
+///
+/// With a timeout, a process that doesn't shut down gracefully within it is
+/// killed with SIGKILL. Without one, it is waited for for a fixed time, and an
+/// error is returned if it doesn't stop.
+pub fn stop_process(
+    immediate: bool,
+    timeout: Option<Duration>,
+    process_name: &str,
+    pid_file: &Path,
+) -> anyhow::Result<StopOutcome> {
     let pid = match pid_file::read(pid_file)
         .with_context(|| format!("read pid_file {pid_file:?}"))?
     {
         PidFileRead::NotExist => {
             println!("{process_name} is already stopped: no pid file present at {pid_file:?}");
-            return Ok(());
+            return Ok(StopOutcome::NotRunning);
         }
         PidFileRead::NotHeldByAnyProcess(_) => {
             // Don't try to kill according to file contents beacuse the pid might have been re-used by another process.
@@ -152,7 +185,7 @@ pub fn stop_process(immediate: bool, process_name: &str, pid_file: &Path) -> any
             println!(
                 "No process is holding the pidfile. The process must have already exited. Leave in place to avoid race conditions: {pid_file:?}"
             );
-            return Ok(());
+            return Ok(StopOutcome::NotRunning);
         }
         PidFileRead::LockedByOtherProcess(pid) => pid,
     };
@@ -174,14 +207,45 @@ pub fn stop_process(immediate: bool, process_name: &str, pid_file: &Path) -> any
             println!(
                 "{process_name} with pid {pid} does not exist, but a pid file {pid_file:?} was found. Likely the pid got recycled. Lucky we didn't harm anyone."
             );
-            return Ok(());
+            return Ok(StopOutcome::NotRunning);
         }
         Err(e) => anyhow::bail!("Failed to send signal to {process_name} with pid {pid}: {e}"),
     }
 
-    // Wait until process is gone
+    if immediate {
+        wait_until_stopped(process_name, pid)?;
+        return Ok(StopOutcome::Immediate);
+    }
+    let Some(timeout) = timeout else {
+        wait_until_stopped(process_name, pid)?;
+        return Ok(StopOutcome::Graceful);
+    };
+
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        if process_has_stopped(pid)? {
+            println!("\n{process_name} stopped");
+            return Ok(StopOutcome::Graceful);
+        }
+        thread::sleep(Duration::from_millis(RETRY_INTERVAL_MILLIS));
+    }
+    println!(
+        "\n{process_name} did not stop in {} seconds, killing it",
+        timeout.as_secs()
+    );
+    kill_process(process_name, pid)
+}
+
+/// Kills the process with SIGKILL, and waits for it to go away.
+pub fn kill_process(process_name: &str, pid: Pid) -> anyhow::Result<StopOutcome> {
+    match kill(pid, Signal::SIGKILL) {
+        Ok(()) => {}
+        // It stopped by itself in the meantime
+        Err(Errno::ESRCH) => return Ok(StopOutcome::Graceful),
+        Err(e) => anyhow::bail!("Failed to kill {process_name} with pid {pid}: {e}"),
+    }
     wait_until_stopped(process_name, pid)?;
-    Ok(())
+    Ok(StopOutcome::Killed)
 }
 
 pub fn wait_until_stopped(process_name: &str, pid: Pid) -> anyhow::Result<()> {
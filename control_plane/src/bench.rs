@@ -0,0 +1,201 @@
+//! A built-in smoke benchmark, behind `neon_local bench`.
+//!
+//! Runs a short pgbench-like workload against an endpoint and reports the commit
+//! throughput seen by the clients, along with what it cost the storage: the
+//! GetPage@LSN latency measured by the pageserver, and the WAL written to the
+//! safekeepers. It is not a replacement for a real benchmark, only a quick way
+//! to spot an order-of-magnitude regression in the storage stack.
+//!
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use postgres::{Client, NoTls};
+use utils::id::{TenantId, TimelineId};
+
+use crate::endpoint::Endpoint;
+use crate::pageserver::PageServerNode;
+use crate::safekeeper::SafekeeperNode;
+use crate::scrape::metric_sum;
+
+/// Number of rows in the accounts table per unit of scale, as in pgbench.
+const ACCOUNTS_PER_SCALE: i32 = 100_000;
+
+pub struct BenchOptions {
+    pub duration: Duration,
+    pub clients: usize,
+    pub scale: u32,
+}
+
+pub struct BenchReport {
+    pub transactions: u64,
+    pub elapsed: Duration,
+    /// Number of GetPage@LSN requests served for the timeline during the run,
+    /// and the time the pageserver spent on them.
+    pub getpage_requests: u64,
+    pub getpage_seconds: f64,
+    /// WAL written by all the safekeepers during the run.
+    pub safekeeper_wal_bytes: u64,
+}
+
+impl BenchReport {
+    pub fn transactions_per_second(&self) -> f64 {
+        self.transactions as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn avg_getpage_latency(&self) -> Option<Duration> {
+        if self.getpage_requests == 0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            self.getpage_seconds / self.getpage_requests as f64,
+        ))
+    }
+}
+
+/// Storage-side counters, scraped from the metrics endpoints.
+struct StorageCounters {
+    getpage_count: f64,
+    getpage_sum: f64,
+    wal_bytes: f64,
+}
+
+impl StorageCounters {
+    fn scrape(
+        pageserver: &PageServerNode,
+        safekeepers: &[SafekeeperNode],
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> anyhow::Result<Self> {
+        let metrics = pageserver
+            .metrics()
+            .context("failed to scrape pageserver metrics")?;
+        let tenant_id = tenant_id.to_string();
+        let timeline_id = timeline_id.to_string();
+        let labels = [
+            ("smgr_query_type", "get_page_at_lsn"),
+            ("tenant_id", tenant_id.as_str()),
+            ("timeline_id", timeline_id.as_str()),
+        ];
+        let getpage_count = metric_sum(&metrics, "pageserver_smgr_query_seconds_count", &labels);
+        let getpage_sum = metric_sum(&metrics, "pageserver_smgr_query_seconds_sum", &labels);
+
+        let mut wal_bytes = 0.0;
+        for sk in safekeepers {
+            let metrics = sk
+                .metrics()
+                .with_context(|| format!("failed to scrape safekeeper {} metrics", sk.id))?;
+            wal_bytes += metric_sum(&metrics, "safekeeper_write_wal_bytes_sum", &[]);
+        }
+
+        Ok(StorageCounters {
+            getpage_count,
+            getpage_sum,
+            wal_bytes,
+        })
+    }
+}
+
+/// Runs the benchmark against a running endpoint.
+pub fn run(
+    endpoint: &Endpoint,
+    pageserver: &PageServerNode,
+    safekeepers: &[SafekeeperNode],
+    options: &BenchOptions,
+) -> anyhow::Result<BenchReport> {
+    let accounts = ACCOUNTS_PER_SCALE
+        .checked_mul(options.scale as i32)
+        .context("scale is too large")?;
+
+    let mut client = Client::connect(&endpoint.connstr(), NoTls)
+        .with_context(|| format!("failed to connect to {}", endpoint.connstr()))?;
+    client
+        .batch_execute(&format!(
+            "DROP TABLE IF EXISTS neon_bench_accounts, neon_bench_history;
+             CREATE TABLE neon_bench_accounts (aid int PRIMARY KEY, abalance int NOT NULL DEFAULT 0, filler char(84));
+             CREATE TABLE neon_bench_history (aid int, delta int, mtime timestamp);
+             INSERT INTO neon_bench_accounts (aid) SELECT generate_series(1, {accounts});
+             VACUUM ANALYZE neon_bench_accounts;"
+        ))
+        .context("failed to initialize the benchmark tables")?;
+
+    let before = StorageCounters::scrape(
+        pageserver,
+        safekeepers,
+        endpoint.tenant_id,
+        endpoint.timeline_id,
+    )?;
+
+    let started = Instant::now();
+    let deadline = started + options.duration;
+    let transactions = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.clients)
+            .map(|i| scope.spawn(move || run_client(endpoint, i as u64, accounts, deadline)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("benchmark client panicked"))
+            .sum::<anyhow::Result<u64>>()
+    })?;
+    let elapsed = started.elapsed();
+
+    let after = StorageCounters::scrape(
+        pageserver,
+        safekeepers,
+        endpoint.tenant_id,
+        endpoint.timeline_id,
+    )?;
+
+    Ok(BenchReport {
+        transactions,
+        elapsed,
+        getpage_requests: (after.getpage_count - before.getpage_count) as u64,
+        getpage_seconds: after.getpage_sum - before.getpage_sum,
+        safekeeper_wal_bytes: (after.wal_bytes - before.wal_bytes) as u64,
+    })
+}
+
+/// Runs TPC-B like transactions until the deadline, returning the number of
+/// committed ones.
+fn run_client(
+    endpoint: &Endpoint,
+    client_no: u64,
+    accounts: i32,
+    deadline: Instant,
+) -> anyhow::Result<u64> {
+    let mut client = Client::connect(&endpoint.connstr(), NoTls)?;
+    let update =
+        client.prepare("UPDATE neon_bench_accounts SET abalance = abalance + $2 WHERE aid = $1")?;
+    let select = client.prepare("SELECT abalance FROM neon_bench_accounts WHERE aid = $1")?;
+    let insert = client.prepare(
+        "INSERT INTO neon_bench_history (aid, delta, mtime) VALUES ($1, $2, CURRENT_TIMESTAMP)",
+    )?;
+
+    // A xorshift generator is plenty to spread the updates over the table.
+    let mut state = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+        ^ (client_no + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        | 1;
+    let mut next_random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut committed = 0;
+    while Instant::now() < deadline {
+        let aid = (next_random() % accounts as u64) as i32 + 1;
+        let delta = (next_random() % 10001) as i32 - 5000;
+
+        let mut tx = client.transaction()?;
+        tx.execute(&update, &[&aid, &delta])?;
+        tx.query_one(&select, &[&aid])?;
+        tx.execute(&insert, &[&aid, &delta])?;
+        tx.commit()?;
+        committed += 1;
+    }
+    Ok(committed)
+}
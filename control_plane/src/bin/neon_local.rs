@@ -8,12 +8,21 @@
 use anyhow::{anyhow, bail, Context, Result};
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
 use compute_api::spec::ComputeMode;
-use control_plane::endpoint::ComputeControlPlane;
+use control_plane::bench::{self, BenchOptions};
+use control_plane::doctor;
+use control_plane::endpoint::{ComputeControlPlane, Endpoint};
+use control_plane::error::{categorize, CategorizeExt, ErrorCategory};
 use control_plane::local_env::LocalEnv;
 use control_plane::pageserver::PageServerNode;
+use control_plane::progress::report_while;
+use control_plane::region_spec::RegionSpec;
 use control_plane::safekeeper::SafekeeperNode;
+use control_plane::safekeeper_membership;
+use control_plane::safekeeper_sync::{self, SyncState};
+use control_plane::tenant_migration;
+use control_plane::watch::{watch, Listing};
 use control_plane::{broker, local_env};
-use pageserver_api::models::TimelineInfo;
+use pageserver_api::models::{TenantInfo, TimelineInfo};
 use pageserver_api::{
     DEFAULT_HTTP_LISTEN_ADDR as DEFAULT_PAGESERVER_HTTP_ADDR,
     DEFAULT_PG_LISTEN_ADDR as DEFAULT_PAGESERVER_PG_ADDR,
@@ -27,6 +36,8 @@ use std::collections::{BTreeSet, HashMap};
 use std::path::PathBuf;
 use std::process::exit;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use storage_broker::DEFAULT_LISTEN_ADDR as DEFAULT_BROKER_ADDR;
 use utils::{
     auth::{Claims, Scope},
@@ -44,6 +55,8 @@ project_git_version!(GIT_VERSION);
 
 const DEFAULT_PG_VERSION: &str = "15";
 
+const DEFAULT_WAIT_LSN_TIMEOUT: Duration = Duration::from_secs(60);
+
 fn default_conf() -> String {
     format!(
         r#"
@@ -51,7 +64,7 @@ fn default_conf() -> String {
 [broker]
 listen_addr = '{DEFAULT_BROKER_ADDR}'
 
-[pageserver]
+[[pageservers]]
 id = {DEFAULT_PAGESERVER_ID}
 listen_pg_addr = '{DEFAULT_PAGESERVER_PG_ADDR}'
 listen_http_addr = '{DEFAULT_PAGESERVER_HTTP_ADDR}'
@@ -97,51 +110,60 @@ fn main() -> Result<()> {
         None => bail!("no subcommand provided"),
     };
 
-    // Check for 'neon init' command first.
-    let subcommand_result = if sub_name == "init" {
-        handle_init(sub_args).map(Some)
-    } else {
-        // all other commands need an existing config
-        let mut env = LocalEnv::load_config().context("Error loading config")?;
-        let original_env = env.clone();
-
-        let subcommand_result = match sub_name {
-            "tenant" => handle_tenant(sub_args, &mut env),
-            "timeline" => handle_timeline(sub_args, &mut env),
-            "start" => handle_start_all(sub_args, &env),
-            "stop" => handle_stop_all(sub_args, &env),
-            "pageserver" => handle_pageserver(sub_args, &env),
-            "safekeeper" => handle_safekeeper(sub_args, &env),
-            "endpoint" => handle_endpoint(sub_args, &env),
-            "pg" => bail!("'pg' subcommand has been renamed to 'endpoint'"),
-            _ => bail!("unexpected subcommand {sub_name}"),
-        };
-
-        if original_env != env {
-            subcommand_result.map(|()| Some(env))
-        } else {
-            subcommand_result.map(|()| None)
-        }
-    };
-
-    match subcommand_result {
+    match run_subcommand(sub_name, sub_args) {
         Ok(Some(updated_env)) => updated_env.persist_config(&updated_env.base_data_dir)?,
         Ok(None) => (),
         Err(e) => {
             eprintln!("command failed: {e:?}");
-            exit(1);
+            exit(ErrorCategory::of(&e).exit_code());
         }
     }
     Ok(())
 }
 
+/// Runs a subcommand, returning the updated config if it changed.
+fn run_subcommand(sub_name: &str, sub_args: &ArgMatches) -> Result<Option<LocalEnv>> {
+    // Check for 'neon init' command first.
+    if sub_name == "init" {
+        return handle_init(sub_args).map(Some);
+    }
+
+    // all other commands need an existing config
+    let mut env = LocalEnv::load_config()
+        .context("Error loading config")
+        .categorize(ErrorCategory::Config)?;
+    let original_env = env.clone();
+
+    match sub_name {
+        "tenant" => handle_tenant(sub_args, &mut env),
+        "timeline" => handle_timeline(sub_args, &mut env),
+        "start" => handle_start_all(sub_args, &env),
+        "stop" => handle_stop_all(sub_args, &env),
+        "pageserver" => handle_pageserver(sub_args, &env),
+        "safekeeper" => handle_safekeeper(sub_args, &env),
+        "endpoint" => handle_endpoint(sub_args, &env),
+        "bench" => handle_bench(sub_args, &env),
+        "doctor" => handle_doctor(sub_args, &env),
+        "auth" => handle_auth(sub_args, &mut env),
+        "pg" => bail!("'pg' subcommand has been renamed to 'endpoint'"),
+        _ => bail!("unexpected subcommand {sub_name}"),
+    }?;
+
+    if original_env != env {
+        Ok(Some(env))
+    } else {
+        Ok(None)
+    }
+}
+
 ///
-/// Prints timelines list as a tree-like structure.
+/// Lists timelines as a tree-like structure, with the last LSN and the sizes of
+/// each timeline and the time WAL was last received for it in separate columns.
 ///
-fn print_timelines_tree(
+fn timelines_tree_listing(
     timelines: Vec<TimelineInfo>,
     mut timeline_name_mappings: HashMap<TenantTimelineId, String>,
-) -> Result<()> {
+) -> Result<Listing> {
     let mut timelines_hash = timelines
         .iter()
         .map(|t| {
@@ -168,25 +190,39 @@ fn print_timelines_tree(
         }
     }
 
+    let mut rows = Vec::new();
     for timeline in timelines_hash.values() {
         // Start with root local timelines (no ancestors) first.
         if timeline.info.ancestor_timeline_id.is_none() {
-            print_timeline(0, &Vec::from([true]), timeline, &timelines_hash)?;
+            add_timeline_rows(&mut rows, 0, &Vec::from([true]), timeline, &timelines_hash)?;
         }
     }
 
-    Ok(())
+    Ok(Listing {
+        header: vec![
+            "TIMELINE",
+            "LSN",
+            "LOGICAL SIZE",
+            "PHYSICAL SIZE",
+            "ON DISK",
+            "LAST WAL RECEIVED",
+        ],
+        rows,
+        watched_columns: vec![1, 2],
+    })
 }
 
 ///
-/// Recursively prints timeline info with all its children.
+/// Recursively adds table rows for a timeline and all its children.
 ///
-fn print_timeline(
+fn add_timeline_rows(
+    rows: &mut Vec<Vec<String>>,
     nesting_level: usize,
     is_last: &[bool],
     timeline: &TimelineTreeEl,
     timelines: &HashMap<TimelineId, TimelineTreeEl>,
 ) -> Result<()> {
+    let mut tree_prefix = String::new();
     if nesting_level > 0 {
         let ancestor_lsn = match timeline.info.ancestor_lsn {
             Some(lsn) => lsn.to_string(),
@@ -200,9 +236,9 @@ fn print_timeline(
         if nesting_level > 1 {
             for l in &is_last[1..is_last.len() - 1] {
                 if *l {
-                    print!("   ");
+                    tree_prefix.push_str("   ");
                 } else {
-                    print!("┃  ");
+                    tree_prefix.push_str("┃  ");
                 }
             }
         }
@@ -212,15 +248,23 @@ fn print_timeline(
             br_sym = "┗━";
         }
 
-        print!("{} @{}: ", br_sym, ancestor_lsn);
+        tree_prefix.push_str(&format!("{} @{}: ", br_sym, ancestor_lsn));
     }
 
-    // Finally print a timeline id and name with new line
-    println!(
-        "{} [{}]",
-        timeline.name.as_deref().unwrap_or("_no_name_"),
-        timeline.info.timeline_id
-    );
+    // Finally add a timeline id and name, followed by its sizes
+    let info = &timeline.info;
+    rows.push(vec![
+        format!(
+            "{tree_prefix}{} [{}]",
+            timeline.name.as_deref().unwrap_or("_no_name_"),
+            info.timeline_id
+        ),
+        info.last_record_lsn.to_string(),
+        format_logical_size(info),
+        format_size(info.current_physical_size),
+        format_size(info.resident_physical_size),
+        format_age(info.last_received_msg_ts),
+    ]);
 
     let len = timeline.children.len();
     let mut i: usize = 0;
@@ -237,7 +281,8 @@ fn print_timeline(
             }
         }
 
-        print_timeline(
+        add_timeline_rows(
+            rows,
             nesting_level + 1,
             &is_last_new,
             timelines
@@ -250,13 +295,226 @@ fn print_timeline(
     Ok(())
 }
 
+///
+/// Prints the LSNs, layers and WAL ingestion state of a timeline.
+///
+fn print_timeline_detail(info: &TimelineInfo, env: &local_env::LocalEnv) {
+    let names = env.timeline_name_mappings();
+    let name = |timeline_id| {
+        names
+            .get(&TenantTimelineId::new(info.tenant_id, timeline_id))
+            .map(String::as_str)
+            .unwrap_or("_no_name_")
+    };
+    let lsn = |lsn: Option<Lsn>| lsn.map_or_else(|| "-".to_string(), |lsn| lsn.to_string());
+
+    println!(
+        "timeline:              {} [{}]",
+        name(info.timeline_id),
+        info.timeline_id
+    );
+    println!("region:                {}", info.region_id);
+    println!("state:                 {:?}", info.state);
+    match info.ancestor_timeline_id {
+        Some(ancestor_id) => println!(
+            "ancestor:              {} [{ancestor_id}] @{}",
+            name(ancestor_id),
+            lsn(info.ancestor_lsn)
+        ),
+        None => println!("ancestor:              -"),
+    }
+    println!("last record lsn:       {}", info.last_record_lsn);
+    println!("disk consistent lsn:   {}", info.disk_consistent_lsn);
+    println!("remote consistent lsn: {}", info.remote_consistent_lsn);
+    println!("gc cutoff lsn:         {}", info.latest_gc_cutoff_lsn);
+    println!(
+        "last received lsn:     {} ({})",
+        lsn(info.last_received_msg_lsn),
+        format_age(info.last_received_msg_ts)
+    );
+    println!(
+        "wal source:            {}",
+        info.wal_source_connstr.as_deref().unwrap_or("-")
+    );
+    println!("logical size:          {}", format_logical_size(info));
+    println!(
+        "physical size:         {}",
+        format_size(info.current_physical_size)
+    );
+    println!(
+        "resident size:         {}{}",
+        format_size(info.resident_physical_size),
+        match (info.resident_physical_size, info.current_physical_size) {
+            (Some(resident), Some(physical)) if physical > 0 => format!(
+                " ({:.0}% of physical)",
+                resident as f64 * 100.0 / physical as f64
+            ),
+            _ => String::new(),
+        }
+    );
+    match &info.layers {
+        Some(layers) => {
+            println!(
+                "layers:                {} delta, {} image, {} evicted",
+                layers.delta_layers, layers.image_layers, layers.evicted_layers
+            );
+            println!(
+                "in-memory layers:      {} ({})",
+                layers.in_memory_layers,
+                format_size(Some(layers.in_memory_size))
+            );
+        }
+        None => println!("layers:                ?"),
+    }
+}
+
+///
+/// Prints the chain of ancestors of a timeline, from the timeline itself up to
+/// the root, followed by its direct children.
+///
+fn print_timeline_ancestry(
+    pageserver: &PageServerNode,
+    tenant_id: TenantId,
+    timeline_id: TimelineId,
+    env: &local_env::LocalEnv,
+) -> Result<()> {
+    let names = env.timeline_name_mappings();
+    let add_row = |table: &mut comfy_table::Table, info: &TimelineInfo| {
+        table.add_row([
+            names
+                .get(&TenantTimelineId::new(tenant_id, info.timeline_id))
+                .map(String::as_str)
+                .unwrap_or("_no_name_")
+                .to_string(),
+            info.timeline_id.to_string(),
+            info.ancestor_lsn
+                .map(|lsn| lsn.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format_created_at(info.created_at),
+        ]);
+    };
+    let new_table = || {
+        let mut table = comfy_table::Table::new();
+        table.load_preset(comfy_table::presets::NOTHING);
+        table.set_header(["BRANCH", "TIMELINE", "BRANCHED AT", "CREATED"]);
+        table
+    };
+
+    let mut ancestry = new_table();
+    let mut next = Some(timeline_id);
+    while let Some(timeline_id) = next {
+        let info = pageserver.timeline_detail(tenant_id, timeline_id)?;
+        add_row(&mut ancestry, &info);
+        next = info.ancestor_timeline_id;
+    }
+    println!("Ancestry:\n{ancestry}");
+
+    let children: Vec<_> = pageserver
+        .timeline_list(&tenant_id)?
+        .into_iter()
+        .filter(|info| info.ancestor_timeline_id == Some(timeline_id))
+        .collect();
+    if children.is_empty() {
+        println!("\nNo children");
+    } else {
+        let mut table = new_table();
+        for info in &children {
+            add_row(&mut table, info);
+        }
+        println!("\nChildren:\n{table}");
+    }
+
+    Ok(())
+}
+
+fn format_created_at(created_at: Option<std::time::SystemTime>) -> String {
+    match created_at.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok()) {
+        Some(since_epoch) => format_age(Some(since_epoch.as_micros())),
+        None => "?".to_string(),
+    }
+}
+
+/// Formats a size in bytes with a binary unit suffix, or '?' if it is not known.
+fn format_size(size: Option<u64>) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let Some(size) = size else {
+        return "?".to_string();
+    };
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Formats the logical size of a timeline, with a '~' if it is still being
+/// calculated.
+fn format_logical_size(info: &TimelineInfo) -> String {
+    let size = format_size(info.current_logical_size);
+    if info.current_logical_size.is_some() && !info.current_logical_size_is_exact {
+        format!("~{size}")
+    } else {
+        size
+    }
+}
+
+/// Formats a timestamp as time elapsed since then, or 'never'.
+fn format_timestamp_age(timestamp: Option<std::time::SystemTime>) -> String {
+    format_age(
+        timestamp
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_micros()),
+    )
+}
+
+/// Formats the time elapsed since a process was launched, or '?' if it is not known.
+fn format_uptime(launched_at: Option<std::time::SystemTime>) -> String {
+    let Some(uptime) = launched_at.and_then(|t| t.elapsed().ok()) else {
+        return "?".to_string();
+    };
+    let secs = uptime.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{days}d {hours}h {mins}m")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else {
+        format!("{mins}m {}s", secs % 60)
+    }
+}
+
+/// Formats a timestamp in microseconds since the epoch as time elapsed since then.
+fn format_age(timestamp_micros: Option<u128>) -> String {
+    let Some(timestamp_micros) = timestamp_micros else {
+        return "never".to_string();
+    };
+    let now_micros = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros())
+        .unwrap_or(0);
+    let secs = now_micros.saturating_sub(timestamp_micros) / 1_000_000;
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
 /// Returns a map of timeline IDs to timeline_id@lsn strings.
 /// Connects to the pageserver to query this information.
 fn get_timeline_infos(
-    env: &local_env::LocalEnv,
+    pageserver: &PageServerNode,
     tenant_id: &TenantId,
 ) -> Result<HashMap<TimelineId, TimelineInfo>> {
-    Ok(PageServerNode::from_env(env)
+    Ok(pageserver
         .timeline_list(tenant_id)?
         .into_iter()
         .map(|timeline_info| (timeline_info.timeline_id, timeline_info))
@@ -270,7 +528,10 @@ fn get_tenant_id(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> anyhow::R
     } else if let Some(default_id) = env.default_tenant_id {
         Ok(default_id)
     } else {
-        anyhow::bail!("No tenant id. Use --tenant-id, or set a default tenant");
+        Err(categorize(
+            ErrorCategory::Config,
+            anyhow!("No tenant id. Use --tenant-id, or set a default tenant"),
+        ))
     }
 }
 
@@ -290,16 +551,62 @@ fn parse_timeline_id(sub_match: &ArgMatches) -> anyhow::Result<Option<TimelineId
         .context("Failed to parse timeline id from the argument string")
 }
 
+/// Resolves the branch an endpoint runs on, and the region it runs in.
+///
+/// With `--region`, the region is looked up in the region catalog of the config,
+/// and its first branch is used unless `--branch-name` is also given. `--regions`
+/// works the same way, using the region marked as current in the spec. Without
+/// either, the region is the one the branch was registered with.
+fn get_endpoint_branch(
+    sub_match: &ArgMatches,
+    env: &local_env::LocalEnv,
+    tenant_id: TenantId,
+) -> anyhow::Result<(String, TimelineId, RegionId)> {
+    let region = if let Some(region_spec) = sub_match.get_one::<RegionSpec>("regions") {
+        env.get_region(region_spec.current_region_id())
+    } else {
+        sub_match
+            .get_one::<String>("region")
+            .map(|name| env.get_region_by_name(name))
+            .transpose()
+            .categorize(ErrorCategory::Config)?
+    };
+
+    let branch_name = match (sub_match.get_one::<String>("branch-name"), region) {
+        (Some(branch_name), _) => branch_name.clone(),
+        (None, Some(region)) => region
+            .branches
+            .first()
+            .cloned()
+            .with_context(|| format!("region '{}' has no branches configured", region.name))?,
+        (None, None) => DEFAULT_BRANCH_NAME.to_string(),
+    };
+
+    let (timeline_id, branch_region_id) = env
+        .get_branch_timeline_id(&branch_name, tenant_id)
+        .ok_or_else(|| {
+            categorize(
+                ErrorCategory::NotFound,
+                anyhow!("Found no timeline id for branch name '{branch_name}'"),
+            )
+        })?;
+
+    let region_id = region.map(|region| region.id).unwrap_or(branch_region_id);
+    Ok((branch_name, timeline_id, region_id))
+}
+
 fn handle_init(init_match: &ArgMatches) -> anyhow::Result<LocalEnv> {
     // Create config file
     let toml_file: String = if let Some(config_path) = init_match.get_one::<PathBuf>("config") {
         // load and parse the file
-        std::fs::read_to_string(config_path).with_context(|| {
-            format!(
-                "Could not read configuration file '{}'",
-                config_path.display()
-            )
-        })?
+        std::fs::read_to_string(config_path)
+            .with_context(|| {
+                format!(
+                    "Could not read configuration file '{}'",
+                    config_path.display()
+                )
+            })
+            .categorize(ErrorCategory::Config)?
     } else {
         // Built-in default config
         default_conf()
@@ -310,24 +617,104 @@ fn handle_init(init_match: &ArgMatches) -> anyhow::Result<LocalEnv> {
         .copied()
         .context("Failed to parse postgres version from the argument string")?;
 
-    let mut env =
-        LocalEnv::parse_config(&toml_file).context("Failed to create neon configuration")?;
+    // Check the basebackup to import before wiping anything with --force.
+    let import = match init_match.get_one::<PathBuf>("import-basebackup") {
+        Some(tarfile) => {
+            if !tarfile.is_file() {
+                return Err(categorize(
+                    ErrorCategory::NotFound,
+                    anyhow!("basebackup tarball '{}' does not exist", tarfile.display()),
+                ));
+            }
+            let base_lsn = init_match
+                .get_one::<String>("base-lsn")
+                .map(|lsn| Lsn::from_str(lsn))
+                .transpose()
+                .context("Failed to parse base Lsn")?
+                .context("--base-lsn is required to import a basebackup")?;
+            Some((base_lsn, tarfile.canonicalize()?))
+        }
+        None => None,
+    };
+
+    let mut env = LocalEnv::parse_config(&toml_file)
+        .context("Failed to create neon configuration")
+        .categorize(ErrorCategory::Config)?;
     let force = init_match.get_flag("force");
     env.init(pg_version, force)
         .context("Failed to initialize neon repository")?;
 
-    // Initialize pageserver, create initial tenant and timeline.
-    let pageserver = PageServerNode::from_env(&env);
-    pageserver
-        .initialize(&pageserver_config_overrides(init_match))
-        .unwrap_or_else(|e| {
-            eprintln!("pageserver init failed: {e:?}");
-            exit(1);
-        });
+    // Initialize pageservers, create initial tenant and timeline.
+    for conf in &env.pageservers {
+        PageServerNode::from_env(&env, conf)
+            .initialize(&pageserver_config_overrides(init_match))
+            .unwrap_or_else(|e| {
+                eprintln!("pageserver {} init failed: {e:?}", conf.id);
+                exit(ErrorCategory::of(&e).exit_code());
+            });
+    }
+
+    if let Some(base) = import {
+        let pageserver = get_pageserver(&env, init_match)?;
+        let (tenant_id, timeline_id) = pageserver.bootstrap_from_basebackup(
+            &pageserver_config_overrides(init_match),
+            base,
+            pg_version,
+        )?;
+        env.register_branch_mapping(
+            DEFAULT_BRANCH_NAME.to_string(),
+            tenant_id,
+            timeline_id,
+            RegionId::default(),
+        )?;
+        env.default_tenant_id = Some(tenant_id);
+        println!(
+            "Imported the basebackup into timeline {timeline_id} of tenant {tenant_id}, set as the default tenant"
+        );
+    }
 
     Ok(env)
 }
 
+/// Returns the pageserver selected with --pageserver-id, or the first one if
+/// the command has no such argument or it is not given.
+fn get_pageserver(env: &local_env::LocalEnv, args: &ArgMatches) -> Result<PageServerNode> {
+    Ok(PageServerNode::from_env(
+        env,
+        env.get_pageserver_conf(parse_pageserver_id(args))?,
+    ))
+}
+
+/// Gets the timeline from `--timeline-id`, or else from the "branch-name" argument.
+fn get_timeline_or_branch_id(
+    args: &ArgMatches,
+    tenant_id: TenantId,
+    env: &local_env::LocalEnv,
+) -> anyhow::Result<TimelineId> {
+    if let Some(timeline_id) = parse_timeline_id(args)? {
+        return Ok(timeline_id);
+    }
+    let branch_name = args
+        .get_one::<String>("branch-name")
+        .ok_or_else(|| anyhow!("No branch name or timeline id provided"))?;
+    let (timeline_id, _) = env
+        .get_branch_timeline_id(branch_name, tenant_id)
+        .ok_or_else(|| {
+            categorize(
+                ErrorCategory::NotFound,
+                anyhow!("Found no timeline id for branch name '{branch_name}'"),
+            )
+        })?;
+    Ok(timeline_id)
+}
+
+fn parse_pageserver_id(args: &ArgMatches) -> Option<NodeId> {
+    args.try_get_one::<u64>("pageserver-id")
+        .ok()
+        .flatten()
+        .map(|id| NodeId(*id))
+}
+
 fn pageserver_config_overrides(init_match: &ArgMatches) -> Vec<&str> {
     init_match
         .get_many::<String>("pageserver-config-override")
@@ -338,12 +725,52 @@ fn pageserver_config_overrides(init_match: &ArgMatches) -> Vec<&str> {
 }
 
 fn handle_tenant(tenant_match: &ArgMatches, env: &mut local_env::LocalEnv) -> anyhow::Result<()> {
-    let pageserver = PageServerNode::from_env(env);
+    let pageserver = match tenant_match.subcommand() {
+        Some((_, args)) => get_pageserver(env, args)?,
+        None => bail!("no tenant subcommand provided"),
+    };
     match tenant_match.subcommand() {
         Some(("list", _)) => {
+            let mut table = comfy_table::Table::new();
+            table.load_preset(comfy_table::presets::NOTHING);
+            table.set_header([
+                "TENANT",
+                "STATE",
+                "TIMELINES",
+                "PHYSICAL SIZE",
+                "WAL INGEST LAG",
+            ]);
+
             for t in pageserver.tenant_list()? {
-                println!("{} {:?}", t.id, t.state);
+                // The resource usage is only reported per tenant. Tenants that
+                // are not active can't report it, so they are listed without.
+                let status = pageserver.tenant_status(t.id).ok();
+                table.add_row([
+                    t.id.to_string(),
+                    format!("{:?}", t.state),
+                    status
+                        .as_ref()
+                        .and_then(|s| s.timeline_count)
+                        .map(|count| count.to_string())
+                        .unwrap_or_else(|| "?".to_string()),
+                    match status.as_ref().and_then(|s| s.physical_size_quota_exceeded) {
+                        Some(true) => format!(
+                            "{} (over quota)",
+                            format_size(status.as_ref().and_then(|s| s.current_physical_size))
+                        ),
+                        _ => format_size(status.as_ref().and_then(|s| s.current_physical_size)),
+                    },
+                    match status {
+                        Some(TenantInfo {
+                            max_wal_ingest_lag: Some(lag),
+                            ..
+                        }) => format_size(Some(lag)),
+                        _ => "-".to_string(),
+                    },
+                ]);
             }
+
+            println!("{table}");
         }
         Some(("create", create_match)) => {
             let initial_tenant_id = parse_tenant_id(create_match)?;
@@ -383,6 +810,51 @@ fn handle_tenant(tenant_match: &ArgMatches, env: &mut local_env::LocalEnv) -> an
                 "Created an initial timeline '{new_timeline_id}' at Lsn {last_record_lsn} for tenant: {new_tenant_id}",
             );
 
+            // Each region of a multi-region tenant gets a timeline of its own,
+            // next to the global one. Computes find the timeline of their region
+            // by its region id.
+            if let Some(region_count) = create_match.get_one::<u8>("region-count").copied() {
+                let mut table = comfy_table::Table::new();
+                table.load_preset(comfy_table::presets::NOTHING);
+                table.set_header(["BRANCH", "REGION", "TIMELINE"]);
+                table.add_row([
+                    DEFAULT_BRANCH_NAME.to_string(),
+                    RegionId::default().to_string(),
+                    new_timeline_id.to_string(),
+                ]);
+
+                for region in 1..=region_count {
+                    let region_id = RegionId(region);
+                    let branch_name = format!("region-{region}");
+                    let timeline_info = pageserver
+                        .timeline_create(
+                            new_tenant_id,
+                            None,
+                            None,
+                            None,
+                            Some(pg_version),
+                            Some(region_id),
+                        )
+                        .with_context(|| {
+                            format!("Failed to create the timeline of region {region_id}")
+                        })?;
+                    env.register_branch_mapping(
+                        branch_name.clone(),
+                        new_tenant_id,
+                        timeline_info.timeline_id,
+                        region_id,
+                    )?;
+                    table.add_row([
+                        branch_name,
+                        region_id.to_string(),
+                        timeline_info.timeline_id.to_string(),
+                    ]);
+                }
+
+                println!("Created a multi-region tenant with {region_count} region(s):");
+                println!("{table}");
+            }
+
             if create_match.get_flag("set-default") {
                 println!("Setting tenant {new_tenant_id} as a default one");
                 env.default_tenant_id = Some(new_tenant_id);
@@ -394,18 +866,140 @@ fn handle_tenant(tenant_match: &ArgMatches, env: &mut local_env::LocalEnv) -> an
             println!("Setting tenant {tenant_id} as a default one");
             env.default_tenant_id = Some(tenant_id);
         }
-        Some(("config", create_match)) => {
-            let tenant_id = get_tenant_id(create_match, env)?;
-            let tenant_conf: HashMap<_, _> = create_match
+        Some(("config", config_match)) => {
+            // `config set` only changes the given overrides, `config` replaces them all.
+            let (is_set, args) = match config_match.subcommand_matches("set") {
+                Some(set_match) => (true, set_match),
+                None => (false, config_match),
+            };
+            let pageserver = get_pageserver(env, args)?;
+            let tenant_id = get_tenant_id(args, env)?;
+            let tenant_conf: HashMap<_, _> = args
                 .get_many::<String>("config")
                 .map(|vals| vals.flat_map(|c| c.split_once(':')).collect())
                 .unwrap_or_default();
 
-            pageserver
-                .tenant_config(tenant_id, tenant_conf)
-                .with_context(|| format!("Tenant config failed for tenant with id {tenant_id}"))?;
+            if is_set {
+                pageserver.tenant_config_set(tenant_id, tenant_conf)
+            } else {
+                pageserver.tenant_config(tenant_id, tenant_conf)
+            }
+            .with_context(|| format!("Tenant config failed for tenant with id {tenant_id}"))?;
             println!("tenant {tenant_id} successfully configured on the pageserver");
         }
+        Some(("detach", detach_match)) => {
+            let tenant_id = get_tenant_id(detach_match, env)?;
+            // The endpoints would lose their pageserver from under them.
+            let cplane = ComputeControlPlane::load(env.clone())?;
+            let running: Vec<_> = cplane
+                .endpoints
+                .iter()
+                .filter(|(_, ep)| {
+                    ep.tenant_id == tenant_id
+                        && ep.pageserver().conf.id == pageserver.conf.id
+                        && ep.status() != "stopped"
+                })
+                .map(|(endpoint_id, _)| endpoint_id.as_str())
+                .collect();
+            if !running.is_empty() {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!(
+                        "tenant {tenant_id} has running endpoints: {}, stop them first",
+                        running.join(", ")
+                    ),
+                ));
+            }
+
+            pageserver.tenant_detach(tenant_id)?;
+            println!(
+                "tenant {tenant_id} detached from pageserver {}, its data is kept in {}",
+                pageserver.conf.id,
+                pageserver.repo_path().display()
+            );
+        }
+        Some(("attach", attach_match)) => {
+            let tenant_id = get_tenant_id(attach_match, env)?;
+            pageserver.tenant_attach(tenant_id)?;
+            println!(
+                "tenant {tenant_id} attached to pageserver {}",
+                pageserver.conf.id
+            );
+        }
+        Some(("migrate", migrate_match)) => {
+            let tenant_id = get_tenant_id(migrate_match, env)?;
+            let destination_id = NodeId(
+                *migrate_match
+                    .get_one::<u64>("to")
+                    .context("No destination pageserver provided")?,
+            );
+            let destination =
+                PageServerNode::from_env(env, env.get_pageserver_conf(Some(destination_id))?);
+            let timeout = migrate_match
+                .get_one::<Duration>("timeout")
+                .copied()
+                .unwrap_or(DEFAULT_WAIT_LSN_TIMEOUT);
+
+            let precopied = report_while("Copying the tenant files", || {
+                tenant_migration::precopy(&pageserver, &destination, tenant_id)
+            })?;
+            println!(
+                "copied {} files ({}) while the tenant stayed on pageserver {}",
+                precopied.files,
+                format_size(Some(precopied.bytes)),
+                pageserver.conf.id
+            );
+
+            // The endpoints only connect to the pageserver they were started with.
+            let mut cplane = ComputeControlPlane::load(env.clone())?;
+            let endpoint_ids: Vec<String> = cplane
+                .endpoints
+                .iter()
+                .filter(|(_, ep)| {
+                    ep.tenant_id == tenant_id && ep.pageserver().conf.id == pageserver.conf.id
+                })
+                .map(|(endpoint_id, _)| endpoint_id.clone())
+                .collect();
+            let downtime_start = Instant::now();
+            let mut running = Vec::new();
+            for endpoint_id in &endpoint_ids {
+                let endpoint = &cplane.endpoints[endpoint_id];
+                if endpoint.status() != "stopped" {
+                    endpoint.stop(false, None)?;
+                    running.push(endpoint_id);
+                }
+            }
+
+            let switched = report_while("Switching the tenant over", || {
+                tenant_migration::switch_over(&pageserver, &destination, tenant_id, timeout)
+            });
+            // If the switch-over failed, the tenant is back where it was.
+            if switched.is_ok() {
+                for endpoint_id in &endpoint_ids {
+                    cplane.set_pageserver(endpoint_id, destination_id)?;
+                }
+            }
+            for endpoint_id in running {
+                let endpoint = &cplane.endpoints[endpoint_id];
+                let auth_token =
+                    if matches!(endpoint.pageserver().conf.pg_auth_type, AuthType::NeonJWT) {
+                        let claims = Claims::new(Some(tenant_id), Scope::Tenant);
+                        Some(env.generate_auth_token(&claims)?)
+                    } else {
+                        None
+                    };
+                endpoint
+                    .start(&auth_token, endpoint.safekeepers(), None, None)
+                    .with_context(|| format!("Failed to restart endpoint {endpoint_id}"))?;
+            }
+            let copied = switched?;
+            println!(
+                "tenant {tenant_id} moved to pageserver {destination_id}, copying {} more files ({}) in a downtime of {:.1}s",
+                copied.files,
+                format_size(Some(copied.bytes)),
+                downtime_start.elapsed().as_secs_f64()
+            );
+        }
         Some((sub_name, _)) => bail!("Unexpected tenant subcommand '{}'", sub_name),
         None => bail!("no tenant subcommand provided"),
     }
@@ -413,13 +1007,22 @@ fn handle_tenant(tenant_match: &ArgMatches, env: &mut local_env::LocalEnv) -> an
 }
 
 fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::LocalEnv) -> Result<()> {
-    let pageserver = PageServerNode::from_env(env);
+    let pageserver = match timeline_match.subcommand() {
+        Some((_, args)) => get_pageserver(env, args)?,
+        None => bail!("no timeline subcommand provided"),
+    };
 
     match timeline_match.subcommand() {
         Some(("list", list_match)) => {
             let tenant_id = get_tenant_id(list_match, env)?;
-            let timelines = pageserver.timeline_list(&tenant_id)?;
-            print_timelines_tree(timelines, env.timeline_name_mappings())?;
+            let get_listing = || {
+                let timelines = pageserver.timeline_list(&tenant_id)?;
+                timelines_tree_listing(timelines, env.timeline_name_mappings())
+            };
+            match parse_watch_interval(list_match) {
+                Some(interval) => watch("neon_local timeline list", interval, get_listing),
+                None => println!("{}", get_listing()?.render(None)),
+            }
         }
         Some(("create", create_match)) => {
             let tenant_id = get_tenant_id(create_match, env)?;
@@ -511,6 +1114,8 @@ fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::LocalEnv) -
                 pg_version,
                 ComputeMode::Primary,
                 RegionId::default(),
+                None,
+                Some(pageserver.conf.id),
             )?;
             println!("Done");
         }
@@ -530,14 +1135,24 @@ fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::LocalEnv) -
             let (ancestor_timeline_id, _) = env
                 .get_branch_timeline_id(ancestor_branch_name, tenant_id)
                 .ok_or_else(|| {
-                    anyhow!("Found no timeline id for branch name '{ancestor_branch_name}'")
+                    categorize(
+                        ErrorCategory::NotFound,
+                        anyhow!("Found no timeline id for branch name '{ancestor_branch_name}'"),
+                    )
                 })?;
 
-            let start_lsn = branch_match
-                .get_one::<String>("ancestor-start-lsn")
-                .map(|lsn_str| Lsn::from_str(lsn_str))
-                .transpose()
-                .context("Failed to parse ancestor start Lsn from the request")?;
+            let start_lsn = match branch_match.get_one::<String>("ancestor-start-time") {
+                Some(time) => Some(pageserver.timeline_lsn_by_timestamp(
+                    tenant_id,
+                    ancestor_timeline_id,
+                    parse_point_in_time(time)?,
+                )?),
+                None => branch_match
+                    .get_one::<String>("ancestor-start-lsn")
+                    .map(|lsn_str| Lsn::from_str(lsn_str))
+                    .transpose()
+                    .context("Failed to parse ancestor start Lsn from the request")?,
+            };
             let timeline_info = pageserver.timeline_create(
                 tenant_id,
                 None,
@@ -562,6 +1177,154 @@ fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::LocalEnv) -
                 timeline_info.timeline_id
             );
         }
+        Some(("show", show_match)) => {
+            let tenant_id = get_tenant_id(show_match, env)?;
+            let branch_name = show_match
+                .get_one::<String>("branch-name")
+                .ok_or_else(|| anyhow!("No branch name provided"))?;
+            let (timeline_id, _) = env
+                .get_branch_timeline_id(branch_name, tenant_id)
+                .ok_or_else(|| {
+                    categorize(
+                        ErrorCategory::NotFound,
+                        anyhow!("Found no timeline id for branch name '{branch_name}'"),
+                    )
+                })?;
+            print_timeline_ancestry(&pageserver, tenant_id, timeline_id, env)?;
+        }
+        Some(("detail", detail_match)) => {
+            let tenant_id = get_tenant_id(detail_match, env)?;
+            let timeline_id = get_timeline_or_branch_id(detail_match, tenant_id, env)?;
+            let info = pageserver.timeline_detail(tenant_id, timeline_id)?;
+            print_timeline_detail(&info, env);
+        }
+        Some(("compact", compact_match)) => {
+            let tenant_id = get_tenant_id(compact_match, env)?;
+            let timeline_id = get_timeline_or_branch_id(compact_match, tenant_id, env)?;
+            let layers_before = pageserver.timeline_detail(tenant_id, timeline_id)?.layers;
+            pageserver.timeline_compact(tenant_id, timeline_id)?;
+            let layers_after = pageserver.timeline_detail(tenant_id, timeline_id)?.layers;
+            match (layers_before, layers_after) {
+                (Some(before), Some(after)) => println!(
+                    "timeline {timeline_id} compacted: {} delta and {} image layers, from {} and {}",
+                    after.delta_layers, after.image_layers, before.delta_layers, before.image_layers
+                ),
+                _ => println!("timeline {timeline_id} compacted"),
+            }
+        }
+        Some(("wait-lsn", wait_match)) => {
+            let tenant_id = get_tenant_id(wait_match, env)?;
+            let timeline_id = get_timeline_or_branch_id(wait_match, tenant_id, env)?;
+            let lsn = wait_match
+                .get_one::<String>("lsn")
+                .map(|lsn_str| Lsn::from_str(lsn_str))
+                .transpose()
+                .context("Failed to parse Lsn from the request")?
+                .ok_or_else(|| anyhow!("No Lsn provided"))?;
+            let timeout = wait_match
+                .get_one::<Duration>("timeout")
+                .copied()
+                .unwrap_or(DEFAULT_WAIT_LSN_TIMEOUT);
+            report_while("Waiting for the pageserver", || {
+                pageserver.timeline_wait_lsn(tenant_id, timeline_id, lsn, timeout)
+            })?;
+            println!("Timeline {timeline_id} ingested and flushed WAL up to Lsn {lsn}");
+        }
+        Some(("truncate", truncate_match)) => {
+            let tenant_id = get_tenant_id(truncate_match, env)?;
+            let timeline_id = get_timeline_or_branch_id(truncate_match, tenant_id, env)?;
+            let lsn = truncate_match
+                .get_one::<String>("lsn")
+                .map(|lsn_str| Lsn::from_str(lsn_str))
+                .transpose()
+                .context("Failed to parse Lsn from the request")?
+                .ok_or_else(|| anyhow!("No Lsn provided"))?;
+            let layers_removed = report_while("Truncating timeline", || {
+                pageserver.timeline_truncate(tenant_id, timeline_id, lsn)
+            })?;
+            println!(
+                "Truncated the history of timeline {timeline_id} before Lsn {lsn}, removing {layers_removed} layers"
+            );
+        }
+        Some(("export", export_match)) => {
+            let tenant_id = get_tenant_id(export_match, env)?;
+            let timeline_id = get_timeline_or_branch_id(export_match, tenant_id, env)?;
+            let path = export_match
+                .get_one::<PathBuf>("file")
+                .ok_or_else(|| anyhow!("No archive file provided"))?;
+            let size = report_while("Exporting timeline", || {
+                pageserver.timeline_export(tenant_id, timeline_id, path)
+            })?;
+            println!(
+                "Exported timeline {timeline_id} into {} ({size} bytes)",
+                path.display()
+            );
+        }
+        Some(("import-archive", import_match)) => {
+            let tenant_id = get_tenant_id(import_match, env)?;
+            let timeline_id = parse_timeline_id(import_match)?.unwrap_or_else(TimelineId::generate);
+            let branch_name = import_match
+                .get_one::<String>("branch-name")
+                .ok_or_else(|| anyhow!("No branch name provided"))?;
+            let path = import_match
+                .get_one::<PathBuf>("file")
+                .ok_or_else(|| anyhow!("No archive file provided"))?;
+
+            let timeline_info = pageserver.timeline_import_archive(tenant_id, timeline_id, path)?;
+            env.register_branch_mapping(
+                branch_name.to_string(),
+                tenant_id,
+                timeline_id,
+                timeline_info.region_id,
+            )?;
+            println!(
+                "Imported timeline {timeline_id} at Lsn {} for tenant: {tenant_id}",
+                timeline_info.last_record_lsn
+            );
+        }
+        Some(("rename", rename_match)) => {
+            let tenant_id = get_tenant_id(rename_match, env)?;
+            let old_name = rename_match
+                .get_one::<String>("old-branch-name")
+                .ok_or_else(|| anyhow!("No branch name provided"))?;
+            let new_name = rename_match
+                .get_one::<String>("new-branch-name")
+                .ok_or_else(|| anyhow!("No new branch name provided"))?;
+            let (timeline_id, _) =
+                env.get_branch_timeline_id(old_name, tenant_id)
+                    .ok_or_else(|| {
+                        categorize(
+                            ErrorCategory::NotFound,
+                            anyhow!("Found no timeline id for branch name '{old_name}'"),
+                        )
+                    })?;
+
+            // Endpoints are created from a branch name, and usually named after
+            // it, so don't pull the name from under the ones that are running.
+            let cplane = ComputeControlPlane::load(env.clone())?;
+            let running: Vec<_> = cplane
+                .endpoints
+                .iter()
+                .filter(|(_, ep)| {
+                    ep.tenant_id == tenant_id
+                        && ep.timeline_id == timeline_id
+                        && ep.status() != "stopped"
+                })
+                .map(|(endpoint_id, _)| endpoint_id.as_str())
+                .collect();
+            if !running.is_empty() {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!(
+                        "Cannot rename branch '{old_name}' while endpoints on it are running: {}",
+                        running.join(", ")
+                    ),
+                ));
+            }
+
+            env.rename_branch(tenant_id, old_name, new_name)?;
+            println!("Renamed branch '{old_name}' to '{new_name}' (timeline {timeline_id})");
+        }
         Some((sub_name, _)) => bail!("Unexpected tenant subcommand '{sub_name}'"),
         None => bail!("no tenant subcommand provided"),
     }
@@ -569,82 +1332,162 @@ fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::LocalEnv) -
     Ok(())
 }
 
-fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
-    let (sub_name, sub_args) = match ep_match.subcommand() {
-        Some(ep_subcommand_data) => ep_subcommand_data,
-        None => bail!("no endpoint subcommand provided"),
+/// Formats the quorum LSN of the endpoint's timeline, the highest LSN flushed
+/// by a majority of the endpoint's safekeepers, and how many of them have
+/// acknowledged the latest commit LSN, e.g. "0/16B5A50 (2/3)". The latest
+/// commit LSN is the highest one known to any of them.
+///
+/// Only shown for endpoints that replicate to more than one safekeeper, or run
+/// in a region other than the global one, where the quorum is of interest.
+fn format_quorum_status(endpoint: &Endpoint, safekeepers: &[SafekeeperNode]) -> String {
+    let endpoint_safekeepers = endpoint.safekeepers();
+    if matches!(endpoint.mode, ComputeMode::Static(_))
+        || (endpoint_safekeepers.len() < 2 && endpoint.region_id() == RegionId::default())
+    {
+        return "-".to_string();
+    }
+
+    let acked: Vec<_> = safekeepers
+        .iter()
+        .filter(|sk| endpoint_safekeepers.contains(&sk.id))
+        .filter_map(|sk| {
+            sk.timeline_acked_lsn(endpoint.tenant_id, endpoint.timeline_id)
+                .ok()
+        })
+        .collect();
+    let Some(commit_lsn) = acked.iter().map(|a| a.commit_lsn).max() else {
+        return "?".to_string();
     };
+    let acked_count = acked.iter().filter(|a| a.flush_lsn >= commit_lsn).count();
+
+    // The safekeepers that didn't answer count as having flushed nothing.
+    let mut flush_lsns: Vec<Lsn> = acked.iter().map(|a| a.flush_lsn).collect();
+    flush_lsns.sort_unstable_by(|a, b| b.cmp(a));
+    let quorum_lsn = flush_lsns
+        .get(endpoint_safekeepers.len() / 2)
+        .map_or_else(|| "?".to_string(), Lsn::to_string);
+    format!(
+        "{quorum_lsn} ({acked_count}/{})",
+        endpoint_safekeepers.len()
+    )
+}
 
-    let mut cplane = ComputeControlPlane::load(env.clone())?;
+fn endpoint_listing(
+    cplane: &ComputeControlPlane,
+    env: &local_env::LocalEnv,
+    tenant_id: TenantId,
+) -> Result<Listing> {
+    // Endpoints of the tenant may be spread over several pageservers
+    let mut timeline_infos = HashMap::new();
 
-    // All subcommands take an optional --tenant-id option
-    let tenant_id = get_tenant_id(sub_args, env)?;
+    let timeline_name_mappings = env.timeline_name_mappings();
 
-    match sub_name {
-        "list" => {
-            let timeline_infos = get_timeline_infos(env, &tenant_id).unwrap_or_else(|e| {
-                eprintln!("Failed to load timeline info: {}", e);
-                HashMap::new()
-            });
+    let safekeepers: Vec<_> = env
+        .safekeepers
+        .iter()
+        .map(|sk| SafekeeperNode::from_env(env, sk))
+        .collect();
 
-            let timeline_name_mappings = env.timeline_name_mappings();
+    let mut rows = Vec::new();
+    for (endpoint_id, endpoint) in cplane
+        .endpoints
+        .iter()
+        .filter(|(_, endpoint)| endpoint.tenant_id == tenant_id)
+    {
+        let lsn_str = match endpoint.mode {
+            ComputeMode::Static(lsn) => {
+                // -> read-only endpoint
+                // Use the node's LSN.
+                lsn.to_string()
+            }
+            _ => {
+                // -> primary endpoint or hot replica
+                // Use the LSN at the end of the timeline.
+                let pageserver = endpoint.pageserver();
+                timeline_infos
+                    .entry(pageserver.conf.id)
+                    .or_insert_with(|| {
+                        get_timeline_infos(pageserver, &tenant_id).unwrap_or_else(|e| {
+                            eprintln!("Failed to load timeline info: {}", e);
+                            HashMap::new()
+                        })
+                    })
+                    .get(&endpoint.timeline_id)
+                    .map(|bi| bi.last_record_lsn.to_string())
+                    .unwrap_or_else(|| "?".to_string())
+            }
+        };
 
-            let mut table = comfy_table::Table::new();
+        let branch_name = timeline_name_mappings
+            .get(&TenantTimelineId::new(tenant_id, endpoint.timeline_id))
+            .map(|name| name.as_str())
+            .unwrap_or("?");
+
+        rows.push(vec![
+            endpoint_id.clone(),
+            endpoint.pg_address.to_string(),
+            endpoint.timeline_id.to_string(),
+            branch_name.to_string(),
+            lsn_str,
+            endpoint.status().to_string(),
+            format_quorum_status(endpoint, &safekeepers),
+        ]);
+    }
 
-            table.load_preset(comfy_table::presets::NOTHING);
+    Ok(Listing {
+        header: vec![
+            "ENDPOINT",
+            "ADDRESS",
+            "TIMELINE",
+            "BRANCH NAME",
+            "LSN",
+            "STATUS",
+            "SAFEKEEPERS",
+        ],
+        rows,
+        watched_columns: vec![4, 5, 6],
+    })
+}
 
-            table.set_header([
-                "ENDPOINT",
-                "ADDRESS",
-                "TIMELINE",
-                "BRANCH NAME",
-                "LSN",
-                "STATUS",
-            ]);
+fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
+    let (sub_name, sub_args) = match ep_match.subcommand() {
+        Some(ep_subcommand_data) => ep_subcommand_data,
+        None => bail!("no endpoint subcommand provided"),
+    };
 
-            for (endpoint_id, endpoint) in cplane
-                .endpoints
-                .iter()
-                .filter(|(_, endpoint)| endpoint.tenant_id == tenant_id)
-            {
-                let lsn_str = match endpoint.mode {
-                    ComputeMode::Static(lsn) => {
-                        // -> read-only endpoint
-                        // Use the node's LSN.
-                        lsn.to_string()
-                    }
-                    _ => {
-                        // -> primary endpoint or hot replica
-                        // Use the LSN at the end of the timeline.
-                        timeline_infos
-                            .get(&endpoint.timeline_id)
-                            .map(|bi| bi.last_record_lsn.to_string())
-                            .unwrap_or_else(|| "?".to_string())
-                    }
-                };
+    // A region topology given with --regions takes the place of the one from
+    // the config. It is validated by clap before we get here, so nothing is
+    // written to disk for a malformed spec.
+    let mut env = env.clone();
+    if let Ok(Some(region_spec)) = sub_args.try_get_one::<RegionSpec>("regions") {
+        env.regions = region_spec.to_region_confs();
+        // The safekeepers of the config must be in the regions of the spec.
+        env.validate_regions().categorize(ErrorCategory::Config)?;
+    }
+    let env = &env;
 
-                let branch_name = timeline_name_mappings
-                    .get(&TenantTimelineId::new(tenant_id, endpoint.timeline_id))
-                    .map(|name| name.as_str())
-                    .unwrap_or("?");
+    let mut cplane = ComputeControlPlane::load(env.clone())?;
 
-                table.add_row([
-                    endpoint_id.as_str(),
-                    &endpoint.pg_address.to_string(),
-                    &endpoint.timeline_id.to_string(),
-                    branch_name,
-                    lsn_str.as_str(),
-                    endpoint.status(),
-                ]);
-            }
+    // All subcommands take an optional --tenant-id option
+    let tenant_id = get_tenant_id(sub_args, env)?;
 
-            println!("{table}");
+    match sub_name {
+        "list" => {
+            if let Some(interval) = parse_watch_interval(sub_args) {
+                // Reload the endpoints on every render, to show the ones
+                // created or removed in the meantime.
+                watch("neon_local endpoint list", interval, || {
+                    endpoint_listing(&ComputeControlPlane::load(env.clone())?, env, tenant_id)
+                });
+            }
+            println!(
+                "{}",
+                endpoint_listing(&cplane, env, tenant_id)?.render(None)
+            );
         }
         "create" => {
-            let branch_name = sub_args
-                .get_one::<String>("branch-name")
-                .map(|s| s.as_str())
-                .unwrap_or(DEFAULT_BRANCH_NAME);
+            let (branch_name, timeline_id, region_id) =
+                get_endpoint_branch(sub_args, env, tenant_id)?;
             let endpoint_id = sub_args
                 .get_one::<String>("endpoint_id")
                 .map(String::to_string)
@@ -655,9 +1498,6 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
                 .map(|lsn_str| Lsn::from_str(lsn_str))
                 .transpose()
                 .context("Failed to parse Lsn from the request")?;
-            let (timeline_id, region_id) = env
-                .get_branch_timeline_id(branch_name, tenant_id)
-                .ok_or_else(|| anyhow!("Found no timeline id for branch name '{branch_name}'"))?;
 
             let pg_port: Option<u16> = sub_args.get_one::<u16>("pg-port").copied();
             let http_port: Option<u16> = sub_args.get_one::<u16>("http-port").copied();
@@ -687,6 +1527,8 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
                 pg_version,
                 mode,
                 region_id,
+                sub_args.get_one::<String>("template").map(String::as_str),
+                parse_pageserver_id(sub_args),
             )?;
         }
         "start" => {
@@ -708,14 +1550,18 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
                         })?);
                         safekeepers.push(sk_id);
                     }
-                    safekeepers
+                    Some(safekeepers)
                 } else {
-                    env.safekeepers.iter().map(|sk| sk.id).collect()
+                    None
                 };
 
             let endpoint = cplane.endpoints.get(endpoint_id.as_str());
 
-            let auth_token = if matches!(env.pageserver.pg_auth_type, AuthType::NeonJWT) {
+            let pageserver_conf = match endpoint {
+                Some(endpoint) => &endpoint.pageserver().conf,
+                None => env.get_pageserver_conf(parse_pageserver_id(sub_args))?,
+            };
+            let auth_token = if matches!(pageserver_conf.pg_auth_type, AuthType::NeonJWT) {
                 let claims = Claims::new(Some(tenant_id), Scope::Tenant);
 
                 Some(env.generate_auth_token(&claims)?)
@@ -733,25 +1579,31 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
             if let Some(endpoint) = endpoint {
                 match (&endpoint.mode, hot_standby) {
                     (ComputeMode::Static(_), true) => {
-                        bail!("Cannot start a node in hot standby mode when it is already configured as a static replica")
+                        return Err(categorize(ErrorCategory::PreconditionFailed, anyhow!("Cannot start a node in hot standby mode when it is already configured as a static replica")))
                     }
                     (ComputeMode::Primary, true) => {
-                        bail!("Cannot start a node as a hot standby replica, it is already configured as primary node")
+                        return Err(categorize(ErrorCategory::PreconditionFailed, anyhow!("Cannot start a node as a hot standby replica, it is already configured as primary node")))
                     }
                     _ => {}
                 }
+                for (requested, addr) in [
+                    (pg_port, endpoint.pg_address),
+                    (http_port, endpoint.http_address),
+                ] {
+                    if let Some(port) = requested.filter(|port| *port != addr.port()) {
+                        return Err(categorize(ErrorCategory::PreconditionFailed, anyhow!("Cannot start endpoint {endpoint_id} on port {port}, it was created with port {}", addr.port())));
+                    }
+                }
+                if let Some(id) =
+                    parse_pageserver_id(sub_args).filter(|id| *id != pageserver_conf.id)
+                {
+                    return Err(categorize(ErrorCategory::PreconditionFailed, anyhow!("Cannot start endpoint {endpoint_id} on pageserver {id}, it was created on pageserver {}", pageserver_conf.id)));
+                }
                 println!("Starting existing endpoint {endpoint_id}...");
+                let safekeepers = safekeepers.unwrap_or_else(|| endpoint.safekeepers());
                 endpoint.start(&auth_token, safekeepers, remote_ext_config, valgrind)?;
             } else {
-                let branch_name = sub_args
-                    .get_one::<String>("branch-name")
-                    .map(|s| s.as_str())
-                    .unwrap_or(DEFAULT_BRANCH_NAME);
-                let (timeline_id, region_id) = env
-                    .get_branch_timeline_id(branch_name, tenant_id)
-                    .ok_or_else(|| {
-                    anyhow!("Found no timeline id for branch name '{}'", branch_name)
-                })?;
+                let (_, timeline_id, region_id) = get_endpoint_branch(sub_args, env, tenant_id)?;
                 let lsn = sub_args
                     .get_one::<String>("lsn")
                     .map(|lsn_str| Lsn::from_str(lsn_str))
@@ -784,21 +1636,99 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
                     pg_version,
                     mode,
                     region_id,
+                    sub_args.get_one::<String>("template").map(String::as_str),
+                    parse_pageserver_id(sub_args),
                 )?;
+                let safekeepers = safekeepers.unwrap_or_else(|| ep.safekeepers());
                 ep.start(&auth_token, safekeepers, remote_ext_config, valgrind)?;
             }
         }
+        "reconfigure" => {
+            let endpoint_id = sub_args
+                .get_one::<String>("endpoint_id")
+                .ok_or_else(|| anyhow!("No endpoint ID was provided to reconfigure"))?;
+            let endpoint = cplane.endpoints.get(endpoint_id.as_str()).ok_or_else(|| {
+                categorize(
+                    ErrorCategory::NotFound,
+                    anyhow!("postgres endpoint {endpoint_id} is not found"),
+                )
+            })?;
+            let options = sub_args
+                .get_many::<String>("pg-option")
+                .into_iter()
+                .flatten()
+                .map(|option| {
+                    option
+                        .split_once('=')
+                        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                        .ok_or_else(|| {
+                            anyhow!("invalid --pg-option '{option}', expected name=value")
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let needs_restart = endpoint.reconfigure(&options)?;
+            if endpoint.status() != "running" {
+                println!("Updated the config of endpoint {endpoint_id}, it takes effect on the next start");
+            } else if needs_restart.is_empty() {
+                println!("Reloaded endpoint {endpoint_id} with the new config");
+            } else {
+                println!(
+                    "Restarting endpoint {endpoint_id} to apply {}",
+                    needs_restart.join(", ")
+                );
+                let auth_token =
+                    if matches!(endpoint.pageserver().conf.pg_auth_type, AuthType::NeonJWT) {
+                        let claims = Claims::new(Some(endpoint.tenant_id), Scope::Tenant);
+                        Some(env.generate_auth_token(&claims)?)
+                    } else {
+                        None
+                    };
+                endpoint.stop(false, None)?;
+                endpoint.start(&auth_token, endpoint.safekeepers(), None, None)?;
+            }
+        }
         "stop" => {
+            let destroy = sub_args.get_flag("destroy");
+            let timeout = parse_stop_timeout(sub_args);
+
+            if sub_args.get_flag("all") {
+                let mut failed = Vec::new();
+                for (endpoint_id, endpoint) in cplane
+                    .endpoints
+                    .iter()
+                    .filter(|(_, endpoint)| endpoint.tenant_id == tenant_id)
+                {
+                    match endpoint.stop(destroy, timeout) {
+                        Ok(outcome) => println!("Endpoint {endpoint_id} {outcome}"),
+                        Err(e) => {
+                            eprintln!("Failed to stop endpoint {endpoint_id}: {e:#}");
+                            failed.push(endpoint_id.as_str());
+                        }
+                    }
+                }
+                if !failed.is_empty() {
+                    bail!(
+                        "failed to stop {} endpoint(s) of tenant {tenant_id}: {}",
+                        failed.len(),
+                        failed.join(", ")
+                    );
+                }
+                return Ok(());
+            }
+
             let endpoint_id = sub_args
                 .get_one::<String>("endpoint_id")
                 .ok_or_else(|| anyhow!("No endpoint ID was provided to stop"))?;
-            let destroy = sub_args.get_flag("destroy");
 
-            let endpoint = cplane
-                .endpoints
-                .get(endpoint_id.as_str())
-                .with_context(|| format!("postgres endpoint {endpoint_id} is not found"))?;
-            endpoint.stop(destroy)?;
+            let endpoint = cplane.endpoints.get(endpoint_id.as_str()).ok_or_else(|| {
+                categorize(
+                    ErrorCategory::NotFound,
+                    anyhow!("postgres endpoint {endpoint_id} is not found"),
+                )
+            })?;
+            let outcome = endpoint.stop(destroy, timeout)?;
+            println!("Endpoint {endpoint_id} {outcome}");
         }
 
         _ => bail!("Unexpected endpoint subcommand '{sub_name}'"),
@@ -807,14 +1737,42 @@ fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<(
     Ok(())
 }
 
+fn parse_watch_interval(sub_match: &ArgMatches) -> Option<Duration> {
+    sub_match
+        .get_one::<u64>("watch")
+        .map(|secs| Duration::from_secs(*secs))
+}
+
+/// Parses a point in time given as an RFC 3339 timestamp, or as a duration ago.
+fn parse_point_in_time(time: &str) -> anyhow::Result<std::time::SystemTime> {
+    if let Ok(timestamp) = humantime::parse_rfc3339_weak(time) {
+        return Ok(timestamp);
+    }
+    let ago = humantime::parse_duration(time)
+        .with_context(|| format!("'{time}' is neither an RFC 3339 timestamp nor a duration ago"))?;
+    std::time::SystemTime::now()
+        .checked_sub(ago)
+        .ok_or_else(|| anyhow!("'{time}' ago is out of range"))
+}
+
+fn parse_stop_timeout(sub_match: &ArgMatches) -> Option<Duration> {
+    sub_match
+        .get_one::<u64>("timeout")
+        .map(|secs| Duration::from_secs(*secs))
+}
+
 fn handle_pageserver(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
-    let pageserver = PageServerNode::from_env(env);
+    // All the commands take an optional pageserver id argument
+    let pageserver = match sub_match.subcommand() {
+        Some((_, args)) => get_pageserver(env, args)?,
+        None => bail!("no pageserver subcommand provided"),
+    };
 
     match sub_match.subcommand() {
         Some(("start", start_match)) => {
             if let Err(e) = pageserver.start(&pageserver_config_overrides(start_match)) {
                 eprintln!("pageserver start failed: {e}");
-                exit(1);
+                exit(ErrorCategory::of(&e).exit_code());
             }
         }
 
@@ -824,33 +1782,91 @@ fn handle_pageserver(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Resul
                 .map(|s| s.as_str())
                 == Some("immediate");
 
-            if let Err(e) = pageserver.stop(immediate) {
-                eprintln!("pageserver stop failed: {}", e);
-                exit(1);
+            match pageserver.stop(immediate, parse_stop_timeout(stop_match)) {
+                Ok(outcome) => println!("pageserver {outcome}"),
+                Err(e) => {
+                    eprintln!("pageserver stop failed: {}", e);
+                    exit(ErrorCategory::of(&e).exit_code());
+                }
             }
         }
 
         Some(("restart", restart_match)) => {
             //TODO what shutdown strategy should we use here?
-            if let Err(e) = pageserver.stop(false) {
+            if let Err(e) = pageserver.stop(false, None) {
                 eprintln!("pageserver stop failed: {}", e);
-                exit(1);
+                exit(ErrorCategory::of(&e).exit_code());
             }
 
             if let Err(e) = pageserver.start(&pageserver_config_overrides(restart_match)) {
                 eprintln!("pageserver start failed: {e}");
-                exit(1);
+                exit(ErrorCategory::of(&e).exit_code());
             }
         }
 
-        Some(("status", _)) => match PageServerNode::from_env(env).check_status() {
-            Ok(_) => println!("Page server is up and running"),
+        Some(("status", _)) => match pageserver.status() {
+            Ok(status) => {
+                println!("Page server {} is up and running", pageserver.conf.id);
+                println!(
+                    "version:                  {}",
+                    status.version.as_deref().unwrap_or("?")
+                );
+                println!(
+                    "uptime:                   {}",
+                    format_uptime(status.launched_at)
+                );
+                println!("active tenants:           {}", status.active_tenants);
+                println!("timelines:                {}", status.timelines);
+                println!(
+                    "wal receiver connections: {}",
+                    status.wal_receiver_connections
+                );
+                println!(
+                    "last checkpoint:          {}",
+                    format_timestamp_age(status.last_checkpoint_at)
+                );
+                println!(
+                    "last gc:                  {}",
+                    format_timestamp_age(status.last_gc_at)
+                );
+            }
             Err(err) => {
-                eprintln!("Page server is not available: {}", err);
-                exit(1);
+                eprintln!("Page server is not available: {:#}", err);
+                exit(ErrorCategory::of(&err).exit_code());
             }
         },
 
+        Some(("scrub", scrub_match)) => {
+            let tenant_id = get_tenant_id(scrub_match, env)?;
+            let reports = report_while("Scrubbing tenant", || pageserver.tenant_scrub(tenant_id))?;
+            let names = env.timeline_name_mappings();
+            let mut problems = 0;
+            for report in &reports {
+                let name = names
+                    .get(&TenantTimelineId::new(tenant_id, report.timeline_id))
+                    .map(String::as_str)
+                    .unwrap_or("_no_name_");
+                println!(
+                    "timeline {name} [{}]: {} layers checked",
+                    report.timeline_id, report.layers_checked
+                );
+                for error in &report.errors {
+                    println!("  error: {error}");
+                }
+                for file in &report.orphaned_files {
+                    println!("  orphaned file: {file}");
+                }
+                problems += report.errors.len() + report.orphaned_files.len();
+            }
+            if problems > 0 {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!("found {problems} problems in tenant {tenant_id}"),
+                ));
+            }
+            println!("No problems found");
+        }
+
         Some((sub_name, _)) => bail!("Unexpected pageserver subcommand '{}'", sub_name),
         None => bail!("no pageserver subcommand provided"),
     }
@@ -861,29 +1877,79 @@ fn get_safekeeper(env: &local_env::LocalEnv, id: NodeId) -> Result<SafekeeperNod
     if let Some(node) = env.safekeepers.iter().find(|node| node.id == id) {
         Ok(SafekeeperNode::from_env(env, node))
     } else {
-        bail!("could not find safekeeper {id}")
+        Err(categorize(
+            ErrorCategory::NotFound,
+            anyhow!("could not find safekeeper {id}"),
+        ))
     }
 }
 
+/// Warns about the running primary endpoints that can't commit WAL anymore
+/// once the `stopping` safekeepers are stopped, i.e. with no majority of one of
+/// their sets of safekeepers left running.
+fn warn_about_lost_quorums(env: &local_env::LocalEnv, stopping: &[NodeId]) -> Result<()> {
+    let running: Vec<NodeId> = env
+        .safekeepers
+        .iter()
+        .map(|conf| SafekeeperNode::from_env(env, conf))
+        .filter(|sk| !stopping.contains(&sk.id) && sk.check_status().is_ok())
+        .map(|sk| sk.id)
+        .collect();
+
+    let cplane = ComputeControlPlane::load(env.clone())?;
+    for (endpoint_id, endpoint) in &cplane.endpoints {
+        if endpoint.mode != ComputeMode::Primary || endpoint.status() != "running" {
+            continue;
+        }
+        for set in endpoint.running_safekeepers()? {
+            let left = set.iter().filter(|id| running.contains(id)).count();
+            if left <= set.len() / 2 {
+                let ids: Vec<String> = set.iter().map(|id| id.to_string()).collect();
+                eprintln!(
+                    "WARNING: endpoint {endpoint_id} is left with {left} of its safekeepers {} running and can't commit WAL until a majority of them is back",
+                    ids.join(",")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 fn handle_safekeeper(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
     let (sub_name, sub_args) = match sub_match.subcommand() {
         Some(safekeeper_command_data) => safekeeper_command_data,
         None => bail!("no safekeeper subcommand provided"),
     };
 
+    // sync looks at all the safekeepers
+    if sub_name == "sync" {
+        return handle_safekeeper_sync(sub_args, env);
+    }
+
     // All the commands take an optional safekeeper name argument
     let sk_id = if let Some(id_str) = sub_args.get_one::<String>("id") {
         NodeId(id_str.parse().context("while parsing safekeeper id")?)
     } else {
         DEFAULT_SAFEKEEPER_ID
     };
-    let safekeeper = get_safekeeper(env, sk_id)?;
+    // start and stop can take a group of safekeepers instead
+    let safekeepers = match sub_args.try_get_one::<String>("group") {
+        Ok(Some(group)) => env
+            .get_safekeeper_group(group)?
+            .into_iter()
+            .map(|conf| SafekeeperNode::from_env(env, conf))
+            .collect(),
+        _ => vec![get_safekeeper(env, sk_id)?],
+    };
+    let safekeeper = &safekeepers[0];
 
     match sub_name {
         "start" => {
-            if let Err(e) = safekeeper.start() {
-                eprintln!("safekeeper start failed: {}", e);
-                exit(1);
+            for safekeeper in &safekeepers {
+                if let Err(e) = safekeeper.start() {
+                    eprintln!("safekeeper {} start failed: {}", safekeeper.id, e);
+                    exit(ErrorCategory::of(&e).exit_code());
+                }
             }
         }
 
@@ -891,9 +1957,18 @@ fn handle_safekeeper(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Resul
             let immediate =
                 sub_args.get_one::<String>("stop-mode").map(|s| s.as_str()) == Some("immediate");
 
-            if let Err(e) = safekeeper.stop(immediate) {
-                eprintln!("safekeeper stop failed: {}", e);
-                exit(1);
+            let ids: Vec<NodeId> = safekeepers.iter().map(|sk| sk.id).collect();
+            if let Err(e) = warn_about_lost_quorums(env, &ids) {
+                eprintln!("WARNING: could not check the write quorums of the endpoints: {e:#}");
+            }
+            for safekeeper in &safekeepers {
+                match safekeeper.stop(immediate, parse_stop_timeout(sub_args)) {
+                    Ok(outcome) => println!("safekeeper {} {outcome}", safekeeper.id),
+                    Err(e) => {
+                        eprintln!("safekeeper {} stop failed: {}", safekeeper.id, e);
+                        exit(ErrorCategory::of(&e).exit_code());
+                    }
+                }
             }
         }
 
@@ -901,17 +1976,115 @@ fn handle_safekeeper(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Resul
             let immediate =
                 sub_args.get_one::<String>("stop-mode").map(|s| s.as_str()) == Some("immediate");
 
-            if let Err(e) = safekeeper.stop(immediate) {
+            if let Err(e) = safekeeper.stop(immediate, None) {
                 eprintln!("safekeeper stop failed: {}", e);
-                exit(1);
+                exit(ErrorCategory::of(&e).exit_code());
             }
 
             if let Err(e) = safekeeper.start() {
                 eprintln!("safekeeper start failed: {}", e);
-                exit(1);
+                exit(ErrorCategory::of(&e).exit_code());
+            }
+        }
+
+        "status" => {
+            let tenant_id = get_tenant_id(sub_args, env)?;
+            let timeline_id = get_timeline_or_branch_id(sub_args, tenant_id, env)?;
+            let status = safekeeper
+                .timeline_status(tenant_id, timeline_id)
+                .with_context(|| {
+                    format!(
+                        "failed to get the status of timeline {timeline_id} on safekeeper {sk_id}"
+                    )
+                })?;
+
+            println!("safekeeper {sk_id}, timeline {tenant_id}/{timeline_id}");
+            println!(
+                "term: {}, epoch: {}",
+                status.acceptor_state.term, status.acceptor_state.epoch
+            );
+            println!("flush_lsn: {}", status.flush_lsn);
+            println!("commit_lsn: {}", status.commit_lsn);
+            println!("backup_lsn: {}", status.backup_lsn);
+            println!("remote_consistent_lsn: {}", status.remote_consistent_lsn);
+            println!("last_removed_segno: {}", status.last_removed_segno);
+            for peer in &status.peers {
+                println!(
+                    "peer {}: term {}, flush_lsn {}, commit_lsn {}",
+                    peer.sk_id, peer.last_log_term, peer.flush_lsn, peer.commit_lsn
+                );
             }
         }
 
+        "add-to-timeline" | "remove-from-timeline" => {
+            let tenant_id = get_tenant_id(sub_args, env)?;
+            let timeline_id = get_timeline_or_branch_id(sub_args, tenant_id, env)?;
+            let timeout = sub_args
+                .get_one::<Duration>("timeout")
+                .copied()
+                .unwrap_or(DEFAULT_WAIT_LSN_TIMEOUT);
+            let cplane = ComputeControlPlane::load(env.clone())?;
+            let endpoint = cplane
+                .endpoints
+                .values()
+                .find(|ep| {
+                    ep.tenant_id == tenant_id
+                        && ep.timeline_id == timeline_id
+                        && ep.mode == ComputeMode::Primary
+                        && ep.status() == "running"
+                })
+                .ok_or_else(|| {
+                    categorize(
+                        ErrorCategory::PreconditionFailed,
+                        anyhow!("timeline {timeline_id} has no running primary endpoint"),
+                    )
+                })?;
+
+            let mut safekeepers = safekeeper_membership::current_safekeepers(endpoint)?;
+            if sub_name == "add-to-timeline" {
+                if safekeepers.contains(&sk_id) {
+                    bail!("safekeeper {sk_id} is already on timeline {timeline_id}");
+                }
+                safekeepers.push(sk_id);
+            } else {
+                if !safekeepers.contains(&sk_id) {
+                    bail!("safekeeper {sk_id} is not on timeline {timeline_id}");
+                }
+                safekeepers.retain(|id| *id != sk_id);
+            }
+            report_while("Changing the safekeepers of the timeline", || {
+                safekeeper_membership::change_safekeepers(env, endpoint, &safekeepers, timeout)
+            })?;
+            let ids: Vec<String> = safekeepers.iter().map(|id| id.to_string()).collect();
+            println!(
+                "timeline {timeline_id} is on safekeepers {}",
+                ids.join(", ")
+            );
+        }
+
+        "remove-wal" => {
+            let tenant_id = get_tenant_id(sub_args, env)?;
+            let timeline_id = get_timeline_or_branch_id(sub_args, tenant_id, env)?;
+            let removed = safekeeper.timeline_remove_wal(tenant_id, timeline_id)?;
+            println!(
+                "safekeeper {sk_id} removed the WAL of timeline {timeline_id} before segment {}",
+                removed.last_removed_segno
+            );
+        }
+
+        "dump-control-file" => {
+            let tenant_id = get_tenant_id(sub_args, env)?;
+            let timeline_id = get_timeline_or_branch_id(sub_args, tenant_id, env)?;
+            // The safekeeper to look at is often a stopped one, read its
+            // control file from disk then.
+            let state = if safekeeper.check_status().is_ok() {
+                safekeeper.timeline_control_file(tenant_id, timeline_id)?
+            } else {
+                safekeeper.read_timeline_control_file(tenant_id, timeline_id)?
+            };
+            println!("{}", serde_json::to_string_pretty(&state)?);
+        }
+
         _ => {
             bail!("Unexpected safekeeper subcommand '{}'", sub_name)
         }
@@ -919,23 +2092,184 @@ fn handle_safekeeper(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Resul
     Ok(())
 }
 
+/// Compares the WAL of a timeline across the safekeepers, and truncates the
+/// divergent tails with `--truncate`.
+fn handle_safekeeper_sync(sub_args: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
+    let tenant_id = get_tenant_id(sub_args, env)?;
+    let timeline_id = get_timeline_or_branch_id(sub_args, tenant_id, env)?;
+    let safekeepers: Vec<SafekeeperNode> = env
+        .safekeepers
+        .iter()
+        .map(|conf| SafekeeperNode::from_env(env, conf))
+        .collect();
+
+    let report = safekeeper_sync::check_timeline(&safekeepers, tenant_id, timeline_id)?;
+    println!(
+        "timeline {tenant_id}/{timeline_id}: commit_lsn {}, most advanced safekeeper {} with WAL up to {}",
+        report.max_commit_lsn, report.reference, report.reference_flush_lsn
+    );
+    for node in &report.nodes {
+        let state = match &node.state {
+            SyncState::InSync => "in sync".to_string(),
+            SyncState::Behind { lag } => format!("behind by {lag} bytes"),
+            SyncState::Diverged { at, tail } => {
+                format!("diverged at {at}, with a divergent tail of {tail} bytes")
+            }
+            SyncState::Unavailable(e) => format!("unavailable: {e}"),
+        };
+        match &node.status {
+            Some(status) => println!(
+                "safekeeper {}: term {}, epoch {}, flush_lsn {}, commit_lsn {}, {state}",
+                node.id,
+                status.acceptor_state.term,
+                status.acceptor_state.epoch,
+                status.flush_lsn,
+                status.commit_lsn
+            ),
+            None => println!("safekeeper {}: {state}", node.id),
+        }
+    }
+
+    let diverged = report.diverged().count();
+    if diverged == 0 {
+        return Ok(());
+    }
+    if !sub_args.get_flag("truncate") {
+        println!(
+            "{diverged} safekeepers have a divergent tail, run with --truncate to truncate it"
+        );
+        return Ok(());
+    }
+
+    // A running walproposer truncates the tails itself, and the timeline must
+    // not go away from under it.
+    let cplane = ComputeControlPlane::load(env.clone())?;
+    if let Some((endpoint_id, _)) = cplane.endpoints.iter().find(|(_, ep)| {
+        ep.tenant_id == tenant_id
+            && ep.timeline_id == timeline_id
+            && ep.mode == ComputeMode::Primary
+            && ep.status() == "running"
+    }) {
+        return Err(categorize(
+            ErrorCategory::PreconditionFailed,
+            anyhow!("endpoint {endpoint_id} of timeline {timeline_id} is running, stop it first"),
+        ));
+    }
+    let truncated = report_while("Truncating the divergent tails", || {
+        safekeeper_sync::truncate_divergent_tails(&safekeepers, &report, tenant_id, timeline_id)
+    })?;
+    for id in truncated {
+        println!(
+            "safekeeper {id}: truncated, timeline copied from safekeeper {}",
+            report.reference
+        );
+    }
+    Ok(())
+}
+
+/// Components of the local environment that the top-level `start` and `stop`
+/// commands act on, as selected with `--only`.
+///
+/// The storage broker goes together with the storage layer, i.e. it is only
+/// started or stopped when both the pageserver and the safekeepers are.
+struct ComponentSelection {
+    pageserver: bool,
+    safekeepers: bool,
+    computes: bool,
+}
+
+impl ComponentSelection {
+    fn all() -> Self {
+        ComponentSelection {
+            pageserver: true,
+            safekeepers: true,
+            computes: true,
+        }
+    }
+
+    /// Without `--only`, everything is selected, except for compute endpoints
+    /// if `computes_by_default` is false.
+    fn from_args(sub_match: &ArgMatches, computes_by_default: bool) -> Self {
+        match sub_match.get_many::<String>("only") {
+            Some(only) => {
+                let only: Vec<&str> = only.map(String::as_str).collect();
+                ComponentSelection {
+                    pageserver: only.contains(&"pageserver"),
+                    safekeepers: only.contains(&"safekeepers"),
+                    computes: only.contains(&"computes"),
+                }
+            }
+            None => ComponentSelection {
+                computes: computes_by_default,
+                ..Self::all()
+            },
+        }
+    }
+
+    fn broker(&self) -> bool {
+        self.pageserver && self.safekeepers
+    }
+}
+
 fn handle_start_all(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> anyhow::Result<()> {
-    // Endpoints are not started automatically
+    // Endpoints are not started automatically, unless asked for with --only
+    let selection = ComponentSelection::from_args(sub_match, false);
 
-    broker::start_broker_process(env)?;
+    if selection.broker() {
+        broker::start_broker_process(env)?;
+    }
+
+    // On failure, stop what this command started, and leave the rest alone.
+    // Endpoints are started last, so there are none to stop.
+    let started = ComponentSelection {
+        computes: false,
+        ..selection
+    };
 
-    let pageserver = PageServerNode::from_env(env);
-    if let Err(e) = pageserver.start(&pageserver_config_overrides(sub_match)) {
-        eprintln!("pageserver {} start failed: {:#}", env.pageserver.id, e);
-        try_stop_all(env, true);
-        exit(1);
+    if selection.pageserver {
+        for conf in &env.pageservers {
+            let pageserver = PageServerNode::from_env(env, conf);
+            if let Err(e) = pageserver.start(&pageserver_config_overrides(sub_match)) {
+                eprintln!("pageserver {} start failed: {:#}", conf.id, e);
+                try_stop(env, true, &started);
+                exit(ErrorCategory::of(&e).exit_code());
+            }
+        }
     }
 
-    for node in env.safekeepers.iter() {
-        let safekeeper = SafekeeperNode::from_env(env, node);
-        if let Err(e) = safekeeper.start() {
-            eprintln!("safekeeper {} start failed: {:#}", safekeeper.id, e);
-            try_stop_all(env, false);
+    if selection.safekeepers {
+        for node in env.safekeepers.iter() {
+            let safekeeper = SafekeeperNode::from_env(env, node);
+            if let Err(e) = safekeeper.start() {
+                eprintln!("safekeeper {} start failed: {:#}", safekeeper.id, e);
+                try_stop(env, false, &started);
+                exit(ErrorCategory::of(&e).exit_code());
+            }
+        }
+    }
+
+    if selection.computes {
+        let cplane = ComputeControlPlane::load(env.clone())?;
+        let mut failed = false;
+        for (endpoint_id, endpoint) in cplane.endpoints.iter() {
+            if endpoint.status() == "running" {
+                continue;
+            }
+            let auth_token = if matches!(endpoint.pageserver().conf.pg_auth_type, AuthType::NeonJWT)
+            {
+                let claims = Claims::new(Some(endpoint.tenant_id), Scope::Tenant);
+                Some(env.generate_auth_token(&claims)?)
+            } else {
+                None
+            };
+            let safekeepers = env.safekeepers.iter().map(|sk| sk.id).collect();
+            println!("Starting existing endpoint {endpoint_id}...");
+            if let Err(e) = endpoint.start(&auth_token, safekeepers, None, None) {
+                eprintln!("endpoint {endpoint_id} start failed: {e:#}");
+                failed = true;
+            }
+        }
+        if failed {
             exit(1);
         }
     }
@@ -946,42 +2280,224 @@ fn handle_stop_all(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<
     let immediate =
         sub_match.get_one::<String>("stop-mode").map(|s| s.as_str()) == Some("immediate");
 
-    try_stop_all(env, immediate);
+    try_stop(
+        env,
+        immediate,
+        &ComponentSelection::from_args(sub_match, true),
+    );
 
     Ok(())
 }
 
-fn try_stop_all(env: &local_env::LocalEnv, immediate: bool) {
-    let pageserver = PageServerNode::from_env(env);
-
+fn try_stop(env: &local_env::LocalEnv, immediate: bool, selection: &ComponentSelection) {
     // Stop all endpoints
-    match ComputeControlPlane::load(env.clone()) {
-        Ok(cplane) => {
-            for (_k, node) in cplane.endpoints {
-                if let Err(e) = node.stop(false) {
-                    eprintln!("postgres stop failed: {e:#}");
+    if selection.computes {
+        match ComputeControlPlane::load(env.clone()) {
+            Ok(cplane) => {
+                for (_k, node) in cplane.endpoints {
+                    if let Err(e) = node.stop(false, None) {
+                        eprintln!("postgres stop failed: {e:#}");
+                    }
                 }
             }
+            Err(e) => {
+                eprintln!(
+                    "postgres stop failed, could not restore control plane data from env: {e:#}"
+                )
+            }
         }
-        Err(e) => {
-            eprintln!("postgres stop failed, could not restore control plane data from env: {e:#}")
+    }
+
+    if selection.pageserver {
+        for conf in &env.pageservers {
+            let pageserver = PageServerNode::from_env(env, conf);
+            if let Err(e) = pageserver.stop(immediate, None) {
+                eprintln!("pageserver {} stop failed: {:#}", conf.id, e);
+            }
+        }
+    }
+
+    if selection.safekeepers {
+        for node in env.safekeepers.iter() {
+            let safekeeper = SafekeeperNode::from_env(env, node);
+            if let Err(e) = safekeeper.stop(immediate, None) {
+                eprintln!("safekeeper {} stop failed: {:#}", safekeeper.id, e);
+            }
+        }
+    }
+
+    if selection.broker() {
+        if let Err(e) = broker::stop_broker_process(env) {
+            eprintln!("neon broker stop failed: {e:#}");
+        }
+    }
+}
+
+fn handle_bench(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
+    let mut cplane = ComputeControlPlane::load(env.clone())?;
+    let tenant_id = get_tenant_id(sub_match, env)?;
+    let endpoint_id = sub_match
+        .get_one::<String>("endpoint_id")
+        .context("No endpoint ID was provided")?;
+
+    let endpoint = match cplane.endpoints.get(endpoint_id) {
+        Some(endpoint) => Arc::clone(endpoint),
+        None => {
+            let (_, timeline_id, region_id) = get_endpoint_branch(sub_match, env, tenant_id)?;
+            let pg_version = sub_match
+                .get_one::<u32>("pg-version")
+                .copied()
+                .context("Failed to parse postgres version from the argument string")?;
+            println!("Creating endpoint {endpoint_id} on timeline {timeline_id} ...");
+            cplane.new_endpoint(
+                endpoint_id,
+                tenant_id,
+                timeline_id,
+                None,
+                None,
+                pg_version,
+                ComputeMode::Primary,
+                region_id,
+                None,
+                parse_pageserver_id(sub_match),
+            )?
+        }
+    };
+    if endpoint.mode != ComputeMode::Primary {
+        return Err(categorize(
+            ErrorCategory::PreconditionFailed,
+            anyhow!("Endpoint {endpoint_id} is not a primary, the benchmark needs to write"),
+        ));
+    }
+    if endpoint.status() != "running" {
+        println!("Starting endpoint {endpoint_id} ...");
+        let auth_token = if matches!(endpoint.pageserver().conf.pg_auth_type, AuthType::NeonJWT) {
+            let claims = Claims::new(Some(endpoint.tenant_id), Scope::Tenant);
+            Some(env.generate_auth_token(&claims)?)
+        } else {
+            None
+        };
+        let safekeepers = env.safekeepers.iter().map(|sk| sk.id).collect();
+        endpoint.start(&auth_token, safekeepers, None, None)?;
+    }
+
+    let options = BenchOptions {
+        duration: Duration::from_secs(*sub_match.get_one::<u64>("duration").unwrap()),
+        clients: *sub_match.get_one::<u64>("clients").unwrap() as usize,
+        scale: *sub_match.get_one::<u32>("scale").unwrap(),
+    };
+    let safekeepers: Vec<_> = env
+        .safekeepers
+        .iter()
+        .map(|sk| SafekeeperNode::from_env(env, sk))
+        .collect();
+
+    println!(
+        "Running the benchmark on endpoint {endpoint_id} with {} client(s) for {}s ...",
+        options.clients,
+        options.duration.as_secs()
+    );
+    let report = bench::run(&endpoint, endpoint.pageserver(), &safekeepers, &options)?;
+
+    println!(
+        "transactions:        {} in {:.1}s",
+        report.transactions,
+        report.elapsed.as_secs_f64()
+    );
+    println!(
+        "commit throughput:   {:.1} tps",
+        report.transactions_per_second()
+    );
+    match report.avg_getpage_latency() {
+        Some(latency) => println!(
+            "getpage@lsn latency: {:.3} ms avg over {} requests",
+            latency.as_secs_f64() * 1000.0,
+            report.getpage_requests
+        ),
+        None => println!("getpage@lsn latency: n/a, no requests reached the pageserver"),
+    }
+    println!(
+        "safekeeper WAL:      {} written across {} safekeeper(s)",
+        format_size(Some(report.safekeeper_wal_bytes)),
+        safekeepers.len()
+    );
+    Ok(())
+}
+
+fn handle_auth(sub_match: &ArgMatches, env: &mut local_env::LocalEnv) -> Result<()> {
+    match sub_match.subcommand() {
+        Some(("issue-token", sub_args)) => {
+            let scope = match sub_args.get_one::<String>("scope").map(String::as_str) {
+                Some("tenant") => Scope::Tenant,
+                Some("pageserverapi") => Scope::PageServerApi,
+                scope => bail!("unexpected token scope {scope:?}"),
+            };
+            let tenant_id = match scope {
+                Scope::Tenant => Some(get_tenant_id(sub_args, env)?),
+                _ => {
+                    if parse_tenant_id(sub_args)?.is_some() {
+                        return Err(categorize(
+                            ErrorCategory::Config,
+                            anyhow!("--tenant-id can only be used with the tenant scope"),
+                        ));
+                    }
+                    None
+                }
+            };
+
+            let claims = Claims::new(tenant_id, scope);
+            let token = match sub_args.get_one::<Duration>("ttl") {
+                Some(ttl) => env.generate_expiring_auth_token(&claims, *ttl)?,
+                None => env.generate_auth_token(&claims)?,
+            };
+            println!("{token}");
         }
+        Some(("rotate-keys", _)) => {
+            let key_id = env.rotate_auth_keys()?;
+            println!(
+                "Generated key '{key_id}', new tokens are signed with it. Tokens signed with the previous keys remain valid until their public keys are removed from '{}'",
+                env.auth_public_keys_dir().display()
+            );
+            println!("Restart the pageservers and safekeepers for them to accept the new key");
+        }
+        Some((sub_name, _)) => bail!("Unexpected auth subcommand '{sub_name}'"),
+        None => bail!("no auth subcommand provided"),
     }
+    Ok(())
+}
 
-    if let Err(e) = pageserver.stop(immediate) {
-        eprintln!("pageserver {} stop failed: {:#}", env.pageserver.id, e);
+fn handle_doctor(sub_match: &ArgMatches, env: &local_env::LocalEnv) -> Result<()> {
+    let fix = sub_match.get_flag("fix");
+
+    let findings = doctor::diagnose(env);
+    if findings.is_empty() {
+        println!("No problems found");
+        return Ok(());
     }
 
-    for node in env.safekeepers.iter() {
-        let safekeeper = SafekeeperNode::from_env(env, node);
-        if let Err(e) = safekeeper.stop(immediate) {
-            eprintln!("safekeeper {} stop failed: {:#}", safekeeper.id, e);
+    let mut unresolved = 0;
+    for finding in &findings {
+        println!("* {finding}");
+        if fix && finding.is_fixable() {
+            match finding.fix() {
+                Ok(()) => println!("    fixed"),
+                Err(e) => {
+                    println!("    fix failed: {e:#}");
+                    unresolved += 1;
+                }
+            }
+        } else {
+            unresolved += 1;
         }
     }
 
-    if let Err(e) = broker::stop_broker_process(env) {
-        eprintln!("neon broker stop failed: {e:#}");
+    if unresolved > 0 {
+        return Err(categorize(
+            ErrorCategory::PreconditionFailed,
+            anyhow!("{unresolved} of {} problems are unresolved", findings.len()),
+        ));
     }
+    Ok(())
 }
 
 fn cli() -> Command {
@@ -996,6 +2512,23 @@ fn cli() -> Command {
 
     let safekeeper_id_arg = Arg::new("id").help("safekeeper id").required(false);
 
+    let safekeeper_group_arg = Arg::new("group")
+        .long("group")
+        .help("Start or stop all the safekeepers of this group instead of one")
+        .required(false)
+        .conflicts_with("id");
+
+    let pageserver_id_arg = Arg::new("pageserver-id")
+        .long("pageserver-id")
+        .help("Id of the pageserver to use, the first one in the config by default")
+        .value_parser(value_parser!(u64))
+        .required(false);
+
+    let pageserver_id_positional_arg = Arg::new("pageserver-id")
+        .help("pageserver id, the first one in the config by default")
+        .value_parser(value_parser!(u64))
+        .required(false);
+
     let tenant_id_arg = Arg::new("tenant-id")
         .long("tenant-id")
         .help("Tenant id. Represented as a hexadecimal string 32 symbols length")
@@ -1012,6 +2545,23 @@ fn cli() -> Command {
         .required(false)
         .default_value("0");
 
+    let region_arg = Arg::new("region")
+        .long("region")
+        .help("Name of the region to run the endpoint in, as defined in the [[regions]] section of the config")
+        .required(false);
+
+    let template_arg = Arg::new("template")
+        .long("template")
+        .help("Name of a set of postgres settings to create the endpoint with, as defined in the [compute_templates] section of the config")
+        .required(false);
+
+    let regions_arg = Arg::new("regions")
+        .long("regions")
+        .help("Region topology as 'branch[,branch...]@ip:port[*];...', in region id order, with the endpoint's region marked by '*'")
+        .value_parser(value_parser!(RegionSpec))
+        .conflicts_with("region")
+        .required(false);
+
     let pg_version_arg = Arg::new("pg-version")
         .long("pg-version")
         .help("Postgres version to use for the initial tenant")
@@ -1044,6 +2594,37 @@ fn cli() -> Command {
         .required(false)
         .value_name("stop-mode");
 
+    let stop_timeout_arg = Arg::new("timeout")
+        .long("timeout")
+        .value_parser(value_parser!(u64))
+        .help("Seconds to wait for a graceful shutdown before killing the process")
+        .required(false)
+        .value_name("secs");
+
+    let membership_timeout_arg = Arg::new("timeout")
+        .long("timeout")
+        .value_parser(humantime::parse_duration)
+        .help("How long to wait for a majority of the new set of safekeepers to catch up, e.g. '30s'. One minute by default")
+        .required(false);
+
+    let watch_arg = Arg::new("watch")
+        .long("watch")
+        .value_parser(value_parser!(u64).range(1..))
+        .num_args(0..=1)
+        .default_missing_value("2")
+        .help("Re-render the listing every few seconds, 2 by default, highlighting what changed")
+        .required(false)
+        .value_name("secs");
+
+    let only_arg = Arg::new("only")
+        .long("only")
+        .value_parser(["pageserver", "safekeepers", "computes"])
+        .value_delimiter(',')
+        .action(ArgAction::Append)
+        .help("Only act on the given components. The storage broker is included when both 'pageserver' and 'safekeepers' are")
+        .required(false)
+        .value_name("component");
+
     let pageserver_config_args = Arg::new("pageserver-config-override")
         .long("pageserver-config-override")
         .num_args(1)
@@ -1093,29 +2674,116 @@ fn cli() -> Command {
                         .value_name("config"),
                 )
                 .arg(pg_version_arg.clone())
+                .arg(pageserver_id_arg.clone().help("Id of the pageserver to import the basebackup into, the first one in the config by default"))
                 .arg(force_arg)
+                .arg(
+                    Arg::new("import-basebackup")
+                        .long("import-basebackup")
+                        .value_parser(value_parser!(PathBuf))
+                        .value_name("tarfile")
+                        .requires("base-lsn")
+                        .help("Create the initial tenant with its timeline imported from a basebackup tarball, instead of running initdb"),
+                )
+                .arg(
+                    Arg::new("base-lsn")
+                        .long("base-lsn")
+                        .requires("import-basebackup")
+                        .help("Lsn the imported basebackup was taken at"),
+                )
         )
         .subcommand(
             Command::new("timeline")
             .about("Manage timelines")
             .subcommand(Command::new("list")
                 .about("List all timelines, available to this pageserver")
-                .arg(tenant_id_arg.clone()))
+                .arg(tenant_id_arg.clone())
+                .arg(watch_arg.clone())
+                .arg(pageserver_id_arg.clone()))
             .subcommand(Command::new("branch")
                 .about("Create a new timeline, using another timeline as a base, copying its data")
                 .arg(tenant_id_arg.clone())
                 .arg(branch_name_arg.clone())
                 .arg(region_id_arg.clone())
+                .arg(pageserver_id_arg.clone())
                 .arg(Arg::new("ancestor-branch-name").long("ancestor-branch-name")
                     .help("Use last Lsn of another timeline (and its data) as base when creating the new timeline. The timeline gets resolved by its branch name.").required(false))
                 .arg(Arg::new("ancestor-start-lsn").long("ancestor-start-lsn")
-                    .help("When using another timeline as base, use a specific Lsn in it instead of the latest one").required(false)))
+                    .help("When using another timeline as base, use a specific Lsn in it instead of the latest one").required(false))
+                .arg(Arg::new("ancestor-start-time").long("ancestor-start-time")
+                    .conflicts_with("ancestor-start-lsn")
+                    .help("When using another timeline as base, use its Lsn at a point in time within its pitr_interval, given as an RFC 3339 timestamp or as a duration ago, e.g. '2h'").required(false)))
             .subcommand(Command::new("create")
                 .about("Create a new blank timeline")
                 .arg(tenant_id_arg.clone())
                 .arg(branch_name_arg.clone())
                 .arg(region_id_arg.clone())
                 .arg(pg_version_arg.clone())
+                .arg(pageserver_id_arg.clone())
+            )
+            .subcommand(Command::new("show")
+                .about("Show the ancestry of a branch: its ancestor branches with their branch points, and its direct children")
+                .arg(tenant_id_arg.clone())
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to show").required(true))
+            )
+            .subcommand(Command::new("detail")
+                .about("Show the LSNs, layers and WAL ingestion state of a timeline")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().conflicts_with("branch-name"))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to show, instead of --timeline-id"))
+            )
+            .subcommand(Command::new("compact")
+                .about("Compact a timeline now, instead of waiting for the next compaction period")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().conflicts_with("branch-name"))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to compact, instead of --timeline-id"))
+            )
+            .subcommand(Command::new("wait-lsn")
+                .about("Wait until the pageserver has ingested the WAL of a timeline up to an Lsn, and flushed it to disk")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().conflicts_with("branch-name"))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to wait for, instead of --timeline-id"))
+                .arg(Arg::new("lsn").long("lsn").help("Lsn to wait for").required(true))
+                .arg(Arg::new("timeout").long("timeout")
+                    .value_parser(humantime::parse_duration)
+                    .help("How long to wait, e.g. '30s'. One minute by default"))
+            )
+            .subcommand(Command::new("truncate")
+                .about("Drop the history of a timeline before an Lsn, for good. Branching and reading before the Lsn is no longer possible")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().conflicts_with("branch-name"))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to truncate, instead of --timeline-id"))
+                .arg(Arg::new("lsn").long("lsn").help("Lsn before which to drop the history").required(true))
+            )
+            .subcommand(Command::new("export")
+                .about("Save the metadata and layer files of a timeline into an archive, to import it into another tenant or pageserver")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().conflicts_with("branch-name"))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("branch-name").help("Name of the branch to export, instead of --timeline-id"))
+                .arg(Arg::new("file").long("file")
+                    .value_parser(value_parser!(PathBuf))
+                    .help("Archive file to save").required(true))
+            )
+            .subcommand(Command::new("import-archive")
+                .about("Create a timeline from an archive saved by 'timeline export'. The ancestor of a branch must be imported first")
+                .arg(tenant_id_arg.clone())
+                .arg(timeline_id_arg.clone().help("Timeline id of the imported timeline, generated if not given"))
+                .arg(branch_name_arg.clone().required(true))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("file").long("file")
+                    .value_parser(value_parser!(PathBuf))
+                    .help("Archive file to import").required(true))
+            )
+            .subcommand(Command::new("rename")
+                .about("Rename a branch")
+                .arg(tenant_id_arg.clone())
+                .arg(Arg::new("old-branch-name").help("Current name of the branch").required(true))
+                .arg(Arg::new("new-branch-name").help("New name of the branch").required(true))
             )
             .subcommand(Command::new("import")
                 .about("Import timeline from basebackup directory")
@@ -1138,35 +2806,75 @@ fn cli() -> Command {
                 .arg(Arg::new("end-lsn").long("end-lsn")
                     .help("Lsn the basebackup ends at"))
                 .arg(pg_version_arg.clone())
+                .arg(pageserver_id_arg.clone())
             )
         ).subcommand(
             Command::new("tenant")
             .arg_required_else_help(true)
             .about("Manage tenants")
-            .subcommand(Command::new("list"))
+            .subcommand(Command::new("list").arg(pageserver_id_arg.clone()))
             .subcommand(Command::new("create")
                 .arg(tenant_id_arg.clone())
+                .arg(pageserver_id_arg.clone())
                 .arg(timeline_id_arg.clone().help("Use a specific timeline id when creating a tenant and its initial timeline"))
                 .arg(Arg::new("config").short('c').num_args(1).action(ArgAction::Append).required(false))
                 .arg(pg_version_arg.clone())
                 .arg(Arg::new("set-default").long("set-default").action(ArgAction::SetTrue).required(false)
                     .help("Use this tenant in future CLI commands where tenant_id is needed, but not specified"))
+                .arg(Arg::new("region-count").long("regions").value_parser(value_parser!(u8).range(1..)).required(false)
+                    .help("Also create a timeline for each of regions 1..=N, next to the global one in region 0"))
                 )
             .subcommand(Command::new("set-default").arg(tenant_id_arg.clone().required(true))
                 .about("Set a particular tenant as default in future CLI commands where tenant_id is needed, but not specified"))
+            .subcommand(Command::new("detach")
+                .about("Stop a tenant and release it from the pageserver, keeping its data on disk")
+                .arg(tenant_id_arg.clone())
+                .arg(pageserver_id_arg.clone()))
+            .subcommand(Command::new("attach")
+                .about("Attach a tenant back to the pageserver, from its local disk if it was detached from it, and from the remote storage otherwise")
+                .arg(tenant_id_arg.clone())
+                .arg(pageserver_id_arg.clone()))
+            .subcommand(Command::new("migrate")
+                .about("Move a tenant to another pageserver, restarting its endpoints there. The files of the tenant are copied while it stays active, and the endpoints are only stopped for the final switch-over")
+                .arg(tenant_id_arg.clone())
+                .arg(pageserver_id_arg.clone().help("Id of the pageserver the tenant is on"))
+                .arg(Arg::new("to").long("to")
+                    .value_parser(value_parser!(u64))
+                    .help("Id of the pageserver to move the tenant to").required(true))
+                .arg(Arg::new("timeout").long("timeout")
+                    .value_parser(humantime::parse_duration)
+                    .help("How long to wait for each timeline to flush its WAL on the pageserver the tenant is on, and to catch up on the other one, e.g. '30s'. One minute by default"))
+            )
             .subcommand(Command::new("config")
+                .about("Replace the config overrides of a tenant")
                 .arg(tenant_id_arg.clone())
-                .arg(Arg::new("config").short('c').num_args(1).action(ArgAction::Append).required(false)))
+                .arg(pageserver_id_arg.clone())
+                .arg(Arg::new("config").short('c').num_args(1).action(ArgAction::Append).required(false))
+                .subcommand(Command::new("set")
+                    .about("Change some config overrides of a tenant, keeping the other ones")
+                    .arg(tenant_id_arg.clone())
+                    .arg(pageserver_id_arg.clone())
+                    .arg(Arg::new("config").short('c').num_args(1).action(ArgAction::Append).required(true))))
         )
         .subcommand(
             Command::new("pageserver")
                 .arg_required_else_help(true)
                 .about("Manage pageserver")
-                .subcommand(Command::new("status"))
-                .subcommand(Command::new("start").about("Start local pageserver").arg(pageserver_config_args.clone()))
+                .subcommand(Command::new("status").arg(pageserver_id_positional_arg.clone()))
+                .subcommand(Command::new("start").about("Start local pageserver")
+                            .arg(pageserver_id_positional_arg.clone())
+                            .arg(pageserver_config_args.clone()))
                 .subcommand(Command::new("stop").about("Stop local pageserver")
-                            .arg(stop_mode_arg.clone()))
-                .subcommand(Command::new("restart").about("Restart local pageserver").arg(pageserver_config_args.clone()))
+                            .arg(pageserver_id_positional_arg.clone())
+                            .arg(stop_mode_arg.clone())
+                            .arg(stop_timeout_arg.clone()))
+                .subcommand(Command::new("restart").about("Restart local pageserver")
+                            .arg(pageserver_id_positional_arg.clone())
+                            .arg(pageserver_config_args.clone()))
+                .subcommand(Command::new("scrub")
+                            .about("Check the layer files of a tenant for corruption, gaps and orphaned files")
+                            .arg(pageserver_id_positional_arg)
+                            .arg(tenant_id_arg.clone()))
         )
         .subcommand(
             Command::new("safekeeper")
@@ -1175,23 +2883,76 @@ fn cli() -> Command {
                 .subcommand(Command::new("start")
                             .about("Start local safekeeper")
                             .arg(safekeeper_id_arg.clone())
+                            .arg(safekeeper_group_arg.clone())
                 )
                 .subcommand(Command::new("stop")
-                            .about("Stop local safekeeper")
+                            .about("Stop local safekeeper, warning when a running endpoint is left without a majority of its safekeepers")
                             .arg(safekeeper_id_arg.clone())
+                            .arg(safekeeper_group_arg)
                             .arg(stop_mode_arg.clone())
+                            .arg(stop_timeout_arg.clone())
                 )
                 .subcommand(Command::new("restart")
                             .about("Restart local safekeeper")
-                            .arg(safekeeper_id_arg)
+                            .arg(safekeeper_id_arg.clone())
                             .arg(stop_mode_arg.clone())
                 )
+                .subcommand(Command::new("status")
+                            .about("Show the state of a timeline on a local safekeeper")
+                            .arg(safekeeper_id_arg.clone())
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                )
+                .subcommand(Command::new("add-to-timeline")
+                            .about("Make a local safekeeper keep the WAL of a timeline too, moving the running endpoint of the timeline over to it through a configuration with both the old and the new set of safekeepers")
+                            .arg(safekeeper_id_arg.clone())
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                            .arg(membership_timeout_arg.clone())
+                )
+                .subcommand(Command::new("remove-from-timeline")
+                            .about("Stop writing the WAL of a timeline to a local safekeeper, e.g. a failed one, moving the running endpoint of the timeline over to the others through a configuration with both the old and the new set of safekeepers")
+                            .arg(safekeeper_id_arg.clone())
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                            .arg(membership_timeout_arg)
+                )
+                .subcommand(Command::new("remove-wal")
+                            .about("Remove the WAL of a timeline that nothing needs anymore from a local safekeeper")
+                            .arg(safekeeper_id_arg.clone())
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                )
+                .subcommand(Command::new("sync")
+                            .about("Compare the WAL of a timeline on all the local safekeepers with the most advanced one: show the highest commit LSN, and which safekeepers are behind or have a divergent tail, i.e. WAL after the point where their term history stops matching")
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                            .arg(
+                                Arg::new("truncate")
+                                    .help("Truncate the divergent tails by copying the timeline from the most advanced safekeeper, if a majority of the safekeepers of the timeline is reachable and no primary endpoint of the timeline is running")
+                                    .long("truncate")
+                                    .action(ArgAction::SetTrue)
+                                    .required(false)
+                            )
+                )
+                .subcommand(Command::new("dump-control-file")
+                            .about("Print the persisted control file state of a timeline on a local safekeeper as JSON: its term, term history, whose last entry starts the current epoch, and LSNs including peer_horizon_lsn, the truncate LSN. Read from disk if the safekeeper is not running")
+                            .arg(safekeeper_id_arg)
+                            .arg(tenant_id_arg.clone())
+                            .arg(timeline_id_arg.clone())
+                            .arg(branch_name_arg.clone())
+                )
         )
         .subcommand(
             Command::new("endpoint")
                 .arg_required_else_help(true)
                 .about("Manage postgres instances")
-                .subcommand(Command::new("list").arg(tenant_id_arg.clone()))
+                .subcommand(Command::new("list").arg(tenant_id_arg.clone()).arg(watch_arg))
                 .subcommand(Command::new("create")
                     .about("Create a compute endpoint")
                     .arg(endpoint_id_arg.clone())
@@ -1201,6 +2962,8 @@ fn cli() -> Command {
                     .arg(pg_port_arg.clone())
                     .arg(http_port_arg.clone())
                     .arg(region_id_arg.clone())
+                    .arg(region_arg.clone())
+                    .arg(regions_arg.clone())
                     .arg(
                         Arg::new("config-only")
                             .help("Don't do basebackup, create endpoint directory with only config files")
@@ -1208,27 +2971,49 @@ fn cli() -> Command {
                             .required(false))
                     .arg(pg_version_arg.clone())
                     .arg(hot_standby_arg.clone())
+                    .arg(template_arg.clone())
+                    .arg(pageserver_id_arg.clone())
                 )
                 .subcommand(Command::new("start")
                     .about("Start postgres.\n If the endpoint doesn't exist yet, it is created.")
                     .arg(endpoint_id_arg.clone())
                     .arg(tenant_id_arg.clone())
-                    .arg(branch_name_arg)
+                    .arg(branch_name_arg.clone())
                     .arg(timeline_id_arg)
                     .arg(lsn_arg)
                     .arg(pg_port_arg)
                     .arg(http_port_arg)
-                    .arg(pg_version_arg)
+                    .arg(pg_version_arg.clone())
                     .arg(hot_standby_arg)
                     .arg(safekeepers_arg)
                     .arg(remote_ext_config_args)
                     .arg(region_id_arg)
+                    .arg(region_arg.clone())
+                    .arg(regions_arg)
                     .arg(valgrind_arg)
+                    .arg(template_arg)
+                    .arg(pageserver_id_arg.clone())
+                )
+                .subcommand(
+                    Command::new("reconfigure")
+                    .about("Change settings in the postgresql.conf of an endpoint, reloading or restarting it if it's running")
+                    .arg(endpoint_id_arg.clone().required(true))
+                    .arg(tenant_id_arg.clone())
+                    .arg(
+                        Arg::new("pg-option")
+                            .long("pg-option")
+                            .help("Setting to change, as name=value")
+                            .value_name("name=value")
+                            .num_args(1)
+                            .action(ArgAction::Append)
+                            .required(true)
+                    )
                 )
                 .subcommand(
                     Command::new("stop")
-                    .arg(endpoint_id_arg)
-                    .arg(tenant_id_arg)
+                    .arg(endpoint_id_arg.clone())
+                    .arg(tenant_id_arg.clone())
+                    .arg(stop_timeout_arg)
                     .arg(
                         Arg::new("destroy")
                             .help("Also delete data directory (now optional, should be default in future)")
@@ -1236,6 +3021,14 @@ fn cli() -> Command {
                             .action(ArgAction::SetTrue)
                             .required(false)
                         )
+                    .arg(
+                        Arg::new("all")
+                            .help("Stop all endpoints of the tenant")
+                            .long("all")
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with("endpoint_id")
+                            .required(false)
+                        )
                 )
 
         )
@@ -1250,11 +3043,81 @@ fn cli() -> Command {
             Command::new("start")
                 .about("Start page server and safekeepers")
                 .arg(pageserver_config_args)
+                .arg(only_arg.clone())
         )
         .subcommand(
             Command::new("stop")
                 .about("Stop page server and safekeepers")
                 .arg(stop_mode_arg)
+                .arg(only_arg)
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Run a short pgbench-like workload against an endpoint and report storage performance.\n If the endpoint doesn't exist yet, it is created, and if it is stopped, it is started.")
+                .arg(endpoint_id_arg.default_value("ep-bench"))
+                .arg(tenant_id_arg.clone())
+                .arg(branch_name_arg)
+                .arg(region_arg)
+                .arg(pg_version_arg)
+                .arg(pageserver_id_arg)
+                .arg(
+                    Arg::new("duration")
+                        .long("duration")
+                        .help("How long to run the workload, in seconds")
+                        .value_parser(value_parser!(u64).range(1..))
+                        .default_value("10")
+                )
+                .arg(
+                    Arg::new("clients")
+                        .long("clients")
+                        .help("Number of concurrent clients")
+                        .value_parser(value_parser!(u64).range(1..))
+                        .default_value("1")
+                )
+                .arg(
+                    Arg::new("scale")
+                        .long("scale")
+                        .help("Scale factor of the accounts table, 100000 rows per unit as in pgbench")
+                        .value_parser(value_parser!(u32).range(1..1000))
+                        .default_value("1")
+                )
+        )
+        .subcommand(
+            Command::new("auth")
+                .arg_required_else_help(true)
+                .about("Manage authentication")
+                .subcommand(Command::new("issue-token")
+                    .about("Issue a JWT token for external tools connecting to the pageserver or the endpoints")
+                    .arg(tenant_id_arg.help("Tenant to issue a tenant scoped token for, the default tenant if not given"))
+                    .arg(
+                        Arg::new("scope")
+                            .long("scope")
+                            .value_parser(["tenant", "pageserverapi"])
+                            .default_value("tenant")
+                            .help("'tenant' for access to the data of one tenant, 'pageserverapi' for access to all tenants and the pageserver management API")
+                    )
+                    .arg(
+                        Arg::new("ttl")
+                            .long("ttl")
+                            .value_parser(humantime::parse_duration)
+                            .help("How long the token is valid, e.g. '1h'. Never expires by default")
+                            .required(false)
+                    )
+                )
+                .subcommand(Command::new("rotate-keys")
+                    .about("Generate a new key pair to sign tokens with, keeping the tokens signed with the previous ones valid")
+                )
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the local environment for common problems")
+                .arg(
+                    Arg::new("fix")
+                        .help("Fix the problems that can be fixed without losing data")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .required(false)
+                )
         )
 }
 
@@ -47,9 +47,15 @@ pub fn start_broker_process(env: &local_env::LocalEnv) -> anyhow::Result<()> {
 }
 
 pub fn stop_broker_process(env: &local_env::LocalEnv) -> anyhow::Result<()> {
-    background_process::stop_process(true, "storage_broker", &storage_broker_pid_file_path(env))
+    background_process::stop_process(
+        true,
+        None,
+        "storage_broker",
+        &storage_broker_pid_file_path(env),
+    )?;
+    Ok(())
 }
 
-fn storage_broker_pid_file_path(env: &local_env::LocalEnv) -> PathBuf {
+pub fn storage_broker_pid_file_path(env: &local_env::LocalEnv) -> PathBuf {
     env.base_data_dir.join("storage_broker.pid")
 }
@@ -0,0 +1,381 @@
+//! Self-diagnostics of a local environment, behind `neon_local doctor`.
+//!
+//! Looks for the usual ways a local environment ends up broken: hung services
+//! holding their pid files, services that exited without being stopped,
+//! leftover compute pid files, ports taken by other
+//! processes, missing or mismatched binaries, and endpoint directories that
+//! `neon_local` can no longer make sense of. Each
+//! problem is reported as a [`Finding`] with a hint on how to resolve it, and
+//! the ones that can be resolved without losing anything can be fixed in place.
+//!
+use std::fmt;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::Context;
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
+use utils::pid_file::{self, PidFileRead};
+
+use crate::endpoint::Endpoint;
+use crate::local_env::{LocalEnv, DEFAULT_PG_VERSION};
+use crate::pageserver::PageServerNode;
+use crate::safekeeper::SafekeeperNode;
+use crate::{broker, endpoint};
+
+pub struct Finding {
+    pub problem: String,
+    pub hint: String,
+    fix: Option<Fix>,
+}
+
+enum Fix {
+    RemoveFile(PathBuf),
+    CreateDir(PathBuf),
+    MoveAside { from: PathBuf, to: PathBuf },
+}
+
+impl Finding {
+    fn new(problem: String, hint: impl Into<String>) -> Self {
+        Finding {
+            problem,
+            hint: hint.into(),
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+
+    /// Whether the finding can be fixed with `--fix` without losing any data.
+    pub fn is_fixable(&self) -> bool {
+        self.fix.is_some()
+    }
+
+    pub fn fix(&self) -> anyhow::Result<()> {
+        match &self.fix {
+            None => anyhow::bail!("no automatic fix available"),
+            Some(Fix::RemoveFile(path)) => std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display())),
+            Some(Fix::CreateDir(path)) => std::fs::create_dir_all(path)
+                .with_context(|| format!("failed to create {}", path.display())),
+            Some(Fix::MoveAside { from, to }) => {
+                if let Some(parent) = to.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(from, to).with_context(|| {
+                    format!("failed to move {} to {}", from.display(), to.display())
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n    hint: {}", self.problem, self.hint)?;
+        if self.is_fixable() {
+            write!(f, " (fixable with --fix)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs all the checks against the environment.
+pub fn diagnose(env: &LocalEnv) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    check_binaries(env, &mut findings);
+    check_services(env, &mut findings);
+    check_endpoints(env, &mut findings);
+    findings
+}
+
+fn check_binaries(env: &LocalEnv, findings: &mut Vec<Finding>) {
+    for binary in ["pageserver", "safekeeper", "storage_broker", "compute_ctl"] {
+        let path = env.neon_distrib_dir.join(binary);
+        if !path.exists() {
+            findings.push(Finding::new(
+                format!("neon binary '{}' is missing", path.display()),
+                "build it with 'make', or point neon_distrib_dir in the config to a build",
+            ));
+        }
+    }
+
+    // Check the postgres versions used by the endpoints, and the default one.
+    let mut pg_versions = vec![DEFAULT_PG_VERSION];
+    if let Ok(entries) = std::fs::read_dir(env.endpoints_path()) {
+        for entry in entries.flatten() {
+            if let Ok(ep) = Endpoint::from_dir_entry(entry, env) {
+                if !pg_versions.contains(&ep.pg_version) {
+                    pg_versions.push(ep.pg_version);
+                }
+            }
+        }
+    }
+
+    for pg_version in pg_versions {
+        let postgres = match env.pg_bin_dir(pg_version) {
+            Ok(bin_dir) => bin_dir.join("postgres"),
+            Err(e) => {
+                findings.push(Finding::new(
+                    format!("{e:#}"),
+                    "use a supported postgres version",
+                ));
+                continue;
+            }
+        };
+        if !postgres.exists() {
+            findings.push(Finding::new(
+                format!(
+                    "postgres v{pg_version} binary '{}' is missing",
+                    postgres.display()
+                ),
+                "build it with 'make postgres', or set POSTGRES_DISTRIB_DIR before 'neon_local init'",
+            ));
+            continue;
+        }
+        match postgres_major_version(&postgres) {
+            Ok(major) if major == pg_version => {}
+            Ok(major) => findings.push(Finding::new(
+                format!(
+                    "'{}' is PostgreSQL {major}, but it is installed as v{pg_version}",
+                    postgres.display()
+                ),
+                format!(
+                    "reinstall PostgreSQL {pg_version} into '{}'",
+                    env.pg_distrib_dir(pg_version)
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_default()
+                ),
+            )),
+            Err(e) => findings.push(Finding::new(
+                format!("could not run '{}': {e:#}", postgres.display()),
+                "check that the postgres installation is complete",
+            )),
+        }
+    }
+}
+
+fn postgres_major_version(postgres: &Path) -> anyhow::Result<u32> {
+    let output = Command::new(postgres).arg("--version").output()?;
+    parse_postgres_major_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the major version out of `postgres --version` output, which looks
+/// like `postgres (PostgreSQL) 15.3`, possibly followed by the packager's
+/// version, as in `postgres (PostgreSQL) 15.3 (Debian 15.3-1.pgdg120+1)`.
+fn parse_postgres_major_version(output: &str) -> anyhow::Result<u32> {
+    let version = output
+        .split_whitespace()
+        .skip_while(|token| *token != "(PostgreSQL)")
+        .nth(1)
+        .with_context(|| format!("unexpected version output '{output}'"))?;
+    let major = version.split('.').next().unwrap_or(version);
+    major
+        .parse()
+        .with_context(|| format!("unexpected version '{version}'"))
+}
+
+fn check_services(env: &LocalEnv, findings: &mut Vec<Finding>) {
+    let mut services = vec![(
+        "storage_broker".to_string(),
+        broker::storage_broker_pid_file_path(env),
+        vec![env.broker.listen_addr.to_string()],
+    )];
+    for ps in &env.pageservers {
+        services.push((
+            format!("pageserver {}", ps.id),
+            PageServerNode::from_env(env, ps).pid_file(),
+            vec![ps.listen_pg_addr.clone(), ps.listen_http_addr.clone()],
+        ));
+    }
+    for sk in &env.safekeepers {
+        let node = SafekeeperNode::from_env(env, sk);
+        let mut addrs = vec![
+            format!("127.0.0.1:{}", sk.pg_port),
+            format!("127.0.0.1:{}", sk.http_port),
+        ];
+        if let Some(port) = sk.pg_tenant_only_port {
+            addrs.push(format!("127.0.0.1:{port}"));
+        }
+        if !node.datadir_path().exists() {
+            findings.push(
+                Finding::new(
+                    format!(
+                        "safekeeper {} data directory '{}' is missing",
+                        sk.id,
+                        node.datadir_path().display()
+                    ),
+                    "it is created on 'neon_local init', recreate it empty",
+                )
+                .with_fix(Fix::CreateDir(node.datadir_path())),
+            );
+        }
+        services.push((format!("safekeeper {}", sk.id), node.pid_file(), addrs));
+    }
+
+    for (name, pid_file, addrs) in services {
+        match pid_file::read(&pid_file) {
+            Ok(PidFileRead::NotExist) => check_ports(&name, &addrs, findings),
+            // Pid files are kept around when a service stops, and reused on the
+            // next start, so this is no problem if the service was stopped on
+            // purpose. Removing it is never safe, see the `pid_file` module.
+            Ok(PidFileRead::NotHeldByAnyProcess(_)) => {
+                findings.push(Finding::new(
+                    format!(
+                        "{name} is not running, and no process holds its pid file '{}'",
+                        pid_file.display()
+                    ),
+                    format!(
+                        "if {name} was not stopped on purpose, check its log and start it again"
+                    ),
+                ));
+                check_ports(&name, &addrs, findings)
+            }
+            Ok(PidFileRead::LockedByOtherProcess(pid)) => {
+                let responds = addrs.iter().any(|addr| {
+                    addr.to_socket_addrs()
+                        .ok()
+                        .and_then(|mut addrs| addrs.next())
+                        .map(|addr| {
+                            TcpStream::connect_timeout(&addr, Duration::from_secs(1)).is_ok()
+                        })
+                        .unwrap_or(false)
+                });
+                if !responds {
+                    findings.push(Finding::new(
+                        format!(
+                            "pid file '{}' is held by process {pid}, but {name} does not accept connections",
+                            pid_file.display()
+                        ),
+                        format!("the process may be hung, stop it with 'kill -9 {pid}'"),
+                    ));
+                }
+            }
+            Err(e) => findings.push(Finding::new(
+                format!(
+                    "could not read {name} pid file '{}': {e:#}",
+                    pid_file.display()
+                ),
+                "check the permissions of the file",
+            )),
+        }
+    }
+}
+
+/// Checks that the addresses of a service that is not running are free.
+fn check_ports(name: &str, addrs: &[String], findings: &mut Vec<Finding>) {
+    for addr in addrs {
+        if let Err(e) = TcpListener::bind(addr.as_str()) {
+            if e.kind() == std::io::ErrorKind::AddrInUse {
+                findings.push(Finding::new(
+                    format!("{name} is not running, but its address {addr} is in use"),
+                    "stop the process listening on it, or change the address in the config",
+                ));
+            }
+        }
+    }
+}
+
+fn check_endpoints(env: &LocalEnv, findings: &mut Vec<Finding>) {
+    let endpoints_path = env.endpoints_path();
+    let entries = match std::fs::read_dir(&endpoints_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let finding = Finding::new(
+                format!(
+                    "could not list endpoints directory '{}': {e}",
+                    endpoints_path.display()
+                ),
+                "it is created on 'neon_local init'",
+            );
+            findings.push(if e.kind() == std::io::ErrorKind::NotFound {
+                finding.with_fix(Fix::CreateDir(endpoints_path))
+            } else {
+                finding
+            });
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let ep = match Endpoint::from_dir_entry(entry, env) {
+            Ok(ep) => ep,
+            Err(e) => {
+                findings.push(
+                    Finding::new(
+                        format!(
+                            "'{}' is not a valid endpoint ({e:#}), which breaks all endpoint commands",
+                            path.display()
+                        ),
+                        "move it out of the endpoints directory",
+                    )
+                    .with_fix(Fix::MoveAside {
+                        from: path,
+                        to: env.base_data_dir.join("endpoints.orphaned").join(name),
+                    }),
+                );
+                continue;
+            }
+        };
+
+        if ep.status() != "stopped" {
+            continue;
+        }
+        let pidfile = ep.endpoint_path().join(endpoint::COMPUTE_CTL_PID_FILE);
+        if let Some(pid) = std::fs::read_to_string(&pidfile)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<i32>().ok())
+        {
+            if kill(Pid::from_raw(pid), None) == Err(Errno::ESRCH) {
+                findings.push(
+                    Finding::new(
+                        format!(
+                            "endpoint {name} is stopped, but left a compute_ctl pid file at '{}'",
+                            pidfile.display()
+                        ),
+                        "remove the pid file",
+                    )
+                    .with_fix(Fix::RemoveFile(pidfile)),
+                );
+            }
+        }
+        check_ports(
+            &format!("endpoint {name}"),
+            &[ep.pg_address.to_string(), ep.http_address.to_string()],
+            findings,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postgres_version_output() {
+        assert_eq!(
+            parse_postgres_major_version("postgres (PostgreSQL) 15.3\n").unwrap(),
+            15
+        );
+        assert_eq!(
+            parse_postgres_major_version("postgres (PostgreSQL) 14.8").unwrap(),
+            14
+        );
+        assert_eq!(
+            parse_postgres_major_version("postgres (PostgreSQL) 15.3 (Debian 15.3-1.pgdg120+1)\n")
+                .unwrap(),
+            15
+        );
+        assert!(parse_postgres_major_version("").is_err());
+        assert!(parse_postgres_major_version("postgres 15.3").is_err());
+        assert!(parse_postgres_major_version("postgres (PostgreSQL) devel").is_err());
+    }
+}
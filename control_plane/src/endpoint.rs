@@ -38,17 +38,20 @@
 //!
 use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream};
 use std::path::PathBuf;
 use std::process::Command;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::Pid;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use utils::id::{NodeId, RegionId, TenantId, TimelineId};
 
+use crate::background_process::{self, StopOutcome};
+use crate::error::{categorize, ErrorCategory};
 use crate::local_env::LocalEnv;
 use crate::pageserver::PageServerNode;
 use crate::postgresql_conf::PostgresConf;
@@ -56,6 +59,19 @@ use crate::postgresql_conf::PostgresConf;
 use compute_api::responses::{ComputeState, ComputeStatus};
 use compute_api::spec::{Cluster, ComputeMode, ComputeSpec};
 
+/// Settings that are derived from the endpoint's own config, and so can't be
+/// changed with [`Endpoint::reconfigure`].
+const MANAGED_SETTINGS: &[&str] = &[
+    "port",
+    "listen_addresses",
+    "current_region",
+    "neon.safekeepers",
+    "neon.safekeepers_next",
+];
+
+/// Written by `compute_ctl` into the endpoint directory.
+pub(crate) const COMPUTE_CTL_PID_FILE: &str = "compute_ctl.pid";
+
 // contents of a endpoint.json file
 #[serde_as]
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -70,50 +86,67 @@ pub struct EndpointConf {
     http_port: u16,
     pg_version: u32,
     skip_pg_catalog_updates: bool,
+    #[serde(default)]
+    catalog_basebackup: bool,
     region_id: RegionId,
+    // The first pageserver if not set, for endpoints created before there
+    // could be several.
+    #[serde(default)]
+    pageserver_id: Option<NodeId>,
+    // The safekeepers of the timeline, once changed with
+    // `neon_local safekeeper add-to-timeline` or `remove-from-timeline`.
+    #[serde(default)]
+    safekeepers: Option<Vec<NodeId>>,
 }
 
 //
 // ComputeControlPlane
 //
 pub struct ComputeControlPlane {
-    base_port: u16,
-
     // endpoint ID is the key
     pub endpoints: BTreeMap<String, Arc<Endpoint>>,
 
     env: LocalEnv,
-    pageserver: Arc<PageServerNode>,
 }
 
 impl ComputeControlPlane {
     // Load current endpoints from the endpoints/ subdirectories
     pub fn load(env: LocalEnv) -> Result<ComputeControlPlane> {
-        let pageserver = Arc::new(PageServerNode::from_env(&env));
-
         let mut endpoints = BTreeMap::default();
         for endpoint_dir in std::fs::read_dir(env.endpoints_path())
             .with_context(|| format!("failed to list {}", env.endpoints_path().display()))?
         {
-            let ep = Endpoint::from_dir_entry(endpoint_dir?, &env, &pageserver)?;
+            let ep = Endpoint::from_dir_entry(endpoint_dir?, &env)?;
             endpoints.insert(ep.endpoint_id.clone(), Arc::new(ep));
         }
 
-        Ok(ComputeControlPlane {
-            base_port: 55431,
-            endpoints,
-            env,
-            pageserver,
-        })
+        Ok(ComputeControlPlane { endpoints, env })
     }
 
-    fn get_port(&mut self) -> u16 {
-        1 + self
-            .endpoints
+    /// Returns the endpoint that the port is assigned to, if any.
+    fn port_owner(&self, port: u16) -> Option<&str> {
+        self.endpoints
             .values()
-            .map(|ep| std::cmp::max(ep.pg_address.port(), ep.http_address.port()))
-            .max()
-            .unwrap_or(self.base_port)
+            .find(|ep| ep.pg_address.port() == port || ep.http_address.port() == port)
+            .map(|ep| ep.endpoint_id.as_str())
+    }
+
+    /// Picks the lowest port from the configured range that is neither assigned
+    /// to an endpoint, even a stopped one, nor in use by any other process.
+    fn get_port(&self, exclude: &[u16]) -> Result<u16> {
+        let (start, end) = self.env.compute.port_range;
+        (start..=end)
+            .find(|port| {
+                !exclude.contains(port) && self.port_owner(*port).is_none() && port_is_free(*port)
+            })
+            .ok_or_else(|| {
+                categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!(
+                        "no free ports left in the compute port range [{start}, {end}], destroy unused endpoints or widen the range in the config"
+                    ),
+                )
+            })
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -127,22 +160,56 @@ impl ComputeControlPlane {
         pg_version: u32,
         mode: ComputeMode,
         region_id: RegionId,
+        template: Option<&str>,
+        pageserver_id: Option<NodeId>,
     ) -> Result<Arc<Endpoint>> {
-        let pg_port = pg_port.unwrap_or_else(|| self.get_port());
-        let http_port = http_port.unwrap_or_else(|| self.get_port() + 1);
+        let pageserver_conf = self.env.get_pageserver_conf(pageserver_id)?;
+        let pageserver = Arc::new(PageServerNode::from_env(&self.env, pageserver_conf));
+        let template = template
+            .map(|name| self.env.get_compute_template(name))
+            .transpose()?;
+        for name in template.iter().flat_map(|settings| settings.keys()) {
+            if MANAGED_SETTINGS.contains(&name.to_lowercase().as_str()) {
+                return Err(categorize(
+                    ErrorCategory::Config,
+                    anyhow!("compute template sets '{name}', which is managed by neon_local"),
+                ));
+            }
+        }
+
+        // Ports of an endpoint are assigned once, on creation, and stay the same
+        // across restarts, so don't hand out a port that another endpoint owns.
+        for port in pg_port.iter().chain(http_port.iter()) {
+            if let Some(owner) = self.port_owner(*port) {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!("port {port} is already assigned to endpoint {owner}"),
+                ));
+            }
+        }
+        let pg_port = match pg_port {
+            Some(port) => port,
+            None => self.get_port(&http_port.into_iter().collect::<Vec<_>>())?,
+        };
+        let http_port = match http_port {
+            Some(port) => port,
+            None => self.get_port(&[pg_port])?,
+        };
 
         let ep = Arc::new(Endpoint {
             endpoint_id: endpoint_id.to_owned(),
             pg_address: SocketAddr::new("127.0.0.1".parse().unwrap(), pg_port),
             http_address: SocketAddr::new("127.0.0.1".parse().unwrap(), http_port),
             env: self.env.clone(),
-            pageserver: Arc::clone(&self.pageserver),
+            pageserver,
             timeline_id,
             mode,
             tenant_id,
             pg_version,
             skip_pg_catalog_updates: false,
+            catalog_basebackup: false,
             region_id,
+            safekeepers: None,
         });
 
         ep.create_endpoint_dir()?;
@@ -157,19 +224,74 @@ impl ComputeControlPlane {
                 pg_port,
                 pg_version,
                 skip_pg_catalog_updates: false,
+                catalog_basebackup: false,
                 region_id,
+                pageserver_id: Some(ep.pageserver.conf.id),
+                safekeepers: None,
             })?,
         )?;
-        std::fs::write(
-            ep.endpoint_path().join("postgresql.conf"),
-            ep.setup_pg_conf()?.to_string(),
-        )?;
+        let mut conf = ep.setup_pg_conf()?;
+        for (name, value) in template.into_iter().flatten() {
+            conf.set(name, value);
+        }
+        std::fs::write(ep.endpoint_path().join("postgresql.conf"), conf.to_string())?;
 
         self.endpoints
             .insert(ep.endpoint_id.clone(), Arc::clone(&ep));
 
         Ok(ep)
     }
+
+    /// Points a stopped endpoint at another pageserver, from its next start on,
+    /// e.g. after its tenant has moved there.
+    pub fn set_pageserver(
+        &mut self,
+        endpoint_id: &str,
+        pageserver_id: NodeId,
+    ) -> Result<Arc<Endpoint>> {
+        let ep = self
+            .endpoints
+            .get(endpoint_id)
+            .ok_or_else(|| anyhow!("endpoint {endpoint_id} does not exist"))?;
+        if ep.status() != "stopped" {
+            return Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("endpoint {endpoint_id} is running, stop it first"),
+            ));
+        }
+        let pageserver = PageServerNode::from_env(
+            &self.env,
+            self.env.get_pageserver_conf(Some(pageserver_id))?,
+        );
+
+        let conf_path = ep.endpoint_path().join("endpoint.json");
+        let mut conf: EndpointConf = serde_json::from_slice(&std::fs::read(&conf_path)?)?;
+        conf.pageserver_id = Some(pageserver_id);
+        std::fs::write(&conf_path, serde_json::to_string_pretty(&conf)?)?;
+
+        let ep = Arc::new(Endpoint {
+            endpoint_id: ep.endpoint_id.clone(),
+            pg_address: ep.pg_address,
+            http_address: ep.http_address,
+            env: self.env.clone(),
+            pageserver: Arc::new(pageserver),
+            timeline_id: ep.timeline_id,
+            mode: ep.mode,
+            tenant_id: ep.tenant_id,
+            pg_version: ep.pg_version,
+            skip_pg_catalog_updates: ep.skip_pg_catalog_updates,
+            catalog_basebackup: ep.catalog_basebackup,
+            region_id: ep.region_id,
+            safekeepers: ep.safekeepers.clone(),
+        });
+        self.endpoints
+            .insert(ep.endpoint_id.clone(), Arc::clone(&ep));
+        Ok(ep)
+    }
+}
+
+fn port_is_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -187,7 +309,7 @@ pub struct Endpoint {
     pub http_address: SocketAddr,
 
     // postgres major version in the format: 14, 15, etc.
-    pg_version: u32,
+    pub pg_version: u32,
 
     // These are not part of the endpoint as such, but the environment
     // the endpoint runs in.
@@ -196,16 +318,14 @@ pub struct Endpoint {
 
     // Optimizations
     skip_pg_catalog_updates: bool,
+    catalog_basebackup: bool,
 
     region_id: RegionId,
+    safekeepers: Option<Vec<NodeId>>,
 }
 
 impl Endpoint {
-    fn from_dir_entry(
-        entry: std::fs::DirEntry,
-        env: &LocalEnv,
-        pageserver: &Arc<PageServerNode>,
-    ) -> Result<Endpoint> {
+    pub(crate) fn from_dir_entry(entry: std::fs::DirEntry, env: &LocalEnv) -> Result<Endpoint> {
         if !entry.file_type()?.is_dir() {
             anyhow::bail!(
                 "Endpoint::from_dir_entry failed: '{}' is not a directory",
@@ -220,19 +340,23 @@ impl Endpoint {
         // Read the endpoint.json file
         let conf: EndpointConf =
             serde_json::from_slice(&std::fs::read(entry.path().join("endpoint.json"))?)?;
+        let pageserver =
+            PageServerNode::from_env(env, env.get_pageserver_conf(conf.pageserver_id)?);
 
         Ok(Endpoint {
             pg_address: SocketAddr::new("127.0.0.1".parse().unwrap(), conf.pg_port),
             http_address: SocketAddr::new("127.0.0.1".parse().unwrap(), conf.http_port),
             endpoint_id,
             env: env.clone(),
-            pageserver: Arc::clone(pageserver),
+            pageserver: Arc::new(pageserver),
             timeline_id: conf.timeline_id,
             mode: conf.mode,
             tenant_id: conf.tenant_id,
             pg_version: conf.pg_version,
             skip_pg_catalog_updates: conf.skip_pg_catalog_updates,
+            catalog_basebackup: conf.catalog_basebackup,
             region_id: conf.region_id,
+            safekeepers: conf.safekeepers,
         })
     }
 
@@ -273,7 +397,10 @@ impl Endpoint {
         conf.append("max_prepared_transactions", "64");
         conf.append(
             "remotexact.connstring",
-            &format!("postgresql://{}", &self.env.xactserver.listen_pg_addr),
+            &format!(
+                "postgresql://{}",
+                self.env.xactserver_pg_addr(self.region_id)
+            ),
         );
         conf.append("remotexact.validate_index", "on");
         conf.append("remotexact.validate_table", "on");
@@ -308,12 +435,26 @@ impl Endpoint {
 
                     let safekeepers = self
                         .env
-                        .safekeepers
+                        .get_region_safekeepers(self.region_id)
                         .iter()
                         .map(|sk| format!("localhost:{}", sk.get_compute_port()))
                         .collect::<Vec<String>>()
                         .join(",");
                     conf.append("neon.safekeepers", &safekeepers);
+
+                    let walproposer = &self.env.walproposer;
+                    if let Some(ref timeout) = walproposer.election_timeout {
+                        conf.append("neon.safekeeper_connect_timeout", timeout);
+                    }
+                    if let Some(ref interval) = walproposer.heartbeat_interval {
+                        conf.append("neon.safekeeper_reconnect_timeout", interval);
+                    }
+                    if let Some(max_inflight_bytes) = walproposer.max_inflight_bytes {
+                        conf.append(
+                            "neon.safekeeper_max_inflight_bytes",
+                            &max_inflight_bytes.to_string(),
+                        );
+                    }
                 } else {
                     // We only use setup without safekeepers for tests,
                     // and don't care about data durability on pageserver,
@@ -352,9 +493,10 @@ impl Endpoint {
                     &self.tenant_id.to_string(),
                 );
 
-                let slot_name = format!("repl_{}_", self.timeline_id);
+                // Replicas read pages from the pageserver and need no WAL
+                // kept for them, so they don't use a replication slot, which
+                // would have to exist on the safekeepers.
                 conf.append("primary_conninfo", connstr.as_str());
-                conf.append("primary_slot_name", slot_name.as_str());
                 conf.append("hot_standby", "on");
                 // prefetching of blocks referenced in WAL doesn't make sense for us
                 // Neon hot standby ignores pages that are not in the shared_buffers
@@ -367,6 +509,14 @@ impl Endpoint {
         Ok(conf)
     }
 
+    pub fn pageserver(&self) -> &PageServerNode {
+        &self.pageserver
+    }
+
+    pub fn region_id(&self) -> RegionId {
+        self.region_id
+    }
+
     pub fn endpoint_path(&self) -> PathBuf {
         self.env.endpoints_path().join(&self.endpoint_id)
     }
@@ -446,14 +596,17 @@ impl Endpoint {
             );
         }
 
-        // Also wait for the compute_ctl process to die. It might have some cleanup
-        // work to do after postgres stops, like syncing safekeepers, etc.
-        //
+        self.wait_for_compute_ctl()
+    }
+
+    /// Waits for the compute_ctl process to die. It might have some cleanup
+    /// work to do after postgres stops, like syncing safekeepers, etc.
+    fn wait_for_compute_ctl(&self) -> Result<()> {
         // TODO use background_process::stop_process instead
-        let pidfile_path = self.endpoint_path().join("compute_ctl.pid");
+        let pidfile_path = self.endpoint_path().join(COMPUTE_CTL_PID_FILE);
         let pid: u32 = std::fs::read_to_string(pidfile_path)?.parse()?;
-        let pid = nix::unistd::Pid::from_raw(pid as i32);
-        crate::background_process::wait_until_stopped("compute_ctl", pid)?;
+        let pid = Pid::from_raw(pid as i32);
+        background_process::wait_until_stopped("compute_ctl", pid)?;
 
         Ok(())
     }
@@ -466,7 +619,25 @@ impl Endpoint {
         valgrind: Option<&String>,
     ) -> Result<()> {
         if self.status() == "running" {
-            anyhow::bail!("The endpoint is already running");
+            return Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("The endpoint is already running"),
+            ));
+        }
+
+        // Never move a stopped endpoint to other ports behind the user's back,
+        // clients remember its address.
+        for addr in [self.pg_address, self.http_address] {
+            if !port_is_free(addr.port()) {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!(
+                        "port {} of endpoint {} is in use by another process",
+                        addr.port(),
+                        self.endpoint_id
+                    ),
+                ));
+            }
         }
 
         // Slurp the endpoints/<endpoint id>/postgresql.conf file into
@@ -498,22 +669,20 @@ impl Endpoint {
             // NOTE: avoid spaces in connection string, because it is less error prone if we forward it somewhere.
             format!("postgresql://no_user@{host}:{port}")
         };
-        let mut safekeeper_connstrings = Vec::new();
-        if self.mode == ComputeMode::Primary {
-            for sk_id in safekeepers {
-                let sk = self
-                    .env
-                    .safekeepers
-                    .iter()
-                    .find(|node| node.id == sk_id)
-                    .ok_or_else(|| anyhow!("safekeeper {sk_id} does not exist"))?;
-                safekeeper_connstrings.push(format!("127.0.0.1:{}", sk.get_compute_port()));
-            }
-        }
+        let safekeeper_connstrings = if self.mode == ComputeMode::Primary {
+            self.env
+                .check_region_safekeepers(self.region_id, &safekeepers)?;
+            // Restarts and membership changes go on with the same safekeepers.
+            self.save_safekeepers(&safekeepers)?;
+            self.safekeeper_connstrings(&safekeepers)?
+        } else {
+            Vec::new()
+        };
 
         // Create spec file
         let spec = ComputeSpec {
             skip_pg_catalog_updates: self.skip_pg_catalog_updates,
+            catalog_basebackup: self.catalog_basebackup,
             format_version: 1.0,
             operation_uuid: None,
             cluster: Cluster {
@@ -578,7 +747,7 @@ impl Endpoint {
         // Write down the pid so we can wait for it when we want to stop
         // TODO use background_process::start_process instead
         let pid = child.id();
-        let pidfile_path = self.endpoint_path().join("compute_ctl.pid");
+        let pidfile_path = self.endpoint_path().join(COMPUTE_CTL_PID_FILE);
         std::fs::write(pidfile_path, pid.to_string())?;
 
         // Wait for it to start
@@ -658,7 +827,7 @@ impl Endpoint {
         }
     }
 
-    pub fn stop(&self, destroy: bool) -> Result<()> {
+    pub fn stop(&self, destroy: bool, timeout: Option<Duration>) -> Result<StopOutcome> {
         // If we are going to destroy data directory,
         // use immediate shutdown mode, otherwise,
         // shutdown gracefully to leave the data directory sane.
@@ -673,9 +842,197 @@ impl Endpoint {
                 self.pgdata().to_str().unwrap()
             );
             std::fs::remove_dir_all(self.endpoint_path())?;
+            Ok(StopOutcome::Immediate)
+        } else if let Some(timeout) = timeout {
+            self.stop_with_timeout(timeout)
         } else {
             self.pg_ctl(&["stop"], &None, None)?;
+            Ok(StopOutcome::Graceful)
+        }
+    }
+
+    /// Asks Postgres for a fast shutdown, and kills the postmaster if it
+    /// doesn't complete within the timeout.
+    fn stop_with_timeout(&self, timeout: Duration) -> Result<StopOutcome> {
+        // pg_ctl only takes whole seconds
+        let secs = timeout.as_secs().max(1).to_string();
+        let err = match self.pg_ctl(&["-m", "fast", "-t", &secs, "stop"], &None, None) {
+            Ok(()) => return Ok(StopOutcome::Graceful),
+            Err(e) => e,
+        };
+
+        // The first line of postmaster.pid is the pid of the postmaster. Without
+        // one, pg_ctl failed for some other reason than the timeout.
+        let Some(pid) = std::fs::read_to_string(self.pgdata().join("postmaster.pid"))
+            .ok()
+            .and_then(|contents| contents.lines().next()?.trim().parse::<i32>().ok())
+        else {
+            return Err(err);
+        };
+        println!("postgres did not stop in {secs} seconds, killing it");
+        let outcome = background_process::kill_process("postgres", Pid::from_raw(pid))?;
+        self.wait_for_compute_ctl()?;
+        Ok(outcome)
+    }
+
+    /// Changes settings in the postgresql.conf of the endpoint, which is kept
+    /// across restarts. A running endpoint is reloaded to pick them up.
+    ///
+    /// Returns the names of the changed settings that only take effect after
+    /// a restart.
+    pub fn reconfigure(&self, options: &[(String, String)]) -> Result<Vec<String>> {
+        for (name, _) in options {
+            if MANAGED_SETTINGS.contains(&name.to_lowercase().as_str()) {
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!("'{name}' is managed by neon_local and cannot be changed"),
+                ));
+            }
+        }
+
+        // The copy in the endpoint directory is used on the next start, the
+        // one in the data directory by the running Postgres.
+        let running = self.status() == "running";
+        let mut conf_paths = vec![self.endpoint_path().join("postgresql.conf")];
+        if running {
+            conf_paths.push(self.pgdata().join("postgresql.conf"));
         }
+        for path in conf_paths {
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            let mut conf = PostgresConf::read(file)?;
+            for (name, value) in options {
+                conf.set(name, value);
+            }
+            std::fs::write(&path, conf.to_string())
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+
+        if !running {
+            return Ok(Vec::new());
+        }
+        self.pg_ctl(&["reload"], &None, None)?;
+
+        let names: Vec<String> = options
+            .iter()
+            .map(|(name, _)| name.to_lowercase())
+            .collect();
+        let mut client = postgres::Client::connect(&self.connstr(), postgres::NoTls)?;
+        let rows = client.query(
+            "SELECT name FROM pg_settings WHERE context = 'postmaster' AND name = ANY($1)",
+            &[&names],
+        )?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// The safekeepers the endpoint writes its WAL to, unless told otherwise
+    /// on start: the ones it was last started with or moved to with
+    /// [`Endpoint::set_safekeepers`], or the safekeepers of its region, see
+    /// [`LocalEnv::get_region_safekeepers`].
+    pub fn safekeepers(&self) -> Vec<NodeId> {
+        self.safekeepers.clone().unwrap_or_else(|| {
+            self.env
+                .get_region_safekeepers(self.region_id)
+                .iter()
+                .map(|sk| sk.id)
+                .collect()
+        })
+    }
+
+    /// The sets of safekeepers the running endpoint writes its WAL to, as in
+    /// its postgresql.conf: the current one, and during a membership change
+    /// the next one as well. WAL is committed with a majority of each set.
+    pub fn running_safekeepers(&self) -> Result<Vec<Vec<NodeId>>> {
+        let path = self.pgdata().join("postgresql.conf");
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let conf = PostgresConf::read(file)?;
+        let mut sets = Vec::new();
+        for option in ["neon.safekeepers", "neon.safekeepers_next"] {
+            let Some(connstrings) = conf.get(option).filter(|s| !s.is_empty()) else {
+                continue;
+            };
+            let set = connstrings
+                .split(',')
+                .map(|connstring| {
+                    let port = connstring
+                        .rsplit_once(':')
+                        .and_then(|(_, port)| port.parse::<u16>().ok())
+                        .with_context(|| format!("invalid safekeeper address {connstring}"))?;
+                    self.env
+                        .safekeepers
+                        .iter()
+                        .find(|sk| sk.get_compute_port() == port)
+                        .map(|sk| sk.id)
+                        .with_context(|| format!("no safekeeper listens on {connstring}"))
+                })
+                .collect::<Result<_>>()?;
+            sets.push(set);
+        }
+        Ok(sets)
+    }
+
+    fn safekeeper_connstrings(&self, safekeepers: &[NodeId]) -> Result<Vec<String>> {
+        safekeepers
+            .iter()
+            .map(|sk_id| {
+                let sk = self
+                    .env
+                    .safekeepers
+                    .iter()
+                    .find(|node| node.id == *sk_id)
+                    .ok_or_else(|| anyhow!("safekeeper {sk_id} does not exist"))?;
+                Ok(format!("127.0.0.1:{}", sk.get_compute_port()))
+            })
+            .collect()
+    }
+
+    /// Points the walproposer of the running endpoint at the `current`
+    /// safekeepers, and during a membership change at the `next` ones as well,
+    /// see [`crate::safekeeper_membership`]. The walproposer restarts to pick
+    /// them up. With no `next` safekeepers, `current` ones are also used on
+    /// the next starts.
+    pub fn set_safekeepers(&self, current: &[NodeId], next: &[NodeId]) -> Result<()> {
+        if self.mode != ComputeMode::Primary || self.status() != "running" {
+            return Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("endpoint {} is not a running primary", self.endpoint_id),
+            ));
+        }
+
+        self.env.check_region_safekeepers(self.region_id, current)?;
+        if !next.is_empty() {
+            self.env.check_region_safekeepers(self.region_id, next)?;
+        }
+
+        let path = self.pgdata().join("postgresql.conf");
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut conf = PostgresConf::read(file)?;
+        conf.set(
+            "neon.safekeepers",
+            &self.safekeeper_connstrings(current)?.join(","),
+        );
+        conf.set(
+            "neon.safekeepers_next",
+            &self.safekeeper_connstrings(next)?.join(","),
+        );
+        std::fs::write(&path, conf.to_string())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        self.pg_ctl(&["reload"], &None, None)?;
+
+        if next.is_empty() {
+            self.save_safekeepers(current)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps `safekeepers` in endpoint.json, for [`Endpoint::safekeepers`].
+    fn save_safekeepers(&self, safekeepers: &[NodeId]) -> Result<()> {
+        let conf_path = self.endpoint_path().join("endpoint.json");
+        let mut conf: EndpointConf = serde_json::from_slice(&std::fs::read(&conf_path)?)?;
+        conf.safekeepers = Some(safekeepers.to_vec());
+        std::fs::write(&conf_path, serde_json::to_string_pretty(&conf)?)?;
         Ok(())
     }
 
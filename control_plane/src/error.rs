@@ -0,0 +1,192 @@
+//! Error categories of the `neon_local` CLI.
+//!
+//! Every failure of the CLI is mapped to one of a few categories, each with its
+//! own exit code, so that scripts and CI can tell e.g. an unreachable pageserver
+//! from a branch that doesn't exist without parsing stderr.
+//!
+//! Errors are categorized either explicitly, by wrapping them into a
+//! [`CategorizedError`] where they are raised, or by looking for well-known error
+//! types, like HTTP client errors, in the chain of causes.
+//!
+use std::fmt;
+use std::io;
+
+use crate::pageserver::PageserverHttpError;
+use crate::safekeeper::SafekeeperHttpError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Anything that doesn't fall into the categories below.
+    Other,
+    /// The config is missing, unreadable or invalid.
+    Config,
+    /// A service could not be reached.
+    Connection,
+    /// A tenant, timeline, branch, endpoint or node does not exist.
+    NotFound,
+    /// The operation is not possible in the current state, e.g. the endpoint is
+    /// already running.
+    PreconditionFailed,
+}
+
+impl ErrorCategory {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Other => 1,
+            ErrorCategory::Config => 2,
+            ErrorCategory::Connection => 3,
+            ErrorCategory::NotFound => 4,
+            ErrorCategory::PreconditionFailed => 5,
+        }
+    }
+
+    /// Finds the category of an error, looking through its chain of causes.
+    /// The outermost categorized error wins.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(Self::of_cause)
+            .unwrap_or(ErrorCategory::Other)
+    }
+
+    fn of_cause(cause: &(dyn std::error::Error + 'static)) -> Option<Self> {
+        if let Some(e) = cause.downcast_ref::<CategorizedError>() {
+            return Some(e.category);
+        }
+        if let Some(e) = cause.downcast_ref::<PageserverHttpError>() {
+            return match e {
+                PageserverHttpError::Transport(_) => Some(ErrorCategory::Connection),
+                PageserverHttpError::NotFound(_) => Some(ErrorCategory::NotFound),
+                PageserverHttpError::PreconditionFailed(_) => {
+                    Some(ErrorCategory::PreconditionFailed)
+                }
+                PageserverHttpError::Response(_) => None,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<SafekeeperHttpError>() {
+            return match e {
+                SafekeeperHttpError::Transport(_) => Some(ErrorCategory::Connection),
+                SafekeeperHttpError::NotFound(_) => Some(ErrorCategory::NotFound),
+                SafekeeperHttpError::PreconditionFailed(_) => {
+                    Some(ErrorCategory::PreconditionFailed)
+                }
+                SafekeeperHttpError::Response(_) => None,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_connect() || e.is_timeout() {
+                return Some(ErrorCategory::Connection);
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<postgres::Error>() {
+            if e.as_db_error().is_none() {
+                return Some(ErrorCategory::Connection);
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            if matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::TimedOut
+            ) {
+                return Some(ErrorCategory::Connection);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorCategory::Other => "error",
+            ErrorCategory::Config => "config error",
+            ErrorCategory::Connection => "connection error",
+            ErrorCategory::NotFound => "not found",
+            ErrorCategory::PreconditionFailed => "precondition failed",
+        })
+    }
+}
+
+/// An error tagged with a category.
+///
+/// It is displayed exactly like the wrapped error, so tagging an error doesn't
+/// change what the user sees, only the exit code.
+pub struct CategorizedError {
+    category: ErrorCategory,
+    inner: anyhow::Error,
+}
+
+impl fmt::Display for CategorizedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Debug for CategorizedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl std::error::Error for CategorizedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner.source()
+    }
+}
+
+/// Tags an error with a category.
+pub fn categorize(category: ErrorCategory, error: impl Into<anyhow::Error>) -> anyhow::Error {
+    anyhow::Error::new(CategorizedError {
+        category,
+        inner: error.into(),
+    })
+}
+
+pub trait CategorizeExt<T> {
+    /// Tags the error, if any, with a category.
+    fn categorize(self, category: ErrorCategory) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> CategorizeExt<T> for Result<T, E> {
+    fn categorize(self, category: ErrorCategory) -> anyhow::Result<T> {
+        self.map_err(|e| categorize(category, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn categories() {
+        let e = anyhow!("boom");
+        assert_eq!(ErrorCategory::of(&e), ErrorCategory::Other);
+
+        let e = categorize(ErrorCategory::NotFound, anyhow!("no such branch"));
+        assert_eq!(ErrorCategory::of(&e), ErrorCategory::NotFound);
+        assert_eq!(e.to_string(), "no such branch");
+
+        // Survives added context
+        let e = Err::<(), _>(e)
+            .context("while creating endpoint")
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of(&e), ErrorCategory::NotFound);
+
+        // The outermost category wins
+        let e = Err::<(), _>(e)
+            .categorize(ErrorCategory::PreconditionFailed)
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of(&e), ErrorCategory::PreconditionFailed);
+
+        let e = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionRefused))
+            .context("connecting to pageserver");
+        assert_eq!(ErrorCategory::of(&e), ErrorCategory::Connection);
+
+        let e = anyhow::Error::new(PageserverHttpError::NotFound("tenant".to_string()));
+        assert_eq!(ErrorCategory::of(&e).exit_code(), 4);
+    }
+}
@@ -8,9 +8,19 @@
 //
 
 mod background_process;
+pub mod bench;
 pub mod broker;
+pub mod doctor;
 pub mod endpoint;
+pub mod error;
 pub mod local_env;
 pub mod pageserver;
 pub mod postgresql_conf;
+pub mod progress;
+pub mod region_spec;
 pub mod safekeeper;
+pub mod safekeeper_membership;
+pub mod safekeeper_sync;
+pub mod scrape;
+pub mod tenant_migration;
+pub mod watch;
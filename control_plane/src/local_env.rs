@@ -3,13 +3,13 @@
 //! Now it also provides init method which acts like a stub for proper installation
 //! script which will use local paths.
 
-use anyhow::{bail, ensure, Context};
+use anyhow::{anyhow, bail, ensure, Context};
 
 use postgres_backend::AuthType;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::net::IpAddr;
@@ -17,11 +17,13 @@ use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use utils::{
-    auth::{encode_from_key_file, Claims},
+    auth::{encode_from_key_file_with_id, Claims},
     id::{NodeId, RegionId, TenantId, TenantTimelineId, TimelineId},
 };
 
+use crate::error::{categorize, ErrorCategory};
 use crate::safekeeper::SafekeeperNode;
 
 pub const DEFAULT_PG_VERSION: u32 = 15;
@@ -66,13 +68,40 @@ pub struct LocalEnv {
     #[serde(default)]
     pub private_key_path: PathBuf,
 
+    // Id of the private key, set once the keys have been rotated. Tokens name
+    // it so that services can pick the matching key out of `auth_public_keys`.
+    #[serde(default)]
+    pub auth_key_id: Option<String>,
+
     pub broker: NeonBroker,
 
-    pub pageserver: PageServerConf,
+    #[serde(default)]
+    pub pageservers: Vec<PageServerConf>,
+
+    // The single pageserver of configs written before multiple pageservers
+    // were supported. Moved into `pageservers` when the config is parsed.
+    #[serde(default, skip_serializing)]
+    pageserver: Option<PageServerConf>,
 
     #[serde(default)]
     pub safekeepers: Vec<SafekeeperConf>,
 
+    /// Regions of a multi-region deployment, so that endpoints can be placed
+    /// into a region by name instead of spelling out its topology each time.
+    #[serde(default)]
+    pub regions: Vec<RegionConf>,
+
+    #[serde(default)]
+    pub compute: ComputeConf,
+
+    #[serde(default)]
+    pub walproposer: WalproposerConf,
+
+    /// Named sets of postgres settings that endpoints can be created with,
+    /// e.g. an "analytics" template with a large work_mem.
+    #[serde(default)]
+    pub compute_templates: BTreeMap<String, BTreeMap<String, String>>,
+
     /// Keep human-readable aliases in memory (and persist them to config), to hide ZId hex strings from the user.
     #[serde(default)]
     // A `HashMap<String, HashMap<TenantId, TimelineId>>` would be more appropriate here,
@@ -146,6 +175,26 @@ pub struct SafekeeperConf {
     pub remote_storage: Option<String>,
     pub backup_threads: Option<u32>,
     pub auth_enabled: bool,
+    // How long to wait for more WAL before flushing it, e.g. '5ms'.
+    pub group_commit_window: Option<String>,
+    // Filters of the WAL streamed to other regions, e.g. '1:*/16384'.
+    pub region_wal_filters: Vec<String>,
+    // Bytes of WAL to keep before the point up to which nothing needs it.
+    pub wal_retention_margin: Option<u64>,
+    // Bytes of WAL the timelines of a tenant may take on disk together.
+    pub tenant_wal_quota: Option<u64>,
+    // Bytes per second of WAL accepted from the compute of each timeline, and
+    // how much of it may arrive at once over that rate.
+    pub ingest_rate_limit: Option<u64>,
+    pub ingest_burst: Option<u64>,
+    // How often to send keepalives to the walproposers, e.g. '1s'.
+    pub walproposer_keepalive_interval: Option<String>,
+    // Name of a group of safekeepers, e.g. of a region, started and stopped
+    // together with `--group`.
+    pub group: Option<String>,
+    // Id of the region the safekeeper is in. Once any safekeeper has one,
+    // primary endpoints write their WAL only to the safekeepers of their region.
+    pub region: Option<RegionId>,
 }
 
 impl Default for SafekeeperConf {
@@ -159,6 +208,15 @@ impl Default for SafekeeperConf {
             remote_storage: None,
             backup_threads: None,
             auth_enabled: false,
+            group_commit_window: None,
+            region_wal_filters: Vec::new(),
+            wal_retention_margin: None,
+            tenant_wal_quota: None,
+            ingest_rate_limit: None,
+            ingest_burst: None,
+            walproposer_keepalive_interval: None,
+            group: None,
+            region: None,
         }
     }
 }
@@ -171,12 +229,58 @@ impl SafekeeperConf {
     }
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(default)]
+pub struct ComputeConf {
+    // Inclusive range of ports that new endpoints get their Postgres and
+    // HTTP ports from, unless they are given explicitly.
+    pub port_range: (u16, u16),
+}
+
+impl Default for ComputeConf {
+    fn default() -> Self {
+        Self {
+            port_range: (55432, 55532),
+        }
+    }
+}
+
+/// Timing of the consensus between the walproposers of primary endpoints and
+/// the safekeepers, for tuning deployments with high RTT between regions.
+/// Unset values are left at the Postgres defaults.
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug)]
+#[serde(default)]
+pub struct WalproposerConf {
+    // How long a safekeeper may stay silent before the walproposer gives up
+    // on it and elects itself with the others, e.g. '10s'.
+    pub election_timeout: Option<String>,
+    // How often the walproposer sends heartbeats to the safekeepers and
+    // reconnects to the offline ones, e.g. '1s'.
+    pub heartbeat_interval: Option<String>,
+    // Bytes of WAL sent to a safekeeper that it may not have acknowledged
+    // yet. Not limited by default.
+    pub max_inflight_bytes: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug)]
 #[serde(default)]
 pub struct XactServerConf {
     pub listen_pg_addr: String,
 }
 
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Clone, Debug)]
+#[serde(default)]
+pub struct RegionConf {
+    pub id: RegionId,
+    pub name: String,
+    // Branches living in this region. The first one is used by default
+    // when an endpoint is started in the region.
+    pub branches: Vec<String>,
+    // Address of the xactserver of this region. If not set, the address
+    // from the [xactserver] section is used.
+    pub xactserver_pg_addr: Option<String>,
+}
+
 impl LocalEnv {
     pub fn pg_distrib_dir_raw(&self) -> PathBuf {
         self.pg_distrib_dir.clone()
@@ -223,9 +327,35 @@ impl LocalEnv {
         self.base_data_dir.join("endpoints")
     }
 
-    // TODO: move pageserver files into ./pageserver
-    pub fn pageserver_data_dir(&self) -> PathBuf {
-        self.base_data_dir.clone()
+    // TODO: move the files of the first pageserver into ./pageserver_<id> too
+    pub fn pageserver_data_dir(&self, pageserver_id: NodeId) -> PathBuf {
+        match self.pageservers.first() {
+            Some(first) if first.id != pageserver_id => self
+                .base_data_dir
+                .join(format!("pageserver_{pageserver_id}")),
+            _ => self.base_data_dir.clone(),
+        }
+    }
+
+    /// Returns the config of the pageserver with the given id, or of the first
+    /// pageserver if no id is given.
+    pub fn get_pageserver_conf(
+        &self,
+        pageserver_id: Option<NodeId>,
+    ) -> anyhow::Result<&PageServerConf> {
+        let conf = match pageserver_id {
+            Some(id) => self.pageservers.iter().find(|ps| ps.id == id),
+            None => self.pageservers.first(),
+        };
+        conf.ok_or_else(|| {
+            categorize(
+                ErrorCategory::NotFound,
+                match pageserver_id {
+                    Some(id) => anyhow!("could not find pageserver {id}"),
+                    None => anyhow!("no pageservers are configured"),
+                },
+            )
+        })
     }
 
     pub fn safekeeper_data_dir(&self, data_dir_name: &str) -> PathBuf {
@@ -260,6 +390,56 @@ impl LocalEnv {
         }
     }
 
+    /// Renames a branch of a tenant. Branch names are only known to
+    /// neon_local, so this doesn't involve the pageserver.
+    ///
+    /// Regions that list the branch are updated too, once no other tenant
+    /// uses the old name.
+    pub fn rename_branch(
+        &mut self,
+        tenant_id: TenantId,
+        old_name: &str,
+        new_name: &str,
+    ) -> anyhow::Result<()> {
+        ensure!(!new_name.is_empty(), "branch name cannot be empty");
+        if let Some((timeline_id, _)) = self.get_branch_timeline_id(new_name, tenant_id) {
+            bail!("branch '{new_name}' already exists and is mapped to timeline {timeline_id}");
+        }
+
+        let tenant_timelines = self.branch_name_mappings.get_mut(old_name);
+        let position = tenant_timelines.as_ref().and_then(|tenant_timelines| {
+            tenant_timelines
+                .iter()
+                .position(|(mapped_tenant_id, _, _)| mapped_tenant_id == &tenant_id)
+        });
+        let (Some(tenant_timelines), Some(position)) = (tenant_timelines, position) else {
+            return Err(categorize(
+                ErrorCategory::NotFound,
+                anyhow!("branch '{old_name}' does not exist in tenant {tenant_id}"),
+            ));
+        };
+        let mapping = tenant_timelines.remove(position);
+        let old_name_unused = tenant_timelines.is_empty();
+        if old_name_unused {
+            self.branch_name_mappings.remove(old_name);
+        }
+        self.branch_name_mappings
+            .entry(new_name.to_string())
+            .or_default()
+            .push(mapping);
+
+        if old_name_unused {
+            for region in &mut self.regions {
+                for branch in &mut region.branches {
+                    if branch == old_name {
+                        *branch = new_name.to_string();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_branch_timeline_id(
         &self,
         branch_name: &str,
@@ -272,6 +452,97 @@ impl LocalEnv {
             .map(|&(_, timeline_id, region_id)| (timeline_id, region_id))
     }
 
+    pub fn get_compute_template(&self, name: &str) -> anyhow::Result<&BTreeMap<String, String>> {
+        self.compute_templates.get(name).ok_or_else(|| {
+            categorize(
+                ErrorCategory::NotFound,
+                anyhow!("compute template '{name}' is not defined in the config"),
+            )
+        })
+    }
+
+    pub fn get_region(&self, region_id: RegionId) -> Option<&RegionConf> {
+        self.regions.iter().find(|region| region.id == region_id)
+    }
+
+    pub fn get_region_by_name(&self, name: &str) -> anyhow::Result<&RegionConf> {
+        self.regions
+            .iter()
+            .find(|region| region.name == name)
+            .with_context(|| format!("region '{name}' is not defined in the config"))
+    }
+
+    pub fn get_safekeeper_group(&self, name: &str) -> anyhow::Result<Vec<&SafekeeperConf>> {
+        let group: Vec<_> = self
+            .safekeepers
+            .iter()
+            .filter(|sk| sk.group.as_deref() == Some(name))
+            .collect();
+        if group.is_empty() {
+            return Err(categorize(
+                ErrorCategory::NotFound,
+                anyhow!("safekeeper group '{name}' is not defined in the config"),
+            ));
+        }
+        Ok(group)
+    }
+
+    /// The safekeepers that primary endpoints of the given region write their
+    /// WAL to by default: the ones in the region, or all of them if none has a
+    /// region.
+    pub fn get_region_safekeepers(&self, region_id: RegionId) -> Vec<&SafekeeperConf> {
+        if self.safekeepers.iter().all(|sk| sk.region.is_none()) {
+            return self.safekeepers.iter().collect();
+        }
+        self.safekeepers
+            .iter()
+            .filter(|sk| sk.region == Some(region_id))
+            .collect()
+    }
+
+    /// Checks that an endpoint of the given region may write its WAL to the
+    /// given safekeepers, i.e. they exist and none is in another region.
+    pub fn check_region_safekeepers(
+        &self,
+        region_id: RegionId,
+        safekeepers: &[NodeId],
+    ) -> anyhow::Result<()> {
+        if safekeepers.is_empty() && !self.safekeepers.is_empty() {
+            return Err(categorize(
+                ErrorCategory::Config,
+                anyhow!("no safekeepers are configured for region {region_id}"),
+            ));
+        }
+        for id in safekeepers {
+            let sk = self
+                .safekeepers
+                .iter()
+                .find(|sk| sk.id == *id)
+                .ok_or_else(|| {
+                    categorize(
+                        ErrorCategory::NotFound,
+                        anyhow!("safekeeper {id} does not exist"),
+                    )
+                })?;
+            if let Some(sk_region_id) = sk.region.filter(|sk_region| *sk_region != region_id) {
+                return Err(categorize(
+                    ErrorCategory::Config,
+                    anyhow!(
+                        "safekeeper {id} is in region {sk_region_id}, not in region {region_id}"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Address of the xactserver that endpoints of the given region talk to.
+    pub fn xactserver_pg_addr(&self, region_id: RegionId) -> &str {
+        self.get_region(region_id)
+            .and_then(|region| region.xactserver_pg_addr.as_deref())
+            .unwrap_or(&self.xactserver.listen_pg_addr)
+    }
+
     pub fn timeline_name_mappings(&self) -> HashMap<TenantTimelineId, String> {
         self.branch_name_mappings
             .iter()
@@ -290,6 +561,30 @@ impl LocalEnv {
     pub fn parse_config(toml: &str) -> anyhow::Result<Self> {
         let mut env: LocalEnv = toml::from_str(toml)?;
 
+        if let Some(pageserver) = env.pageserver.take() {
+            ensure!(
+                env.pageservers.is_empty(),
+                "both [pageserver] and [[pageservers]] are defined in the config, use only [[pageservers]]"
+            );
+            env.pageservers.push(pageserver);
+        }
+        ensure!(!env.pageservers.is_empty(), "no pageservers are configured");
+        for (i, pageserver) in env.pageservers.iter().enumerate() {
+            ensure!(
+                env.pageservers[..i]
+                    .iter()
+                    .all(|other| other.id != pageserver.id),
+                "pageserver id {} is used more than once",
+                pageserver.id
+            );
+        }
+        env.validate_regions()?;
+        let (port_range_start, port_range_end) = env.compute.port_range;
+        ensure!(
+            port_range_start < port_range_end,
+            "compute port range [{port_range_start}, {port_range_end}] must contain at least two ports"
+        );
+
         // Find postgres binaries.
         // Follow POSTGRES_DISTRIB_DIR if set, otherwise look in "pg_install".
         // Note that later in the code we assume, that distrib dirs follow the same pattern
@@ -370,14 +665,107 @@ impl LocalEnv {
 
     // this function is used only for testing purposes in CLI e g generate tokens during init
     pub fn generate_auth_token(&self, claims: &Claims) -> anyhow::Result<String> {
+        encode_from_key_file_with_id(
+            claims,
+            &self.read_private_key()?,
+            self.auth_key_id.as_deref(),
+        )
+    }
+
+    /// Like [`Self::generate_auth_token`], but the token expires after `ttl`,
+    /// for the tokens handed out to external tools.
+    pub fn generate_expiring_auth_token(
+        &self,
+        claims: &Claims,
+        ttl: Duration,
+    ) -> anyhow::Result<String> {
+        let exp = (SystemTime::now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .context("token expiration is before the epoch")?
+            .as_secs();
+        let mut payload = serde_json::to_value(claims)?;
+        payload["exp"] = exp.into();
+        encode_from_key_file_with_id(
+            &payload,
+            &self.read_private_key()?,
+            self.auth_key_id.as_deref(),
+        )
+    }
+
+    /// The directory with the public keys of all the key pairs that tokens
+    /// are accepted from, once the keys have been rotated.
+    pub fn auth_public_keys_dir(&self) -> PathBuf {
+        self.base_data_dir.join("auth_public_keys")
+    }
+
+    /// The public key, or the directory with the public keys, for the services
+    /// to validate tokens with.
+    pub fn auth_public_key_path(&self) -> PathBuf {
+        let keys_dir = self.auth_public_keys_dir();
+        if keys_dir.is_dir() {
+            keys_dir
+        } else {
+            self.base_data_dir.join("auth_public_key.pem")
+        }
+    }
+
+    /// Generates a new key pair to sign tokens with. The previous public keys
+    /// remain valid, so that the tokens issued before keep working until their
+    /// keys are removed from [`Self::auth_public_keys_dir`].
+    ///
+    /// Returns the id of the new key. The services need to be restarted to
+    /// accept tokens signed with it.
+    pub fn rotate_auth_keys(&mut self) -> anyhow::Result<String> {
+        let keys_dir = self.auth_public_keys_dir();
+        if !keys_dir.exists() {
+            fs::create_dir(&keys_dir)
+                .with_context(|| format!("failed to create '{}'", keys_dir.display()))?;
+            // Keep accepting the tokens signed with the key from 'init'.
+            let initial_key = self.base_data_dir.join("auth_public_key.pem");
+            if initial_key.exists() {
+                fs::copy(&initial_key, keys_dir.join("initial.pem"))
+                    .with_context(|| format!("failed to copy '{}'", initial_key.display()))?;
+            }
+        }
+
+        let key_id = format!(
+            "key-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .context("clock is before the epoch")?
+                .as_secs()
+        );
+        let public_key_path = keys_dir.join(format!("{key_id}.pem"));
+        if public_key_path.exists() {
+            return Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("key '{key_id}' already exists, the keys were just rotated"),
+            ));
+        }
+        let private_key_path = PathBuf::from(format!("auth_private_key_{key_id}.pem"));
+        generate_auth_keys(
+            &self.base_data_dir.join(&private_key_path),
+            &public_key_path,
+        )?;
+
+        self.private_key_path = private_key_path;
+        self.auth_key_id = Some(key_id.clone());
+        Ok(key_id)
+    }
+
+    fn read_private_key(&self) -> anyhow::Result<Vec<u8>> {
         let private_key_path = if self.private_key_path.is_absolute() {
             self.private_key_path.to_path_buf()
         } else {
             self.base_data_dir.join(&self.private_key_path)
         };
 
-        let key_data = fs::read(private_key_path)?;
-        encode_from_key_file(claims, &key_data)
+        fs::read(&private_key_path).with_context(|| {
+            format!(
+                "failed to read the auth private key '{}'",
+                private_key_path.display()
+            )
+        })
     }
 
     //
@@ -407,10 +795,13 @@ impl LocalEnv {
                     }
                 }
             } else {
-                bail!(
-                    "directory '{}' already exists. Perhaps already initialized? (Hint: use --force to remove all contents)",
-                    base_path.display()
-                );
+                return Err(categorize(
+                    ErrorCategory::PreconditionFailed,
+                    anyhow!(
+                        "directory '{}' already exists. Perhaps already initialized? (Hint: use --force to remove all contents)",
+                        base_path.display()
+                    ),
+                ));
             }
         }
 
@@ -462,6 +853,10 @@ impl LocalEnv {
 
         fs::create_dir_all(self.endpoints_path())?;
 
+        for pageserver in &self.pageservers {
+            fs::create_dir_all(self.pageserver_data_dir(pageserver.id))?;
+        }
+
         for safekeeper in &self.safekeepers {
             fs::create_dir_all(SafekeeperNode::datadir_path_by_id(self, safekeeper.id))?;
         }
@@ -469,10 +864,44 @@ impl LocalEnv {
         self.persist_config(base_path)
     }
 
+    pub fn validate_regions(&self) -> anyhow::Result<()> {
+        for (i, region) in self.regions.iter().enumerate() {
+            ensure!(!region.name.is_empty(), "region {} has no name", region.id);
+            for other in &self.regions[..i] {
+                ensure!(
+                    other.id != region.id,
+                    "region id {} is used by both '{}' and '{}'",
+                    region.id,
+                    other.name,
+                    region.name
+                );
+                ensure!(
+                    other.name != region.name,
+                    "region name '{}' is defined more than once",
+                    region.name
+                );
+            }
+        }
+        // Without a region catalog, the regions are only known once an
+        // endpoint is given a region spec.
+        if !self.regions.is_empty() {
+            for sk in &self.safekeepers {
+                if let Some(region_id) = sk.region {
+                    ensure!(
+                        self.get_region(region_id).is_some(),
+                        "safekeeper {} is in region {region_id}, which is not defined in the config",
+                        sk.id
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn auth_keys_needed(&self) -> bool {
-        self.pageserver.pg_auth_type == AuthType::NeonJWT
-            || self.pageserver.http_auth_type == AuthType::NeonJWT
-            || self.safekeepers.iter().any(|sk| sk.auth_enabled)
+        self.pageservers.iter().any(|ps| {
+            ps.pg_auth_type == AuthType::NeonJWT || ps.http_auth_type == AuthType::NeonJWT
+        }) || self.safekeepers.iter().any(|sk| sk.auth_enabled)
     }
 }
 
@@ -546,4 +975,202 @@ mod tests {
             "expected toml with invalid Url {spoiled_url_toml} to fail the parsing, but got {spoiled_url_parse_result:?}"
         );
     }
+
+    #[test]
+    fn multiple_pageservers_parsing() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let env = LocalEnv::parse_config(simple_conf_toml).unwrap();
+        assert_eq!(env.pageservers.len(), 1, "legacy [pageserver] section");
+
+        let pageservers_toml = simple_conf_toml.replace("[pageserver]", "[[pageservers]]\nid = 1");
+        let env = LocalEnv::parse_config(&format!(
+            "{pageservers_toml}\n[[pageservers]]\nid = 2\nlisten_pg_addr = '127.0.0.1:64001'\nlisten_http_addr = '127.0.0.1:9899'\n"
+        ))
+        .unwrap();
+        assert_eq!(env.get_pageserver_conf(None).unwrap().id, NodeId(1));
+        assert_eq!(
+            env.get_pageserver_conf(Some(NodeId(2)))
+                .unwrap()
+                .listen_http_addr,
+            "127.0.0.1:9899"
+        );
+        assert!(env.get_pageserver_conf(Some(NodeId(3))).is_err());
+        assert_eq!(env.pageserver_data_dir(NodeId(1)), env.base_data_dir);
+        assert_eq!(
+            env.pageserver_data_dir(NodeId(2)),
+            env.base_data_dir.join("pageserver_2")
+        );
+
+        assert!(
+            LocalEnv::parse_config(&format!("{pageservers_toml}\n[[pageservers]]\nid = 1\n"))
+                .is_err(),
+            "expected a duplicate pageserver id to fail the parsing"
+        );
+        assert!(
+            LocalEnv::parse_config(&format!("{simple_conf_toml}\n[[pageservers]]\nid = 2\n"))
+                .is_err(),
+            "expected both [pageserver] and [[pageservers]] to fail the parsing"
+        );
+    }
+
+    #[test]
+    fn region_catalog_parsing() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let regions_toml = format!(
+            r#"{simple_conf_toml}
+[[regions]]
+id = 0
+name = 'global'
+branches = ['main']
+
+[[regions]]
+id = 1
+name = 'us-east'
+branches = ['us-east-main']
+xactserver_pg_addr = '127.0.0.1:10001'
+"#
+        );
+        let env = LocalEnv::parse_config(&regions_toml).unwrap();
+        let region = env.get_region_by_name("us-east").unwrap();
+        assert_eq!(region.id, RegionId(1));
+        assert_eq!(region.branches, vec!["us-east-main".to_string()]);
+        assert_eq!(env.xactserver_pg_addr(RegionId(1)), "127.0.0.1:10001");
+        assert!(env.get_region_by_name("eu-west").is_err());
+
+        let duplicate_toml = regions_toml.replace("id = 1", "id = 0");
+        assert!(
+            LocalEnv::parse_config(&duplicate_toml).is_err(),
+            "expected duplicate region ids to fail the parsing"
+        );
+    }
+
+    #[test]
+    fn safekeeper_regions() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let env = LocalEnv::parse_config(simple_conf_toml).unwrap();
+        assert_eq!(env.get_region_safekeepers(RegionId(1)).len(), 1);
+        env.check_region_safekeepers(RegionId(1), &[NodeId(1)])
+            .unwrap();
+
+        let regions_toml = format!(
+            r#"{}
+[[safekeepers]]
+id = 2
+pg_port = 5455
+http_port = 7677
+region = 1
+
+[[safekeepers]]
+id = 3
+pg_port = 5456
+http_port = 7678
+region = 1
+
+[[regions]]
+id = 0
+name = 'global'
+branches = ['main']
+
+[[regions]]
+id = 1
+name = 'us-east'
+branches = ['us-east-main']
+"#,
+            simple_conf_toml.replace("http_port = 7676\n", "http_port = 7676\nregion = 0\n")
+        );
+        let env = LocalEnv::parse_config(&regions_toml).unwrap();
+        let ids = |region_id| -> Vec<NodeId> {
+            env.get_region_safekeepers(region_id)
+                .iter()
+                .map(|sk| sk.id)
+                .collect()
+        };
+        assert_eq!(ids(RegionId(0)), vec![NodeId(1)]);
+        assert_eq!(ids(RegionId(1)), vec![NodeId(2), NodeId(3)]);
+        assert_eq!(ids(RegionId(2)), vec![]);
+
+        env.check_region_safekeepers(RegionId(1), &[NodeId(2), NodeId(3)])
+            .unwrap();
+        assert!(env
+            .check_region_safekeepers(RegionId(1), &[NodeId(1), NodeId(2)])
+            .is_err());
+        assert!(env.check_region_safekeepers(RegionId(2), &[]).is_err());
+        assert!(env
+            .check_region_safekeepers(RegionId(1), &[NodeId(4)])
+            .is_err());
+
+        let undefined_toml = regions_toml.replace("region = 1", "region = 2");
+        assert!(
+            LocalEnv::parse_config(&undefined_toml).is_err(),
+            "expected a safekeeper in an undefined region to fail the parsing"
+        );
+    }
+
+    #[test]
+    fn branch_rename() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let mut env = LocalEnv::parse_config(&format!(
+            "{simple_conf_toml}\n[[regions]]\nid = 1\nname = 'r1'\nbranches = ['old']\n"
+        ))
+        .unwrap();
+        let tenant_1 = TenantId::generate();
+        let tenant_2 = TenantId::generate();
+        let timeline_1 = TimelineId::generate();
+        let timeline_2 = TimelineId::generate();
+        env.register_branch_mapping("old".to_string(), tenant_1, timeline_1, RegionId(1))
+            .unwrap();
+        env.register_branch_mapping("old".to_string(), tenant_2, timeline_2, RegionId(1))
+            .unwrap();
+        env.register_branch_mapping("taken".to_string(), tenant_1, timeline_2, RegionId(0))
+            .unwrap();
+
+        assert!(env.rename_branch(tenant_1, "old", "taken").is_err());
+        assert!(env.rename_branch(tenant_1, "missing", "new").is_err());
+
+        env.rename_branch(tenant_1, "old", "new").unwrap();
+        assert_eq!(env.get_branch_timeline_id("old", tenant_1), None);
+        assert_eq!(
+            env.get_branch_timeline_id("new", tenant_1),
+            Some((timeline_1, RegionId(1)))
+        );
+        // Still used by the other tenant
+        assert_eq!(env.get_region(RegionId(1)).unwrap().branches, vec!["old"]);
+
+        env.rename_branch(tenant_2, "old", "new").unwrap();
+        assert_eq!(env.get_region(RegionId(1)).unwrap().branches, vec!["new"]);
+    }
+
+    #[test]
+    fn compute_port_range_parsing() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let env = LocalEnv::parse_config(simple_conf_toml).unwrap();
+        assert_eq!(env.compute.port_range, (55432, 55532));
+
+        let env = LocalEnv::parse_config(&format!(
+            "{simple_conf_toml}\n[compute]\nport_range = [60000, 60100]\n"
+        ))
+        .unwrap();
+        assert_eq!(env.compute.port_range, (60000, 60100));
+
+        assert!(
+            LocalEnv::parse_config(&format!(
+                "{simple_conf_toml}\n[compute]\nport_range = [60100, 60000]\n"
+            ))
+            .is_err(),
+            "expected an empty port range to fail the parsing"
+        );
+    }
+
+    #[test]
+    fn compute_templates_parsing() {
+        let simple_conf_toml = include_str!("../simple.conf");
+        let env = LocalEnv::parse_config(&format!(
+            "{simple_conf_toml}\n[compute_templates.analytics]\nwork_mem = '1GB'\nmax_parallel_workers = '16'\n"
+        ))
+        .unwrap();
+        let template = env.get_compute_template("analytics").unwrap();
+        assert_eq!(template.get("work_mem").map(String::as_str), Some("1GB"));
+        assert_eq!(template.len(), 2);
+        assert!(env.get_compute_template("oltp").is_err());
+    }
 }
@@ -7,18 +7,20 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Write};
-use std::num::NonZeroU64;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 use std::{io, result};
 
-use anyhow::{bail, Context};
-use pageserver_api::models::{self, TenantInfo, TimelineInfo};
+use anyhow::{anyhow, bail, Context};
+use pageserver_api::models::{self, ProgressFrame, TenantInfo, TimelineInfo, TimelineScrubReport};
 use postgres_backend::AuthType;
 use postgres_connection::{parse_host_port, PgConnectionConfig};
 use reqwest::blocking::{Client, RequestBuilder, Response};
-use reqwest::{IntoUrl, Method};
+use reqwest::{IntoUrl, Method, StatusCode};
 use thiserror::Error;
 use utils::auth::{Claims, Scope};
 use utils::{
@@ -27,7 +29,11 @@ use utils::{
     lsn::Lsn,
 };
 
-use crate::{background_process, local_env::LocalEnv};
+use crate::background_process::{self, StopOutcome};
+use crate::error::{categorize, ErrorCategory};
+use crate::local_env::{LocalEnv, PageServerConf};
+use crate::progress::report_while;
+use crate::scrape;
 
 #[derive(Error, Debug)]
 pub enum PageserverHttpError {
@@ -36,6 +42,12 @@ pub enum PageserverHttpError {
 
     #[error("Error: {0}")]
     Response(String),
+
+    #[error("Error: {0}")]
+    NotFound(String),
+
+    #[error("Error: {0}")]
+    PreconditionFailed(String),
 }
 
 impl From<anyhow::Error> for PageserverHttpError {
@@ -46,6 +58,17 @@ impl From<anyhow::Error> for PageserverHttpError {
 
 type Result<T> = result::Result<T, PageserverHttpError>;
 
+/// How long to wait for an attached tenant to become active, in 100ms steps.
+const TENANT_ATTACH_WAIT_RETRIES: u32 = 600;
+
+/// How long a transfer of a timeline archive may take. Archives of large
+/// timelines take longer than the default request timeout.
+const TIMELINE_ARCHIVE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// How long a wait_lsn request may take on top of its timeout, for flushing
+/// the WAL to disk once it is ingested.
+const WAIT_LSN_FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub trait ResponseErrorMessageExt: Sized {
     fn error_from_body(self) -> Result<Self>;
 }
@@ -59,60 +82,81 @@ impl ResponseErrorMessageExt for Response {
 
         // reqwest does not export its error construction utility functions, so let's craft the message ourselves
         let url = self.url().to_owned();
-        Err(PageserverHttpError::Response(
-            match self.json::<HttpErrorBody>() {
-                Ok(err_body) => format!("Error: {}", err_body.msg),
-                Err(_) => format!("Http error ({}) at {}.", status.as_u16(), url),
-            },
-        ))
+        let msg = match self.json::<HttpErrorBody>() {
+            Ok(err_body) => format!("Error: {}", err_body.msg),
+            Err(_) => format!("Http error ({}) at {}.", status.as_u16(), url),
+        };
+        Err(http_error(status, msg))
     }
 }
 
+fn http_error(status: StatusCode, msg: String) -> PageserverHttpError {
+    match status {
+        StatusCode::NOT_FOUND => PageserverHttpError::NotFound(msg),
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+            PageserverHttpError::PreconditionFailed(msg)
+        }
+        _ => PageserverHttpError::Response(msg),
+    }
+}
+
+/// What `neon_local pageserver status` reports about a running pageserver.
+pub struct PageServerStatus {
+    pub version: Option<String>,
+    pub launched_at: Option<SystemTime>,
+    pub active_tenants: usize,
+    pub timelines: usize,
+    /// Number of timelines that are streaming WAL from a safekeeper.
+    pub wal_receiver_connections: usize,
+    pub last_checkpoint_at: Option<SystemTime>,
+    pub last_gc_at: Option<SystemTime>,
+}
+
 //
 // Control routines for pageserver.
 //
-// Used in CLI and tests.
+// Used in CLI and tests. All management requests go through the HTTP API of
+// the pageserver, described in pageserver/src/http/openapi_spec.yml, with the
+// request and response types of pageserver_api; the libpq port is only used by
+// the computes.
 //
 #[derive(Debug)]
 pub struct PageServerNode {
     pub pg_connection_config: PgConnectionConfig,
+    pub conf: PageServerConf,
     pub env: LocalEnv,
     pub http_client: Client,
     pub http_base_url: String,
 }
 
 impl PageServerNode {
-    pub fn from_env(env: &LocalEnv) -> PageServerNode {
-        let (host, port) = parse_host_port(&env.pageserver.listen_pg_addr)
-            .expect("Unable to parse listen_pg_addr");
+    pub fn from_env(env: &LocalEnv, conf: &PageServerConf) -> PageServerNode {
+        let (host, port) =
+            parse_host_port(&conf.listen_pg_addr).expect("Unable to parse listen_pg_addr");
         let port = port.unwrap_or(5432);
         Self {
             pg_connection_config: PgConnectionConfig::new_host_port(host, port),
+            conf: conf.clone(),
             env: env.clone(),
             http_client: Client::new(),
-            http_base_url: format!("http://{}/v1", env.pageserver.listen_http_addr),
+            http_base_url: format!("http://{}/v1", conf.listen_http_addr),
         }
     }
 
     // pageserver conf overrides defined by neon_local configuration.
     fn neon_local_overrides(&self) -> Vec<String> {
-        let id = format!("id={}", self.env.pageserver.id);
+        let id = format!("id={}", self.conf.id);
         // FIXME: the paths should be shell-escaped to handle paths with spaces, quotas etc.
         let pg_distrib_dir_param = format!(
             "pg_distrib_dir='{}'",
             self.env.pg_distrib_dir_raw().display()
         );
 
-        let http_auth_type_param =
-            format!("http_auth_type='{}'", self.env.pageserver.http_auth_type);
-        let listen_http_addr_param = format!(
-            "listen_http_addr='{}'",
-            self.env.pageserver.listen_http_addr
-        );
+        let http_auth_type_param = format!("http_auth_type='{}'", self.conf.http_auth_type);
+        let listen_http_addr_param = format!("listen_http_addr='{}'", self.conf.listen_http_addr);
 
-        let pg_auth_type_param = format!("pg_auth_type='{}'", self.env.pageserver.pg_auth_type);
-        let listen_pg_addr_param =
-            format!("listen_pg_addr='{}'", self.env.pageserver.listen_pg_addr);
+        let pg_auth_type_param = format!("pg_auth_type='{}'", self.conf.pg_auth_type);
+        let listen_pg_addr_param = format!("listen_pg_addr='{}'", self.conf.listen_pg_addr);
 
         let broker_endpoint_param = format!("broker_endpoint='{}'", self.env.broker.client_url());
 
@@ -126,10 +170,12 @@ impl PageServerNode {
             broker_endpoint_param,
         ];
 
-        if self.env.pageserver.http_auth_type != AuthType::Trust
-            || self.env.pageserver.pg_auth_type != AuthType::Trust
+        if self.conf.http_auth_type != AuthType::Trust || self.conf.pg_auth_type != AuthType::Trust
         {
-            overrides.push("auth_validation_public_key_path='auth_public_key.pem'".to_owned());
+            overrides.push(format!(
+                "auth_validation_public_key_path='{}'",
+                self.env.auth_public_key_path().display()
+            ));
         }
         overrides
     }
@@ -137,22 +183,45 @@ impl PageServerNode {
     /// Initializes a pageserver node by creating its config with the overrides provided.
     pub fn initialize(&self, config_overrides: &[&str]) -> anyhow::Result<()> {
         // First, run `pageserver --init` and wait for it to write a config into FS and exit.
-        self.pageserver_init(config_overrides).with_context(|| {
-            format!(
-                "Failed to run init for pageserver node {}",
-                self.env.pageserver.id,
-            )
-        })
+        self.pageserver_init(config_overrides)
+            .with_context(|| format!("Failed to run init for pageserver node {}", self.conf.id,))
+    }
+
+    /// Seeds a freshly initialized pageserver with a new tenant, whose initial
+    /// timeline is imported from a basebackup tarball instead of being
+    /// bootstrapped with initdb. The pageserver is only started for the import.
+    pub fn bootstrap_from_basebackup(
+        &self,
+        config_overrides: &[&str],
+        base: (Lsn, PathBuf),
+        pg_version: u32,
+    ) -> anyhow::Result<(TenantId, TimelineId)> {
+        self.start(config_overrides)?;
+        let imported = (|| {
+            let tenant_id = self.tenant_create(None, HashMap::new())?;
+            let timeline_id = TimelineId::generate();
+            self.timeline_import(tenant_id, timeline_id, base, None, pg_version)?;
+            anyhow::Ok((tenant_id, timeline_id))
+        })();
+        let stopped = self.stop(false, None);
+        let ids = imported.context("Failed to import the basebackup")?;
+        stopped?;
+        Ok(ids)
     }
 
     pub fn repo_path(&self) -> PathBuf {
-        self.env.pageserver_data_dir()
+        self.env.pageserver_data_dir(self.conf.id)
+    }
+
+    /// The directory of the local files of a tenant.
+    pub fn tenant_path(&self, tenant_id: TenantId) -> PathBuf {
+        self.repo_path().join("tenants").join(tenant_id.to_string())
     }
 
     /// The pid file is created by the pageserver process, with its pid stored inside.
     /// Other pageservers cannot lock the same file and overwrite it for as long as the current
     /// pageserver runs. (Unless someone removes the file manually; never do that!)
-    fn pid_file(&self) -> PathBuf {
+    pub fn pid_file(&self) -> PathBuf {
         self.repo_path().join("pageserver.pid")
     }
 
@@ -162,7 +231,7 @@ impl PageServerNode {
 
     fn pageserver_init(&self, config_overrides: &[&str]) -> anyhow::Result<()> {
         let datadir = self.repo_path();
-        let node_id = self.env.pageserver.id;
+        let node_id = self.conf.id;
         println!(
             "Initializing pageserver node {} at '{}' in {:?}",
             node_id,
@@ -177,10 +246,11 @@ impl PageServerNode {
         let mut args = self.pageserver_basic_args(config_overrides, datadir_path_str);
         args.push(Cow::Borrowed("--init"));
 
-        let init_output = Command::new(self.env.pageserver_bin())
+        let mut init_command = Command::new(self.env.pageserver_bin());
+        init_command
             .args(args.iter().map(Cow::as_ref))
-            .envs(self.pageserver_env_variables()?)
-            .output()
+            .envs(self.pageserver_env_variables()?);
+        let init_output = report_while("Initializing pageserver", || init_command.output())
             .with_context(|| format!("Failed to run pageserver init for node {node_id}"))?;
 
         anyhow::ensure!(
@@ -201,7 +271,7 @@ impl PageServerNode {
         let datadir = self.repo_path();
         print!(
             "Starting pageserver node {} at '{}' in {:?}",
-            self.env.pageserver.id,
+            self.conf.id,
             self.pg_connection_config.raw_address(),
             datadir
         );
@@ -210,7 +280,7 @@ impl PageServerNode {
         let datadir_path_str = datadir.to_str().with_context(|| {
             format!(
                 "Cannot start pageserver node {} in path that has no string representation: {:?}",
-                self.env.pageserver.id, datadir,
+                self.conf.id, datadir,
             )
         })?;
         let mut args = self.pageserver_basic_args(config_overrides, datadir_path_str);
@@ -254,7 +324,7 @@ impl PageServerNode {
         // FIXME: why is this tied to pageserver's auth type? Whether or not the safekeeper
         // needs a token, and how to generate that token, seems independent to whether
         // the pageserver requires a token in incoming requests.
-        Ok(if self.env.pageserver.http_auth_type != AuthType::Trust {
+        Ok(if self.conf.http_auth_type != AuthType::Trust {
             // Generate a token to connect from the pageserver to a safekeeper
             let token = self
                 .env
@@ -271,26 +341,18 @@ impl PageServerNode {
     /// If 'immediate' is true, we use SIGQUIT, killing the process immediately.
     /// Otherwise we use SIGTERM, triggering a clean shutdown
     ///
+    /// With a timeout, a graceful shutdown that takes longer is escalated to
+    /// SIGKILL. Returns how the server was stopped.
+    ///
     /// If the server is not running, returns success
     ///
-    pub fn stop(&self, immediate: bool) -> anyhow::Result<()> {
-        background_process::stop_process(immediate, "pageserver", &self.pid_file())
-    }
-
-    pub fn page_server_psql_client(&self) -> anyhow::Result<postgres::Client> {
-        let mut config = self.pg_connection_config.clone();
-        if self.env.pageserver.pg_auth_type == AuthType::NeonJWT {
-            let token = self
-                .env
-                .generate_auth_token(&Claims::new(None, Scope::PageServerApi))?;
-            config = config.set_password(Some(token));
-        }
-        Ok(config.connect_no_tls()?)
+    pub fn stop(&self, immediate: bool, timeout: Option<Duration>) -> anyhow::Result<StopOutcome> {
+        background_process::stop_process(immediate, timeout, "pageserver", &self.pid_file())
     }
 
     fn http_request<U: IntoUrl>(&self, method: Method, url: U) -> anyhow::Result<RequestBuilder> {
         let mut builder = self.http_client.request(method, url);
-        if self.env.pageserver.http_auth_type == AuthType::NeonJWT {
+        if self.conf.http_auth_type == AuthType::NeonJWT {
             let token = self
                 .env
                 .generate_auth_token(&Claims::new(None, Scope::PageServerApi))?;
@@ -299,6 +361,48 @@ impl PageServerNode {
         Ok(builder)
     }
 
+    /// Sends the request of a long-running operation with `progress=true`, and
+    /// prints the progress frames that the pageserver sends while it runs.
+    /// Returns the body of the response of the operation, which comes in the
+    /// last frame.
+    fn send_with_progress(
+        &self,
+        what: &str,
+        request: RequestBuilder,
+    ) -> anyhow::Result<serde_json::Value> {
+        let response = request
+            .query(&[("progress", "true")])
+            .send()?
+            .error_from_body()?;
+        for line in BufReader::new(response).lines() {
+            let line = line.with_context(|| format!("{what}: failed to read the progress"))?;
+            let frame: ProgressFrame = serde_json::from_str(&line)
+                .with_context(|| format!("{what}: malformed progress frame '{line}'"))?;
+            match frame {
+                ProgressFrame::Progress {
+                    elapsed_secs,
+                    message: Some(message),
+                } => println!("{what}: {message}, after {elapsed_secs}s"),
+                ProgressFrame::Progress { elapsed_secs, .. } => {
+                    println!("{what}: still running after {elapsed_secs}s")
+                }
+                ProgressFrame::Done { status, body } => {
+                    let status = StatusCode::from_u16(status)
+                        .with_context(|| format!("{what}: invalid status {status}"))?;
+                    if status.is_client_error() || status.is_server_error() {
+                        let msg = match serde_json::from_value::<HttpErrorBody>(body) {
+                            Ok(err_body) => format!("Error: {}", err_body.msg),
+                            Err(_) => format!("Http error ({}).", status.as_u16()),
+                        };
+                        return Err(http_error(status, msg).into());
+                    }
+                    return Ok(body);
+                }
+            }
+        }
+        bail!("{what}: the pageserver ended the response before the operation was done")
+    }
+
     pub fn check_status(&self) -> Result<()> {
         self.http_request(Method::GET, format!("{}/status", self.http_base_url))?
             .send()?
@@ -306,6 +410,63 @@ impl PageServerNode {
         Ok(())
     }
 
+    /// Returns the Prometheus metrics of the pageserver, in the text format.
+    pub fn metrics(&self) -> Result<String> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!("http://{}/metrics", self.conf.listen_http_addr),
+            )?
+            .send()?
+            .error_from_body()?
+            .text()?)
+    }
+
+    /// Collects the status of the pageserver from its metrics and its tenants.
+    pub fn status(&self) -> anyhow::Result<PageServerStatus> {
+        self.check_status()?;
+        let metrics = self.metrics()?;
+
+        let version = scrape::series(&metrics)
+            .find(|s| s.name == "libmetrics_build_info")
+            .and_then(|s| s.label("revision"))
+            .map(str::to_string);
+        let launched_at = scrape::metric_timestamp(&metrics, "libmetrics_launch_timestamp", &[]);
+        let last_task_at = |task| {
+            scrape::metric_timestamp(
+                &metrics,
+                "pageserver_last_background_task_timestamp",
+                &[("task", task)],
+            )
+        };
+
+        let mut active_tenants = 0;
+        let mut timelines = 0;
+        let mut wal_receiver_connections = 0;
+        for tenant in self.tenant_list()? {
+            if tenant.state != models::TenantState::Active {
+                continue;
+            }
+            active_tenants += 1;
+            for timeline in self.timeline_list(&tenant.id)? {
+                timelines += 1;
+                if timeline.wal_source_connstr.is_some() {
+                    wal_receiver_connections += 1;
+                }
+            }
+        }
+
+        Ok(PageServerStatus {
+            version,
+            launched_at,
+            active_tenants,
+            timelines,
+            wal_receiver_connections,
+            last_checkpoint_at: last_task_at("checkpoint"),
+            last_gc_at: last_task_at("gc"),
+        })
+    }
+
     pub fn tenant_list(&self) -> Result<Vec<TenantInfo>> {
         Ok(self
             .http_request(Method::GET, format!("{}/tenant", self.http_base_url))?
@@ -314,6 +475,19 @@ impl PageServerNode {
             .json()?)
     }
 
+    /// Returns the details of a tenant, including the resource usage that
+    /// `tenant_list` doesn't report.
+    pub fn tenant_status(&self, tenant_id: TenantId) -> Result<TenantInfo> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!("{}/tenant/{tenant_id}", self.http_base_url),
+            )?
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
     pub fn tenant_create(
         &self,
         new_tenant_id: Option<TenantId>,
@@ -380,6 +554,33 @@ impl PageServerNode {
                 .map(|x| x.parse::<bool>())
                 .transpose()
                 .context("Failed to parse 'gc_feedback' as bool")?,
+            physical_size_quota: settings
+                .remove("physical_size_quota")
+                .map(|x| x.parse::<u64>())
+                .transpose()
+                .context("Failed to parse 'physical_size_quota' as integer")?,
+            max_concurrent_getpage_requests: settings
+                .remove("max_concurrent_getpage_requests")
+                .map(|x| x.parse::<NonZeroUsize>())
+                .transpose()
+                .context("Failed to parse 'max_concurrent_getpage_requests' as non zero integer")?,
+            wal_ingest_rate_limit: settings
+                .remove("wal_ingest_rate_limit")
+                .map(|x| x.parse::<NonZeroU64>())
+                .transpose()
+                .context("Failed to parse 'wal_ingest_rate_limit' as non zero integer")?,
+            hot_range_image_creation_threshold: settings
+                .remove("hot_range_image_creation_threshold")
+                .map(|x| x.parse::<NonZeroU64>())
+                .transpose()
+                .context(
+                    "Failed to parse 'hot_range_image_creation_threshold' as non zero integer",
+                )?,
+            max_regions: settings
+                .remove("max_regions")
+                .map(|x| x.parse::<NonZeroU32>())
+                .transpose()
+                .context("Failed to parse 'max_regions' as non zero integer")?,
         };
 
         // If tenant ID was not specified, generate one
@@ -408,84 +609,66 @@ impl PageServerNode {
             })
     }
 
+    /// Stops a tenant and releases its in-memory state, keeping its data on
+    /// disk, so that it can be attached back later.
+    pub fn tenant_detach(&self, tenant_id: TenantId) -> Result<()> {
+        self.http_request(
+            Method::POST,
+            format!("{}/tenant/{tenant_id}/ignore", self.http_base_url),
+        )?
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
+
+    /// Deletes the local files of a tenant detached with [`Self::tenant_detach`].
+    pub fn tenant_delete_detached(&self, tenant_id: TenantId) -> Result<()> {
+        self.http_request(
+            Method::POST,
+            format!("{}/tenant/{tenant_id}/detach", self.http_base_url),
+        )?
+        .query(&[("detach_ignored", "true")])
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
+
+    /// Attaches a tenant back: from the local disk if it was detached from this
+    /// pageserver, and from the remote storage otherwise. Waits for the tenant
+    /// to become active.
+    pub fn tenant_attach(&self, tenant_id: TenantId) -> anyhow::Result<()> {
+        let operation = if self.tenant_path(tenant_id).exists() {
+            "load"
+        } else {
+            "attach"
+        };
+        self.http_request(
+            Method::POST,
+            format!("{}/tenant/{tenant_id}/{operation}", self.http_base_url),
+        )?
+        .send()?
+        .error_from_body()?;
+
+        report_while("Attaching tenant", || -> anyhow::Result<()> {
+            for _ in 0..TENANT_ATTACH_WAIT_RETRIES {
+                match self.tenant_status(tenant_id)?.state {
+                    models::TenantState::Active => return Ok(()),
+                    models::TenantState::Broken { reason, .. } => {
+                        bail!("tenant {tenant_id} is broken: {reason}")
+                    }
+                    _ => std::thread::sleep(Duration::from_millis(100)),
+                }
+            }
+            bail!("tenant {tenant_id} did not become active")
+        })
+    }
+
     pub fn tenant_config(
         &self,
         tenant_id: TenantId,
-        mut settings: HashMap<&str, &str>,
+        settings: HashMap<&str, &str>,
     ) -> anyhow::Result<()> {
-        let config = {
-            // Braces to make the diff easier to read
-            models::TenantConfig {
-                checkpoint_distance: settings
-                    .remove("checkpoint_distance")
-                    .map(|x| x.parse::<u64>())
-                    .transpose()
-                    .context("Failed to parse 'checkpoint_distance' as an integer")?,
-                checkpoint_timeout: settings.remove("checkpoint_timeout").map(|x| x.to_string()),
-                compaction_target_size: settings
-                    .remove("compaction_target_size")
-                    .map(|x| x.parse::<u64>())
-                    .transpose()
-                    .context("Failed to parse 'compaction_target_size' as an integer")?,
-                compaction_period: settings.remove("compaction_period").map(|x| x.to_string()),
-                compaction_threshold: settings
-                    .remove("compaction_threshold")
-                    .map(|x| x.parse::<usize>())
-                    .transpose()
-                    .context("Failed to parse 'compaction_threshold' as an integer")?,
-                gc_horizon: settings
-                    .remove("gc_horizon")
-                    .map(|x| x.parse::<u64>())
-                    .transpose()
-                    .context("Failed to parse 'gc_horizon' as an integer")?,
-                gc_period: settings.remove("gc_period").map(|x| x.to_string()),
-                image_creation_threshold: settings
-                    .remove("image_creation_threshold")
-                    .map(|x| x.parse::<usize>())
-                    .transpose()
-                    .context("Failed to parse 'image_creation_threshold' as non zero integer")?,
-                pitr_interval: settings.remove("pitr_interval").map(|x| x.to_string()),
-                walreceiver_connect_timeout: settings
-                    .remove("walreceiver_connect_timeout")
-                    .map(|x| x.to_string()),
-                lagging_wal_timeout: settings
-                    .remove("lagging_wal_timeout")
-                    .map(|x| x.to_string()),
-                max_lsn_wal_lag: settings
-                    .remove("max_lsn_wal_lag")
-                    .map(|x| x.parse::<NonZeroU64>())
-                    .transpose()
-                    .context("Failed to parse 'max_lsn_wal_lag' as non zero integer")?,
-                trace_read_requests: settings
-                    .remove("trace_read_requests")
-                    .map(|x| x.parse::<bool>())
-                    .transpose()
-                    .context("Failed to parse 'trace_read_requests' as bool")?,
-                eviction_policy: settings
-                    .remove("eviction_policy")
-                    .map(serde_json::from_str)
-                    .transpose()
-                    .context("Failed to parse 'eviction_policy' json")?,
-                min_resident_size_override: settings
-                    .remove("min_resident_size_override")
-                    .map(|x| x.parse::<u64>())
-                    .transpose()
-                    .context("Failed to parse 'min_resident_size_override' as an integer")?,
-                evictions_low_residence_duration_metric_threshold: settings
-                    .remove("evictions_low_residence_duration_metric_threshold")
-                    .map(|x| x.to_string()),
-                gc_feedback: settings
-                    .remove("gc_feedback")
-                    .map(|x| x.parse::<bool>())
-                    .transpose()
-                    .context("Failed to parse 'gc_feedback' as bool")?,
-            }
-        };
-
-        if !settings.is_empty() {
-            bail!("Unrecognized tenant settings: {settings:?}")
-        }
-
+        let config = parse_tenant_config(settings)?;
         self.http_request(Method::PUT, format!("{}/tenant/config", self.http_base_url))?
             .json(&models::TenantConfigRequest { tenant_id, config })
             .send()?
@@ -494,6 +677,25 @@ impl PageServerNode {
         Ok(())
     }
 
+    /// Changes the given settings of a tenant, keeping its other overrides,
+    /// unlike [`Self::tenant_config`] which replaces them all.
+    pub fn tenant_config_set(
+        &self,
+        tenant_id: TenantId,
+        settings: HashMap<&str, &str>,
+    ) -> anyhow::Result<()> {
+        let config = parse_tenant_config(settings)?;
+        self.http_request(
+            Method::PUT,
+            format!("{}/tenant/{tenant_id}/config", self.http_base_url),
+        )?
+        .json(&config)
+        .send()?
+        .error_from_body()?;
+
+        Ok(())
+    }
+
     pub fn timeline_list(&self, tenant_id: &TenantId) -> anyhow::Result<Vec<TimelineInfo>> {
         let timeline_infos: Vec<TimelineInfo> = self
             .http_request(
@@ -507,6 +709,145 @@ impl PageServerNode {
         Ok(timeline_infos)
     }
 
+    pub fn timeline_detail(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> anyhow::Result<TimelineInfo> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}",
+                    self.http_base_url
+                ),
+            )?
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Finds the LSN of a timeline at a point in time, which must be in the
+    /// history the timeline retains, per its `pitr_interval`.
+    pub fn timeline_lsn_by_timestamp(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        timestamp: SystemTime,
+    ) -> anyhow::Result<Lsn> {
+        let result: String = self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/get_lsn_by_timestamp",
+                    self.http_base_url
+                ),
+            )?
+            .query(&[(
+                "timestamp",
+                humantime::format_rfc3339(timestamp).to_string(),
+            )])
+            .send()?
+            .error_from_body()?
+            .json()?;
+
+        let timestamp = humantime::format_rfc3339_seconds(timestamp);
+        match result.as_str() {
+            "past" => Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("{timestamp} is before the history retained by timeline {timeline_id}, see its pitr_interval"),
+            )),
+            "future" => Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("{timestamp} is after the last commit on timeline {timeline_id}"),
+            )),
+            "nodata" => Err(categorize(
+                ErrorCategory::PreconditionFailed,
+                anyhow!("timeline {timeline_id} has no commits to find {timestamp} among"),
+            )),
+            lsn => Lsn::from_str(lsn)
+                .with_context(|| format!("Unexpected LSN '{lsn}' for {timestamp}")),
+        }
+    }
+
+    /// Checks the layer files of all the timelines of a tenant.
+    pub fn tenant_scrub(&self, tenant_id: TenantId) -> Result<Vec<TimelineScrubReport>> {
+        Ok(self
+            .http_request(
+                Method::PUT,
+                format!("{}/tenant/{tenant_id}/scrub", self.http_base_url),
+            )?
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Runs a compaction pass on a timeline, returning once it is done.
+    pub fn timeline_compact(&self, tenant_id: TenantId, timeline_id: TimelineId) -> Result<()> {
+        self.http_request(
+            Method::PUT,
+            format!(
+                "{}/tenant/{tenant_id}/timeline/{timeline_id}/compact",
+                self.http_base_url
+            ),
+        )?
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
+
+    /// Drops the history of the timeline before `lsn` for good, and returns the
+    /// number of layer files removed.
+    pub fn timeline_truncate(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        lsn: Lsn,
+    ) -> Result<u64> {
+        let gc_result: serde_json::Value = self
+            .http_request(
+                Method::PUT,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/truncate",
+                    self.http_base_url
+                ),
+            )?
+            .query(&[("lsn", lsn.to_string())])
+            .send()?
+            .error_from_body()?
+            .json()?;
+        gc_result["layers_removed"]
+            .as_u64()
+            .context("no layers_removed in the truncate response")
+    }
+
+    /// Waits for at most `timeout` until the pageserver has ingested the WAL of
+    /// the timeline up to `lsn`, and flushed it to disk.
+    pub fn timeline_wait_lsn(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        lsn: Lsn,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.http_request(
+            Method::POST,
+            format!(
+                "{}/tenant/{tenant_id}/timeline/{timeline_id}/wait_lsn",
+                self.http_base_url
+            ),
+        )?
+        .query(&[
+            ("lsn", lsn.to_string()),
+            ("timeout_ms", timeout.as_millis().to_string()),
+        ])
+        // Leave the pageserver time to flush the WAL after the wait.
+        .timeout(timeout + WAIT_LSN_FLUSH_TIMEOUT)
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
+
     pub fn timeline_create(
         &self,
         tenant_id: TenantId,
@@ -519,28 +860,33 @@ impl PageServerNode {
         // If timeline ID was not specified, generate one
         let new_timeline_id = new_timeline_id.unwrap_or(TimelineId::generate());
 
-        self.http_request(
-            Method::POST,
-            format!("{}/tenant/{}/timeline", self.http_base_url, tenant_id),
-        )?
-        .json(&models::TimelineCreateRequest {
-            new_timeline_id,
-            ancestor_start_lsn,
-            ancestor_timeline_id,
-            pg_version,
-            region_id,
-        })
-        .send()?
-        .error_from_body()?
-        .json::<Option<TimelineInfo>>()
-        .with_context(|| {
-            format!("Failed to parse timeline creation response for tenant id: {tenant_id}")
-        })?
-        .with_context(|| {
-            format!(
+        let what = if ancestor_timeline_id.is_some() {
+            "Creating branch"
+        } else {
+            "Creating timeline"
+        };
+        let request = self
+            .http_request(
+                Method::POST,
+                format!("{}/tenant/{}/timeline", self.http_base_url, tenant_id),
+            )?
+            .json(&models::TimelineCreateRequest {
+                new_timeline_id,
+                ancestor_start_lsn,
+                ancestor_timeline_id,
+                pg_version,
+                region_id,
+            });
+        let body = self.send_with_progress(what, request)?;
+        serde_json::from_value::<Option<TimelineInfo>>(body)
+            .with_context(|| {
+                format!("Failed to parse timeline creation response for tenant id: {tenant_id}")
+            })?
+            .with_context(|| {
+                format!(
                 "No timeline id was found in the timeline creation response for tenant {tenant_id}"
             )
-        })
+            })
     }
 
     /// Import a basebackup prepared using either:
@@ -560,38 +906,199 @@ impl PageServerNode {
         pg_wal: Option<(Lsn, PathBuf)>,
         pg_version: u32,
     ) -> anyhow::Result<()> {
-        let mut client = self.page_server_psql_client()?;
-
-        // Init base reader
+        // Import base
         let (start_lsn, base_tarfile_path) = base;
         let base_tarfile = File::open(base_tarfile_path)?;
-        let mut base_reader = BufReader::new(base_tarfile);
-
-        // Init wal reader if necessary
-        let (end_lsn, wal_reader) = if let Some((end_lsn, wal_tarfile_path)) = pg_wal {
-            let wal_tarfile = File::open(wal_tarfile_path)?;
-            let wal_reader = BufReader::new(wal_tarfile);
-            (end_lsn, Some(wal_reader))
-        } else {
-            (start_lsn, None)
-        };
-
-        // Import base
-        let import_cmd = format!(
-            "import basebackup {tenant_id} {timeline_id} {start_lsn} {end_lsn} {pg_version}"
-        );
-        let mut writer = client.copy_in(&import_cmd)?;
-        io::copy(&mut base_reader, &mut writer)?;
-        writer.finish()?;
+        let request = self
+            .http_request(
+                Method::PUT,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/import_basebackup",
+                    self.http_base_url
+                ),
+            )?
+            .query(&[
+                ("base_lsn", start_lsn.to_string()),
+                ("pg_version", pg_version.to_string()),
+            ])
+            .timeout(TIMELINE_ARCHIVE_TIMEOUT)
+            .body(reqwest::blocking::Body::new(base_tarfile));
+        self.send_with_progress("Importing basebackup", request)?;
 
         // Import wal if necessary
-        if let Some(mut wal_reader) = wal_reader {
-            let import_cmd = format!("import wal {tenant_id} {timeline_id} {start_lsn} {end_lsn}");
-            let mut writer = client.copy_in(&import_cmd)?;
-            io::copy(&mut wal_reader, &mut writer)?;
-            writer.finish()?;
+        if let Some((end_lsn, wal_tarfile_path)) = pg_wal {
+            let wal_tarfile = File::open(wal_tarfile_path)?;
+            let request = self
+                .http_request(
+                    Method::PUT,
+                    format!(
+                        "{}/tenant/{tenant_id}/timeline/{timeline_id}/import_wal",
+                        self.http_base_url
+                    ),
+                )?
+                .query(&[
+                    ("start_lsn", start_lsn.to_string()),
+                    ("end_lsn", end_lsn.to_string()),
+                ])
+                .timeout(TIMELINE_ARCHIVE_TIMEOUT)
+                .body(reqwest::blocking::Body::new(wal_tarfile));
+            self.send_with_progress("Importing WAL", request)?;
         }
 
         Ok(())
     }
+
+    /// Save the archive of the metadata and layer files of a timeline into
+    /// `path`, returning its size.
+    pub fn timeline_export(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        path: &Path,
+    ) -> anyhow::Result<u64> {
+        let mut response = self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/export",
+                    self.http_base_url
+                ),
+            )?
+            .timeout(TIMELINE_ARCHIVE_TIMEOUT)
+            .send()?
+            .error_from_body()?;
+        let mut file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let size = io::copy(&mut response, &mut file)
+            .context("Failed to download the timeline archive")?;
+        file.sync_all()?;
+        Ok(size)
+    }
+
+    /// Create a timeline from an archive saved by `timeline_export`, possibly
+    /// of another tenant or pageserver.
+    pub fn timeline_import_archive(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        path: &Path,
+    ) -> anyhow::Result<TimelineInfo> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let request = self
+            .http_request(
+                Method::PUT,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/import",
+                    self.http_base_url
+                ),
+            )?
+            .timeout(TIMELINE_ARCHIVE_TIMEOUT)
+            .body(reqwest::blocking::Body::new(file));
+        let body = self.send_with_progress("Importing timeline archive", request)?;
+        serde_json::from_value::<TimelineInfo>(body).with_context(|| {
+            format!(
+                "Failed to parse timeline import response for timeline {tenant_id}/{timeline_id}"
+            )
+        })
+    }
+}
+
+/// Parses the `key:value` tenant settings of the command line.
+fn parse_tenant_config(mut settings: HashMap<&str, &str>) -> anyhow::Result<models::TenantConfig> {
+    let config = models::TenantConfig {
+        checkpoint_distance: settings
+            .remove("checkpoint_distance")
+            .map(|x| x.parse::<u64>())
+            .transpose()
+            .context("Failed to parse 'checkpoint_distance' as an integer")?,
+        checkpoint_timeout: settings.remove("checkpoint_timeout").map(|x| x.to_string()),
+        compaction_target_size: settings
+            .remove("compaction_target_size")
+            .map(|x| x.parse::<u64>())
+            .transpose()
+            .context("Failed to parse 'compaction_target_size' as an integer")?,
+        compaction_period: settings.remove("compaction_period").map(|x| x.to_string()),
+        compaction_threshold: settings
+            .remove("compaction_threshold")
+            .map(|x| x.parse::<usize>())
+            .transpose()
+            .context("Failed to parse 'compaction_threshold' as an integer")?,
+        gc_horizon: settings
+            .remove("gc_horizon")
+            .map(|x| x.parse::<u64>())
+            .transpose()
+            .context("Failed to parse 'gc_horizon' as an integer")?,
+        gc_period: settings.remove("gc_period").map(|x| x.to_string()),
+        image_creation_threshold: settings
+            .remove("image_creation_threshold")
+            .map(|x| x.parse::<usize>())
+            .transpose()
+            .context("Failed to parse 'image_creation_threshold' as non zero integer")?,
+        pitr_interval: settings.remove("pitr_interval").map(|x| x.to_string()),
+        walreceiver_connect_timeout: settings
+            .remove("walreceiver_connect_timeout")
+            .map(|x| x.to_string()),
+        lagging_wal_timeout: settings
+            .remove("lagging_wal_timeout")
+            .map(|x| x.to_string()),
+        max_lsn_wal_lag: settings
+            .remove("max_lsn_wal_lag")
+            .map(|x| x.parse::<NonZeroU64>())
+            .transpose()
+            .context("Failed to parse 'max_lsn_wal_lag' as non zero integer")?,
+        trace_read_requests: settings
+            .remove("trace_read_requests")
+            .map(|x| x.parse::<bool>())
+            .transpose()
+            .context("Failed to parse 'trace_read_requests' as bool")?,
+        eviction_policy: settings
+            .remove("eviction_policy")
+            .map(serde_json::from_str)
+            .transpose()
+            .context("Failed to parse 'eviction_policy' json")?,
+        min_resident_size_override: settings
+            .remove("min_resident_size_override")
+            .map(|x| x.parse::<u64>())
+            .transpose()
+            .context("Failed to parse 'min_resident_size_override' as an integer")?,
+        evictions_low_residence_duration_metric_threshold: settings
+            .remove("evictions_low_residence_duration_metric_threshold")
+            .map(|x| x.to_string()),
+        gc_feedback: settings
+            .remove("gc_feedback")
+            .map(|x| x.parse::<bool>())
+            .transpose()
+            .context("Failed to parse 'gc_feedback' as bool")?,
+        physical_size_quota: settings
+            .remove("physical_size_quota")
+            .map(|x| x.parse::<u64>())
+            .transpose()
+            .context("Failed to parse 'physical_size_quota' as an integer")?,
+        max_concurrent_getpage_requests: settings
+            .remove("max_concurrent_getpage_requests")
+            .map(|x| x.parse::<NonZeroUsize>())
+            .transpose()
+            .context("Failed to parse 'max_concurrent_getpage_requests' as non zero integer")?,
+        wal_ingest_rate_limit: settings
+            .remove("wal_ingest_rate_limit")
+            .map(|x| x.parse::<NonZeroU64>())
+            .transpose()
+            .context("Failed to parse 'wal_ingest_rate_limit' as non zero integer")?,
+        hot_range_image_creation_threshold: settings
+            .remove("hot_range_image_creation_threshold")
+            .map(|x| x.parse::<NonZeroU64>())
+            .transpose()
+            .context("Failed to parse 'hot_range_image_creation_threshold' as non zero integer")?,
+        max_regions: settings
+            .remove("max_regions")
+            .map(|x| x.parse::<NonZeroU32>())
+            .transpose()
+            .context("Failed to parse 'max_regions' as non zero integer")?,
+    };
+
+    if !settings.is_empty() {
+        bail!("Unrecognized tenant settings: {settings:?}")
+    }
+    Ok(config)
 }
@@ -34,7 +34,7 @@ impl PostgresConf {
             let line = line?;
 
             // Store each line in a vector, in original format
-            result.lines.push(line.clone());
+            result.lines.push(format!("{line}\n"));
 
             // Also parse each line and insert key=value lines into a hash map.
             //
@@ -99,8 +99,8 @@ impl PostgresConf {
 
     ///
     /// Note: if you call this multiple times for the same option, the config
-    /// file will a line for each call. It would be nice to have a function
-    /// to change an existing line, but that's a TODO.
+    /// file will a line for each call. Use [`PostgresConf::set`] to replace
+    /// the lines that set it before instead.
     ///
     pub fn append(&mut self, option: &str, value: &str) {
         self.lines
@@ -108,6 +108,20 @@ impl PostgresConf {
         self.hash.insert(option.to_string(), value.to_string());
     }
 
+    /// Set 'option' to 'value', dropping any lines that set it before, so
+    /// that repeated changes don't pile up in the file. Like in PostgreSQL,
+    /// option names are case-insensitive.
+    pub fn set(&mut self, option: &str, value: &str) {
+        self.lines.retain(|line| {
+            CONF_LINE_RE
+                .captures(line.trim())
+                .map_or(true, |caps| !caps[1].eq_ignore_ascii_case(option))
+        });
+        self.hash
+            .retain(|name, _| !name.eq_ignore_ascii_case(option));
+        self.append(option, value);
+    }
+
     /// Append an arbitrary non-setting line to the config file
     pub fn append_line(&mut self, line: &str) {
         self.lines.push(line.to_string());
@@ -194,6 +208,37 @@ fn deescape_str(s: &str) -> Result<String> {
     }
 }
 
+#[test]
+fn test_postgresql_conf_set() -> Result<()> {
+    let mut conf =
+        PostgresConf::read("# comment\nwork_mem=4MB\nfsync=off\nwork_mem=8MB\n".as_bytes())?;
+    assert_eq!(conf.get("work_mem"), Some("8MB"));
+
+    conf.set("work_mem", "16MB");
+    conf.set("application_name", "foo bar");
+    assert_eq!(conf.get("work_mem"), Some("16MB"));
+    assert_eq!(
+        conf.to_string(),
+        "# comment\nfsync=off\nwork_mem=16MB\napplication_name='foo bar'\n"
+    );
+
+    // Option names are case-insensitive
+    conf.set("FSync", "on");
+    assert_eq!(conf.get("fsync"), None);
+    assert_eq!(conf.get("FSync"), Some("on"));
+    assert_eq!(
+        conf.to_string(),
+        "# comment\nwork_mem=16MB\napplication_name='foo bar'\nFSync=on\n"
+    );
+
+    // Survives a round trip
+    let conf = PostgresConf::read(conf.to_string().as_bytes())?;
+    assert_eq!(conf.get("application_name"), Some("foo bar"));
+    assert_eq!(conf.get("fsync"), Some("off"));
+
+    Ok(())
+}
+
 #[test]
 fn test_postgresql_conf_escapes() -> Result<()> {
     assert_eq!(escape_str("foo bar"), "'foo bar'");
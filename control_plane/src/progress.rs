@@ -0,0 +1,42 @@
+//! Progress reporting for long-running operations of the CLI.
+//!
+//! The pageserver sends progress frames for timeline creation and the imports,
+//! which `PageServerNode` prints as they arrive. For the other operations, this
+//! is what we can tell from the client side: how long they have been running.
+//!
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to print a status line.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs the operation, printing a status line every few seconds until it
+/// completes. Quick operations print nothing.
+pub fn report_while<T>(what: &str, operation: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(REPORT_INTERVAL) {
+                println!(
+                    "{what}: still running after {}s",
+                    started.elapsed().as_secs()
+                );
+            }
+        });
+        let result = operation();
+        drop(done_tx);
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_while_returns_result() {
+        assert_eq!(report_while("test", || 42), 42);
+    }
+}
@@ -0,0 +1,277 @@
+//! Parsing of the region topology given on the command line.
+//!
+//! A region spec lists the regions of a multi-region deployment in region id
+//! order, separated by `;`. Each region lists its branches, followed by the
+//! address of the region's xactserver. The region the endpoint runs in is
+//! marked with a trailing `*`:
+//!
+//! ```text
+//!   main@127.0.0.1:10000;us-east,us-east-2@127.0.0.1:10001*
+//! ```
+//!
+//! Prefer the `[[regions]]` section of the config where possible, this format
+//! exists for one-off topologies that are not worth writing down.
+//!
+//! The safekeepers of a region are not part of the spec, they are the ones
+//! with its id as `region` in the `[[safekeepers]]` section of the config.
+//!
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use thiserror::Error;
+use utils::id::RegionId;
+
+use crate::local_env::RegionConf;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RegionSpecError {
+    #[error("region spec is empty")]
+    Empty,
+
+    #[error("region {0} has no '@' separating its branches from its address")]
+    MissingAddress(usize),
+
+    #[error("region {0} has no branches")]
+    NoBranches(usize),
+
+    #[error("region {region} has an empty branch name")]
+    EmptyBranchName { region: usize },
+
+    #[error("region {region} has no port in address '{addr}'")]
+    MissingPort { region: usize, addr: String },
+
+    #[error("region {region} has an invalid address '{addr}'")]
+    BadAddress { region: usize, addr: String },
+
+    #[error("branch '{branch}' is listed in both region {first} and region {second}")]
+    RepeatedBranch {
+        branch: String,
+        first: usize,
+        second: usize,
+    },
+
+    #[error("more than one region is marked as current with '*'")]
+    DuplicateCurrent,
+
+    #[error("no region is marked as current with '*'")]
+    NoCurrent,
+
+    #[error("too many regions, at most {} are supported", u8::MAX as usize + 1)]
+    TooManyRegions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionSpecEntry {
+    pub branches: Vec<String>,
+    pub xactserver_addr: SocketAddr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionSpec {
+    pub regions: Vec<RegionSpecEntry>,
+    current: usize,
+}
+
+impl RegionSpec {
+    /// Id of the region marked with `*`.
+    pub fn current_region_id(&self) -> RegionId {
+        RegionId(self.current as u8)
+    }
+
+    pub fn current_region(&self) -> &RegionSpecEntry {
+        &self.regions[self.current]
+    }
+
+    /// Converts the spec to the same form as the `[[regions]]` config section.
+    /// Regions are named after their ids.
+    pub fn to_region_confs(&self) -> Vec<RegionConf> {
+        self.regions
+            .iter()
+            .enumerate()
+            .map(|(i, region)| RegionConf {
+                id: RegionId(i as u8),
+                name: i.to_string(),
+                branches: region.branches.clone(),
+                xactserver_pg_addr: Some(region.xactserver_addr.to_string()),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for RegionSpec {
+    type Err = RegionSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(RegionSpecError::Empty);
+        }
+
+        let mut regions = Vec::new();
+        let mut current = None;
+        let mut seen_branches = Vec::new();
+
+        for (i, region_str) in s.split(';').map(str::trim).enumerate() {
+            if i > u8::MAX as usize {
+                return Err(RegionSpecError::TooManyRegions);
+            }
+
+            let (region_str, is_current) = match region_str.strip_suffix('*') {
+                Some(stripped) => (stripped.trim_end(), true),
+                None => (region_str, false),
+            };
+            if is_current {
+                if current.is_some() {
+                    return Err(RegionSpecError::DuplicateCurrent);
+                }
+                current = Some(i);
+            }
+
+            let (branches_str, addr_str) = region_str
+                .rsplit_once('@')
+                .ok_or(RegionSpecError::MissingAddress(i))?;
+
+            if branches_str.trim().is_empty() {
+                return Err(RegionSpecError::NoBranches(i));
+            }
+            let mut branches = Vec::new();
+            for branch in branches_str.split(',').map(str::trim) {
+                if branch.is_empty() {
+                    return Err(RegionSpecError::EmptyBranchName { region: i });
+                }
+                if let Some((_, first)) = seen_branches.iter().find(|(b, _)| b == branch) {
+                    return Err(RegionSpecError::RepeatedBranch {
+                        branch: branch.to_string(),
+                        first: *first,
+                        second: i,
+                    });
+                }
+                seen_branches.push((branch.to_string(), i));
+                branches.push(branch.to_string());
+            }
+
+            let xactserver_addr = parse_addr(i, addr_str.trim())?;
+            regions.push(RegionSpecEntry {
+                branches,
+                xactserver_addr,
+            });
+        }
+
+        Ok(RegionSpec {
+            regions,
+            current: current.ok_or(RegionSpecError::NoCurrent)?,
+        })
+    }
+}
+
+fn parse_addr(region: usize, addr: &str) -> Result<SocketAddr, RegionSpecError> {
+    // IPv6 addresses are bracketed, so the port is always after the last ':'
+    // that follows the closing bracket, if any.
+    let host_end = addr.rfind(']').map(|i| i + 1).unwrap_or(0);
+    if !addr[host_end..].contains(':') {
+        return Err(RegionSpecError::MissingPort {
+            region,
+            addr: addr.to_string(),
+        });
+    }
+    addr.parse().map_err(|_| RegionSpecError::BadAddress {
+        region,
+        addr: addr.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_region_spec() {
+        let spec: RegionSpec = "main@127.0.0.1:10000; us-east,us-east-2@127.0.0.1:10001*"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.regions.len(), 2);
+        assert_eq!(spec.current_region_id(), RegionId(1));
+        assert_eq!(
+            spec.current_region().branches,
+            vec!["us-east".to_string(), "us-east-2".to_string()]
+        );
+        assert_eq!(
+            spec.current_region().xactserver_addr,
+            "127.0.0.1:10001".parse().unwrap()
+        );
+
+        let confs = spec.to_region_confs();
+        assert_eq!(confs[0].id, RegionId(0));
+        assert_eq!(confs[0].branches, vec!["main".to_string()]);
+        assert_eq!(
+            confs[1].xactserver_pg_addr.as_deref(),
+            Some("127.0.0.1:10001")
+        );
+
+        let spec: RegionSpec = "main@[::1]:10000*".parse().unwrap();
+        assert_eq!(spec.current_region_id(), RegionId(0));
+    }
+
+    #[test]
+    fn parse_region_spec_errors() {
+        let cases = [
+            ("", RegionSpecError::Empty),
+            ("main@127.0.0.1:10000", RegionSpecError::NoCurrent),
+            (
+                "main@127.0.0.1:10000*;r1@127.0.0.1:10001*",
+                RegionSpecError::DuplicateCurrent,
+            ),
+            ("main*", RegionSpecError::MissingAddress(0)),
+            ("@127.0.0.1:10000*", RegionSpecError::NoBranches(0)),
+            (
+                "main,@127.0.0.1:10000*",
+                RegionSpecError::EmptyBranchName { region: 0 },
+            ),
+            (
+                "main@127.0.0.1*",
+                RegionSpecError::MissingPort {
+                    region: 0,
+                    addr: "127.0.0.1".to_string(),
+                },
+            ),
+            (
+                "main@[::1]*",
+                RegionSpecError::MissingPort {
+                    region: 0,
+                    addr: "[::1]".to_string(),
+                },
+            ),
+            (
+                "main@127.0.0.300:10000*",
+                RegionSpecError::BadAddress {
+                    region: 0,
+                    addr: "127.0.0.300:10000".to_string(),
+                },
+            ),
+            (
+                "main@127.0.0.1:10000*;r1,main@127.0.0.1:10001",
+                RegionSpecError::RepeatedBranch {
+                    branch: "main".to_string(),
+                    first: 0,
+                    second: 1,
+                },
+            ),
+            (
+                "r1,r1@127.0.0.1:10001*",
+                RegionSpecError::RepeatedBranch {
+                    branch: "r1".to_string(),
+                    first: 0,
+                    second: 0,
+                },
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                input.parse::<RegionSpec>(),
+                Err(expected),
+                "unexpected result for '{input}'"
+            );
+        }
+    }
+}
@@ -7,18 +7,23 @@
 //! ```
 use std::io::Write;
 use std::path::PathBuf;
-use std::process::Child;
+use std::process::{Child, Command};
+use std::time::Duration;
 use std::{io, result};
 
 use anyhow::Context;
 use postgres_connection::PgConnectionConfig;
 use reqwest::blocking::{Client, RequestBuilder, Response};
-use reqwest::{IntoUrl, Method};
+use reqwest::{IntoUrl, Method, StatusCode};
+use safekeeper_api::models::{TimelineAckedLsn, TimelineRemoveWalResponse, TimelineStatus};
 use thiserror::Error;
-use utils::{http::error::HttpErrorBody, id::NodeId};
+use utils::{
+    http::error::HttpErrorBody,
+    id::{NodeId, TenantId, TimelineId},
+};
 
 use crate::{
-    background_process,
+    background_process::{self, StopOutcome},
     local_env::{LocalEnv, SafekeeperConf},
 };
 
@@ -29,6 +34,12 @@ pub enum SafekeeperHttpError {
 
     #[error("Error: {0}")]
     Response(String),
+
+    #[error("Error: {0}")]
+    NotFound(String),
+
+    #[error("Error: {0}")]
+    PreconditionFailed(String),
 }
 
 type Result<T> = result::Result<T, SafekeeperHttpError>;
@@ -46,12 +57,17 @@ impl ResponseErrorMessageExt for Response {
 
         // reqwest does not export its error construction utility functions, so let's craft the message ourselves
         let url = self.url().to_owned();
-        Err(SafekeeperHttpError::Response(
-            match self.json::<HttpErrorBody>() {
-                Ok(err_body) => format!("Error: {}", err_body.msg),
-                Err(_) => format!("Http error ({}) at {}.", status.as_u16(), url),
-            },
-        ))
+        let msg = match self.json::<HttpErrorBody>() {
+            Ok(err_body) => format!("Error: {}", err_body.msg),
+            Err(_) => format!("Http error ({}) at {}.", status.as_u16(), url),
+        };
+        Err(match status {
+            StatusCode::NOT_FOUND => SafekeeperHttpError::NotFound(msg),
+            StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+                SafekeeperHttpError::PreconditionFailed(msg)
+            }
+            _ => SafekeeperHttpError::Response(msg),
+        })
     }
 }
 
@@ -159,7 +175,38 @@ impl SafekeeperNode {
             args.extend(["--remote-storage".to_owned(), remote_storage.clone()]);
         }
 
-        let key_path = self.env.base_data_dir.join("auth_public_key.pem");
+        if let Some(ref window) = self.conf.group_commit_window {
+            args.extend(["--group-commit-window".to_owned(), window.clone()]);
+        }
+
+        for filter in &self.conf.region_wal_filters {
+            args.extend(["--region-wal-filter".to_owned(), filter.clone()]);
+        }
+
+        if let Some(margin) = self.conf.wal_retention_margin {
+            args.extend(["--wal-retention-margin".to_owned(), margin.to_string()]);
+        }
+
+        if let Some(quota) = self.conf.tenant_wal_quota {
+            args.extend(["--tenant-wal-quota".to_owned(), quota.to_string()]);
+        }
+
+        if let Some(rate) = self.conf.ingest_rate_limit {
+            args.extend(["--ingest-rate-limit".to_owned(), rate.to_string()]);
+        }
+
+        if let Some(burst) = self.conf.ingest_burst {
+            args.extend(["--ingest-burst".to_owned(), burst.to_string()]);
+        }
+
+        if let Some(ref interval) = self.conf.walproposer_keepalive_interval {
+            args.extend([
+                "--walproposer-keepalive-interval".to_owned(),
+                interval.clone(),
+            ]);
+        }
+
+        let key_path = self.env.auth_public_key_path();
         if self.conf.auth_enabled {
             args.extend([
                 "--auth-validation-public-key-path".to_owned(),
@@ -193,11 +240,15 @@ impl SafekeeperNode {
     /// If 'immediate' is true, we use SIGQUIT, killing the process immediately.
     /// Otherwise we use SIGTERM, triggering a clean shutdown
     ///
+    /// With a timeout, a graceful shutdown that takes longer is escalated to
+    /// SIGKILL. Returns how the server was stopped.
+    ///
     /// If the server is not running, returns success
     ///
-    pub fn stop(&self, immediate: bool) -> anyhow::Result<()> {
+    pub fn stop(&self, immediate: bool, timeout: Option<Duration>) -> anyhow::Result<StopOutcome> {
         background_process::stop_process(
             immediate,
+            timeout,
             &format!("safekeeper {}", self.id),
             &self.pid_file(),
         )
@@ -217,4 +268,162 @@ impl SafekeeperNode {
             .error_from_body()?;
         Ok(())
     }
+
+    /// Returns the Prometheus metrics of the safekeeper, in the text format.
+    pub fn metrics(&self) -> Result<String> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!("http://127.0.0.1:{}/metrics", self.conf.http_port),
+            )
+            .send()?
+            .error_from_body()?
+            .text()?)
+    }
+
+    /// Returns how far the safekeeper has acknowledged the WAL of the timeline.
+    pub fn timeline_acked_lsn(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<TimelineAckedLsn> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/acked_lsn",
+                    self.http_base_url
+                ),
+            )
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Returns the state of the timeline on the safekeeper: its LSNs, terms
+    /// and the peer safekeepers it has heard from.
+    pub fn timeline_status(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<TimelineStatus> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}",
+                    self.http_base_url
+                ),
+            )
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Returns the persisted control file state of the timeline: its term,
+    /// term history and LSNs.
+    pub fn timeline_control_file(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<serde_json::Value> {
+        Ok(self
+            .http_request(
+                Method::GET,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/control_file",
+                    self.http_base_url
+                ),
+            )
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Reads the control file of the timeline from disk with the safekeeper
+    /// binary, for when the safekeeper is not running. Returns the same state
+    /// as [`SafekeeperNode::timeline_control_file`].
+    pub fn read_timeline_control_file(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> anyhow::Result<serde_json::Value> {
+        let path = self
+            .datadir_path()
+            .join(tenant_id.to_string())
+            .join(timeline_id.to_string())
+            .join("safekeeper.control");
+        let output = Command::new(self.env.safekeeper_bin())
+            .arg("--dump-control-file")
+            .arg(&path)
+            .output()
+            .context("failed to run the safekeeper binary")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "failed to read control file {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        serde_json::from_slice(&output.stdout).context("failed to parse the control file dump")
+    }
+
+    /// Removes the WAL segments of the timeline that nothing needs anymore.
+    pub fn timeline_remove_wal(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<TimelineRemoveWalResponse> {
+        Ok(self
+            .http_request(
+                Method::POST,
+                format!(
+                    "{}/tenant/{tenant_id}/timeline/{timeline_id}/remove_wal",
+                    self.http_base_url
+                ),
+            )
+            .send()?
+            .error_from_body()?
+            .json()?)
+    }
+
+    /// Copies the timeline from the most advanced of the `donors`, which the
+    /// safekeeper must not have yet.
+    pub fn pull_timeline(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        donors: &[SafekeeperNode],
+    ) -> Result<()> {
+        let http_hosts: Vec<String> = donors
+            .iter()
+            .map(|sk| format!("http://127.0.0.1:{}", sk.conf.http_port))
+            .collect();
+        self.http_request(
+            Method::POST,
+            format!("{}/pull_timeline", self.http_base_url),
+        )
+        .json(&serde_json::json!({
+            "tenant_id": tenant_id.to_string(),
+            "timeline_id": timeline_id.to_string(),
+            "http_hosts": http_hosts,
+        }))
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
+
+    /// Deletes the timeline and all its WAL from the safekeeper.
+    pub fn timeline_delete(&self, tenant_id: TenantId, timeline_id: TimelineId) -> Result<()> {
+        self.http_request(
+            Method::DELETE,
+            format!(
+                "{}/tenant/{tenant_id}/timeline/{timeline_id}",
+                self.http_base_url
+            ),
+        )
+        .send()?
+        .error_from_body()?;
+        Ok(())
+    }
 }
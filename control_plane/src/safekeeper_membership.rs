@@ -0,0 +1,147 @@
+//! Change of the safekeepers a running endpoint writes its WAL to, behind
+//! `neon_local safekeeper add-to-timeline` and `remove-from-timeline`, e.g. to
+//! replace a failed safekeeper without recreating the endpoint.
+//!
+//! The walproposer commits WAL once a majority of its safekeepers has it, and a
+//! majority of the old set and one of the new set don't necessarily intersect,
+//! so the sets can't be swapped at once. Like in the joint consensus of Raft,
+//! the change goes through a configuration with both of them:
+//!
+//! 1. The safekeepers joining the timeline copy it from the current ones.
+//! 2. The endpoint gets the new set in `neon.safekeepers_next`. Its walproposer
+//!    restarts, and from then on is elected and commits WAL only with a
+//!    majority of each set.
+//! 3. Once a majority of the new set has switched to the term of that
+//!    walproposer, it has all the WAL committed before, and the endpoint is
+//!    left with the new set alone.
+//!
+//! If the new set doesn't catch up in time, the endpoint goes back to the
+//! current set, which has all the WAL committed in the joint configuration too.
+//!
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use utils::id::NodeId;
+
+use crate::endpoint::Endpoint;
+use crate::local_env::LocalEnv;
+use crate::safekeeper::SafekeeperNode;
+
+/// Moves the WAL of the running primary `endpoint` to the `next` safekeepers.
+/// Waits for at most `timeout` for a majority of them to catch up.
+pub fn change_safekeepers(
+    env: &LocalEnv,
+    endpoint: &Endpoint,
+    next: &[NodeId],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let current = current_safekeepers(endpoint)?;
+    if next.is_empty() {
+        bail!("the timeline can't be left without safekeepers");
+    }
+    if next.len() == current.len() && next.iter().all(|id| current.contains(id)) {
+        bail!("the timeline is already on safekeepers {current:?}");
+    }
+    let nodes = |ids: &[NodeId]| -> anyhow::Result<Vec<SafekeeperNode>> {
+        ids.iter()
+            .map(|id| {
+                let conf = env
+                    .safekeepers
+                    .iter()
+                    .find(|conf| conf.id == *id)
+                    .with_context(|| format!("safekeeper {id} does not exist"))?;
+                Ok(SafekeeperNode::from_env(env, conf))
+            })
+            .collect()
+    };
+    let current_nodes = nodes(&current)?;
+    let next_nodes = nodes(next)?;
+    let (tenant_id, timeline_id) = (endpoint.tenant_id, endpoint.timeline_id);
+
+    // A safekeeper being removed may well be down, only copy the timeline
+    // from the ones that answer.
+    let (donors, statuses): (Vec<_>, Vec<_>) = current_nodes
+        .into_iter()
+        .filter_map(|sk| {
+            let status = sk.timeline_status(tenant_id, timeline_id).ok()?;
+            Some((sk, status))
+        })
+        .unzip();
+    if donors.is_empty() {
+        bail!("none of the current safekeepers {current:?} is up with the timeline");
+    }
+
+    for sk in next_nodes.iter().filter(|sk| !current.contains(&sk.id)) {
+        if sk.timeline_status(tenant_id, timeline_id).is_err() {
+            sk.pull_timeline(tenant_id, timeline_id, &donors)
+                .with_context(|| format!("copy the timeline to safekeeper {}", sk.id))?;
+        }
+    }
+
+    // Only a walproposer started with the joint configuration can be elected
+    // in a higher term than the current safekeepers have.
+    let current_term = statuses
+        .iter()
+        .map(|status| status.acceptor_state.term)
+        .max()
+        .unwrap_or_default();
+
+    endpoint.set_safekeepers(&current, next)?;
+    if let Err(e) = wait_for_joint_term(&next_nodes, endpoint, current_term, timeout) {
+        endpoint
+            .set_safekeepers(&current, &[])
+            .context("go back to the current safekeepers")?;
+        return Err(e);
+    }
+    endpoint.set_safekeepers(next, &[])?;
+    Ok(())
+}
+
+/// The safekeepers the running `endpoint` writes its WAL to. Fails while
+/// another change of them is in progress.
+pub fn current_safekeepers(endpoint: &Endpoint) -> anyhow::Result<Vec<NodeId>> {
+    match endpoint.running_safekeepers()?.as_slice() {
+        [current] => Ok(current.clone()),
+        [] => bail!("timeline {} has no safekeepers", endpoint.timeline_id),
+        _ => bail!(
+            "the safekeepers of timeline {} are already being changed",
+            endpoint.timeline_id
+        ),
+    }
+}
+
+/// Waits until a majority of `next` safekeepers has WAL in a term above
+/// `current_term`, i.e. all the WAL of the walproposer elected in the joint
+/// configuration, which includes the WAL committed before it.
+fn wait_for_joint_term(
+    next: &[SafekeeperNode],
+    endpoint: &Endpoint,
+    current_term: u64,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let started_at = Instant::now();
+    loop {
+        let caught_up = next
+            .iter()
+            .filter_map(|sk| {
+                sk.timeline_status(endpoint.tenant_id, endpoint.timeline_id)
+                    .ok()
+            })
+            .filter(|status| {
+                let state = &status.acceptor_state;
+                state.term > current_term && state.epoch == state.term
+            })
+            .count();
+        if caught_up > next.len() / 2 {
+            return Ok(());
+        }
+        if started_at.elapsed() > timeout {
+            bail!(
+                "only {caught_up} of the {} new safekeepers caught up in {timeout:?}",
+                next.len()
+            );
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,347 @@
+//! Reconciliation of the WAL of a timeline across the safekeepers of the local
+//! environment, behind `neon_local safekeeper sync`, e.g. to recover after
+//! safekeepers were killed in the middle of writes.
+//!
+//! Like a walproposer on election, the safekeeper with the highest epoch, and
+//! the most WAL in it, is taken as the reference. The term histories tell where
+//! the WAL of every other safekeeper stops matching the reference's. The WAL of
+//! a safekeeper after that point is a divergent tail, written in a term whose
+//! walproposer didn't get to commit it. A safekeeper without such a tail is
+//! merely behind, and catches up once a walproposer is elected again.
+//!
+//! A divergent tail is truncated by copying the timeline afresh from the
+//! reference. This is only done when a majority of the safekeepers of the
+//! timeline could be queried: the reference then has all the committed WAL,
+//! and the tails of the others can't be committed.
+//!
+use std::cmp::min;
+use std::slice;
+
+use anyhow::{anyhow, Context};
+use safekeeper_api::models::{TermSwitchApiEntry, TimelineStatus};
+use utils::id::{NodeId, TenantId, TimelineId};
+use utils::lsn::Lsn;
+
+use crate::error::{categorize, CategorizeExt, ErrorCategory};
+use crate::safekeeper::{SafekeeperHttpError, SafekeeperNode};
+
+/// How the WAL of a safekeeper compares to the reference one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncState {
+    /// The safekeeper has all the WAL of the reference.
+    InSync,
+    /// The WAL of the safekeeper is `lag` bytes short of the reference's.
+    Behind { lag: u64 },
+    /// The WAL of the safekeeper stops matching the reference's at `at`, and
+    /// has `tail` bytes after it.
+    Diverged { at: Lsn, tail: u64 },
+    /// The safekeeper could not be queried.
+    Unavailable(String),
+}
+
+/// The timeline on one of the safekeepers.
+#[derive(Debug, Clone)]
+pub struct NodeSync {
+    pub id: NodeId,
+    pub status: Option<TimelineStatus>,
+    pub state: SyncState,
+}
+
+/// The timeline on the safekeepers of the local environment that have it.
+#[derive(Debug, Clone)]
+pub struct SyncReport {
+    /// The most advanced safekeeper, which the others are compared to.
+    pub reference: NodeId,
+    /// The end of the WAL of the reference.
+    pub reference_flush_lsn: Lsn,
+    /// The highest commit LSN known to the safekeepers.
+    pub max_commit_lsn: Lsn,
+    pub nodes: Vec<NodeSync>,
+}
+
+impl SyncReport {
+    /// Compares the timeline on the safekeepers, given its status on each of
+    /// them, or why it couldn't be queried.
+    pub fn new(statuses: Vec<(NodeId, Result<TimelineStatus, String>)>) -> anyhow::Result<Self> {
+        let (reference_id, reference) = statuses
+            .iter()
+            .filter_map(|(id, status)| Some((*id, status.as_ref().ok()?)))
+            .max_by_key(|(_, status)| (status.acceptor_state.epoch, status.flush_lsn))
+            .context("none of the safekeepers with the timeline could be queried")?;
+        let reference = reference.clone();
+        let max_commit_lsn = statuses
+            .iter()
+            .filter_map(|(_, status)| Some(status.as_ref().ok()?.commit_lsn))
+            .max()
+            .unwrap_or(Lsn::INVALID);
+
+        let nodes = statuses
+            .into_iter()
+            .map(|(id, status)| match status {
+                Ok(status) => NodeSync {
+                    id,
+                    state: compare(&reference, &status),
+                    status: Some(status),
+                },
+                Err(e) => NodeSync {
+                    id,
+                    status: None,
+                    state: SyncState::Unavailable(e),
+                },
+            })
+            .collect();
+
+        Ok(SyncReport {
+            reference: reference_id,
+            reference_flush_lsn: reference.flush_lsn,
+            max_commit_lsn,
+            nodes,
+        })
+    }
+
+    /// Whether the safekeepers that could be queried are a majority of the
+    /// ones with the timeline, i.e. the reference has all the committed WAL.
+    pub fn has_quorum(&self) -> bool {
+        self.available() > self.nodes.len() / 2
+    }
+
+    fn available(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| node.status.is_some())
+            .count()
+    }
+
+    /// The safekeepers with a divergent tail.
+    pub fn diverged(&self) -> impl Iterator<Item = &NodeSync> {
+        self.nodes
+            .iter()
+            .filter(|node| matches!(node.state, SyncState::Diverged { .. }))
+    }
+}
+
+/// Compares the WAL of a safekeeper to the reference one.
+fn compare(reference: &TimelineStatus, status: &TimelineStatus) -> SyncState {
+    let common_lsn = highest_common_lsn(
+        &reference.acceptor_state.term_history,
+        reference.flush_lsn,
+        &status.acceptor_state.term_history,
+        status.flush_lsn,
+    )
+    .unwrap_or(reference.timeline_start_lsn);
+    if status.flush_lsn > common_lsn {
+        SyncState::Diverged {
+            at: common_lsn,
+            tail: status.flush_lsn.0 - common_lsn.0,
+        }
+    } else if status.flush_lsn < reference.flush_lsn {
+        SyncState::Behind {
+            lag: reference.flush_lsn.0 - status.flush_lsn.0,
+        }
+    } else {
+        SyncState::InSync
+    }
+}
+
+/// Finds the highest LSN up to which the WAL of a safekeeper, ending at
+/// `wal_end` with `th` term history, matches the reference WAL, ending at
+/// `ref_wal_end` with `ref_th` term history. None if the histories have no
+/// term in common. The same as `TermHistory::find_highest_common_point` of
+/// the safekeeper.
+fn highest_common_lsn(
+    ref_th: &[TermSwitchApiEntry],
+    ref_wal_end: Lsn,
+    th: &[TermSwitchApiEntry],
+    wal_end: Lsn,
+) -> Option<Lsn> {
+    let last_common_idx = ref_th
+        .iter()
+        .zip(th.iter())
+        .take_while(|(r, s)| r.term == s.term && r.lsn == s.lsn)
+        .count()
+        .checked_sub(1)?;
+    let ref_common_term_end = ref_th
+        .get(last_common_idx + 1)
+        .map_or(ref_wal_end, |e| e.lsn);
+    let common_term_end = th.get(last_common_idx + 1).map_or(wal_end, |e| e.lsn);
+    Some(min(ref_common_term_end, common_term_end))
+}
+
+/// Queries all the `safekeepers` for the timeline, and compares it on the ones
+/// that have it.
+pub fn check_timeline(
+    safekeepers: &[SafekeeperNode],
+    tenant_id: TenantId,
+    timeline_id: TimelineId,
+) -> anyhow::Result<SyncReport> {
+    let mut statuses = Vec::new();
+    for sk in safekeepers {
+        match sk.timeline_status(tenant_id, timeline_id) {
+            Ok(status) => statuses.push((sk.id, Ok(status))),
+            Err(SafekeeperHttpError::NotFound(_)) => {}
+            Err(e) => statuses.push((sk.id, Err(e.to_string()))),
+        }
+    }
+    if statuses.is_empty() {
+        return Err(categorize(
+            ErrorCategory::NotFound,
+            anyhow!("none of the safekeepers has timeline {timeline_id}"),
+        ));
+    }
+    SyncReport::new(statuses).categorize(ErrorCategory::Connection)
+}
+
+/// Truncates the divergent tails of the WAL of the timeline, copying it afresh
+/// from the reference safekeeper to the safekeepers they are on. Returns the
+/// ids of those.
+pub fn truncate_divergent_tails(
+    safekeepers: &[SafekeeperNode],
+    report: &SyncReport,
+    tenant_id: TenantId,
+    timeline_id: TimelineId,
+) -> anyhow::Result<Vec<NodeId>> {
+    if !report.has_quorum() {
+        return Err(categorize(
+            ErrorCategory::PreconditionFailed,
+            anyhow!(
+                "only {} of the {} safekeepers with timeline {timeline_id} could be queried, an unavailable one might have more WAL than safekeeper {}",
+                report.available(),
+                report.nodes.len(),
+                report.reference
+            ),
+        ));
+    }
+    let node = |id: NodeId| {
+        safekeepers
+            .iter()
+            .find(|sk| sk.id == id)
+            .with_context(|| format!("safekeeper {id} does not exist"))
+    };
+    let reference = node(report.reference)?;
+
+    let mut truncated = Vec::new();
+    for diverged in report.diverged() {
+        let sk = node(diverged.id)?;
+        sk.timeline_delete(tenant_id, timeline_id)
+            .with_context(|| format!("delete the timeline on safekeeper {}", sk.id))?;
+        sk.pull_timeline(tenant_id, timeline_id, slice::from_ref(reference))
+            .with_context(|| {
+                format!(
+                    "copy the timeline from safekeeper {} to safekeeper {}",
+                    reference.id, sk.id
+                )
+            })?;
+        truncated.push(sk.id);
+    }
+    Ok(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use safekeeper_api::models::{AcceptorStateStatus, TimelinePgInfo};
+
+    fn status(term_history: &[(u64, u64)], flush_lsn: u64, commit_lsn: u64) -> TimelineStatus {
+        let term_history: Vec<_> = term_history
+            .iter()
+            .map(|&(term, lsn)| TermSwitchApiEntry {
+                term,
+                lsn: Lsn(lsn),
+            })
+            .collect();
+        let epoch = term_history
+            .iter()
+            .filter(|e| e.lsn <= Lsn(flush_lsn))
+            .last()
+            .map_or(0, |e| e.term);
+        TimelineStatus {
+            tenant_id: TenantId::from_array([1; 16]),
+            timeline_id: TimelineId::from_array([2; 16]),
+            acceptor_state: AcceptorStateStatus {
+                term: term_history.last().map_or(0, |e| e.term),
+                epoch,
+                term_history,
+            },
+            pg_info: TimelinePgInfo {
+                pg_version: 150000,
+                system_id: 0,
+                wal_seg_size: 16 * 1024 * 1024,
+            },
+            flush_lsn: Lsn(flush_lsn),
+            timeline_start_lsn: Lsn(0x10),
+            local_start_lsn: Lsn(0x10),
+            commit_lsn: Lsn(commit_lsn),
+            backup_lsn: Lsn(0x10),
+            peer_horizon_lsn: Lsn(0x10),
+            remote_consistent_lsn: Lsn(0x10),
+            last_removed_segno: 0,
+            peers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn diverged_tail() -> anyhow::Result<()> {
+        // Safekeeper 1 got WAL of term 1 that safekeepers 2 and 3 didn't,
+        // before they elected a walproposer in term 2.
+        let report = SyncReport::new(vec![
+            (NodeId(1), Ok(status(&[(1, 0x10)], 0x120, 0x100))),
+            (
+                NodeId(2),
+                Ok(status(&[(1, 0x10), (2, 0x100)], 0x150, 0x140)),
+            ),
+            (
+                NodeId(3),
+                Ok(status(&[(1, 0x10), (2, 0x100)], 0x140, 0x140)),
+            ),
+        ])?;
+        assert_eq!(report.reference, NodeId(2));
+        assert_eq!(report.reference_flush_lsn, Lsn(0x150));
+        assert_eq!(report.max_commit_lsn, Lsn(0x140));
+        let states: Vec<_> = report.nodes.iter().map(|n| n.state.clone()).collect();
+        assert_eq!(
+            states,
+            vec![
+                SyncState::Diverged {
+                    at: Lsn(0x100),
+                    tail: 0x20
+                },
+                SyncState::InSync,
+                SyncState::Behind { lag: 0x10 },
+            ]
+        );
+        assert!(report.has_quorum());
+        assert_eq!(
+            report.diverged().map(|n| n.id).collect::<Vec<_>>(),
+            vec![NodeId(1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn term_history_beyond_wal() -> anyhow::Result<()> {
+        // Safekeeper 2 voted for a walproposer in term 2 but got none of its
+        // WAL, its term history goes beyond the WAL it has.
+        let report = SyncReport::new(vec![
+            (NodeId(1), Ok(status(&[(1, 0x10)], 0x100, 0x100))),
+            (NodeId(2), Ok(status(&[(1, 0x10), (2, 0x100)], 0x90, 0x90))),
+        ])?;
+        assert_eq!(report.reference, NodeId(1));
+        assert_eq!(report.nodes[1].state, SyncState::Behind { lag: 0x70 });
+        assert_eq!(report.diverged().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn no_quorum() -> anyhow::Result<()> {
+        let report = SyncReport::new(vec![
+            (NodeId(1), Ok(status(&[(1, 0x10)], 0x100, 0x100))),
+            (NodeId(2), Err("connection refused".to_string())),
+            (NodeId(3), Err("connection refused".to_string())),
+        ])?;
+        assert!(!report.has_quorum());
+        assert!(matches!(report.nodes[2].state, SyncState::Unavailable(_)));
+
+        assert!(SyncReport::new(vec![(NodeId(1), Err("timeout".to_string()))]).is_err());
+        Ok(())
+    }
+}
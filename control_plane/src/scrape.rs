@@ -0,0 +1,143 @@
+//! Parsing of the metrics scraped from the services, in the Prometheus text
+//! format.
+//!
+//! Only what the CLI needs to pick a few values out of a scrape: no types, no
+//! escaping in label values, no timestamps.
+//!
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One line of a scrape: a metric name with its labels, and a value.
+pub struct Series<'a> {
+    pub name: &'a str,
+    labels: &'a str,
+    pub value: f64,
+}
+
+impl<'a> Series<'a> {
+    /// Returns the value of a label of the series.
+    pub fn label(&self, key: &str) -> Option<&'a str> {
+        self.labels.split(',').find_map(|label| {
+            let (k, v) = label.split_once('=')?;
+            if k == key {
+                v.strip_prefix('"')?.strip_suffix('"')
+            } else {
+                None
+            }
+        })
+    }
+
+    fn has_labels(&self, labels: &[(&str, &str)]) -> bool {
+        labels
+            .iter()
+            .all(|(key, expected)| self.label(key) == Some(*expected))
+    }
+}
+
+/// Returns all the series of a scrape, skipping comments and lines that can't
+/// be parsed.
+pub fn series(metrics: &str) -> impl Iterator<Item = Series<'_>> {
+    metrics
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            let (name, labels) = match series.split_once('{') {
+                Some((name, rest)) => (name, rest.strip_suffix('}')?),
+                None => (series, ""),
+            };
+            Some(Series {
+                name,
+                labels,
+                value: value.parse().ok()?,
+            })
+        })
+}
+
+/// Sums the values of all the series of a metric that have the given labels.
+pub fn metric_sum(metrics: &str, name: &str, labels: &[(&str, &str)]) -> f64 {
+    series(metrics)
+        .filter(|s| s.name == name && s.has_labels(labels))
+        .map(|s| s.value)
+        .sum()
+}
+
+/// Interprets the value of a series with the given labels as a timestamp in
+/// milliseconds since the epoch, as the `libmetrics_launch_timestamp` is.
+pub fn metric_timestamp(metrics: &str, name: &str, labels: &[(&str, &str)]) -> Option<SystemTime> {
+    series(metrics)
+        .find(|s| s.name == name && s.has_labels(labels))
+        .filter(|s| s.value > 0.0)
+        .map(|s| UNIX_EPOCH + Duration::from_millis(s.value as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METRICS: &str = r#"# HELP pageserver_smgr_query_seconds Time spent on smgr query handling
+# TYPE pageserver_smgr_query_seconds histogram
+pageserver_smgr_query_seconds_sum{smgr_query_type="get_page_at_lsn",tenant_id="t1",timeline_id="tl1",timeline_region="0"} 0.5
+pageserver_smgr_query_seconds_count{smgr_query_type="get_page_at_lsn",tenant_id="t1",timeline_id="tl1",timeline_region="0"} 100
+pageserver_smgr_query_seconds_count{smgr_query_type="get_rel_size",tenant_id="t1",timeline_id="tl1",timeline_region="0"} 7
+pageserver_smgr_query_seconds_count{smgr_query_type="get_page_at_lsn",tenant_id="t2",timeline_id="tl2",timeline_region="0"} 3
+safekeeper_write_wal_bytes_sum 8192
+libmetrics_build_info{revision="0123abcd"} 1
+libmetrics_launch_timestamp 1690000000000
+pageserver_last_background_task_timestamp{task="gc"} 1690000060000
+"#;
+
+    #[test]
+    fn metric_sum_filters_by_labels() {
+        let labels = [("smgr_query_type", "get_page_at_lsn"), ("tenant_id", "t1")];
+        assert_eq!(
+            metric_sum(METRICS, "pageserver_smgr_query_seconds_count", &labels),
+            100.0
+        );
+        assert_eq!(
+            metric_sum(METRICS, "pageserver_smgr_query_seconds_sum", &labels),
+            0.5
+        );
+        assert_eq!(
+            metric_sum(
+                METRICS,
+                "pageserver_smgr_query_seconds_count",
+                &[("smgr_query_type", "get_page_at_lsn")]
+            ),
+            103.0
+        );
+        assert_eq!(
+            metric_sum(METRICS, "safekeeper_write_wal_bytes_sum", &[]),
+            8192.0
+        );
+        assert_eq!(metric_sum(METRICS, "no_such_metric", &[]), 0.0);
+    }
+
+    #[test]
+    fn labels_and_timestamps() {
+        let revision = series(METRICS)
+            .find(|s| s.name == "libmetrics_build_info")
+            .and_then(|s| s.label("revision"));
+        assert_eq!(revision, Some("0123abcd"));
+
+        assert_eq!(
+            metric_timestamp(METRICS, "libmetrics_launch_timestamp", &[]),
+            Some(UNIX_EPOCH + Duration::from_secs(1_690_000_000))
+        );
+        assert_eq!(
+            metric_timestamp(
+                METRICS,
+                "pageserver_last_background_task_timestamp",
+                &[("task", "gc")]
+            ),
+            Some(UNIX_EPOCH + Duration::from_secs(1_690_000_060))
+        );
+        assert_eq!(
+            metric_timestamp(
+                METRICS,
+                "pageserver_last_background_task_timestamp",
+                &[("task", "checkpoint")]
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Migration of a tenant between two pageservers of the local environment,
+//! behind `neon_local tenant migrate`.
+//!
+//! The pageservers share the host, so the local files of the tenant are copied
+//! from one pageserver directory to the other, in two passes:
+//!
+//! 1. [`precopy`] copies the files while the tenant stays active on the source.
+//!    Layer files are immutable, so this copies the bulk of the data outside of
+//!    the downtime.
+//! 2. [`switch_over`], once the endpoints of the tenant are stopped, flushes
+//!    the WAL the source ingested to disk, detaches the tenant from the source,
+//!    copies the layers written since the first pass and the other files, which
+//!    change, and loads the tenant on the destination. The destination then
+//!    streams WAL from the safekeepers in place of the source: the pageservers
+//!    follow the timelines of the tenants they have, through the storage broker.
+//!    Once the destination has caught up with the WAL the source had ingested,
+//!    the copy of the source is deleted.
+//!
+//! If the switch-over fails, the copy of the destination is deleted, and the
+//! tenant is loaded back on the source.
+//!
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use utils::id::TenantId;
+
+use crate::pageserver::PageServerNode;
+
+/// Suffix of the files the pageserver is still writing, see the pageserver's
+/// `TEMP_FILE_SUFFIX`. Copies are made under such names too.
+const TEMP_FILE_SUFFIX: &str = "___temp";
+/// The mark of a tenant detached from a pageserver, but still on its disk.
+const IGNORED_TENANT_FILE_NAME: &str = "___ignored_tenant";
+/// Prefix of the files of the in-memory layers, which are not kept across loads.
+const EPHEMERAL_FILE_PREFIX: &str = "ephemeral-";
+
+/// Files and bytes copied by a pass over the tenant directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyStats {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Copies the local files of the tenant from `source` to `destination`, which
+/// must not have the tenant, while the tenant stays active on `source`.
+pub fn precopy(
+    source: &PageServerNode,
+    destination: &PageServerNode,
+    tenant_id: TenantId,
+) -> anyhow::Result<CopyStats> {
+    if source.conf.id == destination.conf.id {
+        bail!("the tenant is already on pageserver {}", source.conf.id);
+    }
+    // Fails if the tenant is not on the source.
+    source.tenant_status(tenant_id)?;
+    if destination.tenant_status(tenant_id).is_ok() {
+        bail!(
+            "tenant {tenant_id} is already attached to pageserver {}",
+            destination.conf.id
+        );
+    }
+
+    sync_dir(
+        &source.tenant_path(tenant_id),
+        &destination.tenant_path(tenant_id),
+        true,
+    )
+    .context("copy the tenant files")
+}
+
+/// Moves the tenant from `source` to `destination`, after [`precopy`]. The
+/// endpoints of the tenant must be stopped, so that its WAL doesn't move on.
+/// Waits for at most `timeout` for each timeline to flush its WAL on the
+/// source, and then to catch up on the destination.
+pub fn switch_over(
+    source: &PageServerNode,
+    destination: &PageServerNode,
+    tenant_id: TenantId,
+    timeout: Duration,
+) -> anyhow::Result<CopyStats> {
+    let timelines = source.timeline_list(&tenant_id)?;
+    for timeline in &timelines {
+        source
+            .timeline_wait_lsn(
+                tenant_id,
+                timeline.timeline_id,
+                timeline.last_record_lsn,
+                timeout,
+            )
+            .with_context(|| format!("flush timeline {} on the source", timeline.timeline_id))?;
+    }
+    source.tenant_detach(tenant_id)?;
+
+    let moved = (|| {
+        let stats = sync_dir(
+            &source.tenant_path(tenant_id),
+            &destination.tenant_path(tenant_id),
+            false,
+        )
+        .context("copy the tenant files")?;
+        destination.tenant_attach(tenant_id)?;
+        for timeline in &timelines {
+            destination
+                .timeline_wait_lsn(
+                    tenant_id,
+                    timeline.timeline_id,
+                    timeline.last_record_lsn,
+                    timeout,
+                )
+                .with_context(|| {
+                    format!(
+                        "wait for timeline {} to catch up on the destination",
+                        timeline.timeline_id
+                    )
+                })?;
+        }
+        anyhow::Ok(stats)
+    })();
+
+    match moved {
+        Ok(stats) => {
+            source
+                .tenant_delete_detached(tenant_id)
+                .context("delete the tenant files on the source")?;
+            Ok(stats)
+        }
+        Err(e) => {
+            roll_back(source, destination, tenant_id).with_context(|| {
+                format!("failed to roll back the migration, after it failed with: {e:#}")
+            })?;
+            Err(e.context(format!(
+                "the migration failed, tenant {tenant_id} is back on pageserver {}",
+                source.conf.id
+            )))
+        }
+    }
+}
+
+fn roll_back(
+    source: &PageServerNode,
+    destination: &PageServerNode,
+    tenant_id: TenantId,
+) -> anyhow::Result<()> {
+    if destination.tenant_status(tenant_id).is_ok() {
+        destination.tenant_detach(tenant_id)?;
+        destination.tenant_delete_detached(tenant_id)?;
+    } else {
+        match fs::remove_dir_all(destination.tenant_path(tenant_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    source.tenant_attach(tenant_id)
+}
+
+/// Layer files are immutable once written, under names made of their key range
+/// and LSN range, `<keys>__<lsns>`: a layer file of the same size on both sides
+/// is the same layer. The other files change in place.
+fn is_layer_file(name: &str) -> bool {
+    name.contains("__") && !name.contains("___")
+}
+
+/// Makes `destination` a copy of the `source` directory, skipping the files that
+/// are being written and the layer files it already has. With `live`, the files
+/// may go away from under the copy, which are skipped too.
+fn sync_dir(source: &Path, destination: &Path, live: bool) -> io::Result<CopyStats> {
+    let mut stats = CopyStats::default();
+    fs::create_dir_all(destination)?;
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(TEMP_FILE_SUFFIX)
+            || name == IGNORED_TENANT_FILE_NAME
+            || name.starts_with(EPHEMERAL_FILE_PREFIX)
+        {
+            continue;
+        }
+
+        let copied = (|| -> io::Result<()> {
+            let metadata = entry.metadata()?;
+            let target = destination.join(&name);
+            if metadata.is_dir() {
+                let dir_stats = sync_dir(&entry.path(), &target, live)?;
+                stats.files += dir_stats.files;
+                stats.bytes += dir_stats.bytes;
+                return Ok(());
+            }
+            if is_layer_file(&name) {
+                if let Ok(existing) = fs::metadata(&target) {
+                    if existing.len() == metadata.len() {
+                        return Ok(());
+                    }
+                }
+            }
+            // Never leave a partial copy under the final name.
+            let temp_target = destination.join(format!("{name}{TEMP_FILE_SUFFIX}"));
+            stats.bytes += fs::copy(entry.path(), &temp_target)?;
+            fs::rename(&temp_target, &target)?;
+            stats.files += 1;
+            Ok(())
+        })();
+        match copied {
+            Err(e) if live && e.kind() == io::ErrorKind::NotFound => continue,
+            result => result?,
+        }
+        names.push(name);
+    }
+
+    // Drop what the source has removed since the previous pass, e.g. the layers
+    // replaced by compaction.
+    for entry in fs::read_dir(destination)? {
+        let entry = entry?;
+        if names.contains(&entry.file_name().to_string_lossy().into_owned()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYER: &str = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__0000000001696070-00000000016960E9";
+
+    #[test]
+    fn layer_file_names() {
+        assert!(is_layer_file(LAYER));
+        assert!(!is_layer_file(&format!("{LAYER}{TEMP_FILE_SUFFIX}")));
+        assert!(!is_layer_file("metadata"));
+        assert!(!is_layer_file(IGNORED_TENANT_FILE_NAME));
+    }
+
+    #[test]
+    fn sync_tenant_dir() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let source = tempdir.path().join("source");
+        let destination = tempdir.path().join("destination");
+        let timeline = source.join("timelines").join("a");
+        fs::create_dir_all(&timeline)?;
+        fs::write(source.join("config"), "config")?;
+        fs::write(source.join(IGNORED_TENANT_FILE_NAME), "")?;
+        fs::write(timeline.join("metadata"), "metadata 1")?;
+        fs::write(timeline.join(LAYER), "layer")?;
+        fs::write(timeline.join(format!("new{TEMP_FILE_SUFFIX}")), "partial")?;
+
+        let stats = sync_dir(&source, &destination, true)?;
+        assert_eq!(
+            stats,
+            CopyStats {
+                files: 3,
+                bytes: 21
+            }
+        );
+        let copied = destination.join("timelines").join("a");
+        assert_eq!(fs::read_to_string(copied.join(LAYER))?, "layer");
+        assert!(!destination.join(IGNORED_TENANT_FILE_NAME).exists());
+        assert!(!copied.join(format!("new{TEMP_FILE_SUFFIX}")).exists());
+
+        // The second pass copies the files that changed, not the layers, and
+        // removes the ones that are gone from the source.
+        fs::write(timeline.join("metadata"), "metadata 2")?;
+        fs::write(copied.join("stale"), "")?;
+        let stats = sync_dir(&source, &destination, false)?;
+        assert_eq!(
+            stats,
+            CopyStats {
+                files: 2,
+                bytes: 16
+            }
+        );
+        assert_eq!(fs::read_to_string(copied.join("metadata"))?, "metadata 2");
+        assert!(!copied.join("stale").exists());
+        Ok(())
+    }
+}
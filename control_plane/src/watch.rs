@@ -0,0 +1,147 @@
+//! Watch mode of the listing commands, behind `--watch`.
+//!
+//! The listing is re-rendered every few seconds in place, like `watch(1)` does,
+//! with the cells that changed since the previous render highlighted: that is
+//! the quickest way to see whether WAL is being ingested, or an endpoint went
+//! down.
+//!
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use comfy_table::{Attribute, Cell, Color, Table};
+
+/// A listing as rendered by the CLI.
+pub struct Listing {
+    pub header: Vec<&'static str>,
+    /// Rows are told apart between renders by their first cell.
+    pub rows: Vec<Vec<String>>,
+    /// The columns whose changes are highlighted. Others, like the time
+    /// elapsed since something happened, change on every render.
+    pub watched_columns: Vec<usize>,
+}
+
+impl Listing {
+    /// Renders the listing, highlighting what changed since the previous one:
+    /// the rows that are new, and the watched cells with a different value.
+    pub fn render(&self, previous: Option<&Listing>) -> Table {
+        let changed = match previous {
+            Some(previous) => self.changes_since(previous),
+            None => Vec::new(),
+        };
+
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::NOTHING);
+        table.set_header(self.header.clone());
+        for (row_no, row) in self.rows.iter().enumerate() {
+            table.add_row(row.iter().enumerate().map(|(col_no, value)| {
+                let cell = Cell::new(value);
+                let change = changed.iter().find(|change| match change {
+                    Change::NewRow(r) | Change::Cells(r, _) => *r == row_no,
+                });
+                match change {
+                    Some(Change::NewRow(_)) => cell.fg(Color::Green),
+                    Some(Change::Cells(_, columns)) if columns.contains(&col_no) => {
+                        cell.fg(Color::Yellow).add_attribute(Attribute::Bold)
+                    }
+                    _ => cell,
+                }
+            }));
+        }
+        table
+    }
+
+    fn changes_since(&self, previous: &Listing) -> Vec<Change> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(row_no, row)| {
+                let key = row.first()?;
+                let Some(previous_row) = previous.rows.iter().find(|r| r.first() == Some(key))
+                else {
+                    return Some(Change::NewRow(row_no));
+                };
+                let columns: Vec<usize> = self
+                    .watched_columns
+                    .iter()
+                    .copied()
+                    .filter(|&col_no| row.get(col_no) != previous_row.get(col_no))
+                    .collect();
+                (!columns.is_empty()).then_some(Change::Cells(row_no, columns))
+            })
+            .collect()
+    }
+}
+
+/// A change of a row of a listing, by the index of the row.
+#[derive(Debug, PartialEq)]
+enum Change {
+    NewRow(usize),
+    /// The watched columns whose values changed.
+    Cells(usize, Vec<usize>),
+}
+
+/// Renders the listing every `interval` until interrupted. Failures to get the
+/// listing, e.g. while the pageserver restarts, are shown in its place.
+pub fn watch(
+    title: &str,
+    interval: Duration,
+    mut get_listing: impl FnMut() -> anyhow::Result<Listing>,
+) -> ! {
+    let mut previous: Option<Listing> = None;
+    loop {
+        let listing = get_listing();
+
+        // Clear the screen and move the cursor to the top left corner.
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "Every {}: {title}    {}\n",
+            humantime::format_duration(interval),
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        );
+        match listing {
+            Ok(listing) => {
+                println!("{}", listing.render(previous.as_ref()));
+                previous = Some(listing);
+            }
+            Err(e) => println!("{e:#}"),
+        }
+        let _ = std::io::stdout().flush();
+
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(rows: &[[&str; 3]]) -> Listing {
+        Listing {
+            header: vec!["ENDPOINT", "LSN", "AGE"],
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|v| v.to_string()).collect())
+                .collect(),
+            watched_columns: vec![1],
+        }
+    }
+
+    #[test]
+    fn changes_since_previous_listing() {
+        let previous = listing(&[
+            ["ep-main", "0/16B5A50", "1s ago"],
+            ["ep-old", "0/1", "5s ago"],
+        ]);
+        let current = listing(&[
+            ["ep-new", "0/1", "1s ago"],
+            ["ep-main", "0/16B9D68", "2s ago"],
+            ["ep-old", "0/1", "6s ago"],
+        ]);
+        assert_eq!(
+            current.changes_since(&previous),
+            vec![Change::NewRow(0), Change::Cells(1, vec![1])]
+        );
+        assert!(current.changes_since(&current).is_empty());
+    }
+}
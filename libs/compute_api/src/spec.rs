@@ -33,6 +33,13 @@ pub struct ComputeSpec {
     #[serde(default)] // Default false
     pub skip_pg_catalog_updates: bool,
 
+    /// If set, a primary gets the system catalogs with its basebackup and
+    /// keeps them in local files, instead of fetching their pages from the
+    /// pageserver one at a time as it starts up. The pages of the user
+    /// relations are still fetched on demand.
+    #[serde(default)] // Default false
+    pub catalog_basebackup: bool,
+
     // Information needed to connect to the storage layer.
     //
     // `tenant_id`, `timeline_id` and `pageserver_connstring` are always needed.
@@ -1,6 +1,6 @@
 use std::{
     collections::HashMap,
-    num::{NonZeroU64, NonZeroUsize},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     time::SystemTime,
 };
 
@@ -148,6 +148,25 @@ impl std::fmt::Debug for TenantState {
     }
 }
 
+/// A [`TenantState`] a tenant entered, and when.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TenantStateTransition {
+    pub state: TenantState,
+    #[serde(rename = "timestamp_millis_since_epoch")]
+    #[serde_as(as = "serde_with::TimestampMilliSeconds")]
+    pub timestamp: SystemTime,
+}
+
+impl TenantStateTransition {
+    pub fn new(state: TenantState) -> Self {
+        Self {
+            state,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
 /// The only [`TenantState`] variants we could be `TenantState::Activating` from.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ActivatingFrom {
@@ -232,6 +251,11 @@ pub struct TenantConfig {
     pub min_resident_size_override: Option<u64>,
     pub evictions_low_residence_duration_metric_threshold: Option<String>,
     pub gc_feedback: Option<bool>,
+    pub physical_size_quota: Option<u64>,
+    pub max_concurrent_getpage_requests: Option<NonZeroUsize>,
+    pub wal_ingest_rate_limit: Option<NonZeroU64>,
+    pub hot_range_image_creation_threshold: Option<NonZeroU64>,
+    pub max_regions: Option<NonZeroU32>,
 }
 
 #[serde_as]
@@ -291,6 +315,11 @@ impl TenantConfigRequest {
             min_resident_size_override: None,
             evictions_low_residence_duration_metric_threshold: None,
             gc_feedback: None,
+            physical_size_quota: None,
+            max_concurrent_getpage_requests: None,
+            wal_ingest_rate_limit: None,
+            hot_range_image_creation_threshold: None,
+            max_regions: None,
         };
         TenantConfigRequest { tenant_id, config }
     }
@@ -338,6 +367,140 @@ pub struct TenantInfo {
     /// If a layer is present in both local FS and S3, it counts only once.
     pub current_physical_size: Option<u64>, // physical size is only included in `tenant_status` endpoint
     pub attachment_status: TenantAttachmentStatus,
+    /// Number of timelines, only included in `tenant_status` endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline_count: Option<usize>,
+    /// The largest WAL ingest lag of the tenant's timelines in bytes, only
+    /// included in `tenant_status` endpoint, and only if any timeline is
+    /// receiving WAL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_wal_ingest_lag: Option<u64>,
+    /// The physical size quota of the tenant, only included in `tenant_status`
+    /// endpoint, and only if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_size_quota: Option<u64>,
+    /// Whether WAL ingestion is paused because the resident layer files exceed
+    /// the quota, only included along with `physical_size_quota`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_size_quota_exceeded: Option<bool>,
+}
+
+/// A page request that took longer than the threshold of the slow getPage log
+/// of its timeline.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SlowGetPageRequest {
+    pub rel: RelTag,
+    pub blkno: u32,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+    /// Time spent reconstructing the page, not counting waiting for the WAL
+    /// up to `lsn` to arrive.
+    pub latency_micros: u64,
+    /// Number of layers, in-memory or on disk, read to reconstruct the page.
+    pub layers_visited: usize,
+    #[serde(rename = "timestamp_millis_since_epoch")]
+    #[serde_as(as = "serde_with::TimestampMilliSeconds")]
+    pub timestamp: SystemTime,
+}
+
+/// The slow getPage log of a timeline, as returned by the management API.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SlowGetPageLogInfo {
+    /// Page requests taking at least this long are logged, or none if the log
+    /// is disabled.
+    pub threshold_micros: Option<u64>,
+    /// The most recent slow requests, oldest first.
+    pub requests: Vec<SlowGetPageRequest>,
+}
+
+/// Read hits and misses, and evictions, of the page cache.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageCacheStats {
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub evictions: u64,
+}
+
+/// The configuration and statistics of the page cache, as returned by the
+/// management API. Sizes are in 8 kB pages.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageCacheInfo {
+    pub size: usize,
+    /// The page cache can be resized up to this size without a restart.
+    pub max_size: usize,
+    pub stats: PageCacheStats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageCacheConfigRequest {
+    /// New size of the page cache, in 8 kB pages.
+    pub size: usize,
+}
+
+/// What the changed pages API returns for each changed page.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChangedPagesFormat {
+    /// The image of the page at the end of the LSN range.
+    #[default]
+    Image,
+    /// The changes of the page in the LSN range.
+    Delta,
+}
+
+/// A relation block changed in the LSN range of a changed pages request.
+/// Binary data is hex-encoded.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChangedPage {
+    pub rel: RelTag,
+    pub blkno: u32,
+    /// LSN of the last change of the page in the range.
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+    /// Image of the page at the end of the range, with the `image` format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Changes of the page in the range, oldest first, with the `delta` format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deltas: Option<Vec<PageDelta>>,
+}
+
+/// A change of a page: either a page image, or a Postgres WAL record to apply
+/// on the previous version of the page.
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageDelta {
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+    /// The change does not depend on the previous version of the page.
+    pub will_init: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wal_record: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChangedPagesResponse {
+    /// The changed pages, in key order.
+    pub pages: Vec<ChangedPage>,
+    /// Pass as `after` to get the next changed pages, none if these were the
+    /// last ones.
+    pub next_cursor: Option<String>,
 }
 
 /// This represents the output of the "timeline_detail" and "timeline_list" API calls.
@@ -366,9 +529,16 @@ pub struct TimelineInfo {
     #[serde_as(as = "DisplayFromStr")]
     pub remote_consistent_lsn: Lsn,
     pub current_logical_size: Option<u64>, // is None when timeline is Unloaded
+    /// False while the initial logical size calculation of the timeline is
+    /// running: until then, `current_logical_size` only counts the relations
+    /// created and extended by the WAL ingested since the timeline was loaded.
+    #[serde(default)]
+    pub current_logical_size_is_exact: bool,
     /// Sum of the size of all layer files.
     /// If a layer is present in both local FS and S3, it counts only once.
     pub current_physical_size: Option<u64>, // is None when timeline is Unloaded
+    /// Sum of the size of the layer files present on the local disk.
+    pub resident_physical_size: Option<u64>, // is None when timeline is Unloaded
     pub current_logical_size_non_incremental: Option<u64>,
 
     pub timeline_dir_layer_file_size_sum: Option<u64>,
@@ -379,10 +549,152 @@ pub struct TimelineInfo {
     /// the timestamp (in microseconds) of the last received message
     pub last_received_msg_ts: Option<u128>,
     pub pg_version: u32,
+    /// When the timeline was created on this pageserver, if known.
+    #[serde(
+        default,
+        rename = "created_at_millis_since_epoch",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[serde_as(as = "Option<serde_with::TimestampMilliSeconds>")]
+    pub created_at: Option<SystemTime>,
+    /// Only included in the timeline detail endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers: Option<TimelineLayerCounts>,
 
     pub state: TimelineState,
 }
 
+/// Number of the layers of a timeline, by kind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineLayerCounts {
+    pub delta_layers: usize,
+    pub image_layers: usize,
+    /// The delta and image layers evicted from the local disk, which are
+    /// downloaded back from the remote storage on demand.
+    #[serde(default)]
+    pub evicted_layers: usize,
+    /// The open and frozen in-memory layers, and their total size.
+    pub in_memory_layers: usize,
+    pub in_memory_size: u64,
+}
+
+/// The problems found by the scrub of a timeline.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineScrubReport {
+    #[serde_as(as = "DisplayFromStr")]
+    pub timeline_id: TimelineId,
+    /// The resident layers whose files were read. Evicted layers are not
+    /// downloaded to be checked.
+    pub layers_checked: usize,
+    /// Corrupted layer files, and gaps in the layer map.
+    pub errors: Vec<String>,
+    /// Files in the timeline directory that are not part of the layer map.
+    pub orphaned_files: Vec<String>,
+}
+
+/// A line of the newline-delimited JSON body that the long-running management
+/// requests answer with, when they are made with `progress=true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProgressFrame {
+    /// Sent every few seconds while the operation runs.
+    Progress {
+        elapsed_secs: u64,
+        /// How far the operation got, for the operations that can tell.
+        message: Option<String>,
+    },
+    /// The last line: the status and the JSON body that the request answers
+    /// with when made without `progress=true`.
+    Done {
+        status: u16,
+        body: serde_json::Value,
+    },
+}
+
+/// What the GC of a timeline would do with one of its layers, and why.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayerGcVerdict {
+    /// The layer has records newer than the horizon cutoff.
+    NewerThanHorizonCutoff,
+    /// The layer has records newer than the PITR cutoff.
+    NewerThanPitrCutoff,
+    /// The layer may hold the versions of the pages at the LSN a child branch
+    /// was forked off at, which the child reads through its ancestor.
+    NeededByBranch {
+        #[serde_as(as = "DisplayFromStr")]
+        branch_lsn: Lsn,
+    },
+    /// The layer is the latest one of part of its key range: no image layer
+    /// newer than it, and older than the GC cutoff, covers its whole key range.
+    NotCoveredByImageLayers,
+    /// Nothing needs the layer anymore, the next GC removes it.
+    Removable,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerGcExplanation {
+    pub layer_file_name: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn_start: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn_end: Lsn,
+    pub verdict: LayerGcVerdict,
+}
+
+/// A child branch of a timeline, which GC keeps the history of the timeline
+/// at the branch point for.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcBranchPoint {
+    #[serde_as(as = "DisplayFromStr")]
+    pub timeline_id: TimelineId,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+}
+
+/// The cutoffs the next GC of a timeline would use, and its verdict on each
+/// of the layers of the timeline.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineGcExplanation {
+    #[serde_as(as = "DisplayFromStr")]
+    pub horizon_cutoff: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pitr_cutoff: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub latest_gc_cutoff: Lsn,
+    pub branch_points: Vec<GcBranchPoint>,
+    pub layers: Vec<LayerGcExplanation>,
+}
+
+/// Size of a relation fork of a timeline, in blocks.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelSizeInfo {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    pub relnode: u32,
+    pub forknum: u8,
+    pub nblocks: u32,
+    /// LSN of the entry of the relation in the relation size cache, if it
+    /// has one.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub cached_lsn: Option<Lsn>,
+}
+
+/// Sizes of all the relations of a timeline at an LSN.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineRelSizes {
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+    pub relations: Vec<RelSizeInfo>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct LayerMapInfo {
     pub in_memory_layers: Vec<InMemoryLayerInfo>,
@@ -550,6 +862,23 @@ pub struct TimelineGcRequest {
     pub gc_horizon: Option<u64>,
 }
 
+/// A dropped relation, or a whole dropped database if `relnode` is unset,
+/// whose history to purge from a timeline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeDroppedRequest {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    pub relnode: Option<u32>,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeDroppedResponse {
+    /// The history before this LSN is purged.
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+}
+
 // Wrapped in libpq CopyData
 #[derive(PartialEq, Eq, Debug)]
 pub enum PagestreamFeMessage {
@@ -559,6 +888,7 @@ pub enum PagestreamFeMessage {
     DbSize(PagestreamDbSizeRequest),
     GetSlruPage(PagestreamGetSlruPageRequest),
     GetLatestLsn(PagestreamGetLatestLsnRequest),
+    GetPages(PagestreamGetPagesRequest),
 }
 
 // Wrapped in libpq CopyData
@@ -570,6 +900,7 @@ pub enum PagestreamBeMessage {
     GetLatestLsn(PagestreamGetLatestLsnResponse),
     Error(PagestreamErrorResponse),
     DbSize(PagestreamDbSizeResponse),
+    GetPages(PagestreamGetPagesResponse),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -621,6 +952,20 @@ pub struct PagestreamGetLatestLsnRequest {
     pub region: RegionId,
 }
 
+/// Request for the `nblocks` pages of a relation starting at `blkno`, answered
+/// in a single [`PagestreamGetPagesResponse`]. The range is cut at the end of
+/// the relation, so a sequential scan can ask for a whole readahead window
+/// without knowing the exact size of the relation.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PagestreamGetPagesRequest {
+    pub latest: bool,
+    pub lsn: Lsn,
+    pub region: RegionId,
+    pub rel: RelTag,
+    pub blkno: u32,
+    pub nblocks: u32,
+}
+
 #[derive(Debug)]
 pub struct PagestreamExistsResponse {
     pub lsn: Lsn,
@@ -651,6 +996,13 @@ pub struct PagestreamGetLatestLsnResponse {
     pub lsn: Lsn,
 }
 
+/// The pages of a [`PagestreamGetPagesRequest`], in block number order.
+#[derive(Debug)]
+pub struct PagestreamGetPagesResponse {
+    pub lsn: Lsn,
+    pub pages: Vec<Bytes>,
+}
+
 #[derive(Debug)]
 pub struct PagestreamErrorResponse {
     pub message: String,
@@ -724,6 +1076,19 @@ impl PagestreamFeMessage {
                 bytes.put_u8(5);
                 bytes.put_u8(req.region.0);
             }
+
+            Self::GetPages(req) => {
+                bytes.put_u8(6);
+                bytes.put_u8(u8::from(req.latest));
+                bytes.put_u64(req.lsn.0);
+                bytes.put_u8(req.region.0);
+                bytes.put_u32(req.rel.spcnode);
+                bytes.put_u32(req.rel.dbnode);
+                bytes.put_u32(req.rel.relnode);
+                bytes.put_u8(req.rel.forknum);
+                bytes.put_u32(req.blkno);
+                bytes.put_u32(req.nblocks);
+            }
         }
 
         bytes.into()
@@ -794,6 +1159,19 @@ impl PagestreamFeMessage {
                     region: RegionId(body.read_u8()?),
                 },
             )),
+            6 => Ok(PagestreamFeMessage::GetPages(PagestreamGetPagesRequest {
+                latest: body.read_u8()? != 0,
+                lsn: Lsn::from(body.read_u64::<BigEndian>()?),
+                region: RegionId(body.read_u8()?),
+                rel: RelTag {
+                    spcnode: body.read_u32::<BigEndian>()?,
+                    dbnode: body.read_u32::<BigEndian>()?,
+                    relnode: body.read_u32::<BigEndian>()?,
+                    forknum: body.read_u8()?,
+                },
+                blkno: body.read_u32::<BigEndian>()?,
+                nblocks: body.read_u32::<BigEndian>()?,
+            })),
             _ => bail!("unknown smgr message tag: {:?}", msg_tag),
         }
     }
@@ -849,6 +1227,15 @@ impl PagestreamBeMessage {
                 bytes.put_u64(resp.lsn.0);
                 bytes.put_i64(resp.db_size);
             }
+
+            Self::GetPages(resp) => {
+                bytes.put_u8(107); /* tag from pagestore_client.h */
+                bytes.put_u64(resp.lsn.0);
+                bytes.put_u32(resp.pages.len() as u32);
+                for page in &resp.pages {
+                    bytes.put(&page[..]);
+                }
+            }
         }
 
         bytes.into()
@@ -906,6 +1293,19 @@ mod tests {
                 dbnode: 7,
                 region: RegionId(0),
             }),
+            PagestreamFeMessage::GetPages(PagestreamGetPagesRequest {
+                latest: false,
+                lsn: Lsn(4),
+                rel: RelTag {
+                    forknum: 0,
+                    spcnode: 2,
+                    dbnode: 3,
+                    relnode: 4,
+                },
+                blkno: 7,
+                nblocks: 32,
+                region: RegionId(1),
+            }),
         ];
         for msg in messages {
             let bytes = msg.serialize();
@@ -922,6 +1322,10 @@ mod tests {
             state: TenantState::Active,
             current_physical_size: Some(42),
             attachment_status: TenantAttachmentStatus::Attached,
+            timeline_count: None,
+            max_wal_ingest_lag: None,
+            physical_size_quota: None,
+            physical_size_quota_exceeded: None,
         };
         let expected_active = json!({
             "id": original_active.id.to_string(),
@@ -942,6 +1346,10 @@ mod tests {
             },
             current_physical_size: Some(42),
             attachment_status: TenantAttachmentStatus::Attached,
+            timeline_count: None,
+            max_wal_ingest_lag: None,
+            physical_size_quota: None,
+            physical_size_quota_exceeded: None,
         };
         let expected_broken = json!({
             "id": original_broken.id.to_string(),
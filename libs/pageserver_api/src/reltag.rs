@@ -141,6 +141,7 @@ pub enum SlruKind {
     MultiXactMembers,
     MultiXactOffsets,
     Csn,
+    CommitTs,
 }
 
 impl SlruKind {
@@ -150,6 +151,7 @@ impl SlruKind {
             Self::MultiXactMembers => "pg_multixact/members",
             Self::MultiXactOffsets => "pg_multixact/offsets",
             Self::Csn => "pg_csn",
+            Self::CommitTs => "pg_commit_ts",
         }
     }
 }
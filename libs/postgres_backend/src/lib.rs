@@ -19,8 +19,8 @@ use tracing::{debug, error, info, trace};
 
 use pq_proto::framed::{ConnectionError, Framed, FramedReader, FramedWriter};
 use pq_proto::{
-    BeMessage, FeMessage, FeStartupPacket, ProtocolError, SQLSTATE_INTERNAL_ERROR,
-    SQLSTATE_SUCCESSFUL_COMPLETION,
+    BeMessage, FeMessage, FeStartupPacket, ProtocolError, SQLSTATE_CANNOT_CONNECT_NOW,
+    SQLSTATE_INTERNAL_ERROR, SQLSTATE_SUCCESSFUL_COMPLETION,
 };
 
 /// An error, occurred during query processing:
@@ -30,6 +30,10 @@ pub enum QueryError {
     /// The connection was lost while processing the query.
     #[error(transparent)]
     Disconnected(#[from] ConnectionError),
+    /// The query can't be served right now, e.g. because the data is still
+    /// being loaded or is being shut down. The client may retry later.
+    #[error("{0:#}")]
+    Unavailable(anyhow::Error),
     /// Some other error
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -46,6 +50,7 @@ impl QueryError {
         match self {
             Self::Disconnected(_) => b"08006",         // connection failure
             Self::Other(_) => SQLSTATE_INTERNAL_ERROR, // internal error
+            Self::Unavailable(_) => SQLSTATE_CANNOT_CONNECT_NOW,
         }
     }
 }
@@ -913,7 +918,7 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> AsyncWrite for CopyDataWriter<'a, I
 pub fn short_error(e: &QueryError) -> String {
     match e {
         QueryError::Disconnected(connection_error) => connection_error.to_string(),
-        QueryError::Other(e) => format!("{e:#}"),
+        QueryError::Unavailable(e) | QueryError::Other(e) => format!("{e:#}"),
     }
 }
 
@@ -929,6 +934,9 @@ fn log_query_error(query: &str, e: &QueryError) {
         QueryError::Disconnected(other_connection_error) => {
             error!("query handler for '{query}' failed with connection error: {other_connection_error:?}")
         }
+        QueryError::Unavailable(e) => {
+            info!("query handler for '{query}' failed: {e:#}");
+        }
         QueryError::Other(e) => {
             error!("query handler for '{query}' failed: {e:?}");
         }
@@ -5,6 +5,7 @@
  * header here, and whitelist the struct in the build.rs file.
  */
 #include "c.h"
+#include "catalog/catversion.h"
 #include "catalog/pg_control.h"
 #include "access/xlog_internal.h"
 
@@ -12,4 +13,19 @@
 #include "storage/bufpage.h"
 #include "storage/off.h"
 #include "access/multixact.h"
-#include "utils/snapshot.h"
\ No newline at end of file
+#include "utils/snapshot.h"
+
+/*
+ * Headers of the constants of pg_constants.rs, which are checked against the
+ * generated ones.
+ */
+#include "access/clog.h"
+#include "access/commit_ts.h"
+#include "access/heapam_xlog.h"
+#include "access/rmgr.h"
+#include "access/visibilitymap.h"
+#include "access/xact.h"
+#include "access/xlogrecord.h"
+#include "catalog/storage_xlog.h"
+#include "commands/tablespace.h"
+#include "replication/message.h"
@@ -43,6 +43,32 @@ impl ParseCallbacks for PostgresFfiCallbacks {
     }
 }
 
+/// Constants of `pg_constants.rs` that are also generated from the headers, to
+/// check the copies made by hand against them.
+const GENERATED_CONSTANTS: &[&str] = &[
+    "XLOG_SMGR_.*",
+    "SMGR_TRUNCATE_.*",
+    "CLOG_ZEROPAGE",
+    "CLOG_TRUNCATE",
+    "COMMIT_TS_ZEROPAGE",
+    "COMMIT_TS_TRUNCATE",
+    "TRANSACTION_STATUS_.*",
+    "VISIBILITYMAP_.*",
+    "XLOG_XACT_.*",
+    "XACT_XINFO_HAS_(DBINFO|SUBXACTS|RELFILENODES|INVALS|TWOPHASE|ORIGIN|GID)",
+    "XLOG_(NOOP|NEXTOID|SWITCH|PARAMETER_CHANGE|FPI_FOR_HINT|FPI)",
+    "XLOG_MULTIXACT_.*",
+    "XLOG_HEAP_.*",
+    "XLOG_HEAP2_.*",
+    "XLH_.*",
+    "XLOG_LOGICAL_MESSAGE",
+    "XLR_.*",
+    "XLOG_TBLSPC_.*",
+    "BKPBLOCK_.*",
+    "BKPIMAGE_HAS_HOLE",
+    "XLOG_CHECKPOINT_.*",
+];
+
 fn main() -> anyhow::Result<()> {
     // Tell cargo to invalidate the built crate whenever the wrapper changes
     println!("cargo:rerun-if-changed=bindgen_deps.h");
@@ -92,7 +118,7 @@ fn main() -> anyhow::Result<()> {
         // The bindgen::Builder is the main entry point
         // to bindgen, and lets you build up options for
         // the resulting bindings.
-        let bindings = bindgen::Builder::default()
+        let mut builder = bindgen::Builder::default()
             //
             // All the needed PostgreSQL headers are included from 'bindgen_deps.h'
             //
@@ -123,6 +149,7 @@ fn main() -> anyhow::Result<()> {
             .allowlist_var("XLOG_PAGE_MAGIC")
             .allowlist_var("PG_CONTROL_FILE_SIZE")
             .allowlist_var("PG_CONTROLFILEDATA_OFFSETOF_CRC")
+            .allowlist_var("CATALOG_VERSION_NO")
             .allowlist_type("PageHeaderData")
             .allowlist_type("DBState")
             .allowlist_type("XidCSN")
@@ -131,12 +158,21 @@ fn main() -> anyhow::Result<()> {
             // explicit padding fields.
             .explicit_padding(true)
             //
-            .clang_arg(format!("-I{inc_server_path}"))
-            //
-            // Finish the builder and generate the bindings.
-            //
-            .generate()
-            .context("Unable to generate bindings")?;
+            .clang_arg(format!("-I{inc_server_path}"));
+
+        // The rmgr ids are the values of an enum, which end up in the
+        // `RmgrIds` module.
+        builder = builder
+            .allowlist_type("RmgrIds")
+            .constified_enum_module("RmgrIds");
+        for constant in GENERATED_CONSTANTS {
+            builder = builder.allowlist_var(constant);
+        }
+
+        //
+        // Finish the builder and generate the bindings.
+        //
+        let bindings = builder.generate().context("Unable to generate bindings")?;
 
         // Write the bindings to the $OUT_DIR/bindings_$pg_version.rs file.
         let out_path: PathBuf = env::var("OUT_DIR")
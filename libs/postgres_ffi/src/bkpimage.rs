@@ -0,0 +1,512 @@
+//!
+//! Full-page images of WAL records, the XLogRecordBlockImageHeader part of a
+//! block reference and the image that follows it.
+//!
+//! The image leaves out the "hole" between pd_lower and pd_upper of pages in
+//! the standard layout, and with wal_compression it's compressed with pglz,
+//! or from v15 on, lz4 or zstd. [`BkpImage::restore`] turns it back into the
+//! page, like RestoreBlockImage() in xlogreader.c, and [`BkpImage::from_page`]
+//! builds it like XLogRecordAssemble() and XLogCompressBackupBlock() in
+//! xloginsert.c.
+//!
+use anyhow::{bail, ensure, Context};
+use bytes::{Bytes, BytesMut};
+
+use crate::heap_page::{page_get_lower, page_get_upper};
+use crate::pglz::{pglz_compress, pglz_decompress};
+use crate::{pg_constants, v14, v15, BLCKSZ};
+
+/// SizeOfXLogRecordBlockCompressHeader, the hole length that a compressed
+/// image with a hole needs space for.
+const SIZE_OF_XLOG_RECORD_BLOCK_COMPRESS_HEADER: usize = 2;
+
+/// The wal_compression methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BkpImageCompression {
+    Pglz,
+    Lz4,
+    Zstd,
+}
+
+impl BkpImageCompression {
+    /// The compression method of an image, from its bimg_info.
+    pub fn from_bimg_info(
+        bimg_info: u8,
+        pg_version: u32,
+    ) -> anyhow::Result<Option<BkpImageCompression>> {
+        Ok(match pg_version {
+            14 if bimg_info & v14::bindings::BKPIMAGE_IS_COMPRESSED != 0 => {
+                Some(BkpImageCompression::Pglz)
+            }
+            14 => None,
+            15 if bimg_info & v15::bindings::BKPIMAGE_COMPRESS_PGLZ != 0 => {
+                Some(BkpImageCompression::Pglz)
+            }
+            15 if bimg_info & v15::bindings::BKPIMAGE_COMPRESS_LZ4 != 0 => {
+                Some(BkpImageCompression::Lz4)
+            }
+            15 if bimg_info & v15::bindings::BKPIMAGE_COMPRESS_ZSTD != 0 => {
+                Some(BkpImageCompression::Zstd)
+            }
+            15 => None,
+            _ => bail!("Unknown version {}", pg_version),
+        })
+    }
+
+    /// The bimg_info flag of the compression method.
+    pub fn bimg_info(self, pg_version: u32) -> anyhow::Result<u8> {
+        Ok(match (pg_version, self) {
+            (14, BkpImageCompression::Pglz) => v14::bindings::BKPIMAGE_IS_COMPRESSED,
+            (14, _) => bail!("{self:?} compression of block images needs v15 or later"),
+            (15, BkpImageCompression::Pglz) => v15::bindings::BKPIMAGE_COMPRESS_PGLZ,
+            (15, BkpImageCompression::Lz4) => v15::bindings::BKPIMAGE_COMPRESS_LZ4,
+            (15, BkpImageCompression::Zstd) => v15::bindings::BKPIMAGE_COMPRESS_ZSTD,
+            _ => bail!("Unknown version {}", pg_version),
+        })
+    }
+}
+
+/// A full-page image, as stored in a WAL record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BkpImage {
+    pub hole_offset: u16,
+    pub hole_length: u16,
+    pub compression: Option<BkpImageCompression>,
+    /// The image as stored in the record, compressed or without the hole.
+    pub image: Bytes,
+}
+
+impl BkpImage {
+    /// The image of a block reference, from the fields of its
+    /// XLogRecordBlockImageHeader and XLogRecordBlockCompressHeader.
+    pub fn new(
+        bimg_info: u8,
+        hole_offset: u16,
+        hole_length: u16,
+        image: Bytes,
+        pg_version: u32,
+    ) -> anyhow::Result<BkpImage> {
+        let compression = BkpImageCompression::from_bimg_info(bimg_info, pg_version)?;
+        let has_hole = bimg_info & pg_constants::BKPIMAGE_HAS_HOLE != 0;
+        ensure!(
+            has_hole == (hole_length > 0),
+            "BKPIMAGE_HAS_HOLE doesn't match hole length {hole_length}"
+        );
+        ensure!(
+            hole_offset as usize + hole_length as usize <= BLCKSZ as usize,
+            "hole at {hole_offset} of length {hole_length} is past the end of the page"
+        );
+        if compression.is_none() {
+            ensure!(
+                image.len() + hole_length as usize == BLCKSZ as usize,
+                "uncompressed block image length {} doesn't match hole length {hole_length}",
+                image.len()
+            );
+        }
+        Ok(BkpImage {
+            hole_offset,
+            hole_length,
+            compression,
+            image,
+        })
+    }
+
+    /// Builds the image of `page` to store in a WAL record, compressed with
+    /// `compression` if it makes the image smaller. With `page_std`, the page
+    /// is in the standard layout and the hole between pd_lower and pd_upper is
+    /// left out.
+    pub fn from_page(
+        page: &[u8],
+        page_std: bool,
+        compression: Option<BkpImageCompression>,
+    ) -> anyhow::Result<BkpImage> {
+        ensure!(
+            page.len() == BLCKSZ as usize,
+            "page length {} is not BLCKSZ",
+            page.len()
+        );
+
+        let (mut hole_offset, mut hole_length) = (0, 0);
+        if page_std {
+            let lower = page_get_lower(page)?;
+            let upper = page_get_upper(page)?;
+            if lower >= pg_constants::SIZE_OF_PAGE_HEADER && upper > lower && upper <= BLCKSZ {
+                hole_offset = lower;
+                hole_length = upper - lower;
+            }
+        }
+
+        let mut source = BytesMut::with_capacity(BLCKSZ as usize);
+        source.extend_from_slice(&page[..hole_offset as usize]);
+        source.extend_from_slice(&page[(hole_offset + hole_length) as usize..]);
+
+        if let Some(compression) = compression {
+            let compressed = match compression {
+                BkpImageCompression::Pglz => pglz_compress(&source),
+                BkpImageCompression::Lz4 => Some(lz4_compress(&source)),
+                BkpImageCompression::Zstd => Some(zstd::bulk::compress(
+                    &source,
+                    zstd::DEFAULT_COMPRESSION_LEVEL,
+                )?),
+            };
+            // Only keep it compressed if it saves more than the extra header
+            let extra_bytes = if hole_length > 0 {
+                SIZE_OF_XLOG_RECORD_BLOCK_COMPRESS_HEADER
+            } else {
+                0
+            };
+            if let Some(compressed) = compressed {
+                if compressed.len() + extra_bytes < source.len() {
+                    return Ok(BkpImage {
+                        hole_offset,
+                        hole_length,
+                        compression: Some(compression),
+                        image: Bytes::from(compressed),
+                    });
+                }
+            }
+        }
+
+        Ok(BkpImage {
+            hole_offset,
+            hole_length,
+            compression: None,
+            image: source.freeze(),
+        })
+    }
+
+    /// The bimg_info of the image, without BKPIMAGE_APPLY.
+    pub fn bimg_info(&self, pg_version: u32) -> anyhow::Result<u8> {
+        let mut bimg_info = 0;
+        if self.hole_length > 0 {
+            bimg_info |= pg_constants::BKPIMAGE_HAS_HOLE;
+        }
+        if let Some(compression) = self.compression {
+            bimg_info |= compression.bimg_info(pg_version)?;
+        }
+        Ok(bimg_info)
+    }
+
+    /// Restores the page from the image. Port of RestoreBlockImage().
+    pub fn restore(&self) -> anyhow::Result<Bytes> {
+        let len = BLCKSZ as usize - self.hole_length as usize;
+        let decompressed;
+        let data = match self.compression {
+            None => &self.image[..],
+            Some(compression) => {
+                decompressed = match compression {
+                    BkpImageCompression::Pglz => pglz_decompress(&self.image, len, true),
+                    BkpImageCompression::Lz4 => lz4_decompress(&self.image, len),
+                    BkpImageCompression::Zstd => {
+                        zstd::bulk::decompress(&self.image, len).context("invalid zstd data")
+                    }
+                }
+                .with_context(|| format!("could not decompress {compression:?} block image"))?;
+                &decompressed[..]
+            }
+        };
+        ensure!(
+            data.len() == len,
+            "block image length {} doesn't match hole length {}",
+            data.len(),
+            self.hole_length
+        );
+
+        let mut page = BytesMut::with_capacity(BLCKSZ as usize);
+        page.extend_from_slice(&data[..self.hole_offset as usize]);
+        page.resize(page.len() + self.hole_length as usize, 0);
+        page.extend_from_slice(&data[self.hole_offset as usize..]);
+        Ok(page.freeze())
+    }
+}
+
+/*
+ * LZ4 block format, as used by LZ4_compress_default() and
+ * LZ4_decompress_safe(): a sequence of literals and matches, each starting
+ * with a token of 4 bits of literal length and 4 bits of match length.
+ */
+const LZ4_MIN_MATCH: usize = 4;
+const LZ4_MFLIMIT: usize = 12;
+const LZ4_LAST_LITERALS: usize = 5;
+const LZ4_MAX_OFFSET: usize = 65535;
+const LZ4_HASH_LOG: u32 = 12;
+
+fn lz4_put_length(dest: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        dest.push(255);
+        len -= 255;
+    }
+    dest.push(len as u8);
+}
+
+fn lz4_put_sequence(dest: &mut Vec<u8>, literals: &[u8], match_: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = match_.map_or(0, |(_, len)| len - LZ4_MIN_MATCH);
+    dest.push(((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8);
+    if lit_len >= 15 {
+        lz4_put_length(dest, lit_len - 15);
+    }
+    dest.extend_from_slice(literals);
+    if let Some((offset, _)) = match_ {
+        dest.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            lz4_put_length(dest, match_len - 15);
+        }
+    }
+}
+
+/// Compresses `source` into an LZ4 block. This only looks for matches at the
+/// last position of each 4-byte sequence, so it compresses less than liblz4,
+/// but it follows the same end-of-block rules.
+fn lz4_compress(source: &[u8]) -> Vec<u8> {
+    let len = source.len();
+    let mut dest = Vec::with_capacity(len + len / 255 + 16);
+    let mut table = vec![usize::MAX; 1 << LZ4_HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while len >= LZ4_MFLIMIT && pos <= len - LZ4_MFLIMIT {
+        let seq = u32::from_le_bytes(source[pos..pos + 4].try_into().unwrap());
+        let h = (seq.wrapping_mul(2654435761) >> (32 - LZ4_HASH_LOG)) as usize;
+        let cand = std::mem::replace(&mut table[h], pos);
+        if cand != usize::MAX
+            && pos - cand <= LZ4_MAX_OFFSET
+            && source[cand..cand + 4] == source[pos..pos + 4]
+        {
+            let mut match_len = LZ4_MIN_MATCH;
+            while pos + match_len < len - LZ4_LAST_LITERALS
+                && source[cand + match_len] == source[pos + match_len]
+            {
+                match_len += 1;
+            }
+            lz4_put_sequence(
+                &mut dest,
+                &source[anchor..pos],
+                Some((pos - cand, match_len)),
+            );
+            pos += match_len;
+            anchor = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    lz4_put_sequence(&mut dest, &source[anchor..], None);
+    dest
+}
+
+fn lz4_get_length(source: &[u8], sp: &mut usize) -> anyhow::Result<usize> {
+    let mut len = 0;
+    loop {
+        let b = *source.get(*sp).context("truncated lz4 length")?;
+        *sp += 1;
+        len += b as usize;
+        if b != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+/// Decompresses an LZ4 block into exactly `rawsize` bytes.
+fn lz4_decompress(source: &[u8], rawsize: usize) -> anyhow::Result<Vec<u8>> {
+    let mut dest = Vec::with_capacity(rawsize);
+    let mut sp = 0;
+    loop {
+        let token = *source.get(sp).context("truncated lz4 block")?;
+        sp += 1;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len += lz4_get_length(source, &mut sp)?;
+        }
+        let literals = source
+            .get(sp..sp + lit_len)
+            .context("truncated lz4 literals")?;
+        ensure!(
+            dest.len() + lit_len <= rawsize,
+            "lz4 block decompresses into more than {rawsize} bytes"
+        );
+        dest.extend_from_slice(literals);
+        sp += lit_len;
+
+        // The last sequence has literals only
+        if sp == source.len() {
+            break;
+        }
+
+        let offset = source
+            .get(sp..sp + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .context("truncated lz4 match offset")?;
+        sp += 2;
+        if offset == 0 || offset > dest.len() {
+            bail!("invalid lz4 match offset {offset} at {}", dest.len());
+        }
+        let mut match_len = (token & 0x0f) as usize;
+        if match_len == 15 {
+            match_len += lz4_get_length(source, &mut sp)?;
+        }
+        match_len += LZ4_MIN_MATCH;
+        ensure!(
+            dest.len() + match_len <= rawsize,
+            "lz4 block decompresses into more than {rawsize} bytes"
+        );
+        // The match may overlap with the bytes it produces
+        for _ in 0..match_len {
+            dest.push(dest[dest.len() - offset]);
+        }
+    }
+    ensure!(
+        dest.len() == rawsize,
+        "lz4 block decompresses into {} bytes, expected {rawsize}",
+        dest.len()
+    );
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A heap page with a few tuples at the end, and the hole before them.
+    fn test_page() -> Vec<u8> {
+        let mut page = vec![0u8; BLCKSZ as usize];
+        let pd_lower: u16 = 24 + 4 * 30;
+        let pd_upper: u16 = BLCKSZ - 30 * 64;
+        page[12..14].copy_from_slice(&pd_lower.to_le_bytes());
+        page[14..16].copy_from_slice(&pd_upper.to_le_bytes());
+        page[16..18].copy_from_slice(&BLCKSZ.to_le_bytes());
+        for i in 0..30usize {
+            let lp = (pd_upper as u32 + i as u32 * 64) | (1 << 15) | (64 << 17);
+            page[24 + 4 * i..28 + 4 * i].copy_from_slice(&lp.to_le_bytes());
+            let tuple = &mut page[pd_upper as usize + i * 64..pd_upper as usize + (i + 1) * 64];
+            tuple[..8].copy_from_slice(&(i as u64 * 7919).to_le_bytes());
+            tuple[23] = 24;
+            tuple[24..].fill(b'x');
+        }
+        page
+    }
+
+    #[test]
+    fn test_bkpimage_roundtrip() {
+        let page = test_page();
+        for compression in [
+            None,
+            Some(BkpImageCompression::Pglz),
+            Some(BkpImageCompression::Lz4),
+            Some(BkpImageCompression::Zstd),
+        ] {
+            for page_std in [false, true] {
+                let bkpimage = BkpImage::from_page(&page, page_std, compression).unwrap();
+                assert_eq!(bkpimage.compression, compression);
+                if page_std {
+                    assert_eq!(bkpimage.hole_offset, 24 + 4 * 30);
+                    assert_eq!(bkpimage.hole_length, BLCKSZ - 30 * 64 - (24 + 4 * 30));
+                } else {
+                    assert_eq!(bkpimage.hole_length, 0);
+                }
+                if compression.is_some() {
+                    assert!(bkpimage.image.len() < 30 * 64);
+                }
+                assert_eq!(&bkpimage.restore().unwrap()[..], &page[..]);
+
+                // As decoded from a WAL record
+                let bimg_info = bkpimage.bimg_info(15).unwrap();
+                let decoded = BkpImage::new(
+                    bimg_info,
+                    bkpimage.hole_offset,
+                    bkpimage.hole_length,
+                    bkpimage.image.clone(),
+                    15,
+                )
+                .unwrap();
+                assert_eq!(decoded, bkpimage);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bkpimage_v14() {
+        let page = test_page();
+        let bkpimage = BkpImage::from_page(&page, true, Some(BkpImageCompression::Pglz)).unwrap();
+        let bimg_info = bkpimage.bimg_info(14).unwrap();
+        assert_eq!(
+            bimg_info,
+            pg_constants::BKPIMAGE_HAS_HOLE | v14::bindings::BKPIMAGE_IS_COMPRESSED
+        );
+        assert_eq!(
+            BkpImageCompression::from_bimg_info(bimg_info, 14).unwrap(),
+            Some(BkpImageCompression::Pglz)
+        );
+
+        let bkpimage = BkpImage::from_page(&page, true, Some(BkpImageCompression::Lz4)).unwrap();
+        assert!(bkpimage.bimg_info(14).is_err());
+    }
+
+    #[test]
+    fn test_bkpimage_uncompressible() {
+        // Falls back to an uncompressed image
+        let mut x = 1u32;
+        let page: Vec<u8> = (0..BLCKSZ)
+            .map(|_| {
+                // xorshift
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        let bkpimage = BkpImage::from_page(&page, false, Some(BkpImageCompression::Pglz)).unwrap();
+        assert_eq!(bkpimage.compression, None);
+        assert_eq!(bkpimage.image, page);
+        assert_eq!(bkpimage.bimg_info(15).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_bkpimage_invalid() {
+        let page = test_page();
+        let bkpimage = BkpImage::from_page(&page, true, Some(BkpImageCompression::Lz4)).unwrap();
+
+        // Wrong hole length
+        let mut broken = bkpimage.clone();
+        broken.hole_length -= 1;
+        assert!(broken.restore().is_err());
+
+        // Truncated
+        let mut broken = bkpimage;
+        broken.image = broken.image.slice(..broken.image.len() - 1);
+        assert!(broken.restore().is_err());
+
+        // Uncompressed, of the wrong length
+        let image = Bytes::from(page[..100].to_vec());
+        assert!(BkpImage::new(0, 0, 0, image, 15).is_err());
+    }
+
+    #[test]
+    fn test_lz4_decompress() {
+        // 'a', then a match of 11 bytes at offset 1, and "bcdef" as last
+        // literals
+        let block = [0x17, b'a', 0x01, 0x00, 0x50, b'b', b'c', b'd', b'e', b'f'];
+        assert_eq!(
+            lz4_decompress(&block, 17).unwrap(),
+            b"aaaaaaaaaaaabcdef".to_vec()
+        );
+        assert!(lz4_decompress(&block, 16).is_err());
+        assert!(lz4_decompress(&block, 18).is_err());
+        assert!(lz4_decompress(&block[..3], 17).is_err());
+
+        // Long literal and match lengths
+        let source: Vec<u8> = (0..300u32)
+            .map(|i| i as u8)
+            .chain(std::iter::repeat(7).take(1000))
+            .chain(b"end of block".iter().copied())
+            .collect();
+        let block = lz4_compress(&source);
+        assert!(block.len() < 350);
+        assert_eq!(lz4_decompress(&block, source.len()).unwrap(), source);
+
+        // Too short to have a match
+        let block = lz4_compress(b"abcabcabcab");
+        assert_eq!(block[0], 0xb0);
+        assert_eq!(lz4_decompress(&block, 11).unwrap(), b"abcabcabcab");
+    }
+}
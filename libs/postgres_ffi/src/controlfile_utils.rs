@@ -23,10 +23,16 @@
 //! information. You can use PostgreSQL's pg_controldata utility to view its
 //! contents.
 //!
-use super::bindings::{ControlFileData, PG_CONTROL_FILE_SIZE};
+use super::bindings::{CheckPoint, ControlFileData, DBState, PG_CONTROL_FILE_SIZE};
+use super::bindings::{
+    DBState_DB_IN_ARCHIVE_RECOVERY, DBState_DB_IN_CRASH_RECOVERY, DBState_DB_IN_PRODUCTION,
+    DBState_DB_SHUTDOWNED, DBState_DB_SHUTDOWNED_IN_RECOVERY, DBState_DB_SHUTDOWNING,
+    DBState_DB_STARTUP,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use bytes::{Bytes, BytesMut};
+use std::path::Path;
 
 /// Equivalent to sizeof(ControlFileData) in C
 const SIZEOF_CONTROLDATA: usize = std::mem::size_of::<ControlFileData>();
@@ -94,4 +100,62 @@ impl ControlFileData {
 
         buf.into()
     }
+
+    ///
+    /// Read and validate the control file of a data directory, at
+    /// `global/pg_control` under `pgdata`.
+    ///
+    pub fn read_from_datadir(pgdata: &Path) -> Result<ControlFileData> {
+        let path = pgdata.join("global").join("pg_control");
+        let buf =
+            std::fs::read(&path).with_context(|| format!("could not read {}", path.display()))?;
+        ControlFileData::decode(&buf).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    ///
+    /// Write the control file of a data directory, with a recomputed CRC.
+    ///
+    pub fn write_to_datadir(&self, pgdata: &Path) -> Result<()> {
+        let path = pgdata.join("global").join("pg_control");
+        std::fs::write(&path, self.encode())
+            .with_context(|| format!("could not write {}", path.display()))
+    }
+
+    /// Make `checkpoint` the latest checkpoint of the cluster, with its
+    /// record at `checkpoint_lsn`.
+    pub fn set_checkpoint(&mut self, checkpoint: CheckPoint, checkpoint_lsn: u64) {
+        self.checkPoint = checkpoint_lsn;
+        self.checkPointCopy = checkpoint;
+    }
+
+    /// Mark the cluster as shut down cleanly, so that Postgres starts without
+    /// crash recovery.
+    pub fn set_shut_down(&mut self) {
+        self.state = DBState_DB_SHUTDOWNED;
+    }
+
+    /// Whether the cluster was shut down cleanly, outside of recovery.
+    pub fn is_shut_down(&self) -> bool {
+        self.state == DBState_DB_SHUTDOWNED
+    }
+
+    /// The cluster state as pg_controldata prints it.
+    pub fn state_name(&self) -> &'static str {
+        db_state_name(self.state)
+    }
+}
+
+/// Equivalent of dbState() in pg_controldata.
+pub fn db_state_name(state: DBState) -> &'static str {
+    #[allow(non_upper_case_globals)]
+    match state {
+        DBState_DB_STARTUP => "starting up",
+        DBState_DB_SHUTDOWNED => "shut down",
+        DBState_DB_SHUTDOWNED_IN_RECOVERY => "shut down in recovery",
+        DBState_DB_SHUTDOWNING => "shutting down",
+        DBState_DB_IN_CRASH_RECOVERY => "in crash recovery",
+        DBState_DB_IN_ARCHIVE_RECOVERY => "in archive recovery",
+        DBState_DB_IN_PRODUCTION => "in production",
+        _ => "unrecognized status code",
+    }
 }
@@ -0,0 +1,251 @@
+//!
+//! Manipulation of free space map pages, from freespace.c and fsmpage.c.
+//!
+//! Each page of the FSM fork holds a binary tree of free space categories in
+//! an array, after the page header and fp_next_slot: the leaves are the slots,
+//! one per block of the level below, and each inner node holds the maximum of
+//! its children. Only the bottom level of the FSM has slots for heap blocks,
+//! the upper levels address the pages of the level below.
+//!
+use crate::pg_constants::{
+    FSM_LEAF_NODES_PER_PAGE, FSM_NODES_PER_PAGE, FSM_NON_LEAF_NODES_PER_PAGE,
+    MAXALIGN_SIZE_OF_PAGE_HEADER_DATA, SLOTS_PER_FSM_PAGE,
+};
+use crate::{fsm_logical_to_physical, BlockNumber, BLCKSZ};
+
+/// offsetof(FSMPageData, fp_nodes)
+const FP_NODES_OFFSET: usize = MAXALIGN_SIZE_OF_PAGE_HEADER_DATA + 4;
+
+/// Free space of a block above which its category is the highest one,
+/// MaxFSMRequestSize, i.e. MaxHeapTupleSize.
+pub const MAX_FSM_REQUEST_SIZE: usize = BLCKSZ as usize - 32;
+const FSM_CAT_STEP: usize = BLCKSZ as usize / 256;
+
+/// Converts an amount of free space to a category, rounding down. Port of
+/// fsm_space_avail_to_cat().
+pub fn fsm_space_avail_to_cat(avail: usize) -> u8 {
+    if avail >= MAX_FSM_REQUEST_SIZE {
+        return 255;
+    }
+    (avail / FSM_CAT_STEP).min(254) as u8
+}
+
+/// Lower bound of the free space of a category. Port of
+/// fsm_space_cat_to_avail().
+pub fn fsm_space_cat_to_avail(cat: u8) -> usize {
+    if cat == 255 {
+        MAX_FSM_REQUEST_SIZE
+    } else {
+        cat as usize * FSM_CAT_STEP
+    }
+}
+
+/// Block of the bottom level of the FSM and slot in it that hold the free
+/// space of heap block `heap_blkno`. Like fsm_get_location() followed by
+/// fsm_logical_to_physical().
+pub fn fsm_heap_block_location(heap_blkno: BlockNumber) -> (BlockNumber, u16) {
+    let logical = heap_blkno / SLOTS_PER_FSM_PAGE;
+    let slot = (heap_blkno % SLOTS_PER_FSM_PAGE) as u16;
+    (fsm_logical_to_physical(logical), slot)
+}
+
+/// Number of blocks of the FSM that a truncation of the heap to `heap_nblocks`
+/// blocks keeps, and the block among them to truncate with
+/// [`fsm_truncate_avail`], with the number of its slots to keep, if the heap
+/// doesn't end on a bottom level page boundary. Like
+/// FreeSpaceMapPrepareTruncateRel(), except that the upper levels of the tree
+/// are left to be fixed by the next vacuum.
+pub fn fsm_truncate_location(
+    heap_nblocks: BlockNumber,
+) -> (BlockNumber, Option<(BlockNumber, u16)>) {
+    let (block, slot) = fsm_heap_block_location(heap_nblocks);
+    if slot == 0 {
+        (block, None)
+    } else {
+        (block + 1, Some((block, slot)))
+    }
+}
+
+fn nodes(page: &[u8]) -> &[u8] {
+    &page[FP_NODES_OFFSET..FP_NODES_OFFSET + FSM_NODES_PER_PAGE]
+}
+
+fn nodes_mut(page: &mut [u8]) -> &mut [u8] {
+    &mut page[FP_NODES_OFFSET..FP_NODES_OFFSET + FSM_NODES_PER_PAGE]
+}
+
+fn left_child(nodeno: usize) -> usize {
+    2 * nodeno + 1
+}
+
+fn parent(nodeno: usize) -> usize {
+    (nodeno - 1) / 2
+}
+
+/// Category of slot `slot`. Port of fsm_get_avail().
+pub fn fsm_get_avail(page: &[u8], slot: u16) -> u8 {
+    debug_assert!((slot as usize) < FSM_LEAF_NODES_PER_PAGE);
+    nodes(page)[FSM_NON_LEAF_NODES_PER_PAGE + slot as usize]
+}
+
+/// Highest category of the page, stored at the root of its tree. Port of
+/// fsm_get_max_avail().
+pub fn fsm_get_max_avail(page: &[u8]) -> u8 {
+    nodes(page)[0]
+}
+
+/// Sets the category of slot `slot` to `value`, and updates the tree above it.
+/// Returns whether the page changed. Port of fsm_set_avail().
+pub fn fsm_set_avail(page: &mut [u8], slot: u16, value: u8) -> bool {
+    debug_assert!((slot as usize) < FSM_LEAF_NODES_PER_PAGE);
+    let nodes = nodes_mut(page);
+    let mut nodeno = FSM_NON_LEAF_NODES_PER_PAGE + slot as usize;
+
+    // If the value hasn't changed, we don't need to do anything
+    if nodes[nodeno] == value && value <= nodes[0] {
+        return false;
+    }
+    nodes[nodeno] = value;
+
+    // Propagate up, until we hit the root or a node that doesn't need to be
+    // updated.
+    loop {
+        nodeno = parent(nodeno);
+        let lchild = left_child(nodeno);
+        let rchild = lchild + 1;
+
+        let mut newvalue = nodes[lchild];
+        if rchild < FSM_NODES_PER_PAGE {
+            newvalue = newvalue.max(nodes[rchild]);
+        }
+        if nodes[nodeno] == newvalue {
+            break;
+        }
+        nodes[nodeno] = newvalue;
+        if nodeno == 0 {
+            break;
+        }
+    }
+
+    // The page may have been corrupt, with a root lower than a leaf
+    if value > nodes[0] {
+        fsm_rebuild_page(page);
+    }
+    true
+}
+
+/// Recomputes the inner nodes of the tree from the leaves. Returns whether
+/// the page changed. Port of fsm_rebuild_page().
+pub fn fsm_rebuild_page(page: &mut [u8]) -> bool {
+    let nodes = nodes_mut(page);
+    let mut changed = false;
+
+    for nodeno in (0..FSM_NON_LEAF_NODES_PER_PAGE).rev() {
+        let lchild = left_child(nodeno);
+        let rchild = lchild + 1;
+        let mut newvalue = 0;
+
+        // The first few nodes we examine might have zero or one child
+        if lchild < FSM_NODES_PER_PAGE {
+            newvalue = nodes[lchild];
+        }
+        if rchild < FSM_NODES_PER_PAGE {
+            newvalue = newvalue.max(nodes[rchild]);
+        }
+        if nodes[nodeno] != newvalue {
+            nodes[nodeno] = newvalue;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Clears the slots from `nslots` onwards, and updates the tree. Returns
+/// whether the page changed. Port of fsm_truncate_avail().
+pub fn fsm_truncate_avail(page: &mut [u8], nslots: u16) -> bool {
+    debug_assert!((nslots as usize) < FSM_LEAF_NODES_PER_PAGE);
+    let leaves = &mut nodes_mut(page)[FSM_NON_LEAF_NODES_PER_PAGE..];
+    let changed = leaves[nslots as usize..].iter().any(|&v| v != 0);
+    leaves[nslots as usize..].fill(0);
+
+    // Fix the upper nodes. Unlike fsm_truncate_avail(), this also reports the
+    // leaves cleared without changing them.
+    fsm_rebuild_page(page) || changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fsm_categories() {
+        assert_eq!(fsm_space_avail_to_cat(0), 0);
+        assert_eq!(fsm_space_avail_to_cat(31), 0);
+        assert_eq!(fsm_space_avail_to_cat(32), 1);
+        assert_eq!(fsm_space_avail_to_cat(8159), 254);
+        assert_eq!(fsm_space_avail_to_cat(8160), 255);
+        assert_eq!(fsm_space_cat_to_avail(1), 32);
+        assert_eq!(fsm_space_cat_to_avail(254), 8128);
+        assert_eq!(fsm_space_cat_to_avail(255), 8160);
+    }
+
+    #[test]
+    fn test_fsm_location() {
+        // 4069 slots per page, below the root page and the first page of
+        // the middle level
+        assert_eq!(SLOTS_PER_FSM_PAGE, 4069);
+        assert_eq!(fsm_heap_block_location(0), (2, 0));
+        assert_eq!(fsm_heap_block_location(4070), (3, 1));
+        // The second page of the middle level comes after the 4069 bottom
+        // pages under the first one
+        assert_eq!(fsm_heap_block_location(4069 * 4069), (4069 + 3, 0));
+
+        assert_eq!(fsm_truncate_location(0), (2, None));
+        assert_eq!(fsm_truncate_location(4069), (3, None));
+        assert_eq!(fsm_truncate_location(4070), (4, Some((3, 1))));
+    }
+
+    #[test]
+    fn test_fsm_set_avail() {
+        let mut page = vec![0u8; BLCKSZ as usize];
+
+        assert!(fsm_set_avail(&mut page, 10, 100));
+        assert!(!fsm_set_avail(&mut page, 10, 100));
+        assert_eq!(fsm_get_avail(&page, 10), 100);
+        assert_eq!(fsm_get_max_avail(&page), 100);
+        // The leaves are at the end of the tree
+        assert_eq!(page[FP_NODES_OFFSET + 4095 + 10], 100);
+
+        assert!(fsm_set_avail(&mut page, 4068, 200));
+        assert_eq!(fsm_get_max_avail(&page), 200);
+        assert!(fsm_set_avail(&mut page, 4068, 50));
+        assert_eq!(fsm_get_max_avail(&page), 100);
+
+        // Each inner node is the maximum of its children
+        let mut rebuilt = page.clone();
+        assert!(!fsm_rebuild_page(&mut rebuilt));
+        assert_eq!(rebuilt, page);
+
+        // fp_next_slot and the page header are left alone
+        assert!(page[..FP_NODES_OFFSET].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_fsm_truncate_avail() {
+        let mut page = vec![0u8; BLCKSZ as usize];
+        for slot in 0..20 {
+            fsm_set_avail(&mut page, slot, slot as u8 + 1);
+        }
+
+        assert!(fsm_truncate_avail(&mut page, 10));
+        assert_eq!(fsm_get_avail(&page, 9), 10);
+        assert_eq!(fsm_get_avail(&page, 10), 0);
+        assert_eq!(fsm_get_max_avail(&page), 10);
+        assert!(!fsm_truncate_avail(&mut page, 10));
+
+        // A corrupt root is fixed
+        page[FP_NODES_OFFSET] = 0;
+        assert!(fsm_truncate_avail(&mut page, 10));
+        assert_eq!(fsm_get_max_avail(&page), 10);
+    }
+}
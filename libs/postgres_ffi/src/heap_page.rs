@@ -0,0 +1,560 @@
+//!
+//! Decoding of heap pages: line pointers and tuple headers, from itemid.h,
+//! itemptr.h, bufpage.h and htup_details.h.
+//!
+//! This is enough to inspect the tuples stored on a page without a compute
+//! node, like the heap_page_items() function of pageinspect does. The tuple
+//! data itself is returned as raw bytes, deforming it needs the tuple
+//! descriptor of the relation.
+//!
+use anyhow::{bail, ensure, Result};
+
+use crate::{BlockNumber, OffsetNumber, TransactionId, BLCKSZ};
+
+/* Line pointer states, lp_flags of ItemIdData */
+pub const LP_UNUSED: u8 = 0; /* unused (should always have lp_len=0) */
+pub const LP_NORMAL: u8 = 1; /* used (should always have lp_len>0) */
+pub const LP_REDIRECT: u8 = 2; /* HOT redirect (should have lp_len=0) */
+pub const LP_DEAD: u8 = 3; /* dead, may or may not have storage */
+
+/* Bits of pd_flags */
+pub const PD_HAS_FREE_LINES: u16 = 0x0001; /* are there any unused line pointers? */
+pub const PD_PAGE_FULL: u16 = 0x0002; /* not enough free space for new tuple? */
+pub const PD_ALL_VISIBLE: u16 = 0x0004; /* all tuples on page are visible to everyone */
+
+/* Bits of t_infomask */
+pub const HEAP_HASNULL: u16 = 0x0001; /* has null attribute(s) */
+pub const HEAP_HASVARWIDTH: u16 = 0x0002; /* has variable-width attribute(s) */
+pub const HEAP_HASEXTERNAL: u16 = 0x0004; /* has external stored attribute(s) */
+pub const HEAP_HASOID_OLD: u16 = 0x0008; /* has an object-id field */
+pub const HEAP_XMAX_KEYSHR_LOCK: u16 = 0x0010; /* xmax is a key-shared locker */
+pub const HEAP_COMBOCID: u16 = 0x0020; /* t_cid is a combo CID */
+pub const HEAP_XMAX_EXCL_LOCK: u16 = 0x0040; /* xmax is exclusive locker */
+pub const HEAP_XMAX_LOCK_ONLY: u16 = 0x0080; /* xmax, if valid, is only a locker */
+pub const HEAP_XMAX_SHR_LOCK: u16 = HEAP_XMAX_EXCL_LOCK | HEAP_XMAX_KEYSHR_LOCK;
+pub const HEAP_XMIN_COMMITTED: u16 = 0x0100; /* t_xmin committed */
+pub const HEAP_XMIN_INVALID: u16 = 0x0200; /* t_xmin invalid/aborted */
+pub const HEAP_XMIN_FROZEN: u16 = HEAP_XMIN_COMMITTED | HEAP_XMIN_INVALID;
+pub const HEAP_XMAX_COMMITTED: u16 = 0x0400; /* t_xmax committed */
+pub const HEAP_XMAX_INVALID: u16 = 0x0800; /* t_xmax invalid/aborted */
+pub const HEAP_XMAX_IS_MULTI: u16 = 0x1000; /* t_xmax is a MultiXactId */
+pub const HEAP_UPDATED: u16 = 0x2000; /* this is UPDATEd version of row */
+pub const HEAP_MOVED_OFF: u16 = 0x4000; /* moved to another place by pre-9.0 VACUUM FULL */
+pub const HEAP_MOVED_IN: u16 = 0x8000; /* moved from another place by pre-9.0 VACUUM FULL */
+pub const HEAP_MOVED: u16 = HEAP_MOVED_OFF | HEAP_MOVED_IN;
+
+/* Bits of t_infomask2 */
+pub const HEAP_NATTS_MASK: u16 = 0x07FF; /* 11 bits for number of attributes */
+pub const HEAP_KEYS_UPDATED: u16 = 0x2000; /* tuple was updated and key cols modified, or deleted */
+pub const HEAP_HOT_UPDATED: u16 = 0x4000; /* tuple was HOT-updated */
+pub const HEAP_ONLY_TUPLE: u16 = 0x8000; /* this is heap-only tuple */
+
+/// offsetof(HeapTupleHeaderData, t_bits)
+pub const SIZEOF_HEAP_TUPLE_HEADER: usize = 23;
+
+/// Offset of pd_linp in PageHeaderData, where the line pointers start.
+const PD_LINP_OFFSET: usize = 24;
+const SIZEOF_ITEM_ID_DATA: usize = 4;
+
+/// Checks that `page` holds a whole page, so that its header and line
+/// pointers can be read without going out of bounds.
+fn check_page_len(page: &[u8]) -> Result<()> {
+    ensure!(
+        page.len() >= BLCKSZ as usize,
+        "page is {} bytes, expected {BLCKSZ}",
+        page.len()
+    );
+    Ok(())
+}
+
+fn page_get_u16(page: &[u8], off: usize) -> Result<u16> {
+    check_page_len(page)?;
+    Ok(u16::from_le_bytes([page[off], page[off + 1]]))
+}
+
+pub fn page_get_flags(page: &[u8]) -> Result<u16> {
+    page_get_u16(page, 10)
+}
+
+fn page_set_flags(page: &mut [u8], flags: u16) -> Result<()> {
+    check_page_len(page)?;
+    page[10..12].copy_from_slice(&flags.to_le_bytes());
+    Ok(())
+}
+
+/// Port of PageIsAllVisible().
+pub fn page_is_all_visible(page: &[u8]) -> Result<bool> {
+    Ok(page_get_flags(page)? & PD_ALL_VISIBLE != 0)
+}
+
+/// Port of PageSetAllVisible(), as done by the redo of XLOG_HEAP2_VISIBLE.
+pub fn page_set_all_visible(page: &mut [u8]) -> Result<()> {
+    let flags = page_get_flags(page)?;
+    page_set_flags(page, flags | PD_ALL_VISIBLE)
+}
+
+/// Port of PageClearAllVisible().
+pub fn page_clear_all_visible(page: &mut [u8]) -> Result<()> {
+    let flags = page_get_flags(page)?;
+    page_set_flags(page, flags & !PD_ALL_VISIBLE)
+}
+
+pub fn page_get_lower(page: &[u8]) -> Result<u16> {
+    page_get_u16(page, 12)
+}
+
+pub fn page_get_upper(page: &[u8]) -> Result<u16> {
+    page_get_u16(page, 14)
+}
+
+pub fn page_get_special(page: &[u8]) -> Result<u16> {
+    page_get_u16(page, 16)
+}
+
+/// Number of line pointers on a page. Port of PageGetMaxOffsetNumber().
+pub fn page_get_max_offset_number(page: &[u8]) -> Result<OffsetNumber> {
+    let lower = page_get_lower(page)? as usize;
+    Ok(if lower <= PD_LINP_OFFSET {
+        0
+    } else {
+        ((lower - PD_LINP_OFFSET) / SIZEOF_ITEM_ID_DATA) as OffsetNumber
+    })
+}
+
+/// A line pointer, ItemIdData. In C it is a bitfield of 15 bits of offset,
+/// 2 bits of flags and 15 bits of length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemId {
+    pub lp_off: u16,
+    pub lp_flags: u8,
+    pub lp_len: u16,
+}
+
+impl ItemId {
+    pub fn decode(raw: u32) -> ItemId {
+        ItemId {
+            lp_off: (raw & 0x7FFF) as u16,
+            lp_flags: ((raw >> 15) & 0x03) as u8,
+            lp_len: (raw >> 17) as u16,
+        }
+    }
+
+    pub fn encode(&self) -> u32 {
+        (self.lp_off as u32 & 0x7FFF)
+            | ((self.lp_flags as u32 & 0x03) << 15)
+            | ((self.lp_len as u32 & 0x7FFF) << 17)
+    }
+
+    pub fn is_used(&self) -> bool {
+        self.lp_flags != LP_UNUSED
+    }
+
+    pub fn is_normal(&self) -> bool {
+        self.lp_flags == LP_NORMAL
+    }
+
+    pub fn is_redirected(&self) -> bool {
+        self.lp_flags == LP_REDIRECT
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.lp_flags == LP_DEAD
+    }
+
+    pub fn has_storage(&self) -> bool {
+        self.lp_len != 0
+    }
+
+    /// Offset number a redirect line pointer points to, stored in lp_off.
+    pub fn redirect_target(&self) -> Option<OffsetNumber> {
+        self.is_redirected().then_some(self.lp_off)
+    }
+}
+
+/// Line pointer `offnum` of a page, 1-based like OffsetNumbers are.
+/// Port of PageGetItemId().
+pub fn page_get_item_id(page: &[u8], offnum: OffsetNumber) -> Result<ItemId> {
+    let max = page_get_max_offset_number(page)?;
+    ensure!(
+        (1..=max).contains(&offnum),
+        "offset number {offnum} out of range 1..={max}"
+    );
+    let off = PD_LINP_OFFSET + (offnum as usize - 1) * SIZEOF_ITEM_ID_DATA;
+    // pd_lower is not checked against the page size, a corrupt one can put
+    // the line pointer past the end of the page.
+    ensure!(
+        off + SIZEOF_ITEM_ID_DATA <= page.len(),
+        "line pointer {offnum} at {off} is past the end of the page"
+    );
+    Ok(ItemId::decode(u32::from_le_bytes(
+        page[off..off + SIZEOF_ITEM_ID_DATA].try_into().unwrap(),
+    )))
+}
+
+/// The item a line pointer points to. Port of PageGetItem(), with the bounds
+/// checks of PageGetItem()'s callers, so that a corrupt page fails instead of
+/// panicking.
+pub fn page_get_item<'a>(page: &'a [u8], item_id: &ItemId) -> Result<&'a [u8]> {
+    ensure!(
+        item_id.has_storage() && !item_id.is_redirected(),
+        "line pointer {item_id:?} has no storage"
+    );
+    let start = item_id.lp_off as usize;
+    let end = start + item_id.lp_len as usize;
+    ensure!(
+        start >= page_get_upper(page)? as usize && end <= BLCKSZ as usize,
+        "line pointer {item_id:?} points outside of the tuple space of the page"
+    );
+    Ok(&page[start..end])
+}
+
+/// Pointer to a tuple, ItemPointerData, as stored in t_ctid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemPointer {
+    pub blkno: BlockNumber,
+    pub offnum: OffsetNumber,
+}
+
+impl ItemPointer {
+    pub const SIZE: usize = 6;
+
+    pub fn decode(buf: &[u8]) -> ItemPointer {
+        // BlockIdData is stored as two 16-bit halves, high half first
+        let bi_hi = u16::from_le_bytes([buf[0], buf[1]]) as u32;
+        let bi_lo = u16::from_le_bytes([buf[2], buf[3]]) as u32;
+        ItemPointer {
+            blkno: (bi_hi << 16) | bi_lo,
+            offnum: u16::from_le_bytes([buf[4], buf[5]]),
+        }
+    }
+}
+
+impl std::fmt::Display for ItemPointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({},{})", self.blkno, self.offnum)
+    }
+}
+
+/// Header of a heap tuple, HeapTupleHeaderData, followed by its null bitmap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapTupleHeader {
+    pub t_xmin: TransactionId,
+    pub t_xmax: TransactionId,
+    /// t_cid or t_xvac, depending on HEAP_MOVED
+    pub t_field3: u32,
+    pub t_ctid: ItemPointer,
+    pub t_infomask2: u16,
+    pub t_infomask: u16,
+    pub t_hoff: u8,
+    /// The null bitmap, present if HEAP_HASNULL is set.
+    pub t_bits: Option<Vec<u8>>,
+}
+
+impl HeapTupleHeader {
+    pub fn decode(tuple: &[u8]) -> Result<HeapTupleHeader> {
+        if tuple.len() < SIZEOF_HEAP_TUPLE_HEADER {
+            bail!(
+                "heap tuple is {} bytes, expected at least {SIZEOF_HEAP_TUPLE_HEADER}",
+                tuple.len()
+            );
+        }
+        let u32_at = |off: usize| u32::from_le_bytes(tuple[off..off + 4].try_into().unwrap());
+        let u16_at = |off: usize| u16::from_le_bytes([tuple[off], tuple[off + 1]]);
+        let mut hdr = HeapTupleHeader {
+            t_xmin: u32_at(0),
+            t_xmax: u32_at(4),
+            t_field3: u32_at(8),
+            t_ctid: ItemPointer::decode(&tuple[12..18]),
+            t_infomask2: u16_at(18),
+            t_infomask: u16_at(20),
+            t_hoff: tuple[22],
+            t_bits: None,
+        };
+        ensure!(
+            hdr.t_hoff as usize >= SIZEOF_HEAP_TUPLE_HEADER && hdr.t_hoff as usize <= tuple.len(),
+            "t_hoff {} out of range for a heap tuple of {} bytes",
+            hdr.t_hoff,
+            tuple.len()
+        );
+        if hdr.has_nulls() {
+            let bitmap_len = (hdr.natts() as usize + 7) / 8;
+            let bitmap_end = SIZEOF_HEAP_TUPLE_HEADER + bitmap_len;
+            ensure!(
+                bitmap_end <= hdr.t_hoff as usize,
+                "null bitmap of {} attributes overlaps tuple data at {}",
+                hdr.natts(),
+                hdr.t_hoff
+            );
+            hdr.t_bits = Some(tuple[SIZEOF_HEAP_TUPLE_HEADER..bitmap_end].to_vec());
+        }
+        Ok(hdr)
+    }
+
+    /// Number of attributes. Port of HeapTupleHeaderGetNatts().
+    pub fn natts(&self) -> u16 {
+        self.t_infomask2 & HEAP_NATTS_MASK
+    }
+
+    pub fn has_nulls(&self) -> bool {
+        self.t_infomask & HEAP_HASNULL != 0
+    }
+
+    /// Whether attribute `attnum`, 1-based, is null. Attributes past natts,
+    /// added to the relation after the tuple was written, count as null.
+    /// Port of att_isnull(), combined with the natts check of heap_attisnull().
+    pub fn attr_is_null(&self, attnum: u16) -> bool {
+        if attnum == 0 || attnum > self.natts() {
+            return true;
+        }
+        match &self.t_bits {
+            Some(bits) => {
+                let idx = (attnum - 1) as usize;
+                bits[idx / 8] & (1 << (idx % 8)) == 0
+            }
+            None => false,
+        }
+    }
+
+    pub fn xmin_committed(&self) -> bool {
+        self.t_infomask & HEAP_XMIN_COMMITTED != 0
+    }
+
+    pub fn xmin_invalid(&self) -> bool {
+        self.t_infomask & HEAP_XMIN_FROZEN == HEAP_XMIN_INVALID
+    }
+
+    pub fn xmin_frozen(&self) -> bool {
+        self.t_infomask & HEAP_XMIN_FROZEN == HEAP_XMIN_FROZEN
+    }
+
+    pub fn xmax_committed(&self) -> bool {
+        self.t_infomask & HEAP_XMAX_COMMITTED != 0
+    }
+
+    pub fn xmax_invalid(&self) -> bool {
+        self.t_infomask & HEAP_XMAX_INVALID != 0
+    }
+
+    pub fn xmax_is_multi(&self) -> bool {
+        self.t_infomask & HEAP_XMAX_IS_MULTI != 0
+    }
+
+    /// Port of HEAP_XMAX_IS_LOCKED_ONLY().
+    pub fn xmax_is_locked_only(&self) -> bool {
+        self.t_infomask & HEAP_XMAX_LOCK_ONLY != 0
+            || self.t_infomask & (HEAP_XMAX_IS_MULTI | HEAP_XMAX_EXCL_LOCK) == HEAP_XMAX_EXCL_LOCK
+    }
+
+    pub fn is_hot_updated(&self) -> bool {
+        self.t_infomask2 & HEAP_HOT_UPDATED != 0
+    }
+
+    pub fn is_heap_only(&self) -> bool {
+        self.t_infomask2 & HEAP_ONLY_TUPLE != 0
+    }
+
+    /// t_cid, unless the tuple was moved by a pre-9.0 VACUUM FULL, which
+    /// stored its xid in the same field.
+    pub fn cid(&self) -> Option<u32> {
+        (self.t_infomask & HEAP_MOVED == 0).then_some(self.t_field3)
+    }
+}
+
+/// A line pointer of a heap page, with the tuple it points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapPageItem<'a> {
+    pub offnum: OffsetNumber,
+    pub item_id: ItemId,
+    /// Header of the tuple, for normal line pointers.
+    pub header: Option<HeapTupleHeader>,
+    /// Data of the tuple, after t_hoff, for normal line pointers.
+    pub data: Option<&'a [u8]>,
+}
+
+/// Decodes all line pointers of a heap page and the tuples they point to,
+/// like heap_page_items() of pageinspect. A new page has no items.
+pub fn heap_page_items(page: &[u8]) -> Result<Vec<HeapPageItem<'_>>> {
+    ensure!(
+        page.len() == BLCKSZ as usize,
+        "page is {} bytes, expected {BLCKSZ}",
+        page.len()
+    );
+    if crate::page_is_new(page) {
+        return Ok(Vec::new());
+    }
+    let lower = page_get_lower(page)?;
+    let upper = page_get_upper(page)?;
+    let special = page_get_special(page)?;
+    ensure!(
+        lower as usize >= PD_LINP_OFFSET && lower <= upper && upper <= special && special <= BLCKSZ,
+        "corrupt page header: pd_lower {lower}, pd_upper {upper}, pd_special {special}"
+    );
+
+    let mut items = Vec::new();
+    for offnum in 1..=page_get_max_offset_number(page)? {
+        let item_id = page_get_item_id(page, offnum)?;
+        let (header, data) = if item_id.is_normal() {
+            let tuple = page_get_item(page, &item_id)?;
+            let header = HeapTupleHeader::decode(tuple)?;
+            let data = &tuple[header.t_hoff as usize..];
+            (Some(header), Some(data))
+        } else {
+            (None, None)
+        };
+        items.push(HeapPageItem {
+            offnum,
+            item_id,
+            header,
+            data,
+        });
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a heap page holding the given tuples, like PageAddItem() does.
+    fn build_page(tuples: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = vec![0u8; BLCKSZ as usize];
+        let mut upper = BLCKSZ as usize;
+        for (i, tuple) in tuples.iter().enumerate() {
+            upper = (upper - tuple.len()) & !7;
+            page[upper..upper + tuple.len()].copy_from_slice(tuple);
+            let item_id = ItemId {
+                lp_off: upper as u16,
+                lp_flags: LP_NORMAL,
+                lp_len: tuple.len() as u16,
+            };
+            let off = PD_LINP_OFFSET + i * SIZEOF_ITEM_ID_DATA;
+            page[off..off + 4].copy_from_slice(&item_id.encode().to_le_bytes());
+        }
+        let lower = PD_LINP_OFFSET + tuples.len() * SIZEOF_ITEM_ID_DATA;
+        page[12..14].copy_from_slice(&(lower as u16).to_le_bytes());
+        page[14..16].copy_from_slice(&(upper as u16).to_le_bytes());
+        page[16..18].copy_from_slice(&BLCKSZ.to_le_bytes());
+        page
+    }
+
+    fn build_tuple(xmin: u32, xmax: u32, ctid: (u32, u16), infomask: u16, natts: u16) -> Vec<u8> {
+        let mut tuple = Vec::new();
+        tuple.extend_from_slice(&xmin.to_le_bytes());
+        tuple.extend_from_slice(&xmax.to_le_bytes());
+        tuple.extend_from_slice(&0u32.to_le_bytes()); // t_cid
+        tuple.extend_from_slice(&((ctid.0 >> 16) as u16).to_le_bytes());
+        tuple.extend_from_slice(&(ctid.0 as u16).to_le_bytes());
+        tuple.extend_from_slice(&ctid.1.to_le_bytes());
+        tuple.extend_from_slice(&natts.to_le_bytes());
+        tuple.extend_from_slice(&infomask.to_le_bytes());
+        if infomask & HEAP_HASNULL != 0 {
+            tuple.push(32); // t_hoff
+            tuple.push(0b101); // attributes 1 and 3 are not null
+            tuple.resize(32, 0);
+        } else {
+            tuple.push(24); // t_hoff
+            tuple.push(0);
+        }
+        tuple.extend_from_slice(&42u32.to_le_bytes());
+        tuple
+    }
+
+    #[test]
+    fn page_all_visible() {
+        let mut page = build_page(&[]);
+        page[10] = PD_HAS_FREE_LINES as u8;
+        assert!(!page_is_all_visible(&page).unwrap());
+
+        page_set_all_visible(&mut page).unwrap();
+        assert!(page_is_all_visible(&page).unwrap());
+        assert_eq!(
+            page_get_flags(&page).unwrap(),
+            PD_HAS_FREE_LINES | PD_ALL_VISIBLE
+        );
+
+        page_clear_all_visible(&mut page).unwrap();
+        assert_eq!(page_get_flags(&page).unwrap(), PD_HAS_FREE_LINES);
+
+        assert!(page_is_all_visible(&page[..8]).is_err());
+        assert!(page_set_all_visible(&mut page[..8]).is_err());
+    }
+
+    #[test]
+    fn item_id_roundtrip() {
+        let item_id = ItemId {
+            lp_off: 8152,
+            lp_flags: LP_DEAD,
+            lp_len: 28,
+        };
+        assert_eq!(ItemId::decode(item_id.encode()), item_id);
+        // lp_off=8160, lp_flags=LP_NORMAL, lp_len=28, as written by PostgreSQL
+        assert_eq!(
+            ItemId::decode(0x0038_9FE0),
+            ItemId {
+                lp_off: 8160,
+                lp_flags: LP_NORMAL,
+                lp_len: 28,
+            }
+        );
+    }
+
+    #[test]
+    fn decode_heap_page() {
+        let page = build_page(&[
+            build_tuple(735, 0, (0, 1), HEAP_XMIN_COMMITTED | HEAP_XMAX_INVALID, 1),
+            build_tuple(736, 737, (0x1_0002, 7), HEAP_HASNULL | HEAP_XMIN_FROZEN, 3),
+        ]);
+        assert_eq!(page_get_max_offset_number(&page).unwrap(), 2);
+
+        let items = heap_page_items(&page).unwrap();
+        assert_eq!(items.len(), 2);
+
+        let first = items[0].header.as_ref().unwrap();
+        assert_eq!(items[0].offnum, 1);
+        assert_eq!((first.t_xmin, first.t_xmax), (735, 0));
+        assert_eq!(first.t_ctid.to_string(), "(0,1)");
+        assert!(first.xmin_committed() && !first.xmin_frozen() && first.xmax_invalid());
+        assert_eq!(first.t_bits, None);
+        assert!(!first.attr_is_null(1));
+        assert!(first.attr_is_null(2));
+        assert_eq!(items[0].data.unwrap(), &42u32.to_le_bytes());
+
+        let second = items[1].header.as_ref().unwrap();
+        assert_eq!((second.t_xmin, second.t_xmax), (736, 737));
+        assert_eq!(
+            second.t_ctid,
+            ItemPointer {
+                blkno: 0x1_0002,
+                offnum: 7
+            }
+        );
+        assert!(second.xmin_frozen() && !second.xmin_invalid());
+        assert_eq!(second.natts(), 3);
+        assert!(!second.attr_is_null(1));
+        assert!(second.attr_is_null(2));
+        assert!(!second.attr_is_null(3));
+        assert_eq!(items[1].data.unwrap(), &42u32.to_le_bytes());
+    }
+
+    #[test]
+    fn reject_corrupt_page() {
+        assert!(heap_page_items(&[0u8; BLCKSZ as usize]).unwrap().is_empty());
+
+        let mut page = build_page(&[build_tuple(735, 0, (0, 1), 0, 1)]);
+        // point the line pointer past the end of the page
+        let item_id = ItemId {
+            lp_off: BLCKSZ - 8,
+            lp_flags: LP_NORMAL,
+            lp_len: 28,
+        };
+        page[24..28].copy_from_slice(&item_id.encode().to_le_bytes());
+        assert!(heap_page_items(&page).is_err());
+        assert!(page_get_item_id(&page, 2).is_err());
+
+        // pd_lower past the end of the page
+        page[12..14].copy_from_slice(&(BLCKSZ + 64).to_le_bytes());
+        assert!(page_get_item_id(&page, BLCKSZ / 4 + 8).is_err());
+        assert!(page_get_item_id(&page[..100], 1).is_err());
+        assert!(page_get_upper(&page[..10]).is_err());
+    }
+}
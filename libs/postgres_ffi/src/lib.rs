@@ -56,8 +56,20 @@ macro_rules! for_all_postgres_versions {
 
 for_all_postgres_versions! { postgres_ffi }
 
+pub mod bkpimage;
+pub mod free_space_map;
+pub mod heap_page;
+pub mod page_checksum;
 pub mod pg_constants;
+pub mod pglz;
 pub mod relfile_utils;
+pub mod relmapper;
+pub mod slru;
+pub mod transam;
+pub mod twophase;
+pub mod visibility_map;
+pub mod wal_generator;
+pub mod walrecord;
 
 // Export some widely used datatypes that are unlikely to change across Postgres versions
 pub use v14::bindings::{uint32, uint64, Oid};
@@ -78,6 +90,9 @@ pub use v14::bindings::{CheckPoint, ControlFileData};
 pub const BLCKSZ: u16 = 8192;
 pub const RELSEG_SIZE: u32 = 1024 * 1024 * 1024 / (BLCKSZ as u32);
 pub const XLOG_BLCKSZ: usize = 8192;
+
+/// The default WAL segment size. A cluster initialized with a different
+/// `--wal-segsize` has its own in the control file, see [`wal_seg_size`].
 pub const WAL_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
 
 pub const MAX_SEND_SIZE: usize = XLOG_BLCKSZ * 16;
@@ -90,6 +105,8 @@ pub use v14::xlog_utils::XLogFileName;
 
 pub use v14::bindings::DBState_DB_SHUTDOWNED;
 
+pub use transam::{transaction_id_is_normal, transaction_id_precedes, FullTransactionId};
+
 pub fn bkpimage_is_compressed(bimg_info: u8, version: u32) -> anyhow::Result<bool> {
     match version {
         14 => Ok(bimg_info & v14::bindings::BKPIMAGE_IS_COMPRESSED != 0),
@@ -100,17 +117,29 @@ pub fn bkpimage_is_compressed(bimg_info: u8, version: u32) -> anyhow::Result<boo
     }
 }
 
+/// Returns the name of the directory of the files of a cluster in a
+/// tablespace, TABLESPACE_VERSION_DIRECTORY.
+pub fn tablespace_version_directory(pg_version: u32) -> anyhow::Result<String> {
+    let catalog_version = match pg_version {
+        14 => v14::bindings::CATALOG_VERSION_NO,
+        15 => v15::bindings::CATALOG_VERSION_NO,
+        _ => anyhow::bail!("Unknown version {}", pg_version),
+    };
+    Ok(format!("PG_{pg_version}_{catalog_version}"))
+}
+
 pub fn generate_wal_segment(
     segno: u64,
     system_id: u64,
     pg_version: u32,
     lsn: Lsn,
+    wal_seg_size: usize,
 ) -> Result<Bytes, SerializeError> {
-    assert_eq!(segno, lsn.segment_number(WAL_SEGMENT_SIZE));
+    assert_eq!(segno, lsn.segment_number(wal_seg_size));
 
     match pg_version {
-        14 => v14::xlog_utils::generate_wal_segment(segno, system_id, lsn),
-        15 => v15::xlog_utils::generate_wal_segment(segno, system_id, lsn),
+        14 => v14::xlog_utils::generate_wal_segment(segno, system_id, lsn, wal_seg_size),
+        15 => v15::xlog_utils::generate_wal_segment(segno, system_id, lsn, wal_seg_size),
         _ => Err(SerializeError::BadInput),
     }
 }
@@ -128,6 +157,30 @@ pub fn generate_pg_control(
     }
 }
 
+/// Returns the WAL segment size the cluster was initialized with, from its control file.
+pub fn wal_seg_size(pg_control_bytes: &[u8], pg_version: u32) -> anyhow::Result<usize> {
+    let wal_seg_size = match pg_version {
+        14 => v14::ControlFileData::decode(pg_control_bytes)?.xlog_seg_size,
+        15 => v15::ControlFileData::decode(pg_control_bytes)?.xlog_seg_size,
+        _ => anyhow::bail!("Unknown version {}", pg_version),
+    };
+    anyhow::ensure!(
+        wal_seg_size.is_power_of_two() && wal_seg_size as usize >= XLOG_BLCKSZ,
+        "invalid WAL segment size {wal_seg_size} in the control file"
+    );
+    Ok(wal_seg_size as usize)
+}
+
+/// Returns whether the cluster was initialized with data checksums, from its control file.
+pub fn data_checksums_enabled(pg_control_bytes: &[u8], pg_version: u32) -> anyhow::Result<bool> {
+    let data_checksum_version = match pg_version {
+        14 => v14::ControlFileData::decode(pg_control_bytes)?.data_checksum_version,
+        15 => v15::ControlFileData::decode(pg_control_bytes)?.data_checksum_version,
+        _ => anyhow::bail!("Unknown version {}", pg_version),
+    };
+    Ok(data_checksum_version != 0)
+}
+
 // PG timeline is always 1, changing it doesn't have any useful meaning in Neon.
 //
 // NOTE: this is not to be confused with Neon timelines; different concept!
@@ -137,26 +190,6 @@ pub fn generate_pg_control(
 // for example. FIXME later.
 pub const PG_TLI: u32 = 1;
 
-//  See TransactionIdIsNormal in transam.h
-pub const fn transaction_id_is_normal(id: TransactionId) -> bool {
-    id > pg_constants::FIRST_NORMAL_TRANSACTION_ID
-}
-
-// See TransactionIdPrecedes in transam.c
-pub const fn transaction_id_precedes(id1: TransactionId, id2: TransactionId) -> bool {
-    /*
-     * If either ID is a permanent XID then we can just do unsigned
-     * comparison.  If both are normal, do a modulo-2^32 comparison.
-     */
-
-    if !(transaction_id_is_normal(id1)) || !transaction_id_is_normal(id2) {
-        return id1 < id2;
-    }
-
-    let diff = id1.wrapping_sub(id2) as i32;
-    diff < 0
-}
-
 // Check if page is not yet initialized (port of Postgres PageIsInit() macro)
 pub fn page_is_new(pg: &[u8]) -> bool {
     pg[14] == 0 && pg[15] == 0 // pg_upper == 0
@@ -218,22 +251,38 @@ pub mod waldecoder {
     pub struct WalStreamDecoder {
         pub lsn: Lsn,
         pub pg_version: u32,
+        pub wal_seg_size: usize,
         pub inputbuf: BytesMut,
         pub state: State,
     }
 
+    /// The check that WAL failed in the [`WalStreamDecoder`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum WalDecodeErrorKind {
+        /// A page header is invalid or doesn't match the decoding position.
+        PageHeader,
+        /// A record is malformed, e.g. too short.
+        Record,
+        /// The CRC of a record doesn't match its contents.
+        Crc,
+        /// The Postgres version of the WAL is unknown.
+        UnknownVersion,
+    }
+
     #[derive(Error, Debug, Clone)]
     #[error("{msg} at {lsn}")]
     pub struct WalDecodeError {
+        pub kind: WalDecodeErrorKind,
         pub msg: String,
         pub lsn: Lsn,
     }
 
     impl WalStreamDecoder {
-        pub fn new(lsn: Lsn, pg_version: u32) -> WalStreamDecoder {
+        pub fn new(lsn: Lsn, pg_version: u32, wal_seg_size: usize) -> WalStreamDecoder {
             WalStreamDecoder {
                 lsn,
                 pg_version,
+                wal_seg_size,
                 inputbuf: BytesMut::new(),
                 state: State::WaitingForRecord,
             }
@@ -261,6 +310,7 @@ pub mod waldecoder {
                     self.poll_decode_internal()
                 }
                 _ => Err(WalDecodeError {
+                    kind: WalDecodeErrorKind::UnknownVersion,
                     msg: format!("Unknown version {}", self.pg_version),
                     lsn: self.lsn,
                 }),
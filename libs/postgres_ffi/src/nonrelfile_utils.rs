@@ -2,12 +2,12 @@
 //! Common utilities for dealing with PostgreSQL non-relation files.
 //!
 use crate::pg_constants;
-use crate::transaction_id_precedes;
+use crate::slru::{MultiXactMembers, Slru};
 use byteorder::{ByteOrder, LittleEndian};
 use bytes::BytesMut;
 use log::*;
 
-use super::bindings::{MultiXactId, XidCSN};
+use super::bindings::{MultiXactId, TimestampTz, XidCSN};
 
 pub fn transaction_id_set_status(xid: u32, status: u8, page: &mut BytesMut) {
     trace!(
@@ -35,38 +35,74 @@ pub fn transaction_id_get_status(xid: u32, page: &[u8]) -> u8 {
     (page[byteno] >> bshift) & pg_constants::CLOG_XACT_BITMASK
 }
 
+// CSN log utils
+
+fn csn_entry_offset(xid: u32) -> usize {
+    let entryno = xid % pg_constants::CSN_LOG_XACTS_PER_PAGE;
+    (entryno * pg_constants::CSN_SIZE) as usize
+}
+
 pub fn transaction_id_set_csn(xid: u32, csn: XidCSN, page: &mut BytesMut) {
     trace!("handle_apply_csn_request for RM_XACT_ID-{}", csn);
 
-    let entryno: usize = xid as usize % pg_constants::CSN_LOG_XACTS_PER_PAGE as usize;
-    let bytebegin: usize = entryno * pg_constants::CSN_SIZE as usize;
-    let byteend: usize = bytebegin + pg_constants::CSN_SIZE as usize;
-
-    LittleEndian::write_u64(&mut page[bytebegin..byteend], csn);
+    let off = csn_entry_offset(xid);
+    LittleEndian::write_u64(&mut page[off..off + pg_constants::CSN_SIZE as usize], csn);
 }
 
-// See CLOGPagePrecedes in clog.c
-pub const fn clogpage_precedes(page1: u32, page2: u32) -> bool {
-    let mut xid1 = page1 * pg_constants::CLOG_XACTS_PER_PAGE;
-    xid1 += pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1;
-    let mut xid2 = page2 * pg_constants::CLOG_XACTS_PER_PAGE;
-    xid2 += pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1;
+/// Returns the CSN of `xid` stored in its pg_csn page, like
+/// CSNLogGetCSNByXid().
+pub fn transaction_id_get_csn(xid: u32, page: &[u8]) -> XidCSN {
+    let off = csn_entry_offset(xid);
+    LittleEndian::read_u64(&page[off..off + pg_constants::CSN_SIZE as usize])
+}
 
-    transaction_id_precedes(xid1, xid2)
-        && transaction_id_precedes(xid1, xid2 + pg_constants::CLOG_XACTS_PER_PAGE - 1)
+/// Iterates over the XIDs of a pg_csn page, with their CSNs. `logical_pageno`
+/// is the page number in its region, see [`crate::slru::CsnLog`].
+pub fn csn_log_scan_page(
+    logical_pageno: u32,
+    page: &[u8],
+) -> impl Iterator<Item = (u32, XidCSN)> + '_ {
+    let first_xid = logical_pageno.wrapping_mul(pg_constants::CSN_LOG_XACTS_PER_PAGE);
+    page.chunks_exact(pg_constants::CSN_SIZE as usize)
+        .enumerate()
+        .map(move |(entryno, entry)| {
+            (
+                first_xid.wrapping_add(entryno as u32),
+                LittleEndian::read_u64(entry),
+            )
+        })
 }
 
-// See SlruMayDeleteSegment() in slru.c
-pub fn slru_may_delete_segment(
-    segpage: u32,
-    cutoff_page: u32,
-    precedes: fn(u32, u32) -> bool,
-) -> bool {
-    let seg_last_page = segpage + pg_constants::SLRU_PAGES_PER_SEGMENT - 1;
+// Commit timestamp utils
 
-    assert_eq!(segpage % pg_constants::SLRU_PAGES_PER_SEGMENT, 0);
+fn commit_ts_entry_offset(xid: u32) -> usize {
+    let entryno = xid % pg_constants::COMMIT_TS_XACTS_PER_PAGE;
+    (entryno * pg_constants::SIZE_OF_COMMIT_TIMESTAMP_ENTRY) as usize
+}
+
+/// Sets the commit timestamp and the replication origin of `xid` in its
+/// pg_commit_ts page, like TransactionIdSetCommitTs().
+pub fn transaction_id_set_commit_ts(
+    xid: u32,
+    timestamp: TimestampTz,
+    origin_id: u16,
+    page: &mut BytesMut,
+) {
+    trace!("set commit timestamp of xid {} to {}", xid, timestamp);
+
+    let off = commit_ts_entry_offset(xid);
+    LittleEndian::write_i64(&mut page[off..off + 8], timestamp);
+    LittleEndian::write_u16(&mut page[off + 8..off + 10], origin_id);
+}
 
-    precedes(segpage, cutoff_page) && precedes(seg_last_page, cutoff_page)
+/// Returns the commit timestamp and the replication origin of `xid` stored in
+/// its pg_commit_ts page.
+pub fn transaction_id_get_commit_ts(xid: u32, page: &[u8]) -> (TimestampTz, u16) {
+    let off = commit_ts_entry_offset(xid);
+    (
+        LittleEndian::read_i64(&page[off..off + 8]),
+        LittleEndian::read_u16(&page[off + 8..off + 10]),
+    )
 }
 
 // Multixact utils
@@ -89,31 +125,18 @@ pub fn mx_offset_to_member_offset(xid: MultiXactId) -> usize {
             + (xid as u16 % pg_constants::MULTIXACT_MEMBERS_PER_MEMBERGROUP) * 4) as usize
 }
 
-fn mx_offset_to_member_page(xid: u32, region: u32) -> u32 {
-    ((xid / pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32) * pg_constants::MAX_REGIONS) + region
-}
-
-pub fn mx_offset_to_member_segment(xid: u32, region: u32) -> i32 {
-    (mx_offset_to_member_page(xid, region) / pg_constants::SLRU_PAGES_PER_SEGMENT) as i32
+fn mx_offset_to_member_page(xid: u32, region: u32, max_regions: u32) -> u32 {
+    MultiXactMembers::page(xid, region, max_regions)
 }
 
-// See CSNLogPagePrecedes in csn_log.c
-pub const fn csnlogpage_precedes(page1: u32, page2: u32) -> bool {
-    if (page1 % pg_constants::MAX_REGIONS) != (page2 % pg_constants::MAX_REGIONS) {
-        // The two pages don't belong to the same region.
-        return false;
-    }
-    let mut xid1: u32 = (page1 / pg_constants::MAX_REGIONS) * pg_constants::CSN_LOG_XACTS_PER_PAGE;
-    xid1 += pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1;
-    let mut xid2: u32 = (page2 / pg_constants::MAX_REGIONS) * pg_constants::CSN_LOG_XACTS_PER_PAGE;
-    xid2 += pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1;
-
-    transaction_id_precedes(xid1, xid2)
+pub fn mx_offset_to_member_segment(xid: u32, region: u32, max_regions: u32) -> i32 {
+    MultiXactMembers::segment_block(mx_offset_to_member_page(xid, region, max_regions)).0 as i32
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pg_constants::MAX_REGIONS;
 
     #[test]
     fn test_multixid_calc() {
@@ -121,30 +144,107 @@ mod tests {
         // corresponding PostgreSQL C macros (MXOffsetTo*). These test values
         // were generated by calling the PostgreSQL macros with a little C
         // program.
-        assert_eq!(mx_offset_to_member_segment(0, 0), 0);
-        assert_eq!(mx_offset_to_member_page(0, 0), 0);
+        assert_eq!(mx_offset_to_member_segment(0, 0, MAX_REGIONS), 0);
+        assert_eq!(mx_offset_to_member_page(0, 0, MAX_REGIONS), 0);
         assert_eq!(mx_offset_to_flags_offset(0), 0);
         assert_eq!(mx_offset_to_flags_bitshift(0), 0);
         assert_eq!(mx_offset_to_member_offset(0), 4);
-        assert_eq!(mx_offset_to_member_segment(1, 0), 0);
-        assert_eq!(mx_offset_to_member_page(1, 0), 0);
+        assert_eq!(mx_offset_to_member_segment(1, 0, MAX_REGIONS), 0);
+        assert_eq!(mx_offset_to_member_page(1, 0, MAX_REGIONS), 0);
         assert_eq!(mx_offset_to_flags_offset(1), 0);
         assert_eq!(mx_offset_to_flags_bitshift(1), 8);
         assert_eq!(mx_offset_to_member_offset(1), 8);
-        assert_eq!(mx_offset_to_member_segment(123456789, 0), 150924);
-        assert_eq!(mx_offset_to_member_page(123456789, 0), 4829568);
+        assert_eq!(
+            mx_offset_to_member_segment(123456789, 0, MAX_REGIONS),
+            150924
+        );
+        assert_eq!(mx_offset_to_member_page(123456789, 0, MAX_REGIONS), 4829568);
         assert_eq!(mx_offset_to_flags_offset(123456789), 4780);
         assert_eq!(mx_offset_to_flags_bitshift(123456789), 8);
         assert_eq!(mx_offset_to_member_offset(123456789), 4788);
-        assert_eq!(mx_offset_to_member_segment(u32::MAX - 1, 0), 5250570);
-        assert_eq!(mx_offset_to_member_page(u32::MAX - 1, 0), 168018240);
+        assert_eq!(
+            mx_offset_to_member_segment(u32::MAX - 1, 0, MAX_REGIONS),
+            5250570
+        );
+        assert_eq!(
+            mx_offset_to_member_page(u32::MAX - 1, 0, MAX_REGIONS),
+            168018240
+        );
         assert_eq!(mx_offset_to_flags_offset(u32::MAX - 1), 5160);
         assert_eq!(mx_offset_to_flags_bitshift(u32::MAX - 1), 16);
         assert_eq!(mx_offset_to_member_offset(u32::MAX - 1), 5172);
-        assert_eq!(mx_offset_to_member_segment(u32::MAX, 0), 5250570);
-        assert_eq!(mx_offset_to_member_page(u32::MAX, 0), 168018240);
+        assert_eq!(
+            mx_offset_to_member_segment(u32::MAX, 0, MAX_REGIONS),
+            5250570
+        );
+        assert_eq!(
+            mx_offset_to_member_page(u32::MAX, 0, MAX_REGIONS),
+            168018240
+        );
         assert_eq!(mx_offset_to_flags_offset(u32::MAX), 5160);
         assert_eq!(mx_offset_to_flags_bitshift(u32::MAX), 24);
         assert_eq!(mx_offset_to_member_offset(u32::MAX), 5176);
     }
+
+    #[test]
+    fn test_csn_log() {
+        let mut page = BytesMut::zeroed(crate::BLCKSZ as usize);
+        // A transaction on logical page 1, with a subtransaction, and an
+        // aborted one
+        transaction_id_set_csn(1030, 0x1_0000_0028, &mut page);
+        transaction_id_set_csn(1031, 0x1_0000_0028, &mut page);
+        transaction_id_set_csn(1040, pg_constants::AbortedXidCSN, &mut page);
+
+        // 1024 CSNs fit in a page, 8 bytes each
+        assert_eq!(&page[48..56], &[0x28, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(transaction_id_get_csn(1030, &page), 0x1_0000_0028);
+        assert_eq!(transaction_id_get_csn(1031, &page), 0x1_0000_0028);
+        assert_eq!(
+            transaction_id_get_csn(1040, &page),
+            pg_constants::AbortedXidCSN
+        );
+        assert_eq!(
+            transaction_id_get_csn(1041, &page),
+            pg_constants::InProgressXidCSN
+        );
+
+        let entries: Vec<_> = csn_log_scan_page(1, &page).collect();
+        assert_eq!(entries.len(), pg_constants::CSN_LOG_XACTS_PER_PAGE as usize);
+        assert_eq!(entries[0], (1024, pg_constants::InProgressXidCSN));
+        assert_eq!(
+            entries
+                .into_iter()
+                .filter(|&(_, csn)| csn != pg_constants::InProgressXidCSN)
+                .collect::<Vec<_>>(),
+            vec![
+                (1030, 0x1_0000_0028),
+                (1031, 0x1_0000_0028),
+                (1040, pg_constants::AbortedXidCSN)
+            ]
+        );
+
+        // XIDs wrap around on the last page
+        let last_page = u32::MAX / pg_constants::CSN_LOG_XACTS_PER_PAGE;
+        let last = csn_log_scan_page(last_page, &page).last().unwrap();
+        assert_eq!(last.0, u32::MAX);
+    }
+
+    #[test]
+    fn test_commit_ts() {
+        let mut page = BytesMut::zeroed(crate::BLCKSZ as usize);
+        transaction_id_set_commit_ts(1000, 718_000_000_000_000, 3, &mut page);
+        transaction_id_set_commit_ts(1001, -1, 0, &mut page);
+
+        // Entries are packed, 10 bytes each
+        assert_eq!(
+            &page[1810..1820],
+            &[0, 0xE0, 0xF2, 0x66, 0x04, 0x8D, 2, 0, 3, 0]
+        );
+        assert_eq!(
+            transaction_id_get_commit_ts(1000, &page),
+            (718_000_000_000_000, 3)
+        );
+        assert_eq!(transaction_id_get_commit_ts(1001, &page), (-1, 0));
+        assert_eq!(transaction_id_get_commit_ts(1002, &page), (0, 0));
+    }
 }
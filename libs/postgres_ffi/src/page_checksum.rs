@@ -0,0 +1,123 @@
+//!
+//! Data page checksums, a port of pg_checksum_page() from
+//! src/include/storage/checksum_impl.h.
+//!
+//! The checksum is a variant of FNV-1a computed over 32 parallel lanes of
+//! 32-bit words, mixed with the block number so that a page written at the
+//! wrong location is detected too. It is stored in the pd_checksum field of
+//! the page header, which is excluded from the computation.
+//!
+use crate::BLCKSZ;
+
+/// Number of parallel checksum lanes.
+const N_SUMS: usize = 32;
+
+const FNV_PRIME: u32 = 16777619;
+
+/// Offset of pd_checksum in PageHeaderData.
+const PD_CHECKSUM_OFFSET: usize = 8;
+
+/// Base offsets to initialize each of the parallel FNV hashes.
+const CHECKSUM_BASE_OFFSETS: [u32; N_SUMS] = [
+    0x5B1F36E9, 0xB8525960, 0x02AB50AA, 0x1DE66D2A, 0x79FF467A, 0x9BB9F8A3, 0x217E7CD2, 0x83E13D2C,
+    0xF8D4474F, 0xE39EB970, 0x42C6AE16, 0x993216FA, 0x7B093B5D, 0x98DAFF3C, 0xF718902A, 0x0B1C9CDB,
+    0xE58F764B, 0x187636BC, 0x5D7B3BB1, 0xE73DE7DE, 0x92BEC979, 0xCCA6C0B2, 0x304A0979, 0x85AA43D4,
+    0x783125BB, 0x6CA8EAA2, 0xE407EAC6, 0x4B5CFC3E, 0x9FBF8C76, 0x15CA20BE, 0xF2CA9FD3, 0x959BD756,
+];
+
+fn checksum_comp(checksum: u32, value: u32) -> u32 {
+    let tmp = checksum ^ value;
+    tmp.wrapping_mul(FNV_PRIME) ^ (tmp >> 17)
+}
+
+/// Equivalent of pg_checksum_block(), with the pd_checksum field taken as zero.
+fn checksum_block(page: &[u8]) -> u32 {
+    let mut sums = CHECKSUM_BASE_OFFSETS;
+    for (i, row) in page.chunks_exact(4 * N_SUMS).enumerate() {
+        for (j, word) in row.chunks_exact(4).enumerate() {
+            let mut value = u32::from_le_bytes(word.try_into().unwrap());
+            if i == 0 && j == PD_CHECKSUM_OFFSET / 4 {
+                // pd_checksum is the low half of the third word.
+                value &= 0xFFFF0000;
+            }
+            sums[j] = checksum_comp(sums[j], value);
+        }
+    }
+    // Finally add in two rounds of zeroes for additional mixing.
+    for _ in 0..2 {
+        for sum in sums.iter_mut() {
+            *sum = checksum_comp(*sum, 0);
+        }
+    }
+    sums.iter().fold(0, |acc, sum| acc ^ sum)
+}
+
+/// Compute the checksum of a page, stored at block `blkno` of its relation
+/// fork. Equivalent of pg_checksum_page().
+pub fn pg_checksum_page(page: &[u8], blkno: u32) -> u16 {
+    assert_eq!(page.len(), BLCKSZ as usize);
+    let checksum = checksum_block(page) ^ blkno;
+    // Reduce to a u16 with an offset of one, so that the checksum is never zero.
+    ((checksum % 65535) + 1) as u16
+}
+
+pub fn page_get_checksum(page: &[u8]) -> u16 {
+    u16::from_le_bytes([page[PD_CHECKSUM_OFFSET], page[PD_CHECKSUM_OFFSET + 1]])
+}
+
+/// Stamp the checksum of a page, stored at block `blkno` of its relation fork.
+/// Equivalent of PageSetChecksumInplace(), new pages are left alone.
+pub fn page_set_checksum(page: &mut [u8], blkno: u32) {
+    if crate::page_is_new(page) {
+        return;
+    }
+    let checksum = pg_checksum_page(page, blkno);
+    page[PD_CHECKSUM_OFFSET..PD_CHECKSUM_OFFSET + 2].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// Check the checksum of a page, stored at block `blkno` of its relation fork.
+/// New pages don't have a checksum and always pass.
+pub fn page_checksum_is_valid(page: &[u8], blkno: u32) -> bool {
+    crate::page_is_new(page) || page_get_checksum(page) == pg_checksum_page(page, blkno)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_page() -> Vec<u8> {
+        (0..BLCKSZ as usize).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn checksum_matches_postgres() {
+        // Computed with pg_checksum_page() on the same page.
+        let page = test_page();
+        assert_eq!(pg_checksum_page(&page, 0), 44737);
+        assert_eq!(pg_checksum_page(&page, 42), 44699);
+    }
+
+    #[test]
+    fn checksum_ignores_pd_checksum() {
+        let mut page = test_page();
+        let checksum = pg_checksum_page(&page, 1);
+        page[PD_CHECKSUM_OFFSET] ^= 0xFF;
+        assert_eq!(pg_checksum_page(&page, 1), checksum);
+    }
+
+    #[test]
+    fn set_and_verify_checksum() {
+        let mut page = test_page();
+        page_set_checksum(&mut page, 3);
+        assert!(page_checksum_is_valid(&page, 3));
+        assert!(!page_checksum_is_valid(&page, 4));
+
+        page[100] ^= 1;
+        assert!(!page_checksum_is_valid(&page, 3));
+
+        let mut new_page = vec![0u8; BLCKSZ as usize];
+        page_set_checksum(&mut new_page, 3);
+        assert_eq!(page_get_checksum(&new_page), 0);
+        assert!(page_checksum_is_valid(&new_page, 3));
+    }
+}
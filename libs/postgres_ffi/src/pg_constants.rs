@@ -3,15 +3,37 @@
 //!
 //! Only place version-independent constants here.
 //!
-//! TODO: These probably should be auto-generated using bindgen,
-//! rather than copied by hand. Although on the other hand, it's nice
-//! to have them all here in one place, and have the ability to add
-//! comments on them.
+//! The ones defined with `pg_constants!` are plain defines of the headers,
+//! which are also generated in the bindings of v14, see `build.rs`. Each copy
+//! is checked against the generated value at compile time, so that a wrong
+//! copy fails the build. They are still listed here, to have them all in one
+//! place, with comments on them.
 //!
 
 use crate::BLCKSZ;
 use crate::{PageHeaderData, XLogRecord, XidCSN};
 
+/// Defines constants with the given values, and checks at compile time that
+/// they are equal to the ones of the generated bindings, converted to the
+/// given types. With `in $module;`, they are compared with that module of the
+/// bindings.
+macro_rules! pg_constants {
+    (in $module:ident; $($(#[$attr:meta])* $name:ident: $ty:ty = $value:expr;)*) => {
+        $(
+            $(#[$attr])*
+            pub const $name: $ty = $value;
+            const _: () = assert!($name == crate::v14::bindings::$module::$name as $ty);
+        )*
+    };
+    ($($(#[$attr:meta])* $name:ident: $ty:ty = $value:expr;)*) => {
+        $(
+            $(#[$attr])*
+            pub const $name: $ty = $value;
+            const _: () = assert!($name == crate::v14::bindings::$name as $ty);
+        )*
+    };
+}
+
 //
 // From pg_tablespace_d.h
 //
@@ -19,12 +41,14 @@ pub const DEFAULTTABLESPACE_OID: u32 = 1663;
 pub const GLOBALTABLESPACE_OID: u32 = 1664;
 
 // From storage_xlog.h
-pub const XLOG_SMGR_CREATE: u8 = 0x10;
-pub const XLOG_SMGR_TRUNCATE: u8 = 0x20;
+pg_constants! {
+    XLOG_SMGR_CREATE: u8 = 0x10;
+    XLOG_SMGR_TRUNCATE: u8 = 0x20;
 
-pub const SMGR_TRUNCATE_HEAP: u32 = 0x0001;
-pub const SMGR_TRUNCATE_VM: u32 = 0x0002;
-pub const SMGR_TRUNCATE_FSM: u32 = 0x0004;
+    SMGR_TRUNCATE_HEAP: u32 = 0x0001;
+    SMGR_TRUNCATE_VM: u32 = 0x0002;
+    SMGR_TRUNCATE_FSM: u32 = 0x0004;
+}
 
 //
 // From bufpage.h
@@ -42,12 +66,27 @@ pub const CLOG_XACTS_PER_PAGE: u32 = BLCKSZ as u32 * CLOG_XACTS_PER_BYTE;
 pub const CLOG_BITS_PER_XACT: u8 = 2;
 pub const CLOG_XACT_BITMASK: u8 = (1 << CLOG_BITS_PER_XACT) - 1;
 
-pub const TRANSACTION_STATUS_COMMITTED: u8 = 0x01;
-pub const TRANSACTION_STATUS_ABORTED: u8 = 0x02;
-pub const TRANSACTION_STATUS_SUB_COMMITTED: u8 = 0x03;
+pg_constants! {
+    TRANSACTION_STATUS_COMMITTED: u8 = 0x01;
+    TRANSACTION_STATUS_ABORTED: u8 = 0x02;
+    TRANSACTION_STATUS_SUB_COMMITTED: u8 = 0x03;
+
+    CLOG_ZEROPAGE: u8 = 0x00;
+    CLOG_TRUNCATE: u8 = 0x10;
+}
+
+//
+// Constants from commit_ts.c and commit_ts.h
+//
+
+/// Size of a CommitTimestampEntry: the commit time and the replication origin.
+pub const SIZE_OF_COMMIT_TIMESTAMP_ENTRY: u32 = 10;
+pub const COMMIT_TS_XACTS_PER_PAGE: u32 = BLCKSZ as u32 / SIZE_OF_COMMIT_TIMESTAMP_ENTRY;
 
-pub const CLOG_ZEROPAGE: u8 = 0x00;
-pub const CLOG_TRUNCATE: u8 = 0x10;
+pg_constants! {
+    COMMIT_TS_ZEROPAGE: u8 = 0x00;
+    COMMIT_TS_TRUNCATE: u8 = 0x10;
+}
 
 //
 // Constants from csn_log.c, csn_log.h, and csn_snapshpot.h
@@ -87,55 +126,67 @@ pub const fn HEAPBLK_TO_OFFSET(x: u32) -> u32 {
 }
 
 pub const BITS_PER_HEAPBLOCK: u16 = 2;
-pub const VISIBILITYMAP_ALL_VISIBLE: u8 = 0x01;
-pub const VISIBILITYMAP_ALL_FROZEN: u8 = 0x02;
-pub const VISIBILITYMAP_VALID_BITS: u8 = 0x03;
+pg_constants! {
+    VISIBILITYMAP_ALL_VISIBLE: u8 = 0x01;
+    VISIBILITYMAP_ALL_FROZEN: u8 = 0x02;
+    VISIBILITYMAP_VALID_BITS: u8 = 0x03;
+}
 
 // From xact.h
-pub const XLOG_XACT_COMMIT: u8 = 0x00;
-pub const XLOG_XACT_PREPARE: u8 = 0x10;
-pub const XLOG_XACT_ABORT: u8 = 0x20;
-pub const XLOG_XACT_COMMIT_PREPARED: u8 = 0x30;
-pub const XLOG_XACT_ABORT_PREPARED: u8 = 0x40;
+pg_constants! {
+    XLOG_XACT_COMMIT: u8 = 0x00;
+    XLOG_XACT_PREPARE: u8 = 0x10;
+    XLOG_XACT_ABORT: u8 = 0x20;
+    XLOG_XACT_COMMIT_PREPARED: u8 = 0x30;
+    XLOG_XACT_ABORT_PREPARED: u8 = 0x40;
+}
 
 // From srlu.h
 pub const SLRU_PAGES_PER_SEGMENT: u32 = 32;
 pub const SLRU_SEG_SIZE: usize = BLCKSZ as usize * SLRU_PAGES_PER_SEGMENT as usize;
 
-/* mask for filtering opcodes out of xl_info */
-pub const XLOG_XACT_OPMASK: u8 = 0x70;
-pub const XLOG_HEAP_OPMASK: u8 = 0x70;
-/* does this record have a 'xinfo' field or not */
-pub const XLOG_XACT_HAS_INFO: u8 = 0x80;
-
-/*
- * The following flags, stored in xinfo, determine which information is
- * contained in commit/abort records.
- */
-pub const XACT_XINFO_HAS_DBINFO: u32 = 1u32 << 0;
-pub const XACT_XINFO_HAS_SUBXACTS: u32 = 1u32 << 1;
-pub const XACT_XINFO_HAS_RELFILENODES: u32 = 1u32 << 2;
-pub const XACT_XINFO_HAS_INVALS: u32 = 1u32 << 3;
-pub const XACT_XINFO_HAS_TWOPHASE: u32 = 1u32 << 4;
-// pub const XACT_XINFO_HAS_ORIGIN: u32 = 1u32 << 5;
-// pub const XACT_XINFO_HAS_AE_LOCKS: u32 = 1u32 << 6;
-// pub const XACT_XINFO_HAS_GID: u32 = 1u32 << 7;
+pg_constants! {
+    /* mask for filtering opcodes out of xl_info */
+    XLOG_XACT_OPMASK: u8 = 0x70;
+    XLOG_HEAP_OPMASK: u8 = 0x70;
+    /* does this record have a 'xinfo' field or not */
+    XLOG_XACT_HAS_INFO: u8 = 0x80;
+
+    /*
+     * The following flags, stored in xinfo, determine which information is
+     * contained in commit/abort records.
+     */
+    XACT_XINFO_HAS_DBINFO: u32 = 1u32 << 0;
+    XACT_XINFO_HAS_SUBXACTS: u32 = 1u32 << 1;
+    XACT_XINFO_HAS_RELFILENODES: u32 = 1u32 << 2;
+    XACT_XINFO_HAS_INVALS: u32 = 1u32 << 3;
+    XACT_XINFO_HAS_TWOPHASE: u32 = 1u32 << 4;
+    XACT_XINFO_HAS_ORIGIN: u32 = 1u32 << 5;
+    // XACT_XINFO_HAS_AE_LOCKS: u32 = 1u32 << 6;
+    XACT_XINFO_HAS_GID: u32 = 1u32 << 7;
+}
 
 // From pg_control.h and rmgrlist.h
-pub const XLOG_NEXTOID: u8 = 0x30;
-pub const XLOG_SWITCH: u8 = 0x40;
-pub const XLOG_FPI_FOR_HINT: u8 = 0xA0;
-pub const XLOG_FPI: u8 = 0xB0;
+pg_constants! {
+    XLOG_NOOP: u8 = 0x20;
+    XLOG_NEXTOID: u8 = 0x30;
+    XLOG_SWITCH: u8 = 0x40;
+    XLOG_PARAMETER_CHANGE: u8 = 0x60;
+    XLOG_FPI_FOR_HINT: u8 = 0xA0;
+    XLOG_FPI: u8 = 0xB0;
+}
 
 // From multixact.h
 pub const FIRST_MULTIXACT_ID: u32 = 1;
 pub const MAX_MULTIXACT_ID: u32 = 0xFFFFFFFF;
 pub const MAX_MULTIXACT_OFFSET: u32 = 0xFFFFFFFF;
 
-pub const XLOG_MULTIXACT_ZERO_OFF_PAGE: u8 = 0x00;
-pub const XLOG_MULTIXACT_ZERO_MEM_PAGE: u8 = 0x10;
-pub const XLOG_MULTIXACT_CREATE_ID: u8 = 0x20;
-pub const XLOG_MULTIXACT_TRUNCATE_ID: u8 = 0x30;
+pg_constants! {
+    XLOG_MULTIXACT_ZERO_OFF_PAGE: u8 = 0x00;
+    XLOG_MULTIXACT_ZERO_MEM_PAGE: u8 = 0x10;
+    XLOG_MULTIXACT_CREATE_ID: u8 = 0x20;
+    XLOG_MULTIXACT_TRUNCATE_ID: u8 = 0x30;
+}
 
 pub const MULTIXACT_OFFSETS_PER_PAGE: u16 = BLCKSZ / 4;
 pub const MXACT_MEMBER_BITS_PER_XACT: u16 = 8;
@@ -151,65 +202,101 @@ pub const MULTIXACT_MEMBERS_PER_PAGE: u16 =
     MULTIXACT_MEMBERGROUPS_PER_PAGE * MULTIXACT_MEMBERS_PER_MEMBERGROUP;
 
 // From heapam_xlog.h
-pub const XLOG_HEAP_INSERT: u8 = 0x00;
-pub const XLOG_HEAP_DELETE: u8 = 0x10;
-pub const XLOG_HEAP_UPDATE: u8 = 0x20;
-pub const XLOG_HEAP_HOT_UPDATE: u8 = 0x40;
-pub const XLOG_HEAP_INIT_PAGE: u8 = 0x80;
-pub const XLOG_HEAP2_VISIBLE: u8 = 0x40;
-pub const XLOG_HEAP2_MULTI_INSERT: u8 = 0x50;
-pub const XLH_INSERT_ALL_FROZEN_SET: u8 = (1 << 5) as u8;
-pub const XLH_INSERT_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
-pub const XLH_UPDATE_OLD_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
-pub const XLH_UPDATE_NEW_ALL_VISIBLE_CLEARED: u8 = (1 << 1) as u8;
-pub const XLH_DELETE_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
+pg_constants! {
+    XLOG_HEAP_INSERT: u8 = 0x00;
+    XLOG_HEAP_DELETE: u8 = 0x10;
+    XLOG_HEAP_UPDATE: u8 = 0x20;
+    XLOG_HEAP_TRUNCATE: u8 = 0x30;
+    XLOG_HEAP_HOT_UPDATE: u8 = 0x40;
+    XLOG_HEAP_CONFIRM: u8 = 0x50;
+    XLOG_HEAP_LOCK: u8 = 0x60;
+    XLOG_HEAP_INPLACE: u8 = 0x70;
+    XLOG_HEAP_INIT_PAGE: u8 = 0x80;
+    XLOG_HEAP2_REWRITE: u8 = 0x00;
+    XLOG_HEAP2_PRUNE: u8 = 0x10;
+    XLOG_HEAP2_VACUUM: u8 = 0x20;
+    XLOG_HEAP2_FREEZE_PAGE: u8 = 0x30;
+    XLOG_HEAP2_VISIBLE: u8 = 0x40;
+    XLOG_HEAP2_MULTI_INSERT: u8 = 0x50;
+    XLOG_HEAP2_LOCK_UPDATED: u8 = 0x60;
+    XLOG_HEAP2_NEW_CID: u8 = 0x70;
+    XLH_INSERT_ALL_FROZEN_SET: u8 = (1 << 5) as u8;
+    XLH_INSERT_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
+    XLH_UPDATE_OLD_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
+    XLH_UPDATE_NEW_ALL_VISIBLE_CLEARED: u8 = (1 << 1) as u8;
+    XLH_DELETE_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
+}
 
 // From replication/message.h
-pub const XLOG_LOGICAL_MESSAGE: u8 = 0x00;
+pg_constants! {
+    XLOG_LOGICAL_MESSAGE: u8 = 0x00;
+}
 
 // From rmgrlist.h
-pub const RM_XLOG_ID: u8 = 0;
-pub const RM_XACT_ID: u8 = 1;
-pub const RM_SMGR_ID: u8 = 2;
-pub const RM_CLOG_ID: u8 = 3;
-pub const RM_DBASE_ID: u8 = 4;
-pub const RM_TBLSPC_ID: u8 = 5;
-pub const RM_MULTIXACT_ID: u8 = 6;
-pub const RM_RELMAP_ID: u8 = 7;
-pub const RM_STANDBY_ID: u8 = 8;
-pub const RM_HEAP2_ID: u8 = 9;
-pub const RM_HEAP_ID: u8 = 10;
-pub const RM_LOGICALMSG_ID: u8 = 21;
-pub const RM_CSNLOG_ID: u8 = 22;
-
-// from xlogreader.h
-pub const XLR_INFO_MASK: u8 = 0x0F;
-pub const XLR_RMGR_INFO_MASK: u8 = 0xF0;
-
-pub const XLOG_TBLSPC_CREATE: u8 = 0x00;
-pub const XLOG_TBLSPC_DROP: u8 = 0x10;
+pg_constants! {
+    in RmgrIds;
+    RM_XLOG_ID: u8 = 0;
+    RM_XACT_ID: u8 = 1;
+    RM_SMGR_ID: u8 = 2;
+    RM_CLOG_ID: u8 = 3;
+    RM_DBASE_ID: u8 = 4;
+    RM_TBLSPC_ID: u8 = 5;
+    RM_MULTIXACT_ID: u8 = 6;
+    RM_RELMAP_ID: u8 = 7;
+    RM_STANDBY_ID: u8 = 8;
+    RM_HEAP2_ID: u8 = 9;
+    RM_HEAP_ID: u8 = 10;
+    RM_BTREE_ID: u8 = 11;
+    RM_HASH_ID: u8 = 12;
+    RM_GIN_ID: u8 = 13;
+    RM_GIST_ID: u8 = 14;
+    RM_SEQ_ID: u8 = 15;
+    RM_SPGIST_ID: u8 = 16;
+    RM_BRIN_ID: u8 = 17;
+    RM_COMMIT_TS_ID: u8 = 18;
+    RM_REPLORIGIN_ID: u8 = 19;
+    RM_GENERIC_ID: u8 = 20;
+    RM_LOGICALMSG_ID: u8 = 21;
+    RM_CSNLOG_ID: u8 = 22;
+}
+
+// from xlogrecord.h
+pg_constants! {
+    XLR_INFO_MASK: u8 = 0x0F;
+    XLR_RMGR_INFO_MASK: u8 = 0xF0;
+}
+
+// From tablespace.h
+pg_constants! {
+    XLOG_TBLSPC_CREATE: u8 = 0x00;
+    XLOG_TBLSPC_DROP: u8 = 0x10;
+}
 
 pub const SIZEOF_XLOGRECORD: u32 = std::mem::size_of::<XLogRecord>() as u32;
 
 //
 // from xlogrecord.h
 //
-pub const XLR_MAX_BLOCK_ID: u8 = 32;
+pg_constants! {
+    XLR_MAX_BLOCK_ID: u8 = 32;
 
-pub const XLR_BLOCK_ID_DATA_SHORT: u8 = 255;
-pub const XLR_BLOCK_ID_DATA_LONG: u8 = 254;
-pub const XLR_BLOCK_ID_ORIGIN: u8 = 253;
-pub const XLR_BLOCK_ID_TOPLEVEL_XID: u8 = 252;
+    XLR_BLOCK_ID_DATA_SHORT: u8 = 255;
+    XLR_BLOCK_ID_DATA_LONG: u8 = 254;
+    XLR_BLOCK_ID_ORIGIN: u8 = 253;
+    XLR_BLOCK_ID_TOPLEVEL_XID: u8 = 252;
 
-pub const BKPBLOCK_FORK_MASK: u8 = 0x0F;
+    BKPBLOCK_FORK_MASK: u8 = 0x0F;
+}
 pub const _BKPBLOCK_FLAG_MASK: u8 = 0xF0;
-pub const BKPBLOCK_HAS_IMAGE: u8 = 0x10; /* block data is an XLogRecordBlockImage */
-pub const BKPBLOCK_HAS_DATA: u8 = 0x20;
-pub const BKPBLOCK_WILL_INIT: u8 = 0x40; /* redo will re-init the page */
-pub const BKPBLOCK_SAME_REL: u8 = 0x80; /* RelFileNode omitted, same as previous */
-
-/* Information stored in bimg_info */
-pub const BKPIMAGE_HAS_HOLE: u8 = 0x01; /* page image has "hole" */
+pg_constants! {
+    BKPBLOCK_HAS_IMAGE: u8 = 0x10; /* block data is an XLogRecordBlockImage */
+    BKPBLOCK_HAS_DATA: u8 = 0x20;
+    BKPBLOCK_WILL_INIT: u8 = 0x40; /* redo will re-init the page */
+    BKPBLOCK_SAME_REL: u8 = 0x80; /* RelFileNode omitted, same as previous */
+
+    /* Information stored in bimg_info */
+    BKPIMAGE_HAS_HOLE: u8 = 0x01; /* page image has "hole" */
+}
 
 /* From transam.h */
 pub const FIRST_NORMAL_TRANSACTION_ID: u32 = 3;
@@ -217,15 +304,17 @@ pub const INVALID_TRANSACTION_ID: u32 = 0;
 pub const FIRST_BOOTSTRAP_OBJECT_ID: u32 = 12000;
 pub const FIRST_NORMAL_OBJECT_ID: u32 = 16384;
 
-pub const XLOG_CHECKPOINT_SHUTDOWN: u8 = 0x00;
-pub const XLOG_CHECKPOINT_ONLINE: u8 = 0x10;
+pg_constants! {
+    XLOG_CHECKPOINT_SHUTDOWN: u8 = 0x00;
+    XLOG_CHECKPOINT_ONLINE: u8 = 0x10;
+}
 pub const XLP_FIRST_IS_CONTRECORD: u16 = 0x0001;
 pub const XLP_LONG_HEADER: u16 = 0x0002;
 
 /* From fsm_internals.h */
-const FSM_NODES_PER_PAGE: usize = BLCKSZ as usize - SIZEOF_PAGE_HEADER_DATA - 4;
-const FSM_NON_LEAF_NODES_PER_PAGE: usize = BLCKSZ as usize / 2 - 1;
-const FSM_LEAF_NODES_PER_PAGE: usize = FSM_NODES_PER_PAGE - FSM_NON_LEAF_NODES_PER_PAGE;
+pub const FSM_NODES_PER_PAGE: usize = BLCKSZ as usize - SIZEOF_PAGE_HEADER_DATA - 4;
+pub const FSM_NON_LEAF_NODES_PER_PAGE: usize = BLCKSZ as usize / 2 - 1;
+pub const FSM_LEAF_NODES_PER_PAGE: usize = FSM_NODES_PER_PAGE - FSM_NON_LEAF_NODES_PER_PAGE;
 pub const SLOTS_PER_FSM_PAGE: u32 = FSM_LEAF_NODES_PER_PAGE as u32;
 
 /* From visibilitymap.c */
@@ -233,6 +322,8 @@ pub const VM_HEAPBLOCKS_PER_PAGE: u32 =
     (BLCKSZ as usize - SIZEOF_PAGE_HEADER_DATA) as u32 * (8 / 2); // MAPSIZE * (BITS_PER_BYTE / BITS_PER_HEAPBLOCK)
 
 /* From remotexact.h */
+/// Number of regions the computes are built for. It's only the default of the
+/// `max_regions` tenant setting of the pageserver, which has to match them.
 pub const MAX_REGIONS: u32 = 64;
 
 // List of subdirectories inside pgdata.
@@ -0,0 +1,210 @@
+//!
+//! The PGLZ compression format, from pg_lzcompress.c.
+//!
+//! A compressed stream is a sequence of control bytes, each followed by up to
+//! 8 items: a literal byte for a 0 bit of the control byte, starting from the
+//! lowest one, and a tag for a 1 bit. A tag is 2 or 3 bytes, and copies a
+//! match of 3 to 273 bytes from up to 4095 bytes back in the output.
+//!
+//! The compressor here doesn't produce the same output as PostgreSQL's, it
+//! only keeps the last position of each 3-byte sequence, but any valid stream
+//! decompresses the same.
+//!
+use anyhow::{bail, ensure};
+
+const PGLZ_MAX_OFFSET: usize = 0x0fff;
+const PGLZ_MIN_MATCH: usize = 3;
+const PGLZ_MAX_MATCH: usize = 273;
+const PGLZ_HISTORY_SIZE: usize = 4096;
+
+/* PGLZ_strategy_default */
+const PGLZ_MIN_INPUT_SIZE: usize = 32;
+const PGLZ_MIN_COMP_RATE: usize = 25;
+const PGLZ_FIRST_SUCCESS_BY: usize = 1024;
+
+fn hist_index(source: &[u8], pos: usize) -> usize {
+    let s = &source[pos..pos + PGLZ_MIN_MATCH];
+    (((s[0] as usize) << 6) ^ ((s[1] as usize) << 3) ^ s[2] as usize) & (PGLZ_HISTORY_SIZE - 1)
+}
+
+/// Compresses `source`, with the default strategy of PostgreSQL: returns None
+/// if it's too small to bother, or doesn't compress by at least 25%. Like
+/// pglz_compress() with PGLZ_strategy_default.
+pub fn pglz_compress(source: &[u8]) -> Option<Vec<u8>> {
+    let slen = source.len();
+    if slen < PGLZ_MIN_INPUT_SIZE {
+        return None;
+    }
+    let result_max = slen * (100 - PGLZ_MIN_COMP_RATE) / 100;
+
+    let mut dest = Vec::with_capacity(result_max + 4);
+    let mut hist = [usize::MAX; PGLZ_HISTORY_SIZE];
+    let mut found_match = false;
+    let mut ctrl_pos = 0;
+    let mut nitems = 0;
+    let mut sp = 0;
+
+    while sp < slen {
+        // Give up as soon as the result is too large, or if nothing matched
+        // early on.
+        if dest.len() >= result_max {
+            return None;
+        }
+        if !found_match && dest.len() >= PGLZ_FIRST_SUCCESS_BY {
+            return None;
+        }
+
+        if nitems % 8 == 0 {
+            ctrl_pos = dest.len();
+            dest.push(0);
+        }
+
+        let mut match_len = 0;
+        let mut match_off = 0;
+        if sp + PGLZ_MIN_MATCH <= slen {
+            let cand = hist[hist_index(source, sp)];
+            if cand != usize::MAX && sp - cand <= PGLZ_MAX_OFFSET {
+                let max_len = PGLZ_MAX_MATCH.min(slen - sp);
+                match_len = (0..max_len)
+                    .take_while(|&i| source[cand + i] == source[sp + i])
+                    .count();
+                match_off = sp - cand;
+            }
+        }
+
+        let item_len = if match_len >= PGLZ_MIN_MATCH {
+            dest[ctrl_pos] |= 1 << (nitems % 8);
+            let hi = ((match_off & 0xf00) >> 4) as u8;
+            if match_len > 17 {
+                dest.push(hi | 0x0f);
+                dest.push(match_off as u8);
+                dest.push((match_len - 18) as u8);
+            } else {
+                dest.push(hi | (match_len - 3) as u8);
+                dest.push(match_off as u8);
+            }
+            found_match = true;
+            match_len
+        } else {
+            dest.push(source[sp]);
+            1
+        };
+        for pos in sp..sp + item_len {
+            if pos + PGLZ_MIN_MATCH <= slen {
+                hist[hist_index(source, pos)] = pos;
+            }
+        }
+        sp += item_len;
+        nitems += 1;
+    }
+
+    if dest.len() >= result_max {
+        return None;
+    }
+    Some(dest)
+}
+
+/// Decompresses `source` into `rawsize` bytes. With `check_complete`, it's an
+/// error if `source` doesn't decompress into exactly `rawsize` bytes. Port of
+/// pglz_decompress().
+pub fn pglz_decompress(
+    source: &[u8],
+    rawsize: usize,
+    check_complete: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let mut dest = Vec::with_capacity(rawsize);
+    let mut sp = 0;
+
+    while sp < source.len() && dest.len() < rawsize {
+        let mut ctrl = source[sp];
+        sp += 1;
+        for _ in 0..8 {
+            if sp >= source.len() || dest.len() >= rawsize {
+                break;
+            }
+            if ctrl & 1 != 0 {
+                // A tag: copy a match from the output so far. It may overlap
+                // with the bytes it produces, so copy byte by byte.
+                ensure!(sp + 2 <= source.len(), "pglz tag at {sp} is truncated");
+                let mut len = (source[sp] & 0x0f) as usize + 3;
+                let off = (((source[sp] & 0xf0) as usize) << 4) | source[sp + 1] as usize;
+                sp += 2;
+                if len == 18 {
+                    ensure!(sp < source.len(), "pglz tag at {sp} is truncated");
+                    len += source[sp] as usize;
+                    sp += 1;
+                }
+                if off == 0 || off > dest.len() {
+                    bail!("invalid pglz match offset {off} at {}", dest.len());
+                }
+                len = len.min(rawsize - dest.len());
+                for _ in 0..len {
+                    dest.push(dest[dest.len() - off]);
+                }
+            } else {
+                dest.push(source[sp]);
+                sp += 1;
+            }
+            ctrl >>= 1;
+        }
+    }
+
+    if check_complete {
+        ensure!(
+            dest.len() == rawsize && sp == source.len(),
+            "pglz data decompresses into {} bytes, expected {rawsize}",
+            dest.len()
+        );
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pglz_decompress() {
+        // "abc" as literals, then a tag copying 7 bytes from 3 back, and one
+        // copying 20 bytes, with the extra length byte
+        let source = [0b0011_1000, b'a', b'b', b'c', 0x04, 0x03, 0x0f, 0x06, 0x02];
+        let raw = pglz_decompress(&source, 30, true).unwrap();
+        assert_eq!(raw, b"abcabcabcabcabcabcabcabcabcabc");
+
+        // Shorter output is cut off, unless the whole stream must be used
+        assert_eq!(pglz_decompress(&source, 5, false).unwrap(), b"abcab");
+        assert!(pglz_decompress(&source, 5, true).is_err());
+        assert!(pglz_decompress(&source, 31, true).is_err());
+
+        // A match before the start of the output
+        assert!(pglz_decompress(&[0b0000_0010, b'a', 0x00, 0x02], 10, false).is_err());
+    }
+
+    #[test]
+    fn test_pglz_roundtrip() {
+        let mut page = vec![0u8; 8192];
+        for (i, b) in page.iter_mut().enumerate().skip(4000) {
+            *b = (i % 251) as u8 ^ (i / 1000) as u8;
+        }
+        let compressed = pglz_compress(&page).unwrap();
+        assert!(compressed.len() < page.len() / 2);
+        assert_eq!(
+            pglz_decompress(&compressed, page.len(), true).unwrap(),
+            page
+        );
+
+        // Too small, or incompressible
+        assert_eq!(pglz_compress(&page[..31]), None);
+        let mut x = 1u32;
+        let noise: Vec<u8> = (0..8192)
+            .map(|_| {
+                // xorshift
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        assert_eq!(pglz_compress(&noise), None);
+    }
+}
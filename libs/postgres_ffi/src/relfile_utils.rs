@@ -1,6 +1,7 @@
 //!
 //! Common utilities for dealing with PostgreSQL relation files.
 //!
+use crate::pg_constants::{DEFAULTTABLESPACE_OID, GLOBALTABLESPACE_OID};
 use once_cell::sync::OnceCell;
 use regex::Regex;
 
@@ -18,6 +19,8 @@ pub enum FilePathError {
     InvalidForkName,
     #[error("invalid relation data file name")]
     InvalidFileName,
+    #[error("invalid relation data file path")]
+    InvalidFilePath,
 }
 
 impl From<core::num::ParseIntError> for FilePathError {
@@ -60,12 +63,26 @@ pub fn forknumber_to_name(forknum: u8) -> Option<&'static str> {
 /// <oid>_<fork name>.<segment number>
 /// ```
 ///
-/// See functions relpath() and _mdfd_segpath() in PostgreSQL sources.
+/// See functions relpath() and _mdfd_segpath() in PostgreSQL sources. The files
+/// of temporary relations are rejected, see [`parse_temp_relfilename`].
 ///
 pub fn parse_relfilename(fname: &str) -> Result<(u32, u8, u32), FilePathError> {
+    match parse_temp_relfilename(fname)? {
+        (None, relnode, forknum, segno) => Ok((relnode, forknum, segno)),
+        (Some(_), _, _, _) => Err(FilePathError::InvalidFileName),
+    }
+}
+
+/// Like [`parse_relfilename`], but also accepts the filenames of temporary
+/// relations, which start with `t<backend id>_`. Returns (backend, relfilenode,
+/// forknum, segno) tuple, where backend is None for a permanent relation.
+pub fn parse_temp_relfilename(fname: &str) -> Result<(Option<i32>, u32, u8, u32), FilePathError> {
     static RELFILE_RE: OnceCell<Regex> = OnceCell::new();
     RELFILE_RE.get_or_init(|| {
-        Regex::new(r"^(?P<relnode>\d+)(_(?P<forkname>[a-z]+))?(\.(?P<segno>\d+))?$").unwrap()
+        Regex::new(
+            r"^(t(?P<backend>\d+)_)?(?P<relnode>\d+)(_(?P<forkname>[a-z]+))?(\.(?P<segno>\d+))?$",
+        )
+        .unwrap()
     });
 
     let caps = RELFILE_RE
@@ -74,6 +91,11 @@ pub fn parse_relfilename(fname: &str) -> Result<(u32, u8, u32), FilePathError> {
         .captures(fname)
         .ok_or(FilePathError::InvalidFileName)?;
 
+    let backend = caps
+        .name("backend")
+        .map(|b| b.as_str().parse::<i32>())
+        .transpose()?;
+
     let relnode_str = caps.name("relnode").unwrap().as_str();
     let relnode = relnode_str.parse::<u32>()?;
 
@@ -87,7 +109,106 @@ pub fn parse_relfilename(fname: &str) -> Result<(u32, u8, u32), FilePathError> {
         segno_match.unwrap().as_str().parse::<u32>()?
     };
 
-    Ok((relnode, forknum, segno))
+    Ok((backend, relnode, forknum, segno))
+}
+
+/// Returns the filename of segment `segno` of a relation file, the inverse of
+/// [`parse_temp_relfilename`]. `backend` is the backend owning a temporary
+/// relation, None for a permanent one.
+pub fn relfilename(relnode: u32, backend: Option<i32>, forknum: u8, segno: u32) -> String {
+    let mut name = match backend {
+        Some(backend) => format!("t{backend}_{relnode}"),
+        None => relnode.to_string(),
+    };
+
+    if let Some(fork_name) = forknumber_to_name(forknum) {
+        name += "_";
+        name += fork_name;
+    }
+
+    if segno != 0 {
+        name += ".";
+        name += &segno.to_string();
+    }
+
+    name
+}
+
+/// A segment of a relation file, with everything that makes its path in the
+/// data directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelFilePath {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    pub relnode: u32,
+    /// The backend owning a temporary relation, None for a permanent one.
+    pub backend: Option<i32>,
+    pub forknum: u8,
+    pub segno: u32,
+}
+
+impl RelFilePath {
+    /// Returns the path of the file relative to the data directory, like
+    /// relpath() followed by _mdfd_segpath(). `tablespace_version_dir` is the
+    /// directory of the cluster in the tablespaces, see
+    /// [`crate::tablespace_version_directory`].
+    ///
+    /// Formats:
+    ///
+    /// ```text
+    /// global/<filename>
+    /// base/<dbnode>/<filename>
+    /// pg_tblspc/<spcnode>/<tablespace version dir>/<dbnode>/<filename>
+    /// ```
+    pub fn to_path(&self, tablespace_version_dir: &str) -> String {
+        let fname = relfilename(self.relnode, self.backend, self.forknum, self.segno);
+        match self.spcnode {
+            GLOBALTABLESPACE_OID => format!("global/{fname}"),
+            DEFAULTTABLESPACE_OID => format!("base/{}/{fname}", self.dbnode),
+            spcnode => format!(
+                "pg_tblspc/{spcnode}/{tablespace_version_dir}/{}/{fname}",
+                self.dbnode
+            ),
+        }
+    }
+
+    /// Parses a path relative to the data directory, the inverse of
+    /// [`RelFilePath::to_path`].
+    pub fn parse(path: &str, tablespace_version_dir: &str) -> Result<Self, FilePathError> {
+        let components: Vec<&str> = path.split('/').collect();
+        let (spcnode, dbnode, fname) = match components[..] {
+            ["global", fname] => (GLOBALTABLESPACE_OID, 0, fname),
+            ["base", dbnode, fname] => (DEFAULTTABLESPACE_OID, parse_oid(dbnode)?, fname),
+            ["pg_tblspc", spcnode, version_dir, dbnode, fname]
+                if version_dir == tablespace_version_dir =>
+            {
+                (parse_oid(spcnode)?, parse_oid(dbnode)?, fname)
+            }
+            _ => return Err(FilePathError::InvalidFilePath),
+        };
+        let (backend, relnode, forknum, segno) = parse_temp_relfilename(fname)?;
+
+        // There are no temporary shared relations
+        if spcnode == GLOBALTABLESPACE_OID && backend.is_some() {
+            return Err(FilePathError::InvalidFileName);
+        }
+
+        Ok(RelFilePath {
+            spcnode,
+            dbnode,
+            relnode,
+            backend,
+            forknum,
+            segno,
+        })
+    }
+}
+
+fn parse_oid(s: &str) -> Result<u32, FilePathError> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(FilePathError::InvalidFilePath);
+    }
+    s.parse::<u32>().map_err(|_| FilePathError::InvalidFilePath)
 }
 
 #[cfg(test)]
@@ -150,4 +271,138 @@ mod tests {
         // currently.
         assert_eq!(parse_relfilename("1.123456"), Ok((1, 0, 123456)));
     }
+
+    #[test]
+    fn test_parse_temp_relfilenames() {
+        assert_eq!(parse_temp_relfilename("t3_1234"), Ok((Some(3), 1234, 0, 0)));
+        assert_eq!(
+            parse_temp_relfilename("t3_1234_fsm.12"),
+            Ok((Some(3), 1234, 1, 12))
+        );
+        assert_eq!(parse_temp_relfilename("1234_vm"), Ok((None, 1234, 2, 0)));
+
+        // parse_relfilename() only accepts permanent relations
+        assert_eq!(
+            parse_relfilename("t3_1234"),
+            Err(FilePathError::InvalidFileName)
+        );
+        assert_eq!(
+            parse_temp_relfilename("t_1234"),
+            Err(FilePathError::InvalidFileName)
+        );
+        assert_eq!(
+            parse_temp_relfilename("t3_"),
+            Err(FilePathError::InvalidFileName)
+        );
+    }
+
+    #[test]
+    fn test_relfilename_roundtrip() {
+        for fname in [
+            "1234",
+            "1234_fsm",
+            "1234_vm.3",
+            "1234_init",
+            "1234.12",
+            "t3_1234",
+            "t3_1234_fsm.12",
+        ] {
+            let (backend, relnode, forknum, segno) = parse_temp_relfilename(fname).unwrap();
+            assert_eq!(relfilename(relnode, backend, forknum, segno), fname);
+        }
+    }
+
+    #[test]
+    fn test_relpath_roundtrip() {
+        const VERSION_DIR: &str = "PG_15_202209061";
+
+        let path = RelFilePath {
+            spcnode: GLOBALTABLESPACE_OID,
+            dbnode: 0,
+            relnode: 1262,
+            backend: None,
+            forknum: MAIN_FORKNUM,
+            segno: 0,
+        };
+        assert_eq!(path.to_path(VERSION_DIR), "global/1262");
+
+        let path = RelFilePath {
+            spcnode: DEFAULTTABLESPACE_OID,
+            dbnode: 5,
+            relnode: 16384,
+            backend: None,
+            forknum: VISIBILITYMAP_FORKNUM,
+            segno: 2,
+        };
+        assert_eq!(path.to_path(VERSION_DIR), "base/5/16384_vm.2");
+
+        let path = RelFilePath {
+            spcnode: DEFAULTTABLESPACE_OID,
+            dbnode: 5,
+            relnode: 16390,
+            backend: Some(7),
+            forknum: MAIN_FORKNUM,
+            segno: 0,
+        };
+        assert_eq!(path.to_path(VERSION_DIR), "base/5/t7_16390");
+
+        let path = RelFilePath {
+            spcnode: 16400,
+            dbnode: 5,
+            relnode: 16401,
+            backend: None,
+            forknum: FSM_FORKNUM,
+            segno: 0,
+        };
+        assert_eq!(
+            path.to_path(VERSION_DIR),
+            "pg_tblspc/16400/PG_15_202209061/5/16401_fsm"
+        );
+
+        for path in [
+            "global/1262",
+            "global/1262_fsm.1",
+            "base/5/16384_vm.2",
+            "base/5/t7_16390",
+            "pg_tblspc/16400/PG_15_202209061/5/16401_fsm",
+            "pg_tblspc/16400/PG_15_202209061/5/t7_16402.3",
+        ] {
+            let parsed = RelFilePath::parse(path, VERSION_DIR).unwrap();
+            assert_eq!(parsed.to_path(VERSION_DIR), path);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_relpaths() {
+        const VERSION_DIR: &str = "PG_15_202209061";
+
+        for path in [
+            "",
+            "1234",
+            "global",
+            "global/5/1234",
+            "base/1234",
+            "base/x/1234",
+            "base/+5/1234",
+            "base/5/6/1234",
+            "pg_tblspc/16400/5/1234",
+            "pg_tblspc/16400/PG_14_202107181/5/1234",
+            "/base/5/1234",
+        ] {
+            assert_eq!(
+                RelFilePath::parse(path, VERSION_DIR),
+                Err(FilePathError::InvalidFilePath),
+                "{path}"
+            );
+        }
+        assert_eq!(
+            RelFilePath::parse("base/5/1234_bad", VERSION_DIR),
+            Err(FilePathError::InvalidForkName)
+        );
+        // There are no temporary shared relations
+        assert_eq!(
+            RelFilePath::parse("global/t7_1262", VERSION_DIR),
+            Err(FilePathError::InvalidFileName)
+        );
+    }
 }
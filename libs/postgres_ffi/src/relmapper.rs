@@ -0,0 +1,207 @@
+//!
+//! The relation mapping files pg_filenode.map, from relmapper.c.
+//!
+//! The catalogs that are needed before pg_class can be read, and the shared
+//! catalogs, don't store their relfilenode in pg_class, but in a map file:
+//! global/pg_filenode.map for the shared catalogs, and base/<db>/pg_filenode.map
+//! for the nailed catalogs of each database. Rewriting such a catalog, e.g.
+//! with VACUUM FULL, assigns it a new relfilenode and rewrites the whole map
+//! file, which is WAL-logged in an XLOG_RELMAP_UPDATE record.
+//!
+//! A map file is a RelMapFile struct of exactly 512 bytes: a magic number,
+//! the number of mappings, an array of up to 62 (OID, relfilenode) mappings,
+//! a CRC of all the above, and padding.
+//!
+use anyhow::{ensure, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::Oid;
+
+pub const RELMAPPER_FILENAME: &str = "pg_filenode.map";
+pub const RELMAPPER_FILEMAGIC: u32 = 0x592717;
+pub const MAX_MAPPINGS: usize = 62;
+/// sizeof(RelMapFile)
+pub const RELMAPPER_FILESIZE: usize = 512;
+
+/// sizeof(RelMapping)
+const SIZEOF_RELMAPPING: usize = 8;
+/// offsetof(RelMapFile, crc)
+const OFFSETOF_CRC: usize = 8 + MAX_MAPPINGS * SIZEOF_RELMAPPING;
+
+/// Mapping of a catalog to its relfilenode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelMapping {
+    pub mapoid: Oid,
+    pub mapfilenode: Oid,
+}
+
+/// The contents of a map file, its mappings in no particular order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelMapFile {
+    pub mappings: Vec<RelMapping>,
+}
+
+impl RelMapFile {
+    /// Decodes and checks a map file, like read_relmap_file() does.
+    pub fn decode(buf: &[u8]) -> Result<RelMapFile> {
+        ensure!(
+            buf.len() == RELMAPPER_FILESIZE,
+            "relation mapping file is {} bytes, expected {RELMAPPER_FILESIZE}",
+            buf.len()
+        );
+        let expected_crc = crc32c::crc32c(&buf[..OFFSETOF_CRC]);
+
+        let mut buf = buf;
+        let magic = buf.get_u32_le();
+        ensure!(
+            magic == RELMAPPER_FILEMAGIC,
+            "relation mapping file contains invalid magic 0x{magic:x}"
+        );
+        let num_mappings = buf.get_i32_le();
+        ensure!(
+            (0..=MAX_MAPPINGS as i32).contains(&num_mappings),
+            "relation mapping file contains invalid number of mappings {num_mappings}"
+        );
+        let mappings = (0..num_mappings)
+            .map(|_| RelMapping {
+                mapoid: buf.get_u32_le(),
+                mapfilenode: buf.get_u32_le(),
+            })
+            .collect();
+        buf.advance((MAX_MAPPINGS - num_mappings as usize) * SIZEOF_RELMAPPING);
+        let crc = buf.get_u32_le();
+        ensure!(
+            crc == expected_crc,
+            "relation mapping file has checksum 0x{crc:08x}, expected 0x{expected_crc:08x}"
+        );
+        Ok(RelMapFile { mappings })
+    }
+
+    /// Encodes the map file, with its CRC, like write_relmap_file() does.
+    pub fn encode(&self) -> Result<Bytes> {
+        ensure!(
+            self.mappings.len() <= MAX_MAPPINGS,
+            "ran out of space in relation map, {} mappings",
+            self.mappings.len()
+        );
+        let mut buf = BytesMut::with_capacity(RELMAPPER_FILESIZE);
+        buf.put_u32_le(RELMAPPER_FILEMAGIC);
+        buf.put_i32_le(self.mappings.len() as i32);
+        for mapping in &self.mappings {
+            buf.put_u32_le(mapping.mapoid);
+            buf.put_u32_le(mapping.mapfilenode);
+        }
+        buf.resize(OFFSETOF_CRC, 0);
+        let crc = crc32c::crc32c(&buf);
+        buf.put_u32_le(crc);
+        buf.resize(RELMAPPER_FILESIZE, 0);
+        Ok(buf.freeze())
+    }
+
+    /// Relfilenode of the catalog `relid`, if it's mapped. Like
+    /// RelationMapOidToFilenode().
+    pub fn filenode(&self, relid: Oid) -> Option<Oid> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.mapoid == relid)
+            .map(|mapping| mapping.mapfilenode)
+    }
+
+    /// The catalog mapped to relfilenode `filenode`, if any. Like
+    /// RelationMapFilenodeToOid().
+    pub fn relid(&self, filenode: Oid) -> Option<Oid> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.mapfilenode == filenode)
+            .map(|mapping| mapping.mapoid)
+    }
+
+    /// Maps `relid` to `filenode`, replacing its mapping if it has one, or
+    /// adding one if `add_okay`. Port of apply_map_update().
+    pub fn apply_update(&mut self, relid: Oid, filenode: Oid, add_okay: bool) -> Result<()> {
+        if let Some(mapping) = self.mappings.iter_mut().find(|m| m.mapoid == relid) {
+            mapping.mapfilenode = filenode;
+            return Ok(());
+        }
+        ensure!(
+            add_okay,
+            "attempt to apply a mapping to unmapped relation {relid}"
+        );
+        ensure!(
+            self.mappings.len() < MAX_MAPPINGS,
+            "ran out of space in relation map"
+        );
+        self.mappings.push(RelMapping {
+            mapoid: relid,
+            mapfilenode: filenode,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relmap_file_roundtrip() {
+        // pg_class, pg_attribute and pg_proc mapped to themselves, as after
+        // initdb
+        let mut map = RelMapFile {
+            mappings: [1259, 1249, 1255]
+                .iter()
+                .map(|&oid| RelMapping {
+                    mapoid: oid,
+                    mapfilenode: oid,
+                })
+                .collect(),
+        };
+        let buf = map.encode().unwrap();
+        assert_eq!(buf.len(), RELMAPPER_FILESIZE);
+        assert_eq!(&buf[..8], &[0x17, 0x27, 0x59, 0x00, 3, 0, 0, 0]);
+        assert_eq!(RelMapFile::decode(&buf).unwrap(), map);
+        assert_eq!(map.filenode(1249), Some(1249));
+        assert_eq!(map.filenode(16384), None);
+
+        // VACUUM FULL pg_attribute
+        map.apply_update(1249, 16390, false).unwrap();
+        assert_eq!(map.filenode(1249), Some(16390));
+        assert_eq!(map.relid(16390), Some(1249));
+        assert_eq!(map.relid(1249), None);
+        assert!(map.apply_update(2619, 16391, false).is_err());
+        map.apply_update(2619, 16391, true).unwrap();
+        assert_eq!(map.mappings.len(), 4);
+        assert_eq!(RelMapFile::decode(&map.encode().unwrap()).unwrap(), map);
+    }
+
+    #[test]
+    fn test_relmap_file_invalid() {
+        let map = RelMapFile {
+            mappings: vec![RelMapping {
+                mapoid: 1262,
+                mapfilenode: 1262,
+            }],
+        };
+        let buf = map.encode().unwrap();
+
+        // Wrong size, magic, number of mappings or CRC
+        assert!(RelMapFile::decode(&buf[..508]).is_err());
+        let mut corrupt = buf.to_vec();
+        corrupt[0] = 0x18;
+        assert!(RelMapFile::decode(&corrupt).is_err());
+        let mut corrupt = buf.to_vec();
+        corrupt[4] = 63;
+        assert!(RelMapFile::decode(&corrupt).is_err());
+        let mut corrupt = buf.to_vec();
+        corrupt[12] ^= 1;
+        assert!(RelMapFile::decode(&corrupt).is_err());
+
+        // The map is full at 62 mappings
+        let mut map = RelMapFile::default();
+        for oid in 0..MAX_MAPPINGS as Oid {
+            map.apply_update(oid, oid, true).unwrap();
+        }
+        assert!(map.apply_update(100, 100, true).is_err());
+        assert_eq!(RelMapFile::decode(&map.encode().unwrap()).unwrap(), map);
+    }
+}
@@ -0,0 +1,448 @@
+//!
+//! Page math of the SLRUs, the simple LRU buffered logs of PostgreSQL, from
+//! slru.c and the modules built on it.
+//!
+//! An SLRU stores fixed-size entries, indexed by a 32-bit counter that wraps
+//! around, like a TransactionId. Each [`Slru`] implementation provides the
+//! layout of one of them and its PagePrecedes comparator, and gets the page
+//! math and the choice of the segments to truncate from the trait.
+//!
+//! The SLRUs of the multi-region cluster state, the CSN log and the multixact
+//! ones, interleave the pages of the regions: logical page `n` of region `r`
+//! is stored in page `n * max_regions + r`. The number of regions is a setting
+//! of the deployment, see `pg_constants::MAX_REGIONS` for its default, so the
+//! functions that depend on the interleaving take it as an argument. The other
+//! SLRUs ignore it, and their pages are their logical pages.
+//!
+//! A segment of an interleaved SLRU holds the pages of several regions, but a
+//! region only ever writes its own pages, and leaves the other ones zeroed.
+//! Truncating such an SLRU up to a page of a region thus only considers the
+//! pages of that region.
+//!
+use crate::pg_constants;
+use crate::transaction_id_precedes;
+
+pub trait Slru {
+    /// Name of the directory of the SLRU in the data directory.
+    const DIR: &'static str;
+    /// Number of entries stored in a page.
+    const ENTRIES_PER_PAGE: u32;
+    const PAGES_PER_SEGMENT: u32 = pg_constants::SLRU_PAGES_PER_SEGMENT;
+    /// Whether the pages of the regions are interleaved.
+    const MULTI_REGION: bool = false;
+
+    /// The PagePrecedes callback of the SLRU, on logical page numbers, i.e.
+    /// without the interleaving of the regions.
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool;
+
+    /// Logical page number of the page storing `entry`.
+    fn logical_page(entry: u32) -> u32 {
+        entry / Self::ENTRIES_PER_PAGE
+    }
+
+    /// Page number of the page storing `entry` for `region`, out of
+    /// `max_regions`. Regions are ignored by SLRUs that don't interleave them.
+    /// `region` must be less than `max_regions`, which callers check.
+    fn page(entry: u32, region: u32, max_regions: u32) -> u32 {
+        let page = Self::logical_page(entry);
+        if Self::MULTI_REGION {
+            debug_assert!(region < max_regions);
+            page * max_regions + region
+        } else {
+            page
+        }
+    }
+
+    /// Region of the page `pageno`, out of `max_regions`.
+    fn page_region(pageno: u32, max_regions: u32) -> u32 {
+        if Self::MULTI_REGION {
+            pageno % max_regions
+        } else {
+            0
+        }
+    }
+
+    /// Index of `entry` in its page.
+    fn entry_in_page(entry: u32) -> u32 {
+        entry % Self::ENTRIES_PER_PAGE
+    }
+
+    /// Segment number and block number in the segment of page `pageno`.
+    fn segment_block(pageno: u32) -> (u32, u32) {
+        (
+            pageno / Self::PAGES_PER_SEGMENT,
+            pageno % Self::PAGES_PER_SEGMENT,
+        )
+    }
+
+    /// Whether page `page1` precedes `page2`. Pages of different regions
+    /// never precede each other.
+    fn page_precedes(page1: u32, page2: u32, max_regions: u32) -> bool {
+        if Self::MULTI_REGION {
+            page1 % max_regions == page2 % max_regions
+                && Self::logical_page_precedes(page1 / max_regions, page2 / max_regions)
+        } else {
+            Self::logical_page_precedes(page1, page2)
+        }
+    }
+
+    /// First and last pages of `region` in segment `segno`, out of
+    /// `max_regions`, or None if the segment holds no page of the region.
+    fn region_pages_in_segment(segno: u32, region: u32, max_regions: u32) -> Option<(u32, u32)> {
+        let seg_first_page = segno * Self::PAGES_PER_SEGMENT;
+        let seg_last_page = seg_first_page + Self::PAGES_PER_SEGMENT - 1;
+        if !Self::MULTI_REGION {
+            return Some((seg_first_page, seg_last_page));
+        }
+
+        let first_page =
+            seg_first_page + (region + max_regions - seg_first_page % max_regions) % max_regions;
+        if first_page > seg_last_page {
+            return None;
+        }
+        let last_page =
+            seg_last_page - (seg_last_page % max_regions + max_regions - region) % max_regions;
+        Some((first_page, last_page))
+    }
+
+    /// Whether segment `segno` holds only pages preceding `cutoff_page`, out
+    /// of the pages of the region of `cutoff_page`. Port of
+    /// SlruMayDeleteSegment().
+    fn may_delete_segment(segno: u32, cutoff_page: u32, max_regions: u32) -> bool {
+        let region = Self::page_region(cutoff_page, max_regions);
+        match Self::region_pages_in_segment(segno, region, max_regions) {
+            Some((first_page, last_page)) => {
+                Self::page_precedes(first_page, cutoff_page, max_regions)
+                    && Self::page_precedes(last_page, cutoff_page, max_regions)
+            }
+            // Not a segment of the region
+            None => false,
+        }
+    }
+
+    /// The segments among `segments` that a truncation of the SLRU up to
+    /// `cutoff_page` deletes, like SimpleLruTruncate() does. `latest_page` is
+    /// the page of the latest entry. If it precedes `cutoff_page`, there has
+    /// been an apparent wraparound and nothing can be deleted, so this returns
+    /// None.
+    fn segments_to_truncate(
+        segments: impl IntoIterator<Item = u32>,
+        cutoff_page: u32,
+        latest_page: u32,
+        max_regions: u32,
+    ) -> Option<Vec<u32>> {
+        // The current endpoint page must not be eligible for removal.
+        if Self::page_precedes(latest_page, cutoff_page, max_regions) {
+            return None;
+        }
+        Some(
+            segments
+                .into_iter()
+                .filter(|&segno| Self::may_delete_segment(segno, cutoff_page, max_regions))
+                .collect(),
+        )
+    }
+}
+
+/// Commit status of the transactions, pg_xact.
+pub struct Clog;
+
+impl Slru for Clog {
+    const DIR: &'static str = "pg_xact";
+    const ENTRIES_PER_PAGE: u32 = pg_constants::CLOG_XACTS_PER_PAGE;
+
+    // See CLOGPagePrecedes in clog.c
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool {
+        let xid1 = page1
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+        let xid2 = page2
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+
+        transaction_id_precedes(xid1, xid2)
+            && transaction_id_precedes(xid1, xid2.wrapping_add(Self::ENTRIES_PER_PAGE - 1))
+    }
+}
+
+/// Commit timestamps of the transactions, pg_commit_ts. Only maintained with
+/// track_commit_timestamp.
+pub struct CommitTs;
+
+impl Slru for CommitTs {
+    const DIR: &'static str = "pg_commit_ts";
+    const ENTRIES_PER_PAGE: u32 = pg_constants::COMMIT_TS_XACTS_PER_PAGE;
+
+    // See CommitTsPagePrecedes in commit_ts.c
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool {
+        let xid1 = page1
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+        let xid2 = page2
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+
+        transaction_id_precedes(xid1, xid2)
+            && transaction_id_precedes(xid1, xid2.wrapping_add(Self::ENTRIES_PER_PAGE - 1))
+    }
+}
+
+/// Offsets of the members of each multixact, pg_multixact/offsets.
+pub struct MultiXactOffsets;
+
+impl Slru for MultiXactOffsets {
+    const DIR: &'static str = "pg_multixact/offsets";
+    const ENTRIES_PER_PAGE: u32 = pg_constants::MULTIXACT_OFFSETS_PER_PAGE as u32;
+    const MULTI_REGION: bool = true;
+
+    // See MultiXactOffsetPagePrecedes in multixact.c
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool {
+        let multi1 = page1
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_MULTIXACT_ID + 1);
+        let multi2 = page2
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_MULTIXACT_ID + 1);
+
+        wrapping_precedes(multi1, multi2)
+            && wrapping_precedes(multi1, multi2.wrapping_add(Self::ENTRIES_PER_PAGE - 1))
+    }
+}
+
+/// Members of the multixacts, pg_multixact/members, indexed by offset.
+pub struct MultiXactMembers;
+
+impl Slru for MultiXactMembers {
+    const DIR: &'static str = "pg_multixact/members";
+    const ENTRIES_PER_PAGE: u32 = pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32;
+    const MULTI_REGION: bool = true;
+
+    // See MultiXactMemberPagePrecedes in multixact.c
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool {
+        let offset1 = page1.wrapping_mul(Self::ENTRIES_PER_PAGE);
+        let offset2 = page2.wrapping_mul(Self::ENTRIES_PER_PAGE);
+
+        wrapping_precedes(offset1, offset2)
+            && wrapping_precedes(offset1, offset2.wrapping_add(Self::ENTRIES_PER_PAGE - 1))
+    }
+}
+
+/// Commit sequence numbers of the transactions, pg_csn.
+pub struct CsnLog;
+
+impl Slru for CsnLog {
+    const DIR: &'static str = "pg_csn";
+    const ENTRIES_PER_PAGE: u32 = pg_constants::CSN_LOG_XACTS_PER_PAGE;
+    const MULTI_REGION: bool = true;
+
+    // See CSNLogPagePrecedes in csn_log.c
+    fn logical_page_precedes(page1: u32, page2: u32) -> bool {
+        let xid1 = page1
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+        let xid2 = page2
+            .wrapping_mul(Self::ENTRIES_PER_PAGE)
+            .wrapping_add(pg_constants::FIRST_NORMAL_TRANSACTION_ID + 1);
+
+        transaction_id_precedes(xid1, xid2)
+    }
+}
+
+/// Modulo-2^32 comparison, like MultiXactIdPrecedes and MultiXactOffsetPrecedes.
+fn wrapping_precedes(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_REGIONS: u32 = pg_constants::MAX_REGIONS;
+
+    #[test]
+    fn test_page_math() {
+        assert_eq!(Clog::page(32767, 5, MAX_REGIONS), 0);
+        assert_eq!(Clog::page(32768, 5, MAX_REGIONS), 1);
+        assert_eq!(Clog::segment_block(33), (1, 1));
+        assert_eq!(Clog::entry_in_page(32769), 1);
+
+        // 819 timestamps fit in a page, regions are ignored
+        assert_eq!(CommitTs::page(818, 5, MAX_REGIONS), 0);
+        assert_eq!(CommitTs::page(819, 5, MAX_REGIONS), 1);
+        assert_eq!(CommitTs::entry_in_page(1000), 181);
+
+        // The pages of the regions are interleaved
+        assert_eq!(CsnLog::page(0, 3, MAX_REGIONS), 3);
+        assert_eq!(CsnLog::page(1024, 3, MAX_REGIONS), MAX_REGIONS + 3);
+        assert_eq!(
+            CsnLog::page_region(CsnLog::page(5000, 7, MAX_REGIONS), MAX_REGIONS),
+            7
+        );
+        assert_eq!(
+            CsnLog::segment_block(CsnLog::page(1024, 40, MAX_REGIONS)),
+            (3, 8) // page 104
+        );
+
+        // These match mx_offset_to_member_page
+        assert_eq!(MultiXactMembers::page(123456789, 0, MAX_REGIONS), 4829568);
+        assert_eq!(MultiXactMembers::page(u32::MAX, 0, MAX_REGIONS), 168018240);
+    }
+
+    #[test]
+    fn test_page_math_max_regions() {
+        // With 4 regions, logical page 1 of region 3 is page 7
+        assert_eq!(CsnLog::page(1024, 3, 4), 7);
+        assert_eq!(CsnLog::page_region(7, 4), 3);
+        assert_eq!(CsnLog::segment_block(CsnLog::page(8 * 1024, 1, 4)), (1, 1));
+        assert_eq!(MultiXactOffsets::page(2048, 2, 4), 6);
+        assert!(CsnLog::page_precedes(3, 7, 4));
+        assert!(!CsnLog::page_precedes(3, 6, 4));
+
+        // A single region is like no interleaving at all
+        assert_eq!(CsnLog::page(5000, 0, 1), CsnLog::logical_page(5000));
+    }
+
+    #[test]
+    fn test_page_precedes() {
+        // Pages compare by their first entries, modulo 2^32
+        assert!(Clog::page_precedes(0, 1, MAX_REGIONS));
+        assert!(!Clog::page_precedes(1, 0, MAX_REGIONS));
+        assert!(!Clog::page_precedes(1, 1, MAX_REGIONS));
+        assert!(Clog::page_precedes(131071, 0, MAX_REGIONS));
+        assert!(!Clog::page_precedes(0, 131071, MAX_REGIONS));
+        assert!(CommitTs::page_precedes(0, 1, MAX_REGIONS));
+        assert!(!CommitTs::page_precedes(1, 1, MAX_REGIONS));
+
+        // Pages of the multixact SLRUs are interleaved too
+        let (off1, off2) = (
+            MultiXactOffsets::page(0, 1, MAX_REGIONS),
+            MultiXactOffsets::page(2048, 1, MAX_REGIONS),
+        );
+        assert!(MultiXactOffsets::page_precedes(off1, off2, MAX_REGIONS));
+        assert!(!MultiXactOffsets::page_precedes(off2, off1, MAX_REGIONS));
+        let (mem1, mem2) = (
+            MultiXactMembers::page(0, 1, MAX_REGIONS),
+            MultiXactMembers::page(1636, 1, MAX_REGIONS),
+        );
+        assert!(MultiXactMembers::page_precedes(mem1, mem2, MAX_REGIONS));
+        assert!(!MultiXactMembers::page_precedes(mem2, mem1, MAX_REGIONS));
+
+        // Pages of the same region compare by their logical page number, pages
+        // of different regions never precede each other
+        assert!(CsnLog::page_precedes(2, MAX_REGIONS + 2, MAX_REGIONS));
+        assert!(!CsnLog::page_precedes(MAX_REGIONS + 2, 2, MAX_REGIONS));
+        assert!(!CsnLog::page_precedes(1, MAX_REGIONS + 2, MAX_REGIONS));
+    }
+
+    #[test]
+    fn test_page_precedes_wraparound() {
+        // The last page of pg_xact ends with the permanent XIDs of the next
+        // wraparound, and the pages up to 2^31 XIDs before it precede it
+        assert_eq!(Clog::logical_page(u32::MAX), 131071);
+        assert!(Clog::page_precedes(131070, 131071, MAX_REGIONS));
+        assert!(Clog::page_precedes(65537, 131071, MAX_REGIONS));
+        assert!(!Clog::page_precedes(65535, 131071, MAX_REGIONS));
+        assert!(!Clog::page_precedes(131071, 131070, MAX_REGIONS));
+
+        // Of the pages 2^31 XIDs apart, neither precedes the other
+        assert!(!Clog::page_precedes(0, 65536, MAX_REGIONS));
+        assert!(!Clog::page_precedes(65536, 0, MAX_REGIONS));
+        assert!(Clog::page_precedes(65537, 0, MAX_REGIONS));
+        assert!(CsnLog::page_precedes(
+            0,
+            (1 << 21) * MAX_REGIONS,
+            MAX_REGIONS
+        ));
+
+        // Truncating up to the last page, and then across the wraparound
+        assert_eq!(
+            Clog::segments_to_truncate([3000, 4094, 4095], 131071, 131071, MAX_REGIONS),
+            Some(vec![3000, 4094])
+        );
+        assert_eq!(
+            Clog::segments_to_truncate([4094, 4095, 0], 10, 20, MAX_REGIONS),
+            Some(vec![4094, 4095])
+        );
+        assert_eq!(
+            Clog::segments_to_truncate([4095, 0], 10, 131071, MAX_REGIONS),
+            None
+        );
+    }
+
+    #[test]
+    fn test_segments_to_truncate() {
+        // Segment 0 holds pages 0..32, segment 1 pages 32..64
+        assert_eq!(
+            Clog::segments_to_truncate([0, 1, 2], 64, 70, MAX_REGIONS),
+            Some(vec![0, 1])
+        );
+        // Segment 1 still holds the cutoff page
+        assert_eq!(
+            Clog::segments_to_truncate([0, 1, 2], 40, 70, MAX_REGIONS),
+            Some(vec![0])
+        );
+        // Apparent wraparound
+        assert_eq!(
+            Clog::segments_to_truncate([0, 1, 2], 64, 10, MAX_REGIONS),
+            None
+        );
+    }
+
+    #[test]
+    fn test_region_pages_in_segment() {
+        // Segment 0 holds logical page 0 of regions 0 to 31
+        assert_eq!(
+            CsnLog::region_pages_in_segment(0, 3, MAX_REGIONS),
+            Some((3, 3))
+        );
+        assert_eq!(CsnLog::region_pages_in_segment(0, 40, MAX_REGIONS), None);
+        assert_eq!(
+            CsnLog::region_pages_in_segment(1, 40, MAX_REGIONS),
+            Some((40, 40))
+        );
+        // With 4 regions, it holds logical pages 0 to 7 of each
+        assert_eq!(CsnLog::region_pages_in_segment(0, 1, 4), Some((1, 29)));
+        assert_eq!(CsnLog::region_pages_in_segment(1, 3, 4), Some((35, 63)));
+        // Other SLRUs don't interleave regions
+        assert_eq!(Clog::region_pages_in_segment(1, 3, 4), Some((32, 63)));
+    }
+
+    #[test]
+    fn test_segments_to_truncate_region() {
+        // Truncation of region 3 up to its logical page 2, i.e. page 131. The
+        // segments 0 and 2 hold logical pages 0 and 1 of the regions 0 to 31,
+        // the odd ones don't hold pages of region 3.
+        let cutoff_page = CsnLog::page(2 * 1024, 3, MAX_REGIONS);
+        let latest_page = CsnLog::page(10 * 1024, 3, MAX_REGIONS);
+        assert_eq!(
+            CsnLog::segments_to_truncate(0..6, cutoff_page, latest_page, MAX_REGIONS),
+            Some(vec![0, 2])
+        );
+        // Region 40 keeps its page 1, in segment 3
+        let cutoff_page = CsnLog::page(1024, 40, MAX_REGIONS);
+        let latest_page = CsnLog::page(10 * 1024, 40, MAX_REGIONS);
+        assert_eq!(
+            CsnLog::segments_to_truncate(0..6, cutoff_page, latest_page, MAX_REGIONS),
+            Some(vec![1])
+        );
+
+        // With 4 regions, segment 0 holds logical pages 0 to 7 of each region
+        let cutoff_page = CsnLog::page(8 * 1024, 1, 4);
+        let latest_page = CsnLog::page(20 * 1024, 1, 4);
+        assert_eq!(
+            CsnLog::segments_to_truncate(0..3, cutoff_page, latest_page, 4),
+            Some(vec![0])
+        );
+        let cutoff_page = CsnLog::page(7 * 1024, 1, 4);
+        assert_eq!(
+            CsnLog::segments_to_truncate(0..3, cutoff_page, latest_page, 4),
+            Some(vec![])
+        );
+
+        // Apparent wraparound of the region
+        assert_eq!(
+            CsnLog::segments_to_truncate(0..6, latest_page, cutoff_page, 4),
+            None
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//!
+//! Transaction ID arithmetic, from transam.h and transam.c.
+//!
+//! A TransactionId is 32 bits and wraps around, so normal XIDs are compared
+//! modulo 2^32: each XID has 2^31 XIDs before and after it. The permanent XIDs
+//! below FirstNormalTransactionId are older than all normal XIDs. A
+//! FullTransactionId carries the number of wraparounds, the epoch, in its high
+//! half, and compares as a plain 64-bit integer.
+//!
+use crate::pg_constants::FIRST_NORMAL_TRANSACTION_ID;
+use crate::TransactionId;
+use std::fmt;
+
+//  See TransactionIdIsNormal in transam.h
+pub const fn transaction_id_is_normal(id: TransactionId) -> bool {
+    id >= FIRST_NORMAL_TRANSACTION_ID
+}
+
+// See TransactionIdPrecedes in transam.c
+pub const fn transaction_id_precedes(id1: TransactionId, id2: TransactionId) -> bool {
+    /*
+     * If either ID is a permanent XID then we can just do unsigned
+     * comparison.  If both are normal, do a modulo-2^32 comparison.
+     */
+
+    if !(transaction_id_is_normal(id1)) || !transaction_id_is_normal(id2) {
+        return id1 < id2;
+    }
+
+    let diff = id1.wrapping_sub(id2) as i32;
+    diff < 0
+}
+
+// See TransactionIdPrecedesOrEquals in transam.c
+pub const fn transaction_id_precedes_or_equals(id1: TransactionId, id2: TransactionId) -> bool {
+    id1 == id2 || transaction_id_precedes(id1, id2)
+}
+
+// See TransactionIdFollows in transam.c
+pub const fn transaction_id_follows(id1: TransactionId, id2: TransactionId) -> bool {
+    transaction_id_precedes(id2, id1)
+}
+
+/// FullTransactionId, printed as epoch:xid like PostgreSQL does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FullTransactionId(pub u64);
+
+impl FullTransactionId {
+    /// See FullTransactionIdFromEpochAndXid in transam.h
+    pub const fn from_epoch_and_xid(epoch: u32, xid: TransactionId) -> Self {
+        FullTransactionId(((epoch as u64) << 32) | xid as u64)
+    }
+
+    pub const fn epoch(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    pub const fn xid(self) -> TransactionId {
+        self.0 as u32
+    }
+
+    /// Returns the FullTransactionId of `xid`, which must be less than 2^31
+    /// XIDs away from this one, in either direction. It's in the next epoch
+    /// if `xid` follows this one across a wraparound, and in the previous one
+    /// if it precedes it across one. See FullXidRelativeTo in procarray.c.
+    pub const fn relative(self, xid: TransactionId) -> Self {
+        let diff = xid.wrapping_sub(self.xid()) as i32;
+        FullTransactionId(self.0.wrapping_add_signed(diff as i64))
+    }
+
+    /// Returns the next FullTransactionId, skipping the permanent XIDs at the
+    /// start of each epoch. See FullTransactionIdAdvance in transam.h
+    pub const fn advance(self) -> Self {
+        let next = FullTransactionId(self.0 + 1);
+        if transaction_id_is_normal(next.xid()) {
+            next
+        } else {
+            FullTransactionId::from_epoch_and_xid(next.epoch(), FIRST_NORMAL_TRANSACTION_ID)
+        }
+    }
+}
+
+impl fmt::Display for FullTransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.epoch(), self.xid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_id_precedes() {
+        // Permanent XIDs precede all normal XIDs, and each other in order
+        assert!(transaction_id_precedes(0, 2));
+        assert!(transaction_id_precedes(2, 3));
+        assert!(transaction_id_precedes(2, u32::MAX));
+        assert!(!transaction_id_precedes(3, 2));
+
+        // 3 is the first normal XID, and compares modulo 2^32
+        assert!(transaction_id_is_normal(3));
+        assert!(!transaction_id_is_normal(2));
+        assert!(transaction_id_precedes(u32::MAX, 3));
+        assert!(transaction_id_follows(3, u32::MAX));
+        assert!(transaction_id_precedes(u32::MAX - 5, 10));
+
+        // Around 2^31 XIDs apart. At exactly 2^31, each precedes the other.
+        let xid = 100;
+        assert!(transaction_id_precedes(xid, xid + (1 << 31) - 1));
+        assert!(!transaction_id_precedes(xid + (1 << 31) - 1, xid));
+        assert!(transaction_id_precedes(xid, xid + (1 << 31)));
+        assert!(transaction_id_precedes(xid + (1 << 31), xid));
+        assert!(transaction_id_precedes(xid + (1 << 31) + 1, xid));
+        assert!(!transaction_id_precedes(xid, xid + (1 << 31) + 1));
+        assert!(transaction_id_precedes(3, (1 << 31) + 2));
+        assert!(transaction_id_precedes((1 << 31) + 4, 3));
+
+        assert!(transaction_id_precedes_or_equals(xid, xid));
+        assert!(!transaction_id_precedes(xid, xid));
+        assert!(!transaction_id_follows(xid, xid));
+    }
+
+    #[test]
+    fn test_full_transaction_id() {
+        let fxid = FullTransactionId::from_epoch_and_xid(1, u32::MAX - 10);
+        assert_eq!(fxid.epoch(), 1);
+        assert_eq!(fxid.xid(), u32::MAX - 10);
+        assert_eq!(fxid.to_string(), format!("1:{}", u32::MAX - 10));
+
+        // Relative XIDs across the wraparound, in both directions
+        assert_eq!(
+            fxid.relative(5),
+            FullTransactionId::from_epoch_and_xid(2, 5)
+        );
+        assert_eq!(fxid.relative(u32::MAX - 20).epoch(), 1);
+        assert_eq!(fxid.relative(fxid.xid()), fxid);
+        let fxid = FullTransactionId::from_epoch_and_xid(2, 5);
+        assert_eq!(
+            fxid.relative(u32::MAX),
+            FullTransactionId::from_epoch_and_xid(1, u32::MAX)
+        );
+        assert_eq!(fxid.relative(5 + (1 << 31) - 1).epoch(), 2);
+        assert_eq!(fxid.relative(5 + (1 << 31)).epoch(), 1);
+
+        // Full XIDs compare without wraparound, even when the XIDs are more
+        // than 2^31 apart
+        let far = FullTransactionId::from_epoch_and_xid(2, 5 + (1 << 31));
+        assert!(FullTransactionId::from_epoch_and_xid(1, u32::MAX) < far);
+        assert!(!transaction_id_precedes(u32::MAX, far.xid()));
+
+        // Advancing skips the permanent XIDs
+        let last = FullTransactionId::from_epoch_and_xid(1, u32::MAX);
+        assert_eq!(last.advance(), FullTransactionId::from_epoch_and_xid(2, 3));
+        assert_eq!(fxid.advance(), FullTransactionId::from_epoch_and_xid(2, 6));
+    }
+}
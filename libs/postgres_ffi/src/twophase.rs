@@ -0,0 +1,349 @@
+//!
+//! Two-phase state data of prepared transactions, from twophase.c.
+//!
+//! PREPARE TRANSACTION writes the state data of the transaction as the main
+//! data of its XLOG_XACT_PREPARE record. At checkpoint, it is copied to the
+//! state file pg_twophase/<xid>, followed by its CRC. The state data is a
+//! TwoPhaseFileHeader, followed by the GID, the subtransaction XIDs, the
+//! relations to drop at commit and abort, the stats to drop at commit and
+//! abort (v15), the invalidation messages and the records of the resource
+//! managers that have state to restore, each part padded to MAXALIGN.
+//!
+use anyhow::{bail, ensure, Result};
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::walrecord::{ensure_size, RelFileNode};
+use crate::{Oid, TimestampTz, TransactionId, XLogRecPtr};
+
+pub const TWOPHASE_MAGIC: u32 = 0x57F94534;
+
+/* Resource managers of the records, TwoPhaseRmgrId in twophase_rmgr.h */
+pub const TWOPHASE_RM_END_ID: u8 = 0;
+pub const TWOPHASE_RM_LOCK_ID: u8 = 1;
+pub const TWOPHASE_RM_PGSTAT_ID: u8 = 2;
+pub const TWOPHASE_RM_MULTIXACT_ID: u8 = 3;
+pub const TWOPHASE_RM_PREDICATELOCK_ID: u8 = 4;
+
+const SIZEOF_XL_XACT_STATS_ITEM: usize = 12;
+const SIZEOF_SHARED_INVALIDATION_MESSAGE: usize = 16;
+/// sizeof(TwoPhaseRecordOnDisk)
+const SIZEOF_TWOPHASE_RECORD: usize = 8;
+
+fn maxalign(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+/// Reads a count of the header as usize, failing on negative ones.
+fn count(value: i32, name: &str) -> Result<usize> {
+    ensure!(value >= 0, "negative {name} {value} in two-phase state");
+    Ok(value as usize)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoPhaseFileHeader {
+    pub magic: u32,
+    /// Length of the state data, including the CRC of the state file.
+    pub total_len: u32,
+    pub xid: TransactionId,
+    pub database: Oid,
+    pub prepared_at: TimestampTz,
+    pub owner: Oid,
+    pub nsubxacts: i32,
+    pub ncommitrels: i32,
+    pub nabortrels: i32,
+    /// Only in v15, zero for v14
+    pub ncommitstats: i32,
+    /// Only in v15, zero for v14
+    pub nabortstats: i32,
+    pub ninvalmsgs: i32,
+    pub initfileinval: bool,
+    /// Length of the GID, including its terminating zero
+    pub gidlen: u16,
+    pub origin_lsn: XLogRecPtr,
+    pub origin_timestamp: TimestampTz,
+}
+
+impl TwoPhaseFileHeader {
+    /// sizeof(TwoPhaseFileHeader) of the given version.
+    pub fn size(pg_version: u32) -> Result<usize> {
+        match pg_version {
+            14 => Ok(64),
+            15 => Ok(72),
+            _ => bail!("Unknown version {}", pg_version),
+        }
+    }
+
+    pub fn decode(buf: &mut Bytes, pg_version: u32) -> Result<TwoPhaseFileHeader> {
+        ensure_size(buf, Self::size(pg_version)?, "TwoPhaseFileHeader")?;
+        let magic = buf.get_u32_le();
+        ensure!(
+            magic == TWOPHASE_MAGIC,
+            "invalid magic number {magic:#X} in two-phase state"
+        );
+        let total_len = buf.get_u32_le();
+        let xid = buf.get_u32_le();
+        let database = buf.get_u32_le();
+        let prepared_at = buf.get_i64_le();
+        let owner = buf.get_u32_le();
+        let nsubxacts = buf.get_i32_le();
+        let ncommitrels = buf.get_i32_le();
+        let nabortrels = buf.get_i32_le();
+        let (ncommitstats, nabortstats) = if pg_version >= 15 {
+            (buf.get_i32_le(), buf.get_i32_le())
+        } else {
+            (0, 0)
+        };
+        let ninvalmsgs = buf.get_i32_le();
+        let initfileinval = buf.get_u8() != 0;
+        buf.advance(1); // padding
+        let gidlen = buf.get_u16_le();
+        Ok(TwoPhaseFileHeader {
+            magic,
+            total_len,
+            xid,
+            database,
+            prepared_at,
+            owner,
+            nsubxacts,
+            ncommitrels,
+            nabortrels,
+            ncommitstats,
+            nabortstats,
+            ninvalmsgs,
+            initfileinval,
+            gidlen,
+            origin_lsn: buf.get_u64_le(),
+            origin_timestamp: buf.get_i64_le(),
+        })
+    }
+}
+
+/// A record of a resource manager, TwoPhaseRecordOnDisk followed by its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoPhaseRecord {
+    pub rmid: u8,
+    pub info: u16,
+    pub data: Bytes,
+}
+
+/// The decoded state data of a prepared transaction. The stats to drop and
+/// the invalidation messages are skipped, only their counts in the header
+/// are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoPhaseState {
+    pub header: TwoPhaseFileHeader,
+    pub gid: String,
+    pub subxacts: Vec<TransactionId>,
+    pub commitrels: Vec<RelFileNode>,
+    pub abortrels: Vec<RelFileNode>,
+    /// The records of the resource managers, without the end record.
+    pub records: Vec<TwoPhaseRecord>,
+}
+
+impl TwoPhaseState {
+    /// Decodes the state data of a prepared transaction, the main data of its
+    /// XLOG_XACT_PREPARE record. Use [`twophase_file_to_state_data`] first
+    /// for a state file.
+    pub fn decode(data: &Bytes, pg_version: u32) -> Result<TwoPhaseState> {
+        let mut buf = data.clone();
+        let header = TwoPhaseFileHeader::decode(&mut buf, pg_version)?;
+        ensure!(
+            header.total_len as usize == data.len() + 4,
+            "two-phase state of xid {} is {} bytes, total_len is {}",
+            header.xid,
+            data.len(),
+            header.total_len
+        );
+
+        let gidlen = header.gidlen as usize;
+        ensure_size(&buf, maxalign(gidlen), "GID")?;
+        let gid_bytes = &buf[..gidlen];
+        let gid =
+            String::from_utf8_lossy(gid_bytes.strip_suffix(&[0]).unwrap_or(gid_bytes)).into_owned();
+        buf.advance(maxalign(gidlen));
+
+        let nsubxacts = count(header.nsubxacts, "nsubxacts")?;
+        ensure_size(&buf, maxalign(nsubxacts * 4), "subxacts")?;
+        let mut part = buf.split_to(maxalign(nsubxacts * 4));
+        let subxacts = (0..nsubxacts).map(|_| part.get_u32_le()).collect();
+
+        let mut decode_rels = |n: i32, name: &str| -> Result<Vec<RelFileNode>> {
+            let n = count(n, name)?;
+            let len = maxalign(n * RelFileNode::SIZE);
+            ensure_size(&buf, len, name)?;
+            let mut part = buf.split_to(len);
+            Ok((0..n).map(|_| RelFileNode::decode(&mut part)).collect())
+        };
+        let commitrels = decode_rels(header.ncommitrels, "commitrels")?;
+        let abortrels = decode_rels(header.nabortrels, "abortrels")?;
+
+        let mut skip = |n: i32, size: usize, name: &str| -> Result<()> {
+            let len = maxalign(count(n, name)? * size);
+            ensure_size(&buf, len, name)?;
+            buf.advance(len);
+            Ok(())
+        };
+        skip(
+            header.ncommitstats,
+            SIZEOF_XL_XACT_STATS_ITEM,
+            "commitstats",
+        )?;
+        skip(header.nabortstats, SIZEOF_XL_XACT_STATS_ITEM, "abortstats")?;
+        skip(
+            header.ninvalmsgs,
+            SIZEOF_SHARED_INVALIDATION_MESSAGE,
+            "invalmsgs",
+        )?;
+
+        let mut records = Vec::new();
+        loop {
+            ensure_size(&buf, SIZEOF_TWOPHASE_RECORD, "TwoPhaseRecordOnDisk")?;
+            let len = buf.get_u32_le() as usize;
+            let rmid = buf.get_u8();
+            buf.advance(1); // padding
+            let info = buf.get_u16_le();
+            if rmid == TWOPHASE_RM_END_ID {
+                break;
+            }
+            ensure!(
+                rmid <= TWOPHASE_RM_PREDICATELOCK_ID,
+                "invalid resource manager {rmid} in two-phase state"
+            );
+            ensure_size(&buf, maxalign(len), "two-phase record")?;
+            let data = buf.split_to(len);
+            buf.advance(maxalign(len) - len);
+            records.push(TwoPhaseRecord { rmid, info, data });
+        }
+        ensure!(
+            !buf.has_remaining(),
+            "{} bytes of garbage after the end of two-phase state",
+            buf.remaining()
+        );
+
+        Ok(TwoPhaseState {
+            header,
+            gid,
+            subxacts,
+            commitrels,
+            abortrels,
+            records,
+        })
+    }
+}
+
+/// Checks the CRC of the state file `file` and returns its state data.
+pub fn twophase_file_to_state_data(file: &[u8]) -> Result<Bytes> {
+    ensure!(file.len() >= 4, "two-phase state file is too short");
+    let (data, crc) = file.split_at(file.len() - 4);
+    let crc = u32::from_le_bytes(crc.try_into().unwrap());
+    ensure!(
+        crc32c::crc32c(data) == crc,
+        "calculated CRC checksum does not match value stored in two-phase state file"
+    );
+    Ok(Bytes::copy_from_slice(data))
+}
+
+/// Builds the state file of the state data `data`, by appending its CRC.
+pub fn twophase_state_data_to_file(data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(data.len() + 4);
+    buf.extend_from_slice(data);
+    buf.extend_from_slice(&crc32c::crc32c(data).to_le_bytes());
+    buf.freeze()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    fn pad(buf: &mut BytesMut) {
+        buf.resize(maxalign(buf.len()), 0);
+    }
+
+    /// Builds the state data like StartPrepare() and EndPrepare() do.
+    fn build_state(pg_version: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(TWOPHASE_MAGIC);
+        buf.put_u32_le(0); // total_len, set below
+        buf.put_u32_le(727); // xid
+        buf.put_u32_le(5); // database
+        buf.put_i64_le(750_000_000_000_000); // prepared_at
+        buf.put_u32_le(10); // owner
+        buf.put_i32_le(2); // nsubxacts
+        buf.put_i32_le(1); // ncommitrels
+        buf.put_i32_le(1); // nabortrels
+        if pg_version >= 15 {
+            buf.put_i32_le(1); // ncommitstats
+            buf.put_i32_le(0); // nabortstats
+        }
+        buf.put_i32_le(2); // ninvalmsgs
+        buf.put_u8(0); // initfileinval
+        buf.put_u8(0);
+        buf.put_u16_le(8); // gidlen
+        buf.put_u64_le(0); // origin_lsn
+        buf.put_i64_le(0); // origin_timestamp
+        assert_eq!(buf.len(), TwoPhaseFileHeader::size(pg_version).unwrap());
+
+        buf.put_slice(b"gid_one\0");
+        buf.put_u32_le(728);
+        buf.put_u32_le(729);
+        for relnode in [16385, 16384] {
+            buf.put_u32_le(1663);
+            buf.put_u32_le(5);
+            buf.put_u32_le(relnode);
+            pad(&mut buf);
+        }
+        if pg_version >= 15 {
+            buf.put_bytes(7, SIZEOF_XL_XACT_STATS_ITEM);
+            pad(&mut buf);
+        }
+        buf.put_bytes(9, 2 * SIZEOF_SHARED_INVALIDATION_MESSAGE);
+
+        // a lock record and the end record
+        buf.put_u32_le(20);
+        buf.put_u8(TWOPHASE_RM_LOCK_ID);
+        buf.put_u8(0);
+        buf.put_u16_le(0);
+        buf.put_bytes(1, 20);
+        pad(&mut buf);
+        buf.put_bytes(0, SIZEOF_TWOPHASE_RECORD);
+
+        let total_len = buf.len() as u32 + 4;
+        buf[4..8].copy_from_slice(&total_len.to_le_bytes());
+        buf.freeze()
+    }
+
+    #[test]
+    fn decode_twophase_state() {
+        for pg_version in [14, 15] {
+            let data = build_state(pg_version);
+            let state = TwoPhaseState::decode(&data, pg_version).unwrap();
+            assert_eq!(state.header.xid, 727);
+            assert_eq!(state.header.database, 5);
+            assert_eq!(state.gid, "gid_one");
+            assert_eq!(state.subxacts, vec![728, 729]);
+            assert_eq!(state.commitrels[0].relnode, 16385);
+            assert_eq!(state.abortrels[0].relnode, 16384);
+            assert_eq!(state.records.len(), 1);
+            assert_eq!(state.records[0].rmid, TWOPHASE_RM_LOCK_ID);
+            assert_eq!(state.records[0].data.len(), 20);
+
+            // The state data of one version doesn't decode as the other.
+            let other_version = if pg_version == 14 { 15 } else { 14 };
+            assert!(TwoPhaseState::decode(&data, other_version).is_err());
+            assert!(TwoPhaseState::decode(&data.slice(..data.len() - 8), pg_version).is_err());
+        }
+    }
+
+    #[test]
+    fn state_file_crc() {
+        let data = build_state(15);
+        let file = twophase_state_data_to_file(&data);
+        assert_eq!(file.len(), data.len() + 4);
+        assert_eq!(twophase_file_to_state_data(&file).unwrap(), data);
+
+        let mut corrupt = file.to_vec();
+        corrupt[20] ^= 1;
+        assert!(twophase_file_to_state_data(&corrupt).is_err());
+    }
+}
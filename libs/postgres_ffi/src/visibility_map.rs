@@ -0,0 +1,172 @@
+//!
+//! Manipulation of visibility map pages, from visibilitymap.c.
+//!
+//! The visibility map has two bits per heap block, all-visible and
+//! all-frozen, packed after the page header of the pages of the VM fork. The
+//! functions here take a VM page and a heap block number, which must be one of
+//! the heap blocks the page covers, see [`vm_block`].
+//!
+use crate::pg_constants::{
+    self, HEAPBLK_TO_MAPBLOCK, HEAPBLK_TO_MAPBYTE, HEAPBLK_TO_OFFSET,
+    MAXALIGN_SIZE_OF_PAGE_HEADER_DATA, VISIBILITYMAP_ALL_FROZEN, VISIBILITYMAP_ALL_VISIBLE,
+    VISIBILITYMAP_VALID_BITS,
+};
+use crate::BlockNumber;
+
+/// Block of the visibility map that holds the bits of heap block `heap_blkno`.
+pub fn vm_block(heap_blkno: BlockNumber) -> BlockNumber {
+    HEAPBLK_TO_MAPBLOCK(heap_blkno)
+}
+
+/// Number of blocks of the visibility map of a heap of `heap_nblocks` blocks.
+pub fn vm_nblocks(heap_nblocks: BlockNumber) -> BlockNumber {
+    let nblocks = HEAPBLK_TO_MAPBLOCK(heap_nblocks);
+    if heap_nblocks % pg_constants::HEAPBLOCKS_PER_PAGE != 0 {
+        nblocks + 1
+    } else {
+        nblocks
+    }
+}
+
+// equivalent to PageGetContents(page)
+fn map_byte(page: &[u8], heap_blkno: BlockNumber) -> usize {
+    debug_assert!(page.len() > MAXALIGN_SIZE_OF_PAGE_HEADER_DATA);
+    MAXALIGN_SIZE_OF_PAGE_HEADER_DATA + HEAPBLK_TO_MAPBYTE(heap_blkno) as usize
+}
+
+/// Returns the bits of heap block `heap_blkno`. Port of
+/// visibilitymap_get_status().
+pub fn vm_get_flags(page: &[u8], heap_blkno: BlockNumber) -> u8 {
+    let byte = page[map_byte(page, heap_blkno)];
+    (byte >> HEAPBLK_TO_OFFSET(heap_blkno)) & VISIBILITYMAP_VALID_BITS
+}
+
+/// Sets `flags` for heap block `heap_blkno`. Returns whether any of them was
+/// clear. Port of the page update of visibilitymap_set().
+pub fn vm_set_flags(page: &mut [u8], heap_blkno: BlockNumber, flags: u8) -> bool {
+    debug_assert!(flags & !VISIBILITYMAP_VALID_BITS == 0);
+    let off = map_byte(page, heap_blkno);
+    let mask = flags << HEAPBLK_TO_OFFSET(heap_blkno);
+    let changed = page[off] & mask != mask;
+    page[off] |= mask;
+    changed
+}
+
+/// Clears `flags` for heap block `heap_blkno`. Returns whether any of them was
+/// set. Port of visibilitymap_clear().
+pub fn vm_clear_flags(page: &mut [u8], heap_blkno: BlockNumber, flags: u8) -> bool {
+    debug_assert!(flags & !VISIBILITYMAP_VALID_BITS == 0);
+    let off = map_byte(page, heap_blkno);
+    let mask = flags << HEAPBLK_TO_OFFSET(heap_blkno);
+    let changed = page[off] & mask != 0;
+    page[off] &= !mask;
+    changed
+}
+
+/// Clears the bits of the heap blocks from `heap_nblocks` onwards, on the last
+/// page of the visibility map of a heap truncated to `heap_nblocks` blocks.
+/// Port of the page update of visibilitymap_prepare_truncate().
+pub fn vm_truncate_page(page: &mut [u8], heap_nblocks: BlockNumber) {
+    let trunc_byte = map_byte(page, heap_nblocks);
+    let trunc_offset = HEAPBLK_TO_OFFSET(heap_nblocks);
+
+    page[trunc_byte + 1..].fill(0);
+    page[trunc_byte] &= (1u8 << trunc_offset).wrapping_sub(1);
+}
+
+/// Counts the all-visible and all-frozen heap blocks of a page. Port of the
+/// loop body of visibilitymap_count().
+pub fn vm_count(page: &[u8]) -> (u32, u32) {
+    let map = &page[MAXALIGN_SIZE_OF_PAGE_HEADER_DATA..];
+    let mut all_visible = 0;
+    let mut all_frozen = 0;
+    for byte in map {
+        for offset in (0..8).step_by(pg_constants::BITS_PER_HEAPBLOCK as usize) {
+            let flags = byte >> offset;
+            all_visible += (flags & VISIBILITYMAP_ALL_VISIBLE != 0) as u32;
+            all_frozen += (flags & VISIBILITYMAP_ALL_FROZEN != 0) as u32;
+        }
+    }
+    (all_visible, all_frozen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BLCKSZ;
+
+    const ALL_BITS: u8 = VISIBILITYMAP_ALL_VISIBLE | VISIBILITYMAP_ALL_FROZEN;
+
+    #[test]
+    fn test_vm_location() {
+        // 32672 heap blocks per VM page
+        assert_eq!(vm_block(0), 0);
+        assert_eq!(vm_block(32671), 0);
+        assert_eq!(vm_block(32672), 1);
+        assert_eq!(vm_nblocks(0), 0);
+        assert_eq!(vm_nblocks(1), 1);
+        assert_eq!(vm_nblocks(32672), 1);
+        assert_eq!(vm_nblocks(32673), 2);
+    }
+
+    #[test]
+    fn test_vm_set_clear() {
+        let mut page = vec![0u8; BLCKSZ as usize];
+
+        // Like the redo of XLOG_HEAP2_VISIBLE, for heap block 5 of VM block 1
+        let heap_blkno = 32672 + 5;
+        assert!(vm_set_flags(&mut page, heap_blkno, ALL_BITS));
+        assert!(!vm_set_flags(
+            &mut page,
+            heap_blkno,
+            VISIBILITYMAP_ALL_VISIBLE
+        ));
+        assert_eq!(vm_get_flags(&page, heap_blkno), ALL_BITS);
+        // 4 heap blocks per byte, 2 bits each
+        assert_eq!(page[24 + 1], 0b0000_1100);
+
+        assert!(vm_set_flags(
+            &mut page,
+            heap_blkno + 1,
+            VISIBILITYMAP_ALL_VISIBLE
+        ));
+        assert_eq!(page[24 + 1], 0b0001_1100);
+        assert_eq!(vm_count(&page), (2, 1));
+
+        // Like an insert on an all-visible page
+        assert!(vm_clear_flags(&mut page, heap_blkno, ALL_BITS));
+        assert!(!vm_clear_flags(&mut page, heap_blkno, ALL_BITS));
+        assert_eq!(vm_get_flags(&page, heap_blkno), 0);
+        assert_eq!(
+            vm_get_flags(&page, heap_blkno + 1),
+            VISIBILITYMAP_ALL_VISIBLE
+        );
+        assert_eq!(page[24 + 1], 0b0001_0000);
+    }
+
+    #[test]
+    fn test_vm_truncate_page() {
+        let mut page = vec![0u8; BLCKSZ as usize];
+        for heap_blkno in 0..20 {
+            vm_set_flags(&mut page, heap_blkno, ALL_BITS);
+        }
+        vm_set_flags(&mut page, 32671, ALL_BITS);
+
+        // Keep heap blocks 0 to 9, cutting the third byte in half
+        vm_truncate_page(&mut page, 10);
+        assert_eq!(vm_count(&page), (10, 10));
+        assert_eq!(page[24 + 2], 0b0000_1111);
+        assert!(page[24 + 3..].iter().all(|&b| b == 0));
+
+        // Truncating on a byte boundary clears the whole byte
+        vm_truncate_page(&mut page, 8);
+        assert_eq!(vm_count(&page), (8, 8));
+        assert_eq!(page[24 + 2], 0);
+
+        // The page header is left alone
+        let mut page = vec![0xFFu8; BLCKSZ as usize];
+        vm_truncate_page(&mut page, 32672 + 4);
+        assert!(page[..25].iter().all(|&b| b == 0xFF));
+        assert!(page[25..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,439 @@
+//!
+//! Generation of synthetic WAL, for tests.
+//!
+//! This builds syntactically valid WAL without a running PostgreSQL: records
+//! with correct headers and CRCs, for any rmgr, info bits and main data, laid
+//! out on pages with their page headers, and split into continuation records
+//! where they cross page boundaries. The content of the records is up to the
+//! caller, so the WAL is only as meaningful as the records fed to it, but
+//! that's enough to exercise the WalStreamDecoder and the WAL handling of the
+//! safekeeper and the pageserver. To craft WAL that PostgreSQL would replay,
+//! use the wal_craft crate.
+//!
+use anyhow::{bail, ensure};
+use bytes::{BufMut, Bytes, BytesMut};
+use crc32c::crc32c_append;
+use utils::lsn::Lsn;
+
+use crate::v14::bindings::{XLogLongPageHeaderData, XLogPageHeaderData};
+use crate::v14::xlog_utils::{
+    XLOG_RECORD_CRC_OFFS, XLOG_SIZE_OF_XLOG_LONG_PHD, XLOG_SIZE_OF_XLOG_SHORT_PHD,
+};
+use crate::{pg_constants, TransactionId, XLogRecord, XLogSegNo, PG_TLI, XLOG_BLCKSZ};
+use crate::{v14, v15, XLOG_SIZE_OF_XLOG_RECORD};
+
+/// A WAL record with main data only, before it gets its place in the WAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub rmid: u8,
+    pub info: u8,
+    pub xid: TransactionId,
+    pub main_data: Bytes,
+}
+
+impl Record {
+    pub fn new(rmid: u8, info: u8, main_data: impl Into<Bytes>) -> Record {
+        Record {
+            rmid,
+            info,
+            xid: 0,
+            main_data: main_data.into(),
+        }
+    }
+
+    /// A non-transactional logical message, as written by
+    /// pg_logical_emit_message(false, prefix, message).
+    pub fn logical_message(prefix: &str, message: &[u8]) -> Record {
+        let mut data = BytesMut::new();
+        data.put_u32_le(0); // db_id
+        data.put_u32_le(0); // transactional, padded
+        data.put_u64_le(prefix.len() as u64 + 1);
+        data.put_u64_le(message.len() as u64);
+        data.put_slice(prefix.as_bytes());
+        data.put_u8(0);
+        data.put_slice(message);
+        Record::new(
+            pg_constants::RM_LOGICALMSG_ID,
+            pg_constants::XLOG_LOGICAL_MESSAGE,
+            data.freeze(),
+        )
+    }
+
+    /// An XLOG_SWITCH record, after which the WAL continues in the next
+    /// segment.
+    pub fn xlog_switch() -> Record {
+        Record::new(
+            pg_constants::RM_XLOG_ID,
+            pg_constants::XLOG_SWITCH,
+            Bytes::new(),
+        )
+    }
+
+    /// Encodes the record, with `prev_lsn` as xl_prev. The result is the
+    /// record as the WalStreamDecoder returns it, without padding.
+    pub fn encode(&self, prev_lsn: Lsn) -> Bytes {
+        let mut data = BytesMut::new();
+        if self.main_data.len() > u8::MAX as usize {
+            data.put_u8(pg_constants::XLR_BLOCK_ID_DATA_LONG);
+            data.put_u32_le(self.main_data.len() as u32);
+        } else if !self.main_data.is_empty() {
+            data.put_u8(pg_constants::XLR_BLOCK_ID_DATA_SHORT);
+            data.put_u8(self.main_data.len() as u8);
+        }
+        data.put_slice(&self.main_data);
+
+        let mut header = XLogRecord {
+            xl_tot_len: (XLOG_SIZE_OF_XLOG_RECORD + data.len()) as u32,
+            xl_xid: self.xid,
+            xl_prev: prev_lsn.0,
+            xl_info: self.info,
+            xl_rmid: self.rmid,
+            __bindgen_padding_0: [0u8; 2usize],
+            xl_crc: 0, // crc will be calculated later
+        };
+        let header_bytes = header.encode().expect("failed to encode header");
+        let crc = crc32c_append(0, &data);
+        header.xl_crc = crc32c_append(crc, &header_bytes[0..XLOG_RECORD_CRC_OFFS]);
+
+        let mut record = BytesMut::with_capacity(header.xl_tot_len as usize);
+        record.put_slice(&header.encode().expect("failed to encode header"));
+        record.put_slice(&data);
+        record.freeze()
+    }
+}
+
+/// Lays out records in the WAL, one after the other, from a start LSN.
+///
+/// If the start LSN is at the beginning of a page, the generated WAL starts
+/// with the page header. Otherwise the page header is assumed to be there
+/// already, e.g. when appending to the WAL of a timeline.
+pub struct WalGenerator {
+    pg_version: u32,
+    system_id: u64,
+    wal_seg_size: usize,
+    xlog_page_magic: u16,
+    /// Where the next record goes, or the page header before it.
+    lsn: Lsn,
+    /// Start of the last record, for xl_prev.
+    prev_lsn: Lsn,
+}
+
+impl WalGenerator {
+    pub fn new(
+        pg_version: u32,
+        system_id: u64,
+        wal_seg_size: usize,
+        start_lsn: Lsn,
+    ) -> anyhow::Result<WalGenerator> {
+        let xlog_page_magic = match pg_version {
+            14 => v14::bindings::XLOG_PAGE_MAGIC,
+            15 => v15::bindings::XLOG_PAGE_MAGIC,
+            _ => bail!("Unknown version {}", pg_version),
+        } as u16;
+        ensure!(
+            wal_seg_size.is_power_of_two() && wal_seg_size >= XLOG_BLCKSZ,
+            "invalid WAL segment size {wal_seg_size}"
+        );
+        ensure!(start_lsn.is_aligned(), "unaligned start LSN {start_lsn}");
+        Ok(WalGenerator {
+            pg_version,
+            system_id,
+            wal_seg_size,
+            xlog_page_magic,
+            lsn: start_lsn,
+            prev_lsn: Lsn(0),
+        })
+    }
+
+    pub fn pg_version(&self) -> u32 {
+        self.pg_version
+    }
+
+    /// End of the generated WAL, where the next record goes.
+    pub fn lsn(&self) -> Lsn {
+        self.lsn
+    }
+
+    /// Sets the xl_prev of the next record, when continuing existing WAL.
+    pub fn set_prev_lsn(&mut self, prev_lsn: Lsn) {
+        self.prev_lsn = prev_lsn;
+    }
+
+    /// Appends a record. Returns its LSN, and the WAL to append after the end
+    /// of the previously generated WAL: the record, the page headers before
+    /// and inside it, and the padding after it, up to the next 8-byte
+    /// boundary, or to the end of the segment for an XLOG_SWITCH record.
+    pub fn append_record(&mut self, record: &Record) -> (Lsn, Bytes) {
+        let record_bytes = record.encode(self.prev_lsn);
+        let mut buf = BytesMut::with_capacity(record_bytes.len() + XLOG_SIZE_OF_XLOG_LONG_PHD);
+
+        if self.lsn.block_offset() == 0 {
+            self.put_page_header(&mut buf, 0);
+        }
+        let record_lsn = self.lsn;
+
+        let mut rest = &record_bytes[..];
+        loop {
+            let n = rest.len().min(self.lsn.remaining_in_block() as usize);
+            buf.put_slice(&rest[..n]);
+            rest = &rest[n..];
+            self.lsn += n as u64;
+            if rest.is_empty() {
+                break;
+            }
+            self.put_page_header(&mut buf, rest.len() as u32);
+        }
+
+        let padding = if record.rmid == pg_constants::RM_XLOG_ID
+            && record.info == pg_constants::XLOG_SWITCH
+        {
+            self.lsn.calc_padding(self.wal_seg_size as u64)
+        } else {
+            self.lsn.calc_padding(8u32)
+        };
+        buf.put_bytes(0, padding as usize);
+        self.lsn += padding;
+
+        self.prev_lsn = record_lsn;
+        (record_lsn, buf.freeze())
+    }
+
+    /// Appends records, and returns the WAL generated for them.
+    pub fn append_records<'a>(&mut self, records: impl IntoIterator<Item = &'a Record>) -> Bytes {
+        let mut buf = BytesMut::new();
+        for record in records {
+            buf.put(self.append_record(record).1);
+        }
+        buf.freeze()
+    }
+
+    /// Puts the header of the page at `self.lsn`, long at the start of a
+    /// segment, and moves past it. A non-zero `rem_len` makes it the header
+    /// of a page starting with a continuation record.
+    fn put_page_header(&mut self, buf: &mut BytesMut, rem_len: u32) {
+        debug_assert_eq!(self.lsn.block_offset(), 0);
+        let mut xlp_info = 0;
+        if rem_len != 0 {
+            xlp_info |= pg_constants::XLP_FIRST_IS_CONTRECORD;
+        }
+        let long = self.lsn.segment_offset(self.wal_seg_size) == 0;
+        if long {
+            xlp_info |= pg_constants::XLP_LONG_HEADER;
+        }
+
+        let std = XLogPageHeaderData {
+            xlp_magic: self.xlog_page_magic,
+            xlp_info,
+            xlp_tli: PG_TLI,
+            xlp_pageaddr: self.lsn.0,
+            xlp_rem_len: rem_len,
+            ..Default::default() // Put 0 in padding fields.
+        };
+        if long {
+            let hdr = XLogLongPageHeaderData {
+                std,
+                xlp_sysid: self.system_id,
+                xlp_seg_size: self.wal_seg_size as u32,
+                xlp_xlog_blcksz: XLOG_BLCKSZ as u32,
+            };
+            buf.put(hdr.encode().expect("failed to encode page header"));
+            self.lsn += XLOG_SIZE_OF_XLOG_LONG_PHD as u64;
+        } else {
+            buf.put(std.encode().expect("failed to encode page header"));
+            self.lsn += XLOG_SIZE_OF_XLOG_SHORT_PHD as u64;
+        }
+    }
+}
+
+/// Splits the WAL generated from `start_lsn`, which must be the start of a
+/// segment, into WAL segment files. The last one is filled up with zeros.
+pub fn wal_to_segments(
+    start_lsn: Lsn,
+    wal: &[u8],
+    wal_seg_size: usize,
+) -> anyhow::Result<Vec<(XLogSegNo, Bytes)>> {
+    ensure!(
+        start_lsn.segment_offset(wal_seg_size) == 0,
+        "WAL doesn't start at a segment boundary: {start_lsn}"
+    );
+    let first_segno = start_lsn.segment_number(wal_seg_size);
+    Ok(wal
+        .chunks(wal_seg_size)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut segment = BytesMut::zeroed(wal_seg_size);
+            segment[..chunk.len()].copy_from_slice(chunk);
+            (first_segno + i as u64, segment.freeze())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waldecoder::WalStreamDecoder;
+    use crate::walrecord::WalRecord;
+
+    const WAL_SEG_SIZE: usize = 1024 * 1024;
+
+    fn decode_all(start_lsn: Lsn, pg_version: u32, wal: &[u8]) -> Vec<(Lsn, Bytes)> {
+        let mut decoder = WalStreamDecoder::new(start_lsn, pg_version, WAL_SEG_SIZE);
+        decoder.feed_bytes(wal);
+        let mut records = Vec::new();
+        while let Some(record) = decoder.poll_decode().unwrap() {
+            records.push(record);
+        }
+        assert_eq!(decoder.available(), start_lsn + wal.len() as u64);
+        records
+    }
+
+    #[test]
+    fn test_record_encode() {
+        let record = Record {
+            xid: 1234,
+            ..Record::new(pg_constants::RM_HEAP_ID, 0x10, &b"main data"[..])
+        };
+        let bytes = record.encode(Lsn(0x1000028));
+        assert_eq!(bytes.len(), 24 + 2 + 9);
+
+        let decoded = WalRecord::decode(bytes, 14).unwrap();
+        assert_eq!(decoded.xl_rmid, pg_constants::RM_HEAP_ID);
+        assert_eq!(decoded.xl_info, 0x10);
+        assert_eq!(decoded.xl_xid, 1234);
+        assert_eq!(decoded.xl_prev, 0x1000028);
+        assert_eq!(&decoded.main_data[..], b"main data");
+
+        // Main data longer than 255 bytes has a long header
+        let record = Record::new(pg_constants::RM_HEAP_ID, 0, vec![7u8; 300]);
+        let bytes = record.encode(Lsn(0));
+        assert_eq!(bytes.len(), 24 + 5 + 300);
+        assert_eq!(bytes[24], pg_constants::XLR_BLOCK_ID_DATA_LONG);
+        let decoded = WalRecord::decode(bytes, 14).unwrap();
+        assert_eq!(decoded.main_data.len(), 300);
+    }
+
+    #[test]
+    fn test_logical_message() {
+        // Same as the hand-made one
+        let record = Record::logical_message("prefix", b"message");
+        let mut expected = record.encode(Lsn(0)).to_vec();
+        expected.resize((expected.len() + 7) & !7, 0);
+        assert_eq!(expected, crate::encode_logical_message("prefix", "message"));
+    }
+
+    #[test]
+    fn test_wal_generator() {
+        for pg_version in [14, 15] {
+            // Start at the beginning of the second segment, with records that
+            // cross pages, and enough of them to get into the third segment
+            let start_lsn = Lsn(WAL_SEG_SIZE as u64);
+            let mut generator = WalGenerator::new(pg_version, 42, WAL_SEG_SIZE, start_lsn).unwrap();
+            let mut records = Vec::new();
+            for i in 0..300 {
+                let len = [0, 1, 255, 256, 5000, 20000][i % 6] + i;
+                records.push(Record::new(pg_constants::RM_HEAP_ID, 0, vec![i as u8; len]));
+            }
+            let mut wal = BytesMut::new();
+            let mut lsns = Vec::new();
+            for record in &records {
+                let (lsn, bytes) = generator.append_record(record);
+                assert!(lsn.is_aligned());
+                // After the page header, if any
+                let pos = start_lsn.0 + wal.len() as u64;
+                assert!(lsn.0 >= pos && lsn.0 - pos <= XLOG_SIZE_OF_XLOG_LONG_PHD as u64);
+                lsns.push(lsn);
+                wal.put(bytes);
+            }
+            assert!(generator.lsn() > start_lsn + WAL_SEG_SIZE as u64);
+            assert_eq!(generator.lsn(), start_lsn + wal.len() as u64);
+
+            // The decoder gets them back, and checks the page headers and CRCs
+            let decoded = decode_all(start_lsn, pg_version, &wal);
+            assert_eq!(decoded.len(), records.len());
+            for (i, (end_lsn, bytes)) in decoded.into_iter().enumerate() {
+                let next_lsn = lsns.get(i + 1).copied().unwrap_or(generator.lsn());
+                assert!(end_lsn <= next_lsn);
+                assert!(next_lsn.0 - end_lsn.0 <= XLOG_SIZE_OF_XLOG_LONG_PHD as u64);
+
+                let decoded = WalRecord::decode(bytes, pg_version).unwrap();
+                assert_eq!(decoded.main_data, records[i].main_data);
+                let prev_lsn = if i == 0 { Lsn(0) } else { lsns[i - 1] };
+                assert_eq!(decoded.xl_prev, prev_lsn.0);
+            }
+
+            // A decoder starting at any of the records, e.g. on a safekeeper
+            // restart, decodes the rest
+            let offset = (lsns[100].0 - start_lsn.0) as usize;
+            let decoded = decode_all(lsns[100], pg_version, &wal[offset..]);
+            assert_eq!(decoded.len(), records.len() - 100);
+        }
+    }
+
+    #[test]
+    fn test_wal_generator_mid_page() {
+        // Continuing WAL in the middle of a page adds no page header
+        let start_lsn = Lsn(0x1000028);
+        let mut generator = WalGenerator::new(15, 42, WAL_SEG_SIZE, start_lsn).unwrap();
+        generator.set_prev_lsn(Lsn(0x1000000));
+        let (lsn, wal) = generator.append_record(&Record::logical_message("prefix", b"msg"));
+        assert_eq!(lsn, start_lsn);
+        assert_eq!(wal.len() % 8, 0);
+        let decoded = decode_all(start_lsn, 15, &wal);
+        let decoded = WalRecord::decode(decoded[0].1.clone(), 15).unwrap();
+        assert_eq!(decoded.xl_prev, 0x1000000);
+
+        // A record ending on a page boundary leaves the next one to start
+        // with a fresh page header
+        let left = generator.lsn().remaining_in_block() as usize;
+        let len = left - 24 - 5;
+        let (_, wal2) = generator.append_record(&Record::new(0, 0, vec![0u8; len]));
+        assert_eq!(generator.lsn().block_offset(), 0);
+        let (lsn, wal3) = generator.append_record(&Record::new(0, 0, Bytes::new()));
+        assert_eq!(
+            lsn,
+            generator.lsn().page_lsn() + XLOG_SIZE_OF_XLOG_SHORT_PHD as u64
+        );
+        let wal = [wal, wal2, wal3].concat();
+        assert_eq!(decode_all(start_lsn, 15, &wal).len(), 3);
+
+        assert!(WalGenerator::new(16, 42, WAL_SEG_SIZE, start_lsn).is_err());
+        assert!(WalGenerator::new(15, 42, WAL_SEG_SIZE, Lsn(0x1000001)).is_err());
+    }
+
+    #[test]
+    fn test_wal_segments() {
+        let start_lsn = Lsn(0);
+        let mut generator = WalGenerator::new(14, 42, WAL_SEG_SIZE, start_lsn).unwrap();
+        let message = Record::logical_message("prefix", b"message");
+        let mut wal = BytesMut::new();
+        wal.put(generator.append_records([&message, &message]));
+
+        // XLOG_SWITCH pads the rest of the segment
+        let (switch_lsn, bytes) = generator.append_record(&Record::xlog_switch());
+        wal.put(bytes);
+        assert_eq!(generator.lsn(), Lsn(WAL_SEG_SIZE as u64));
+        let (lsn, bytes) = generator.append_record(&message);
+        assert_eq!(lsn, Lsn((WAL_SEG_SIZE + XLOG_SIZE_OF_XLOG_LONG_PHD) as u64));
+        wal.put(bytes);
+
+        let decoded = decode_all(start_lsn, 14, &wal);
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded[2].0, Lsn(WAL_SEG_SIZE as u64));
+        let switch = WalRecord::decode(decoded[2].1.clone(), 14).unwrap();
+        assert_eq!(switch.xl_info, pg_constants::XLOG_SWITCH);
+        let last = WalRecord::decode(decoded[3].1.clone(), 14).unwrap();
+        assert_eq!(last.xl_prev, switch_lsn.0);
+
+        let segments = wal_to_segments(start_lsn, &wal, WAL_SEG_SIZE).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].0, 1);
+        assert!(segments.iter().all(|(_, s)| s.len() == WAL_SEG_SIZE));
+        // Each starts with a long page header
+        let hdr = XLogLongPageHeaderData::from_bytes(&mut &segments[1].1[..]).unwrap();
+        assert_eq!(hdr.std.xlp_pageaddr, WAL_SEG_SIZE as u64);
+        assert_eq!(hdr.xlp_sysid, 42);
+        assert_eq!(hdr.xlp_seg_size, WAL_SEG_SIZE as u32);
+
+        assert!(wal_to_segments(Lsn(0x1000028), &wal, WAL_SEG_SIZE).is_err());
+    }
+}
@@ -8,10 +8,9 @@
 //! to look deeper into the WAL records to also understand which blocks they modify, the code
 //! for that is in pageserver/src/walrecord.rs
 //!
-use super::super::waldecoder::{State, WalDecodeError, WalStreamDecoder};
+use super::super::waldecoder::{State, WalDecodeError, WalDecodeErrorKind, WalStreamDecoder};
 use super::bindings::{XLogLongPageHeaderData, XLogPageHeaderData, XLogRecord, XLOG_PAGE_MAGIC};
 use super::xlog_utils::*;
-use crate::WAL_SEGMENT_SIZE;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use crc32c::*;
 use log::*;
@@ -86,7 +85,11 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
             };
             Ok(())
         };
-        validate_impl().map_err(|msg| WalDecodeError { msg, lsn: self.lsn })
+        validate_impl().map_err(|msg| WalDecodeError {
+            kind: WalDecodeErrorKind::PageHeader,
+            msg,
+            lsn: self.lsn,
+        })
     }
 
     /// Attempt to decode another WAL record from the input that has been fed to the
@@ -105,7 +108,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
             // However, we may have to skip some page headers if we're processing the XLOG_SWITCH record or skipping padding for whatever reason.
             match self.state {
                 State::WaitingForRecord | State::ReassemblingRecord { .. } => {
-                    if self.lsn.segment_offset(WAL_SEGMENT_SIZE) == 0 {
+                    if self.lsn.segment_offset(self.wal_seg_size) == 0 {
                         // parse long header
 
                         if self.inputbuf.remaining() < XLOG_SIZE_OF_XLOG_LONG_PHD {
@@ -114,6 +117,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
 
                         let hdr = XLogLongPageHeaderData::from_bytes(&mut self.inputbuf).map_err(
                             |e| WalDecodeError {
+                                kind: WalDecodeErrorKind::PageHeader,
                                 msg: format!("long header deserialization failed {}", e),
                                 lsn: self.lsn,
                             },
@@ -130,6 +134,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
                         let hdr =
                             XLogPageHeaderData::from_bytes(&mut self.inputbuf).map_err(|e| {
                                 WalDecodeError {
+                                    kind: WalDecodeErrorKind::PageHeader,
                                     msg: format!("header deserialization failed {}", e),
                                     lsn: self.lsn,
                                 }
@@ -155,6 +160,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
                     let xl_tot_len = (&self.inputbuf[0..4]).get_u32_le();
                     if (xl_tot_len as usize) < XLOG_SIZE_OF_XLOG_RECORD {
                         return Err(WalDecodeError {
+                            kind: WalDecodeErrorKind::Record,
                             msg: format!("invalid xl_tot_len {}", xl_tot_len),
                             lsn: self.lsn,
                         });
@@ -218,6 +224,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
         let xlogrec =
             XLogRecord::from_slice(&recordbuf[0..XLOG_SIZE_OF_XLOG_RECORD]).map_err(|e| {
                 WalDecodeError {
+                    kind: WalDecodeErrorKind::Record,
                     msg: format!("xlog record deserialization failed {}", e),
                     lsn: self.lsn,
                 }
@@ -228,6 +235,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
         crc = crc32c_append(crc, &recordbuf[0..XLOG_RECORD_CRC_OFFS]);
         if crc != xlogrec.xl_crc {
             return Err(WalDecodeError {
+                kind: WalDecodeErrorKind::Crc,
                 msg: "WAL record crc mismatch".into(),
                 lsn: self.lsn,
             });
@@ -237,7 +245,7 @@ impl WalStreamDecoderHandler for WalStreamDecoder {
         // to the next WAL segment.
         let next_lsn = if xlogrec.is_xlog_switch_record() {
             trace!("saw xlog switch record at {}", self.lsn);
-            self.lsn + self.lsn.calc_padding(WAL_SEGMENT_SIZE as u64)
+            self.lsn + self.lsn.calc_padding(self.wal_seg_size as u64)
         } else {
             // Pad to an 8-byte boundary
             self.lsn.align()
@@ -0,0 +1,587 @@
+//!
+//! Decoding of WAL records into typed structs.
+//!
+//! [`WalRecord::decode`] splits a record, as returned by the WalStreamDecoder,
+//! into its header, block references and main data. [`RmgrRecord::decode`]
+//! then decodes the main data according to the resource manager of the record,
+//! like the rmgrdesc routines of PostgreSQL do for pg_waldump. This covers the
+//! heap, the index access methods, sequences, logical messages, the relation
+//! mapper and hot standby. Records of other resource managers are left
+//! undecoded.
+//!
+//! The Display implementations print the records the way pg_waldump does: the
+//! name of the record type, followed by its fields.
+//!
+use std::fmt;
+
+use anyhow::{bail, ensure, Context, Result};
+use bytes::{Buf, Bytes};
+
+use crate::bkpimage::BkpImage;
+use crate::pg_constants;
+use crate::{BlockNumber, Oid, TransactionId, XLogRecPtr, XLogRecord};
+use crate::{BLCKSZ, XLOG_SIZE_OF_XLOG_RECORD};
+
+pub use crate::transam::FullTransactionId;
+
+pub mod brin;
+pub mod btree;
+pub mod gin;
+pub mod gist;
+pub mod hash;
+pub mod heap;
+pub mod logicalmsg;
+pub mod relmap;
+pub mod seq;
+pub mod spgist;
+pub mod standby;
+
+/// Checks that `buf` holds at least `size` bytes of the struct `name`, before
+/// they are read with the panicking getters of `Buf`.
+pub(crate) fn ensure_size(buf: &Bytes, size: usize, name: &str) -> Result<()> {
+    ensure!(
+        buf.remaining() >= size,
+        "{name} is {} bytes, expected at least {size}",
+        buf.remaining()
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelFileNode {
+    pub spcnode: Oid, /* tablespace */
+    pub dbnode: Oid,  /* database */
+    pub relnode: Oid, /* relation */
+}
+
+impl RelFileNode {
+    pub const SIZE: usize = 12;
+
+    pub fn decode(buf: &mut Bytes) -> RelFileNode {
+        RelFileNode {
+            spcnode: buf.get_u32_le(),
+            dbnode: buf.get_u32_le(),
+            relnode: buf.get_u32_le(),
+        }
+    }
+}
+
+impl fmt::Display for RelFileNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.spcnode, self.dbnode, self.relnode)
+    }
+}
+
+/// A block referenced by a WAL record, with its full-page image and data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRef {
+    pub block_id: u8,
+    pub rnode: RelFileNode,
+    pub forknum: u8,
+    pub blkno: BlockNumber,
+    pub will_init: bool,
+
+    /* Information on full-page image, if any */
+    pub has_image: bool,
+    pub apply_image: bool,
+    pub bimg_info: u8,
+    pub hole_offset: u16,
+    pub hole_length: u16,
+    /// The image as stored in the record, compressed or without the hole.
+    pub image: Bytes,
+
+    /// The resource manager specific data of the block.
+    pub data: Bytes,
+}
+
+impl BlockRef {
+    /// The full-page image of the block, if any.
+    pub fn bkpimage(&self, pg_version: u32) -> Result<Option<BkpImage>> {
+        if !self.has_image {
+            return Ok(None);
+        }
+        let bkpimage = BkpImage::new(
+            self.bimg_info,
+            self.hole_offset,
+            self.hole_length,
+            self.image.clone(),
+            pg_version,
+        )?;
+        Ok(Some(bkpimage))
+    }
+}
+
+/// A WAL record split into its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalRecord {
+    pub xl_tot_len: u32,
+    pub xl_xid: TransactionId,
+    pub xl_prev: XLogRecPtr,
+    pub xl_info: u8,
+    pub xl_rmid: u8,
+    pub origin: Option<u16>,
+    pub toplevel_xid: Option<TransactionId>,
+    pub blocks: Vec<BlockRef>,
+    pub main_data: Bytes,
+}
+
+impl WalRecord {
+    /// Decodes the record `record`, including its XLogRecord header.
+    ///
+    /// This follows DecodeXLogRecord in xlogreader.c, but doesn't check the CRC
+    /// of the record, which the WalStreamDecoder does already.
+    pub fn decode(record: Bytes, pg_version: u32) -> Result<WalRecord> {
+        let mut buf = record.clone();
+        ensure_size(&buf, XLOG_SIZE_OF_XLOG_RECORD, "XLogRecord")?;
+        let xlogrec = XLogRecord::from_bytes(&mut buf)?;
+        ensure!(
+            xlogrec.xl_tot_len as usize == record.len(),
+            "record length {} doesn't match xl_tot_len {}",
+            record.len(),
+            xlogrec.xl_tot_len
+        );
+
+        let mut origin = None;
+        let mut toplevel_xid = None;
+        let mut blocks = Vec::new();
+        let mut main_data_len = 0;
+        let mut datatotal = 0;
+        let mut rnode = None;
+        let mut max_block_id = None;
+        // The headers are followed by the block images and data, and the main data.
+        while buf.remaining() > datatotal {
+            let block_id = buf.get_u8();
+            match block_id {
+                pg_constants::XLR_BLOCK_ID_DATA_SHORT => {
+                    ensure_size(&buf, 1, "XLogRecordDataHeaderShort")?;
+                    main_data_len = buf.get_u8() as usize;
+                    datatotal += main_data_len;
+                    // The main data header is the last one.
+                    break;
+                }
+                pg_constants::XLR_BLOCK_ID_DATA_LONG => {
+                    ensure_size(&buf, 4, "XLogRecordDataHeaderLong")?;
+                    main_data_len = buf.get_u32_le() as usize;
+                    datatotal += main_data_len;
+                    break;
+                }
+                pg_constants::XLR_BLOCK_ID_ORIGIN => {
+                    ensure_size(&buf, 2, "RepOriginId")?;
+                    origin = Some(buf.get_u16_le());
+                }
+                pg_constants::XLR_BLOCK_ID_TOPLEVEL_XID => {
+                    ensure_size(&buf, 4, "TransactionId")?;
+                    toplevel_xid = Some(buf.get_u32_le());
+                }
+                0..=pg_constants::XLR_MAX_BLOCK_ID => {
+                    ensure!(
+                        max_block_id.map_or(true, |max| block_id > max),
+                        "out-of-order block_id {block_id}"
+                    );
+                    max_block_id = Some(block_id);
+                    let (block, bimg_len, data_len) =
+                        decode_block_header(&mut buf, block_id, &mut rnode, pg_version)?;
+                    datatotal += bimg_len as usize + data_len as usize;
+                    blocks.push((block, bimg_len, data_len));
+                }
+                _ => bail!("invalid block_id {block_id}"),
+            }
+        }
+        ensure!(
+            buf.remaining() == datatotal,
+            "record has {} bytes of data, the headers describe {datatotal}",
+            buf.remaining()
+        );
+
+        let blocks = blocks
+            .into_iter()
+            .map(|(mut block, bimg_len, data_len)| {
+                block.image = buf.split_to(bimg_len as usize);
+                block.data = buf.split_to(data_len as usize);
+                block
+            })
+            .collect();
+        debug_assert_eq!(buf.remaining(), main_data_len);
+
+        Ok(WalRecord {
+            xl_tot_len: xlogrec.xl_tot_len,
+            xl_xid: xlogrec.xl_xid,
+            xl_prev: xlogrec.xl_prev,
+            xl_info: xlogrec.xl_info,
+            xl_rmid: xlogrec.xl_rmid,
+            origin,
+            toplevel_xid,
+            blocks,
+            main_data: buf,
+        })
+    }
+
+    /// Decodes the main data of the record, for its resource manager.
+    pub fn rmgr_record(&self) -> Result<RmgrRecord> {
+        RmgrRecord::decode(self.xl_rmid, self.xl_info, &self.main_data)
+    }
+}
+
+/// Decodes an XLogRecordBlockHeader and the headers following it. Returns the
+/// block without its image and data, and their lengths.
+fn decode_block_header(
+    buf: &mut Bytes,
+    block_id: u8,
+    rnode: &mut Option<RelFileNode>,
+    pg_version: u32,
+) -> Result<(BlockRef, u16, u16)> {
+    ensure_size(buf, 3, "XLogRecordBlockHeader")?;
+    let fork_flags = buf.get_u8();
+    let data_len = buf.get_u16_le();
+    let has_data = fork_flags & pg_constants::BKPBLOCK_HAS_DATA != 0;
+    ensure!(
+        has_data == (data_len > 0),
+        "BKPBLOCK_HAS_DATA doesn't match data length {data_len} of block {block_id}"
+    );
+
+    let mut block = BlockRef {
+        block_id,
+        rnode: RelFileNode {
+            spcnode: 0,
+            dbnode: 0,
+            relnode: 0,
+        },
+        forknum: fork_flags & pg_constants::BKPBLOCK_FORK_MASK,
+        blkno: 0,
+        will_init: fork_flags & pg_constants::BKPBLOCK_WILL_INIT != 0,
+        has_image: fork_flags & pg_constants::BKPBLOCK_HAS_IMAGE != 0,
+        apply_image: false,
+        bimg_info: 0,
+        hole_offset: 0,
+        hole_length: 0,
+        image: Bytes::new(),
+        data: Bytes::new(),
+    };
+
+    let mut bimg_len = 0;
+    if block.has_image {
+        ensure_size(buf, 5, "XLogRecordBlockImageHeader")?;
+        bimg_len = buf.get_u16_le();
+        block.hole_offset = buf.get_u16_le();
+        block.bimg_info = buf.get_u8();
+        block.apply_image = match pg_version {
+            14 => block.bimg_info & crate::v14::bindings::BKPIMAGE_APPLY != 0,
+            15 => block.bimg_info & crate::v15::bindings::BKPIMAGE_APPLY != 0,
+            _ => bail!("Unknown version {}", pg_version),
+        };
+        let has_hole = block.bimg_info & pg_constants::BKPIMAGE_HAS_HOLE != 0;
+        if crate::bkpimage_is_compressed(block.bimg_info, pg_version)? {
+            if has_hole {
+                ensure_size(buf, 2, "XLogRecordBlockCompressHeader")?;
+                block.hole_length = buf.get_u16_le();
+            }
+        } else {
+            ensure!(
+                bimg_len <= BLCKSZ,
+                "block image length {bimg_len} of block {block_id} is over BLCKSZ"
+            );
+            block.hole_length = BLCKSZ - bimg_len;
+        }
+        ensure!(
+            has_hole == (block.hole_length > 0),
+            "BKPIMAGE_HAS_HOLE doesn't match hole length {} of block {block_id}",
+            block.hole_length
+        );
+    }
+
+    if fork_flags & pg_constants::BKPBLOCK_SAME_REL == 0 {
+        ensure_size(buf, RelFileNode::SIZE, "RelFileNode")?;
+        *rnode = Some(RelFileNode::decode(buf));
+    }
+    block.rnode = rnode.with_context(|| {
+        format!("BKPBLOCK_SAME_REL set but no previous rel for block {block_id}")
+    })?;
+    ensure_size(buf, 4, "BlockNumber")?;
+    block.blkno = buf.get_u32_le();
+
+    Ok((block, bimg_len, data_len))
+}
+
+impl fmt::Display for WalRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rmgr: {}, len: {}, tx: {}, prev {:X}/{:08X}, desc: ",
+            rmgr_name(self.xl_rmid),
+            self.xl_tot_len,
+            self.xl_xid,
+            self.xl_prev >> 32,
+            self.xl_prev as u32,
+        )?;
+        match self.rmgr_record() {
+            Ok(rec) => write!(f, "{rec}")?,
+            Err(e) => write!(f, "INVALID ({e:#})")?,
+        }
+        for block in &self.blocks {
+            write!(
+                f,
+                ", blkref #{}: rel {} fork {} blk {}",
+                block.block_id,
+                block.rnode,
+                fork_name(block.forknum),
+                block.blkno
+            )?;
+            if block.has_image {
+                write!(f, " FPW")?;
+                if block.hole_length > 0 {
+                    write!(
+                        f,
+                        " (hole: offset: {}, length: {})",
+                        block.hole_offset, block.hole_length
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of the fork `forknum`, as in relpath.c.
+pub fn fork_name(forknum: u8) -> &'static str {
+    match forknum {
+        crate::relfile_utils::MAIN_FORKNUM => "main",
+        crate::relfile_utils::FSM_FORKNUM => "fsm",
+        crate::relfile_utils::VISIBILITYMAP_FORKNUM => "vm",
+        crate::relfile_utils::INIT_FORKNUM => "init",
+        _ => "unknown",
+    }
+}
+
+/// Returns the name of the resource manager `rmid`, as printed by pg_waldump.
+pub fn rmgr_name(rmid: u8) -> &'static str {
+    match rmid {
+        pg_constants::RM_XLOG_ID => "XLOG",
+        pg_constants::RM_XACT_ID => "Transaction",
+        pg_constants::RM_SMGR_ID => "Storage",
+        pg_constants::RM_CLOG_ID => "CLOG",
+        pg_constants::RM_DBASE_ID => "Database",
+        pg_constants::RM_TBLSPC_ID => "Tablespace",
+        pg_constants::RM_MULTIXACT_ID => "MultiXact",
+        pg_constants::RM_RELMAP_ID => "RelMap",
+        pg_constants::RM_STANDBY_ID => "Standby",
+        pg_constants::RM_HEAP2_ID => "Heap2",
+        pg_constants::RM_HEAP_ID => "Heap",
+        pg_constants::RM_BTREE_ID => "Btree",
+        pg_constants::RM_HASH_ID => "Hash",
+        pg_constants::RM_GIN_ID => "Gin",
+        pg_constants::RM_GIST_ID => "Gist",
+        pg_constants::RM_SEQ_ID => "Sequence",
+        pg_constants::RM_SPGIST_ID => "SPGist",
+        pg_constants::RM_BRIN_ID => "BRIN",
+        pg_constants::RM_COMMIT_TS_ID => "CommitTs",
+        pg_constants::RM_REPLORIGIN_ID => "ReplicationOrigin",
+        pg_constants::RM_GENERIC_ID => "Generic",
+        pg_constants::RM_LOGICALMSG_ID => "LogicalMessage",
+        pg_constants::RM_CSNLOG_ID => "CSNLog",
+        _ => "UNKNOWN",
+    }
+}
+
+/// The main data of a WAL record, decoded according to its resource manager.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RmgrRecord {
+    Heap(heap::HeapRecord),
+    Heap2(heap::Heap2Record),
+    Btree(btree::BtreeRecord),
+    Hash(hash::HashRecord),
+    Gin(gin::GinRecord),
+    Gist(gist::GistRecord),
+    SpGist(spgist::SpGistRecord),
+    Brin(brin::BrinRecord),
+    Seq(seq::SeqRecord),
+    LogicalMessage(logicalmsg::LogicalMessageRecord),
+    Relmap(relmap::RelmapRecord),
+    Standby(standby::StandbyRecord),
+    /// A record of a resource manager that is not decoded here.
+    Other {
+        rmid: u8,
+        info: u8,
+    },
+}
+
+impl RmgrRecord {
+    /// Decodes the main data `main_data` of a record of the resource manager
+    /// `rmid`, with `info` the xl_info of the record.
+    pub fn decode(rmid: u8, info: u8, main_data: &Bytes) -> Result<RmgrRecord> {
+        let mut buf = main_data.clone();
+        let info = info & pg_constants::XLR_RMGR_INFO_MASK;
+        let rec = match rmid {
+            pg_constants::RM_HEAP_ID => RmgrRecord::Heap(heap::HeapRecord::decode(info, &mut buf)?),
+            pg_constants::RM_HEAP2_ID => {
+                RmgrRecord::Heap2(heap::Heap2Record::decode(info, &mut buf)?)
+            }
+            pg_constants::RM_BTREE_ID => {
+                RmgrRecord::Btree(btree::BtreeRecord::decode(info, &mut buf)?)
+            }
+            pg_constants::RM_HASH_ID => RmgrRecord::Hash(hash::HashRecord::decode(info, &mut buf)?),
+            pg_constants::RM_GIN_ID => RmgrRecord::Gin(gin::GinRecord::decode(info, &mut buf)?),
+            pg_constants::RM_GIST_ID => RmgrRecord::Gist(gist::GistRecord::decode(info, &mut buf)?),
+            pg_constants::RM_SPGIST_ID => {
+                RmgrRecord::SpGist(spgist::SpGistRecord::decode(info, &mut buf)?)
+            }
+            pg_constants::RM_BRIN_ID => RmgrRecord::Brin(brin::BrinRecord::decode(info, &mut buf)?),
+            pg_constants::RM_SEQ_ID => RmgrRecord::Seq(seq::SeqRecord::decode(info, &mut buf)?),
+            pg_constants::RM_LOGICALMSG_ID => RmgrRecord::LogicalMessage(
+                logicalmsg::LogicalMessageRecord::decode(info, &mut buf)?,
+            ),
+            pg_constants::RM_RELMAP_ID => {
+                RmgrRecord::Relmap(relmap::RelmapRecord::decode(info, &mut buf)?)
+            }
+            pg_constants::RM_STANDBY_ID => {
+                RmgrRecord::Standby(standby::StandbyRecord::decode(info, &mut buf)?)
+            }
+            _ => RmgrRecord::Other { rmid, info },
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for RmgrRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RmgrRecord::Heap(rec) => rec.fmt(f),
+            RmgrRecord::Heap2(rec) => rec.fmt(f),
+            RmgrRecord::Btree(rec) => rec.fmt(f),
+            RmgrRecord::Hash(rec) => rec.fmt(f),
+            RmgrRecord::Gin(rec) => rec.fmt(f),
+            RmgrRecord::Gist(rec) => rec.fmt(f),
+            RmgrRecord::SpGist(rec) => rec.fmt(f),
+            RmgrRecord::Brin(rec) => rec.fmt(f),
+            RmgrRecord::Seq(rec) => rec.fmt(f),
+            RmgrRecord::LogicalMessage(rec) => rec.fmt(f),
+            RmgrRecord::Relmap(rec) => rec.fmt(f),
+            RmgrRecord::Standby(rec) => rec.fmt(f),
+            RmgrRecord::Other { info, .. } => write!(f, "UNKNOWN ({info:x})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_logical_message() {
+        let record = Bytes::from(crate::encode_logical_message("prefix", "message"));
+        let record = WalRecord::decode(record, 14).unwrap();
+        assert_eq!(record.xl_rmid, pg_constants::RM_LOGICALMSG_ID);
+        assert!(record.blocks.is_empty());
+
+        let RmgrRecord::LogicalMessage(logicalmsg::LogicalMessageRecord::Message(message)) =
+            record.rmgr_record().unwrap()
+        else {
+            panic!("not a logical message");
+        };
+        assert_eq!(message.prefix, "prefix");
+        assert_eq!(&message.message[..], b"message");
+        assert!(!message.transactional);
+        assert_eq!(
+            record.rmgr_record().unwrap().to_string(),
+            "MESSAGE non-transactional, prefix \"prefix\"; payload (7 bytes): 6D 65 73 73 61 67 65"
+        );
+    }
+
+    #[test]
+    fn decode_heap_insert() {
+        // A HEAP INSERT+INIT of offset 1 into block 0 of rel 1663/5/16384, with
+        // 3 bytes of tuple data.
+        let mut data = vec![
+            0,    // block_id
+            0x60, // main fork, BKPBLOCK_HAS_DATA | BKPBLOCK_WILL_INIT
+            3,
+            0, // data length
+            0x7f,
+            0x06,
+            0,
+            0,
+            5,
+            0,
+            0,
+            0,
+            0,
+            0x40,
+            0,
+            0, // RelFileNode
+            0,
+            0,
+            0,
+            0, // block number
+            pg_constants::XLR_BLOCK_ID_DATA_SHORT,
+            3, // main data length
+        ];
+        data.extend_from_slice(&[1, 2, 3]); // block data
+        data.extend_from_slice(&[1, 0, 0x08]); // xl_heap_insert
+        let mut record = XLogRecord {
+            xl_tot_len: (XLOG_SIZE_OF_XLOG_RECORD + data.len()) as u32,
+            xl_xid: 735,
+            xl_prev: 0x016D_59D8,
+            xl_info: pg_constants::XLOG_HEAP_INSERT | pg_constants::XLOG_HEAP_INIT_PAGE,
+            xl_rmid: pg_constants::RM_HEAP_ID,
+            __bindgen_padding_0: [0u8; 2usize],
+            xl_crc: 0,
+        }
+        .encode()
+        .unwrap()
+        .to_vec();
+        record.extend_from_slice(&data);
+
+        let record = WalRecord::decode(Bytes::from(record), 15).unwrap();
+        assert_eq!(record.blocks.len(), 1);
+        let block = &record.blocks[0];
+        assert_eq!(block.rnode.to_string(), "1663/5/16384");
+        assert!(block.will_init && !block.has_image);
+        assert_eq!(&block.data[..], &[1, 2, 3]);
+        assert_eq!(
+            record.rmgr_record().unwrap(),
+            RmgrRecord::Heap(heap::HeapRecord::Insert {
+                init_page: true,
+                rec: heap::XlHeapInsert {
+                    offnum: 1,
+                    flags: 0x08
+                }
+            })
+        );
+        assert_eq!(
+            record.to_string(),
+            "rmgr: Heap, len: 52, tx: 735, prev 0/016D59D8, desc: INSERT+INIT off 1 flags 0x08, \
+             blkref #0: rel 1663/5/16384 fork main blk 0"
+        );
+
+        // Truncated main data is an error rather than a panic
+        let mut main_data = record.main_data.clone();
+        main_data.truncate(2);
+        assert!(RmgrRecord::decode(record.xl_rmid, record.xl_info, &main_data).is_err());
+    }
+
+    #[test]
+    fn decode_relmap_update() {
+        // The map of database 5 after VACUUM FULL pg_class
+        let mut map = crate::relmapper::RelMapFile::default();
+        map.apply_update(1259, 16390, true).unwrap();
+        let mut main_data = vec![];
+        main_data.extend_from_slice(&5u32.to_le_bytes());
+        main_data.extend_from_slice(&pg_constants::DEFAULTTABLESPACE_OID.to_le_bytes());
+        main_data.extend_from_slice(&512i32.to_le_bytes());
+        main_data.extend_from_slice(&map.encode().unwrap());
+        let main_data = Bytes::from(main_data);
+
+        let rec = RmgrRecord::decode(pg_constants::RM_RELMAP_ID, 0, &main_data).unwrap();
+        assert_eq!(
+            rec.to_string(),
+            "UPDATE database 5 tablespace 1663 size 512"
+        );
+        let RmgrRecord::Relmap(relmap::RelmapRecord::Update(xlrec)) = rec else {
+            panic!("not a relmap update");
+        };
+        assert_eq!(xlrec.map_file().unwrap().filenode(1259), Some(16390));
+
+        // The map file is cut short
+        let main_data = main_data.slice(..100);
+        assert!(RmgrRecord::decode(pg_constants::RM_RELMAP_ID, 0, &main_data).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//!
+//! Records of the BRIN resource manager, from brin_xlog.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::{BlockNumber, OffsetNumber};
+
+pub const XLOG_BRIN_CREATE_INDEX: u8 = 0x00;
+pub const XLOG_BRIN_INSERT: u8 = 0x10;
+pub const XLOG_BRIN_UPDATE: u8 = 0x20;
+pub const XLOG_BRIN_SAMEPAGE_UPDATE: u8 = 0x30;
+pub const XLOG_BRIN_REVMAP_EXTEND: u8 = 0x40;
+pub const XLOG_BRIN_DESUMMARIZE: u8 = 0x50;
+
+pub const XLOG_BRIN_OPMASK: u8 = 0x70;
+pub const XLOG_BRIN_INIT_PAGE: u8 = 0x80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBrinInsert {
+    pub heap_blk: BlockNumber,
+    pub pages_per_range: BlockNumber,
+    pub offnum: OffsetNumber,
+}
+
+impl XlBrinInsert {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBrinInsert> {
+        ensure_size(buf, 10, "xl_brin_insert")?;
+        Ok(XlBrinInsert {
+            heap_blk: buf.get_u32_le(),
+            pages_per_range: buf.get_u32_le(),
+            offnum: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBrinDesummarize {
+    pub pages_per_range: BlockNumber,
+    pub heap_blk: BlockNumber,
+    pub reg_offset: OffsetNumber,
+}
+
+impl XlBrinDesummarize {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBrinDesummarize> {
+        ensure_size(buf, 10, "xl_brin_desummarize")?;
+        Ok(XlBrinDesummarize {
+            pages_per_range: buf.get_u32_le(),
+            heap_blk: buf.get_u32_le(),
+            reg_offset: buf.get_u16_le(),
+        })
+    }
+}
+
+/// The BRIN records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrinRecord {
+    CreateIndex {
+        pages_per_range: BlockNumber,
+        version: u16,
+    },
+    Insert {
+        init_page: bool,
+        rec: XlBrinInsert,
+    },
+    Update {
+        init_page: bool,
+        old_offnum: OffsetNumber,
+        rec: XlBrinInsert,
+    },
+    SamepageUpdate {
+        offnum: OffsetNumber,
+    },
+    RevmapExtend {
+        target_blk: BlockNumber,
+    },
+    Desummarize(XlBrinDesummarize),
+}
+
+impl BrinRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<BrinRecord> {
+        let init_page = info & XLOG_BRIN_INIT_PAGE != 0;
+        let rec = match info & XLOG_BRIN_OPMASK {
+            XLOG_BRIN_CREATE_INDEX => {
+                ensure_size(buf, 6, "xl_brin_createidx")?;
+                BrinRecord::CreateIndex {
+                    pages_per_range: buf.get_u32_le(),
+                    version: buf.get_u16_le(),
+                }
+            }
+            XLOG_BRIN_INSERT => BrinRecord::Insert {
+                init_page,
+                rec: XlBrinInsert::decode(buf)?,
+            },
+            XLOG_BRIN_UPDATE => {
+                ensure_size(buf, 4, "xl_brin_update")?;
+                let old_offnum = buf.get_u16_le();
+                buf.advance(2); // padding
+                BrinRecord::Update {
+                    init_page,
+                    old_offnum,
+                    rec: XlBrinInsert::decode(buf)?,
+                }
+            }
+            XLOG_BRIN_SAMEPAGE_UPDATE => {
+                ensure_size(buf, 2, "xl_brin_samepage_update")?;
+                BrinRecord::SamepageUpdate {
+                    offnum: buf.get_u16_le(),
+                }
+            }
+            XLOG_BRIN_REVMAP_EXTEND => {
+                ensure_size(buf, 4, "xl_brin_revmap_extend")?;
+                BrinRecord::RevmapExtend {
+                    target_blk: buf.get_u32_le(),
+                }
+            }
+            XLOG_BRIN_DESUMMARIZE => BrinRecord::Desummarize(XlBrinDesummarize::decode(buf)?),
+            op => bail!("unknown brin record 0x{op:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for BrinRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let init = |init_page: bool| if init_page { "+INIT" } else { "" };
+        match self {
+            BrinRecord::CreateIndex {
+                pages_per_range,
+                version,
+            } => write!(f, "CREATE_INDEX v{version} pagesPerRange {pages_per_range}"),
+            BrinRecord::Insert { init_page, rec } => write!(
+                f,
+                "INSERT{} heapBlk {} pagesPerRange {} offnum {}",
+                init(*init_page),
+                rec.heap_blk,
+                rec.pages_per_range,
+                rec.offnum
+            ),
+            BrinRecord::Update {
+                init_page,
+                old_offnum,
+                rec,
+            } => write!(
+                f,
+                "UPDATE{} heapBlk {} pagesPerRange {} old offnum {}, new offnum {}",
+                init(*init_page),
+                rec.heap_blk,
+                rec.pages_per_range,
+                old_offnum,
+                rec.offnum
+            ),
+            BrinRecord::SamepageUpdate { offnum } => {
+                write!(f, "SAMEPAGE_UPDATE offnum {offnum}")
+            }
+            BrinRecord::RevmapExtend { target_blk } => {
+                write!(f, "REVMAP_EXTEND targetBlk {target_blk}")
+            }
+            BrinRecord::Desummarize(rec) => write!(
+                f,
+                "DESUMMARIZE pagesPerRange {}, heapBlk {}, page offset {}",
+                rec.pages_per_range, rec.heap_blk, rec.reg_offset
+            ),
+        }
+    }
+}
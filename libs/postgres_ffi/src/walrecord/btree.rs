@@ -0,0 +1,295 @@
+//!
+//! Records of the B-tree resource manager, from nbtxlog.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::{ensure_size, FullTransactionId, RelFileNode};
+use crate::{BlockNumber, OffsetNumber, TransactionId};
+
+pub const XLOG_BTREE_INSERT_LEAF: u8 = 0x00;
+pub const XLOG_BTREE_INSERT_UPPER: u8 = 0x10;
+pub const XLOG_BTREE_INSERT_META: u8 = 0x20;
+pub const XLOG_BTREE_SPLIT_L: u8 = 0x30;
+pub const XLOG_BTREE_SPLIT_R: u8 = 0x40;
+pub const XLOG_BTREE_INSERT_POST: u8 = 0x50;
+pub const XLOG_BTREE_DEDUP: u8 = 0x60;
+pub const XLOG_BTREE_DELETE: u8 = 0x70;
+pub const XLOG_BTREE_UNLINK_PAGE: u8 = 0x80;
+pub const XLOG_BTREE_UNLINK_PAGE_META: u8 = 0x90;
+pub const XLOG_BTREE_NEWROOT: u8 = 0xA0;
+pub const XLOG_BTREE_MARK_PAGE_HALFDEAD: u8 = 0xB0;
+pub const XLOG_BTREE_VACUUM: u8 = 0xC0;
+pub const XLOG_BTREE_REUSE_PAGE: u8 = 0xD0;
+pub const XLOG_BTREE_META_CLEANUP: u8 = 0xE0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBtreeSplit {
+    pub level: u32,
+    pub firstrightoff: OffsetNumber,
+    pub newitemoff: OffsetNumber,
+    pub postingoff: u16,
+}
+
+impl XlBtreeSplit {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBtreeSplit> {
+        ensure_size(buf, 10, "xl_btree_split")?;
+        Ok(XlBtreeSplit {
+            level: buf.get_u32_le(),
+            firstrightoff: buf.get_u16_le(),
+            newitemoff: buf.get_u16_le(),
+            postingoff: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBtreeDelete {
+    pub latest_removed_xid: TransactionId,
+    pub ndeleted: u16,
+    pub nupdated: u16,
+}
+
+impl XlBtreeDelete {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBtreeDelete> {
+        ensure_size(buf, 8, "xl_btree_delete")?;
+        Ok(XlBtreeDelete {
+            latest_removed_xid: buf.get_u32_le(),
+            ndeleted: buf.get_u16_le(),
+            nupdated: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBtreeMarkPageHalfdead {
+    pub poffset: OffsetNumber,
+    pub leafblk: BlockNumber,
+    pub leftblk: BlockNumber,
+    pub rightblk: BlockNumber,
+    pub topparent: BlockNumber,
+}
+
+impl XlBtreeMarkPageHalfdead {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBtreeMarkPageHalfdead> {
+        ensure_size(buf, 20, "xl_btree_mark_page_halfdead")?;
+        let poffset = buf.get_u16_le();
+        buf.advance(2); // padding
+        Ok(XlBtreeMarkPageHalfdead {
+            poffset,
+            leafblk: buf.get_u32_le(),
+            leftblk: buf.get_u32_le(),
+            rightblk: buf.get_u32_le(),
+            topparent: buf.get_u32_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBtreeUnlinkPage {
+    pub leftsib: BlockNumber,
+    pub rightsib: BlockNumber,
+    pub level: u32,
+    pub safexid: FullTransactionId,
+    pub leafleftsib: BlockNumber,
+    pub leafrightsib: BlockNumber,
+    pub leaftopparent: BlockNumber,
+}
+
+impl XlBtreeUnlinkPage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBtreeUnlinkPage> {
+        ensure_size(buf, 36, "xl_btree_unlink_page")?;
+        let leftsib = buf.get_u32_le();
+        let rightsib = buf.get_u32_le();
+        let level = buf.get_u32_le();
+        buf.advance(4); // padding
+        Ok(XlBtreeUnlinkPage {
+            leftsib,
+            rightsib,
+            level,
+            safexid: FullTransactionId(buf.get_u64_le()),
+            leafleftsib: buf.get_u32_le(),
+            leafrightsib: buf.get_u32_le(),
+            leaftopparent: buf.get_u32_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlBtreeReusePage {
+    pub node: RelFileNode,
+    pub block: BlockNumber,
+    pub latest_removed_full_xid: FullTransactionId,
+}
+
+impl XlBtreeReusePage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlBtreeReusePage> {
+        ensure_size(buf, 24, "xl_btree_reuse_page")?;
+        Ok(XlBtreeReusePage {
+            node: RelFileNode::decode(buf),
+            block: buf.get_u32_le(),
+            latest_removed_full_xid: FullTransactionId(buf.get_u64_le()),
+        })
+    }
+}
+
+/// Decodes the records that are a single OffsetNumber or uint16.
+fn decode_u16(buf: &mut Bytes, name: &str) -> Result<u16> {
+    ensure_size(buf, 2, name)?;
+    Ok(buf.get_u16_le())
+}
+
+/// The B-tree records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BtreeRecord {
+    InsertLeaf {
+        offnum: OffsetNumber,
+    },
+    InsertUpper {
+        offnum: OffsetNumber,
+    },
+    InsertMeta {
+        offnum: OffsetNumber,
+    },
+    InsertPost {
+        offnum: OffsetNumber,
+    },
+    Split {
+        right: bool,
+        rec: XlBtreeSplit,
+    },
+    Dedup {
+        nintervals: u16,
+    },
+    Delete(XlBtreeDelete),
+    MarkPageHalfdead(XlBtreeMarkPageHalfdead),
+    UnlinkPage {
+        meta: bool,
+        rec: XlBtreeUnlinkPage,
+    },
+    NewRoot {
+        rootblk: BlockNumber,
+        level: u32,
+    },
+    Vacuum {
+        ndeleted: u16,
+        nupdated: u16,
+    },
+    ReusePage(XlBtreeReusePage),
+    /// The metapage data is in the data of the block, not in the main data.
+    MetaCleanup,
+}
+
+impl BtreeRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<BtreeRecord> {
+        let rec = match info {
+            XLOG_BTREE_INSERT_LEAF => BtreeRecord::InsertLeaf {
+                offnum: decode_u16(buf, "xl_btree_insert")?,
+            },
+            XLOG_BTREE_INSERT_UPPER => BtreeRecord::InsertUpper {
+                offnum: decode_u16(buf, "xl_btree_insert")?,
+            },
+            XLOG_BTREE_INSERT_META => BtreeRecord::InsertMeta {
+                offnum: decode_u16(buf, "xl_btree_insert")?,
+            },
+            XLOG_BTREE_INSERT_POST => BtreeRecord::InsertPost {
+                offnum: decode_u16(buf, "xl_btree_insert")?,
+            },
+            XLOG_BTREE_SPLIT_L | XLOG_BTREE_SPLIT_R => BtreeRecord::Split {
+                right: info == XLOG_BTREE_SPLIT_R,
+                rec: XlBtreeSplit::decode(buf)?,
+            },
+            XLOG_BTREE_DEDUP => BtreeRecord::Dedup {
+                nintervals: decode_u16(buf, "xl_btree_dedup")?,
+            },
+            XLOG_BTREE_DELETE => BtreeRecord::Delete(XlBtreeDelete::decode(buf)?),
+            XLOG_BTREE_MARK_PAGE_HALFDEAD => {
+                BtreeRecord::MarkPageHalfdead(XlBtreeMarkPageHalfdead::decode(buf)?)
+            }
+            XLOG_BTREE_UNLINK_PAGE | XLOG_BTREE_UNLINK_PAGE_META => BtreeRecord::UnlinkPage {
+                meta: info == XLOG_BTREE_UNLINK_PAGE_META,
+                rec: XlBtreeUnlinkPage::decode(buf)?,
+            },
+            XLOG_BTREE_NEWROOT => {
+                ensure_size(buf, 8, "xl_btree_newroot")?;
+                BtreeRecord::NewRoot {
+                    rootblk: buf.get_u32_le(),
+                    level: buf.get_u32_le(),
+                }
+            }
+            XLOG_BTREE_VACUUM => {
+                ensure_size(buf, 4, "xl_btree_vacuum")?;
+                BtreeRecord::Vacuum {
+                    ndeleted: buf.get_u16_le(),
+                    nupdated: buf.get_u16_le(),
+                }
+            }
+            XLOG_BTREE_REUSE_PAGE => BtreeRecord::ReusePage(XlBtreeReusePage::decode(buf)?),
+            XLOG_BTREE_META_CLEANUP => BtreeRecord::MetaCleanup,
+            _ => bail!("unknown btree record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for BtreeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BtreeRecord::InsertLeaf { offnum } => write!(f, "INSERT_LEAF off {offnum}"),
+            BtreeRecord::InsertUpper { offnum } => write!(f, "INSERT_UPPER off {offnum}"),
+            BtreeRecord::InsertMeta { offnum } => write!(f, "INSERT_META off {offnum}"),
+            BtreeRecord::InsertPost { offnum } => write!(f, "INSERT_POST off {offnum}"),
+            BtreeRecord::Split { right, rec } => write!(
+                f,
+                "{} level {}, firstrightoff {}, newitemoff {}, postingoff {}",
+                if *right { "SPLIT_R" } else { "SPLIT_L" },
+                rec.level,
+                rec.firstrightoff,
+                rec.newitemoff,
+                rec.postingoff
+            ),
+            BtreeRecord::Dedup { nintervals } => write!(f, "DEDUP nintervals {nintervals}"),
+            BtreeRecord::Delete(rec) => write!(
+                f,
+                "DELETE latestRemovedXid {}; ndeleted {}; nupdated {}",
+                rec.latest_removed_xid, rec.ndeleted, rec.nupdated
+            ),
+            BtreeRecord::MarkPageHalfdead(rec) => write!(
+                f,
+                "MARK_PAGE_HALFDEAD topparent {}; leaf {}; left {}; right {}",
+                rec.topparent, rec.leafblk, rec.leftblk, rec.rightblk
+            ),
+            BtreeRecord::UnlinkPage { meta, rec } => write!(
+                f,
+                "{} left {}; right {}; level {}; safexid {}; leafleft {}; leafright {}; \
+                 leaftopparent {}",
+                if *meta {
+                    "UNLINK_PAGE_META"
+                } else {
+                    "UNLINK_PAGE"
+                },
+                rec.leftsib,
+                rec.rightsib,
+                rec.level,
+                rec.safexid,
+                rec.leafleftsib,
+                rec.leafrightsib,
+                rec.leaftopparent
+            ),
+            BtreeRecord::NewRoot { rootblk, level } => {
+                write!(f, "NEWROOT lev {level} root {rootblk}")
+            }
+            BtreeRecord::Vacuum { ndeleted, nupdated } => {
+                write!(f, "VACUUM ndeleted {ndeleted}; nupdated {nupdated}")
+            }
+            BtreeRecord::ReusePage(rec) => write!(
+                f,
+                "REUSE_PAGE rel {}; latestRemovedXid {}",
+                rec.node, rec.latest_removed_full_xid
+            ),
+            BtreeRecord::MetaCleanup => write!(f, "META_CLEANUP"),
+        }
+    }
+}
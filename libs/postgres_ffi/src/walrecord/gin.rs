@@ -0,0 +1,182 @@
+//!
+//! Records of the GIN resource manager, from ginxlog.h.
+//!
+//! Most of them keep their contents in the data of their blocks, only the
+//! fields in the main data are decoded.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::{ensure_size, RelFileNode};
+use crate::{BlockNumber, OffsetNumber, TransactionId};
+
+pub const XLOG_GIN_CREATE_PTREE: u8 = 0x10;
+pub const XLOG_GIN_INSERT: u8 = 0x20;
+pub const XLOG_GIN_SPLIT: u8 = 0x30;
+pub const XLOG_GIN_VACUUM_PAGE: u8 = 0x40;
+pub const XLOG_GIN_DELETE_PAGE: u8 = 0x50;
+pub const XLOG_GIN_UPDATE_META_PAGE: u8 = 0x60;
+pub const XLOG_GIN_INSERT_LISTPAGE: u8 = 0x70;
+pub const XLOG_GIN_DELETE_LISTPAGE: u8 = 0x80;
+pub const XLOG_GIN_VACUUM_DATA_LEAF_PAGE: u8 = 0x90;
+
+/* Flags of ginxlogInsert and ginxlogSplit */
+pub const GIN_INSERT_ISDATA: u16 = 0x01;
+pub const GIN_INSERT_ISLEAF: u16 = 0x02;
+pub const GIN_SPLIT_ROOT: u16 = 0x04;
+
+/// Size of GinMetaPageData, which ginxlogUpdateMeta and ginxlogDeleteListPages
+/// start with.
+const SIZE_OF_GIN_META_PAGE_DATA: usize = 56;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GinXlogSplit {
+    pub node: RelFileNode,
+    pub rrlink: BlockNumber,
+    pub left_child_blkno: BlockNumber,
+    pub right_child_blkno: BlockNumber,
+    pub flags: u16,
+}
+
+impl GinXlogSplit {
+    pub fn decode(buf: &mut Bytes) -> Result<GinXlogSplit> {
+        ensure_size(buf, 26, "ginxlogSplit")?;
+        Ok(GinXlogSplit {
+            node: RelFileNode::decode(buf),
+            rrlink: buf.get_u32_le(),
+            left_child_blkno: buf.get_u32_le(),
+            right_child_blkno: buf.get_u32_le(),
+            flags: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GinXlogDeletePage {
+    pub parent_offset: OffsetNumber,
+    pub right_link: BlockNumber,
+    pub delete_xid: TransactionId,
+}
+
+impl GinXlogDeletePage {
+    pub fn decode(buf: &mut Bytes) -> Result<GinXlogDeletePage> {
+        ensure_size(buf, 12, "ginxlogDeletePage")?;
+        let parent_offset = buf.get_u16_le();
+        buf.advance(2); // padding
+        Ok(GinXlogDeletePage {
+            parent_offset,
+            right_link: buf.get_u32_le(),
+            delete_xid: buf.get_u32_le(),
+        })
+    }
+}
+
+/// The GIN records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GinRecord {
+    CreatePostingTree { size: u32 },
+    Insert { flags: u16 },
+    Split(GinXlogSplit),
+    VacuumPage,
+    VacuumDataLeafPage,
+    DeletePage(GinXlogDeletePage),
+    UpdateMetaPage { node: RelFileNode },
+    InsertListPage,
+    DeleteListPage { ndeleted: i32 },
+}
+
+impl GinRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<GinRecord> {
+        let rec = match info {
+            XLOG_GIN_CREATE_PTREE => {
+                ensure_size(buf, 4, "ginxlogCreatePostingTree")?;
+                GinRecord::CreatePostingTree {
+                    size: buf.get_u32_le(),
+                }
+            }
+            XLOG_GIN_INSERT => {
+                ensure_size(buf, 2, "ginxlogInsert")?;
+                GinRecord::Insert {
+                    flags: buf.get_u16_le(),
+                }
+            }
+            XLOG_GIN_SPLIT => GinRecord::Split(GinXlogSplit::decode(buf)?),
+            XLOG_GIN_VACUUM_PAGE => GinRecord::VacuumPage,
+            XLOG_GIN_VACUUM_DATA_LEAF_PAGE => GinRecord::VacuumDataLeafPage,
+            XLOG_GIN_DELETE_PAGE => GinRecord::DeletePage(GinXlogDeletePage::decode(buf)?),
+            XLOG_GIN_UPDATE_META_PAGE => {
+                ensure_size(buf, RelFileNode::SIZE, "ginxlogUpdateMeta")?;
+                GinRecord::UpdateMetaPage {
+                    node: RelFileNode::decode(buf),
+                }
+            }
+            XLOG_GIN_INSERT_LISTPAGE => GinRecord::InsertListPage,
+            XLOG_GIN_DELETE_LISTPAGE => {
+                ensure_size(
+                    buf,
+                    SIZE_OF_GIN_META_PAGE_DATA + 4,
+                    "ginxlogDeleteListPages",
+                )?;
+                buf.advance(SIZE_OF_GIN_META_PAGE_DATA);
+                GinRecord::DeleteListPage {
+                    ndeleted: buf.get_i32_le(),
+                }
+            }
+            _ => bail!("unknown gin record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+fn fmt_flags(f: &mut fmt::Formatter<'_>, flags: u16) -> fmt::Result {
+    let fmt_bool = |b| if b { 'T' } else { 'F' };
+    write!(
+        f,
+        "isdata: {} isleaf: {}",
+        fmt_bool(flags & GIN_INSERT_ISDATA != 0),
+        fmt_bool(flags & GIN_INSERT_ISLEAF != 0)
+    )
+}
+
+impl fmt::Display for GinRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GinRecord::CreatePostingTree { size } => write!(f, "CREATE_PTREE size: {size}"),
+            GinRecord::Insert { flags } => {
+                write!(f, "INSERT ")?;
+                fmt_flags(f, *flags)
+            }
+            GinRecord::Split(rec) => {
+                write!(
+                    f,
+                    "SPLIT isrootsplit: {} ",
+                    if rec.flags & GIN_SPLIT_ROOT != 0 {
+                        'T'
+                    } else {
+                        'F'
+                    }
+                )?;
+                fmt_flags(f, rec.flags)?;
+                write!(
+                    f,
+                    " left: {} right: {} rrlink: {}",
+                    rec.left_child_blkno, rec.right_child_blkno, rec.rrlink
+                )
+            }
+            GinRecord::VacuumPage => write!(f, "VACUUM_PAGE"),
+            GinRecord::VacuumDataLeafPage => write!(f, "VACUUM_DATA_LEAF_PAGE"),
+            GinRecord::DeletePage(rec) => write!(
+                f,
+                "DELETE_PAGE parentoff: {} rightlink: {} deletexid: {}",
+                rec.parent_offset, rec.right_link, rec.delete_xid
+            ),
+            GinRecord::UpdateMetaPage { node } => write!(f, "UPDATE_META_PAGE rel: {node}"),
+            GinRecord::InsertListPage => write!(f, "INSERT_LISTPAGE"),
+            GinRecord::DeleteListPage { ndeleted } => {
+                write!(f, "DELETE_LISTPAGE ndeleted: {ndeleted}")
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//!
+//! Records of the GiST resource manager, from gistxlog.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::{ensure_size, FullTransactionId, RelFileNode};
+use crate::{BlockNumber, OffsetNumber, TransactionId, XLogRecPtr};
+
+pub const XLOG_GIST_PAGE_UPDATE: u8 = 0x00;
+pub const XLOG_GIST_DELETE: u8 = 0x10;
+pub const XLOG_GIST_PAGE_REUSE: u8 = 0x20;
+pub const XLOG_GIST_PAGE_SPLIT: u8 = 0x30;
+pub const XLOG_GIST_PAGE_DELETE: u8 = 0x60;
+pub const XLOG_GIST_ASSIGN_LSN: u8 = 0x70;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GistXlogPageSplit {
+    pub origrlink: BlockNumber,
+    pub orignsn: XLogRecPtr,
+    pub origleaf: bool,
+    pub npage: u16,
+    pub markfollowright: bool,
+}
+
+impl GistXlogPageSplit {
+    pub fn decode(buf: &mut Bytes) -> Result<GistXlogPageSplit> {
+        ensure_size(buf, 21, "gistxlogPageSplit")?;
+        let origrlink = buf.get_u32_le();
+        buf.advance(4); // padding
+        let orignsn = buf.get_u64_le();
+        let origleaf = buf.get_u8() != 0;
+        buf.advance(1); // padding
+        Ok(GistXlogPageSplit {
+            origrlink,
+            orignsn,
+            origleaf,
+            npage: buf.get_u16_le(),
+            markfollowright: buf.get_u8() != 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GistXlogPageReuse {
+    pub node: RelFileNode,
+    pub block: BlockNumber,
+    pub latest_removed_full_xid: FullTransactionId,
+}
+
+impl GistXlogPageReuse {
+    pub fn decode(buf: &mut Bytes) -> Result<GistXlogPageReuse> {
+        ensure_size(buf, 24, "gistxlogPageReuse")?;
+        Ok(GistXlogPageReuse {
+            node: RelFileNode::decode(buf),
+            block: buf.get_u32_le(),
+            latest_removed_full_xid: FullTransactionId(buf.get_u64_le()),
+        })
+    }
+}
+
+/// The GiST records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GistRecord {
+    PageUpdate {
+        ntodelete: u16,
+        ntoinsert: u16,
+    },
+    Delete {
+        latest_removed_xid: TransactionId,
+        ntodelete: u16,
+    },
+    PageReuse(GistXlogPageReuse),
+    PageSplit(GistXlogPageSplit),
+    PageDelete {
+        delete_xid: FullTransactionId,
+        downlink_offset: OffsetNumber,
+    },
+    AssignLsn,
+}
+
+impl GistRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<GistRecord> {
+        let rec = match info {
+            XLOG_GIST_PAGE_UPDATE => {
+                ensure_size(buf, 4, "gistxlogPageUpdate")?;
+                GistRecord::PageUpdate {
+                    ntodelete: buf.get_u16_le(),
+                    ntoinsert: buf.get_u16_le(),
+                }
+            }
+            XLOG_GIST_DELETE => {
+                ensure_size(buf, 6, "gistxlogDelete")?;
+                GistRecord::Delete {
+                    latest_removed_xid: buf.get_u32_le(),
+                    ntodelete: buf.get_u16_le(),
+                }
+            }
+            XLOG_GIST_PAGE_REUSE => GistRecord::PageReuse(GistXlogPageReuse::decode(buf)?),
+            XLOG_GIST_PAGE_SPLIT => GistRecord::PageSplit(GistXlogPageSplit::decode(buf)?),
+            XLOG_GIST_PAGE_DELETE => {
+                ensure_size(buf, 10, "gistxlogPageDelete")?;
+                GistRecord::PageDelete {
+                    delete_xid: FullTransactionId(buf.get_u64_le()),
+                    downlink_offset: buf.get_u16_le(),
+                }
+            }
+            XLOG_GIST_ASSIGN_LSN => GistRecord::AssignLsn,
+            _ => bail!("unknown gist record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for GistRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GistRecord::PageUpdate {
+                ntodelete,
+                ntoinsert,
+            } => write!(f, "PAGE_UPDATE ntodelete {ntodelete} ntoinsert {ntoinsert}"),
+            GistRecord::Delete {
+                latest_removed_xid,
+                ntodelete,
+            } => write!(
+                f,
+                "DELETE delete: latestRemovedXid {latest_removed_xid}, nitems: {ntodelete}"
+            ),
+            GistRecord::PageReuse(rec) => write!(
+                f,
+                "PAGE_REUSE rel {}; blk {}; latestRemovedXid {}",
+                rec.node, rec.block, rec.latest_removed_full_xid
+            ),
+            GistRecord::PageSplit(rec) => {
+                write!(f, "PAGE_SPLIT page_split: splits to {} pages", rec.npage)
+            }
+            GistRecord::PageDelete {
+                delete_xid,
+                downlink_offset,
+            } => write!(
+                f,
+                "PAGE_DELETE deleteXid {delete_xid}; downlink {downlink_offset}"
+            ),
+            GistRecord::AssignLsn => write!(f, "ASSIGN_LSN"),
+        }
+    }
+}
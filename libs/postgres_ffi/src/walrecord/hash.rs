@@ -0,0 +1,274 @@
+//!
+//! Records of the hash index resource manager, from hash_xlog.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::{BlockNumber, OffsetNumber, Oid, TransactionId};
+
+pub const XLOG_HASH_INIT_META_PAGE: u8 = 0x00;
+pub const XLOG_HASH_INIT_BITMAP_PAGE: u8 = 0x10;
+pub const XLOG_HASH_INSERT: u8 = 0x20;
+pub const XLOG_HASH_ADD_OVFL_PAGE: u8 = 0x30;
+pub const XLOG_HASH_SPLIT_ALLOCATE_PAGE: u8 = 0x40;
+pub const XLOG_HASH_SPLIT_PAGE: u8 = 0x50;
+pub const XLOG_HASH_SPLIT_COMPLETE: u8 = 0x60;
+pub const XLOG_HASH_MOVE_PAGE_CONTENTS: u8 = 0x70;
+pub const XLOG_HASH_SQUEEZE_PAGE: u8 = 0x80;
+pub const XLOG_HASH_DELETE: u8 = 0x90;
+pub const XLOG_HASH_SPLIT_CLEANUP: u8 = 0xA0;
+pub const XLOG_HASH_UPDATE_META_PAGE: u8 = 0xB0;
+pub const XLOG_HASH_VACUUM_ONE_PAGE: u8 = 0xC0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XlHashInitMetaPage {
+    pub num_tuples: f64,
+    pub procid: Oid,
+    pub ffactor: u16,
+}
+
+impl XlHashInitMetaPage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHashInitMetaPage> {
+        ensure_size(buf, 14, "xl_hash_init_meta_page")?;
+        Ok(XlHashInitMetaPage {
+            num_tuples: buf.get_f64_le(),
+            procid: buf.get_u32_le(),
+            ffactor: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHashSplitAllocatePage {
+    pub new_bucket: u32,
+    pub old_bucket_flag: u16,
+    pub new_bucket_flag: u16,
+    pub flags: u8,
+}
+
+impl XlHashSplitAllocatePage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHashSplitAllocatePage> {
+        ensure_size(buf, 9, "xl_hash_split_allocate_page")?;
+        Ok(XlHashSplitAllocatePage {
+            new_bucket: buf.get_u32_le(),
+            old_bucket_flag: buf.get_u16_le(),
+            new_bucket_flag: buf.get_u16_le(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHashSqueezePage {
+    pub prevblkno: BlockNumber,
+    pub nextblkno: BlockNumber,
+    pub ntups: u16,
+    pub is_prim_bucket_same_wrt: bool,
+    pub is_prev_bucket_same_wrt: bool,
+}
+
+impl XlHashSqueezePage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHashSqueezePage> {
+        ensure_size(buf, 12, "xl_hash_squeeze_page")?;
+        Ok(XlHashSqueezePage {
+            prevblkno: buf.get_u32_le(),
+            nextblkno: buf.get_u32_le(),
+            ntups: buf.get_u16_le(),
+            is_prim_bucket_same_wrt: buf.get_u8() != 0,
+            is_prev_bucket_same_wrt: buf.get_u8() != 0,
+        })
+    }
+}
+
+/// The hash index records, by their info.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashRecord {
+    InitMetaPage(XlHashInitMetaPage),
+    InitBitmapPage {
+        bmsize: u16,
+    },
+    Insert {
+        offnum: OffsetNumber,
+    },
+    AddOvflPage {
+        bmsize: u16,
+        bmpage_found: bool,
+    },
+    SplitAllocatePage(XlHashSplitAllocatePage),
+    SplitPage,
+    SplitComplete {
+        old_bucket_flag: u16,
+        new_bucket_flag: u16,
+    },
+    MovePageContents {
+        ntups: u16,
+        is_prim_bucket_same_wrt: bool,
+    },
+    SqueezePage(XlHashSqueezePage),
+    Delete {
+        clear_dead_marking: bool,
+        is_primary_bucket_page: bool,
+    },
+    SplitCleanup,
+    UpdateMetaPage {
+        ntuples: f64,
+    },
+    VacuumOnePage {
+        latest_removed_xid: TransactionId,
+        ntuples: i32,
+    },
+}
+
+impl HashRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<HashRecord> {
+        let rec = match info {
+            XLOG_HASH_INIT_META_PAGE => HashRecord::InitMetaPage(XlHashInitMetaPage::decode(buf)?),
+            XLOG_HASH_INIT_BITMAP_PAGE => {
+                ensure_size(buf, 2, "xl_hash_init_bitmap_page")?;
+                HashRecord::InitBitmapPage {
+                    bmsize: buf.get_u16_le(),
+                }
+            }
+            XLOG_HASH_INSERT => {
+                ensure_size(buf, 2, "xl_hash_insert")?;
+                HashRecord::Insert {
+                    offnum: buf.get_u16_le(),
+                }
+            }
+            XLOG_HASH_ADD_OVFL_PAGE => {
+                ensure_size(buf, 3, "xl_hash_add_ovfl_page")?;
+                HashRecord::AddOvflPage {
+                    bmsize: buf.get_u16_le(),
+                    bmpage_found: buf.get_u8() != 0,
+                }
+            }
+            XLOG_HASH_SPLIT_ALLOCATE_PAGE => {
+                HashRecord::SplitAllocatePage(XlHashSplitAllocatePage::decode(buf)?)
+            }
+            XLOG_HASH_SPLIT_PAGE => HashRecord::SplitPage,
+            XLOG_HASH_SPLIT_COMPLETE => {
+                ensure_size(buf, 4, "xl_hash_split_complete")?;
+                HashRecord::SplitComplete {
+                    old_bucket_flag: buf.get_u16_le(),
+                    new_bucket_flag: buf.get_u16_le(),
+                }
+            }
+            XLOG_HASH_MOVE_PAGE_CONTENTS => {
+                ensure_size(buf, 3, "xl_hash_move_page_contents")?;
+                HashRecord::MovePageContents {
+                    ntups: buf.get_u16_le(),
+                    is_prim_bucket_same_wrt: buf.get_u8() != 0,
+                }
+            }
+            XLOG_HASH_SQUEEZE_PAGE => HashRecord::SqueezePage(XlHashSqueezePage::decode(buf)?),
+            XLOG_HASH_DELETE => {
+                ensure_size(buf, 2, "xl_hash_delete")?;
+                HashRecord::Delete {
+                    clear_dead_marking: buf.get_u8() != 0,
+                    is_primary_bucket_page: buf.get_u8() != 0,
+                }
+            }
+            XLOG_HASH_SPLIT_CLEANUP => HashRecord::SplitCleanup,
+            XLOG_HASH_UPDATE_META_PAGE => {
+                ensure_size(buf, 8, "xl_hash_update_meta_page")?;
+                HashRecord::UpdateMetaPage {
+                    ntuples: buf.get_f64_le(),
+                }
+            }
+            XLOG_HASH_VACUUM_ONE_PAGE => {
+                ensure_size(buf, 8, "xl_hash_vacuum_one_page")?;
+                HashRecord::VacuumOnePage {
+                    latest_removed_xid: buf.get_u32_le(),
+                    ntuples: buf.get_i32_le(),
+                }
+            }
+            _ => bail!("unknown hash record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+fn fmt_bool(b: bool) -> char {
+    if b {
+        'T'
+    } else {
+        'F'
+    }
+}
+
+impl fmt::Display for HashRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashRecord::InitMetaPage(rec) => write!(
+                f,
+                "INIT_META_PAGE num_tuples {}, procid {}, ffactor {}",
+                rec.num_tuples, rec.procid, rec.ffactor
+            ),
+            HashRecord::InitBitmapPage { bmsize } => write!(f, "INIT_BITMAP_PAGE bmsize {bmsize}"),
+            HashRecord::Insert { offnum } => write!(f, "INSERT off {offnum}"),
+            HashRecord::AddOvflPage {
+                bmsize,
+                bmpage_found,
+            } => write!(
+                f,
+                "ADD_OVFL_PAGE bmsize {bmsize}, bmpage_found {}",
+                fmt_bool(*bmpage_found)
+            ),
+            HashRecord::SplitAllocatePage(rec) => write!(
+                f,
+                "SPLIT_ALLOCATE_PAGE new_bucket {}, meta_page_masks_updated {}, \
+                 issplitpoint_changed {}",
+                rec.new_bucket,
+                fmt_bool(rec.flags & 0x01 != 0),
+                fmt_bool(rec.flags & 0x02 != 0)
+            ),
+            HashRecord::SplitPage => write!(f, "SPLIT_PAGE"),
+            HashRecord::SplitComplete {
+                old_bucket_flag,
+                new_bucket_flag,
+            } => write!(
+                f,
+                "SPLIT_COMPLETE old_bucket_flag {old_bucket_flag}, new_bucket_flag {new_bucket_flag}"
+            ),
+            HashRecord::MovePageContents {
+                ntups,
+                is_prim_bucket_same_wrt,
+            } => write!(
+                f,
+                "MOVE_PAGE_CONTENTS ntups {ntups}, is_primary {}",
+                fmt_bool(*is_prim_bucket_same_wrt)
+            ),
+            HashRecord::SqueezePage(rec) => write!(
+                f,
+                "SQUEEZE_PAGE prevblkno {}, nextblkno {}, ntups {}, is_primary {}",
+                rec.prevblkno,
+                rec.nextblkno,
+                rec.ntups,
+                fmt_bool(rec.is_prim_bucket_same_wrt)
+            ),
+            HashRecord::Delete {
+                clear_dead_marking,
+                is_primary_bucket_page,
+            } => write!(
+                f,
+                "DELETE clear_dead_marking {}, is_primary {}",
+                fmt_bool(*clear_dead_marking),
+                fmt_bool(*is_primary_bucket_page)
+            ),
+            HashRecord::SplitCleanup => write!(f, "SPLIT_CLEANUP"),
+            HashRecord::UpdateMetaPage { ntuples } => {
+                write!(f, "UPDATE_META_PAGE ntuples {ntuples}")
+            }
+            HashRecord::VacuumOnePage {
+                latest_removed_xid,
+                ntuples,
+            } => write!(
+                f,
+                "VACUUM_ONE_PAGE ntuples {ntuples}, latestRemovedXid {latest_removed_xid}"
+            ),
+        }
+    }
+}
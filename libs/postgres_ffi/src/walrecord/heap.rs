@@ -0,0 +1,539 @@
+//!
+//! Records of the heap and heap2 resource managers, from heapam_xlog.h.
+//!
+//! xl_heap_delete, xl_heap_update and xl_heap_lock have the t_cid field that
+//! Neon's PostgreSQL adds to them.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::{ensure_size, RelFileNode};
+use crate::pg_constants;
+use crate::{BlockNumber, OffsetNumber, Oid, TransactionId, XLogRecPtr};
+
+/* Infomask bits of xl_heap_delete, xl_heap_update and xl_heap_lock */
+pub const XLHL_XMAX_IS_MULTI: u8 = 0x01;
+pub const XLHL_XMAX_LOCK_ONLY: u8 = 0x02;
+pub const XLHL_XMAX_EXCL_LOCK: u8 = 0x04;
+pub const XLHL_XMAX_KEYSHR_LOCK: u8 = 0x08;
+pub const XLHL_KEYS_UPDATED: u8 = 0x10;
+
+/* Flags of xl_heap_truncate */
+pub const XLH_TRUNCATE_CASCADE: u8 = 1 << 0;
+pub const XLH_TRUNCATE_RESTART_SEQS: u8 = 1 << 1;
+
+/// Appends the names of the infomask bits set in `infobits`, like out_infobits
+/// in heapdesc.c.
+fn fmt_infobits(f: &mut fmt::Formatter<'_>, infobits: u8) -> fmt::Result {
+    for (bit, name) in [
+        (XLHL_XMAX_IS_MULTI, "IS_MULTI"),
+        (XLHL_XMAX_LOCK_ONLY, "LOCK_ONLY"),
+        (XLHL_XMAX_EXCL_LOCK, "EXCL_LOCK"),
+        (XLHL_XMAX_KEYSHR_LOCK, "KEYSHR_LOCK"),
+        (XLHL_KEYS_UPDATED, "KEYS_UPDATED"),
+    ] {
+        if infobits & bit != 0 {
+            write!(f, " {name}")?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapInsert {
+    pub offnum: OffsetNumber,
+    pub flags: u8,
+}
+
+impl XlHeapInsert {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapInsert> {
+        ensure_size(buf, 3, "xl_heap_insert")?;
+        Ok(XlHeapInsert {
+            offnum: buf.get_u16_le(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapDelete {
+    pub xmax: TransactionId,
+    pub offnum: OffsetNumber,
+    pub t_cid: u32,
+    pub infobits_set: u8,
+    pub flags: u8,
+}
+
+impl XlHeapDelete {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapDelete> {
+        ensure_size(buf, 14, "xl_heap_delete")?;
+        let xmax = buf.get_u32_le();
+        let offnum = buf.get_u16_le();
+        buf.advance(2); // padding
+        Ok(XlHeapDelete {
+            xmax,
+            offnum,
+            t_cid: buf.get_u32_le(),
+            infobits_set: buf.get_u8(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapUpdate {
+    pub old_xmax: TransactionId,
+    pub old_offnum: OffsetNumber,
+    pub old_infobits_set: u8,
+    pub flags: u8,
+    pub t_cid: u32,
+    pub new_xmax: TransactionId,
+    pub new_offnum: OffsetNumber,
+}
+
+impl XlHeapUpdate {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapUpdate> {
+        ensure_size(buf, 18, "xl_heap_update")?;
+        Ok(XlHeapUpdate {
+            old_xmax: buf.get_u32_le(),
+            old_offnum: buf.get_u16_le(),
+            old_infobits_set: buf.get_u8(),
+            flags: buf.get_u8(),
+            t_cid: buf.get_u32_le(),
+            new_xmax: buf.get_u32_le(),
+            new_offnum: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapTruncate {
+    pub db_id: Oid,
+    pub flags: u8,
+    pub relids: Vec<Oid>,
+}
+
+impl XlHeapTruncate {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapTruncate> {
+        ensure_size(buf, 12, "xl_heap_truncate")?;
+        let db_id = buf.get_u32_le();
+        let nrelids = buf.get_u32_le() as usize;
+        let flags = buf.get_u8();
+        buf.advance(3); // padding
+        ensure_size(buf, nrelids * 4, "xl_heap_truncate relids")?;
+        let relids = (0..nrelids).map(|_| buf.get_u32_le()).collect();
+        Ok(XlHeapTruncate {
+            db_id,
+            flags,
+            relids,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapLock {
+    pub locking_xid: TransactionId,
+    pub offnum: OffsetNumber,
+    pub t_cid: u32,
+    pub infobits_set: u8,
+    pub flags: u8,
+}
+
+impl XlHeapLock {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapLock> {
+        ensure_size(buf, 14, "xl_heap_lock")?;
+        let locking_xid = buf.get_u32_le();
+        let offnum = buf.get_u16_le();
+        buf.advance(2); // padding
+        Ok(XlHeapLock {
+            locking_xid,
+            offnum,
+            t_cid: buf.get_u32_le(),
+            infobits_set: buf.get_u8(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+/// The heap records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeapRecord {
+    Insert {
+        init_page: bool,
+        rec: XlHeapInsert,
+    },
+    Delete(XlHeapDelete),
+    Update {
+        hot: bool,
+        init_page: bool,
+        rec: XlHeapUpdate,
+    },
+    Truncate(XlHeapTruncate),
+    Confirm {
+        offnum: OffsetNumber,
+    },
+    Lock(XlHeapLock),
+    Inplace {
+        offnum: OffsetNumber,
+    },
+}
+
+impl HeapRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<HeapRecord> {
+        let init_page = info & pg_constants::XLOG_HEAP_INIT_PAGE != 0;
+        let rec = match info & pg_constants::XLOG_HEAP_OPMASK {
+            pg_constants::XLOG_HEAP_INSERT => HeapRecord::Insert {
+                init_page,
+                rec: XlHeapInsert::decode(buf)?,
+            },
+            pg_constants::XLOG_HEAP_DELETE => HeapRecord::Delete(XlHeapDelete::decode(buf)?),
+            op @ (pg_constants::XLOG_HEAP_UPDATE | pg_constants::XLOG_HEAP_HOT_UPDATE) => {
+                HeapRecord::Update {
+                    hot: op == pg_constants::XLOG_HEAP_HOT_UPDATE,
+                    init_page,
+                    rec: XlHeapUpdate::decode(buf)?,
+                }
+            }
+            pg_constants::XLOG_HEAP_TRUNCATE => HeapRecord::Truncate(XlHeapTruncate::decode(buf)?),
+            pg_constants::XLOG_HEAP_CONFIRM => {
+                ensure_size(buf, 2, "xl_heap_confirm")?;
+                HeapRecord::Confirm {
+                    offnum: buf.get_u16_le(),
+                }
+            }
+            pg_constants::XLOG_HEAP_LOCK => HeapRecord::Lock(XlHeapLock::decode(buf)?),
+            pg_constants::XLOG_HEAP_INPLACE => {
+                ensure_size(buf, 2, "xl_heap_inplace")?;
+                HeapRecord::Inplace {
+                    offnum: buf.get_u16_le(),
+                }
+            }
+            op => bail!("unknown heap record 0x{op:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for HeapRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let init = |init_page: bool| if init_page { "+INIT" } else { "" };
+        match self {
+            HeapRecord::Insert { init_page, rec } => write!(
+                f,
+                "INSERT{} off {} flags 0x{:02X}",
+                init(*init_page),
+                rec.offnum,
+                rec.flags
+            ),
+            HeapRecord::Delete(rec) => {
+                write!(f, "DELETE off {} flags 0x{:02X}", rec.offnum, rec.flags)?;
+                fmt_infobits(f, rec.infobits_set)
+            }
+            HeapRecord::Update {
+                hot,
+                init_page,
+                rec,
+            } => {
+                write!(
+                    f,
+                    "{}{} off {} xmax {} flags 0x{:02X}",
+                    if *hot { "HOT_UPDATE" } else { "UPDATE" },
+                    init(*init_page),
+                    rec.old_offnum,
+                    rec.old_xmax,
+                    rec.flags
+                )?;
+                fmt_infobits(f, rec.old_infobits_set)?;
+                write!(f, "; new off {} xmax {}", rec.new_offnum, rec.new_xmax)
+            }
+            HeapRecord::Truncate(rec) => {
+                write!(f, "TRUNCATE")?;
+                if rec.flags & XLH_TRUNCATE_CASCADE != 0 {
+                    write!(f, " cascade")?;
+                }
+                if rec.flags & XLH_TRUNCATE_RESTART_SEQS != 0 {
+                    write!(f, " restart_seqs")?;
+                }
+                write!(f, " nrelids {} relids", rec.relids.len())?;
+                for relid in &rec.relids {
+                    write!(f, " {relid}")?;
+                }
+                Ok(())
+            }
+            HeapRecord::Confirm { offnum } => write!(f, "HEAP_CONFIRM off {offnum}"),
+            HeapRecord::Lock(rec) => {
+                write!(
+                    f,
+                    "LOCK off {}: xid {}: flags 0x{:02X}",
+                    rec.offnum, rec.locking_xid, rec.flags
+                )?;
+                fmt_infobits(f, rec.infobits_set)
+            }
+            HeapRecord::Inplace { offnum } => write!(f, "INPLACE off {offnum}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapRewriteMapping {
+    pub mapped_xid: TransactionId,
+    pub mapped_db: Oid,
+    pub mapped_rel: Oid,
+    pub offset: i64,
+    pub num_mappings: u32,
+    pub start_lsn: XLogRecPtr,
+}
+
+impl XlHeapRewriteMapping {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapRewriteMapping> {
+        ensure_size(buf, 40, "xl_heap_rewrite_mapping")?;
+        let mapped_xid = buf.get_u32_le();
+        let mapped_db = buf.get_u32_le();
+        let mapped_rel = buf.get_u32_le();
+        buf.advance(4); // padding
+        let offset = buf.get_i64_le();
+        let num_mappings = buf.get_u32_le();
+        buf.advance(4); // padding
+        Ok(XlHeapRewriteMapping {
+            mapped_xid,
+            mapped_db,
+            mapped_rel,
+            offset,
+            num_mappings,
+            start_lsn: buf.get_u64_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapPrune {
+    pub latest_removed_xid: TransactionId,
+    pub nredirected: u16,
+    pub ndead: u16,
+}
+
+impl XlHeapPrune {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapPrune> {
+        ensure_size(buf, 8, "xl_heap_prune")?;
+        Ok(XlHeapPrune {
+            latest_removed_xid: buf.get_u32_le(),
+            nredirected: buf.get_u16_le(),
+            ndead: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapFreezePage {
+    pub cutoff_xid: TransactionId,
+    pub ntuples: u16,
+}
+
+impl XlHeapFreezePage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapFreezePage> {
+        ensure_size(buf, 6, "xl_heap_freeze_page")?;
+        Ok(XlHeapFreezePage {
+            cutoff_xid: buf.get_u32_le(),
+            ntuples: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapVisible {
+    pub cutoff_xid: TransactionId,
+    pub flags: u8,
+}
+
+impl XlHeapVisible {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapVisible> {
+        ensure_size(buf, 5, "xl_heap_visible")?;
+        Ok(XlHeapVisible {
+            cutoff_xid: buf.get_u32_le(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapMultiInsert {
+    pub flags: u8,
+    pub ntuples: u16,
+}
+
+impl XlHeapMultiInsert {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapMultiInsert> {
+        ensure_size(buf, 4, "xl_heap_multi_insert")?;
+        let flags = buf.get_u8();
+        buf.advance(1); // padding
+        Ok(XlHeapMultiInsert {
+            flags,
+            ntuples: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapLockUpdated {
+    pub xmax: TransactionId,
+    pub offnum: OffsetNumber,
+    pub infobits_set: u8,
+    pub flags: u8,
+}
+
+impl XlHeapLockUpdated {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapLockUpdated> {
+        ensure_size(buf, 8, "xl_heap_lock_updated")?;
+        Ok(XlHeapLockUpdated {
+            xmax: buf.get_u32_le(),
+            offnum: buf.get_u16_le(),
+            infobits_set: buf.get_u8(),
+            flags: buf.get_u8(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlHeapNewCid {
+    pub top_xid: TransactionId,
+    pub cmin: u32,
+    pub cmax: u32,
+    pub combocid: u32,
+    pub target_node: RelFileNode,
+    pub target_blkno: BlockNumber,
+    pub target_offnum: OffsetNumber,
+}
+
+impl XlHeapNewCid {
+    pub fn decode(buf: &mut Bytes) -> Result<XlHeapNewCid> {
+        ensure_size(buf, 34, "xl_heap_new_cid")?;
+        let top_xid = buf.get_u32_le();
+        let cmin = buf.get_u32_le();
+        let cmax = buf.get_u32_le();
+        let combocid = buf.get_u32_le();
+        let target_node = RelFileNode::decode(buf);
+        // ItemPointerData, with the block number split in two halves
+        let bi_hi = buf.get_u16_le() as u32;
+        let bi_lo = buf.get_u16_le() as u32;
+        Ok(XlHeapNewCid {
+            top_xid,
+            cmin,
+            cmax,
+            combocid,
+            target_node,
+            target_blkno: bi_hi << 16 | bi_lo,
+            target_offnum: buf.get_u16_le(),
+        })
+    }
+}
+
+/// The heap2 records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Heap2Record {
+    Rewrite(XlHeapRewriteMapping),
+    Prune(XlHeapPrune),
+    Vacuum {
+        nunused: u16,
+    },
+    FreezePage(XlHeapFreezePage),
+    Visible(XlHeapVisible),
+    MultiInsert {
+        init_page: bool,
+        rec: XlHeapMultiInsert,
+    },
+    LockUpdated(XlHeapLockUpdated),
+    NewCid(XlHeapNewCid),
+}
+
+impl Heap2Record {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<Heap2Record> {
+        let rec = match info & pg_constants::XLOG_HEAP_OPMASK {
+            pg_constants::XLOG_HEAP2_REWRITE => {
+                Heap2Record::Rewrite(XlHeapRewriteMapping::decode(buf)?)
+            }
+            pg_constants::XLOG_HEAP2_PRUNE => Heap2Record::Prune(XlHeapPrune::decode(buf)?),
+            pg_constants::XLOG_HEAP2_VACUUM => {
+                ensure_size(buf, 2, "xl_heap_vacuum")?;
+                Heap2Record::Vacuum {
+                    nunused: buf.get_u16_le(),
+                }
+            }
+            pg_constants::XLOG_HEAP2_FREEZE_PAGE => {
+                Heap2Record::FreezePage(XlHeapFreezePage::decode(buf)?)
+            }
+            pg_constants::XLOG_HEAP2_VISIBLE => Heap2Record::Visible(XlHeapVisible::decode(buf)?),
+            pg_constants::XLOG_HEAP2_MULTI_INSERT => Heap2Record::MultiInsert {
+                init_page: info & pg_constants::XLOG_HEAP_INIT_PAGE != 0,
+                rec: XlHeapMultiInsert::decode(buf)?,
+            },
+            pg_constants::XLOG_HEAP2_LOCK_UPDATED => {
+                Heap2Record::LockUpdated(XlHeapLockUpdated::decode(buf)?)
+            }
+            pg_constants::XLOG_HEAP2_NEW_CID => Heap2Record::NewCid(XlHeapNewCid::decode(buf)?),
+            op => bail!("unknown heap2 record 0x{op:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for Heap2Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Heap2Record::Rewrite(rec) => write!(
+                f,
+                "REWRITE mapped_xid {} mapped_db {} mapped_rel {} off {} num_mappings {} \
+                 start_lsn {:X}/{:08X}",
+                rec.mapped_xid,
+                rec.mapped_db,
+                rec.mapped_rel,
+                rec.offset,
+                rec.num_mappings,
+                rec.start_lsn >> 32,
+                rec.start_lsn as u32
+            ),
+            Heap2Record::Prune(rec) => write!(
+                f,
+                "PRUNE latestRemovedXid {} nredirected {} ndead {}",
+                rec.latest_removed_xid, rec.nredirected, rec.ndead
+            ),
+            Heap2Record::Vacuum { nunused } => write!(f, "VACUUM nunused {nunused}"),
+            Heap2Record::FreezePage(rec) => write!(
+                f,
+                "FREEZE_PAGE cutoff xid {} ntuples {}",
+                rec.cutoff_xid, rec.ntuples
+            ),
+            Heap2Record::Visible(rec) => write!(
+                f,
+                "VISIBLE cutoff xid {} flags 0x{:02X}",
+                rec.cutoff_xid, rec.flags
+            ),
+            Heap2Record::MultiInsert { init_page, rec } => write!(
+                f,
+                "MULTI_INSERT{} {} tuples flags 0x{:02X}",
+                if *init_page { "+INIT" } else { "" },
+                rec.ntuples,
+                rec.flags
+            ),
+            Heap2Record::LockUpdated(rec) => {
+                write!(
+                    f,
+                    "LOCK_UPDATED off {}: xmax {}: flags 0x{:02X}",
+                    rec.offnum, rec.xmax, rec.flags
+                )?;
+                fmt_infobits(f, rec.infobits_set)
+            }
+            Heap2Record::NewCid(rec) => write!(
+                f,
+                "NEW_CID rel {}; tid {}/{}; cmin: {}, cmax: {}, combo: {}",
+                rec.target_node,
+                rec.target_blkno,
+                rec.target_offnum,
+                rec.cmin,
+                rec.cmax,
+                rec.combocid
+            ),
+        }
+    }
+}
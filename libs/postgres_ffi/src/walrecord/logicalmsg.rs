@@ -0,0 +1,90 @@
+//!
+//! Records of the logical decoding message resource manager, from message.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::pg_constants;
+use crate::Oid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlLogicalMessage {
+    pub db_id: Oid,
+    pub transactional: bool,
+    pub prefix: String,
+    pub message: Bytes,
+}
+
+impl XlLogicalMessage {
+    pub fn decode(buf: &mut Bytes) -> Result<XlLogicalMessage> {
+        ensure_size(buf, 24, "xl_logical_message")?;
+        let db_id = buf.get_u32_le();
+        let transactional = buf.get_u8() != 0;
+        buf.advance(3); // padding
+        let prefix_size = buf.get_u64_le() as usize;
+        let message_size = buf.get_u64_le() as usize;
+        ensure_size(
+            buf,
+            prefix_size.saturating_add(message_size),
+            "xl_logical_message prefix and payload",
+        )?;
+
+        // The prefix is null-terminated.
+        let prefix = buf.split_to(prefix_size);
+        ensure!(
+            prefix.last() == Some(&0),
+            "prefix of logical message is not null-terminated"
+        );
+        let prefix = String::from_utf8_lossy(&prefix[..prefix_size - 1]).into_owned();
+        Ok(XlLogicalMessage {
+            db_id,
+            transactional,
+            prefix,
+            message: buf.split_to(message_size),
+        })
+    }
+}
+
+/// The logical decoding message records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogicalMessageRecord {
+    Message(XlLogicalMessage),
+}
+
+impl LogicalMessageRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<LogicalMessageRecord> {
+        match info {
+            pg_constants::XLOG_LOGICAL_MESSAGE => Ok(LogicalMessageRecord::Message(
+                XlLogicalMessage::decode(buf)?,
+            )),
+            _ => bail!("unknown logical message record 0x{info:02x}"),
+        }
+    }
+}
+
+impl fmt::Display for LogicalMessageRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogicalMessageRecord::Message(rec) => {
+                write!(
+                    f,
+                    "MESSAGE {}, prefix \"{}\"; payload ({} bytes):",
+                    if rec.transactional {
+                        "transactional"
+                    } else {
+                        "non-transactional"
+                    },
+                    rec.prefix,
+                    rec.message.len()
+                )?;
+                for byte in &rec.message {
+                    write!(f, " {byte:02X}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
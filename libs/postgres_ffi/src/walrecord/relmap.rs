@@ -0,0 +1,73 @@
+//!
+//! Records of the relation mapper resource manager, from relmapper.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, ensure, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::relmapper::RelMapFile;
+use crate::Oid;
+
+pub const XLOG_RELMAP_UPDATE: u8 = 0x00;
+
+/// An update of a map file. The data is the whole new map file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlRelmapUpdate {
+    /// Database, or 0 for the shared map
+    pub dbid: Oid,
+    /// Tablespace of the database, or pg_global
+    pub tsid: Oid,
+    pub data: Bytes,
+}
+
+impl XlRelmapUpdate {
+    pub fn decode(buf: &mut Bytes) -> Result<XlRelmapUpdate> {
+        ensure_size(buf, 12, "xl_relmap_update")?;
+        let dbid = buf.get_u32_le();
+        let tsid = buf.get_u32_le();
+        let nbytes = buf.get_i32_le();
+        ensure!(nbytes >= 0, "negative size {nbytes} of relation map data");
+        ensure_size(buf, nbytes as usize, "relation map data")?;
+        Ok(XlRelmapUpdate {
+            dbid,
+            tsid,
+            data: buf.split_to(nbytes as usize),
+        })
+    }
+
+    /// The new map file.
+    pub fn map_file(&self) -> Result<RelMapFile> {
+        RelMapFile::decode(&self.data)
+    }
+}
+
+/// The relation mapper records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelmapRecord {
+    Update(XlRelmapUpdate),
+}
+
+impl RelmapRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<RelmapRecord> {
+        match info {
+            XLOG_RELMAP_UPDATE => Ok(RelmapRecord::Update(XlRelmapUpdate::decode(buf)?)),
+            _ => bail!("unknown relmap record 0x{info:02x}"),
+        }
+    }
+}
+
+impl fmt::Display for RelmapRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelmapRecord::Update(rec) => write!(
+                f,
+                "UPDATE database {} tablespace {} size {}",
+                rec.dbid,
+                rec.tsid,
+                rec.data.len()
+            ),
+        }
+    }
+}
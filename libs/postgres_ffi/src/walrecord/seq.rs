@@ -0,0 +1,40 @@
+//!
+//! Records of the sequence resource manager, from sequence.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use super::{ensure_size, RelFileNode};
+
+pub const XLOG_SEQ_LOG: u8 = 0x00;
+
+/// The sequence records, by their info. The new tuple of the sequence follows
+/// the RelFileNode in the main data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeqRecord {
+    Log { node: RelFileNode },
+}
+
+impl SeqRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<SeqRecord> {
+        match info {
+            XLOG_SEQ_LOG => {
+                ensure_size(buf, RelFileNode::SIZE, "xl_seq_rec")?;
+                Ok(SeqRecord::Log {
+                    node: RelFileNode::decode(buf),
+                })
+            }
+            _ => bail!("unknown sequence record 0x{info:02x}"),
+        }
+    }
+}
+
+impl fmt::Display for SeqRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeqRecord::Log { node } => write!(f, "LOG rel {node}"),
+        }
+    }
+}
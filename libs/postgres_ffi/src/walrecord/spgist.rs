@@ -0,0 +1,285 @@
+//!
+//! Records of the SP-GiST resource manager, from spgxlog.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::{OffsetNumber, TransactionId};
+
+pub const XLOG_SPGIST_ADD_LEAF: u8 = 0x10;
+pub const XLOG_SPGIST_MOVE_LEAFS: u8 = 0x20;
+pub const XLOG_SPGIST_ADD_NODE: u8 = 0x30;
+pub const XLOG_SPGIST_SPLIT_TUPLE: u8 = 0x40;
+pub const XLOG_SPGIST_PICKSPLIT: u8 = 0x50;
+pub const XLOG_SPGIST_VACUUM_LEAF: u8 = 0x60;
+pub const XLOG_SPGIST_VACUUM_ROOT: u8 = 0x70;
+pub const XLOG_SPGIST_VACUUM_REDIRECT: u8 = 0x80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogAddLeaf {
+    pub new_page: bool,
+    pub stores_nulls: bool,
+    pub offnum_leaf: OffsetNumber,
+    pub offnum_head_leaf: OffsetNumber,
+    pub offnum_parent: OffsetNumber,
+    pub node_i: u16,
+}
+
+impl SpgXlogAddLeaf {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogAddLeaf> {
+        ensure_size(buf, 10, "spgxlogAddLeaf")?;
+        Ok(SpgXlogAddLeaf {
+            new_page: buf.get_u8() != 0,
+            stores_nulls: buf.get_u8() != 0,
+            offnum_leaf: buf.get_u16_le(),
+            offnum_head_leaf: buf.get_u16_le(),
+            offnum_parent: buf.get_u16_le(),
+            node_i: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogMoveLeafs {
+    pub n_moves: u16,
+    pub new_page: bool,
+    pub replace_dead: bool,
+    pub stores_nulls: bool,
+    pub offnum_parent: OffsetNumber,
+    pub node_i: u16,
+}
+
+impl SpgXlogMoveLeafs {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogMoveLeafs> {
+        ensure_size(buf, 10, "spgxlogMoveLeafs")?;
+        let n_moves = buf.get_u16_le();
+        let new_page = buf.get_u8() != 0;
+        let replace_dead = buf.get_u8() != 0;
+        let stores_nulls = buf.get_u8() != 0;
+        buf.advance(1); // padding
+        Ok(SpgXlogMoveLeafs {
+            n_moves,
+            new_page,
+            replace_dead,
+            stores_nulls,
+            offnum_parent: buf.get_u16_le(),
+            node_i: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogAddNode {
+    pub offnum: OffsetNumber,
+    pub offnum_new: OffsetNumber,
+    pub new_page: bool,
+    pub parent_blk: i8,
+    pub offnum_parent: OffsetNumber,
+    pub node_i: u16,
+}
+
+impl SpgXlogAddNode {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogAddNode> {
+        ensure_size(buf, 10, "spgxlogAddNode")?;
+        Ok(SpgXlogAddNode {
+            offnum: buf.get_u16_le(),
+            offnum_new: buf.get_u16_le(),
+            new_page: buf.get_u8() != 0,
+            parent_blk: buf.get_i8(),
+            offnum_parent: buf.get_u16_le(),
+            node_i: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogSplitTuple {
+    pub offnum_prefix: OffsetNumber,
+    pub offnum_postfix: OffsetNumber,
+    pub new_page: bool,
+    pub postfix_blk_same: bool,
+}
+
+impl SpgXlogSplitTuple {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogSplitTuple> {
+        ensure_size(buf, 6, "spgxlogSplitTuple")?;
+        Ok(SpgXlogSplitTuple {
+            offnum_prefix: buf.get_u16_le(),
+            offnum_postfix: buf.get_u16_le(),
+            new_page: buf.get_u8() != 0,
+            postfix_blk_same: buf.get_u8() != 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogPickSplit {
+    pub is_root_split: bool,
+    pub n_delete: u16,
+    pub n_insert: u16,
+    pub init_src: bool,
+    pub init_dest: bool,
+    pub offnum_inner: OffsetNumber,
+    pub init_inner: bool,
+    pub stores_nulls: bool,
+    pub inner_is_parent: bool,
+    pub offnum_parent: OffsetNumber,
+    pub node_i: u16,
+}
+
+impl SpgXlogPickSplit {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogPickSplit> {
+        ensure_size(buf, 18, "spgxlogPickSplit")?;
+        let is_root_split = buf.get_u8() != 0;
+        buf.advance(1); // padding
+        let n_delete = buf.get_u16_le();
+        let n_insert = buf.get_u16_le();
+        let init_src = buf.get_u8() != 0;
+        let init_dest = buf.get_u8() != 0;
+        let offnum_inner = buf.get_u16_le();
+        let init_inner = buf.get_u8() != 0;
+        let stores_nulls = buf.get_u8() != 0;
+        let inner_is_parent = buf.get_u8() != 0;
+        buf.advance(1); // padding
+        Ok(SpgXlogPickSplit {
+            is_root_split,
+            n_delete,
+            n_insert,
+            init_src,
+            init_dest,
+            offnum_inner,
+            init_inner,
+            stores_nulls,
+            inner_is_parent,
+            offnum_parent: buf.get_u16_le(),
+            node_i: buf.get_u16_le(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpgXlogVacuumLeaf {
+    pub n_dead: u16,
+    pub n_placeholder: u16,
+    pub n_move: u16,
+    pub n_chain: u16,
+}
+
+impl SpgXlogVacuumLeaf {
+    pub fn decode(buf: &mut Bytes) -> Result<SpgXlogVacuumLeaf> {
+        ensure_size(buf, 8, "spgxlogVacuumLeaf")?;
+        Ok(SpgXlogVacuumLeaf {
+            n_dead: buf.get_u16_le(),
+            n_placeholder: buf.get_u16_le(),
+            n_move: buf.get_u16_le(),
+            n_chain: buf.get_u16_le(),
+        })
+    }
+}
+
+/// The SP-GiST records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpGistRecord {
+    AddLeaf(SpgXlogAddLeaf),
+    MoveLeafs(SpgXlogMoveLeafs),
+    AddNode(SpgXlogAddNode),
+    SplitTuple(SpgXlogSplitTuple),
+    PickSplit(SpgXlogPickSplit),
+    VacuumLeaf(SpgXlogVacuumLeaf),
+    VacuumRoot {
+        n_delete: u16,
+    },
+    VacuumRedirect {
+        n_to_placeholder: u16,
+        first_placeholder: OffsetNumber,
+        newest_redirect_xid: TransactionId,
+    },
+}
+
+impl SpGistRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<SpGistRecord> {
+        let rec = match info {
+            XLOG_SPGIST_ADD_LEAF => SpGistRecord::AddLeaf(SpgXlogAddLeaf::decode(buf)?),
+            XLOG_SPGIST_MOVE_LEAFS => SpGistRecord::MoveLeafs(SpgXlogMoveLeafs::decode(buf)?),
+            XLOG_SPGIST_ADD_NODE => SpGistRecord::AddNode(SpgXlogAddNode::decode(buf)?),
+            XLOG_SPGIST_SPLIT_TUPLE => SpGistRecord::SplitTuple(SpgXlogSplitTuple::decode(buf)?),
+            XLOG_SPGIST_PICKSPLIT => SpGistRecord::PickSplit(SpgXlogPickSplit::decode(buf)?),
+            XLOG_SPGIST_VACUUM_LEAF => SpGistRecord::VacuumLeaf(SpgXlogVacuumLeaf::decode(buf)?),
+            XLOG_SPGIST_VACUUM_ROOT => {
+                ensure_size(buf, 2, "spgxlogVacuumRoot")?;
+                SpGistRecord::VacuumRoot {
+                    n_delete: buf.get_u16_le(),
+                }
+            }
+            XLOG_SPGIST_VACUUM_REDIRECT => {
+                ensure_size(buf, 8, "spgxlogVacuumRedirect")?;
+                SpGistRecord::VacuumRedirect {
+                    n_to_placeholder: buf.get_u16_le(),
+                    first_placeholder: buf.get_u16_le(),
+                    newest_redirect_xid: buf.get_u32_le(),
+                }
+            }
+            _ => bail!("unknown spgist record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for SpGistRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpGistRecord::AddLeaf(rec) => {
+                write!(
+                    f,
+                    "ADD_LEAF off: {}, headoff: {}, parentoff: {}, nodeI: {}",
+                    rec.offnum_leaf, rec.offnum_head_leaf, rec.offnum_parent, rec.node_i
+                )?;
+                if rec.new_page {
+                    write!(f, " (newpage)")?;
+                }
+                if rec.stores_nulls {
+                    write!(f, " (nulls)")?;
+                }
+                Ok(())
+            }
+            SpGistRecord::MoveLeafs(rec) => write!(
+                f,
+                "MOVE_LEAFS nmoves: {}, parentoff: {}, nodeI: {}",
+                rec.n_moves, rec.offnum_parent, rec.node_i
+            ),
+            SpGistRecord::AddNode(rec) => write!(
+                f,
+                "ADD_NODE off: {}, newoff: {}, parentBlk: {}, parentoff: {}, nodeI: {}",
+                rec.offnum, rec.offnum_new, rec.parent_blk, rec.offnum_parent, rec.node_i
+            ),
+            SpGistRecord::SplitTuple(rec) => write!(
+                f,
+                "SPLIT_TUPLE prefixoff: {}, postfixoff: {}",
+                rec.offnum_prefix, rec.offnum_postfix
+            ),
+            SpGistRecord::PickSplit(rec) => write!(
+                f,
+                "PICKSPLIT ndelete: {}, ninsert: {}, inneroff: {}, parentoff: {}, nodeI: {}",
+                rec.n_delete, rec.n_insert, rec.offnum_inner, rec.offnum_parent, rec.node_i
+            ),
+            SpGistRecord::VacuumLeaf(rec) => write!(
+                f,
+                "VACUUM_LEAF ndead: {}, nplaceholder: {}, nmove: {}, nchain: {}",
+                rec.n_dead, rec.n_placeholder, rec.n_move, rec.n_chain
+            ),
+            SpGistRecord::VacuumRoot { n_delete } => write!(f, "VACUUM_ROOT ndelete: {n_delete}"),
+            SpGistRecord::VacuumRedirect {
+                n_to_placeholder,
+                first_placeholder,
+                newest_redirect_xid,
+            } => write!(
+                f,
+                "VACUUM_REDIRECT ntoplaceholder: {n_to_placeholder}, \
+                 firstplaceholder: {first_placeholder}, newestredirectxid: {newest_redirect_xid}"
+            ),
+        }
+    }
+}
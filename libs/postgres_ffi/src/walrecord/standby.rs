@@ -0,0 +1,165 @@
+//!
+//! Records of the hot standby resource manager, from standbydefs.h.
+//!
+use std::fmt;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, Bytes};
+
+use super::ensure_size;
+use crate::{Oid, TransactionId};
+
+pub const XLOG_STANDBY_LOCK: u8 = 0x00;
+pub const XLOG_RUNNING_XACTS: u8 = 0x10;
+pub const XLOG_INVALIDATIONS: u8 = 0x20;
+
+/// Size of a SharedInvalidationMessage.
+const SIZE_OF_SHARED_INVALIDATION_MESSAGE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlStandbyLock {
+    pub xid: TransactionId,
+    pub db_oid: Oid,
+    pub rel_oid: Oid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlRunningXacts {
+    pub subxid_overflow: bool,
+    pub next_xid: TransactionId,
+    pub oldest_running_xid: TransactionId,
+    pub latest_completed_xid: TransactionId,
+    pub xids: Vec<TransactionId>,
+    pub subxids: Vec<TransactionId>,
+}
+
+impl XlRunningXacts {
+    pub fn decode(buf: &mut Bytes) -> Result<XlRunningXacts> {
+        ensure_size(buf, 24, "xl_running_xacts")?;
+        let xcnt = buf.get_i32_le().max(0) as usize;
+        let subxcnt = buf.get_i32_le().max(0) as usize;
+        let subxid_overflow = buf.get_u8() != 0;
+        buf.advance(3); // padding
+        let next_xid = buf.get_u32_le();
+        let oldest_running_xid = buf.get_u32_le();
+        let latest_completed_xid = buf.get_u32_le();
+        ensure_size(buf, (xcnt + subxcnt) * 4, "xl_running_xacts xids")?;
+        Ok(XlRunningXacts {
+            subxid_overflow,
+            next_xid,
+            oldest_running_xid,
+            latest_completed_xid,
+            xids: (0..xcnt).map(|_| buf.get_u32_le()).collect(),
+            subxids: (0..subxcnt).map(|_| buf.get_u32_le()).collect(),
+        })
+    }
+}
+
+/// The hot standby records, by their info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StandbyRecord {
+    Lock(Vec<XlStandbyLock>),
+    RunningXacts(XlRunningXacts),
+    Invalidations {
+        db_id: Oid,
+        ts_id: Oid,
+        relcache_init_file_inval: bool,
+        nmsgs: i32,
+    },
+}
+
+impl StandbyRecord {
+    pub fn decode(info: u8, buf: &mut Bytes) -> Result<StandbyRecord> {
+        let rec = match info {
+            XLOG_STANDBY_LOCK => {
+                ensure_size(buf, 4, "xl_standby_locks")?;
+                let nlocks = buf.get_i32_le().max(0) as usize;
+                ensure_size(buf, nlocks * 12, "xl_standby_locks locks")?;
+                StandbyRecord::Lock(
+                    (0..nlocks)
+                        .map(|_| XlStandbyLock {
+                            xid: buf.get_u32_le(),
+                            db_oid: buf.get_u32_le(),
+                            rel_oid: buf.get_u32_le(),
+                        })
+                        .collect(),
+                )
+            }
+            XLOG_RUNNING_XACTS => StandbyRecord::RunningXacts(XlRunningXacts::decode(buf)?),
+            XLOG_INVALIDATIONS => {
+                ensure_size(buf, 16, "xl_invalidations")?;
+                let db_id = buf.get_u32_le();
+                let ts_id = buf.get_u32_le();
+                let relcache_init_file_inval = buf.get_u8() != 0;
+                buf.advance(3); // padding
+                let nmsgs = buf.get_i32_le();
+                ensure_size(
+                    buf,
+                    nmsgs.max(0) as usize * SIZE_OF_SHARED_INVALIDATION_MESSAGE,
+                    "xl_invalidations msgs",
+                )?;
+                StandbyRecord::Invalidations {
+                    db_id,
+                    ts_id,
+                    relcache_init_file_inval,
+                    nmsgs,
+                }
+            }
+            _ => bail!("unknown standby record 0x{info:02x}"),
+        };
+        Ok(rec)
+    }
+}
+
+impl fmt::Display for StandbyRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StandbyRecord::Lock(locks) => {
+                write!(f, "LOCK")?;
+                for lock in locks {
+                    write!(
+                        f,
+                        " xid {} db {} rel {}",
+                        lock.xid, lock.db_oid, lock.rel_oid
+                    )?;
+                }
+                Ok(())
+            }
+            StandbyRecord::RunningXacts(rec) => {
+                write!(
+                    f,
+                    "RUNNING_XACTS nextXid {} latestCompletedXid {} oldestRunningXid {}",
+                    rec.next_xid, rec.latest_completed_xid, rec.oldest_running_xid
+                )?;
+                if !rec.xids.is_empty() {
+                    write!(f, "; {} xacts:", rec.xids.len())?;
+                    for xid in &rec.xids {
+                        write!(f, " {xid}")?;
+                    }
+                }
+                if rec.subxid_overflow {
+                    write!(f, "; subxid overflowed")?;
+                }
+                if !rec.subxids.is_empty() {
+                    write!(f, "; {} subxacts:", rec.subxids.len())?;
+                    for xid in &rec.subxids {
+                        write!(f, " {xid}")?;
+                    }
+                }
+                Ok(())
+            }
+            StandbyRecord::Invalidations {
+                db_id,
+                ts_id,
+                relcache_init_file_inval,
+                nmsgs,
+            } => {
+                write!(f, "INVALIDATIONS")?;
+                if *relcache_init_file_inval {
+                    write!(f, " relcache init file inval dbid {db_id} tsid {ts_id}")?;
+                }
+                write!(f, "; {nmsgs} inval msgs")
+            }
+        }
+    }
+}
@@ -11,7 +11,7 @@ use crc32c::crc32c_append;
 
 use super::super::waldecoder::WalStreamDecoder;
 use super::bindings::{
-    CheckPoint, ControlFileData, DBState_DB_SHUTDOWNED, FullTransactionId, TimeLineID, TimestampTz,
+    CheckPoint, ControlFileData, FullTransactionId, TimeLineID, TimestampTz,
     XLogLongPageHeaderData, XLogPageHeaderData, XLogRecPtr, XLogRecord, XLogSegNo, XLOG_PAGE_MAGIC,
 };
 use super::PG_MAJORVERSION;
@@ -117,7 +117,7 @@ pub fn generate_pg_control(
     let mut checkpoint = CheckPoint::decode(checkpoint_bytes)?;
 
     // Generate new pg_control needed for bootstrap
-    checkpoint.redo = normalize_lsn(lsn, WAL_SEGMENT_SIZE).0;
+    checkpoint.redo = normalize_lsn(lsn, pg_control.xlog_seg_size as usize).0;
 
     //reset some fields we don't want to preserve
     //TODO Check this.
@@ -125,9 +125,8 @@ pub fn generate_pg_control(
     checkpoint.oldestActiveXid = 0;
 
     //save new values in pg_control
-    pg_control.checkPoint = 0;
-    pg_control.checkPointCopy = checkpoint;
-    pg_control.state = DBState_DB_SHUTDOWNED;
+    pg_control.set_checkpoint(checkpoint, 0);
+    pg_control.set_shut_down();
 
     Ok((pg_control.encode(), pg_control.system_identifier))
 }
@@ -166,7 +165,7 @@ pub fn find_end_of_wal(
     let pg_version = PG_MAJORVERSION[1..3].parse::<u32>().unwrap();
     debug!("find_end_of_wal PG_VERSION: {}", pg_version);
 
-    let mut decoder = WalStreamDecoder::new(start_lsn, pg_version);
+    let mut decoder = WalStreamDecoder::new(start_lsn, pg_version, wal_seg_size);
 
     // loop over segments
     loop {
@@ -308,26 +307,26 @@ impl CheckPoint {
     ///
     /// Returns 'true' if the XID was updated.
     pub fn update_next_xid(&mut self, xid: u32) -> bool {
-        // nextXid should nw greater than any XID in WAL, so increment provided XID and check for wraparround.
-        let mut new_xid = std::cmp::max(xid + 1, pg_constants::FIRST_NORMAL_TRANSACTION_ID);
+        // nextXid should be greater than any XID in WAL, so increment provided XID and check for wraparound.
+        let mut new_xid = std::cmp::max(
+            xid.wrapping_add(1),
+            pg_constants::FIRST_NORMAL_TRANSACTION_ID,
+        );
         // To reduce number of metadata checkpoints, we forward align XID on XID_CHECKPOINT_INTERVAL.
         // XID_CHECKPOINT_INTERVAL should not be larger than BLCKSZ*CLOG_XACTS_PER_BYTE
         new_xid =
             new_xid.wrapping_add(XID_CHECKPOINT_INTERVAL - 1) & !(XID_CHECKPOINT_INTERVAL - 1);
-        let full_xid = self.nextXid.value;
-        let old_xid = full_xid as u32;
-        if new_xid.wrapping_sub(old_xid) as i32 > 0 {
-            let mut epoch = full_xid >> 32;
-            if new_xid < old_xid {
-                // wrap-around
-                epoch += 1;
-            }
-            let nextXid = (epoch << 32) | new_xid as u64;
-
-            if nextXid != self.nextXid.value {
-                self.nextXid = FullTransactionId { value: nextXid };
-                return true;
-            }
+        // Aligning may wrap around to 0, skip the permanent XIDs like
+        // FullTransactionIdAdvance() does.
+        new_xid = std::cmp::max(new_xid, pg_constants::FIRST_NORMAL_TRANSACTION_ID);
+        // The new XID is in the next epoch if it follows ours across a wraparound.
+        let full_xid = crate::FullTransactionId(self.nextXid.value);
+        let new_full_xid = full_xid.relative(new_xid);
+        if new_full_xid > full_xid {
+            self.nextXid = FullTransactionId {
+                value: new_full_xid.0,
+            };
+            return true;
         }
         false
     }
@@ -336,13 +335,18 @@ impl CheckPoint {
 /// Generate new, empty WAL segment, with correct block headers at the first
 /// page of the segment and the page that contains the given LSN.
 /// We need this segment to start compute node.
-pub fn generate_wal_segment(segno: u64, system_id: u64, lsn: Lsn) -> Result<Bytes, SerializeError> {
-    let mut seg_buf = BytesMut::with_capacity(WAL_SEGMENT_SIZE);
+pub fn generate_wal_segment(
+    segno: u64,
+    system_id: u64,
+    lsn: Lsn,
+    wal_seg_size: usize,
+) -> Result<Bytes, SerializeError> {
+    let mut seg_buf = BytesMut::with_capacity(wal_seg_size);
 
-    let pageaddr = XLogSegNoOffsetToRecPtr(segno, 0, WAL_SEGMENT_SIZE);
+    let pageaddr = XLogSegNoOffsetToRecPtr(segno, 0, wal_seg_size);
 
     let page_off = lsn.block_offset();
-    let seg_off = lsn.segment_offset(WAL_SEGMENT_SIZE);
+    let seg_off = lsn.segment_offset(wal_seg_size);
 
     let first_page_only = seg_off < XLOG_BLCKSZ;
     let (shdr_rem_len, infoflags) = if first_page_only {
@@ -363,7 +367,7 @@ pub fn generate_wal_segment(segno: u64, system_id: u64, lsn: Lsn) -> Result<Byte
             }
         },
         xlp_sysid: system_id,
-        xlp_seg_size: WAL_SEGMENT_SIZE as u32,
+        xlp_seg_size: wal_seg_size as u32,
         xlp_xlog_blcksz: XLOG_BLCKSZ as u32,
     };
 
@@ -371,10 +375,10 @@ pub fn generate_wal_segment(segno: u64, system_id: u64, lsn: Lsn) -> Result<Byte
     seg_buf.extend_from_slice(&hdr_bytes);
 
     //zero out the rest of the file
-    seg_buf.resize(WAL_SEGMENT_SIZE, 0);
+    seg_buf.resize(wal_seg_size, 0);
 
     if !first_page_only {
-        let block_offset = lsn.page_offset_in_segment(WAL_SEGMENT_SIZE) as usize;
+        let block_offset = lsn.page_offset_in_segment(wal_seg_size) as usize;
         let header = XLogPageHeaderData {
             xlp_magic: XLOG_PAGE_MAGIC as u16,
             xlp_info: if page_off >= pg_constants::SIZE_OF_PAGE_HEADER as u64 {
@@ -205,6 +205,30 @@ pub fn test_update_next_xid() {
     // XID_CHECKPOINT_INTERVAL boundary.
     checkpoint.update_next_xid(1024);
     assert_eq!(checkpoint.nextXid.value, 2048);
+
+    // Near the wraparound, the XID moves to the next epoch, skipping the
+    // permanent XIDs
+    let epoch = 1u64 << 32;
+    checkpoint.nextXid = FullTransactionId {
+        value: epoch | (u32::MAX - 1023) as u64,
+    };
+    checkpoint.update_next_xid(u32::MAX - 1);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | 3);
+    checkpoint.update_next_xid(u32::MAX);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | 1024);
+    checkpoint.update_next_xid(5);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | 1024);
+
+    // XIDs from before the wraparound don't move it back
+    checkpoint.update_next_xid(u32::MAX - 5000);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | 1024);
+
+    // Up to 2^31 XIDs ahead, further ones are taken for XIDs of the
+    // previous epoch
+    checkpoint.update_next_xid((1 << 31) - 1000);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | (1 << 31));
+    checkpoint.update_next_xid(2000);
+    assert_eq!(checkpoint.nextXid.value, 2 * epoch | (1 << 31));
 }
 
 #[test]
@@ -217,3 +241,168 @@ pub fn test_encode_logical_message() {
     let actual = encode_logical_message("prefix", "message");
     assert_eq!(expected, actual[..]);
 }
+
+/// Decode the records of all the resource managers supported by
+/// postgres_ffi::walrecord from WAL written by a real Postgres, and check
+/// them against the rmgr and the record type printed by pg_waldump.
+#[test]
+pub fn test_decode_walrecords() {
+    use crate::*;
+    use postgres_ffi::waldecoder::WalStreamDecoder;
+    use postgres_ffi::walrecord::{rmgr_name, RmgrRecord, WalRecord};
+    use std::collections::{HashMap, HashSet};
+
+    init_logging();
+    let pg_version = PG_MAJORVERSION[1..3].parse::<u32>().unwrap();
+
+    let top_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..")
+        .join("..");
+    let cfg = Conf {
+        pg_version,
+        pg_distrib_dir: top_path.join("pg_install"),
+        datadir: top_path.join(format!("test_output/test_decode_walrecords-{PG_MAJORVERSION}")),
+    };
+    if cfg.datadir.exists() {
+        fs::remove_dir_all(&cfg.datadir).unwrap();
+    }
+    cfg.initdb().unwrap();
+    let srv = cfg.start_server().unwrap();
+    let mut client = srv.connect_with_timeout().unwrap();
+    ensure_server_config(&mut client).unwrap();
+
+    client.execute("SELECT pg_switch_wal()", &[]).unwrap();
+    let start_lsn = u64::from(client.pg_current_wal_insert_lsn().unwrap());
+    client
+        .batch_execute(
+            "
+            CREATE TABLE t (id int PRIMARY KEY, a int[], p point, v text);
+            INSERT INTO t SELECT g, ARRAY[g, g + 1], point(g, g), 'v' || g
+                FROM generate_series(1, 1000) g;
+            CREATE INDEX t_hash ON t USING hash (id);
+            CREATE INDEX t_gin ON t USING gin (a);
+            CREATE INDEX t_gist ON t USING gist (p);
+            CREATE INDEX t_spgist ON t USING spgist (p);
+            CREATE INDEX t_brin ON t USING brin (id);
+            INSERT INTO t SELECT g, ARRAY[g], point(g, -g), 'w' || g
+                FROM generate_series(1001, 1100) g;
+            UPDATE t SET v = 'u' WHERE id % 10 = 0;
+            DELETE FROM t WHERE id % 7 = 0;
+            SELECT * FROM t WHERE id = 1 FOR UPDATE;
+            VACUUM t;
+            CREATE SEQUENCE s;
+            SELECT nextval('s');
+            SELECT pg_logical_emit_message(false, 'prefix', 'message');
+            CHECKPOINT;
+            ",
+        )
+        .unwrap();
+    let end_lsn = u64::from(client.pg_current_wal_insert_lsn().unwrap());
+    srv.kill();
+
+    // Decode our records, from the beginning of the segment of the start LSN
+    // up to the end LSN.
+    let segno = start_lsn / WAL_SEGMENT_SIZE as u64;
+    let last_segno = end_lsn / WAL_SEGMENT_SIZE as u64;
+    let mut decoder = WalStreamDecoder::new(
+        Lsn(XLogSegNoOffsetToRecPtr(segno, 0, WAL_SEGMENT_SIZE)),
+        pg_version,
+        WAL_SEGMENT_SIZE,
+    );
+    let mut records = Vec::new();
+    for segno in segno..=last_segno {
+        let fname = XLogFileName(1, segno, WAL_SEGMENT_SIZE);
+        decoder.feed_bytes(&fs::read(cfg.wal_dir().join(&fname)).unwrap());
+        while let Some((lsn, recdata)) = decoder.poll_decode().unwrap() {
+            if u64::from(lsn) > end_lsn {
+                break;
+            }
+            records.push(WalRecord::decode(recdata, pg_version).unwrap());
+        }
+    }
+
+    // pg_waldump prints the rmgr and the type of each record, which we look
+    // up by the LSN of the previous record.
+    let waldump_output = cfg
+        .pg_waldump(
+            &XLogFileName(1, segno, WAL_SEGMENT_SIZE),
+            &XLogFileName(1, last_segno, WAL_SEGMENT_SIZE),
+        )
+        .unwrap()
+        .stdout;
+    let waldump_output = std::str::from_utf8(&waldump_output).unwrap();
+    let re = Regex::new(r"^rmgr: (\w+) .*, prev ([0-9A-F]+/[0-9A-F]+), desc: (\S+)").unwrap();
+    let waldump_records: HashMap<Lsn, (String, String)> = waldump_output
+        .lines()
+        .filter_map(|line| re.captures(line))
+        .map(|caps| {
+            (
+                Lsn::from_str(&caps[2]).unwrap(),
+                (caps[1].to_string(), caps[3].to_string()),
+            )
+        })
+        .collect();
+
+    let mut seen_rmgrs = HashSet::new();
+    for record in &records {
+        let rmgr_record = record
+            .rmgr_record()
+            .unwrap_or_else(|e| panic!("could not decode {record}: {e:#}"));
+        if matches!(rmgr_record, RmgrRecord::Other { .. }) {
+            continue;
+        }
+        let (rmgr, identify) = waldump_records
+            .get(&Lsn(record.xl_prev))
+            .unwrap_or_else(|| panic!("no pg_waldump record after {}", Lsn(record.xl_prev)));
+        assert_eq!(rmgr_name(record.xl_rmid), rmgr, "{record}");
+        let desc = rmgr_record.to_string();
+        assert_eq!(desc.split_whitespace().next(), Some(identify.as_str()), "{record}");
+        seen_rmgrs.insert(rmgr_name(record.xl_rmid));
+    }
+    for rmgr in [
+        "Heap",
+        "Heap2",
+        "Btree",
+        "Hash",
+        "Gin",
+        "Gist",
+        "SPGist",
+        "BRIN",
+        "Sequence",
+        "LogicalMessage",
+        "Standby",
+    ] {
+        assert!(seen_rmgrs.contains(rmgr), "no {rmgr} records were decoded");
+    }
+}
+
+#[test]
+pub fn test_control_file_read_write() {
+    let pgdata = tempfile::tempdir().unwrap();
+    fs::create_dir(pgdata.path().join("global")).unwrap();
+
+    let mut control_file = ControlFileData {
+        system_identifier: 7,
+        ..Default::default()
+    };
+    let checkpoint = CheckPoint {
+        redo: 0x1000028,
+        ..Default::default()
+    };
+    control_file.set_checkpoint(checkpoint, 0x1000028);
+    control_file.set_shut_down();
+    control_file.write_to_datadir(pgdata.path()).unwrap();
+
+    let read_back = ControlFileData::read_from_datadir(pgdata.path()).unwrap();
+    assert_eq!(read_back.system_identifier, 7);
+    assert_eq!(read_back.checkPoint, 0x1000028);
+    assert_eq!(read_back.checkPointCopy.redo, 0x1000028);
+    assert!(read_back.is_shut_down());
+    assert_eq!(read_back.state_name(), "shut down");
+
+    // A modified control file doesn't pass the CRC check.
+    let mut bytes = control_file.encode().to_vec();
+    bytes[0] ^= 1;
+    assert!(ControlFileData::decode(&bytes).is_err());
+}
@@ -671,6 +671,7 @@ pub fn read_cstr(buf: &mut Bytes) -> Result<Bytes, ProtocolError> {
 
 pub const SQLSTATE_INTERNAL_ERROR: &[u8; 5] = b"XX000";
 pub const SQLSTATE_SUCCESSFUL_COMPLETION: &[u8; 5] = b"00000";
+pub const SQLSTATE_CANNOT_CONNECT_NOW: &[u8; 5] = b"57P03";
 
 impl<'a> BeMessage<'a> {
     /// Serialize `message` to the given `buf`.
@@ -38,6 +38,9 @@ use crate::{
 
 const MAX_DELETE_OBJECTS_REQUEST_SIZE: usize = 1000;
 
+/// The host of the S3-compatible XML API of Google Cloud Storage.
+const GCS_ENDPOINT_HOST: &str = "storage.googleapis.com";
+
 pub(super) mod metrics;
 
 use self::metrics::{AttemptOutcome, RequestKind};
@@ -48,6 +51,8 @@ pub struct S3Bucket {
     bucket_name: String,
     prefix_in_bucket: Option<String>,
     max_keys_per_list_response: Option<i32>,
+    // Google Cloud Storage speaks the S3 API, except for the multi-object delete.
+    single_object_deletes: bool,
     // Every request to S3 can be throttled or cancelled, if a certain number of requests per second is exceeded.
     // Same goes to IAM, which is queried before every S3 request, if enabled. IAM has even lower RPS threshold.
     // The helps to ensure we don't exceed the thresholds.
@@ -89,6 +94,11 @@ impl S3Bucket {
             .or_else("imds", ImdsCredentialsProvider::builder().build())
         };
 
+        let single_object_deletes = aws_config
+            .endpoint
+            .as_deref()
+            .map_or(false, |endpoint| endpoint.contains(GCS_ENDPOINT_HOST));
+
         let mut config_builder = Config::builder()
             .region(region)
             .credentials_cache(CredentialsCache::lazy())
@@ -118,6 +128,7 @@ impl S3Bucket {
             bucket_name: aws_config.bucket_name.clone(),
             max_keys_per_list_response: aws_config.max_keys_per_list_response,
             prefix_in_bucket,
+            single_object_deletes,
             concurrency_limiter: Arc::new(Semaphore::new(aws_config.concurrency_limit.get())),
         })
     }
@@ -507,6 +518,13 @@ impl RemoteStorage for S3Bucket {
         .await
     }
     async fn delete_objects<'a>(&self, paths: &'a [RemotePath]) -> anyhow::Result<()> {
+        if self.single_object_deletes {
+            for path in paths {
+                self.delete(path).await?;
+            }
+            return Ok(());
+        }
+
         let kind = RequestKind::Delete;
         let _guard = self.permit(kind).await;
 
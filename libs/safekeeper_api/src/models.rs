@@ -58,4 +58,158 @@ pub struct SkTimelineInfo {
     /// A connection string to use for WAL receiving.
     #[serde(default)]
     pub safekeeper_connstr: Option<String>,
+    /// A connection string to use for the HTTP management API.
+    #[serde(default)]
+    pub http_connstr: Option<String>,
+}
+
+/// How far a safekeeper has received and acknowledged the WAL of a timeline.
+#[serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct TimelineAckedLsn {
+    /// LSN up to which the WAL is flushed to disk, and acknowledged to the
+    /// compute.
+    #[serde_as(as = "DisplayFromStr")]
+    pub flush_lsn: Lsn,
+    /// Up to which LSN the safekeeper regards the WAL as committed.
+    #[serde_as(as = "DisplayFromStr")]
+    pub commit_lsn: Lsn,
+}
+
+/// A switch of the term of the timeline, at the LSN where the WAL of the term
+/// starts.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermSwitchApiEntry {
+    pub term: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lsn: Lsn,
+}
+
+/// The acceptor state of the safekeeper, with the epoch for convenience.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptorStateStatus {
+    pub term: u64,
+    pub epoch: u64,
+    pub term_history: Vec<TermSwitchApiEntry>,
+}
+
+/// The Postgres the WAL of the timeline comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelinePgInfo {
+    pub pg_version: u32,
+    pub system_id: u64,
+    pub wal_seg_size: u32,
+}
+
+/// How far another safekeeper of the timeline has acknowledged its WAL, as
+/// last heard through the storage broker.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub sk_id: NodeId,
+    /// Term of the last entry.
+    pub last_log_term: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub flush_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub commit_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub local_start_lsn: Lsn,
+}
+
+/// Info about timeline on safekeeper ready for reporting.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineStatus {
+    #[serde_as(as = "DisplayFromStr")]
+    pub tenant_id: TenantId,
+    #[serde_as(as = "DisplayFromStr")]
+    pub timeline_id: TimelineId,
+    pub acceptor_state: AcceptorStateStatus,
+    pub pg_info: TimelinePgInfo,
+    #[serde_as(as = "DisplayFromStr")]
+    pub flush_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub timeline_start_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub local_start_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub commit_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub backup_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_horizon_lsn: Lsn,
+    #[serde_as(as = "DisplayFromStr")]
+    pub remote_consistent_lsn: Lsn,
+    /// The WAL segments up to this one, excluded, are removed.
+    #[serde(default)]
+    pub last_removed_segno: u64,
+    /// The other safekeepers of the timeline heard from recently.
+    #[serde(default)]
+    pub peers: Vec<PeerStatus>,
+}
+
+/// The LSN before which the WAL of a timeline is removed, and the LSNs it is
+/// computed from.
+#[serde_as]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct TimelineWalHorizon {
+    /// Up to which LSN the pageserver has the WAL in remote storage, as
+    /// persisted in the control file.
+    #[serde_as(as = "DisplayFromStr")]
+    pub remote_consistent_lsn: Lsn,
+    /// Up to which LSN all the peer safekeepers have the WAL.
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_horizon_lsn: Lsn,
+    /// Up to which LSN the WAL is backed up, if WAL backup is enabled.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub backup_lsn: Option<Lsn>,
+    /// Up to which LSN the standbys of all the replication slots have flushed
+    /// the WAL, if there are any slots.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub slots_restart_lsn: Option<Lsn>,
+    /// Bytes of WAL kept before the lowest of the LSNs above.
+    pub retention_margin: u64,
+    /// The WAL before this LSN isn't needed anymore. It is removed by whole
+    /// segments.
+    #[serde_as(as = "DisplayFromStr")]
+    pub horizon_lsn: Lsn,
+    /// The WAL segments up to this one, excluded, are removed.
+    pub last_removed_segno: u64,
+}
+
+/// Result of a removal of the WAL segments of a timeline that nothing needs
+/// anymore.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TimelineRemoveWalResponse {
+    /// The WAL segments up to this one, excluded, are removed.
+    pub last_removed_segno: u64,
+}
+
+/// A tenant with timelines on the safekeeper.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantInfo {
+    #[serde_as(as = "DisplayFromStr")]
+    pub tenant_id: TenantId,
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub timelines: Vec<TimelineId>,
+    /// Estimated disk space taken by the WAL of all the timelines.
+    pub wal_disk_usage_bytes: u64,
+    /// Disk space the WAL may take before accepting more of it is held off.
+    pub wal_quota_bytes: Option<u64>,
+}
+
+pub type ConfigureFailpointsRequest = Vec<FailpointConfig>;
+
+/// Information for configuring a single fail point
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailpointConfig {
+    /// Name of the fail point
+    pub name: String,
+    /// List of actions to take, using the format described in `fail::cfg`
+    ///
+    /// We also support `actions = "exit"` to cause the fail point to immediately exit.
+    pub actions: String,
 }
@@ -34,11 +34,16 @@ pub struct PageserverFeedback {
     // Serialize with RFC3339 format.
     #[serde(with = "serde_systemtime")]
     pub replytime: SystemTime,
+    /// Set when the pageserver is falling behind persisting the WAL it
+    /// received, asking the compute to throttle WAL generation until it
+    /// catches up.
+    #[serde(default)]
+    pub slow_down: bool,
 }
 
 // NOTE: Do not forget to increment this number when adding new fields to PageserverFeedback.
 // Do not remove previously available fields because this might be backwards incompatible.
-pub const PAGESERVER_FEEDBACK_FIELDS_NUMBER: u8 = 5;
+pub const PAGESERVER_FEEDBACK_FIELDS_NUMBER: u8 = 6;
 
 impl PageserverFeedback {
     pub fn empty() -> PageserverFeedback {
@@ -48,6 +53,7 @@ impl PageserverFeedback {
             remote_consistent_lsn: Lsn::INVALID,
             disk_consistent_lsn: Lsn::INVALID,
             replytime: *PG_EPOCH,
+            slow_down: false,
         }
     }
 
@@ -88,6 +94,10 @@ impl PageserverFeedback {
         buf.put_slice(b"ps_replytime\0");
         buf.put_i32(8);
         buf.put_i64(timestamp);
+
+        buf.put_slice(b"ps_slowdown\0");
+        buf.put_i32(8);
+        buf.put_u64(self.slow_down as u64);
     }
 
     // Deserialize PageserverFeedback message
@@ -128,6 +138,11 @@ impl PageserverFeedback {
                         rf.replytime = *PG_EPOCH - Duration::from_micros(-raw_time as u64);
                     }
                 }
+                b"ps_slowdown" => {
+                    let len = buf.get_i32();
+                    assert_eq!(len, 8);
+                    rf.slow_down = buf.get_u64() != 0;
+                }
                 _ => {
                     let len = buf.get_i32();
                     warn!(
@@ -177,6 +192,7 @@ mod tests {
         let mut rf = PageserverFeedback::empty();
         // Fill rf with some values
         rf.current_timeline_size = 12345678;
+        rf.slow_down = true;
         // Set rounded time to be able to compare it with deserialized value,
         // because it is rounded up to microseconds during serialization.
         rf.replytime = *PG_EPOCH + Duration::from_secs(100_000_000);
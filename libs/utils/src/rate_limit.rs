@@ -1,4 +1,4 @@
-//! A helper to rate limit operations.
+//! Helpers to rate limit operations.
 
 use std::time::{Duration, Instant};
 
@@ -31,6 +31,47 @@ impl RateLimit {
     }
 }
 
+/// A token bucket: lets through `rate` units per second on average, and up
+/// to `burst` units at once after a pause.
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// The bucket starts full. `rate` must be positive.
+    pub fn new(rate: u64, burst: u64) -> Self {
+        assert!(rate > 0, "token bucket rate must be positive");
+        Self {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take `n` units, going into debt if the bucket doesn't hold as many.
+    /// Returns how long to wait before using them for the average rate to
+    /// hold; zero if they are available right away.
+    pub fn take(&mut self, n: u64) -> Duration {
+        self.take_at(n, Instant::now())
+    }
+
+    fn take_at(&mut self, n: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = self.last.max(now);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.tokens -= n as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::atomic::AtomicUsize;
@@ -63,4 +104,27 @@ mod tests {
         f.call(cl);
         assert_eq!(called.load(Relaxed), 3);
     }
+
+    #[test]
+    fn token_bucket() {
+        use super::TokenBucket;
+        use std::time::{Duration, Instant};
+
+        let mut bucket = TokenBucket::new(1000, 500);
+        let start = Instant::now();
+        bucket.last = start;
+
+        // The burst goes through right away.
+        assert_eq!(bucket.take_at(500, start), Duration::ZERO);
+        // Then units are let through at the rate, in debt.
+        assert_eq!(bucket.take_at(250, start), Duration::from_millis(250));
+        assert_eq!(bucket.take_at(250, start), Duration::from_millis(500));
+        // The debt is paid off over time.
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bucket.take_at(0, later), Duration::ZERO);
+        // No more than the burst accumulates.
+        let much_later = later + Duration::from_secs(10);
+        assert_eq!(bucket.take_at(500, much_later), Duration::ZERO);
+        assert_eq!(bucket.take_at(100, much_later), Duration::from_millis(100));
+    }
 }
@@ -16,6 +16,7 @@ use pageserver::{
     walrecord::NeonWalRecord,
     walredo::{PostgresRedoManager, WalRedoError},
 };
+use postgres_ffi::pg_constants;
 use utils::{id::TenantId, lsn::Lsn};
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
@@ -484,6 +485,13 @@ impl Request {
             pg_version,
         } = self;
 
-        manager.request_redo(key, lsn, base_img, records, pg_version)
+        manager.request_redo(
+            key,
+            lsn,
+            base_img,
+            records,
+            pg_version,
+            pg_constants::MAX_REGIONS,
+        )
     }
 }
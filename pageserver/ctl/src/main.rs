@@ -3,10 +3,12 @@
 //! and prints its interpreted context.
 //!
 //! Separate, `metadata` subcommand allows to print and update pageserver's metadata file.
+//! The `wal dump` subcommand prints the decoded records of WAL segment files.
 
 mod draw_timeline_dir;
 mod layer_map_analyzer;
 mod layers;
+mod wal;
 
 use clap::{Parser, Subcommand};
 use layers::LayerCmd;
@@ -20,6 +22,7 @@ use pageserver::{
 use postgres_ffi::ControlFileData;
 use std::path::{Path, PathBuf};
 use utils::{lsn::Lsn, project_git_version};
+use wal::WalCmd;
 
 project_git_version!(GIT_VERSION);
 
@@ -43,6 +46,8 @@ enum Commands {
     AnalyzeLayerMap(AnalyzeLayerMapCmd),
     #[command(subcommand)]
     Layer(LayerCmd),
+    #[command(subcommand)]
+    Wal(WalCmd),
 }
 
 /// Read and update pageserver metadata file
@@ -80,6 +85,9 @@ async fn main() -> anyhow::Result<()> {
         Commands::Layer(cmd) => {
             layers::main(&cmd).await?;
         }
+        Commands::Wal(cmd) => {
+            wal::main(&cmd)?;
+        }
         Commands::Metadata(cmd) => {
             handle_metadata(&cmd)?;
         }
@@ -105,6 +113,7 @@ async fn main() -> anyhow::Result<()> {
 fn read_pg_control_file(control_file_path: &Path) -> anyhow::Result<()> {
     let control_file = ControlFileData::decode(&std::fs::read(control_file_path)?)?;
     println!("{control_file:?}");
+    println!("state: {}", control_file.state_name());
     let control_file_initdb = Lsn(control_file.checkPoint);
     println!(
         "pg_initdb_lsn: {}, aligned: {}",
@@ -117,7 +126,7 @@ fn read_pg_control_file(control_file_path: &Path) -> anyhow::Result<()> {
 async fn print_layerfile(path: &Path) -> anyhow::Result<()> {
     // Basic initialization of things that don't change after startup
     virtual_file::init(10);
-    page_cache::init(100);
+    page_cache::init(100, 100);
     let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
     dump_layerfile_from_path(path, true, &ctx).await
 }
@@ -144,6 +153,7 @@ fn handle_metadata(
             meta.initdb_lsn(),
             meta.pg_version(),
             meta.region_id(),
+            meta.purge_floor_lsn(),
         );
         update_meta = true;
     }
@@ -157,6 +167,7 @@ fn handle_metadata(
             meta.initdb_lsn(),
             meta.pg_version(),
             meta.region_id(),
+            meta.purge_floor_lsn(),
         );
         update_meta = true;
     }
@@ -170,6 +181,7 @@ fn handle_metadata(
             meta.initdb_lsn(),
             meta.pg_version(),
             meta.region_id(),
+            meta.purge_floor_lsn(),
         );
         update_meta = true;
     }
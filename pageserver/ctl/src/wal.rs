@@ -0,0 +1,244 @@
+//! Dump of WAL segment files, like pg_waldump, but with the decoder of
+//! postgres_ffi, to debug discrepancies between what Postgres wrote and what
+//! the pageserver ingested.
+//!
+//! Works on a directory of segments, such as a safekeeper timeline directory,
+//! a pageserver WAL archive directory or a pg_wal, or on a single segment file.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::Subcommand;
+use postgres_ffi::v14::bindings::XLogPageHeaderData;
+use postgres_ffi::v14::xlog_utils::{
+    normalize_lsn, IsPartialXLogFileName, IsXLogFileName, XLogFromFileName,
+    XLOG_SIZE_OF_XLOG_LONG_PHD,
+};
+use postgres_ffi::waldecoder::WalStreamDecoder;
+use postgres_ffi::walrecord::{rmgr_name, RelFileNode, WalRecord};
+use postgres_ffi::{pg_constants, XLogSegNo, WAL_SEGMENT_SIZE, XLOG_BLCKSZ};
+use postgres_ffi::{TransactionId, XLOG_SIZE_OF_XLOG_SHORT_PHD};
+use utils::lsn::Lsn;
+
+#[derive(Subcommand)]
+pub(crate) enum WalCmd {
+    /// Print the decoded records of WAL segment files
+    ///
+    /// Example: `cargo run --bin pagectl wal dump .neon/safekeepers/sk1/<tenant>/<timeline>`
+    Dump(WalDumpCmd),
+}
+
+#[derive(clap::Args)]
+pub(crate) struct WalDumpCmd {
+    /// Directory of WAL segment files, or a single segment file
+    path: PathBuf,
+    /// Postgres version that wrote the WAL
+    #[arg(long, default_value_t = pageserver::DEFAULT_PG_VERSION)]
+    pg_version: u32,
+    /// WAL segment size in bytes
+    #[arg(long, default_value_t = WAL_SEGMENT_SIZE)]
+    wal_seg_size: usize,
+    /// Start reading at this LSN, which must be the start of a record.
+    /// Defaults to the first record of the first segment.
+    #[arg(long)]
+    start_lsn: Option<Lsn>,
+    /// Stop reading at this LSN
+    #[arg(long)]
+    end_lsn: Option<Lsn>,
+    /// Only print the records of this resource manager, e.g. Heap or Btree
+    #[arg(long)]
+    rmgr: Option<String>,
+    /// Only print the records of this transaction
+    #[arg(long)]
+    xid: Option<TransactionId>,
+    /// Only print the records referencing this relation, as tablespace/database/relfilenode
+    #[arg(long, value_parser = parse_rel)]
+    rel: Option<RelFileNode>,
+    /// Only print the records referencing this block, of the relation given by --rel if any
+    #[arg(long)]
+    block: Option<u32>,
+}
+
+fn parse_rel(s: &str) -> Result<RelFileNode> {
+    let parts = s
+        .split('/')
+        .map(u32::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid relation {s:?}"))?;
+    let [spcnode, dbnode, relnode] = parts[..] else {
+        bail!("invalid relation {s:?}, expected tablespace/database/relfilenode");
+    };
+    Ok(RelFileNode {
+        spcnode,
+        dbnode,
+        relnode,
+    })
+}
+
+pub(crate) fn main(cmd: &WalCmd) -> Result<()> {
+    match cmd {
+        WalCmd::Dump(cmd) => dump(cmd),
+    }
+}
+
+impl WalDumpCmd {
+    fn matches(&self, record: &WalRecord) -> bool {
+        if let Some(rmgr) = &self.rmgr {
+            if !rmgr_name(record.xl_rmid).eq_ignore_ascii_case(rmgr) {
+                return false;
+            }
+        }
+        if let Some(xid) = self.xid {
+            if record.xl_xid != xid {
+                return false;
+            }
+        }
+        if self.rel.is_some() || self.block.is_some() {
+            return record.blocks.iter().any(|blk| {
+                self.rel.as_ref().map_or(true, |rel| blk.rnode == *rel)
+                    && self.block.map_or(true, |blkno| blk.blkno == blkno)
+            });
+        }
+        true
+    }
+}
+
+/// Lists the segment files under `path`, ordered by segment number. If there
+/// are several timelines of the same segment, the latest one is used.
+fn list_segments(path: &Path, wal_seg_size: usize) -> Result<Vec<(XLogSegNo, PathBuf)>> {
+    let files = if path.is_dir() {
+        fs::read_dir(path)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut segments = Vec::new();
+    for file in files {
+        let Some(fname) = file.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        if !IsXLogFileName(fname) && !IsPartialXLogFileName(fname) {
+            continue;
+        }
+        let (segno, tli) = XLogFromFileName(fname, wal_seg_size);
+        segments.push((segno, tli, file));
+    }
+    segments.sort();
+    // Keep the last, i.e. latest timeline, of each segment number.
+    segments.reverse();
+    segments.dedup_by_key(|(segno, _, _)| *segno);
+    segments.reverse();
+    Ok(segments
+        .into_iter()
+        .map(|(segno, _, file)| (segno, file))
+        .collect())
+}
+
+/// Finds the first record starting in a segment, skipping the end of a record
+/// continued from the previous segment.
+fn first_record_lsn(segment: &[u8], segno: XLogSegNo, wal_seg_size: usize) -> Result<Lsn> {
+    let seg_start = Lsn(segno * wal_seg_size as u64);
+    let hdr = XLogPageHeaderData::from_bytes(&mut &segment[..])?;
+    let mut lsn = seg_start + XLOG_SIZE_OF_XLOG_LONG_PHD as u64;
+    if hdr.xlp_info & pg_constants::XLP_FIRST_IS_CONTRECORD == 0 {
+        return Ok(lsn);
+    }
+
+    let mut rem_len = hdr.xlp_rem_len as u64;
+    loop {
+        let page_left = XLOG_BLCKSZ as u64 - lsn.block_offset();
+        if rem_len <= page_left {
+            return Ok(normalize_lsn(lsn + rem_len, wal_seg_size));
+        }
+        rem_len -= page_left;
+        lsn += page_left + XLOG_SIZE_OF_XLOG_SHORT_PHD as u64;
+        ensure!(
+            lsn.segment_number(wal_seg_size) == segno,
+            "no record starts in segment {segno}"
+        );
+    }
+}
+
+fn dump(cmd: &WalDumpCmd) -> Result<()> {
+    let wal_seg_size = cmd.wal_seg_size;
+    let segments = list_segments(&cmd.path, wal_seg_size)?;
+    let start_segno = match cmd.start_lsn {
+        Some(lsn) => lsn.segment_number(wal_seg_size),
+        None => match segments.first() {
+            Some((segno, _)) => *segno,
+            None => bail!("no WAL segment files in {}", cmd.path.display()),
+        },
+    };
+    let end_lsn = cmd.end_lsn.unwrap_or(Lsn::MAX);
+
+    let mut decoder: Option<WalStreamDecoder> = None;
+    let mut record_lsn = Lsn(0);
+    let mut expected_segno = start_segno;
+    'segments: for (segno, file) in segments.iter().filter(|(segno, _)| *segno >= start_segno) {
+        if *segno != expected_segno {
+            println!("segment {expected_segno} is missing, stopping");
+            break;
+        }
+        expected_segno += 1;
+
+        let segment =
+            fs::read(file).with_context(|| format!("could not read {}", file.display()))?;
+        let decoder = match decoder.as_mut() {
+            Some(decoder) => {
+                decoder.feed_bytes(&segment);
+                decoder
+            }
+            None => {
+                record_lsn = match cmd.start_lsn {
+                    Some(lsn) => lsn,
+                    None => first_record_lsn(&segment, *segno, wal_seg_size)?,
+                };
+                let offset = record_lsn.segment_offset(wal_seg_size);
+                ensure!(
+                    offset <= segment.len(),
+                    "{} is too short to contain {record_lsn}",
+                    file.display()
+                );
+                let mut new_decoder =
+                    WalStreamDecoder::new(record_lsn, cmd.pg_version, wal_seg_size);
+                new_decoder.feed_bytes(&segment[offset..]);
+                decoder.insert(new_decoder)
+            }
+        };
+
+        loop {
+            let (next_lsn, recdata) = match decoder.poll_decode() {
+                Ok(Some(res)) => res,
+                Ok(None) => break,
+                Err(e) => {
+                    println!("end of WAL: {e}");
+                    break 'segments;
+                }
+            };
+            if record_lsn >= end_lsn {
+                break 'segments;
+            }
+
+            let record = WalRecord::decode(recdata, cmd.pg_version)
+                .with_context(|| format!("could not decode record at {record_lsn}"))?;
+            if cmd.matches(&record) {
+                println!("lsn: {record_lsn}, {record}");
+            }
+
+            // A switch record is followed by the padding up to the end of the
+            // segment.
+            record_lsn = if record.xl_rmid == pg_constants::RM_XLOG_ID
+                && record.xl_info & pg_constants::XLR_RMGR_INFO_MASK == pg_constants::XLOG_SWITCH
+            {
+                let next_segno = record_lsn.segment_number(wal_seg_size) + 1;
+                normalize_lsn(Lsn(next_segno * wal_seg_size as u64), wal_seg_size)
+            } else {
+                normalize_lsn(next_lsn, wal_seg_size)
+            };
+        }
+    }
+    Ok(())
+}
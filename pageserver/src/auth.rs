@@ -2,13 +2,18 @@ use anyhow::{bail, Result};
 use utils::auth::{Claims, Scope};
 use utils::id::TenantId;
 
+/// Checks that the claims of a JWT allow access to the API of `tenant_id`, or
+/// to the management API of the whole pageserver if `tenant_id` is `None`.
+///
+/// A token of tenant scope only gives access to the API of its own tenant,
+/// a token of pageserverapi scope gives access to everything.
 pub fn check_permission(claims: &Claims, tenant_id: Option<TenantId>) -> Result<()> {
     match (&claims.scope, tenant_id) {
         (Scope::Tenant, None) => {
             bail!("Attempt to access management api with tenant scope. Permission denied")
         }
         (Scope::Tenant, Some(tenant_id)) => {
-            if claims.tenant_id.unwrap() != tenant_id {
+            if claims.tenant_id != Some(tenant_id) {
                 bail!("Tenant id mismatch. Permission denied")
             }
             Ok(())
@@ -20,3 +25,30 @@ pub fn check_permission(claims: &Claims, tenant_id: Option<TenantId>) -> Result<
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        let tenant_id = TenantId::generate();
+        let other_tenant_id = TenantId::generate();
+
+        let tenant = Claims::new(Some(tenant_id), Scope::Tenant);
+        assert!(check_permission(&tenant, Some(tenant_id)).is_ok());
+        assert!(check_permission(&tenant, Some(other_tenant_id)).is_err());
+        assert!(check_permission(&tenant, None).is_err());
+        // A tenant token without a tenant gives access to no tenant.
+        let no_tenant = Claims::new(None, Scope::Tenant);
+        assert!(check_permission(&no_tenant, Some(tenant_id)).is_err());
+
+        let pageserver = Claims::new(None, Scope::PageServerApi);
+        assert!(check_permission(&pageserver, Some(tenant_id)).is_ok());
+        assert!(check_permission(&pageserver, None).is_ok());
+
+        let safekeeper = Claims::new(None, Scope::SafekeeperData);
+        assert!(check_permission(&safekeeper, Some(tenant_id)).is_err());
+        assert!(check_permission(&safekeeper, None).is_err());
+    }
+}
@@ -11,7 +11,6 @@
 //! from data stored in object storage.
 //!
 use anyhow::{anyhow, bail, ensure, Context};
-use bytes::{BufMut, BytesMut};
 use fail::fail_point;
 use std::fmt::Write as FmtWrite;
 use std::time::SystemTime;
@@ -26,17 +25,22 @@ use crate::pgdatadir_mapping::Version;
 use crate::tenant::Timeline;
 use pageserver_api::reltag::{RelTag, SlruKind};
 
-use postgres_ffi::pg_constants::{DEFAULTTABLESPACE_OID, GLOBALTABLESPACE_OID};
+use postgres_ffi::pg_constants::{
+    DEFAULTTABLESPACE_OID, FIRST_NORMAL_OBJECT_ID, GLOBALTABLESPACE_OID,
+};
 use postgres_ffi::pg_constants::{PGDATA_SPECIAL_FILES, PGDATA_SUBDIRS, PG_HBA};
 use postgres_ffi::relfile_utils::{INIT_FORKNUM, MAIN_FORKNUM};
+use postgres_ffi::relmapper::RelMapFile;
+use postgres_ffi::twophase::twophase_state_data_to_file;
 use postgres_ffi::TransactionId;
 use postgres_ffi::XLogFileName;
 use postgres_ffi::PG_TLI;
-use postgres_ffi::{BLCKSZ, RELSEG_SIZE, WAL_SEGMENT_SIZE};
+use postgres_ffi::{BLCKSZ, RELSEG_SIZE};
 use utils::lsn::Lsn;
 
 /// Create basebackup with non-rel data in it.
-/// Only include relational data if 'full_backup' is true.
+/// Only include relational data if 'full_backup' is true, or only that of the
+/// system catalogs if 'include_catalog' is true.
 ///
 /// Currently we use empty 'req_lsn' in two cases:
 ///  * During the basebackup right after timeline creation
@@ -49,6 +53,7 @@ pub async fn send_basebackup_tarball<'a, W>(
     req_lsn: Option<Lsn>,
     prev_lsn: Option<Lsn>,
     full_backup: bool,
+    include_catalog: bool,
     ctx: &'a RequestContext,
 ) -> anyhow::Result<()>
 where
@@ -98,8 +103,8 @@ where
     };
 
     info!(
-        "taking basebackup lsn={}, prev_lsn={} (full_backup={})",
-        backup_lsn, prev_lsn, full_backup
+        "taking basebackup lsn={}, prev_lsn={} (full_backup={}, include_catalog={})",
+        backup_lsn, prev_lsn, full_backup, include_catalog
     );
 
     let basebackup = Basebackup {
@@ -108,6 +113,7 @@ where
         lsn: backup_lsn,
         prev_record_lsn: prev_lsn,
         full_backup,
+        include_catalog,
         ctx,
     };
     basebackup
@@ -128,6 +134,7 @@ where
     lsn: Lsn,
     prev_record_lsn: Lsn,
     full_backup: bool,
+    include_catalog: bool,
     ctx: &'a RequestContext,
 }
 
@@ -171,6 +178,7 @@ where
             SlruKind::MultiXactOffsets,
             SlruKind::MultiXactMembers,
             SlruKind::Csn,
+            SlruKind::CommitTs,
         ] {
             for segno in self
                 .timeline
@@ -187,8 +195,9 @@ where
         {
             self.add_dbdir(spcnode, dbnode, has_relmap_file).await?;
 
-            // If full backup is requested, include all relation files.
-            // Otherwise only include init forks of unlogged relations.
+            // If full backup is requested, include all relation files, or
+            // those of the system catalogs if requested. Otherwise only
+            // include init forks of unlogged relations.
             let rels = self
                 .timeline
                 .list_rels(spcnode, dbnode, Version::Lsn(self.lsn), self.ctx)
@@ -205,7 +214,7 @@ where
                     continue;
                 }
 
-                if self.full_backup {
+                if self.full_backup || (self.include_catalog && is_catalog_rel(&rel)) {
                     if rel.forknum == MAIN_FORKNUM && rels.contains(&rel.with_forknum(INIT_FORKNUM))
                     {
                         // skip this, will include it when we reach the init fork
@@ -325,7 +334,9 @@ where
                 .timeline
                 .get_relmap_file(spcnode, dbnode, Version::Lsn(self.lsn), self.ctx)
                 .await?;
-            ensure!(img.len() == 512);
+            RelMapFile::decode(&img).with_context(|| {
+                format!("invalid pg_filenode.map of tablespace {spcnode} database {dbnode}")
+            })?;
             Some(img)
         } else {
             None
@@ -397,10 +408,7 @@ where
             .get_twophase_file(xid, self.lsn, self.ctx)
             .await?;
 
-        let mut buf = BytesMut::new();
-        buf.extend_from_slice(&img[..]);
-        let crc = crc32c::crc32c(&img[..]);
-        buf.put_u32_le(crc);
+        let buf = twophase_state_data_to_file(&img);
         let path = format!("pg_twophase/{:>08X}", xid);
         let header = new_tar_header(&path, buf.len() as u64)?;
         self.ar.append(&header, &buf[..]).await?;
@@ -441,6 +449,7 @@ where
             .get_control_file(self.lsn, self.ctx)
             .await
             .context("failed get control bytes")?;
+        let wal_seg_size = postgres_ffi::wal_seg_size(&pg_control_bytes, self.timeline.pg_version)?;
 
         let (pg_control_bytes, system_identifier) = postgres_ffi::generate_pg_control(
             &pg_control_bytes,
@@ -454,19 +463,20 @@ where
         self.ar.append(&header, &pg_control_bytes[..]).await?;
 
         //send wal segment
-        let segno = self.lsn.segment_number(WAL_SEGMENT_SIZE);
-        let wal_file_name = XLogFileName(PG_TLI, segno, WAL_SEGMENT_SIZE);
+        let segno = self.lsn.segment_number(wal_seg_size);
+        let wal_file_name = XLogFileName(PG_TLI, segno, wal_seg_size);
         let wal_file_path = format!("pg_wal/{}", wal_file_name);
-        let header = new_tar_header(&wal_file_path, WAL_SEGMENT_SIZE as u64)?;
+        let header = new_tar_header(&wal_file_path, wal_seg_size as u64)?;
 
         let wal_seg = postgres_ffi::generate_wal_segment(
             segno,
             system_identifier,
             self.timeline.pg_version,
             self.lsn,
+            wal_seg_size,
         )
         .map_err(|e| anyhow!(e).context("Failed generating wal segment"))?;
-        ensure!(wal_seg.len() == WAL_SEGMENT_SIZE);
+        ensure!(wal_seg.len() == wal_seg_size);
         self.ar.append(&header, &wal_seg[..]).await?;
         Ok(())
     }
@@ -475,6 +485,13 @@ where
 //
 // Create new tarball entry header
 //
+/// System catalogs are created by initdb with relfilenodes below the first
+/// normal object id. A catalog rewritten later, e.g. by VACUUM FULL, gets a
+/// normal relfilenode and is left out, like the user relations.
+fn is_catalog_rel(rel: &RelTag) -> bool {
+    rel.relnode < FIRST_NORMAL_OBJECT_ID
+}
+
 fn new_tar_header(path: &str, size: u64) -> anyhow::Result<Header> {
     let mut header = Header::new_gnu();
     header.set_size(size);
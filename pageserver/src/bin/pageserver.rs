@@ -125,7 +125,10 @@ fn main() -> anyhow::Result<()> {
 
     // Basic initialization of things that don't change after startup
     virtual_file::init(conf.max_file_descriptors);
-    page_cache::init(conf.page_cache_size);
+    page_cache::init(
+        conf.page_cache_size,
+        conf.max_page_cache_size.unwrap_or(conf.page_cache_size),
+    );
 
     start_pageserver(launch_ts, conf).context("Failed to start pageserver")?;
 
@@ -24,11 +24,12 @@ use toml_edit::{Document, Item};
 
 use postgres_backend::AuthType;
 use utils::{
-    id::{NodeId, TenantId, TimelineId},
+    id::{NodeId, RegionId, TenantId, TimelineId},
     logging::LogFormat,
 };
 
 use crate::disk_usage_eviction_task::DiskUsageEvictionTaskConfig;
+use crate::tenant::blob_io::BlobCompression;
 use crate::tenant::config::TenantConf;
 use crate::tenant::config::TenantConfOpt;
 use crate::tenant::{
@@ -36,7 +37,7 @@ use crate::tenant::{
 };
 use crate::{
     IGNORED_TENANT_FILE_NAME, METADATA_FILE_NAME, TENANT_CONFIG_NAME, TIMELINE_DELETE_MARK_SUFFIX,
-    TIMELINE_UNINIT_MARK_SUFFIX,
+    TIMELINE_UNINIT_MARK_SUFFIX, WAL_RECEIVER_STATE_FILE_NAME,
 };
 
 pub mod defaults {
@@ -69,6 +70,15 @@ pub mod defaults {
     pub const DEFAULT_BACKGROUND_TASK_MAXIMUM_DELAY: &str = "10s";
 
     pub const DEFAULT_INGEST_BATCH_SIZE: u64 = 100;
+    pub const DEFAULT_INGEST_PARALLELISM: usize = 1;
+
+    pub const DEFAULT_BASEBACKUP_ZSTD_LEVEL: i32 = 1;
+
+    pub const DEFAULT_LAYER_COMPRESSION: &str = "none";
+
+    pub const DEFAULT_INGEST_BACKPRESSURE_LAG: u64 = 1024 * 1024 * 1024;
+
+    pub const DEFAULT_WAL_RECEIVER_COMPRESSION: bool = false;
 
     ///
     /// Default built-in configuration file.
@@ -83,6 +93,7 @@ pub mod defaults {
 #wal_redo_timeout = '{DEFAULT_WAL_REDO_TIMEOUT}'
 
 #page_cache_size = {DEFAULT_PAGE_CACHE_SIZE}
+#max_page_cache_size = <page_cache_size>
 #max_file_descriptors = {DEFAULT_MAX_FILE_DESCRIPTORS}
 
 # initial superuser role name to use when creating a new tenant
@@ -103,6 +114,18 @@ pub mod defaults {
 #background_task_maximum_delay = '{DEFAULT_BACKGROUND_TASK_MAXIMUM_DELAY}'
 
 #ingest_batch_size = {DEFAULT_INGEST_BATCH_SIZE}
+#ingest_parallelism = {DEFAULT_INGEST_PARALLELISM}
+
+#basebackup_zstd_level = {DEFAULT_BASEBACKUP_ZSTD_LEVEL}
+
+#layer_compression = '{DEFAULT_LAYER_COMPRESSION}' # or 'zstd', 'zstd:<level>'
+
+#ingest_backpressure_lag = {DEFAULT_INGEST_BACKPRESSURE_LAG} # in bytes
+
+#wal_archive_dir = '<path>' # ingest WAL of timelines from <path>/<tenant_id>/<timeline_id>/
+
+#wal_receiver_compression = {DEFAULT_WAL_RECEIVER_COMPRESSION}
+#wal_receiver_region = <region id> # ask safekeepers to filter the WAL of other regions for this one
 
 [tenant_config]
 #checkpoint_distance = {DEFAULT_CHECKPOINT_DISTANCE} # in bytes
@@ -119,6 +142,11 @@ pub mod defaults {
 #min_resident_size_override = .. # in bytes
 #evictions_low_residence_duration_metric_threshold = '{DEFAULT_EVICTIONS_LOW_RESIDENCE_DURATION_METRIC_THRESHOLD}'
 #gc_feedback = false
+#physical_size_quota = .. # in bytes
+#max_concurrent_getpage_requests = ..
+#wal_ingest_rate_limit = .. # in bytes per second
+#hot_range_image_creation_threshold = ..
+#max_regions = {DEFAULT_MAX_REGIONS}
 
 [remote_storage]
 
@@ -148,6 +176,9 @@ pub struct PageServerConf {
     pub superuser: String,
 
     pub page_cache_size: usize,
+    /// The page cache can be resized at runtime up to this many pages, the
+    /// buffers for them are allocated at startup. `page_cache_size` if `None`.
+    pub max_page_cache_size: Option<usize>,
     pub max_file_descriptors: usize,
 
     // Repository directory, relative to current working directory.
@@ -165,7 +196,8 @@ pub struct PageServerConf {
     pub http_auth_type: AuthType,
     /// authentication method for libpq connections from compute
     pub pg_auth_type: AuthType,
-    /// Path to a file containing public key for verifying JWT tokens.
+    /// Path to a file containing public key for verifying JWT tokens, or to a
+    /// directory of `<key id>.pem` public keys, to accept tokens from any of.
     /// Used for both mgmt and compute auth, if enabled.
     pub auth_validation_public_key_path: Option<PathBuf>,
 
@@ -212,6 +244,44 @@ pub struct PageServerConf {
 
     /// Maximum number of WAL records to be ingested and committed at the same time
     pub ingest_batch_size: u64,
+
+    /// Number of workers applying a batch of ingested WAL records to the
+    /// in-memory layer of a timeline in parallel. The layer is split by key
+    /// into as many shards, each written by one worker, so the versions of a
+    /// page are still written in LSN order. 1 applies the batches serially.
+    pub ingest_parallelism: usize,
+
+    /// Compression level of the basebackups compressed with zstd, from 1 to 22.
+    /// Basebackups are on the critical path of compute startup, so the default
+    /// is the fastest level.
+    pub basebackup_zstd_level: i32,
+
+    /// Compression of the values in the delta and image layer files written
+    /// from now on. Each value is compressed on its own, so that random reads
+    /// stay cheap. The layer files written before are read either way.
+    pub layer_compression: BlobCompression,
+
+    /// When the WAL received for a timeline is more than this many bytes ahead
+    /// of what is persisted to disk, the compute is asked to slow down through
+    /// the replication feedback, instead of the in-memory layers growing
+    /// without bounds. Zero disables the backpressure.
+    pub ingest_backpressure_lag: u64,
+
+    /// Directory of archived WAL segments, laid out as
+    /// `<wal_archive_dir>/<tenant_id>/<timeline_id>/<WAL segment files>`.
+    /// Timelines with a directory there ingest their WAL from it instead of
+    /// streaming it from the safekeepers.
+    pub wal_archive_dir: Option<PathBuf>,
+
+    /// Ask the safekeepers to compress the WAL they stream to us with zstd,
+    /// e.g. when it crosses a WAN link. Safekeepers that don't advertise the
+    /// support in the broker stream it uncompressed regardless.
+    pub wal_receiver_compression: bool,
+
+    /// Region this pageserver runs in, in a multi-region deployment. The WAL
+    /// of the timelines of other regions is then requested filtered for this
+    /// region, from the safekeepers that support it.
+    pub wal_receiver_region: Option<RegionId>,
 }
 
 /// We do not want to store this in a PageServerConf because the latter may be logged
@@ -252,6 +322,7 @@ struct PageServerConfigBuilder {
     superuser: BuilderValue<String>,
 
     page_cache_size: BuilderValue<usize>,
+    max_page_cache_size: BuilderValue<Option<usize>>,
     max_file_descriptors: BuilderValue<usize>,
 
     workdir: BuilderValue<PathBuf>,
@@ -288,6 +359,16 @@ struct PageServerConfigBuilder {
     background_task_maximum_delay: BuilderValue<Duration>,
 
     ingest_batch_size: BuilderValue<u64>,
+    ingest_parallelism: BuilderValue<usize>,
+
+    basebackup_zstd_level: BuilderValue<i32>,
+    layer_compression: BuilderValue<BlobCompression>,
+    ingest_backpressure_lag: BuilderValue<u64>,
+
+    wal_archive_dir: BuilderValue<Option<PathBuf>>,
+
+    wal_receiver_compression: BuilderValue<bool>,
+    wal_receiver_region: BuilderValue<Option<RegionId>>,
 }
 
 impl Default for PageServerConfigBuilder {
@@ -304,6 +385,7 @@ impl Default for PageServerConfigBuilder {
                 .expect("cannot parse default wal redo timeout")),
             superuser: Set(DEFAULT_SUPERUSER.to_string()),
             page_cache_size: Set(DEFAULT_PAGE_CACHE_SIZE),
+            max_page_cache_size: Set(None),
             max_file_descriptors: Set(DEFAULT_MAX_FILE_DESCRIPTORS),
             workdir: Set(PathBuf::new()),
             pg_distrib_dir: Set(env::current_dir()
@@ -352,6 +434,18 @@ impl Default for PageServerConfigBuilder {
             .unwrap()),
 
             ingest_batch_size: Set(DEFAULT_INGEST_BATCH_SIZE),
+            ingest_parallelism: Set(DEFAULT_INGEST_PARALLELISM),
+
+            basebackup_zstd_level: Set(DEFAULT_BASEBACKUP_ZSTD_LEVEL),
+            layer_compression: Set(DEFAULT_LAYER_COMPRESSION
+                .parse()
+                .expect("cannot parse default layer compression")),
+            ingest_backpressure_lag: Set(DEFAULT_INGEST_BACKPRESSURE_LAG),
+
+            wal_archive_dir: Set(None),
+
+            wal_receiver_compression: Set(DEFAULT_WAL_RECEIVER_COMPRESSION),
+            wal_receiver_region: Set(None),
         }
     }
 }
@@ -385,6 +479,10 @@ impl PageServerConfigBuilder {
         self.page_cache_size = BuilderValue::Set(page_cache_size)
     }
 
+    pub fn max_page_cache_size(&mut self, max_page_cache_size: Option<usize>) {
+        self.max_page_cache_size = BuilderValue::Set(max_page_cache_size)
+    }
+
     pub fn max_file_descriptors(&mut self, max_file_descriptors: usize) {
         self.max_file_descriptors = BuilderValue::Set(max_file_descriptors)
     }
@@ -484,6 +582,34 @@ impl PageServerConfigBuilder {
         self.ingest_batch_size = BuilderValue::Set(ingest_batch_size)
     }
 
+    pub fn ingest_parallelism(&mut self, ingest_parallelism: usize) {
+        self.ingest_parallelism = BuilderValue::Set(ingest_parallelism)
+    }
+
+    pub fn basebackup_zstd_level(&mut self, basebackup_zstd_level: i32) {
+        self.basebackup_zstd_level = BuilderValue::Set(basebackup_zstd_level)
+    }
+
+    pub fn layer_compression(&mut self, layer_compression: BlobCompression) {
+        self.layer_compression = BuilderValue::Set(layer_compression)
+    }
+
+    pub fn ingest_backpressure_lag(&mut self, ingest_backpressure_lag: u64) {
+        self.ingest_backpressure_lag = BuilderValue::Set(ingest_backpressure_lag)
+    }
+
+    pub fn wal_archive_dir(&mut self, wal_archive_dir: Option<PathBuf>) {
+        self.wal_archive_dir = BuilderValue::Set(wal_archive_dir)
+    }
+
+    pub fn wal_receiver_compression(&mut self, wal_receiver_compression: bool) {
+        self.wal_receiver_compression = BuilderValue::Set(wal_receiver_compression)
+    }
+
+    pub fn wal_receiver_region(&mut self, wal_receiver_region: Option<RegionId>) {
+        self.wal_receiver_region = BuilderValue::Set(wal_receiver_region)
+    }
+
     pub fn build(self) -> anyhow::Result<PageServerConf> {
         let concurrent_tenant_size_logical_size_queries = self
             .concurrent_tenant_size_logical_size_queries
@@ -510,6 +636,9 @@ impl PageServerConfigBuilder {
             page_cache_size: self
                 .page_cache_size
                 .ok_or(anyhow!("missing page_cache_size"))?,
+            max_page_cache_size: self
+                .max_page_cache_size
+                .ok_or(anyhow!("missing max_page_cache_size"))?,
             max_file_descriptors: self
                 .max_file_descriptors
                 .ok_or(anyhow!("missing max_file_descriptors"))?,
@@ -572,6 +701,27 @@ impl PageServerConfigBuilder {
             ingest_batch_size: self
                 .ingest_batch_size
                 .ok_or(anyhow!("missing ingest_batch_size"))?,
+            ingest_parallelism: self
+                .ingest_parallelism
+                .ok_or(anyhow!("missing ingest_parallelism"))?,
+            basebackup_zstd_level: self
+                .basebackup_zstd_level
+                .ok_or(anyhow!("missing basebackup_zstd_level"))?,
+            layer_compression: self
+                .layer_compression
+                .ok_or(anyhow!("missing layer_compression"))?,
+            ingest_backpressure_lag: self
+                .ingest_backpressure_lag
+                .ok_or(anyhow!("missing ingest_backpressure_lag"))?,
+            wal_archive_dir: self
+                .wal_archive_dir
+                .ok_or(anyhow!("missing wal_archive_dir"))?,
+            wal_receiver_compression: self
+                .wal_receiver_compression
+                .ok_or(anyhow!("missing wal_receiver_compression"))?,
+            wal_receiver_region: self
+                .wal_receiver_region
+                .ok_or(anyhow!("missing wal_receiver_region"))?,
         })
     }
 }
@@ -639,6 +789,19 @@ impl PageServerConf {
             .join(TENANT_DELETED_MARKER_FILE_NAME)
     }
 
+    /// Directory of the archived WAL segments of the timeline, if the WAL
+    /// archive is configured.
+    pub fn timeline_wal_archive_path(
+        &self,
+        tenant_id: &TenantId,
+        timeline_id: &TimelineId,
+    ) -> Option<PathBuf> {
+        self.wal_archive_dir.as_ref().map(|dir| {
+            dir.join(tenant_id.to_string())
+                .join(timeline_id.to_string())
+        })
+    }
+
     pub fn traces_path(&self) -> PathBuf {
         self.workdir.join("traces")
     }
@@ -662,6 +825,17 @@ impl PageServerConf {
             .join(METADATA_FILE_NAME)
     }
 
+    /// Points to the file where the WAL streaming connection of the timeline
+    /// is saved at graceful shutdown.
+    pub fn wal_receiver_state_path(
+        &self,
+        tenant_id: &TenantId,
+        timeline_id: &TimelineId,
+    ) -> PathBuf {
+        self.timeline_path(tenant_id, timeline_id)
+            .join(WAL_RECEIVER_STATE_FILE_NAME)
+    }
+
     /// Files on the remote storage are stored with paths, relative to the workdir.
     /// That path includes in itself both tenant and timeline ids, allowing to have a unique remote storage path.
     ///
@@ -731,6 +905,9 @@ impl PageServerConf {
                 "wal_redo_timeout" => builder.wal_redo_timeout(parse_toml_duration(key, item)?),
                 "initial_superuser_name" => builder.superuser(parse_toml_string(key, item)?),
                 "page_cache_size" => builder.page_cache_size(parse_toml_u64(key, item)? as usize),
+                "max_page_cache_size" => {
+                    builder.max_page_cache_size(Some(parse_toml_u64(key, item)? as usize))
+                }
                 "max_file_descriptors" => {
                     builder.max_file_descriptors(parse_toml_u64(key, item)? as usize)
                 }
@@ -778,12 +955,41 @@ impl PageServerConf {
                 "ondemand_download_behavior_treat_error_as_warn" => builder.ondemand_download_behavior_treat_error_as_warn(parse_toml_bool(key, item)?),
                 "background_task_maximum_delay" => builder.background_task_maximum_delay(parse_toml_duration(key, item)?),
                 "ingest_batch_size" => builder.ingest_batch_size(parse_toml_u64(key, item)?),
+                "ingest_parallelism" => builder.ingest_parallelism({
+                    let parallelism = parse_toml_u64(key, item)?;
+                    ensure!(parallelism >= 1, "ingest_parallelism must be at least 1");
+                    parallelism as usize
+                }),
+                "basebackup_zstd_level" => builder.basebackup_zstd_level({
+                    let level = parse_toml_u64(key, item)?;
+                    ensure!((1..=22).contains(&level), "basebackup_zstd_level must be between 1 and 22");
+                    level as i32
+                }),
+                "layer_compression" => builder.layer_compression(
+                    parse_toml_string(key, item)?
+                        .parse()
+                        .context("failed to parse 'layer_compression'")?,
+                ),
+                "ingest_backpressure_lag" => builder.ingest_backpressure_lag(parse_toml_u64(key, item)?),
+                "wal_archive_dir" => builder.wal_archive_dir(Some(workdir.join(parse_toml_string(key, item)?))),
+                "wal_receiver_compression" => builder.wal_receiver_compression(parse_toml_bool(key, item)?),
+                "wal_receiver_region" => builder.wal_receiver_region(Some(RegionId(
+                    u8::try_from(parse_toml_u64(key, item)?).context("region id out of range")?,
+                ))),
                 _ => bail!("unrecognized pageserver option '{key}'"),
             }
         }
 
         let mut conf = builder.build().context("invalid config")?;
 
+        if let Some(max_page_cache_size) = conf.max_page_cache_size {
+            ensure!(
+                max_page_cache_size >= conf.page_cache_size,
+                "max_page_cache_size {max_page_cache_size} is less than page_cache_size {}",
+                conf.page_cache_size
+            );
+        }
+
         if conf.http_auth_type == AuthType::NeonJWT || conf.pg_auth_type == AuthType::NeonJWT {
             let auth_validation_public_key_path = conf
                 .auth_validation_public_key_path
@@ -904,6 +1110,39 @@ impl PageServerConf {
             );
         }
 
+        if let Some(item) = item.get("physical_size_quota") {
+            t_conf.physical_size_quota = Some(
+                deserialize_from_item("physical_size_quota", item)
+                    .context("parse physical_size_quota")?,
+            );
+        }
+
+        if let Some(item) = item.get("max_concurrent_getpage_requests") {
+            t_conf.max_concurrent_getpage_requests = Some(
+                deserialize_from_item("max_concurrent_getpage_requests", item)
+                    .context("parse max_concurrent_getpage_requests")?,
+            );
+        }
+
+        if let Some(item) = item.get("wal_ingest_rate_limit") {
+            t_conf.wal_ingest_rate_limit = Some(
+                deserialize_from_item("wal_ingest_rate_limit", item)
+                    .context("parse wal_ingest_rate_limit")?,
+            );
+        }
+
+        if let Some(item) = item.get("hot_range_image_creation_threshold") {
+            t_conf.hot_range_image_creation_threshold = Some(
+                deserialize_from_item("hot_range_image_creation_threshold", item)
+                    .context("parse hot_range_image_creation_threshold")?,
+            );
+        }
+
+        if let Some(item) = item.get("max_regions") {
+            t_conf.max_regions =
+                Some(deserialize_from_item("max_regions", item).context("parse max_regions")?);
+        }
+
         Ok(t_conf)
     }
 
@@ -920,6 +1159,7 @@ impl PageServerConf {
             wait_lsn_timeout: Duration::from_secs(60),
             wal_redo_timeout: Duration::from_secs(60),
             page_cache_size: defaults::DEFAULT_PAGE_CACHE_SIZE,
+            max_page_cache_size: None,
             max_file_descriptors: defaults::DEFAULT_MAX_FILE_DESCRIPTORS,
             listen_pg_addr: defaults::DEFAULT_PG_LISTEN_ADDR.to_string(),
             listen_http_addr: defaults::DEFAULT_HTTP_LISTEN_ADDR.to_string(),
@@ -947,6 +1187,13 @@ impl PageServerConf {
             ondemand_download_behavior_treat_error_as_warn: false,
             background_task_maximum_delay: Duration::ZERO,
             ingest_batch_size: defaults::DEFAULT_INGEST_BATCH_SIZE,
+            ingest_parallelism: defaults::DEFAULT_INGEST_PARALLELISM,
+            basebackup_zstd_level: defaults::DEFAULT_BASEBACKUP_ZSTD_LEVEL,
+            layer_compression: BlobCompression::None,
+            ingest_backpressure_lag: defaults::DEFAULT_INGEST_BACKPRESSURE_LAG,
+            wal_archive_dir: None,
+            wal_receiver_compression: defaults::DEFAULT_WAL_RECEIVER_COMPRESSION,
+            wal_receiver_region: None,
         }
     }
 }
@@ -1076,7 +1323,7 @@ impl ConfigurableSemaphore {
 mod tests {
     use std::{
         fs,
-        num::{NonZeroU32, NonZeroUsize},
+        num::{NonZeroU32, NonZeroU64, NonZeroUsize},
     };
 
     use remote_storage::{RemoteStorageKind, S3Config};
@@ -1096,6 +1343,7 @@ wait_lsn_timeout = '111 s'
 wal_redo_timeout = '111 s'
 
 page_cache_size = 444
+max_page_cache_size = 555
 max_file_descriptors = 333
 
 # initial superuser role name to use when creating a new tenant
@@ -1110,6 +1358,14 @@ synthetic_size_calculation_interval = '333 s'
 log_format = 'json'
 background_task_maximum_delay = '334 s'
 
+ingest_parallelism = 4
+basebackup_zstd_level = 3
+layer_compression = 'zstd:3'
+ingest_backpressure_lag = 104857600
+wal_archive_dir = '/wal_archive'
+wal_receiver_compression = true
+wal_receiver_region = 1
+
 "#;
 
     #[test]
@@ -1138,6 +1394,7 @@ background_task_maximum_delay = '334 s'
                 wal_redo_timeout: humantime::parse_duration(defaults::DEFAULT_WAL_REDO_TIMEOUT)?,
                 superuser: defaults::DEFAULT_SUPERUSER.to_string(),
                 page_cache_size: defaults::DEFAULT_PAGE_CACHE_SIZE,
+                max_page_cache_size: None,
                 max_file_descriptors: defaults::DEFAULT_MAX_FILE_DESCRIPTORS,
                 workdir,
                 pg_distrib_dir,
@@ -1171,6 +1428,13 @@ background_task_maximum_delay = '334 s'
                     defaults::DEFAULT_BACKGROUND_TASK_MAXIMUM_DELAY
                 )?,
                 ingest_batch_size: defaults::DEFAULT_INGEST_BATCH_SIZE,
+                ingest_parallelism: defaults::DEFAULT_INGEST_PARALLELISM,
+                basebackup_zstd_level: defaults::DEFAULT_BASEBACKUP_ZSTD_LEVEL,
+                layer_compression: BlobCompression::None,
+                ingest_backpressure_lag: defaults::DEFAULT_INGEST_BACKPRESSURE_LAG,
+                wal_archive_dir: None,
+                wal_receiver_compression: defaults::DEFAULT_WAL_RECEIVER_COMPRESSION,
+                wal_receiver_region: None,
             },
             "Correct defaults should be used when no config values are provided"
         );
@@ -1204,6 +1468,7 @@ background_task_maximum_delay = '334 s'
                 wal_redo_timeout: Duration::from_secs(111),
                 superuser: "zzzz".to_string(),
                 page_cache_size: 444,
+                max_page_cache_size: Some(555),
                 max_file_descriptors: 333,
                 workdir,
                 pg_distrib_dir,
@@ -1227,6 +1492,13 @@ background_task_maximum_delay = '334 s'
                 ondemand_download_behavior_treat_error_as_warn: false,
                 background_task_maximum_delay: Duration::from_secs(334),
                 ingest_batch_size: 100,
+                ingest_parallelism: 4,
+                basebackup_zstd_level: 3,
+                layer_compression: BlobCompression::Zstd { level: 3 },
+                ingest_backpressure_lag: 104857600,
+                wal_archive_dir: Some(PathBuf::from("/wal_archive")),
+                wal_receiver_compression: true,
+                wal_receiver_region: Some(RegionId(1)),
             },
             "Should be able to parse all basic config values correctly"
         );
@@ -1374,7 +1646,12 @@ pg_distrib_dir='{}'
 broker_endpoint = '{broker_endpoint}'
 
 [tenant_config]
-trace_read_requests = {trace_read_requests}"#,
+trace_read_requests = {trace_read_requests}
+physical_size_quota = 1073741824
+max_concurrent_getpage_requests = 8
+wal_ingest_rate_limit = 10485760
+hot_range_image_creation_threshold = 100
+max_regions = 16"#,
             pg_distrib_dir.display(),
         );
 
@@ -1385,6 +1662,26 @@ trace_read_requests = {trace_read_requests}"#,
             conf.default_tenant_conf.trace_read_requests, trace_read_requests,
             "Tenant config from pageserver config file should be parsed and udpated values used as defaults for all tenants",
         );
+        assert_eq!(
+            conf.default_tenant_conf.physical_size_quota,
+            Some(1024 * 1024 * 1024)
+        );
+        assert_eq!(
+            conf.default_tenant_conf.max_concurrent_getpage_requests,
+            NonZeroUsize::new(8)
+        );
+        assert_eq!(
+            conf.default_tenant_conf.wal_ingest_rate_limit,
+            NonZeroU64::new(10 * 1024 * 1024)
+        );
+        assert_eq!(
+            conf.default_tenant_conf.hot_range_image_creation_threshold,
+            NonZeroU64::new(100)
+        );
+        assert_eq!(
+            conf.default_tenant_conf.max_regions,
+            NonZeroU32::new(16).unwrap()
+        );
 
         Ok(())
     }
@@ -1,3 +1,4 @@
+mod progress;
 pub mod routes;
 pub use routes::make_router;
 
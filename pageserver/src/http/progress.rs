@@ -0,0 +1,123 @@
+//!
+//! Progress frames of the long-running management requests.
+//!
+//! Timeline creation and the imports can run for minutes. Made with
+//! `progress=true`, their handlers answer right away with `200 OK` and a body
+//! of newline-delimited JSON [`ProgressFrame`]s: a `progress` frame every few
+//! seconds while the operation runs, with how far it got if it can tell, and a
+//! `done` frame at the end, with the status and body that the request answers
+//! with otherwise.
+//!
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Response, StatusCode};
+use pageserver_api::models::ProgressFrame;
+use tracing::*;
+use utils::http::error::{api_error_handler, ApiError};
+
+/// How often to send a progress frame.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs `operation` in a task of its own, and answers with the stream of its
+/// progress frames. `message` is called for each progress frame, to tell how
+/// far the operation got.
+///
+/// The operation runs to completion even if the client goes away, like the
+/// handlers do without `progress=true`.
+pub(super) fn with_progress<F, M>(operation: F, message: M) -> Result<Response<Body>, ApiError>
+where
+    F: Future<Output = Result<Response<Body>, ApiError>> + Send + 'static,
+    M: Fn() -> Option<String> + Send + 'static,
+{
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(
+        async move {
+            let started = Instant::now();
+            let mut operation = std::pin::pin!(operation);
+            let mut interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + PROGRESS_INTERVAL,
+                PROGRESS_INTERVAL,
+            );
+            let result = loop {
+                tokio::select! {
+                    result = &mut operation => break result,
+                    _ = interval.tick() => {
+                        let frame = ProgressFrame::Progress {
+                            elapsed_secs: started.elapsed().as_secs(),
+                            message: message(),
+                        };
+                        // Don't wait for a client that doesn't read, the
+                        // operation isn't polled meanwhile.
+                        if sender.try_send_data(frame_line(&frame)).is_err() {
+                            debug!("progress frame not sent, the client is not reading");
+                        }
+                    }
+                }
+            };
+
+            let response = result.unwrap_or_else(api_error_handler);
+            let status = response.status().as_u16();
+            let body = match hyper::body::to_bytes(response.into_body()).await {
+                Ok(bytes) if bytes.is_empty() => serde_json::Value::Null,
+                Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    warn!("response body of the operation is not JSON: {e}");
+                    serde_json::Value::Null
+                }),
+                Err(e) => {
+                    warn!("failed to read the response body of the operation: {e}");
+                    serde_json::Value::Null
+                }
+            };
+            if sender
+                .send_data(frame_line(&ProgressFrame::Done { status, body }))
+                .await
+                .is_err()
+            {
+                info!("client went away before the operation was done");
+            }
+        }
+        .in_current_span(),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
+/// The stream of a request body, counting the bytes read from it into
+/// `received`, for the progress frames of the operations that read it.
+pub(super) fn counted_body(
+    body: Body,
+    received: Arc<AtomicU64>,
+) -> impl Stream<Item = std::io::Result<Bytes>> {
+    body.map(move |chunk| {
+        let chunk = chunk.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        Ok(chunk)
+    })
+}
+
+/// The message of the progress frames of an operation reading a request body
+/// counted by [`counted_body`].
+pub(super) fn received_message(
+    received: Arc<AtomicU64>,
+) -> impl Fn() -> Option<String> + Send + 'static {
+    move || {
+        let received = received.load(Ordering::Relaxed);
+        Some(format!("received {} MiB", received / (1024 * 1024)))
+    }
+}
+
+fn frame_line(frame: &ProgressFrame) -> Bytes {
+    let mut line = serde_json::to_vec(frame).expect("progress frames serialize to JSON");
+    line.push(b'\n');
+    line.into()
+}
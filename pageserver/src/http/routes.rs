@@ -2,16 +2,24 @@
 //! Management HTTP API
 //!
 use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use futures::StreamExt;
 use hyper::StatusCode;
 use hyper::{Body, Request, Response, Uri};
 use metrics::launch_timestamp::LaunchTimestamp;
-use pageserver_api::models::{DownloadRemoteLayersTaskSpawnRequest, TenantAttachRequest};
+use pageserver_api::models::{
+    ChangedPagesFormat, DownloadRemoteLayersTaskSpawnRequest, PageCacheConfigRequest,
+    PageCacheInfo, PageCacheStats, PurgeDroppedRequest, PurgeDroppedResponse, RelSizeInfo,
+    TenantAttachRequest, TimelineRelSizes,
+};
 use remote_storage::GenericRemoteStorage;
 use storage_broker::BrokerClientChannel;
 use tenant_size_model::{SizeResult, StorageModel};
+use tokio_util::io::{ReaderStream, StreamReader};
 use tokio_util::sync::CancellationToken;
 use tracing::*;
 use utils::http::endpoint::request_span;
@@ -19,12 +27,16 @@ use utils::http::json::json_request_or_empty_body;
 use utils::http::request::{get_request_param, must_get_query_param, parse_query_param};
 
 use super::models::{
-    StatusResponse, TenantConfigRequest, TenantCreateRequest, TenantCreateResponse, TenantInfo,
-    TimelineCreateRequest, TimelineGcRequest, TimelineInfo,
+    StatusResponse, TenantConfig, TenantConfigRequest, TenantCreateRequest, TenantCreateResponse,
+    TenantInfo, TimelineCreateRequest, TimelineGcRequest, TimelineInfo,
 };
+use super::progress::{counted_body, received_message, with_progress};
 use crate::context::{DownloadBehavior, RequestContext};
+use crate::import_datadir::import_wal_from_tar;
 use crate::metrics::{StorageTimeOperation, STORAGE_TIME_GLOBAL};
+use crate::page_service::read_tar_eof;
 use crate::pgdatadir_mapping::LsnForTimestamp;
+use crate::repository::Key;
 use crate::task_mgr::TaskKind;
 use crate::tenant::config::TenantConfOpt;
 use crate::tenant::mgr::{
@@ -51,6 +63,9 @@ use utils::{
 // Imports only used for testing APIs
 use super::models::ConfigureFailpointsRequest;
 
+/// Size of the pipe between the timeline export and the response body.
+const TIMELINE_ARCHIVE_BUFFER_SIZE: usize = 1024 * 1024;
+
 struct State {
     conf: &'static PageServerConf,
     auth: Option<Arc<JwtAuth>>,
@@ -171,6 +186,7 @@ impl From<SetNewTenantConfigError> for ApiError {
             SetNewTenantConfigError::GetTenant(tid) => {
                 ApiError::NotFound(anyhow!("tenant {}", tid).into())
             }
+            SetNewTenantConfigError::Invalid(e) => ApiError::BadRequest(e),
             e @ SetNewTenantConfigError::Persist(_) => {
                 ApiError::InternalServerError(anyhow::Error::new(e))
             }
@@ -221,6 +237,17 @@ impl From<crate::tenant::delete::DeleteTenantError> for ApiError {
     }
 }
 
+impl From<crate::tenant::timeline::WaitLsnDurableError> for ApiError {
+    fn from(value: crate::tenant::timeline::WaitLsnDurableError) -> Self {
+        use crate::tenant::timeline::WaitLsnDurableError::*;
+        match value {
+            e @ Timeout { .. } => ApiError::Timeout(e.to_string().into_boxed_str()),
+            Cancelled => ApiError::InternalServerError(anyhow!("request was cancelled")),
+            Other(e) => ApiError::InternalServerError(e),
+        }
+    }
+}
+
 // Helper function to construct a TimelineInfo struct for a timeline
 async fn build_timeline_info(
     timeline: &Arc<Timeline>,
@@ -271,14 +298,16 @@ async fn build_timeline_info_common(
         Lsn(0) => None,
         lsn @ Lsn(_) => Some(lsn),
     };
-    let current_logical_size = match timeline.get_current_logical_size(ctx) {
-        Ok((size, _)) => Some(size),
-        Err(err) => {
-            error!("Timeline info creation failed to get current logical size: {err:?}");
-            None
-        }
-    };
+    let (current_logical_size, current_logical_size_is_exact) =
+        match timeline.get_current_logical_size(ctx) {
+            Ok((size, is_exact)) => (Some(size), is_exact),
+            Err(err) => {
+                error!("Timeline info creation failed to get current logical size: {err:?}");
+                (None, false)
+            }
+        };
     let current_physical_size = Some(timeline.layer_size_sum().await);
+    let resident_physical_size = Some(timeline.resident_physical_size());
     let state = timeline.current_state();
     let remote_consistent_lsn = timeline.get_remote_consistent_lsn().unwrap_or(Lsn(0));
 
@@ -294,13 +323,17 @@ async fn build_timeline_info_common(
         prev_record_lsn: Some(timeline.get_prev_record_lsn()),
         latest_gc_cutoff_lsn: *timeline.get_latest_gc_cutoff_lsn(),
         current_logical_size,
+        current_logical_size_is_exact,
         current_physical_size,
+        resident_physical_size,
         current_logical_size_non_incremental: None,
         timeline_dir_layer_file_size_sum: None,
         wal_source_connstr,
         last_received_msg_lsn,
         last_received_msg_ts,
         pg_version: timeline.pg_version,
+        created_at: timeline.created_at(),
+        layers: None,
 
         state,
     };
@@ -327,22 +360,27 @@ async fn timeline_create_handler(
 
     let new_timeline_id = request_data.new_timeline_id;
 
+    let progress = parse_query_param(&request, "progress")?.unwrap_or(false);
+
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
 
-    let state = get_state(&request);
+    let broker_client = get_state(&request).broker_client.clone();
 
-    async {
+    let span = info_span!("timeline_create", %tenant_id, timeline_id = %new_timeline_id, lsn=?request_data.ancestor_start_lsn, pg_version=?request_data.pg_version);
+    let operation = async move {
         let tenant = mgr::get_tenant(tenant_id, true).await?;
-        match tenant.create_timeline(
-            new_timeline_id,
-            request_data.ancestor_timeline_id.map(TimelineId::from),
-            request_data.ancestor_start_lsn,
-            request_data.pg_version.unwrap_or(crate::DEFAULT_PG_VERSION),
-            state.broker_client.clone(),
-            request_data.region_id.unwrap_or_default(),
-            &ctx,
-        )
-        .await {
+        match tenant
+            .create_timeline(
+                new_timeline_id,
+                request_data.ancestor_timeline_id.map(TimelineId::from),
+                request_data.ancestor_start_lsn,
+                request_data.pg_version.unwrap_or(crate::DEFAULT_PG_VERSION),
+                broker_client,
+                request_data.region_id.unwrap_or_default(),
+                &ctx,
+            )
+            .await
+        {
             Ok(new_timeline) => {
                 // Created. Construct a TimelineInfo for it.
                 let timeline_info = build_timeline_info_common(&new_timeline, &ctx)
@@ -353,16 +391,22 @@ async fn timeline_create_handler(
             Err(tenant::CreateTimelineError::AlreadyExists) => {
                 json_response(StatusCode::CONFLICT, ())
             }
-            Err(tenant::CreateTimelineError::AncestorLsn(err)) => {
-                json_response(StatusCode::NOT_ACCEPTABLE, HttpErrorBody::from_msg(
-                    format!("{err:#}")
-                ))
-            }
+            Err(tenant::CreateTimelineError::AncestorLsn(err)) => json_response(
+                StatusCode::NOT_ACCEPTABLE,
+                HttpErrorBody::from_msg(format!("{err:#}")),
+            ),
+            Err(tenant::CreateTimelineError::InvalidRegion(err)) => Err(ApiError::BadRequest(err)),
             Err(tenant::CreateTimelineError::Other(err)) => Err(ApiError::InternalServerError(err)),
         }
     }
-    .instrument(info_span!("timeline_create", %tenant_id, timeline_id = %new_timeline_id, lsn=?request_data.ancestor_start_lsn, pg_version=?request_data.pg_version))
-    .await
+    .instrument(span);
+    if progress {
+        // Bootstrapping a timeline runs initdb and imports its output, which
+        // doesn't report how far it got.
+        with_progress(operation, || None)
+    } else {
+        operation.await
+    }
 }
 
 async fn timeline_list_handler(
@@ -422,7 +466,7 @@ async fn timeline_detail_handler(
             .get_timeline(timeline_id, false)
             .map_err(|e| ApiError::NotFound(e.into()))?;
 
-        let timeline_info = build_timeline_info(
+        let mut timeline_info = build_timeline_info(
             &timeline,
             include_non_incremental_logical_size.unwrap_or(false),
             &ctx,
@@ -430,6 +474,13 @@ async fn timeline_detail_handler(
         .await
         .context("get local timeline info")
         .map_err(ApiError::InternalServerError)?;
+        timeline_info.layers = Some(
+            timeline
+                .layer_counts()
+                .await
+                .context("count timeline layers")
+                .map_err(ApiError::InternalServerError)?,
+        );
 
         Ok::<_, ApiError>(timeline_info)
     }
@@ -596,6 +647,10 @@ async fn tenant_list_handler(
             state: state.clone(),
             current_physical_size: None,
             attachment_status: state.attachment_status(),
+            timeline_count: None,
+            max_wal_ingest_lag: None,
+            physical_size_quota: None,
+            physical_size_quota_exceeded: None,
         })
         .collect::<Vec<TenantInfo>>();
 
@@ -613,10 +668,16 @@ async fn tenant_status(
         let tenant = mgr::get_tenant(tenant_id, false).await?;
 
         // Calculate total physical size of all timelines
+        let timelines = tenant.list_timelines();
         let mut current_physical_size = 0;
-        for timeline in tenant.list_timelines().iter() {
+        for timeline in timelines.iter() {
             current_physical_size += timeline.layer_size_sum().await;
         }
+        let max_wal_ingest_lag = timelines
+            .iter()
+            .filter_map(|timeline| timeline.wal_ingest_lag())
+            .max();
+        let physical_size_quota = tenant.get_physical_size_quota();
 
         let state = tenant.current_state();
         Result::<_, ApiError>::Ok(TenantInfo {
@@ -624,6 +685,11 @@ async fn tenant_status(
             state: state.clone(),
             current_physical_size: Some(current_physical_size),
             attachment_status: state.attachment_status(),
+            timeline_count: Some(timelines.len()),
+            max_wal_ingest_lag,
+            physical_size_quota,
+            physical_size_quota_exceeded: physical_size_quota
+                .map(|quota| tenant.resident_physical_size() > quota),
         })
     }
     .instrument(info_span!("tenant_status_handler", %tenant_id))
@@ -632,6 +698,17 @@ async fn tenant_status(
     json_response(StatusCode::OK, tenant_info)
 }
 
+async fn tenant_state_history_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let tenant = mgr::get_tenant(tenant_id, false).await?;
+    json_response(StatusCode::OK, tenant.state_history())
+}
+
 async fn tenant_delete_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
@@ -950,12 +1027,31 @@ async fn update_tenant_config_handler(
     json_response(StatusCode::OK, ())
 }
 
+async fn patch_tenant_config_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let request_data: TenantConfig = json_request(&mut request).await?;
+    let changes = TenantConfOpt::try_from(&request_data).map_err(ApiError::BadRequest)?;
+
+    let state = get_state(&request);
+    mgr::update_tenant_config(state.conf, changes, tenant_id)
+        .instrument(info_span!("tenant_config", %tenant_id))
+        .await?;
+
+    json_response(StatusCode::OK, ())
+}
+
 /// Testing helper to transition a tenant to [`crate::tenant::TenantState::Broken`].
 async fn handle_tenant_break(
     r: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     let tenant_id: TenantId = parse_request_param(&r, "tenant_id")?;
+    check_permission(&r, Some(tenant_id))?;
 
     let tenant = crate::tenant::mgr::get_tenant(tenant_id, true)
         .await
@@ -970,6 +1066,7 @@ async fn failpoints_handler(
     mut request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
     if !fail::has_failpoints() {
         return Err(ApiError::BadRequest(anyhow!(
             "Cannot manage failpoints because pageserver was compiled without failpoints support"
@@ -1023,6 +1120,159 @@ async fn timeline_gc_handler(
     json_response(StatusCode::OK, gc_result)
 }
 
+// Explain what the next GC of the timeline would do with each of its layers.
+async fn timeline_gc_explanation_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    async {
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        tenant
+            .get_timeline(timeline_id, true)
+            .map_err(|e| ApiError::NotFound(e.into()))?;
+        let explanation = tenant
+            .explain_gc(timeline_id, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        json_response(StatusCode::OK, explanation)
+    }
+    .instrument(info_span!("timeline_gc_explanation", %tenant_id, %timeline_id))
+    .await
+}
+
+// Purge the history of a dropped relation or database from the timeline, at
+// the next compactions.
+async fn timeline_purge_dropped_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let request_data: PurgeDroppedRequest = json_request(&mut request).await?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    async {
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        match tenant
+            .purge_dropped(
+                timeline_id,
+                request_data.spcnode,
+                request_data.dbnode,
+                request_data.relnode,
+                &ctx,
+            )
+            .await
+        {
+            Ok(lsn) => json_response(StatusCode::OK, PurgeDroppedResponse { lsn }),
+            Err(tenant::PurgeDroppedError::Timeline(e)) => Err(ApiError::NotFound(e.into())),
+            Err(tenant::PurgeDroppedError::NotDropped(e)) => Err(ApiError::Conflict(e.to_string())),
+            Err(tenant::PurgeDroppedError::Other(e)) => Err(ApiError::InternalServerError(e)),
+        }
+    }
+    .instrument(info_span!("timeline_purge_dropped", %tenant_id, %timeline_id))
+    .await
+}
+
+// Dump the sizes of the relations of the timeline, at the given LSN or the
+// last record LSN.
+async fn timeline_rel_sizes_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let lsn: Option<Lsn> = parse_query_param(&request, "lsn")?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    async {
+        let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+        let last_record_lsn = timeline.get_last_record_lsn();
+        let lsn = lsn.unwrap_or(last_record_lsn);
+        if lsn > last_record_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "lsn {lsn} is past the last record lsn {last_record_lsn} of the timeline"
+            )));
+        }
+        let relations = timeline
+            .list_rel_sizes(lsn, &ctx)
+            .await?
+            .into_iter()
+            .map(|(rel, nblocks, cached_lsn)| RelSizeInfo {
+                spcnode: rel.spcnode,
+                dbnode: rel.dbnode,
+                relnode: rel.relnode,
+                forknum: rel.forknum,
+                nblocks,
+                cached_lsn,
+            })
+            .collect();
+        json_response(StatusCode::OK, TimelineRelSizes { lsn, relations })
+    }
+    .instrument(info_span!("timeline_rel_sizes", %tenant_id, %timeline_id))
+    .await
+}
+
+// Drop the history of the timeline before an LSN, for good.
+async fn timeline_truncate_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let lsn: Lsn = parse_query_param(&request, "lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'lsn' query parameter")))?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    async {
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        match tenant
+            .truncate_timeline_before(timeline_id, lsn, &ctx)
+            .await
+        {
+            Ok(gc_result) => json_response(StatusCode::OK, gc_result),
+            Err(tenant::TruncateTimelineError::Timeline(e)) => Err(ApiError::NotFound(e.into())),
+            Err(tenant::TruncateTimelineError::InvalidLsn(e)) => Err(ApiError::BadRequest(e)),
+            Err(tenant::TruncateTimelineError::Other(e)) => Err(ApiError::InternalServerError(e)),
+        }
+    }
+    .instrument(info_span!("timeline_truncate", %tenant_id, %timeline_id, %lsn))
+    .await
+}
+
+// Check the layer files of all the timelines of a tenant.
+async fn tenant_scrub_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    async {
+        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        let mut reports = Vec::new();
+        for timeline in tenant.list_timelines() {
+            reports.push(
+                timeline
+                    .scrub(&ctx)
+                    .await
+                    .map_err(ApiError::InternalServerError)?,
+            );
+        }
+        json_response(StatusCode::OK, reports)
+    }
+    .instrument(info_span!("tenant_scrub", %tenant_id))
+    .await
+}
+
 // Run compaction immediately on given timeline.
 async fn timeline_compact_handler(
     request: Request<Body>,
@@ -1045,6 +1295,334 @@ async fn timeline_compact_handler(
     .await
 }
 
+async fn timeline_slow_getpage_log_get_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+    let info = timeline.slow_getpage_log.lock().unwrap().info();
+    json_response(StatusCode::OK, info)
+}
+
+// Start logging the page requests that take longer than the `threshold`.
+async fn timeline_slow_getpage_log_enable_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let threshold: humantime::Duration = parse_query_param(&request, "threshold")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'threshold' query parameter")))?;
+
+    let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+    timeline
+        .slow_getpage_log
+        .lock()
+        .unwrap()
+        .enable(threshold.into());
+    info!(%tenant_id, %timeline_id, "enabled slow getPage log with threshold {threshold}");
+    json_response(StatusCode::OK, ())
+}
+
+async fn timeline_slow_getpage_log_disable_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+    timeline.slow_getpage_log.lock().unwrap().disable();
+    info!(%tenant_id, %timeline_id, "disabled slow getPage log");
+    json_response(StatusCode::OK, ())
+}
+
+fn page_cache_info() -> PageCacheInfo {
+    let page_cache = crate::page_cache::get();
+    let metrics = &crate::metrics::PAGE_CACHE;
+    let read_hits = metrics.read_hits();
+    PageCacheInfo {
+        size: page_cache.size(),
+        max_size: page_cache.max_size(),
+        stats: PageCacheStats {
+            read_hits,
+            read_misses: metrics.read_accesses().saturating_sub(read_hits),
+            evictions: metrics.evictions(),
+        },
+    }
+}
+
+async fn page_cache_info_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    json_response(StatusCode::OK, page_cache_info())
+}
+
+// Resize the page cache, up to the max_page_cache_size it was started with.
+async fn page_cache_config_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let request_data: PageCacheConfigRequest = json_request(&mut request).await?;
+    check_permission(&request, None)?;
+
+    // Evicting the pages of the buffers going out of use waits for their
+    // locks, don't block the executor on them.
+    tokio::task::spawn_blocking(move || crate::page_cache::get().resize(request_data.size))
+        .await
+        .context("resize page cache")
+        .map_err(ApiError::InternalServerError)?
+        .map_err(ApiError::BadRequest)?;
+    json_response(StatusCode::OK, page_cache_info())
+}
+
+async fn tenant_page_cache_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    mgr::get_tenant(tenant_id, false).await?;
+    let stats = crate::metrics::existing_page_cache_tenant_metrics(&tenant_id)
+        .map(|metrics| PageCacheStats {
+            read_hits: metrics.read_hits.get(),
+            read_misses: metrics.read_misses.get(),
+            evictions: metrics.evictions.get(),
+        })
+        .unwrap_or_default();
+    json_response(StatusCode::OK, stats)
+}
+
+// Stream a tar archive of the metadata and layer files of the timeline.
+async fn timeline_export_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+
+    let (reader, writer) = tokio::io::duplex(TIMELINE_ARCHIVE_BUFFER_SIZE);
+    let export = tokio::spawn(
+        async move { timeline.export_archive(writer, &ctx).await }
+            .instrument(info_span!("timeline_export", %tenant_id, %timeline_id)),
+    );
+    // Fail the response body rather than end it if the export fails midway, so
+    // that the client does not take a truncated archive for a complete one.
+    let export_error = futures::stream::once(async move {
+        let result = match export.await {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("export task failed: {e}")),
+        };
+        result.err().map(|e| {
+            error!(%tenant_id, %timeline_id, "timeline export failed: {e:#}");
+            Err::<Bytes, _>(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{e:#}"),
+            ))
+        })
+    })
+    .filter_map(std::future::ready);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/x-tar")
+        .body(Body::wrap_stream(
+            ReaderStream::new(reader).chain(export_error),
+        ))
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
+// Create a timeline from an archive of the export API, sent as the request body.
+async fn timeline_import_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+
+    let progress = parse_query_param(&request, "progress")?.unwrap_or(false);
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
+    let broker_client = get_state(&request).broker_client.clone();
+    let received = Arc::new(AtomicU64::new(0));
+    let archive = StreamReader::new(counted_body(request.into_body(), Arc::clone(&received)));
+
+    let operation = async move {
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        if tenant.get_timeline(timeline_id, false).is_ok() {
+            return Err(ApiError::Conflict(format!(
+                "timeline {timeline_id} already exists"
+            )));
+        }
+
+        let timeline = tenant
+            .import_timeline_archive(timeline_id, archive, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        timeline.activate(broker_client, None, &ctx);
+
+        if let Some(remote_client) = timeline.remote_client.as_ref() {
+            // Like on timeline creation, return once the timeline is durable in
+            // remote storage.
+            remote_client
+                .wait_completion()
+                .await
+                .context("wait for imported timeline uploads to complete")
+                .map_err(ApiError::InternalServerError)?;
+        }
+
+        let timeline_info = build_timeline_info_common(&timeline, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        json_response(StatusCode::CREATED, timeline_info)
+    }
+    .instrument(info_span!("timeline_import", %tenant_id, %timeline_id));
+    if progress {
+        with_progress(operation, received_message(received))
+    } else {
+        operation.await
+    }
+}
+
+// Create a timeline from a basebackup tarball, as taken by `pg_basebackup -F tar`
+// or the `fullbackup` page service command, streamed in the request body.
+async fn timeline_import_basebackup_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let base_lsn: Lsn = parse_query_param(&request, "base_lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'base_lsn' query parameter")))?;
+    let pg_version: u32 = parse_query_param(&request, "pg_version")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'pg_version' query parameter")))?;
+
+    let progress = parse_query_param(&request, "progress")?.unwrap_or(false);
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
+    let broker_client = get_state(&request).broker_client.clone();
+    let received = Arc::new(AtomicU64::new(0));
+    let tarball = StreamReader::new(counted_body(request.into_body(), Arc::clone(&received)));
+
+    let operation = async move {
+        let mut tarball = std::pin::pin!(tarball);
+        let tenant = mgr::get_tenant(tenant_id, true).await?;
+        if tenant.get_timeline(timeline_id, false).is_ok() {
+            return Err(ApiError::Conflict(format!(
+                "timeline {timeline_id} already exists"
+            )));
+        }
+
+        // TODO leave clean state on error. For now you can use detach to clean
+        // up broken state from a failed import.
+        let timeline = tenant
+            .create_empty_timeline(
+                timeline_id,
+                base_lsn,
+                pg_version,
+                utils::id::RegionId::default(),
+                &ctx,
+            )
+            .map_err(ApiError::InternalServerError)?
+            .import_basebackup_from_tar(&mut tarball, base_lsn, broker_client, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        read_tar_eof(tarball).await.map_err(ApiError::BadRequest)?;
+
+        let timeline_info = build_timeline_info_common(&timeline, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        json_response(StatusCode::CREATED, timeline_info)
+    }
+    .instrument(
+        info_span!("timeline_import_basebackup", %tenant_id, %timeline_id, %base_lsn, %pg_version),
+    );
+    if progress {
+        with_progress(operation, received_message(received))
+    } else {
+        operation.await
+    }
+}
+
+// Ingest a tarball of WAL segments, streamed in the request body, into a timeline
+// created by `import_basebackup`.
+async fn timeline_import_wal_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let start_lsn: Lsn = parse_query_param(&request, "start_lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'start_lsn' query parameter")))?;
+    let end_lsn: Lsn = parse_query_param(&request, "end_lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'end_lsn' query parameter")))?;
+
+    let progress = parse_query_param(&request, "progress")?.unwrap_or(false);
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
+    let received = Arc::new(AtomicU64::new(0));
+    let tarball = StreamReader::new(counted_body(request.into_body(), Arc::clone(&received)));
+
+    let operation = async move {
+        let mut tarball = std::pin::pin!(tarball);
+        let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+        let last_record_lsn = timeline.get_last_record_lsn();
+        if last_record_lsn != start_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "Cannot import WAL from Lsn {start_lsn} because timeline does not start from the same lsn: {last_record_lsn}"
+            )));
+        }
+
+        import_wal_from_tar(&timeline, &mut tarball, start_lsn, end_lsn, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        read_tar_eof(tarball)
+            .await
+            .map_err(ApiError::BadRequest)?;
+
+        let last_record_lsn = timeline.get_last_record_lsn();
+        if last_record_lsn < end_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "The WAL ends at {last_record_lsn}, before the end lsn {end_lsn}"
+            )));
+        }
+
+        // Persist the data. It doesn't matter if it's in the shape of deltas or
+        // images, so no need for a forced checkpoint.
+        timeline
+            .freeze_and_flush()
+            .await
+            .map_err(ApiError::InternalServerError)?;
+
+        let timeline_info = build_timeline_info_common(&timeline, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        json_response(StatusCode::OK, timeline_info)
+    }
+    .instrument(info_span!("timeline_import_wal", %tenant_id, %timeline_id, %start_lsn, %end_lsn));
+    if progress {
+        with_progress(operation, received_message(received))
+    } else {
+        operation.await
+    }
+}
+
 // Run checkpoint immediately on given timeline.
 async fn timeline_checkpoint_handler(
     request: Request<Body>,
@@ -1071,6 +1649,95 @@ async fn timeline_checkpoint_handler(
     .await
 }
 
+async fn timeline_wait_lsn_handler(
+    request: Request<Body>,
+    cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let lsn: Lsn = parse_query_param(&request, "lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'lsn' query parameter")))?;
+    let timeout_ms: Option<u64> = parse_query_param(&request, "timeout_ms")?;
+
+    let timeout = timeout_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(get_config(&request).wait_lsn_timeout);
+    async {
+        let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+        timeline.wait_lsn_durable(lsn, timeout, &cancel).await?;
+        json_response(StatusCode::OK, ())
+    }
+    .instrument(info_span!("wait_lsn", %tenant_id, %timeline_id, %lsn))
+    .await
+}
+
+/// Default and maximum number of pages returned by one changed pages request.
+const DEFAULT_CHANGED_PAGES_LIMIT: usize = 1000;
+const MAX_CHANGED_PAGES_LIMIT: usize = 10000;
+
+// Get the relation blocks changed between two LSNs, a page of them at a time.
+async fn timeline_changed_pages_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    check_permission(&request, Some(tenant_id))?;
+    let from_lsn: Lsn = parse_query_param(&request, "from_lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'from_lsn' query parameter")))?;
+    let to_lsn: Lsn = parse_query_param(&request, "to_lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'to_lsn' query parameter")))?;
+    let after = parse_query_param::<_, String>(&request, "after")?
+        .map(|after| Key::from_hex(&after))
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(e.context("invalid 'after' cursor")))?;
+    let limit: usize = parse_query_param(&request, "limit")?.unwrap_or(DEFAULT_CHANGED_PAGES_LIMIT);
+    let format: ChangedPagesFormat = parse_query_param(&request, "format")?.unwrap_or_default();
+    if from_lsn >= to_lsn {
+        return Err(ApiError::BadRequest(anyhow!(
+            "from_lsn {from_lsn} must be less than to_lsn {to_lsn}"
+        )));
+    }
+    if !(1..=MAX_CHANGED_PAGES_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(anyhow!(
+            "limit must be between 1 and {MAX_CHANGED_PAGES_LIMIT}"
+        )));
+    }
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    async {
+        let timeline = active_timeline_of_active_tenant(tenant_id, timeline_id).await?;
+        let last_record_lsn = timeline.get_last_record_lsn();
+        if to_lsn > last_record_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "to_lsn {to_lsn} is ahead of the last record LSN {last_record_lsn}"
+            )));
+        }
+        // GC may have removed the changes before its cutoff.
+        let gc_cutoff_lsn = *timeline.get_latest_gc_cutoff_lsn();
+        if from_lsn < gc_cutoff_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "from_lsn {from_lsn} is behind the GC cutoff {gc_cutoff_lsn}"
+            )));
+        }
+        let ancestor_lsn = timeline.get_ancestor_lsn();
+        if timeline.get_ancestor_timeline_id().is_some() && from_lsn < ancestor_lsn {
+            return Err(ApiError::BadRequest(anyhow!(
+                "from_lsn {from_lsn} is before the branch point {ancestor_lsn}, ask the ancestor timeline for the changes before it"
+            )));
+        }
+
+        let changed_pages = timeline
+            .changed_pages(from_lsn, to_lsn, after, limit, format, &ctx)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        json_response(StatusCode::OK, changed_pages)
+    }
+    .instrument(info_span!("changed_pages", %tenant_id, %timeline_id, %from_lsn, %to_lsn))
+    .await
+}
+
 async fn timeline_download_remote_layers_handler_post(
     mut request: Request<Body>,
     _cancel: CancellationToken,
@@ -1117,6 +1784,7 @@ async fn always_panic_handler(
     req: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
+    check_permission(&req, None)?;
     // Deliberately cause a panic to exercise the panic hook registered via std::panic::set_hook().
     // For pageserver, the relevant panic hook is `tracing_panic_hook` , and the `sentry` crate's wrapper around it.
     // Use catch_unwind to ensure that tokio nor hyper are distracted by our panic.
@@ -1236,6 +1904,7 @@ async fn post_tracing_event_handler(
         level: Level,
         message: String,
     }
+    check_permission(&r, None)?;
     let body: Request = json_request(&mut r)
         .await
         .map_err(|_| ApiError::BadRequest(anyhow::anyhow!("invalid JSON body")))?;
@@ -1371,6 +2040,12 @@ pub fn make_router(
             .context("Failed to initialize router state")?,
         ))
         .get("/v1/status", |r| api_handler(r, status_handler))
+        .get("/v1/page_cache", |r| {
+            api_handler(r, page_cache_info_handler)
+        })
+        .put("/v1/page_cache", |r| {
+            api_handler(r, page_cache_config_handler)
+        })
         .put("/v1/failpoints", |r| {
             testing_api_handler("manage failpoints", r, failpoints_handler)
         })
@@ -1380,6 +2055,12 @@ pub fn make_router(
         .delete("/v1/tenant/:tenant_id", |r| {
             api_handler(r, tenant_delete_handler)
         })
+        .get("/v1/tenant/:tenant_id/state_history", |r| {
+            api_handler(r, tenant_state_history_handler)
+        })
+        .get("/v1/tenant/:tenant_id/page_cache", |r| {
+            api_handler(r, tenant_page_cache_handler)
+        })
         .get("/v1/tenant/:tenant_id/synthetic_size", |r| {
             api_handler(r, tenant_size_handler)
         })
@@ -1389,6 +2070,9 @@ pub fn make_router(
         .get("/v1/tenant/:tenant_id/config", |r| {
             api_handler(r, get_tenant_config_handler)
         })
+        .put("/v1/tenant/:tenant_id/config", |r| {
+            api_handler(r, patch_tenant_config_handler)
+        })
         .get("/v1/tenant/:tenant_id/timeline", |r| {
             api_handler(r, timeline_list_handler)
         })
@@ -1417,9 +2101,62 @@ pub fn make_router(
         .put("/v1/tenant/:tenant_id/timeline/:timeline_id/do_gc", |r| {
             api_handler(r, timeline_gc_handler)
         })
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/gc_explanation",
+            |r| api_handler(r, timeline_gc_explanation_handler),
+        )
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/purge_dropped",
+            |r| api_handler(r, timeline_purge_dropped_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/rel_sizes",
+            |r| api_handler(r, timeline_rel_sizes_handler),
+        )
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/truncate",
+            |r| api_handler(r, timeline_truncate_handler),
+        )
+        .put("/v1/tenant/:tenant_id/scrub", |r| {
+            api_handler(r, tenant_scrub_handler)
+        })
         .put("/v1/tenant/:tenant_id/timeline/:timeline_id/compact", |r| {
-            testing_api_handler("run timeline compaction", r, timeline_compact_handler)
+            api_handler(r, timeline_compact_handler)
+        })
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/slow_getpage_log",
+            |r| api_handler(r, timeline_slow_getpage_log_get_handler),
+        )
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/slow_getpage_log",
+            |r| api_handler(r, timeline_slow_getpage_log_enable_handler),
+        )
+        .delete(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/slow_getpage_log",
+            |r| api_handler(r, timeline_slow_getpage_log_disable_handler),
+        )
+        .get("/v1/tenant/:tenant_id/timeline/:timeline_id/export", |r| {
+            api_handler(r, timeline_export_handler)
         })
+        .put("/v1/tenant/:tenant_id/timeline/:timeline_id/import", |r| {
+            api_handler(r, timeline_import_handler)
+        })
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/import_basebackup",
+            |r| api_handler(r, timeline_import_basebackup_handler),
+        )
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/import_wal",
+            |r| api_handler(r, timeline_import_wal_handler),
+        )
+        .post(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/wait_lsn",
+            |r| api_handler(r, timeline_wait_lsn_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/changed_pages",
+            |r| api_handler(r, timeline_changed_pages_handler),
+        )
         .put(
             "/v1/tenant/:tenant_id/timeline/:timeline_id/checkpoint",
             |r| testing_api_handler("run timeline checkpoint", r, timeline_checkpoint_handler),
@@ -18,14 +18,15 @@ use crate::tenant::Timeline;
 use crate::walingest::WalIngest;
 use crate::walrecord::DecodedWALRecord;
 use pageserver_api::reltag::{RelTag, SlruKind};
+use postgres_ffi::page_checksum::page_checksum_is_valid;
 use postgres_ffi::pg_constants;
 use postgres_ffi::relfile_utils::*;
+use postgres_ffi::twophase::{twophase_file_to_state_data, TwoPhaseState};
 use postgres_ffi::waldecoder::WalStreamDecoder;
 use postgres_ffi::ControlFileData;
-use postgres_ffi::DBState_DB_SHUTDOWNED;
 use postgres_ffi::Oid;
 use postgres_ffi::XLogFileName;
-use postgres_ffi::{BLCKSZ, WAL_SEGMENT_SIZE};
+use postgres_ffi::BLCKSZ;
 use utils::lsn::{Lsn, RecordLsn};
 
 // Returns checkpoint LSN from controlfile
@@ -52,6 +53,10 @@ pub async fn import_timeline_from_postgres_datadir(
 ) -> Result<()> {
     let mut pg_control: Option<ControlFileData> = None;
 
+    // Verify the checksums of the relation pages, if the cluster has them.
+    let verify_checksums =
+        ControlFileData::read_from_datadir(pgdata_path)?.data_checksum_version != 0;
+
     // TODO this shoud be start_lsn, which is not necessarily equal to end_lsn (aka lsn)
     // Then fishing out pg_control would be unnecessary
     let mut modification = tline.begin_modification(pgdata_lsn);
@@ -70,8 +75,15 @@ pub async fn import_timeline_from_postgres_datadir(
 
             let mut file = tokio::fs::File::open(absolute_path).await?;
             let len = metadata.len() as usize;
-            if let Some(control_file) =
-                import_file(&mut modification, relative_path, &mut file, len, ctx).await?
+            if let Some(control_file) = import_file(
+                &mut modification,
+                relative_path,
+                &mut file,
+                len,
+                verify_checksums,
+                ctx,
+            )
+            .await?
             {
                 pg_control = Some(control_file);
             }
@@ -85,8 +97,9 @@ pub async fn import_timeline_from_postgres_datadir(
     // We expect the Postgres server to be shut down cleanly.
     let pg_control = pg_control.context("pg_control file not found")?;
     ensure!(
-        pg_control.state == DBState_DB_SHUTDOWNED,
-        "Postgres cluster was not shut down cleanly"
+        pg_control.is_shut_down(),
+        "Postgres cluster was not shut down cleanly, its state is \"{}\"",
+        pg_control.state_name()
     );
     ensure!(
         pg_control.checkPointCopy.redo == pgdata_lsn.0,
@@ -101,6 +114,7 @@ pub async fn import_timeline_from_postgres_datadir(
         tline,
         Lsn(pg_control.checkPointCopy.redo),
         pgdata_lsn,
+        pg_control.xlog_seg_size as usize,
         ctx,
     )
     .await?;
@@ -109,6 +123,7 @@ pub async fn import_timeline_from_postgres_datadir(
 }
 
 // subroutine of import_timeline_from_postgres_datadir(), to load one relation file.
+#[allow(clippy::too_many_arguments)]
 async fn import_rel(
     modification: &mut DatadirModification<'_>,
     path: &Path,
@@ -116,6 +131,7 @@ async fn import_rel(
     dboid: Oid,
     reader: &mut (impl AsyncRead + Unpin),
     len: usize,
+    verify_checksums: bool,
     ctx: &RequestContext,
 ) -> anyhow::Result<()> {
     // Does it look like a relation file?
@@ -166,6 +182,11 @@ async fn import_rel(
         let r = reader.read_exact(&mut buf).await;
         match r {
             Ok(_) => {
+                ensure!(
+                    !verify_checksums || page_checksum_is_valid(&buf, blknum),
+                    "invalid checksum in block {blknum} of {}",
+                    path.display()
+                );
                 modification.put_rel_page_image(rel, blknum, Bytes::copy_from_slice(&buf))?;
             }
 
@@ -260,19 +281,20 @@ async fn import_wal(
     tline: &Timeline,
     startpoint: Lsn,
     endpoint: Lsn,
+    wal_seg_size: usize,
     ctx: &RequestContext,
 ) -> anyhow::Result<()> {
-    let mut waldecoder = WalStreamDecoder::new(startpoint, tline.pg_version);
+    let mut waldecoder = WalStreamDecoder::new(startpoint, tline.pg_version, wal_seg_size);
 
-    let mut segno = startpoint.segment_number(WAL_SEGMENT_SIZE);
-    let mut offset = startpoint.segment_offset(WAL_SEGMENT_SIZE);
+    let mut segno = startpoint.segment_number(wal_seg_size);
+    let mut offset = startpoint.segment_offset(wal_seg_size);
     let mut last_lsn = startpoint;
 
     let mut walingest = WalIngest::new(tline, startpoint, ctx).await?;
 
     while last_lsn <= endpoint {
         // FIXME: assume postgresql tli 1 for now
-        let filename = XLogFileName(1, segno, WAL_SEGMENT_SIZE);
+        let filename = XLogFileName(1, segno, wal_seg_size);
         let mut buf = Vec::new();
 
         // Read local file
@@ -293,7 +315,7 @@ async fn import_wal(
 
         use std::io::Read;
         let nread = file.read_to_end(&mut buf)?;
-        if nread != WAL_SEGMENT_SIZE - offset {
+        if nread != wal_seg_size - offset {
             // Maybe allow this for .partial files?
             error!("read only {} bytes from WAL file", nread);
         }
@@ -354,8 +376,20 @@ pub async fn import_basebackup_from_tar(
 
         match header.entry_type() {
             tokio_tar::EntryType::Regular => {
-                if let Some(res) =
-                    import_file(&mut modification, file_path.as_ref(), &mut entry, len, ctx).await?
+                // pg_control usually comes last in a base backup, so the
+                // checksums can only be verified if it came earlier.
+                let verify_checksums = pg_control
+                    .as_ref()
+                    .map_or(false, |pg_control| pg_control.data_checksum_version != 0);
+                if let Some(res) = import_file(
+                    &mut modification,
+                    file_path.as_ref(),
+                    &mut entry,
+                    len,
+                    verify_checksums,
+                    ctx,
+                )
+                .await?
                 {
                     // We found the pg_control file.
                     pg_control = Some(res);
@@ -389,10 +423,13 @@ pub async fn import_wal_from_tar(
     end_lsn: Lsn,
     ctx: &RequestContext,
 ) -> Result<()> {
+    // The WAL segment size is in the control file imported with the base backup
+    let wal_seg_size = tline.get_wal_seg_size(start_lsn, ctx).await?;
+
     // Set up walingest mutable state
-    let mut waldecoder = WalStreamDecoder::new(start_lsn, tline.pg_version);
-    let mut segno = start_lsn.segment_number(WAL_SEGMENT_SIZE);
-    let mut offset = start_lsn.segment_offset(WAL_SEGMENT_SIZE);
+    let mut waldecoder = WalStreamDecoder::new(start_lsn, tline.pg_version, wal_seg_size);
+    let mut segno = start_lsn.segment_number(wal_seg_size);
+    let mut offset = start_lsn.segment_offset(wal_seg_size);
     let mut last_lsn = start_lsn;
     let mut walingest = WalIngest::new(tline, start_lsn, ctx).await?;
 
@@ -412,7 +449,7 @@ pub async fn import_wal_from_tar(
             match header.entry_type() {
                 tokio_tar::EntryType::Regular => {
                     // FIXME: assume postgresql tli 1 for now
-                    let expected_filename = XLogFileName(1, segno, WAL_SEGMENT_SIZE);
+                    let expected_filename = XLogFileName(1, segno, wal_seg_size);
                     let file_name = file_path
                         .file_name()
                         .expect("missing wal filename")
@@ -479,6 +516,7 @@ async fn import_file(
     file_path: &Path,
     reader: &mut (impl AsyncRead + Send + Sync + Unpin),
     len: usize,
+    verify_checksums: bool,
     ctx: &RequestContext,
 ) -> Result<Option<ControlFileData>> {
     let file_name = match file_path.file_name() {
@@ -521,7 +559,17 @@ async fn import_file(
                 debug!("ignored PG_VERSION file");
             }
             _ => {
-                import_rel(modification, file_path, spcnode, dbnode, reader, len, ctx).await?;
+                import_rel(
+                    modification,
+                    file_path,
+                    spcnode,
+                    dbnode,
+                    reader,
+                    len,
+                    verify_checksums,
+                    ctx,
+                )
+                .await?;
                 debug!("imported rel creation");
             }
         }
@@ -546,7 +594,17 @@ async fn import_file(
                 debug!("ignored PG_VERSION file");
             }
             _ => {
-                import_rel(modification, file_path, spcnode, dbnode, reader, len, ctx).await?;
+                import_rel(
+                    modification,
+                    file_path,
+                    spcnode,
+                    dbnode,
+                    reader,
+                    len,
+                    verify_checksums,
+                    ctx,
+                )
+                .await?;
                 debug!("imported rel creation");
             }
         }
@@ -570,13 +628,26 @@ async fn import_file(
 
         import_slru(modification, slru, file_path, reader, len, ctx).await?;
         debug!("imported csn slru");
+    } else if file_path.starts_with("pg_commit_ts") {
+        let slru = SlruKind::CommitTs;
+
+        import_slru(modification, slru, file_path, reader, len, ctx).await?;
+        debug!("imported commit timestamp slru");
     } else if file_path.starts_with("pg_twophase") {
         let xid = u32::from_str_radix(file_name.as_ref(), 16)?;
 
         let bytes = read_all_bytes(reader).await?;
-        modification
-            .put_twophase_file(xid, Bytes::copy_from_slice(&bytes[..]), ctx)
-            .await?;
+        // The state file ends with a CRC, which is added back in basebackup.
+        let data = twophase_file_to_state_data(&bytes)
+            .with_context(|| format!("invalid two-phase state file {}", file_path.display()))?;
+        let state = TwoPhaseState::decode(&data, modification.tline.pg_version)?;
+        ensure!(
+            state.header.xid == xid,
+            "two-phase state file {} is of xid {}",
+            file_path.display(),
+            state.header.xid
+        );
+        modification.put_twophase_file(xid, data, ctx).await?;
         debug!("imported twophase file");
     } else if file_path.starts_with("pg_wal") {
         debug!("found wal file in base section. ignore it");
@@ -33,7 +33,10 @@ use tracing::info;
 /// format, bump this!
 /// Note that TimelineMetadata uses its own version number to track
 /// backwards-compatible changes to the metadata format.
-pub const STORAGE_FORMAT_VERSION: u16 = 3;
+///
+/// Version 4 added the content checksum to the summary of the layer files.
+/// Version 3 files are still read, see `tenant::storage_layer::checksum`.
+pub const STORAGE_FORMAT_VERSION: u16 = 4;
 
 pub const DEFAULT_PG_VERSION: u32 = 15;
 
@@ -99,6 +102,11 @@ pub async fn shutdown_pageserver(exit_code: i32) {
 /// Full path: `tenants/<tenant_id>/timelines/<timeline_id>/metadata`.
 pub const METADATA_FILE_NAME: &str = "metadata";
 
+/// The WAL streaming connection of a timeline, saved at graceful shutdown to
+/// reconnect to the same safekeeper at startup.
+/// Full path: `tenants/<tenant_id>/timelines/<timeline_id>/wal_receiver_state`.
+pub const WAL_RECEIVER_STATE_FILE_NAME: &str = "wal_receiver_state";
+
 /// Per-tenant configuration file.
 /// Full path: `tenants/<tenant_id>/config`.
 pub const TENANT_CONFIG_NAME: &str = "config";
@@ -110,6 +110,22 @@ pub(crate) static MATERIALIZED_PAGE_CACHE_HIT_DIRECT: Lazy<IntCounter> = Lazy::n
     .expect("failed to define a metric")
 });
 
+pub(crate) static RELSIZE_CACHE_HITS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_relsize_cache_hits_total",
+        "Number of relation size lookups served from the relation size cache",
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static RELSIZE_CACHE_MISSES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_relsize_cache_misses_total",
+        "Number of relation size lookups that had to read the relation size from the layers",
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static GET_RECONSTRUCT_DATA_TIME: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!(
         "pageserver_getpage_get_reconstruct_data_seconds",
@@ -136,6 +152,10 @@ pub struct PageCacheMetrics {
     pub read_hits_immutable: IntCounter,
     pub read_hits_materialized_page_exact: IntCounter,
     pub read_hits_materialized_page_older_lsn: IntCounter,
+
+    pub evictions_materialized_page: IntCounter,
+    pub evictions_ephemeral: IntCounter,
+    pub evictions_immutable: IntCounter,
 }
 
 static PAGE_CACHE_READ_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
@@ -156,6 +176,15 @@ static PAGE_CACHE_READ_ACCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+static PAGE_CACHE_EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_evictions_total",
+        "Number of pages evicted from the page cache",
+        &["key_kind"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub static PAGE_CACHE: Lazy<PageCacheMetrics> = Lazy::new(|| PageCacheMetrics {
     read_accesses_materialized_page: {
         PAGE_CACHE_READ_ACCESSES
@@ -198,8 +227,118 @@ pub static PAGE_CACHE: Lazy<PageCacheMetrics> = Lazy::new(|| PageCacheMetrics {
             .get_metric_with_label_values(&["materialized_page", "older_lsn"])
             .unwrap()
     },
+
+    evictions_materialized_page: {
+        PAGE_CACHE_EVICTIONS
+            .get_metric_with_label_values(&["materialized_page"])
+            .unwrap()
+    },
+
+    evictions_ephemeral: {
+        PAGE_CACHE_EVICTIONS
+            .get_metric_with_label_values(&["ephemeral"])
+            .unwrap()
+    },
+
+    evictions_immutable: {
+        PAGE_CACHE_EVICTIONS
+            .get_metric_with_label_values(&["immutable"])
+            .unwrap()
+    },
 });
 
+impl PageCacheMetrics {
+    pub fn read_accesses(&self) -> u64 {
+        self.read_accesses_materialized_page.get()
+            + self.read_accesses_ephemeral.get()
+            + self.read_accesses_immutable.get()
+    }
+
+    pub fn read_hits(&self) -> u64 {
+        self.read_hits_ephemeral.get()
+            + self.read_hits_immutable.get()
+            + self.read_hits_materialized_page_exact.get()
+            + self.read_hits_materialized_page_older_lsn.get()
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.evictions_materialized_page.get()
+            + self.evictions_ephemeral.get()
+            + self.evictions_immutable.get()
+    }
+}
+
+static PAGE_CACHE_TENANT_READ_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_tenant_read_hits_total",
+        "Number of lookups of materialized pages of the tenant that hit the page cache",
+        &["tenant_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static PAGE_CACHE_TENANT_READ_MISSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_tenant_read_misses_total",
+        "Number of lookups of materialized pages of the tenant that missed the page cache",
+        &["tenant_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static PAGE_CACHE_TENANT_EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_tenant_evictions_total",
+        "Number of materialized pages of the tenant evicted from the page cache",
+        &["tenant_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+/// Page cache statistics of a tenant. Only the materialized pages are tracked
+/// per tenant, the pages of layer files are not keyed by tenant in the cache.
+pub struct PageCacheTenantMetrics {
+    pub read_hits: IntCounter,
+    pub read_misses: IntCounter,
+    pub evictions: IntCounter,
+}
+
+/// The page cache statistics of the tenants that used the page cache, kept
+/// here so that the page cache does not format the tenant id on every lookup.
+static PAGE_CACHE_TENANT_METRICS: Lazy<
+    std::sync::RwLock<HashMap<TenantId, Arc<PageCacheTenantMetrics>>>,
+> = Lazy::new(Default::default);
+
+/// Returns the page cache statistics of the tenant, registering them on first
+/// use.
+pub fn page_cache_tenant_metrics(tenant_id: &TenantId) -> Arc<PageCacheTenantMetrics> {
+    if let Some(metrics) = existing_page_cache_tenant_metrics(tenant_id) {
+        return metrics;
+    }
+    let tid = tenant_id.to_string();
+    let mut tenants = PAGE_CACHE_TENANT_METRICS.write().unwrap();
+    Arc::clone(tenants.entry(*tenant_id).or_insert_with(|| {
+        Arc::new(PageCacheTenantMetrics {
+            read_hits: PAGE_CACHE_TENANT_READ_HITS.with_label_values(&[&tid]),
+            read_misses: PAGE_CACHE_TENANT_READ_MISSES.with_label_values(&[&tid]),
+            evictions: PAGE_CACHE_TENANT_EVICTIONS.with_label_values(&[&tid]),
+        })
+    }))
+}
+
+/// Like [`page_cache_tenant_metrics`], but does not register the statistics
+/// of a tenant that has none, e.g. of a detached tenant whose pages are still
+/// in the cache.
+pub fn existing_page_cache_tenant_metrics(
+    tenant_id: &TenantId,
+) -> Option<Arc<PageCacheTenantMetrics>> {
+    PAGE_CACHE_TENANT_METRICS
+        .read()
+        .unwrap()
+        .get(tenant_id)
+        .cloned()
+}
+
 pub struct PageCacheSizeMetrics {
     pub max_bytes: UIntGauge,
 
@@ -290,6 +429,34 @@ static WAL_REPLICATION_MSG_RECORDS: Lazy<HistogramVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+static WAL_INGEST_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_wal_ingest_bytes_total",
+        "Bytes of WAL received from the safekeepers and ingested",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static WAL_INGEST_RECORDS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_wal_ingest_records_total",
+        "Number of WAL records ingested",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static LAYER_COUNT: Lazy<UIntGaugeVec> = Lazy::new(|| {
+    register_uint_gauge_vec!(
+        "pageserver_layer_count",
+        "Number of the historic layers of a timeline, resident or not, by kind. \
+         Updated after each layer flush, compaction and gc.",
+        &["tenant_id", "timeline_id", "kind"]
+    )
+    .expect("failed to define a metric")
+});
+
 static RESIDENT_PHYSICAL_SIZE: Lazy<UIntGaugeVec> = Lazy::new(|| {
     register_uint_gauge_vec!(
         "pageserver_resident_physical_size",
@@ -365,6 +532,24 @@ pub(crate) static TENANT_SYNTHETIC_SIZE_METRIC: Lazy<UIntGaugeVec> = Lazy::new(|
     .expect("Failed to register pageserver_tenant_synthetic_cached_size_bytes metric")
 });
 
+pub(crate) static TENANT_THROTTLED_GETPAGE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_tenant_throttled_getpage_requests_total",
+        "Number of getpage requests of the tenant that waited for max_concurrent_getpage_requests",
+        &["tenant_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TENANT_THROTTLED_WAL_INGEST_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "pageserver_tenant_throttled_wal_ingest_seconds_total",
+        "Time the WAL ingestion of the tenant waited for wal_ingest_rate_limit",
+        &["tenant_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 // Metrics for cloud upload. These metrics reflect data uploaded to cloud storage,
 // or in testing they estimate how much we would upload if we did.
 static NUM_PERSISTENT_FILES_CREATED: Lazy<IntCounterVec> = Lazy::new(|| {
@@ -385,6 +570,24 @@ static PERSISTENT_BYTES_WRITTEN: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+// Metrics for the compression of the values written to layer files, with
+// compression enabled. Their ratio is the compression ratio.
+pub(crate) static LAYER_COMPRESSION_INPUT_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_layer_compression_input_bytes_total",
+        "Bytes of the values written to layer files with compression enabled, before compression",
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static LAYER_COMPRESSION_OUTPUT_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_layer_compression_output_bytes_total",
+        "Bytes of the values written to layer files with compression enabled, after compression",
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static EVICTION_ITERATION_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
     register_histogram_vec!(
         "pageserver_eviction_iteration_duration_seconds_global",
@@ -404,6 +607,44 @@ static EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+static READ_HEAT_REDO_READS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_read_heat_redo_reads_total",
+        "Number of page reads of a timeline that replayed WAL records on top of a page image",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static READ_HEAT_REDO_RECORDS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_read_heat_redo_records_total",
+        "Number of WAL records replayed by the page reads of a timeline",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static READ_HEAT_HOT_RANGES: Lazy<UIntGaugeVec> = Lazy::new(|| {
+    register_uint_gauge_vec!(
+        "pageserver_read_heat_hot_ranges",
+        "Number of the key ranges of a timeline that were hot at the last compaction, \
+         i.e. that had at least hot_range_image_creation_threshold reads replaying WAL records",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static READ_HEAT_IMAGE_LAYERS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_read_heat_image_layers_created_total",
+        "Number of image layers of a timeline created ahead of image_creation_threshold, \
+         because their key range was hot",
+        &["tenant_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 static EVICTIONS_WITH_LOW_RESIDENCE_DURATION: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "pageserver_evictions_with_low_residence_duration",
@@ -603,6 +844,7 @@ const SMGR_QUERY_TIME_OPERATIONS: &[&str] = &[
     "get_rel_exists",
     "get_rel_size",
     "get_page_at_lsn",
+    "get_pages_at_lsn",
     "get_db_size",
 ];
 
@@ -771,6 +1013,26 @@ pub(crate) static BACKGROUND_LOOP_PERIOD_OVERRUN_COUNT: Lazy<IntCounterVec> = La
     .expect("failed to define a metric")
 });
 
+pub(crate) static LAST_BACKGROUND_TASK_TIMESTAMP: Lazy<UIntGaugeVec> = Lazy::new(|| {
+    register_uint_gauge_vec!(
+        "pageserver_last_background_task_timestamp",
+        "Timestamp (millis since epoch) at which a checkpoint or gc of any timeline last completed.",
+        &["task"],
+    )
+    .expect("failed to define a metric")
+});
+
+/// Records that a background task completed now, for `neon_local pageserver status`.
+pub(crate) fn record_background_task_completion(task: &str) {
+    let millis_since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    LAST_BACKGROUND_TASK_TIMESTAMP
+        .with_label_values(&[task])
+        .set(millis_since_epoch);
+}
+
 // walreceiver metrics
 
 pub(crate) static WALRECEIVER_STARTED_CONNECTIONS: Lazy<IntCounter> = Lazy::new(|| {
@@ -989,6 +1251,10 @@ pub struct TimelineMetrics {
     pub last_receive_gauge: IntGauge,
     pub wal_receive_time: Histogram,
     pub wal_replication_msg_records: Histogram,
+    pub wal_ingest_bytes: IntCounter,
+    pub wal_ingest_records: IntCounter,
+    pub delta_layer_count_gauge: UIntGauge,
+    pub image_layer_count_gauge: UIntGauge,
     pub resident_physical_size_gauge: UIntGauge,
     /// copy of LayeredTimeline.current_logical_size
     pub current_logical_size_gauge: UIntGauge,
@@ -996,6 +1262,13 @@ pub struct TimelineMetrics {
     pub persistent_bytes_written: IntCounter,
     pub evictions: IntCounter,
     pub evictions_with_low_residence_duration: std::sync::RwLock<EvictionsWithLowResidenceDuration>,
+    pub read_heat_redo_reads: IntCounter,
+    pub read_heat_redo_records: IntCounter,
+    pub read_heat_hot_ranges_gauge: UIntGauge,
+    pub read_heat_image_layers: IntCounter,
+    /// The page cache statistics of the tenant, resolved once here rather
+    /// than on every lookup.
+    pub page_cache: Arc<PageCacheTenantMetrics>,
 }
 
 impl TimelineMetrics {
@@ -1005,6 +1278,7 @@ impl TimelineMetrics {
         region_id: &RegionId,
         evictions_with_low_residence_duration_builder: EvictionsWithLowResidenceDurationBuilder,
     ) -> Self {
+        let page_cache = page_cache_tenant_metrics(tenant_id);
         let tenant_id = tenant_id.to_string();
         let timeline_id = timeline_id.to_string();
         let region_id = region_id.to_string();
@@ -1037,6 +1311,18 @@ impl TimelineMetrics {
         let wal_replication_msg_records = WAL_REPLICATION_MSG_RECORDS
             .get_metric_with_label_values(&[&tenant_id, &timeline_id, &region_id])
             .unwrap();
+        let wal_ingest_bytes = WAL_INGEST_BYTES
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
+        let wal_ingest_records = WAL_INGEST_RECORDS
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
+        let delta_layer_count_gauge = LAYER_COUNT
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id, "delta"])
+            .unwrap();
+        let image_layer_count_gauge = LAYER_COUNT
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id, "image"])
+            .unwrap();
         let resident_physical_size_gauge = RESIDENT_PHYSICAL_SIZE
             .get_metric_with_label_values(&[&tenant_id, &timeline_id])
             .unwrap();
@@ -1054,6 +1340,18 @@ impl TimelineMetrics {
             .unwrap();
         let evictions_with_low_residence_duration =
             evictions_with_low_residence_duration_builder.build(&tenant_id, &timeline_id);
+        let read_heat_redo_reads = READ_HEAT_REDO_READS
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
+        let read_heat_redo_records = READ_HEAT_REDO_RECORDS
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
+        let read_heat_hot_ranges_gauge = READ_HEAT_HOT_RANGES
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
+        let read_heat_image_layers = READ_HEAT_IMAGE_LAYERS
+            .get_metric_with_label_values(&[&tenant_id, &timeline_id])
+            .unwrap();
 
         TimelineMetrics {
             tenant_id,
@@ -1069,6 +1367,10 @@ impl TimelineMetrics {
             last_receive_gauge,
             wal_receive_time,
             wal_replication_msg_records,
+            wal_ingest_bytes,
+            wal_ingest_records,
+            delta_layer_count_gauge,
+            image_layer_count_gauge,
             resident_physical_size_gauge,
             current_logical_size_gauge,
             num_persistent_files_created,
@@ -1077,6 +1379,11 @@ impl TimelineMetrics {
             evictions_with_low_residence_duration: std::sync::RwLock::new(
                 evictions_with_low_residence_duration,
             ),
+            read_heat_redo_reads,
+            read_heat_redo_records,
+            read_heat_hot_ranges_gauge,
+            read_heat_image_layers,
+            page_cache,
         }
     }
 }
@@ -1086,11 +1393,20 @@ impl Drop for TimelineMetrics {
         let tenant_id = &self.tenant_id;
         let timeline_id = &self.timeline_id;
         let _ = LAST_RECORD_LSN.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = WAL_INGEST_BYTES.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = WAL_INGEST_RECORDS.remove_label_values(&[tenant_id, timeline_id]);
+        for kind in ["delta", "image"] {
+            let _ = LAYER_COUNT.remove_label_values(&[tenant_id, timeline_id, kind]);
+        }
         let _ = RESIDENT_PHYSICAL_SIZE.remove_label_values(&[tenant_id, timeline_id]);
         let _ = CURRENT_LOGICAL_SIZE.remove_label_values(&[tenant_id, timeline_id]);
         let _ = NUM_PERSISTENT_FILES_CREATED.remove_label_values(&[tenant_id, timeline_id]);
         let _ = PERSISTENT_BYTES_WRITTEN.remove_label_values(&[tenant_id, timeline_id]);
         let _ = EVICTIONS.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = READ_HEAT_REDO_READS.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = READ_HEAT_REDO_RECORDS.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = READ_HEAT_HOT_RANGES.remove_label_values(&[tenant_id, timeline_id]);
+        let _ = READ_HEAT_IMAGE_LAYERS.remove_label_values(&[tenant_id, timeline_id]);
 
         self.evictions_with_low_residence_duration
             .write()
@@ -1116,6 +1432,18 @@ impl Drop for TimelineMetrics {
 pub fn remove_tenant_metrics(tenant_id: &TenantId) {
     let tid = tenant_id.to_string();
     let _ = TENANT_SYNTHETIC_SIZE_METRIC.remove_label_values(&[&tid]);
+    let _ = TENANT_THROTTLED_GETPAGE_REQUESTS.remove_label_values(&[&tid]);
+    let _ = TENANT_THROTTLED_WAL_INGEST_SECONDS.remove_label_values(&[&tid]);
+    if PAGE_CACHE_TENANT_METRICS
+        .write()
+        .unwrap()
+        .remove(tenant_id)
+        .is_some()
+    {
+        let _ = PAGE_CACHE_TENANT_READ_HITS.remove_label_values(&[&tid]);
+        let _ = PAGE_CACHE_TENANT_READ_MISSES.remove_label_values(&[&tid]);
+        let _ = PAGE_CACHE_TENANT_EVICTIONS.remove_label_values(&[&tid]);
+    }
     // we leave the BROKEN_TENANTS_SET entry if any
 }
 
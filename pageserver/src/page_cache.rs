@@ -35,26 +35,37 @@
 //! initialized it. If the guard is dropped without calling mark_valid(), the
 //! mapping is automatically removed and the slot is marked free.
 //!
+//! # Resizing
+//!
+//! The buffers are allocated at startup, for `max_page_cache_size` pages, but
+//! only the first `page_cache_size` of them are used. The number of buffers in
+//! use can be changed at runtime with [`PageCache::resize`], through the
+//! management API. When it shrinks, the pages in the buffers going out of use
+//! are evicted, and the clock sweep no longer visits them.
+//!
 
 use std::{
     collections::{hash_map::Entry, HashMap},
     convert::TryInto,
     sync::{
         atomic::{AtomicU8, AtomicUsize, Ordering},
-        RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+        Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
     },
 };
 
-use anyhow::Context;
+use anyhow::{ensure, Context};
 use once_cell::sync::OnceCell;
-use tracing::error;
+use tracing::{error, info};
 use utils::{
     id::{TenantId, TimelineId},
     lsn::Lsn,
 };
 
 use crate::tenant::writeback_ephemeral_file;
-use crate::{metrics::PageCacheSizeMetrics, repository::Key};
+use crate::{
+    metrics::{PageCacheSizeMetrics, PageCacheTenantMetrics},
+    repository::Key,
+};
 
 static PAGE_CACHE: OnceCell<PageCache> = OnceCell::new();
 const TEST_PAGE_CACHE_SIZE: usize = 50;
@@ -62,8 +73,10 @@ const TEST_PAGE_CACHE_SIZE: usize = 50;
 ///
 /// Initialize the page cache. This must be called once at page server startup.
 ///
-pub fn init(size: usize) {
-    if PAGE_CACHE.set(PageCache::new(size)).is_err() {
+/// Buffers are allocated for `max_size` pages, of which `size` are used.
+///
+pub fn init(size: usize, max_size: usize) {
+    if PAGE_CACHE.set(PageCache::new(size, max_size)).is_err() {
         panic!("page cache already initialized");
     }
 }
@@ -78,7 +91,7 @@ pub fn get() -> &'static PageCache {
     // page cache is usable in unit tests.
     //
     if cfg!(test) {
-        PAGE_CACHE.get_or_init(|| PageCache::new(TEST_PAGE_CACHE_SIZE))
+        PAGE_CACHE.get_or_init(|| PageCache::new(TEST_PAGE_CACHE_SIZE, TEST_PAGE_CACHE_SIZE))
     } else {
         PAGE_CACHE.get().expect("page cache not initialized")
     }
@@ -184,6 +197,12 @@ pub struct PageCache {
     /// The actual buffers with their metadata.
     slots: Box<[Slot]>,
 
+    /// Number of buffers in use, the first ones in `slots`.
+    size: AtomicUsize,
+
+    /// Serializes the resizes.
+    resize_lock: Mutex<()>,
+
     /// Index of the next candidate to evict, for the Clock replacement algorithm.
     /// This is interpreted modulo the page cache size.
     next_evict_slot: AtomicUsize,
@@ -307,13 +326,15 @@ impl PageCache {
     ///
     /// The 'lsn' is an upper bound, this will return the latest version of
     /// the given block, but not newer than 'lsn'. Returns the actual LSN of the
-    /// returned page.
+    /// returned page. The hit or miss is counted in `tenant_metrics`, the page
+    /// cache statistics of the tenant.
     pub fn lookup_materialized_page(
         &self,
         tenant_id: TenantId,
         timeline_id: TimelineId,
         key: &Key,
         lsn: Lsn,
+        tenant_metrics: &PageCacheTenantMetrics,
     ) -> Option<(Lsn, PageReadGuard)> {
         crate::metrics::PAGE_CACHE
             .read_accesses_materialized_page
@@ -329,6 +350,7 @@ impl PageCache {
         };
 
         if let Some(guard) = self.try_lock_for_read(&mut cache_key) {
+            tenant_metrics.read_hits.inc();
             if let CacheKey::MaterializedPage {
                 hash_key: _,
                 lsn: available_lsn,
@@ -348,6 +370,7 @@ impl PageCache {
                 panic!("unexpected key type in slot");
             }
         } else {
+            tenant_metrics.read_misses.inc();
             None
         }
     }
@@ -452,6 +475,56 @@ impl PageCache {
         }
     }
 
+    // Section 1.4: Public interface functions for the configuration of the
+    // page cache.
+
+    /// Number of buffers in use.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Number of buffers allocated, the maximum size.
+    pub fn max_size(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Changes the number of buffers in use. The pages in the buffers going out
+    /// of use are evicted, except for the dirty ones that cannot be written
+    /// back: those stay cached until their file is dropped.
+    ///
+    /// This blocks until the buffers going out of use are unlocked.
+    pub fn resize(&self, num_pages: usize) -> anyhow::Result<()> {
+        ensure!(num_pages > 0, "page cache size must be > 0");
+        ensure!(
+            num_pages <= self.max_size(),
+            "page cache size {num_pages} is more than the max_page_cache_size {}",
+            self.max_size()
+        );
+
+        let _guard = self.resize_lock.lock().unwrap();
+        // From here on, find_victim() no longer puts pages into the buffers
+        // going out of use, see the check there.
+        let old_num_pages = self.size.swap(num_pages, Ordering::SeqCst);
+        let mut num_evicted = 0;
+        for slot in self.slots.iter().take(old_num_pages).skip(num_pages) {
+            let mut inner = slot.inner.write().unwrap();
+            if inner.key.is_none() {
+                continue;
+            }
+            match self.evict(&mut inner) {
+                Ok(()) => num_evicted += 1,
+                Err(err) => error!(
+                    "writeback of buffer {:?} failed, leaving it in the page cache: {err}",
+                    inner.key
+                ),
+            }
+        }
+        self.size_metrics.max_bytes.set_page_sz(num_pages);
+
+        info!("resized page cache from {old_num_pages} to {num_pages} pages, evicted {num_evicted} pages");
+        Ok(())
+    }
+
     //
     // Section 2: Internal interface functions for lookup/update.
     //
@@ -809,11 +882,11 @@ impl PageCache {
     ///
     /// On return, the slot is empty and write-locked.
     fn find_victim(&self) -> anyhow::Result<(usize, RwLockWriteGuard<SlotInner>)> {
-        let iter_limit = self.slots.len() * 10;
+        let iter_limit = self.size() * 10;
         let mut iters = 0;
         loop {
             iters += 1;
-            let slot_idx = self.next_evict_slot.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+            let slot_idx = self.next_evict_slot.fetch_add(1, Ordering::Relaxed) % self.size();
 
             let slot = &self.slots[slot_idx];
 
@@ -836,33 +909,60 @@ impl PageCache {
                         continue;
                     }
                 };
-                if let Some(old_key) = &inner.key {
-                    if inner.dirty {
-                        if let Err(err) = Self::writeback(old_key, inner.buf) {
-                            // Writing the page to disk failed.
-                            //
-                            // FIXME: What to do here, when? We could propagate the error to the
-                            // caller, but victim buffer is generally unrelated to the original
-                            // call. It can even belong to a different tenant. Currently, we
-                            // report the error to the log and continue the clock sweep to find
-                            // a different victim. But if the problem persists, the page cache
-                            // could fill up with dirty pages that we cannot evict, and we will
-                            // loop retrying the writebacks indefinitely.
-                            error!("writeback of buffer {:?} failed: {}", old_key, err);
-                            continue;
-                        }
-                    }
-
-                    // remove mapping for old buffer
-                    self.remove_mapping(old_key);
-                    inner.dirty = false;
-                    inner.key = None;
+                // The page cache was shrunk concurrently, and resize() is
+                // evicting this buffer, or already did.
+                if slot_idx >= self.size.load(Ordering::SeqCst) {
+                    continue;
+                }
+                if let Err(err) = self.evict(&mut inner) {
+                    // Writing the page to disk failed.
+                    //
+                    // FIXME: What to do here, when? We could propagate the error to the
+                    // caller, but victim buffer is generally unrelated to the original
+                    // call. It can even belong to a different tenant. Currently, we
+                    // report the error to the log and continue the clock sweep to find
+                    // a different victim. But if the problem persists, the page cache
+                    // could fill up with dirty pages that we cannot evict, and we will
+                    // loop retrying the writebacks indefinitely.
+                    error!("writeback of buffer {:?} failed: {}", inner.key, err);
+                    continue;
                 }
                 return Ok((slot_idx, inner));
             }
         }
     }
 
+    /// Evict the page in the write-locked slot, if any, writing it back first
+    /// if it's dirty. If the writeback fails, the page is left in place.
+    fn evict(&self, inner: &mut SlotInner) -> Result<(), std::io::Error> {
+        let Some(old_key) = &inner.key else {
+            return Ok(());
+        };
+        if inner.dirty {
+            Self::writeback(old_key, inner.buf)?;
+        }
+
+        let metrics = &crate::metrics::PAGE_CACHE;
+        match old_key {
+            CacheKey::MaterializedPage { hash_key, lsn: _ } => {
+                metrics.evictions_materialized_page.inc();
+                if let Some(tenant_metrics) =
+                    crate::metrics::existing_page_cache_tenant_metrics(&hash_key.tenant_id)
+                {
+                    tenant_metrics.evictions.inc();
+                }
+            }
+            CacheKey::EphemeralPage { .. } => metrics.evictions_ephemeral.inc(),
+            CacheKey::ImmutableFilePage { .. } => metrics.evictions_immutable.inc(),
+        }
+
+        // remove mapping for old buffer
+        self.remove_mapping(old_key);
+        inner.dirty = false;
+        inner.key = None;
+        Ok(())
+    }
+
     fn writeback(cache_key: &CacheKey, buf: &[u8]) -> Result<(), std::io::Error> {
         match cache_key {
             CacheKey::MaterializedPage {
@@ -888,10 +988,16 @@ impl PageCache {
     /// Initialize a new page cache
     ///
     /// This should be called only once at page server startup.
-    fn new(num_pages: usize) -> Self {
+    fn new(num_pages: usize, max_num_pages: usize) -> Self {
         assert!(num_pages > 0, "page cache size must be > 0");
+        assert!(
+            num_pages <= max_num_pages,
+            "page cache size must be <= the max size"
+        );
 
-        let page_buffer = Box::leak(vec![0u8; num_pages * PAGE_SZ].into_boxed_slice());
+        // The memory of the buffers not in use is not touched, so that the OS
+        // does not back it until the page cache grows into them.
+        let page_buffer = Box::leak(vec![0u8; max_num_pages * PAGE_SZ].into_boxed_slice());
 
         let size_metrics = &crate::metrics::PAGE_CACHE_SIZE;
         size_metrics.max_bytes.set_page_sz(num_pages);
@@ -920,6 +1026,8 @@ impl PageCache {
             ephemeral_page_map: Default::default(),
             immutable_page_map: Default::default(),
             slots,
+            size: AtomicUsize::new(num_pages),
+            resize_lock: Mutex::new(()),
             next_evict_slot: AtomicUsize::new(0),
             size_metrics,
         }
@@ -948,3 +1056,66 @@ impl PageSzBytesMetric for metrics::UIntGauge {
         self.sub(count_times_page_sz(count));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_evicts_pages_of_unused_buffers() -> anyhow::Result<()> {
+        let cache = PageCache::new(8, 8);
+        let tenant_id = TenantId::generate();
+        let timeline_id = TimelineId::generate();
+        let lsn = Lsn(0x10);
+        let img = [0u8; PAGE_SZ];
+        let tenant_metrics = crate::metrics::page_cache_tenant_metrics(&tenant_id);
+        let cached = |i| {
+            cache
+                .lookup_materialized_page(
+                    tenant_id,
+                    timeline_id,
+                    &Key::from_i128(i),
+                    lsn,
+                    &tenant_metrics,
+                )
+                .is_some()
+        };
+
+        // The clock sweep fills the buffers in order.
+        for i in 0..8 {
+            cache.memorize_materialized_page(
+                tenant_id,
+                timeline_id,
+                Key::from_i128(i),
+                lsn,
+                &img,
+            )?;
+        }
+        assert!((0..8).all(cached));
+
+        cache.resize(4)?;
+        assert_eq!(cache.size(), 4);
+        assert!((0..4).all(cached));
+        assert!(!(4..8).any(cached));
+
+        // New pages only go to the buffers in use.
+        for i in 8..12 {
+            cache.memorize_materialized_page(
+                tenant_id,
+                timeline_id,
+                Key::from_i128(i),
+                lsn,
+                &img,
+            )?;
+        }
+        assert!(cache.slots[4..]
+            .iter()
+            .all(|slot| slot.inner.read().unwrap().key.is_none()));
+
+        cache.resize(8)?;
+        assert!(cache.resize(0).is_err());
+        assert!(cache.resize(9).is_err());
+        assert_eq!(cache.size(), 8);
+        Ok(())
+    }
+}
@@ -10,19 +10,24 @@
 //
 
 use anyhow::Context;
-use async_compression::tokio::write::GzipEncoder;
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
 use bytes::Buf;
 use bytes::Bytes;
-use futures::Stream;
+use bytes::BytesMut;
+use futures::{SinkExt, Stream, StreamExt};
 use pageserver_api::models::TenantState;
 use pageserver_api::models::{
     PagestreamBeMessage, PagestreamDbSizeRequest, PagestreamDbSizeResponse,
     PagestreamErrorResponse, PagestreamExistsRequest, PagestreamExistsResponse,
     PagestreamFeMessage, PagestreamGetLatestLsnResponse, PagestreamGetPageRequest,
-    PagestreamGetPageResponse, PagestreamGetSlruPageRequest, PagestreamGetSlruPageResponse,
-    PagestreamNblocksRequest, PagestreamNblocksResponse,
+    PagestreamGetPageResponse, PagestreamGetPagesRequest, PagestreamGetPagesResponse,
+    PagestreamGetSlruPageRequest, PagestreamGetSlruPageResponse, PagestreamNblocksRequest,
+    PagestreamNblocksResponse,
+};
+use postgres::SimpleQueryMessage;
+use postgres_backend::{
+    self, is_expected_io_error, AuthType, CopyStreamHandlerEnd, PostgresBackend, QueryError,
 };
-use postgres_backend::{self, is_expected_io_error, AuthType, PostgresBackend, QueryError};
 use pq_proto::framed::ConnectionError;
 use pq_proto::FeStartupPacket;
 use pq_proto::{BeMessage, FeMessage, RowDescriptor};
@@ -63,8 +68,9 @@ use crate::tenant::mgr::GetTenantError;
 use crate::tenant::{Tenant, Timeline};
 use crate::trace::Tracer;
 
+use postgres_ffi::page_checksum::page_set_checksum;
 use postgres_ffi::pg_constants::DEFAULTTABLESPACE_OID;
-use postgres_ffi::BLCKSZ;
+use postgres_ffi::{page_is_new, BLCKSZ};
 
 fn copyin_stream<IO>(pgb: &mut PostgresBackend<IO>) -> impl Stream<Item = io::Result<Bytes>> + '_
 where
@@ -137,7 +143,7 @@ where
 ///
 /// XXX: Currently, any trailing data after the EOF marker prints a warning.
 /// Perhaps it should be a hard error?
-async fn read_tar_eof(mut reader: (impl AsyncRead + Unpin)) -> anyhow::Result<()> {
+pub(crate) async fn read_tar_eof(mut reader: (impl AsyncRead + Unpin)) -> anyhow::Result<()> {
     use tokio::io::AsyncReadExt;
     let mut buf = [0u8; 512];
 
@@ -313,6 +319,7 @@ struct PageRequestMetrics {
     get_rel_exists: metrics::Histogram,
     get_rel_size: metrics::Histogram,
     get_page_at_lsn: metrics::Histogram,
+    get_pages_at_lsn: metrics::Histogram,
     get_db_size: metrics::Histogram,
     get_slru_page: metrics::Histogram,
     get_latest_lsn: metrics::Histogram,
@@ -345,6 +352,13 @@ impl PageRequestMetrics {
             &timeline_region,
         ]);
 
+        let get_pages_at_lsn = SMGR_QUERY_TIME.with_label_values(&[
+            "get_pages_at_lsn",
+            &tenant_id,
+            &timeline_id,
+            &timeline_region,
+        ]);
+
         let get_db_size = SMGR_QUERY_TIME.with_label_values(&[
             "get_db_size",
             &tenant_id,
@@ -370,6 +384,7 @@ impl PageRequestMetrics {
             get_rel_exists,
             get_rel_size,
             get_page_at_lsn,
+            get_pages_at_lsn,
             get_db_size,
             get_slru_page,
             get_latest_lsn,
@@ -377,8 +392,20 @@ impl PageRequestMetrics {
     }
 }
 
+/// Maximum number of pages that can be requested in a single GetPages request,
+/// which bounds the size of the response to 1 MB.
+const MAX_GET_PAGES_BATCH: u32 = 128;
+
+/// Compression of a basebackup tarball, as requested by the client with the
+/// `--gzip` or `--zstd` parameter of the `basebackup` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BasebackupCompression {
+    Gzip,
+    Zstd,
+}
+
 struct PageServerHandler {
-    _conf: &'static PageServerConf,
+    conf: &'static PageServerConf,
     broker_client: storage_broker::BrokerClientChannel,
     auth: Option<Arc<JwtAuth>>,
     claims: Option<Claims>,
@@ -388,6 +415,12 @@ struct PageServerHandler {
     /// For each query received over the connection,
     /// `process_query` creates a child context from this one.
     connection_ctx: RequestContext,
+
+    /// The timeline named in the options of the connection, which standby
+    /// computes replicating it use, like they do when connecting to safekeepers.
+    replication_tenant_id: Option<TenantId>,
+    replication_timeline_id: Option<TimelineId>,
+    replication_appname: Option<String>,
 }
 
 impl PageServerHandler {
@@ -398,11 +431,14 @@ impl PageServerHandler {
         connection_ctx: RequestContext,
     ) -> Self {
         PageServerHandler {
-            _conf: conf,
+            conf,
             broker_client,
             auth,
             claims: None,
             connection_ctx,
+            replication_tenant_id: None,
+            replication_timeline_id: None,
+            replication_appname: None,
         }
     }
 
@@ -494,6 +530,14 @@ impl PageServerHandler {
 
             trace!("query: {copy_data_bytes:?}");
 
+            // Stop serving the pages of a tenant that is being detached, or the
+            // pageserver is shutting down: its layers may be gone already.
+            if let TenantState::Stopping { .. } = tenant.current_state() {
+                return Err(QueryError::Unavailable(anyhow::anyhow!(
+                    "tenant {tenant_id} is stopping"
+                )));
+            }
+
             // Trace request if needed
             if let Some(t) = tracer.as_mut() {
                 t.trace(&copy_data_bytes)
@@ -557,6 +601,7 @@ impl PageServerHandler {
                     }
                 }
                 PagestreamFeMessage::GetPage(mut req) => {
+                    let _permit = tenant.admit_getpage_request().await;
                     match get_timeline_and_metrics_by_region_id(&timelines, &metrics, req.region) {
                         Ok((timeline, metrics)) => {
                             let timer = metrics.get_page_at_lsn.start_timer();
@@ -579,6 +624,32 @@ impl PageServerHandler {
                         Err(e) => Err(e),
                     }
                 }
+                PagestreamFeMessage::GetPages(mut req) => {
+                    let _permit = tenant.admit_getpage_request().await;
+                    match get_timeline_and_metrics_by_region_id(&timelines, &metrics, req.region) {
+                        Ok((timeline, metrics)) => {
+                            let timer = metrics.get_pages_at_lsn.start_timer();
+                            match Self::handle_get_pages_at_lsn_request(&timeline, &req, &ctx).await
+                            {
+                                res @ Ok(_) => res,
+                                Err(_) => {
+                                    timer.stop_and_record();
+                                    // Start a new timer for the main timeline
+                                    let _timer = main_metrics.get_pages_at_lsn.start_timer();
+                                    req.latest = true;
+                                    req.lsn = Lsn(0);
+                                    Self::handle_get_pages_at_lsn_request(
+                                        &main_timeline,
+                                        &req,
+                                        &ctx,
+                                    )
+                                    .await
+                                }
+                            }
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
                 PagestreamFeMessage::DbSize(req) => {
                     match get_timeline_and_metrics_by_region_id(&timelines, &metrics, req.region) {
                         Ok((timeline, metrics)) => {
@@ -804,6 +875,7 @@ impl PageServerHandler {
             "tried to request a page version that was garbage collected. requested at {} gc cutoff {}",
             lsn, **latest_gc_cutoff_lsn
         );
+        timeline.check_lsn_above_purge_floor(lsn)?;
         Ok(lsn)
     }
 
@@ -903,6 +975,7 @@ impl PageServerHandler {
         let page = timeline
             .get_rel_page_at_lsn(req.rel, req.blkno, Version::Lsn(lsn), req.latest, ctx)
             .await?;
+        let page = with_page_checksum(timeline, page, req.blkno, lsn, ctx).await?;
 
         Ok(PagestreamBeMessage::GetPage(PagestreamGetPageResponse {
             lsn,
@@ -910,6 +983,44 @@ impl PageServerHandler {
         }))
     }
 
+    #[instrument(skip(timeline, req, ctx), fields(region = %timeline.region_id, rel = %req.rel, blkno = %req.blkno, nblocks = %req.nblocks, req_lsn = %req.lsn))]
+    async fn handle_get_pages_at_lsn_request(
+        timeline: &Timeline,
+        req: &PagestreamGetPagesRequest,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<PagestreamBeMessage> {
+        anyhow::ensure!(
+            req.nblocks <= MAX_GET_PAGES_BATCH,
+            "requested {} pages in a batch, the maximum is {MAX_GET_PAGES_BATCH}",
+            req.nblocks
+        );
+
+        let latest_gc_cutoff_lsn = timeline.get_latest_gc_cutoff_lsn();
+        let lsn =
+            Self::wait_or_get_last_lsn(timeline, req.lsn, req.latest, &latest_gc_cutoff_lsn, ctx)
+                .await?;
+
+        // All the pages are read at the same LSN, so they are consistent with
+        // each other, as if they had been requested one by one at that LSN.
+        let rel_size = timeline
+            .get_rel_size(req.rel, Version::Lsn(lsn), req.latest, ctx)
+            .await?;
+        let end = rel_size.min(req.blkno.saturating_add(req.nblocks));
+
+        let mut pages = Vec::with_capacity(end.saturating_sub(req.blkno) as usize);
+        for blkno in req.blkno..end {
+            let page = timeline
+                .get_rel_page_at_lsn(req.rel, blkno, Version::Lsn(lsn), req.latest, ctx)
+                .await?;
+            pages.push(with_page_checksum(timeline, page, blkno, lsn, ctx).await?);
+        }
+
+        Ok(PagestreamBeMessage::GetPages(PagestreamGetPagesResponse {
+            lsn,
+            pages,
+        }))
+    }
+
     #[instrument(skip(self, timeline, req, ctx), fields(region = %timeline.region_id, slru_kind = %req.kind.to_str(), segno = %req.segno,
                  check_blkno = %req.blkno, req_lsn = %req.lsn, check_exists_only = %req.check_exists_only))]
     async fn handle_get_slru_page_at_lsn_request(
@@ -974,7 +1085,7 @@ impl PageServerHandler {
     }
 
     #[allow(clippy::too_many_arguments)]
-    #[instrument(skip_all, fields(?lsn, ?prev_lsn, %full_backup))]
+    #[instrument(skip_all, fields(?lsn, ?prev_lsn, %full_backup, %include_catalog))]
     async fn handle_basebackup_request<IO>(
         &mut self,
         pgb: &mut PostgresBackend<IO>,
@@ -983,7 +1094,8 @@ impl PageServerHandler {
         lsn: Option<Lsn>,
         prev_lsn: Option<Lsn>,
         full_backup: bool,
-        gzip: bool,
+        include_catalog: bool,
+        compression: Option<BasebackupCompression>,
         ctx: RequestContext,
     ) -> anyhow::Result<()>
     where
@@ -1003,6 +1115,9 @@ impl PageServerHandler {
             timeline
                 .check_lsn_is_in_scope(lsn, &latest_gc_cutoff_lsn)
                 .context("invalid basebackup lsn")?;
+            timeline
+                .check_lsn_above_purge_floor(lsn)
+                .context("invalid basebackup lsn")?;
         }
 
         let lsn_awaited_after = started.elapsed();
@@ -1021,42 +1136,66 @@ impl PageServerHandler {
                 lsn,
                 prev_lsn,
                 full_backup,
+                include_catalog,
                 &ctx,
             )
             .await?;
         } else {
             let mut writer = pgb.copyout_writer();
-            if gzip {
-                let mut encoder = GzipEncoder::with_quality(
-                    writer,
-                    // NOTE using fast compression because it's on the critical path
-                    //      for compute startup. For an empty database, we get
-                    //      <100KB with this method. The Level::Best compression method
-                    //      gives us <20KB, but maybe we should add basebackup caching
-                    //      on compute shutdown first.
-                    async_compression::Level::Fastest,
-                );
-                basebackup::send_basebackup_tarball(
-                    &mut encoder,
-                    &timeline,
-                    lsn,
-                    prev_lsn,
-                    full_backup,
-                    &ctx,
-                )
-                .await?;
-                // shutdown the encoder to ensure the gzip footer is written
-                encoder.shutdown().await?;
-            } else {
-                basebackup::send_basebackup_tarball(
-                    &mut writer,
-                    &timeline,
-                    lsn,
-                    prev_lsn,
-                    full_backup,
-                    &ctx,
-                )
-                .await?;
+            match compression {
+                Some(BasebackupCompression::Gzip) => {
+                    let mut encoder = GzipEncoder::with_quality(
+                        writer,
+                        // NOTE using fast compression because it's on the critical path
+                        //      for compute startup. For an empty database, we get
+                        //      <100KB with this method. The Level::Best compression method
+                        //      gives us <20KB, but maybe we should add basebackup caching
+                        //      on compute shutdown first.
+                        async_compression::Level::Fastest,
+                    );
+                    basebackup::send_basebackup_tarball(
+                        &mut encoder,
+                        &timeline,
+                        lsn,
+                        prev_lsn,
+                        full_backup,
+                        include_catalog,
+                        &ctx,
+                    )
+                    .await?;
+                    // shutdown the encoder to ensure the gzip footer is written
+                    encoder.shutdown().await?;
+                }
+                Some(BasebackupCompression::Zstd) => {
+                    let mut encoder = ZstdEncoder::with_quality(
+                        writer,
+                        async_compression::Level::Precise(self.conf.basebackup_zstd_level),
+                    );
+                    basebackup::send_basebackup_tarball(
+                        &mut encoder,
+                        &timeline,
+                        lsn,
+                        prev_lsn,
+                        full_backup,
+                        include_catalog,
+                        &ctx,
+                    )
+                    .await?;
+                    // shutdown the encoder to ensure the end of the zstd frame is written
+                    encoder.shutdown().await?;
+                }
+                None => {
+                    basebackup::send_basebackup_tarball(
+                        &mut writer,
+                        &timeline,
+                        lsn,
+                        prev_lsn,
+                        full_backup,
+                        include_catalog,
+                        &ctx,
+                    )
+                    .await?;
+                }
             }
         }
 
@@ -1077,6 +1216,168 @@ impl PageServerHandler {
         Ok(())
     }
 
+    /// Connects to the safekeeper that the timeline named in the connection
+    /// options receives WAL from, to relay the replication commands of a
+    /// standby compute to it. The pageserver doesn't keep the WAL itself, only
+    /// the records decoded from it.
+    async fn connect_to_wal_source(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<
+        (
+            tokio_postgres::Client,
+            tokio_postgres::Connection<tokio_postgres::Socket, tokio_postgres::tls::NoTlsStream>,
+        ),
+        QueryError,
+    > {
+        let (Some(tenant_id), Some(timeline_id)) =
+            (self.replication_tenant_id, self.replication_timeline_id)
+        else {
+            return Err(QueryError::Other(anyhow::anyhow!(
+                "replication connections must set the tenant_id and timeline_id options"
+            )));
+        };
+        tracing::Span::current()
+            .record("tenant_id", field::display(tenant_id))
+            .record("timeline_id", field::display(timeline_id));
+
+        self.check_permission(Some(tenant_id))?;
+        let timeline = get_active_tenant_timeline(tenant_id, timeline_id, ctx).await?;
+
+        let wal_source_connconf = timeline
+            .last_received_wal
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|info| info.wal_source_connconf.clone())
+            .ok_or_else(|| {
+                QueryError::Unavailable(anyhow::anyhow!(
+                    "timeline {timeline_id} is not receiving WAL from any safekeeper"
+                ))
+            })?;
+
+        let mut config = wal_source_connconf.to_tokio_postgres_config();
+        config.application_name(self.replication_appname.as_deref().unwrap_or("standby"));
+        config.replication_mode(tokio_postgres::config::ReplicationMode::Physical);
+        let client_and_conn = config
+            .connect(postgres::NoTls)
+            .await
+            .with_context(|| format!("failed to connect to safekeeper {wal_source_connconf:?}"))?;
+        Ok(client_and_conn)
+    }
+
+    async fn handle_identify_system<IO>(
+        &self,
+        pgb: &mut PostgresBackend<IO>,
+        ctx: RequestContext,
+    ) -> Result<(), QueryError>
+    where
+        IO: AsyncRead + AsyncWrite + Send + Sync + Unpin,
+    {
+        let (client, connection) = self.connect_to_wal_source(&ctx).await?;
+        let response = tokio::select! {
+            response = client.simple_query("IDENTIFY_SYSTEM") => {
+                response.context("IDENTIFY_SYSTEM failed on safekeeper")?
+            }
+            result = connection => {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "safekeeper connection closed: {result:?}"
+                )));
+            }
+        };
+        let row = response
+            .iter()
+            .find_map(|message| match message {
+                SimpleQueryMessage::Row(row) => Some(row),
+                _ => None,
+            })
+            .context("safekeeper returned no IDENTIFY_SYSTEM row")?;
+
+        let columns = row
+            .columns()
+            .iter()
+            .map(|column| RowDescriptor::text_col(column.name().as_bytes()))
+            .collect::<Vec<_>>();
+        let values = (0..row.len())
+            .map(|i| row.get(i).map(str::as_bytes))
+            .collect::<Vec<_>>();
+        pgb.write_message_noflush(&BeMessage::RowDescription(&columns))?
+            .write_message_noflush(&BeMessage::DataRow(&values))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        Ok(())
+    }
+
+    /// Relays the WAL stream of the safekeeper to a standby compute, and its
+    /// replies back to the safekeeper, until either side ends it.
+    async fn handle_start_replication<IO>(
+        &self,
+        pgb: &mut PostgresBackend<IO>,
+        query_string: &str,
+        ctx: RequestContext,
+    ) -> Result<(), QueryError>
+    where
+        IO: AsyncRead + AsyncWrite + Send + Sync + Unpin,
+    {
+        let (client, connection) = self.connect_to_wal_source(&ctx).await?;
+        let mut connection = pin!(connection);
+        let duplex = tokio::select! {
+            duplex = client.copy_both_simple::<Bytes>(query_string) => {
+                duplex.context("START_REPLICATION failed on safekeeper")?
+            }
+            result = &mut connection => {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "safekeeper connection closed: {result:?}"
+                )));
+            }
+        };
+        let mut duplex = pin!(duplex);
+
+        info!("relaying replication from safekeeper: {query_string}");
+        pgb.write_message(&BeMessage::CopyBothResponse).await?;
+
+        let mut reader = pgb.split().context("START_REPLICATION split")?;
+        let result: Result<(), CopyStreamHandlerEnd> = async {
+            loop {
+                tokio::select! {
+                    _ = task_mgr::shutdown_watcher() => {
+                        return Err(CopyStreamHandlerEnd::ServerInitiated(
+                            "pageserver is shutting down".to_string(),
+                        ));
+                    }
+                    wal = duplex.next() => match wal {
+                        Some(data) => {
+                            let data = data.context("failed to receive WAL from safekeeper")?;
+                            pgb.write_message(&BeMessage::CopyData(&data)).await?;
+                        }
+                        None => {
+                            return Err(CopyStreamHandlerEnd::ServerInitiated(
+                                "safekeeper ended streaming".to_string(),
+                            ));
+                        }
+                    },
+                    reply = reader.read_copy_message() => {
+                        duplex
+                            .send(reply?)
+                            .await
+                            .context("failed to send reply to safekeeper")?;
+                    }
+                    result = &mut connection => {
+                        return Err(CopyStreamHandlerEnd::Other(anyhow::anyhow!(
+                            "safekeeper connection closed: {result:?}"
+                        )));
+                    }
+                }
+            }
+        }
+        .await;
+        pgb.unsplit(reader)?;
+
+        if let Err(end) = result {
+            pgb.handle_copy_stream_end(end).await;
+        }
+        Ok(())
+    }
+
     // when accessing management api supply None as an argument
     // when using to authorize tenant pass corresponding tenant id
     fn check_permission(&self, tenant_id: Option<TenantId>) -> anyhow::Result<()> {
@@ -1131,8 +1432,30 @@ where
     fn startup(
         &mut self,
         _pgb: &mut PostgresBackend<IO>,
-        _sm: &FeStartupPacket,
+        sm: &FeStartupPacket,
     ) -> Result<(), QueryError> {
+        if let FeStartupPacket::StartupMessage { params, .. } = sm {
+            if let Some(options) = params.options_raw() {
+                for opt in options {
+                    match opt.split_once('=') {
+                        Some(("tenant_id", value)) => {
+                            self.replication_tenant_id =
+                                Some(value.parse().with_context(|| {
+                                    format!("Failed to parse {value} as tenant id")
+                                })?);
+                        }
+                        Some(("timeline_id", value)) => {
+                            self.replication_timeline_id =
+                                Some(value.parse().with_context(|| {
+                                    format!("Failed to parse {value} as timeline id")
+                                })?);
+                        }
+                        _ => continue,
+                    }
+                }
+            }
+            self.replication_appname = params.get("application_name").map(str::to_owned);
+        }
         Ok(())
     }
 
@@ -1202,27 +1525,28 @@ where
 
             self.check_permission(Some(tenant_id))?;
 
-            let lsn = if params.len() >= 3 {
-                Some(
-                    Lsn::from_str(params[2])
-                        .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?,
-                )
-            } else {
-                None
-            };
-
-            let gzip = if params.len() >= 4 {
-                if params[3] == "--gzip" {
-                    true
-                } else {
-                    return Err(QueryError::Other(anyhow::anyhow!(
-                        "Parameter in position 3 unknown {}",
-                        params[3],
-                    )));
+            // The Lsn is optional, and can be followed by the flags.
+            let mut lsn = None;
+            let mut compression = None;
+            let mut include_catalog = false;
+            for (i, param) in params.iter().enumerate().skip(2) {
+                match *param {
+                    "--gzip" => compression = Some(BasebackupCompression::Gzip),
+                    "--zstd" => compression = Some(BasebackupCompression::Zstd),
+                    "--catalog" => include_catalog = true,
+                    _ if i == 2 => {
+                        lsn = Some(
+                            Lsn::from_str(param)
+                                .with_context(|| format!("Failed to parse Lsn from {param}"))?,
+                        )
+                    }
+                    _ => {
+                        return Err(QueryError::Other(anyhow::anyhow!(
+                            "Parameter in position {i} unknown {param}",
+                        )))
+                    }
                 }
-            } else {
-                false
-            };
+            }
 
             metrics::metric_vec_duration::observe_async_block_duration_by_result(
                 &*crate::metrics::BASEBACKUP_QUERY_TIME,
@@ -1234,7 +1558,8 @@ where
                         lsn,
                         None,
                         false,
-                        gzip,
+                        include_catalog,
+                        compression,
                         ctx,
                     )
                     .await?;
@@ -1279,6 +1604,46 @@ where
             ]))?
             .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
         }
+        // wait until the WAL up to the LSN is ingested and flushed to disk
+        else if query_string.starts_with("wait_lsn ") {
+            let (_, params_raw) = query_string.split_at("wait_lsn ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+
+            if params.len() < 3 || params.len() > 4 {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "invalid param number for wait_lsn command"
+                )));
+            }
+
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+            let lsn = Lsn::from_str(params[2])
+                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?;
+            let timeout = match params.get(3) {
+                Some(timeout_ms) => Duration::from_millis(
+                    timeout_ms
+                        .parse()
+                        .with_context(|| format!("Failed to parse timeout from {timeout_ms}"))?,
+                ),
+                None => self.conf.wait_lsn_timeout,
+            };
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+            let timeline = get_active_tenant_timeline(tenant_id, timeline_id, &ctx).await?;
+
+            timeline
+                .wait_lsn_durable(lsn, timeout, &task_mgr::shutdown_token())
+                .await
+                .map_err(|e| QueryError::Other(e.into()))?;
+
+            pgb.write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        }
         // same as basebackup, but result includes relational data as well
         else if query_string.starts_with("fullbackup ") {
             let (_, params_raw) = query_string.split_at("fullbackup ".len());
@@ -1328,6 +1693,7 @@ where
                 prev_lsn,
                 true,
                 false,
+                None,
                 ctx,
             )
             .await?;
@@ -1429,6 +1795,11 @@ where
                     ))?
                 }
             };
+        } else if query_string.starts_with("IDENTIFY_SYSTEM") {
+            self.handle_identify_system(pgb, ctx).await?;
+        } else if query_string.starts_with("START_REPLICATION") {
+            self.handle_start_replication(pgb, query_string, ctx)
+                .await?;
         } else if query_string.to_ascii_lowercase().starts_with("set ") {
             // important because psycopg2 executes "SET datestyle TO 'ISO'"
             // on connect
@@ -1513,9 +1884,18 @@ enum GetActiveTenantError {
 impl From<GetActiveTenantError> for QueryError {
     fn from(e: GetActiveTenantError) -> Self {
         match e {
-            GetActiveTenantError::WaitForActiveTimeout { .. } => QueryError::Disconnected(
-                ConnectionError::Io(io::Error::new(io::ErrorKind::TimedOut, e.to_string())),
-            ),
+            // The tenant is still loading, or is being detached: tell the client to
+            // come back later with a specific error code, rather than failing.
+            GetActiveTenantError::WaitForActiveTimeout { .. } => {
+                QueryError::Unavailable(anyhow::Error::new(e))
+            }
+            GetActiveTenantError::WaitTenantActive(
+                e @ (tenant::WaitToBecomeActiveError::WillNotBecomeActive {
+                    state: TenantState::Stopping { .. },
+                    ..
+                }
+                | tenant::WaitToBecomeActiveError::TenantDropped { .. }),
+            ) => QueryError::Unavailable(anyhow::Error::new(e)),
             GetActiveTenantError::WaitTenantActive(e) => QueryError::Other(anyhow::Error::new(e)),
             GetActiveTenantError::NotFound(e) => QueryError::Other(anyhow::Error::new(e)),
         }
@@ -1616,3 +1996,129 @@ fn get_timeline_and_metrics_by_region_id<'a>(
                 .map(|metrics| (timeline, metrics))
         })
 }
+
+/// Stamps the checksum of a relation page served to a compute, if the cluster
+/// uses data checksums. The pages reconstructed by WAL redo and the full-page
+/// images from the WAL don't carry a valid one: Postgres only computes it when
+/// writing a page out to disk.
+async fn with_page_checksum(
+    timeline: &Timeline,
+    page: Bytes,
+    blkno: u32,
+    lsn: Lsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<Bytes> {
+    if page_is_new(&page) || !timeline.get_data_checksums_enabled(lsn, ctx).await? {
+        return Ok(page);
+    }
+    let mut page = BytesMut::from(&page[..]);
+    page_set_checksum(&mut page, blkno);
+    Ok(page.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::harness::*;
+    use crate::DEFAULT_PG_VERSION;
+    use pageserver_api::reltag::RelTag;
+
+    const TESTREL: RelTag = RelTag {
+        spcnode: 1663,
+        dbnode: 111,
+        relnode: 1000,
+        forknum: 0,
+    };
+
+    fn get_pages_request(
+        latest: bool,
+        lsn: Lsn,
+        blkno: u32,
+        nblocks: u32,
+    ) -> PagestreamGetPagesRequest {
+        PagestreamGetPagesRequest {
+            latest,
+            lsn,
+            region: RegionId(0),
+            rel: TESTREL,
+            blkno,
+            nblocks,
+        }
+    }
+
+    async fn get_pages(
+        timeline: &Timeline,
+        req: PagestreamGetPagesRequest,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<Bytes>> {
+        match PageServerHandler::handle_get_pages_at_lsn_request(timeline, &req, ctx).await? {
+            PagestreamBeMessage::GetPages(resp) => Ok(resp.pages),
+            _ => panic!("unexpected response to a GetPages request"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_pages_at_lsn() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_get_pages_at_lsn")?.load().await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x10),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        // Four blocks at 0x20, the first two of them overwritten at 0x30. The
+        // control file says whether to stamp checksums on the pages.
+        let mut m = tline.begin_modification(Lsn(0x20));
+        m.put_control_file(postgres_ffi::v15::ControlFileData::default().encode())?;
+        m.put_relmap_file(TESTREL.spcnode, TESTREL.dbnode, Bytes::new(), &ctx)
+            .await?;
+        m.put_rel_creation(TESTREL, 4, &ctx).await?;
+        for blkno in 0..4 {
+            m.put_rel_page_image(TESTREL, blkno, TEST_IMG(&format!("blk {blkno} at 0x20")))?;
+        }
+        m.commit().await?;
+        let mut m = tline.begin_modification(Lsn(0x30));
+        for blkno in 0..2 {
+            m.put_rel_page_image(TESTREL, blkno, TEST_IMG(&format!("blk {blkno} at 0x30")))?;
+        }
+        m.commit().await?;
+
+        // All the pages are read at the requested LSN.
+        let pages = get_pages(&tline, get_pages_request(false, Lsn(0x20), 1, 2), &ctx).await?;
+        assert_eq!(
+            pages,
+            [TEST_IMG("blk 1 at 0x20"), TEST_IMG("blk 2 at 0x20")]
+        );
+        let pages = get_pages(&tline, get_pages_request(true, Lsn(0x20), 1, 2), &ctx).await?;
+        assert_eq!(
+            pages,
+            [TEST_IMG("blk 1 at 0x30"), TEST_IMG("blk 2 at 0x20")]
+        );
+
+        // The range is cut at the end of the relation.
+        let pages = get_pages(&tline, get_pages_request(false, Lsn(0x30), 2, 10), &ctx).await?;
+        assert_eq!(
+            pages,
+            [TEST_IMG("blk 2 at 0x20"), TEST_IMG("blk 3 at 0x20")]
+        );
+        let pages = get_pages(&tline, get_pages_request(false, Lsn(0x30), 4, 10), &ctx).await?;
+        assert!(pages.is_empty());
+
+        // Too many pages in one request.
+        let req = get_pages_request(false, Lsn(0x30), 0, MAX_GET_PAGES_BATCH + 1);
+        assert!(get_pages(&tline, req, &ctx).await.is_err());
+
+        // LSN 0 is only valid for the latest pages, and the relation doesn't
+        // exist before 0x20.
+        let req = get_pages_request(false, Lsn(0), 0, 1);
+        assert!(get_pages(&tline, req, &ctx).await.is_err());
+        let req = get_pages_request(false, Lsn(0x10), 0, 1);
+        assert!(get_pages(&tline, req, &ctx).await.is_err());
+
+        Ok(())
+    }
+}
@@ -9,6 +9,7 @@
 use super::tenant::{PageReconstructError, Timeline};
 use crate::context::RequestContext;
 use crate::keyspace::{KeySpace, KeySpaceAccum};
+use crate::metrics::{RELSIZE_CACHE_HITS, RELSIZE_CACHE_MISSES};
 use crate::repository::*;
 use crate::walrecord::NeonWalRecord;
 use anyhow::{ensure, Context};
@@ -169,8 +170,10 @@ impl Timeline {
         }
 
         if let Some(nblocks) = self.get_cached_rel_size(&tag, version.get_lsn()) {
+            RELSIZE_CACHE_HITS.inc();
             return Ok(nblocks);
         }
+        RELSIZE_CACHE_MISSES.inc();
 
         if (tag.forknum == FSM_FORKNUM || tag.forknum == VISIBILITYMAP_FORKNUM)
             && !self.get_rel_exists(tag, version, latest, ctx).await?
@@ -504,6 +507,39 @@ impl Timeline {
         self.get(CHECKPOINT_KEY, lsn, ctx).await
     }
 
+    /// Get the WAL segment size the cluster was initialized with, from the
+    /// control file.
+    pub async fn get_wal_seg_size(
+        &self,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<usize, PageReconstructError> {
+        let pg_control_bytes = self.get_control_file(lsn, ctx).await?;
+        Ok(postgres_ffi::wal_seg_size(
+            &pg_control_bytes,
+            self.pg_version,
+        )?)
+    }
+
+    /// Whether the cluster was initialized with data checksums, from the
+    /// control file. Data checksums are chosen at initdb, so the answer is
+    /// cached for the lifetime of the timeline.
+    pub async fn get_data_checksums_enabled(
+        &self,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<bool, PageReconstructError> {
+        self.data_checksums_enabled
+            .get_or_try_init(|| async {
+                let pg_control_bytes = self.get_control_file(lsn, ctx).await?;
+                let enabled =
+                    postgres_ffi::data_checksums_enabled(&pg_control_bytes, self.pg_version)?;
+                Ok::<_, PageReconstructError>(enabled)
+            })
+            .await
+            .copied()
+    }
+
     /// Does the same as get_current_logical_size but counted on demand.
     /// Used to initialize the logical size tracking on startup.
     ///
@@ -591,6 +627,7 @@ impl Timeline {
             SlruKind::MultiXactMembers,
             SlruKind::MultiXactOffsets,
             SlruKind::Csn,
+            SlruKind::CommitTs,
         ] {
             let slrudir_key = slru_dir_to_key(kind);
             result.add_key(slrudir_key);
@@ -626,6 +663,73 @@ impl Timeline {
         Ok(result.to_keyspace())
     }
 
+    /// Get the key range of the relation `relnode` of the given database, all
+    /// its forks, or of the whole database if `relnode` is `None`, if it
+    /// doesn't exist at `lsn`.
+    pub async fn dropped_key_range(
+        &self,
+        spcnode: Oid,
+        dbnode: Oid,
+        relnode: Option<Oid>,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<Option<Range<Key>>, PageReconstructError> {
+        let db_exists = self
+            .list_dbdirs(lsn, ctx)
+            .await?
+            .contains_key(&(spcnode, dbnode));
+        match relnode {
+            None if db_exists => Ok(None),
+            None => Ok(Some(dbdir_key_range(spcnode, dbnode))),
+            Some(relnode) => {
+                if db_exists
+                    && self
+                        .list_rels(spcnode, dbnode, Version::Lsn(lsn), ctx)
+                        .await?
+                        .iter()
+                        .any(|rel| rel.relnode == relnode)
+                {
+                    return Ok(None);
+                }
+                Ok(Some(rel_forks_key_range(spcnode, dbnode, relnode)))
+            }
+        }
+    }
+
+    /// Get the sizes of all the relations at `lsn`, along with the LSN of their
+    /// entry in the relation size cache, if any.
+    pub async fn list_rel_sizes(
+        &self,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<Vec<(RelTag, BlockNumber, Option<Lsn>)>, PageReconstructError> {
+        let mut dbdirs: Vec<(Oid, Oid)> = self.list_dbdirs(lsn, ctx).await?.into_keys().collect();
+        dbdirs.sort_unstable();
+
+        let mut result = Vec::new();
+        for (spcnode, dbnode) in dbdirs {
+            let mut rels: Vec<RelTag> = self
+                .list_rels(spcnode, dbnode, Version::Lsn(lsn), ctx)
+                .await?
+                .into_iter()
+                .collect();
+            rels.sort_unstable();
+            for rel in rels {
+                let nblocks = self
+                    .get_rel_size(rel, Version::Lsn(lsn), false, ctx)
+                    .await?;
+                let cached_lsn = self
+                    .rel_size_cache
+                    .read()
+                    .unwrap()
+                    .get(&rel)
+                    .map(|(cached_lsn, _)| *cached_lsn);
+                result.push((rel, nblocks, cached_lsn));
+            }
+        }
+        Ok(result)
+    }
+
     /// Get cached size of relation if it not updated after specified LSN
     pub fn get_cached_rel_size(&self, tag: &RelTag, lsn: Lsn) -> Option<BlockNumber> {
         let rel_size_cache = self.rel_size_cache.read().unwrap();
@@ -733,7 +837,8 @@ impl<'a> DatadirModification<'a> {
             slru_dir_to_key(SlruKind::MultiXactOffsets),
             empty_dir.clone(),
         );
-        self.put(slru_dir_to_key(SlruKind::Csn), empty_dir);
+        self.put(slru_dir_to_key(SlruKind::Csn), empty_dir.clone());
+        self.put(slru_dir_to_key(SlruKind::CommitTs), empty_dir);
 
         Ok(())
     }
@@ -1515,6 +1620,24 @@ fn rel_key_range(rel: RelTag) -> Range<Key> {
     }
 }
 
+fn rel_forks_key_range(spcnode: Oid, dbnode: Oid, relnode: Oid) -> Range<Key> {
+    Key {
+        field1: 0x00,
+        field2: spcnode,
+        field3: dbnode,
+        field4: relnode,
+        field5: 0,
+        field6: 0,
+    }..Key {
+        field1: 0x00,
+        field2: spcnode,
+        field3: dbnode,
+        field4: relnode,
+        field5: 0xff,
+        field6: 0xffffffff,
+    }
+}
+
 //-- Section 02: SLRUs
 
 fn slru_dir_to_key(kind: SlruKind) -> Key {
@@ -1525,6 +1648,7 @@ fn slru_dir_to_key(kind: SlruKind) -> Key {
             SlruKind::MultiXactMembers => 0x01,
             SlruKind::MultiXactOffsets => 0x02,
             SlruKind::Csn => 0x03,
+            SlruKind::CommitTs => 0x04,
         },
         field3: 0,
         field4: 0,
@@ -1541,6 +1665,7 @@ fn slru_block_to_key(kind: SlruKind, segno: u32, blknum: BlockNumber) -> Key {
             SlruKind::MultiXactMembers => 0x01,
             SlruKind::MultiXactOffsets => 0x02,
             SlruKind::Csn => 0x03,
+            SlruKind::CommitTs => 0x04,
         },
         field3: 1,
         field4: segno,
@@ -1557,6 +1682,7 @@ fn slru_segment_size_to_key(kind: SlruKind, segno: u32) -> Key {
             SlruKind::MultiXactMembers => 0x01,
             SlruKind::MultiXactOffsets => 0x02,
             SlruKind::Csn => 0x03,
+            SlruKind::CommitTs => 0x04,
         },
         field3: 1,
         field4: segno,
@@ -1571,6 +1697,7 @@ fn slru_segment_key_range(kind: SlruKind, segno: u32) -> Range<Key> {
         SlruKind::MultiXactMembers => 0x01,
         SlruKind::MultiXactOffsets => 0x02,
         SlruKind::Csn => 0x03,
+        SlruKind::CommitTs => 0x04,
     };
 
     Key {
@@ -1669,7 +1796,7 @@ pub fn key_to_rel_block(key: Key) -> anyhow::Result<(RelTag, BlockNumber)> {
     })
 }
 
-fn is_rel_block_key(key: Key) -> bool {
+pub fn is_rel_block_key(key: Key) -> bool {
     key.field1 == 0x00 && key.field4 != 0
 }
 
@@ -1692,6 +1819,7 @@ pub fn key_to_slru_block(key: Key) -> anyhow::Result<(SlruKind, u32, BlockNumber
                 0x01 => SlruKind::MultiXactMembers,
                 0x02 => SlruKind::MultiXactOffsets,
                 0x03 => SlruKind::Csn,
+                0x04 => SlruKind::CommitTs,
                 _ => anyhow::bail!("unrecognized slru kind 0x{:02x}", key.field2),
             };
             let segno = key.field4;
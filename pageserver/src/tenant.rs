@@ -13,11 +13,15 @@
 
 use anyhow::{bail, Context};
 use futures::FutureExt;
+use pageserver_api::models::GcBranchPoint;
+use pageserver_api::models::TenantStateTransition;
+use pageserver_api::models::TimelineGcExplanation;
 use pageserver_api::models::TimelineState;
+use postgres_ffi::Oid;
 use remote_storage::DownloadError;
 use remote_storage::GenericRemoteStorage;
 use storage_broker::BrokerClientChannel;
-use tokio::sync::watch;
+use tokio::sync::{watch, OwnedSemaphorePermit};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 use tracing::*;
@@ -28,12 +32,14 @@ use std::cmp::min;
 use std::collections::hash_map::Entry;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io;
 use std::io::Write;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::ops::Bound::Included;
 use std::path::Path;
 use std::path::PathBuf;
@@ -50,8 +56,10 @@ use self::config::TenantConf;
 use self::delete::DeleteTenantFlow;
 use self::metadata::LoadMetadataError;
 use self::metadata::TimelineMetadata;
+use self::mgr::SetNewTenantConfigError;
 use self::mgr::TenantsMap;
 use self::remote_timeline_client::RemoteTimelineClient;
+use self::throttle::TenantThrottle;
 use self::timeline::uninit::TimelineUninitMark;
 use self::timeline::uninit::UninitializedTimeline;
 use self::timeline::EvictionTaskTenantState;
@@ -125,6 +133,7 @@ pub mod config;
 pub mod delete;
 pub mod mgr;
 pub mod tasks;
+pub(crate) mod throttle;
 pub mod upload_queue;
 
 pub(crate) mod timeline;
@@ -152,6 +161,9 @@ pub const TENANT_ATTACHING_MARKER_FILENAME: &str = "attaching";
 
 pub const TENANT_DELETED_MARKER_FILE_NAME: &str = "deleted";
 
+/// How many of the last state transitions of a tenant are kept in its state history.
+const TENANT_STATE_HISTORY_LEN: usize = 32;
+
 ///
 /// Tenant consists of multiple timelines. Keep them in a hash table.
 ///
@@ -165,12 +177,20 @@ pub struct Tenant {
 
     state: watch::Sender<TenantState>,
 
+    /// The last states of the tenant, oldest first. Every change of `state`
+    /// is followed by a call to [`Tenant::record_state_transition`].
+    state_history: Mutex<VecDeque<TenantStateTransition>>,
+
     // Overridden tenant-specific config parameters.
     // We keep TenantConfOpt sturct here to preserve the information
     // about parameters that are not set.
     // This is necessary to allow global config updates.
     tenant_conf: Arc<RwLock<TenantConfOpt>>,
 
+    /// Admission control of the getpage requests and WAL ingestion of the
+    /// tenant, shared with its timelines.
+    throttle: Arc<TenantThrottle>,
+
     tenant_id: TenantId,
     timelines: Mutex<HashMap<TimelineId, Arc<Timeline>>>,
     // This mutex prevents creation of new timelines during GC.
@@ -363,6 +383,28 @@ pub enum CreateTimelineError {
     #[error(transparent)]
     AncestorLsn(anyhow::Error),
     #[error(transparent)]
+    InvalidRegion(anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TruncateTimelineError {
+    #[error(transparent)]
+    Timeline(#[from] GetTimelineError),
+    #[error(transparent)]
+    InvalidLsn(anyhow::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PurgeDroppedError {
+    #[error(transparent)]
+    Timeline(#[from] GetTimelineError),
+    #[error(transparent)]
+    NotDropped(anyhow::Error),
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
@@ -542,6 +584,7 @@ impl Tenant {
                             assert_eq!(*state, TenantState::Attaching, "the attach task owns the tenant state until activation is complete");
                             *state = TenantState::broken_from_reason(e.to_string());
                         });
+                        tenant_clone.record_state_transition();
                     }
                 }
                 Ok(())
@@ -820,6 +863,7 @@ impl Tenant {
                         );
                         *state = TenantState::broken_from_reason(err.to_string());
                     });
+                    t.record_state_transition();
                 };
 
                 let mut init_order = init_order;
@@ -1363,9 +1407,16 @@ impl Tenant {
             self.is_active(),
             "Cannot create empty timelines on inactive tenant"
         );
+        let max_regions = self.get_max_regions();
+        anyhow::ensure!(
+            u32::from(region_id.0) < max_regions.get(),
+            "region {} is out of range for a tenant with max_regions {max_regions}",
+            region_id.0
+        );
 
         let timelines = self.timelines.lock().unwrap();
-        let timeline_uninit_mark = self.create_timeline_uninit_mark(new_timeline_id, &timelines)?;
+        let timeline_uninit_mark =
+            self.create_timeline_uninit_mark(new_timeline_id, region_id, &timelines)?;
         drop(timelines);
 
         let new_metadata = TimelineMetadata::new(
@@ -1379,6 +1430,7 @@ impl Tenant {
             initdb_lsn,
             pg_version,
             region_id,
+            Lsn(0),
         );
         self.prepare_new_timeline(
             new_timeline_id,
@@ -1472,6 +1524,14 @@ impl Tenant {
             return Err(CreateTimelineError::AlreadyExists);
         }
 
+        let max_regions = self.get_max_regions();
+        if u32::from(region_id.0) >= max_regions.get() {
+            return Err(CreateTimelineError::InvalidRegion(anyhow::anyhow!(
+                "region {} is out of range for a tenant with max_regions {max_regions}",
+                region_id.0
+            )));
+        }
+
         let loaded_timeline = match ancestor_timeline_id {
             Some(ancestor_timeline_id) => {
                 let ancestor_timeline = self
@@ -1562,6 +1622,172 @@ impl Tenant {
             .await
     }
 
+    /// Explains what the next GC of a timeline would do with each of its
+    /// layers, with the `gc_horizon` and `pitr_interval` of the tenant.
+    /// Refreshes the GC info of the timeline, like a GC iteration, but
+    /// removes nothing.
+    pub async fn explain_gc(
+        &self,
+        timeline_id: TimelineId,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<TimelineGcExplanation> {
+        anyhow::ensure!(self.is_active(), "Cannot explain GC of an inactive tenant");
+        let timeline = self.get_timeline(timeline_id, false)?;
+
+        self.refresh_gc_info_internal(
+            Some(timeline_id),
+            self.get_gc_horizon(),
+            self.get_pitr_interval(),
+            ctx,
+        )
+        .await?;
+
+        let mut branch_points: Vec<GcBranchPoint> = self
+            .timelines
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|child| child.get_ancestor_timeline_id() == Some(timeline_id))
+            .map(|child| GcBranchPoint {
+                timeline_id: child.timeline_id,
+                lsn: child.get_ancestor_lsn(),
+            })
+            .collect();
+        branch_points.sort_by_key(|branch_point| branch_point.lsn);
+
+        let (horizon_cutoff, pitr_cutoff) = {
+            let gc_info = timeline.gc_info.read().unwrap();
+            (gc_info.horizon_cutoff, gc_info.pitr_cutoff)
+        };
+        Ok(TimelineGcExplanation {
+            horizon_cutoff,
+            pitr_cutoff,
+            latest_gc_cutoff: *timeline.get_latest_gc_cutoff_lsn(),
+            branch_points,
+            layers: timeline.explain_gc().await?,
+        })
+    }
+
+    /// Drops the history of a timeline before `lsn` for good, whatever the
+    /// `gc_horizon` and `pitr_interval` of the tenant.
+    ///
+    /// The history the child timelines were branched from is kept, so `lsn`
+    /// cannot be past any of their branch points. Nor can it be before the
+    /// timeline's own branch point: that history belongs to the ancestor.
+    pub async fn truncate_timeline_before(
+        &self,
+        timeline_id: TimelineId,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<GcResult, TruncateTimelineError> {
+        if !self.is_active() {
+            return Err(TruncateTimelineError::Other(anyhow::anyhow!(
+                "Cannot truncate a timeline of an inactive tenant"
+            )));
+        }
+
+        // Keep new branches from being created meanwhile.
+        let _gc_cs = self.gc_cs.lock().await;
+        let timeline = self.get_timeline(timeline_id, true)?;
+
+        let last_record_lsn = timeline.get_last_record_lsn();
+        if lsn > last_record_lsn {
+            return Err(TruncateTimelineError::InvalidLsn(anyhow::anyhow!(
+                "{lsn} is ahead of the last record LSN {last_record_lsn}"
+            )));
+        }
+        let ancestor_lsn = timeline.get_ancestor_lsn();
+        if timeline.get_ancestor_timeline_id().is_some() && lsn < ancestor_lsn {
+            return Err(TruncateTimelineError::InvalidLsn(anyhow::anyhow!(
+                "{lsn} is before the branch point {ancestor_lsn} of the timeline"
+            )));
+        }
+        let branchpoints: Vec<Lsn> = self
+            .timelines
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|child| child.get_ancestor_timeline_id() == Some(timeline_id))
+            .map(|child| child.get_ancestor_lsn())
+            .collect();
+        if let Some(branchpoint) = branchpoints.iter().min().filter(|bp| **bp < lsn) {
+            return Err(TruncateTimelineError::InvalidLsn(anyhow::anyhow!(
+                "{lsn} is past the branch point {branchpoint} of a child timeline"
+            )));
+        }
+
+        Ok(timeline
+            .truncate_before(lsn, branchpoints, ctx)
+            .instrument(info_span!("truncate_timeline", %timeline_id, %lsn))
+            .await?)
+    }
+
+    /// Schedules the purge of the history of the dropped relation `relnode` of
+    /// a database, or of the whole database if `relnode` is `None`, from a
+    /// timeline: the next compactions reclaim its page versions from before
+    /// the last record LSN, whatever the `gc_horizon` and `pitr_interval` of
+    /// the tenant. Returns that LSN, which becomes the purge floor of the
+    /// timeline, see `Timeline::check_lsn_above_purge_floor`.
+    ///
+    /// The relation must not exist at the last record LSN, nor at the branch
+    /// points of the child timelines, whose history is kept.
+    pub async fn purge_dropped(
+        &self,
+        timeline_id: TimelineId,
+        spcnode: Oid,
+        dbnode: Oid,
+        relnode: Option<Oid>,
+        ctx: &RequestContext,
+    ) -> Result<Lsn, PurgeDroppedError> {
+        if !self.is_active() {
+            return Err(PurgeDroppedError::Other(anyhow::anyhow!(
+                "Cannot purge a timeline of an inactive tenant"
+            )));
+        }
+
+        // Keep new branches from being created meanwhile.
+        let _gc_cs = self.gc_cs.lock().await;
+        let timeline = self.get_timeline(timeline_id, true)?;
+
+        let what = match relnode {
+            Some(relnode) => format!("relation {spcnode}/{dbnode}/{relnode}"),
+            None => format!("database {spcnode}/{dbnode}"),
+        };
+        let lsn = timeline.get_last_record_lsn();
+        let Some(key_range) = timeline
+            .dropped_key_range(spcnode, dbnode, relnode, lsn, ctx)
+            .await
+            .map_err(anyhow::Error::from)?
+        else {
+            return Err(PurgeDroppedError::NotDropped(anyhow::anyhow!(
+                "{what} exists at the last record LSN {lsn}"
+            )));
+        };
+        let branchpoints: Vec<Lsn> = self
+            .timelines
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|child| child.get_ancestor_timeline_id() == Some(timeline_id))
+            .map(|child| child.get_ancestor_lsn())
+            .collect();
+        for branchpoint in branchpoints {
+            if timeline
+                .dropped_key_range(spcnode, dbnode, relnode, branchpoint, ctx)
+                .await
+                .map_err(anyhow::Error::from)?
+                .is_none()
+            {
+                return Err(PurgeDroppedError::NotDropped(anyhow::anyhow!(
+                    "{what} exists at the branch point {branchpoint} of a child timeline"
+                )));
+            }
+        }
+
+        timeline.schedule_purge(key_range, lsn)?;
+        Ok(lsn)
+    }
+
     /// Perform one compaction iteration.
     /// This function is periodically called by compactor task.
     /// Also it can be explicitly requested per timeline through page server
@@ -1704,6 +1930,7 @@ impl Tenant {
             // Continue outside the closure. We need to grab timelines.lock()
             // and we plan to turn it into a tokio::sync::Mutex in a future patch.
         });
+        self.record_state_transition();
 
         if activating {
             let timelines_accessor = self.timelines.lock().unwrap();
@@ -1745,6 +1972,7 @@ impl Tenant {
 
                 TENANT_ACTIVATION.observe(elapsed.as_secs_f64());
             });
+            self.record_state_transition();
         }
     }
 
@@ -1884,7 +2112,7 @@ impl Tenant {
             }
         });
         match (stopping, err) {
-            (true, None) => {} // continue
+            (true, None) => self.record_state_transition(), // continue
             (false, Some(err)) => return Err(err),
             (true, Some(_)) => unreachable!(
                 "send_if_modified closure must error out if not transitioning to Stopping"
@@ -1959,6 +2187,28 @@ impl Tenant {
                 }
            }
         });
+        self.record_state_transition();
+    }
+
+    /// Appends the current state to the state history, unless it is the state
+    /// recorded last, e.g. when a state change was refused.
+    fn record_state_transition(&self) {
+        let state = self.current_state();
+        let mut history = self.state_history.lock().unwrap();
+        if let Some(last) = history.back() {
+            if <&'static str>::from(&last.state) == <&'static str>::from(&state) {
+                return;
+            }
+        }
+        if history.len() == TENANT_STATE_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(TenantStateTransition::new(state));
+    }
+
+    /// Returns the last state transitions of the tenant, oldest first.
+    pub fn state_history(&self) -> Vec<TenantStateTransition> {
+        self.state_history.lock().unwrap().iter().cloned().collect()
     }
 
     pub fn subscribe_for_state_updates(&self) -> watch::Receiver<TenantState> {
@@ -2131,8 +2381,108 @@ impl Tenant {
             .or(self.conf.default_tenant_conf.min_resident_size_override)
     }
 
-    pub fn set_new_tenant_config(&self, new_tenant_conf: TenantConfOpt) {
+    pub fn get_physical_size_quota(&self) -> Option<u64> {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf
+            .physical_size_quota
+            .or(self.conf.default_tenant_conf.physical_size_quota)
+    }
+
+    pub fn get_max_concurrent_getpage_requests(&self) -> Option<NonZeroUsize> {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf.max_concurrent_getpage_requests.or(self
+            .conf
+            .default_tenant_conf
+            .max_concurrent_getpage_requests)
+    }
+
+    pub fn get_max_regions(&self) -> NonZeroU32 {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf
+            .max_regions
+            .unwrap_or(self.conf.default_tenant_conf.max_regions)
+    }
+
+    /// Checks that the tenant can switch to `new_tenant_conf`. The pages of the
+    /// multi-region SLRUs are interleaved by `max_regions`, so it can't change
+    /// once the tenant has timelines, or is creating one.
+    pub fn validate_new_tenant_config(
+        &self,
+        new_tenant_conf: &TenantConfOpt,
+    ) -> anyhow::Result<()> {
+        let timelines = self.timelines.lock().unwrap();
+        self.validate_new_tenant_config_locked(new_tenant_conf, &timelines)
+    }
+
+    fn validate_new_tenant_config_locked(
+        &self,
+        new_tenant_conf: &TenantConfOpt,
+        timelines: &MutexGuard<HashMap<TimelineId, Arc<Timeline>>>,
+    ) -> anyhow::Result<()> {
+        let max_regions = self.get_max_regions();
+        let new_max_regions = new_tenant_conf
+            .max_regions
+            .unwrap_or(self.conf.default_tenant_conf.max_regions);
+        if new_max_regions == max_regions {
+            return Ok(());
+        }
+        if !timelines.is_empty() {
+            anyhow::bail!(
+                "cannot change max_regions from {max_regions} to {new_max_regions}: \
+                 the tenant has {} timelines",
+                timelines.len()
+            );
+        }
+        // The timelines being created are not in `timelines` yet, but their
+        // uninit marks are created under the same lock.
+        let timelines_path = self.conf.timelines_path(&self.tenant_id);
+        for entry in fs::read_dir(&timelines_path)
+            .with_context(|| format!("list timelines directory {}", timelines_path.display()))?
+        {
+            if is_uninit_mark(&entry?.path()) {
+                anyhow::bail!(
+                    "cannot change max_regions from {max_regions} to {new_max_regions}: \
+                     the tenant is creating a timeline"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for a getpage request to be admitted under the tenant's
+    /// `max_concurrent_getpage_requests`. The request is served while the
+    /// returned permit is held.
+    pub(crate) async fn admit_getpage_request(&self) -> Option<OwnedSemaphorePermit> {
+        self.throttle
+            .admit_getpage_request(self.get_max_concurrent_getpage_requests())
+            .await
+    }
+
+    /// Sum of the size of the layer files of all timelines that are resident
+    /// on local disk, which is what the `physical_size_quota` limits.
+    pub fn resident_physical_size(&self) -> u64 {
+        self.list_timelines()
+            .iter()
+            .map(|timeline| timeline.resident_physical_size())
+            .sum()
+    }
+
+    /// Switches the tenant to `new_tenant_conf`, once it is validated with
+    /// [`Self::validate_new_tenant_config`] and saved with `persist`. The
+    /// timelines lock is held from the validation until the switch, so that
+    /// no timeline is created meanwhile that makes the new config invalid.
+    pub fn set_new_tenant_config(
+        &self,
+        new_tenant_conf: TenantConfOpt,
+        persist: impl FnOnce(TenantConfOpt) -> anyhow::Result<()>,
+    ) -> Result<(), SetNewTenantConfigError> {
+        let timelines = self.timelines.lock().unwrap();
+        self.validate_new_tenant_config_locked(&new_tenant_conf, &timelines)
+            .map_err(SetNewTenantConfigError::Invalid)?;
+        persist(new_tenant_conf).map_err(SetNewTenantConfigError::Persist)?;
         *self.tenant_conf.write().unwrap() = new_tenant_conf;
+        drop(timelines);
+
         // Don't hold self.timelines.lock() during the notifies.
         // There's no risk of deadlock right now, but there could be if we consolidate
         // mutexes in struct Timeline in the future.
@@ -2140,6 +2490,7 @@ impl Tenant {
         for timeline in timelines {
             timeline.tenant_conf_updated();
         }
+        Ok(())
     }
 
     /// Helper function to create a new Timeline struct.
@@ -2180,6 +2531,7 @@ impl Tenant {
         let timeline = Timeline::new(
             self.conf,
             Arc::clone(&self.tenant_conf),
+            Arc::clone(&self.throttle),
             new_metadata,
             ancestor,
             new_timeline_id,
@@ -2203,6 +2555,7 @@ impl Tenant {
         tenant_id: TenantId,
         remote_storage: Option<GenericRemoteStorage>,
     ) -> Tenant {
+        let state_history = Mutex::new(VecDeque::from([TenantStateTransition::new(state.clone())]));
         let (state, mut rx) = watch::channel(state);
 
         tokio::spawn(async move {
@@ -2264,11 +2617,13 @@ impl Tenant {
             // activation times.
             loading_started_at: Instant::now(),
             tenant_conf: Arc::new(RwLock::new(tenant_conf)),
+            throttle: Arc::new(TenantThrottle::new(&tenant_id)),
             timelines: Mutex::new(HashMap::new()),
             gc_cs: tokio::sync::Mutex::new(()),
             walredo_mgr,
             remote_storage,
             state,
+            state_history,
             cached_logical_sizes: tokio::sync::Mutex::new(HashMap::new()),
             cached_synthetic_tenant_size: Arc::new(AtomicU64::new(0)),
             eviction_task_tenant_state: tokio::sync::Mutex::new(EvictionTaskTenantState::default()),
@@ -2631,7 +2986,7 @@ impl Tenant {
         // out if the new timeline ID is already in use.
         let timeline_uninit_mark = {
             let timelines = self.timelines.lock().unwrap();
-            self.create_timeline_uninit_mark(dst_id, &timelines)?
+            self.create_timeline_uninit_mark(dst_id, region_id, &timelines)?
         };
 
         // Ensure that `start_lsn` is valid, i.e. the LSN is within the PITR
@@ -2654,6 +3009,10 @@ impl Tenant {
                 *latest_gc_cutoff_lsn,
             ))
             .map_err(CreateTimelineError::AncestorLsn)?;
+        src_timeline
+            .check_lsn_above_purge_floor(start_lsn)
+            .context("invalid branch start lsn")
+            .map_err(CreateTimelineError::AncestorLsn)?;
 
         // and then the planned GC cutoff
         {
@@ -2696,6 +3055,7 @@ impl Tenant {
             src_timeline.initdb_lsn,
             src_timeline.pg_version,
             region_id,
+            Lsn(0),
         );
 
         let uninitialized_timeline = self.prepare_new_timeline(
@@ -2706,6 +3066,12 @@ impl Tenant {
             Some(Arc::clone(src_timeline)),
         )?;
 
+        fail::fail_point!("branch-timeline-before-finish", |_| {
+            Err(CreateTimelineError::Other(anyhow::anyhow!(
+                "failpoint branch-timeline-before-finish"
+            )))
+        });
+
         let new_timeline = uninitialized_timeline.finish_creation()?;
 
         // Root timeline gets its layers during creation and uploads them along with the metadata.
@@ -2737,7 +3103,7 @@ impl Tenant {
     ) -> anyhow::Result<Arc<Timeline>> {
         let timeline_uninit_mark = {
             let timelines = self.timelines.lock().unwrap();
-            self.create_timeline_uninit_mark(timeline_id, &timelines)?
+            self.create_timeline_uninit_mark(timeline_id, region_id, &timelines)?
         };
         // create a `tenant/{tenant_id}/timelines/basebackup-{timeline_id}.{TEMP_FILE_SUFFIX}/`
         // temporary directory for basebackup files for the given timeline.
@@ -2783,6 +3149,7 @@ impl Tenant {
             pgdata_lsn,
             pg_version,
             region_id,
+            Lsn(0),
         );
         let raw_timeline = self.prepare_new_timeline(
             timeline_id,
@@ -2926,6 +3293,7 @@ impl Tenant {
     fn create_timeline_uninit_mark(
         &self,
         timeline_id: TimelineId,
+        region_id: RegionId,
         timelines: &MutexGuard<HashMap<TimelineId, Arc<Timeline>>>,
     ) -> anyhow::Result<TimelineUninitMark> {
         let tenant_id = self.tenant_id;
@@ -2934,6 +3302,14 @@ impl Tenant {
             timelines.get(&timeline_id).is_none(),
             "Timeline {tenant_id}/{timeline_id} already exists in pageserver's memory"
         );
+        // Checked by the callers already, but `max_regions` can only change
+        // under the timelines lock, see `set_new_tenant_config`.
+        let max_regions = self.get_max_regions();
+        anyhow::ensure!(
+            u32::from(region_id.0) < max_regions.get(),
+            "region {} is out of range for a tenant with max_regions {max_regions}",
+            region_id.0
+        );
         let timeline_path = self.conf.timeline_path(&tenant_id, &timeline_id);
         anyhow::ensure!(
             !timeline_path.exists(),
@@ -3319,6 +3695,70 @@ pub mod harness {
     pub const NEW_TIMELINE_ID: TimelineId =
         TimelineId::from_array(hex!("AA223344556677881122334455667788"));
 
+    #[tokio::test]
+    async fn max_regions_is_fixed_once_there_are_timelines() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("max_regions_is_fixed_once_there_are_timelines")?
+            .load()
+            .await;
+        let max_regions = tenant.get_max_regions();
+        let other_max_regions = NonZeroU32::new(max_regions.get() + 1).unwrap();
+        let changes = TenantConfOpt {
+            max_regions: Some(other_max_regions),
+            ..TenantConfOpt::default()
+        };
+
+        // Can be changed while the tenant is empty.
+        tenant.validate_new_tenant_config(&changes)?;
+
+        let region_out_of_range = RegionId(max_regions.get() as u8);
+        assert!(tenant
+            .create_empty_timeline(
+                TIMELINE_ID,
+                Lsn(0x10),
+                DEFAULT_PG_VERSION,
+                region_out_of_range,
+                &ctx
+            )
+            .is_err());
+
+        // Nor while a timeline is being created.
+        let uninit = tenant.create_empty_timeline(
+            TIMELINE_ID,
+            Lsn(0x10),
+            DEFAULT_PG_VERSION,
+            RegionId(0),
+            &ctx,
+        )?;
+        assert!(tenant.validate_new_tenant_config(&changes).is_err());
+        drop(uninit);
+
+        tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x10),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        assert!(tenant.validate_new_tenant_config(&changes).is_err());
+        assert!(matches!(
+            tenant.set_new_tenant_config(changes, |_| panic!("invalid config persisted")),
+            Err(SetNewTenantConfigError::Invalid(_))
+        ));
+        assert_eq!(tenant.get_max_regions(), max_regions);
+        // Other settings can still change.
+        let changes = TenantConfOpt {
+            max_regions: Some(max_regions),
+            gc_horizon: Some(0),
+            ..TenantConfOpt::default()
+        };
+        tenant.validate_new_tenant_config(&changes)?;
+
+        Ok(())
+    }
+
     /// Convenience function to create a page image with given string as the only content
     #[allow(non_snake_case)]
     pub fn TEST_IMG(s: &str) -> Bytes {
@@ -3351,6 +3791,11 @@ pub mod harness {
                     tenant_conf.evictions_low_residence_duration_metric_threshold,
                 ),
                 gc_feedback: Some(tenant_conf.gc_feedback),
+                physical_size_quota: tenant_conf.physical_size_quota,
+                max_concurrent_getpage_requests: tenant_conf.max_concurrent_getpage_requests,
+                wal_ingest_rate_limit: tenant_conf.wal_ingest_rate_limit,
+                hot_range_image_creation_threshold: tenant_conf.hot_range_image_creation_threshold,
+                max_regions: Some(tenant_conf.max_regions),
             }
         }
     }
@@ -3435,6 +3880,7 @@ pub mod harness {
 
             // TODO reuse Tenant::activate (needs broker)
             tenant.state.send_replace(TenantState::Active);
+            tenant.record_state_transition();
             for timeline in tenant.timelines.lock().unwrap().values() {
                 timeline.set_state(TimelineState::Active);
             }
@@ -3457,6 +3903,7 @@ pub mod harness {
             base_img: Option<(Lsn, Bytes)>,
             records: Vec<(Lsn, NeonWalRecord)>,
             _pg_version: u32,
+            _max_regions: u32,
         ) -> Result<Bytes, WalRedoError> {
             let s = format!(
                 "redo for {} to get to {}, with {} and {} records",
@@ -3480,6 +3927,7 @@ pub mod harness {
 mod tests {
     use super::*;
     use crate::keyspace::KeySpaceAccum;
+    use crate::page_cache::PAGE_SZ;
     use crate::repository::{Key, Value};
     use crate::tenant::harness::*;
     use crate::DEFAULT_PG_VERSION;
@@ -3487,6 +3935,7 @@ mod tests {
     use bytes::BytesMut;
     use hex_literal::hex;
     use once_cell::sync::Lazy;
+    use pageserver_api::models::LayerGcVerdict;
     use rand::{thread_rng, Rng};
     use tokio_util::sync::CancellationToken;
 
@@ -3542,6 +3991,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_state_history() -> anyhow::Result<()> {
+        let (tenant, _ctx) = TenantHarness::create("test_state_history")?.load().await;
+
+        let (_completion, progress) = completion::channel();
+        tenant.set_stopping(progress.clone(), false).await.unwrap();
+        // Refused transitions are not recorded.
+        assert!(tenant.set_stopping(progress, false).await.is_err());
+
+        let states = tenant
+            .state_history()
+            .into_iter()
+            .map(|transition| <&'static str>::from(&transition.state))
+            .collect::<Vec<_>>();
+        assert_eq!(states, ["Loading", "Active", "Stopping"]);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn no_duplicate_timelines() -> anyhow::Result<()> {
         let (tenant, ctx) = TenantHarness::create("no_duplicate_timelines")?
@@ -3968,6 +4435,74 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_gc_keeps_branch_points_of_branch_chain() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_gc_keeps_branch_points_of_branch_chain")?
+            .load()
+            .await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x10),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+        make_some_layers(tline.as_ref(), Lsn(0x20)).await?;
+
+        // A branch of the timeline, and a branch of the branch.
+        let child = tenant
+            .branch_timeline_test(&tline, NEW_TIMELINE_ID, Some(Lsn(0x40)), RegionId(0), &ctx)
+            .await?;
+        make_some_layers(child.as_ref(), Lsn(0x60)).await?;
+        let grandchild_id = TimelineId::generate();
+        let grandchild = tenant
+            .branch_timeline_test(&child, grandchild_id, Some(Lsn(0x70)), RegionId(0), &ctx)
+            .await?;
+        make_some_layers(grandchild.as_ref(), Lsn(0xa0)).await?;
+        make_some_layers(tline.as_ref(), Lsn(0x60)).await?;
+
+        tenant
+            .gc_iteration(None, 0x10, Duration::ZERO, &ctx)
+            .await?;
+
+        // The branches still read their ancestors at their branch points.
+        assert_eq!(
+            child.get(*TEST_KEY, Lsn(0x50), &ctx).await?,
+            TEST_IMG(&format!("foo at {}", Lsn(0x40)))
+        );
+        assert_eq!(
+            grandchild.get(*TEST_KEY, Lsn(0x80), &ctx).await?,
+            TEST_IMG(&format!("foo at {}", Lsn(0x70)))
+        );
+
+        // The layers up to the branch points are kept for the branches.
+        for (timeline, branch_lsn) in [(&tline, Lsn(0x40)), (&child, Lsn(0x70))] {
+            let explanations = timeline.explain_gc().await?;
+            assert!(!explanations.is_empty());
+            for layer in explanations {
+                if layer.lsn_start <= branch_lsn {
+                    assert_eq!(
+                        layer.verdict,
+                        LayerGcVerdict::NeededByBranch { branch_lsn },
+                        "{}",
+                        layer.layer_file_name
+                    );
+                } else {
+                    assert_ne!(layer.verdict, LayerGcVerdict::Removable);
+                }
+            }
+        }
+
+        let explanation = tenant.explain_gc(TIMELINE_ID, &ctx).await?;
+        assert_eq!(explanation.branch_points.len(), 1);
+        assert_eq!(explanation.branch_points[0].timeline_id, NEW_TIMELINE_ID);
+        assert_eq!(explanation.branch_points[0].lsn, Lsn(0x40));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn timeline_load() -> anyhow::Result<()> {
         const TEST_NAME: &str = "timeline_load";
@@ -4098,6 +4633,60 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn corrupt_layer_file() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("corrupt_layer_file")?.load().await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        let writer = tline.writer().await;
+        writer
+            .put(*TEST_KEY, Lsn(0x10), &Value::Image(TEST_IMG("foo at 0x10")))
+            .await?;
+        writer.finish_write(RecordLsn {
+            last: Lsn(0x10),
+            prev: Lsn::INVALID,
+        });
+        drop(writer);
+        tline.freeze_and_flush().await?;
+
+        // Flip a bit in the values of the delta layer written by the flush.
+        // Loading the layer doesn't read the whole file, scrub does.
+        let layer_paths: Vec<PathBuf> = {
+            let guard = tline.layers.read().await;
+            guard
+                .layer_map()
+                .iter_historic_layers()
+                .filter(|desc| desc.is_delta())
+                .filter_map(|desc| guard.get_from_desc(&desc).local_path())
+                .collect()
+        };
+        for path in layer_paths {
+            let mut bytes = std::fs::read(&path)?;
+            bytes[PAGE_SZ + 1] ^= 1;
+            std::fs::write(&path, bytes)?;
+        }
+
+        let report = tline.scrub(&ctx).await?;
+        assert!(
+            report
+                .errors
+                .iter()
+                .any(|e| e.contains("layer file checksum mismatch")),
+            "errors {:?} expected to contain the checksum mismatch",
+            report.errors
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_images() -> anyhow::Result<()> {
         let (tenant, ctx) = TenantHarness::create("test_images")?.load().await;
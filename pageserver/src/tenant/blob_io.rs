@@ -9,12 +9,72 @@
 //! by peeking at the first byte.
 //!
 //! len <  128: 0XXXXXXX
-//! len >= 128: 1XXXXXXX XXXXXXXX XXXXXXXX XXXXXXXX
+//! len >= 128: 1CXXXXXX XXXXXXXX XXXXXXXX XXXXXXXX
 //!
+//! The C bit of a four-byte length is set if the blob is compressed with
+//! zstd, see [`BlobCompression`]. Each blob is compressed on its own, so
+//! reading a value still reads only the blocks of that value. Compressed
+//! blobs always have a four-byte length, and the length is the compressed
+//! one.
+//!
+use crate::metrics::{LAYER_COMPRESSION_INPUT_BYTES, LAYER_COMPRESSION_OUTPUT_BYTES};
 use crate::page_cache::PAGE_SZ;
 use crate::tenant::block_io::{BlockCursor, BlockReader};
+use anyhow::{bail, ensure, Context};
 use std::cmp::min;
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Read};
+use std::str::FromStr;
+
+/// Blobs shorter than this are not worth compressing.
+const MIN_COMPRESSED_BLOB_LEN: usize = 128;
+
+/// The longest blob, with the two high bits of the four-byte length taken.
+/// Compressed blobs are not longer than this once decompressed either.
+pub(super) const MAX_BLOB_LEN: usize = 0x3fff_ffff;
+
+const LEN_LONG_FLAG: u8 = 0x80;
+const LEN_COMPRESSED_FLAG: u8 = 0x40;
+
+/// Compression of the blobs written to layer files, from the
+/// `layer_compression` option: `none`, `zstd`, or `zstd:<level>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlobCompression {
+    #[default]
+    None,
+    Zstd {
+        level: i32,
+    },
+}
+
+impl BlobCompression {
+    /// The zstd level of plain `zstd`, the fastest one, as the layer files
+    /// are written on the ingest path.
+    pub const DEFAULT_ZSTD_LEVEL: i32 = 1;
+}
+
+impl FromStr for BlobCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.split_once(':') {
+            None if s == "none" => Ok(Self::None),
+            None if s == "zstd" => Ok(Self::Zstd {
+                level: Self::DEFAULT_ZSTD_LEVEL,
+            }),
+            Some(("zstd", level)) => {
+                let level: i32 = level
+                    .parse()
+                    .with_context(|| format!("invalid zstd level '{level}'"))?;
+                ensure!(
+                    (1..=22).contains(&level),
+                    "zstd level must be between 1 and 22"
+                );
+                Ok(Self::Zstd { level })
+            }
+            _ => bail!("unknown compression '{s}', expected 'none', 'zstd' or 'zstd:<level>'"),
+        }
+    }
+}
 
 impl<R> BlockCursor<R>
 where
@@ -40,7 +100,9 @@ where
 
         // peek at the first byte, to determine if it's a 1- or 4-byte length
         let first_len_byte = buf[off];
-        let len: usize = if first_len_byte < 0x80 {
+        let compressed = first_len_byte & (LEN_LONG_FLAG | LEN_COMPRESSED_FLAG)
+            == LEN_LONG_FLAG | LEN_COMPRESSED_FLAG;
+        let len: usize = if first_len_byte < LEN_LONG_FLAG {
             // 1-byte length header
             off += 1;
             first_len_byte as usize
@@ -59,7 +121,7 @@ where
                 len_buf.copy_from_slice(&buf[off..off + 4]);
                 off += 4;
             }
-            len_buf[0] &= 0x7f;
+            len_buf[0] &= !(LEN_LONG_FLAG | LEN_COMPRESSED_FLAG);
             u32::from_be_bytes(len_buf) as usize
         };
 
@@ -82,6 +144,21 @@ where
             remain -= this_blk_len;
             off += this_blk_len;
         }
+
+        if compressed {
+            let compressed_buf = std::mem::take(dstbuf);
+            // Stop at the longest blob that can be written, so that a corrupted
+            // blob can't decompress into an unbounded buffer.
+            zstd::stream::read::Decoder::new(compressed_buf.as_slice())?
+                .take(MAX_BLOB_LEN as u64 + 1)
+                .read_to_end(dstbuf)?;
+            if dstbuf.len() > MAX_BLOB_LEN {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("compressed blob at {offset} is longer than {MAX_BLOB_LEN} bytes"),
+                ));
+            }
+        }
         Ok(())
     }
 }
@@ -105,16 +182,18 @@ where
 {
     inner: W,
     offset: u64,
+    compression: BlobCompression,
 }
 
 impl<W> WriteBlobWriter<W>
 where
     W: std::io::Write,
 {
-    pub fn new(inner: W, start_offset: u64) -> Self {
+    pub fn new(inner: W, start_offset: u64, compression: BlobCompression) -> Self {
         WriteBlobWriter {
             inner,
             offset: start_offset,
+            compression,
         }
     }
 
@@ -139,26 +218,133 @@ where
     fn write_blob(&mut self, srcbuf: &[u8]) -> Result<u64, Error> {
         let offset = self.offset;
 
-        if srcbuf.len() < 128 {
+        // Checked before compression, so that a compressed blob doesn't
+        // decompress into more than a blob can hold.
+        if srcbuf.len() > MAX_BLOB_LEN {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("blob too large ({} bytes)", srcbuf.len()),
+            ));
+        }
+
+        let compressed = match self.compression {
+            BlobCompression::None => None,
+            BlobCompression::Zstd { level } => {
+                let compressed = if srcbuf.len() >= MIN_COMPRESSED_BLOB_LEN {
+                    let compressed = zstd::bulk::compress(srcbuf, level)?;
+                    // Keep the blobs that do not get smaller as they are.
+                    Some(compressed).filter(|compressed| compressed.len() < srcbuf.len())
+                } else {
+                    None
+                };
+                LAYER_COMPRESSION_INPUT_BYTES.inc_by(srcbuf.len() as u64);
+                LAYER_COMPRESSION_OUTPUT_BYTES.inc_by(
+                    compressed
+                        .as_ref()
+                        .map_or(srcbuf.len(), |compressed| compressed.len())
+                        as u64,
+                );
+                compressed
+            }
+        };
+        let data = compressed.as_deref().unwrap_or(srcbuf);
+
+        if data.len() < 128 && compressed.is_none() {
             // Short blob. Write a 1-byte length header
-            let len_buf = srcbuf.len() as u8;
+            let len_buf = data.len() as u8;
             self.inner.write_all(&[len_buf])?;
             self.offset += 1;
         } else {
             // Write a 4-byte length header
-            if srcbuf.len() > 0x7fff_ffff {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("blob too large ({} bytes)", srcbuf.len()),
-                ));
+            let mut len_buf = (data.len() as u32).to_be_bytes();
+            len_buf[0] |= LEN_LONG_FLAG;
+            if compressed.is_some() {
+                len_buf[0] |= LEN_COMPRESSED_FLAG;
             }
-            let mut len_buf = ((srcbuf.len()) as u32).to_be_bytes();
-            len_buf[0] |= 0x80;
             self.inner.write_all(&len_buf)?;
             self.offset += 4;
         }
-        self.inner.write_all(srcbuf)?;
-        self.offset += srcbuf.len() as u64;
+        self.inner.write_all(data)?;
+        self.offset += data.len() as u64;
         Ok(offset)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::block_io::BlockLease;
+    use std::rc::Rc;
+
+    /// Blocks of a file in memory.
+    struct TestFile(Vec<u8>);
+
+    impl BlockReader for TestFile {
+        fn read_blk(&self, blknum: u32) -> Result<BlockLease, Error> {
+            let mut buf = [0u8; PAGE_SZ];
+            let start = blknum as usize * PAGE_SZ;
+            let end = min(start + PAGE_SZ, self.0.len());
+            if start < end {
+                buf[..end - start].copy_from_slice(&self.0[start..end]);
+            }
+            Ok(BlockLease::from(Rc::new(buf)))
+        }
+    }
+
+    #[tokio::test]
+    async fn compressed_blobs() -> anyhow::Result<()> {
+        let mut x: u32 = 1;
+        let random: Vec<u8> = (0..1000)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect();
+        let blobs = vec![
+            Vec::new(),
+            b"short".to_vec(),
+            vec![1; MIN_COMPRESSED_BLOB_LEN],
+            random,
+            vec![7; 3 * PAGE_SZ],
+        ];
+
+        let mut sizes = Vec::new();
+        for compression in [BlobCompression::None, BlobCompression::Zstd { level: 1 }] {
+            let mut writer = WriteBlobWriter::new(Vec::new(), 0, compression);
+            let offsets = blobs
+                .iter()
+                .map(|blob| writer.write_blob(blob))
+                .collect::<Result<Vec<_>, _>>()?;
+            sizes.push(writer.size());
+
+            let file = TestFile(writer.into_inner());
+            let cursor = file.block_cursor();
+            for (blob, offset) in blobs.iter().zip(offsets) {
+                assert_eq!(&cursor.read_blob(offset).await?, blob);
+            }
+        }
+        assert!(sizes[1] < sizes[0], "sizes: {sizes:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_compression() {
+        assert_eq!(
+            "none".parse::<BlobCompression>().unwrap(),
+            BlobCompression::None
+        );
+        assert_eq!(
+            "zstd".parse::<BlobCompression>().unwrap(),
+            BlobCompression::Zstd {
+                level: BlobCompression::DEFAULT_ZSTD_LEVEL
+            }
+        );
+        assert_eq!(
+            "zstd:19".parse::<BlobCompression>().unwrap(),
+            BlobCompression::Zstd { level: 19 }
+        );
+        for invalid in ["lz4", "zstd:0", "zstd:23", "zstd:x", "none:1"] {
+            assert!(invalid.parse::<BlobCompression>().is_err(), "{invalid}");
+        }
+    }
+}
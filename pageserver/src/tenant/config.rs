@@ -11,7 +11,7 @@
 use anyhow::Context;
 use pageserver_api::models;
 use serde::{Deserialize, Serialize};
-use std::num::NonZeroU64;
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
 use std::time::Duration;
 
 pub mod defaults {
@@ -44,6 +44,8 @@ pub mod defaults {
     pub const DEFAULT_EVICTIONS_LOW_RESIDENCE_DURATION_METRIC_THRESHOLD: &str = "24 hour";
 
     pub const DEFAULT_INGEST_BATCH_SIZE: u64 = 100;
+
+    pub const DEFAULT_MAX_REGIONS: u32 = postgres_ffi::pg_constants::MAX_REGIONS;
 }
 
 /// Per-tenant configuration options
@@ -102,6 +104,24 @@ pub struct TenantConf {
     #[serde(with = "humantime_serde")]
     pub evictions_low_residence_duration_metric_threshold: Duration,
     pub gc_feedback: bool,
+    /// Once the layer files of the tenant's timelines resident on local disk
+    /// exceed this size, WAL ingestion of the timelines is paused until they
+    /// shrink below it again, e.g. through eviction or GC.
+    pub physical_size_quota: Option<u64>,
+    /// Maximum number of getpage requests of the tenant served at a time, over
+    /// all its connections. The requests over the limit wait for their turn.
+    pub max_concurrent_getpage_requests: Option<NonZeroUsize>,
+    /// Maximum rate of WAL ingestion of the tenant's timelines, in bytes per
+    /// second, allowing bursts of up to one second worth of WAL.
+    pub wal_ingest_rate_limit: Option<NonZeroU64>,
+    /// Number of reads through WAL redo of a key range, decaying by half at
+    /// each compaction, above which compaction creates the image layers of
+    /// the range as soon as it has any delta over its last image.
+    pub hot_range_image_creation_threshold: Option<NonZeroU64>,
+    /// Number of regions whose pages the CSN log and multixact SLRUs
+    /// interleave. It has to match the MAX_REGIONS the computes are built
+    /// with, and must not change once the tenant has data.
+    pub max_regions: NonZeroU32,
 }
 
 /// Same as TenantConf, but this struct preserves the information about
@@ -182,6 +202,26 @@ pub struct TenantConfOpt {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub gc_feedback: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub physical_size_quota: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub max_concurrent_getpage_requests: Option<NonZeroUsize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub wal_ingest_rate_limit: Option<NonZeroU64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hot_range_image_creation_threshold: Option<NonZeroU64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub max_regions: Option<NonZeroU32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -250,6 +290,59 @@ impl TenantConfOpt {
                 .evictions_low_residence_duration_metric_threshold
                 .unwrap_or(global_conf.evictions_low_residence_duration_metric_threshold),
             gc_feedback: self.gc_feedback.unwrap_or(global_conf.gc_feedback),
+            physical_size_quota: self.physical_size_quota.or(global_conf.physical_size_quota),
+            max_concurrent_getpage_requests: self
+                .max_concurrent_getpage_requests
+                .or(global_conf.max_concurrent_getpage_requests),
+            wal_ingest_rate_limit: self
+                .wal_ingest_rate_limit
+                .or(global_conf.wal_ingest_rate_limit),
+            hot_range_image_creation_threshold: self
+                .hot_range_image_creation_threshold
+                .or(global_conf.hot_range_image_creation_threshold),
+            max_regions: self.max_regions.unwrap_or(global_conf.max_regions),
+        }
+    }
+
+    /// Applies the overrides set in `changes` on top of these ones.
+    pub fn update(&self, changes: &TenantConfOpt) -> TenantConfOpt {
+        TenantConfOpt {
+            checkpoint_distance: changes.checkpoint_distance.or(self.checkpoint_distance),
+            checkpoint_timeout: changes.checkpoint_timeout.or(self.checkpoint_timeout),
+            compaction_target_size: changes
+                .compaction_target_size
+                .or(self.compaction_target_size),
+            compaction_period: changes.compaction_period.or(self.compaction_period),
+            compaction_threshold: changes.compaction_threshold.or(self.compaction_threshold),
+            gc_horizon: changes.gc_horizon.or(self.gc_horizon),
+            gc_period: changes.gc_period.or(self.gc_period),
+            image_creation_threshold: changes
+                .image_creation_threshold
+                .or(self.image_creation_threshold),
+            pitr_interval: changes.pitr_interval.or(self.pitr_interval),
+            walreceiver_connect_timeout: changes
+                .walreceiver_connect_timeout
+                .or(self.walreceiver_connect_timeout),
+            lagging_wal_timeout: changes.lagging_wal_timeout.or(self.lagging_wal_timeout),
+            max_lsn_wal_lag: changes.max_lsn_wal_lag.or(self.max_lsn_wal_lag),
+            trace_read_requests: changes.trace_read_requests.or(self.trace_read_requests),
+            eviction_policy: changes.eviction_policy.or(self.eviction_policy),
+            min_resident_size_override: changes
+                .min_resident_size_override
+                .or(self.min_resident_size_override),
+            evictions_low_residence_duration_metric_threshold: changes
+                .evictions_low_residence_duration_metric_threshold
+                .or(self.evictions_low_residence_duration_metric_threshold),
+            gc_feedback: changes.gc_feedback.or(self.gc_feedback),
+            physical_size_quota: changes.physical_size_quota.or(self.physical_size_quota),
+            max_concurrent_getpage_requests: changes
+                .max_concurrent_getpage_requests
+                .or(self.max_concurrent_getpage_requests),
+            wal_ingest_rate_limit: changes.wal_ingest_rate_limit.or(self.wal_ingest_rate_limit),
+            hot_range_image_creation_threshold: changes
+                .hot_range_image_creation_threshold
+                .or(self.hot_range_image_creation_threshold),
+            max_regions: changes.max_regions.or(self.max_regions),
         }
     }
 }
@@ -287,6 +380,12 @@ impl Default for TenantConf {
             )
             .expect("cannot parse default evictions_low_residence_duration_metric_threshold"),
             gc_feedback: false,
+            physical_size_quota: None,
+            max_concurrent_getpage_requests: None,
+            wal_ingest_rate_limit: None,
+            hot_range_image_creation_threshold: None,
+            max_regions: NonZeroU32::new(DEFAULT_MAX_REGIONS)
+                .expect("cannot parse default max regions"),
         }
     }
 }
@@ -382,6 +481,12 @@ impl TryFrom<&'_ models::TenantConfig> for TenantConfOpt {
             );
         }
         tenant_conf.gc_feedback = request_data.gc_feedback;
+        tenant_conf.physical_size_quota = request_data.physical_size_quota;
+        tenant_conf.max_concurrent_getpage_requests = request_data.max_concurrent_getpage_requests;
+        tenant_conf.wal_ingest_rate_limit = request_data.wal_ingest_rate_limit;
+        tenant_conf.hot_range_image_creation_threshold =
+            request_data.hot_range_image_creation_threshold;
+        tenant_conf.max_regions = request_data.max_regions;
 
         Ok(tenant_conf)
     }
@@ -406,4 +511,27 @@ mod tests {
         assert_eq!(json_form, "{\"gc_horizon\":42}");
         assert_eq!(small_conf, serde_json::from_str(&json_form).unwrap());
     }
+
+    #[test]
+    fn update_keeps_unchanged_overrides() {
+        let conf = TenantConfOpt {
+            gc_horizon: Some(42),
+            pitr_interval: Some(Duration::from_secs(3600)),
+            ..TenantConfOpt::default()
+        };
+        let changes = TenantConfOpt {
+            gc_horizon: Some(7),
+            checkpoint_distance: Some(1024),
+            ..TenantConfOpt::default()
+        };
+        assert_eq!(
+            conf.update(&changes),
+            TenantConfOpt {
+                gc_horizon: Some(7),
+                checkpoint_distance: Some(1024),
+                pitr_interval: Some(Duration::from_secs(3600)),
+                ..TenantConfOpt::default()
+            }
+        );
+    }
 }
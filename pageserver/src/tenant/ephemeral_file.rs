@@ -3,7 +3,7 @@
 
 use crate::config::PageServerConf;
 use crate::page_cache::{self, ReadBufResult, WriteBufResult, PAGE_SZ};
-use crate::tenant::blob_io::BlobWriter;
+use crate::tenant::blob_io::{BlobWriter, MAX_BLOB_LEN};
 use crate::tenant::block_io::{BlockLease, BlockReader};
 use crate::virtual_file::VirtualFile;
 use once_cell::sync::Lazy;
@@ -198,6 +198,13 @@ impl FileExt for EphemeralFile {
 
 impl BlobWriter for EphemeralFile {
     fn write_blob(&mut self, srcbuf: &[u8]) -> Result<u64, io::Error> {
+        // A longer length would set the compressed flag of the length field.
+        if srcbuf.len() > MAX_BLOB_LEN {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!("blob too large ({} bytes)", srcbuf.len()),
+            ));
+        }
         let pos = self.size;
 
         let mut blknum = (self.size / PAGE_SZ as u64) as u32;
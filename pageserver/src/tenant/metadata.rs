@@ -26,10 +26,11 @@ use crate::config::PageServerConf;
 use crate::virtual_file::VirtualFile;
 
 /// Use special format number to enable backward compatibility.
-const METADATA_FORMAT_VERSION: u16 = 4;
+const METADATA_FORMAT_VERSION: u16 = 5;
 
 /// Previous supported format versions.
 const METADATA_OLD_FORMAT_VERSION: u16 = 3;
+const METADATA_V2_FORMAT_VERSION: u16 = 4;
 
 /// We assume that a write of up to METADATA_MAX_SIZE bytes is atomic.
 ///
@@ -43,7 +44,7 @@ const METADATA_MAX_SIZE: usize = 512;
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TimelineMetadata {
     hdr: TimelineMetadataHeader,
-    body: TimelineMetadataBodyV2,
+    body: TimelineMetadataBodyV3,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -54,6 +55,31 @@ struct TimelineMetadataHeader {
 }
 const METADATA_HDR_SIZE: usize = std::mem::size_of::<TimelineMetadataHeader>();
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct TimelineMetadataBodyV3 {
+    disk_consistent_lsn: Lsn,
+    // This is only set if we know it. We track it in memory when the page
+    // server is running, but we only track the value corresponding to
+    // 'last_record_lsn', not 'disk_consistent_lsn' which can lag behind by a
+    // lot. We only store it in the metadata file when we flush *all* the
+    // in-memory data so that 'last_record_lsn' is the same as
+    // 'disk_consistent_lsn'.  That's OK, because after page server restart, as
+    // soon as we reprocess at least one record, we will have a valid
+    // 'prev_record_lsn' value in memory again. This is only really needed when
+    // doing a clean shutdown, so that there is no more WAL beyond
+    // 'disk_consistent_lsn'
+    prev_record_lsn: Option<Lsn>,
+    ancestor_timeline: Option<TimelineId>,
+    ancestor_lsn: Lsn,
+    latest_gc_cutoff_lsn: Lsn,
+    initdb_lsn: Lsn,
+    pg_version: u32,
+    region_id: RegionId,
+    // Reads before this LSN are refused, as the history of dropped relations
+    // and databases before it is purged, see `Timeline::schedule_purge`.
+    purge_floor_lsn: Lsn,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct TimelineMetadataBodyV2 {
     disk_consistent_lsn: Lsn,
@@ -108,6 +134,7 @@ impl TimelineMetadata {
         initdb_lsn: Lsn,
         pg_version: u32,
         region_id: RegionId,
+        purge_floor_lsn: Lsn,
     ) -> Self {
         Self {
             hdr: TimelineMetadataHeader {
@@ -115,7 +142,7 @@ impl TimelineMetadata {
                 size: 0,
                 format_version: METADATA_FORMAT_VERSION,
             },
-            body: TimelineMetadataBodyV2 {
+            body: TimelineMetadataBodyV3 {
                 disk_consistent_lsn,
                 prev_record_lsn,
                 ancestor_timeline,
@@ -124,6 +151,7 @@ impl TimelineMetadata {
                 initdb_lsn,
                 pg_version,
                 region_id,
+                purge_floor_lsn,
             },
         }
     }
@@ -131,27 +159,40 @@ impl TimelineMetadata {
     fn upgrade_timeline_metadata(metadata_bytes: &[u8]) -> anyhow::Result<Self> {
         let mut hdr = TimelineMetadataHeader::des(&metadata_bytes[0..METADATA_HDR_SIZE])?;
 
-        // backward compatible only up to this version
-        ensure!(
-            hdr.format_version == METADATA_OLD_FORMAT_VERSION,
-            "unsupported metadata format version {}",
-            hdr.format_version
-        );
-
         let metadata_size = hdr.size as usize;
-
-        let body: TimelineMetadataBodyV1 =
-            TimelineMetadataBodyV1::des(&metadata_bytes[METADATA_HDR_SIZE..metadata_size])?;
-
-        let body = TimelineMetadataBodyV2 {
-            disk_consistent_lsn: body.disk_consistent_lsn,
-            prev_record_lsn: body.prev_record_lsn,
-            ancestor_timeline: body.ancestor_timeline,
-            ancestor_lsn: body.ancestor_lsn,
-            latest_gc_cutoff_lsn: body.latest_gc_cutoff_lsn,
-            initdb_lsn: body.initdb_lsn,
-            pg_version: 14, // All timelines created before this version had pg_version 14
-            region_id: body.region_id,
+        let body_bytes = &metadata_bytes[METADATA_HDR_SIZE..metadata_size];
+
+        let body = match hdr.format_version {
+            METADATA_V2_FORMAT_VERSION => {
+                let body = TimelineMetadataBodyV2::des(body_bytes)?;
+                TimelineMetadataBodyV3 {
+                    disk_consistent_lsn: body.disk_consistent_lsn,
+                    prev_record_lsn: body.prev_record_lsn,
+                    ancestor_timeline: body.ancestor_timeline,
+                    ancestor_lsn: body.ancestor_lsn,
+                    latest_gc_cutoff_lsn: body.latest_gc_cutoff_lsn,
+                    initdb_lsn: body.initdb_lsn,
+                    pg_version: body.pg_version,
+                    region_id: body.region_id,
+                    purge_floor_lsn: Lsn(0), // Nothing was purged before this version
+                }
+            }
+            METADATA_OLD_FORMAT_VERSION => {
+                let body = TimelineMetadataBodyV1::des(body_bytes)?;
+                TimelineMetadataBodyV3 {
+                    disk_consistent_lsn: body.disk_consistent_lsn,
+                    prev_record_lsn: body.prev_record_lsn,
+                    ancestor_timeline: body.ancestor_timeline,
+                    ancestor_lsn: body.ancestor_lsn,
+                    latest_gc_cutoff_lsn: body.latest_gc_cutoff_lsn,
+                    initdb_lsn: body.initdb_lsn,
+                    pg_version: 14, // All timelines created before this version had pg_version 14
+                    region_id: body.region_id,
+                    purge_floor_lsn: Lsn(0),
+                }
+            }
+            // backward compatible only up to these versions
+            _ => bail!("unsupported metadata format version {}", hdr.format_version),
         };
 
         hdr.format_version = METADATA_FORMAT_VERSION;
@@ -183,7 +224,7 @@ impl TimelineMetadata {
             TimelineMetadata::upgrade_timeline_metadata(metadata_bytes)
         } else {
             let body =
-                TimelineMetadataBodyV2::des(&metadata_bytes[METADATA_HDR_SIZE..metadata_size])?;
+                TimelineMetadataBodyV3::des(&metadata_bytes[METADATA_HDR_SIZE..metadata_size])?;
             ensure!(
                 body.disk_consistent_lsn.is_aligned(),
                 "disk_consistent_lsn is not aligned"
@@ -240,6 +281,10 @@ impl TimelineMetadata {
     pub fn region_id(&self) -> RegionId {
         self.body.region_id
     }
+
+    pub fn purge_floor_lsn(&self) -> Lsn {
+        self.body.purge_floor_lsn
+    }
 }
 
 /// Save timeline metadata to file
@@ -315,6 +360,7 @@ mod tests {
             // Any version will do here, so use the default
             crate::DEFAULT_PG_VERSION,
             RegionId(0),
+            Lsn(0x180),
         );
 
         let metadata_bytes = original_metadata
@@ -391,6 +437,7 @@ mod tests {
             Lsn(0),
             14, // All timelines created before this version had pg_version 14
             RegionId(0),
+            Lsn(0),
         );
 
         assert_eq!(
@@ -399,4 +446,66 @@ mod tests {
             METADATA_OLD_FORMAT_VERSION, METADATA_FORMAT_VERSION
         );
     }
+
+    // Generate metadata of the previous version, from before the purge floor,
+    // and read it with current code. Ensure that it is upgraded correctly.
+    #[test]
+    fn test_metadata_upgrade_from_v2() {
+        let body_v2 = TimelineMetadataBodyV2 {
+            disk_consistent_lsn: Lsn(0x200),
+            prev_record_lsn: Some(Lsn(0x100)),
+            ancestor_timeline: Some(TIMELINE_ID),
+            ancestor_lsn: Lsn(0x30),
+            latest_gc_cutoff_lsn: Lsn(0x40),
+            initdb_lsn: Lsn(0x20),
+            pg_version: 15,
+            region_id: RegionId(2),
+        };
+        let body_bytes = body_v2.ser().expect("Should serialize the v2 body");
+        let metadata_size = METADATA_HDR_SIZE + body_bytes.len();
+        let hdr = TimelineMetadataHeader {
+            size: metadata_size as u16,
+            format_version: METADATA_V2_FORMAT_VERSION,
+            checksum: crc32c::crc32c(&body_bytes),
+        };
+        let mut metadata_bytes = vec![0u8; METADATA_MAX_SIZE];
+        metadata_bytes[0..METADATA_HDR_SIZE]
+            .copy_from_slice(&hdr.ser().expect("Should serialize the header"));
+        metadata_bytes[METADATA_HDR_SIZE..metadata_size].copy_from_slice(&body_bytes);
+
+        let deserialized_metadata = TimelineMetadata::from_bytes(&metadata_bytes)
+            .expect("Should deserialize the v2 metadata");
+
+        let expected_metadata = TimelineMetadata::new(
+            Lsn(0x200),
+            Some(Lsn(0x100)),
+            Some(TIMELINE_ID),
+            Lsn(0x30),
+            Lsn(0x40),
+            Lsn(0x20),
+            15,
+            RegionId(2),
+            Lsn(0), // Nothing was purged before the purge floor
+        );
+        assert_eq!(
+            deserialized_metadata.body, expected_metadata.body,
+            "Metadata of the version {} should be upgraded to the latest version {}",
+            METADATA_V2_FORMAT_VERSION, METADATA_FORMAT_VERSION
+        );
+        assert_eq!(deserialized_metadata.purge_floor_lsn(), Lsn(0));
+
+        // It is written back in the latest version.
+        let upgraded_bytes = deserialized_metadata
+            .to_bytes()
+            .expect("Should serialize the upgraded metadata");
+        let upgraded_hdr = TimelineMetadataHeader::des(&upgraded_bytes[0..METADATA_HDR_SIZE])
+            .expect("Should deserialize the header");
+        assert_eq!(upgraded_hdr.format_version, METADATA_FORMAT_VERSION);
+        assert_eq!(
+            TimelineMetadata::from_bytes(&upgraded_bytes)
+                .expect("Should deserialize the upgraded metadata")
+                .body,
+            expected_metadata.body
+        );
+    }
 }
@@ -381,6 +381,8 @@ pub enum SetNewTenantConfigError {
     #[error(transparent)]
     GetTenant(#[from] GetTenantError),
     #[error(transparent)]
+    Invalid(anyhow::Error),
+    #[error(transparent)]
     Persist(anyhow::Error),
 }
 
@@ -393,10 +395,26 @@ pub async fn set_new_tenant_config(
     let tenant = get_tenant(tenant_id, true).await?;
 
     let tenant_config_path = conf.tenant_config_path(&tenant_id);
-    Tenant::persist_tenant_config(&tenant_id, &tenant_config_path, new_tenant_conf, false)
-        .map_err(SetNewTenantConfigError::Persist)?;
-    tenant.set_new_tenant_config(new_tenant_conf);
-    Ok(())
+    tenant.set_new_tenant_config(new_tenant_conf, |new_tenant_conf| {
+        Tenant::persist_tenant_config(&tenant_id, &tenant_config_path, new_tenant_conf, false)
+    })
+}
+
+/// Like [`set_new_tenant_config`], but only changes the overrides set in
+/// `changes`, keeping the other ones of the tenant.
+pub async fn update_tenant_config(
+    conf: &'static PageServerConf,
+    changes: TenantConfOpt,
+    tenant_id: TenantId,
+) -> Result<(), SetNewTenantConfigError> {
+    info!("updating tenant {tenant_id} config");
+    let tenant = get_tenant(tenant_id, true).await?;
+
+    let new_tenant_conf = tenant.tenant_specific_overrides().update(&changes);
+    let tenant_config_path = conf.tenant_config_path(&tenant_id);
+    tenant.set_new_tenant_config(new_tenant_conf, |new_tenant_conf| {
+        Tenant::persist_tenant_config(&tenant_id, &tenant_config_path, new_tenant_conf, false)
+    })
 }
 
 #[derive(Debug, thiserror::Error)]
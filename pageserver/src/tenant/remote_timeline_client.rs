@@ -1375,6 +1375,7 @@ mod tests {
             // but it should be consistent with the one in the tests
             crate::DEFAULT_PG_VERSION,
             utils::id::RegionId(0),
+            Lsn(0),
         );
 
         // go through serialize + deserialize to fix the header, including checksum
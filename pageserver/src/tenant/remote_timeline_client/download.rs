@@ -14,7 +14,7 @@ use tokio::io::AsyncWriteExt;
 use utils::backoff;
 
 use crate::config::PageServerConf;
-use crate::tenant::storage_layer::LayerFileName;
+use crate::tenant::storage_layer::{DeltaLayer, ImageLayer, LayerFileName};
 use crate::tenant::timeline::span::debug_assert_current_span_has_tenant_and_timeline_id;
 use remote_storage::{DownloadError, GenericRemoteStorage};
 use utils::crashsafe::path_with_suffix_extension;
@@ -31,7 +31,8 @@ static MAX_DOWNLOAD_DURATION: Duration = Duration::from_secs(120);
 
 ///
 /// If 'metadata' is given, we will validate that the downloaded file's size matches that
-/// in the metadata. (In the future, we might do more cross-checks, like CRC validation)
+/// in the metadata. The content checksum of the file is verified before it is renamed
+/// into place, so that loading the layer later doesn't have to.
 ///
 /// Returns the size of the downloaded file.
 pub async fn download_layer_file<'a>(
@@ -134,6 +135,24 @@ pub async fn download_layer_file<'a>(
         .map_err(DownloadError::Other)?;
     drop(destination_file);
 
+    let verify_path = temp_file_path.clone();
+    let verify_result = match layer_file_name {
+        LayerFileName::Delta(_) => {
+            tokio::task::spawn_blocking(move || DeltaLayer::verify_checksum(&verify_path)).await
+        }
+        LayerFileName::Image(_) => {
+            tokio::task::spawn_blocking(move || ImageLayer::verify_checksum(&verify_path)).await
+        }
+    };
+    if let Err(e) = verify_result
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result)
+    {
+        // Don't leave the corrupted file behind, the next attempt downloads it again.
+        let _ = fs::remove_file(&temp_file_path).await;
+        return Err(DownloadError::Other(e));
+    }
+
     fail::fail_point!("remote-storage-download-pre-rename", |_| {
         Err(DownloadError::Other(anyhow!(
             "remote-storage-download-pre-rename failpoint triggered"
@@ -1,5 +1,6 @@
 //! Common traits and structs for layers
 
+mod checksum;
 pub mod delta_layer;
 mod filename;
 mod image_layer;
@@ -426,6 +427,10 @@ pub trait PersistentLayer: Layer + AsLayerDesc {
         None
     }
 
+    fn downcast_image_layer(self: Arc<Self>) -> Option<std::sync::Arc<ImageLayer>> {
+        None
+    }
+
     fn is_remote_layer(&self) -> bool {
         false
     }
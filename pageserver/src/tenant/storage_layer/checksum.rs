@@ -0,0 +1,142 @@
+//! Content checksums of the layer files.
+//!
+//! Since format version 4, the summary of a delta or image layer file holds
+//! the crc32c checksum of the rest of the file, its values and its index, from
+//! block 1 to the end. The checksum is computed as the file is written, and
+//! verified by reading the whole file back once, before the file is renamed
+//! into place after it is written or downloaded, and again by scrub. Loading a
+//! layer only checks the format version, so a corruption that happens on the
+//! local disk afterwards is found by scrub, or as errors from decoding the
+//! values or index. The summary itself is not covered: it is checked against
+//! the layer file name, and importing a timeline rewrites the ids in it.
+//!
+//! Layer files of format version 3 have no checksum, the field reads as zero
+//! from the padding of the summary block. They are still loaded and
+//! downloaded, without the check, and are replaced as compaction and GC
+//! rewrite the layers.
+//!
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+
+use anyhow::{ensure, Context};
+
+use crate::page_cache::PAGE_SZ;
+use crate::STORAGE_FORMAT_VERSION;
+
+/// The oldest format version of the layer files that can still be read.
+const OLDEST_READABLE_FORMAT_VERSION: u16 = 3;
+
+/// The first format version with a content checksum in the summary.
+const CHECKSUM_FORMAT_VERSION: u16 = 4;
+
+/// How much of the layer file to read at a time, to verify its checksum.
+const VERIFY_CHUNK_SIZE: usize = 128 * PAGE_SZ;
+
+/// A [`Write`] that computes the crc32c checksum of the bytes written through
+/// it.
+pub(super) struct ChecksumWriter<W> {
+    inner: W,
+    checksum: u32,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(super) fn new(inner: W) -> Self {
+        ChecksumWriter { inner, checksum: 0 }
+    }
+
+    pub(super) fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub(super) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum = crc32c::crc32c_append(self.checksum, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Checks that a layer file of `format_version` can be read.
+pub(super) fn check_format_version(format_version: u16) -> anyhow::Result<()> {
+    ensure!(
+        (OLDEST_READABLE_FORMAT_VERSION..=STORAGE_FORMAT_VERSION).contains(&format_version),
+        "unsupported layer file format version {format_version}"
+    );
+    Ok(())
+}
+
+/// Checks that a layer file of `format_version` can be read, and that its
+/// content matches `content_checksum` if the format has checksums. This reads
+/// the whole file.
+pub(super) fn verify_layer_file(
+    file: &impl FileExt,
+    file_size: u64,
+    format_version: u16,
+    content_checksum: u32,
+) -> anyhow::Result<()> {
+    check_format_version(format_version)?;
+    if format_version < CHECKSUM_FORMAT_VERSION {
+        return Ok(());
+    }
+
+    let mut checksum = 0;
+    let mut buf = vec![0; VERIFY_CHUNK_SIZE];
+    let mut offset = PAGE_SZ as u64;
+    while offset < file_size {
+        let len = VERIFY_CHUNK_SIZE.min((file_size - offset) as usize);
+        file.read_exact_at(&mut buf[..len], offset)
+            .with_context(|| format!("read layer file at offset {offset}"))?;
+        checksum = crc32c::crc32c_append(checksum, &buf[..len]);
+        offset += len as u64;
+    }
+    ensure!(
+        checksum == content_checksum,
+        "layer file checksum mismatch: the content has checksum {checksum:#010x}, the summary {content_checksum:#010x}"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_checksum() -> anyhow::Result<()> {
+        let content: Vec<u8> = (0..3 * PAGE_SZ + 100).map(|i| i as u8).collect();
+        let mut writer = ChecksumWriter::new(vec![0; PAGE_SZ]);
+        writer.write_all(&content[..10])?;
+        writer.write_all(&content[10..])?;
+        let checksum = writer.checksum();
+        let file_content = writer.into_inner();
+        assert_eq!(checksum, crc32c::crc32c(&content));
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("layer");
+        std::fs::write(&path, &file_content)?;
+        let file = std::fs::File::open(&path)?;
+        let size = file_content.len() as u64;
+        verify_layer_file(&file, size, STORAGE_FORMAT_VERSION, checksum)?;
+        assert!(verify_layer_file(&file, size, STORAGE_FORMAT_VERSION, checksum ^ 1).is_err());
+        // Version 3 files have no checksum to verify.
+        verify_layer_file(&file, size, 3, 0)?;
+        assert!(verify_layer_file(&file, size, 2, 0).is_err());
+
+        // Flip a bit of the content.
+        let mut corrupted = file_content;
+        corrupted[2 * PAGE_SZ] ^= 1;
+        std::fs::write(&path, &corrupted)?;
+        let file = std::fs::File::open(&path)?;
+        let err = verify_layer_file(&file, size, STORAGE_FORMAT_VERSION, checksum).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err:#}");
+        Ok(())
+    }
+}
@@ -39,7 +39,7 @@ use crate::tenant::storage_layer::{
 };
 use crate::virtual_file::VirtualFile;
 use crate::{walrecord, TEMP_FILE_SUFFIX};
-use crate::{DELTA_FILE_MAGIC, STORAGE_FORMAT_VERSION};
+use crate::{DELTA_FILE_MAGIC, STORAGE_FORMAT_VERSION, ZERO_PAGE};
 use anyhow::{bail, ensure, Context, Result};
 use pageserver_api::models::{HistoricLayerInfo, LayerAccessKind};
 use rand::{distributions::Alphanumeric, Rng};
@@ -61,6 +61,7 @@ use utils::{
     lsn::Lsn,
 };
 
+use super::checksum::{check_format_version, verify_layer_file, ChecksumWriter};
 use super::{
     AsLayerDesc, DeltaFileName, Layer, LayerAccessStats, LayerAccessStatsReset, PathOrConf,
     PersistentLayerDesc,
@@ -87,6 +88,9 @@ pub struct Summary {
     pub index_start_blk: u32,
     /// Block within the 'index', where the B-tree root page is stored
     pub index_root_blk: u32,
+    /// The crc32c checksum of the file after the summary block, zero before
+    /// format version 4.
+    pub content_checksum: u32,
 }
 
 impl From<&DeltaLayer> for Summary {
@@ -118,6 +122,7 @@ impl Summary {
 
             index_start_blk: 0,
             index_root_blk: 0,
+            content_checksum: 0,
         }
     }
 }
@@ -537,6 +542,52 @@ impl DeltaLayer {
         })
     }
 
+    /// Verifies the content checksum of the delta layer file at `path`, by
+    /// reading the whole file. Done once after the file is written or
+    /// downloaded, and by scrub, not each time the layer is loaded.
+    pub(crate) fn verify_checksum(path: &Path) -> Result<()> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file '{}'", path.display()))?;
+        let mut summary_buf = vec![0; PAGE_SZ];
+        file.read_exact_at(&mut summary_buf, 0)?;
+        let summary = Summary::des_prefix(&summary_buf)?;
+        ensure!(
+            summary.magic == DELTA_FILE_MAGIC,
+            "not a delta layer file, magic is {:#x}",
+            summary.magic
+        );
+        verify_layer_file(
+            &file,
+            file.metadata()?.len(),
+            summary.format_version,
+            summary.content_checksum,
+        )
+        .with_context(|| format!("verify layer file '{}'", path.display()))
+    }
+
+    /// Overwrites the tenant and timeline ids in the summary of the layer file
+    /// at `path`, to import the file into another timeline.
+    pub(crate) fn rewrite_summary_ids(
+        path: &Path,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<()> {
+        let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let mut summary_buf = vec![0; PAGE_SZ];
+        file.read_exact_at(&mut summary_buf, 0)?;
+        let mut summary = Summary::des_prefix(&summary_buf)?;
+        ensure!(
+            summary.magic == DELTA_FILE_MAGIC,
+            "not a delta layer file, magic is {:#x}",
+            summary.magic
+        );
+        summary.tenant_id = tenant_id;
+        summary.timeline_id = timeline_id;
+        file.write_all_at(&Summary::ser(&summary)?, 0)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
     fn layer_name(&self) -> DeltaFileName {
         self.desc.delta_file_name()
     }
@@ -566,6 +617,21 @@ impl DeltaLayer {
             .context("Layer index is corrupted")
     }
 
+    /// Checks the layer file: its content checksum, that its summary matches
+    /// the layer, and that all the keys and LSNs of its index are within the
+    /// ranges of the layer.
+    pub(crate) async fn verify(&self, ctx: &RequestContext) -> Result<()> {
+        let path = self.path();
+        tokio::task::spawn_blocking(move || Self::verify_checksum(&path)).await??;
+        for (key, lsn, _) in self.load_keys(ctx).await? {
+            ensure!(
+                self.desc.key_range.contains(&key) && self.desc.lsn_range.contains(&lsn),
+                "key {key} at {lsn} is outside of the layer ranges"
+            );
+        }
+        Ok(())
+    }
+
     /// Loads all keys stored in the layer. Returns key, lsn and value size.
     pub async fn load_keys(&self, ctx: &RequestContext) -> Result<Vec<(Key, Lsn, u64)>> {
         let inner = self
@@ -600,7 +666,7 @@ struct DeltaLayerWriterInner {
 
     tree: DiskBtreeBuilder<BlockBuf, DELTA_KEY_SIZE>,
 
-    blob_writer: WriteBlobWriter<BufWriter<VirtualFile>>,
+    blob_writer: WriteBlobWriter<ChecksumWriter<BufWriter<VirtualFile>>>,
 }
 
 impl DeltaLayerWriterInner {
@@ -625,8 +691,8 @@ impl DeltaLayerWriterInner {
         let mut file = VirtualFile::create(&path)?;
         // make room for the header block
         file.seek(SeekFrom::Start(PAGE_SZ as u64))?;
-        let buf_writer = BufWriter::new(file);
-        let blob_writer = WriteBlobWriter::new(buf_writer, PAGE_SZ as u64);
+        let buf_writer = ChecksumWriter::new(BufWriter::new(file));
+        let blob_writer = WriteBlobWriter::new(buf_writer, PAGE_SZ as u64, conf.layer_compression);
 
         // Initialize the b-tree index builder
         let block_buf = BlockBuf::new();
@@ -683,15 +749,18 @@ impl DeltaLayerWriterInner {
         let index_start_blk =
             ((self.blob_writer.size() + PAGE_SZ as u64 - 1) / PAGE_SZ as u64) as u32;
 
-        let buf_writer = self.blob_writer.into_inner();
-        let mut file = buf_writer.into_inner()?;
+        let padding = index_start_blk as u64 * PAGE_SZ as u64 - self.blob_writer.size();
+        let mut writer = self.blob_writer.into_inner();
 
-        // Write out the index
+        // Write out the index, after zeroes up to its first block, so that the
+        // checksum covers them too.
+        writer.write_all(&ZERO_PAGE[..padding as usize])?;
         let (index_root_blk, block_buf) = self.tree.finish()?;
-        file.seek(SeekFrom::Start(index_start_blk as u64 * PAGE_SZ as u64))?;
         for buf in block_buf.blocks {
-            file.write_all(buf.as_ref())?;
+            writer.write_all(buf.as_ref())?;
         }
+        let content_checksum = writer.checksum();
+        let mut file = writer.into_inner().into_inner()?;
         assert!(self.lsn_range.start < self.lsn_range.end);
         // Fill in the summary on blk 0
         let summary = Summary {
@@ -703,6 +772,7 @@ impl DeltaLayerWriterInner {
             lsn_range: self.lsn_range.clone(),
             index_start_blk,
             index_root_blk,
+            content_checksum,
         };
         file.seek(SeekFrom::Start(0))?;
         Summary::ser_into(&summary, &mut file)?;
@@ -729,6 +799,9 @@ impl DeltaLayerWriterInner {
 
         // fsync the file
         file.sync_all()?;
+        // Read the file back once before it becomes visible, instead of on
+        // each load of the layer.
+        DeltaLayer::verify_checksum(&self.path)?;
         // Rename the file to its final name
         //
         // Note: This overwrites any existing file. There shouldn't be any.
@@ -835,7 +908,7 @@ impl DeltaLayerWriter {
 impl Drop for DeltaLayerWriter {
     fn drop(&mut self) {
         if let Some(inner) = self.inner.take() {
-            match inner.blob_writer.into_inner().into_inner() {
+            match inner.blob_writer.into_inner().into_inner().into_inner() {
                 Ok(vfile) => vfile.remove(),
                 Err(err) => warn!(
                     "error while flushing buffer of image layer temporary file: {}",
@@ -857,8 +930,10 @@ impl DeltaLayerInner {
 
         if let Some(mut expected_summary) = summary {
             // production code path
+            expected_summary.format_version = actual_summary.format_version;
             expected_summary.index_start_blk = actual_summary.index_start_blk;
             expected_summary.index_root_blk = actual_summary.index_root_blk;
+            expected_summary.content_checksum = actual_summary.content_checksum;
             if actual_summary != expected_summary {
                 bail!(
                     "in-file summary does not match expected summary. actual = {:?} expected = {:?}",
@@ -867,6 +942,7 @@ impl DeltaLayerInner {
                 );
             }
         }
+        check_format_version(actual_summary.format_version)?;
 
         Ok(DeltaLayerInner {
             file,
@@ -34,7 +34,7 @@ use crate::tenant::storage_layer::{
     LayerAccessStats, PersistentLayer, ValueReconstructResult, ValueReconstructState,
 };
 use crate::virtual_file::VirtualFile;
-use crate::{IMAGE_FILE_MAGIC, STORAGE_FORMAT_VERSION, TEMP_FILE_SUFFIX};
+use crate::{IMAGE_FILE_MAGIC, STORAGE_FORMAT_VERSION, TEMP_FILE_SUFFIX, ZERO_PAGE};
 use anyhow::{bail, ensure, Context, Result};
 use bytes::Bytes;
 use hex;
@@ -47,6 +47,7 @@ use std::io::{Seek, SeekFrom};
 use std::ops::Range;
 use std::os::unix::prelude::FileExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::sync::OnceCell;
 use tracing::*;
 
@@ -56,6 +57,7 @@ use utils::{
     lsn::Lsn,
 };
 
+use super::checksum::{check_format_version, verify_layer_file, ChecksumWriter};
 use super::filename::ImageFileName;
 use super::{AsLayerDesc, Layer, LayerAccessStatsReset, PathOrConf, PersistentLayerDesc};
 
@@ -80,6 +82,9 @@ pub(super) struct Summary {
     index_start_blk: u32,
     /// Block within the 'index', where the B-tree root page is stored
     index_root_blk: u32,
+    /// The crc32c checksum of the file after the summary block, zero before
+    /// format version 4.
+    content_checksum: u32,
     // the 'values' part starts after the summary header, on block 1.
 }
 
@@ -111,6 +116,7 @@ impl Summary {
 
             index_start_blk: 0,
             index_root_blk: 0,
+            content_checksum: 0,
         }
     }
 }
@@ -259,6 +265,10 @@ impl PersistentLayer for ImageLayer {
         Some(self.path())
     }
 
+    fn downcast_image_layer(self: Arc<Self>) -> Option<std::sync::Arc<ImageLayer>> {
+        Some(self)
+    }
+
     fn delete_resident_layer_file(&self) -> Result<()> {
         // delete underlying file
         fs::remove_file(self.path())?;
@@ -355,6 +365,68 @@ impl ImageLayer {
         Ok(loaded)
     }
 
+    /// Checks the layer file: its content checksum, that its summary matches
+    /// the layer, and that all the keys of its index are within the key range
+    /// of the layer.
+    pub(crate) async fn verify(&self, ctx: &RequestContext) -> Result<()> {
+        let path = self.path();
+        tokio::task::spawn_blocking(move || Self::verify_checksum(&path)).await??;
+        let inner = self.load(LayerAccessKind::KeyIter, ctx).await?;
+        let tree_reader = DiskBtreeReader::<_, KEY_SIZE>::new(
+            inner.index_start_blk,
+            inner.index_root_blk,
+            &inner.file,
+        );
+
+        let mut outside = None;
+        tree_reader
+            .visit(&[0u8; KEY_SIZE], VisitDirection::Forwards, |key, _| {
+                let key = Key::from_slice(key);
+                if self.desc.key_range.contains(&key) {
+                    true
+                } else {
+                    outside = Some(key);
+                    false
+                }
+            })
+            .await
+            .context("Layer index is corrupted")?;
+        if let Some(key) = outside {
+            bail!("key {key} is outside of the layer key range");
+        }
+        Ok(())
+    }
+
+    /// Loads all the images of the layer, in key order.
+    pub(crate) async fn load_images(&self, ctx: &RequestContext) -> Result<Vec<(Key, Bytes)>> {
+        let inner = self.load(LayerAccessKind::Iter, ctx).await?;
+        let tree_reader = DiskBtreeReader::<_, KEY_SIZE>::new(
+            inner.index_start_blk,
+            inner.index_root_blk,
+            &inner.file,
+        );
+
+        let mut offsets = Vec::new();
+        tree_reader
+            .visit(&[0u8; KEY_SIZE], VisitDirection::Forwards, |key, offset| {
+                offsets.push((Key::from_slice(key), offset));
+                true
+            })
+            .await
+            .context("Layer index is corrupted")?;
+
+        let cursor = inner.file.block_cursor();
+        let mut images = Vec::with_capacity(offsets.len());
+        for (key, offset) in offsets {
+            let blob = cursor
+                .read_blob(offset)
+                .await
+                .with_context(|| format!("failed to read value from offset {}", offset))?;
+            images.push((key, Bytes::from(blob)));
+        }
+        Ok(images)
+    }
+
     /// Create an ImageLayer struct representing an existing file on disk
     pub fn new(
         conf: &'static PageServerConf,
@@ -407,6 +479,52 @@ impl ImageLayer {
         })
     }
 
+    /// Verifies the content checksum of the image layer file at `path`, by
+    /// reading the whole file. Done once after the file is written or
+    /// downloaded, and by scrub, not each time the layer is loaded.
+    pub(crate) fn verify_checksum(path: &Path) -> Result<()> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file '{}'", path.display()))?;
+        let mut summary_buf = vec![0; PAGE_SZ];
+        file.read_exact_at(&mut summary_buf, 0)?;
+        let summary = Summary::des_prefix(&summary_buf)?;
+        ensure!(
+            summary.magic == IMAGE_FILE_MAGIC,
+            "not an image layer file, magic is {:#x}",
+            summary.magic
+        );
+        verify_layer_file(
+            &file,
+            file.metadata()?.len(),
+            summary.format_version,
+            summary.content_checksum,
+        )
+        .with_context(|| format!("verify layer file '{}'", path.display()))
+    }
+
+    /// Overwrites the tenant and timeline ids in the summary of the layer file
+    /// at `path`, to import the file into another timeline.
+    pub(crate) fn rewrite_summary_ids(
+        path: &Path,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> Result<()> {
+        let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        let mut summary_buf = vec![0; PAGE_SZ];
+        file.read_exact_at(&mut summary_buf, 0)?;
+        let mut summary = Summary::des_prefix(&summary_buf)?;
+        ensure!(
+            summary.magic == IMAGE_FILE_MAGIC,
+            "not an image layer file, magic is {:#x}",
+            summary.magic
+        );
+        summary.tenant_id = tenant_id;
+        summary.timeline_id = timeline_id;
+        file.write_all_at(&Summary::ser(&summary)?, 0)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
     fn layer_name(&self) -> ImageFileName {
         self.desc.image_file_name()
     }
@@ -436,8 +554,10 @@ impl ImageLayerInner {
 
         if let Some(mut expected_summary) = summary {
             // production code path
+            expected_summary.format_version = actual_summary.format_version;
             expected_summary.index_start_blk = actual_summary.index_start_blk;
             expected_summary.index_root_blk = actual_summary.index_root_blk;
+            expected_summary.content_checksum = actual_summary.content_checksum;
 
             if actual_summary != expected_summary {
                 bail!(
@@ -447,6 +567,7 @@ impl ImageLayerInner {
                 );
             }
         }
+        check_format_version(actual_summary.format_version)?;
 
         Ok(ImageLayerInner {
             index_start_blk: actual_summary.index_start_blk,
@@ -502,7 +623,7 @@ struct ImageLayerWriterInner {
     lsn: Lsn,
     is_incremental: bool,
 
-    blob_writer: WriteBlobWriter<VirtualFile>,
+    blob_writer: WriteBlobWriter<ChecksumWriter<VirtualFile>>,
     tree: DiskBtreeBuilder<BlockBuf, KEY_SIZE>,
 }
 
@@ -536,7 +657,11 @@ impl ImageLayerWriterInner {
         )?;
         // make room for the header block
         file.seek(SeekFrom::Start(PAGE_SZ as u64))?;
-        let blob_writer = WriteBlobWriter::new(file, PAGE_SZ as u64);
+        let blob_writer = WriteBlobWriter::new(
+            ChecksumWriter::new(file),
+            PAGE_SZ as u64,
+            conf.layer_compression,
+        );
 
         // Initialize the b-tree index builder
         let block_buf = BlockBuf::new();
@@ -580,14 +705,18 @@ impl ImageLayerWriterInner {
         let index_start_blk =
             ((self.blob_writer.size() + PAGE_SZ as u64 - 1) / PAGE_SZ as u64) as u32;
 
-        let mut file = self.blob_writer.into_inner();
+        let padding = index_start_blk as u64 * PAGE_SZ as u64 - self.blob_writer.size();
+        let mut writer = self.blob_writer.into_inner();
 
-        // Write out the index
-        file.seek(SeekFrom::Start(index_start_blk as u64 * PAGE_SZ as u64))?;
+        // Write out the index, after zeroes up to its first block, so that the
+        // checksum covers them too.
+        writer.write_all(&ZERO_PAGE[..padding as usize])?;
         let (index_root_blk, block_buf) = self.tree.finish()?;
         for buf in block_buf.blocks {
-            file.write_all(buf.as_ref())?;
+            writer.write_all(buf.as_ref())?;
         }
+        let content_checksum = writer.checksum();
+        let mut file = writer.into_inner();
 
         // Fill in the summary on blk 0
         let summary = Summary {
@@ -599,6 +728,7 @@ impl ImageLayerWriterInner {
             lsn: self.lsn,
             index_start_blk,
             index_root_blk,
+            content_checksum,
         };
         file.seek(SeekFrom::Start(0))?;
         Summary::ser_into(&summary, &mut file)?;
@@ -629,6 +759,9 @@ impl ImageLayerWriterInner {
 
         // fsync the file
         file.sync_all()?;
+        // Read the file back once before it becomes visible, instead of on
+        // each load of the layer.
+        ImageLayer::verify_checksum(&self.path)?;
 
         // Rename the file to its final name
         //
@@ -721,7 +854,7 @@ impl ImageLayerWriter {
 impl Drop for ImageLayerWriter {
     fn drop(&mut self) {
         if let Some(inner) = self.inner.take() {
-            inner.blob_writer.into_inner().remove();
+            inner.blob_writer.into_inner().into_inner().remove();
         }
     }
 }
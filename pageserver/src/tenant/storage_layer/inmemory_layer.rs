@@ -4,6 +4,11 @@
 //! held in an ephemeral file, not in memory. The metadata for each page version, i.e.
 //! its position in the file, is kept in memory, though.
 //!
+//! The layer is split by key into shards, each with its own ephemeral file and
+//! lock, so that a batch of ingested values can be written to the shards in
+//! parallel, see [`PageServerConf::ingest_parallelism`]. All the versions of a
+//! key are in the same shard, so they are still written in LSN order.
+//!
 use crate::config::PageServerConf;
 use crate::context::RequestContext;
 use crate::repository::{Key, Value};
@@ -15,8 +20,10 @@ use crate::walrecord;
 use anyhow::{ensure, Result};
 use pageserver_api::models::InMemoryLayerInfo;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
 use tracing::*;
 use utils::{
     bin_ser::BeSer,
@@ -28,7 +35,7 @@ use utils::{
 // while being able to use std::fmt::Write's methods
 use std::fmt::Write as _;
 use std::ops::Range;
-use tokio::sync::{RwLock, RwLockWriteGuard};
+use tokio::sync::RwLock;
 
 use super::{DeltaLayer, DeltaLayerWriter, Layer};
 
@@ -52,16 +59,21 @@ pub struct InMemoryLayer {
     end_lsn: OnceLock<Lsn>,
 
     /// The above fields never change, except for `end_lsn`, which is only set once.
-    /// All other changing parts are in `inner`, and protected by a mutex.
-    inner: RwLock<InMemoryLayerInner>,
+    /// All other changing parts are in the shards, each protected by a lock.
+    /// A key always goes to the same shard, see [`InMemoryLayer::shard`].
+    shards: Vec<RwLock<InMemoryLayerInner>>,
 }
 
+/// Batches with fewer values than this are written to the shards one after
+/// the other, as the workers would cost more than they save.
+const MIN_VALUES_FOR_PARALLEL_PUT: usize = 64;
+
 impl std::fmt::Debug for InMemoryLayer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("InMemoryLayer")
             .field("start_lsn", &self.start_lsn)
             .field("end_lsn", &self.end_lsn)
-            .field("inner", &self.inner)
+            .field("shards", &self.shards)
             .finish()
     }
 }
@@ -106,6 +118,19 @@ impl InMemoryLayer {
     fn end_lsn_or_max(&self) -> Lsn {
         self.end_lsn.get().copied().unwrap_or(Lsn::MAX)
     }
+
+    fn shard_idx(&self, key: &Key) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    fn shard(&self, key: &Key) -> &RwLock<InMemoryLayerInner> {
+        &self.shards[self.shard_idx(key)]
+    }
 }
 
 #[async_trait::async_trait]
@@ -125,8 +150,6 @@ impl Layer for InMemoryLayer {
 
     /// debugging function to print out the contents of the layer
     async fn dump(&self, verbose: bool, _ctx: &RequestContext) -> Result<()> {
-        let inner = self.inner.read().await;
-
         let end_str = self.end_lsn_or_max();
 
         println!(
@@ -138,32 +161,35 @@ impl Layer for InMemoryLayer {
             return Ok(());
         }
 
-        let cursor = inner.file.block_cursor();
         let mut buf = Vec::new();
-        for (key, vec_map) in inner.index.iter() {
-            for (lsn, pos) in vec_map.as_slice() {
-                let mut desc = String::new();
-                cursor.read_blob_into_buf(*pos, &mut buf).await?;
-                let val = Value::des(&buf);
-                match val {
-                    Ok(Value::Image(img)) => {
-                        write!(&mut desc, " img {} bytes", img.len())?;
-                    }
-                    Ok(Value::WalRecord(rec)) => {
-                        let wal_desc = walrecord::describe_wal_record(&rec).unwrap();
-                        write!(
-                            &mut desc,
-                            " rec {} bytes will_init: {} {}",
-                            buf.len(),
-                            rec.will_init(),
-                            wal_desc
-                        )?;
-                    }
-                    Err(err) => {
-                        write!(&mut desc, " DESERIALIZATION ERROR: {}", err)?;
+        for shard in &self.shards {
+            let inner = shard.read().await;
+            let cursor = inner.file.block_cursor();
+            for (key, vec_map) in inner.index.iter() {
+                for (lsn, pos) in vec_map.as_slice() {
+                    let mut desc = String::new();
+                    cursor.read_blob_into_buf(*pos, &mut buf).await?;
+                    let val = Value::des(&buf);
+                    match val {
+                        Ok(Value::Image(img)) => {
+                            write!(&mut desc, " img {} bytes", img.len())?;
+                        }
+                        Ok(Value::WalRecord(rec)) => {
+                            let wal_desc = walrecord::describe_wal_record(&rec).unwrap();
+                            write!(
+                                &mut desc,
+                                " rec {} bytes will_init: {} {}",
+                                buf.len(),
+                                rec.will_init(),
+                                wal_desc
+                            )?;
+                        }
+                        Err(err) => {
+                            write!(&mut desc, " DESERIALIZATION ERROR: {}", err)?;
+                        }
                     }
+                    println!("  key {} at {}: {}", key, lsn, desc);
                 }
-                println!("  key {} at {}: {}", key, lsn, desc);
             }
         }
 
@@ -181,7 +207,7 @@ impl Layer for InMemoryLayer {
         ensure!(lsn_range.start >= self.start_lsn);
         let mut need_image = true;
 
-        let inner = self.inner.read().await;
+        let inner = self.shard(&key).read().await;
 
         let reader = inner.file.block_cursor();
 
@@ -233,22 +259,34 @@ impl InMemoryLayer {
     /// Get layer size on the disk
     ///
     pub async fn size(&self) -> Result<u64> {
-        let inner = self.inner.read().await;
-        Ok(inner.file.size)
+        let mut size = 0;
+        for shard in &self.shards {
+            size += shard.read().await.file.size;
+        }
+        Ok(size)
     }
 
     ///
-    /// Create a new, empty, in-memory layer
+    /// Create a new, empty, in-memory layer, split into `num_shards` shards
     ///
     pub fn create(
         conf: &'static PageServerConf,
         timeline_id: TimelineId,
         tenant_id: TenantId,
         start_lsn: Lsn,
+        num_shards: usize,
     ) -> Result<InMemoryLayer> {
         trace!("initializing new empty InMemoryLayer for writing on timeline {timeline_id} at {start_lsn}");
 
-        let file = EphemeralFile::create(conf, tenant_id, timeline_id)?;
+        let shards = (0..num_shards.max(1))
+            .map(|_| {
+                let file = EphemeralFile::create(conf, tenant_id, timeline_id)?;
+                Ok(RwLock::new(InMemoryLayerInner {
+                    index: HashMap::new(),
+                    file,
+                }))
+            })
+            .collect::<Result<_>>()?;
 
         Ok(InMemoryLayer {
             conf,
@@ -256,10 +294,7 @@ impl InMemoryLayer {
             tenant_id,
             start_lsn,
             end_lsn: OnceLock::new(),
-            inner: RwLock::new(InMemoryLayerInner {
-                index: HashMap::new(),
-                file,
-            }),
+            shards,
         })
     }
 
@@ -268,25 +303,70 @@ impl InMemoryLayer {
     /// Common subroutine of the public put_wal_record() and put_page_image() functions.
     /// Adds the page version to the in-memory tree
     pub async fn put_value(&self, key: Key, lsn: Lsn, val: &Value) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.shard(&key).write().await;
         self.assert_writable();
-        self.put_value_locked(&mut inner, key, lsn, val).await
+        self.put_value_locked(&mut inner, key, lsn, val)
     }
 
-    pub async fn put_values(&self, values: &HashMap<Key, Vec<(Lsn, Value)>>) -> Result<()> {
-        let mut inner = self.inner.write().await;
-        self.assert_writable();
+    /// Writes a batch of values, in parallel if the layer has several shards
+    /// and the batch is large enough: the values of each shard are written by
+    /// a blocking task of its own.
+    pub async fn put_values(
+        self: &Arc<Self>,
+        values: &HashMap<Key, Vec<(Lsn, Value)>>,
+    ) -> Result<()> {
+        let mut shard_values = vec![Vec::new(); self.shards.len()];
         for (key, vals) in values {
-            for (lsn, val) in vals {
-                self.put_value_locked(&mut inner, *key, *lsn, val).await?;
+            shard_values[self.shard_idx(key)].push((key, vals));
+        }
+
+        let num_values: usize = values.values().map(Vec::len).sum();
+        if self.shards.len() == 1 || num_values < MIN_VALUES_FOR_PARALLEL_PUT {
+            for (shard, values) in self.shards.iter().zip(shard_values) {
+                if values.is_empty() {
+                    continue;
+                }
+                let mut inner = shard.write().await;
+                self.assert_writable();
+                for (key, vals) in values {
+                    for (lsn, val) in vals {
+                        self.put_value_locked(&mut inner, *key, *lsn, val)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let mut tasks = Vec::with_capacity(self.shards.len());
+        for (shard_idx, values) in shard_values.into_iter().enumerate() {
+            if values.is_empty() {
+                continue;
             }
+            let values: Vec<(Key, Vec<(Lsn, Value)>)> = values
+                .into_iter()
+                .map(|(key, vals)| (*key, vals.clone()))
+                .collect();
+            let layer = Arc::clone(self);
+            tasks.push(tokio::task::spawn_blocking(move || {
+                let mut inner = layer.shards[shard_idx].blocking_write();
+                layer.assert_writable();
+                for (key, vals) in values {
+                    for (lsn, val) in vals {
+                        layer.put_value_locked(&mut inner, key, lsn, &val)?;
+                    }
+                }
+                Ok::<_, anyhow::Error>(())
+            }));
+        }
+        for task in tasks {
+            task.await??;
         }
         Ok(())
     }
 
-    async fn put_value_locked(
+    fn put_value_locked(
         &self,
-        locked_inner: &mut RwLockWriteGuard<'_, InMemoryLayerInner>,
+        locked_inner: &mut InMemoryLayerInner,
         key: Key,
         lsn: Lsn,
         val: &Value,
@@ -327,14 +407,20 @@ impl InMemoryLayer {
     /// Records the end_lsn for non-dropped layers.
     /// `end_lsn` is exclusive
     pub async fn freeze(&self, end_lsn: Lsn) {
-        let inner = self.inner.write().await;
+        // Lock all the shards, in order, to wait for the writes in progress.
+        let mut shards = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            shards.push(shard.write().await);
+        }
 
         assert!(self.start_lsn < end_lsn);
         self.end_lsn.set(end_lsn).expect("end_lsn set only once");
 
-        for vec_map in inner.index.values() {
-            for (lsn, _pos) in vec_map.as_slice() {
-                assert!(*lsn < end_lsn);
+        for inner in &shards {
+            for vec_map in inner.index.values() {
+                for (lsn, _pos) in vec_map.as_slice() {
+                    assert!(*lsn < end_lsn);
+                }
             }
         }
     }
@@ -352,7 +438,10 @@ impl InMemoryLayer {
         // lock, it will see that it's not writeable anymore and retry, but it
         // would have to wait until we release it. That race condition is very
         // rare though, so we just accept the potential latency hit for now.
-        let inner = self.inner.read().await;
+        let mut shards = Vec::with_capacity(self.shards.len());
+        for shard in &self.shards {
+            shards.push(shard.read().await);
+        }
 
         let end_lsn = *self.end_lsn.get().unwrap();
 
@@ -366,13 +455,27 @@ impl InMemoryLayer {
 
         let mut buf = Vec::new();
 
-        let cursor = inner.file.block_cursor();
-
-        let mut keys: Vec<(&Key, &VecMap<Lsn, u64>)> = inner.index.iter().collect();
+        let cursors: Vec<_> = shards
+            .iter()
+            .map(|inner| inner.file.block_cursor())
+            .collect();
+
+        // The keys of all the shards, in key order.
+        let mut keys: Vec<(&Key, &VecMap<Lsn, u64>, usize)> = shards
+            .iter()
+            .enumerate()
+            .flat_map(|(shard_idx, inner)| {
+                inner
+                    .index
+                    .iter()
+                    .map(move |(key, vec_map)| (key, vec_map, shard_idx))
+            })
+            .collect();
         keys.sort_by_key(|k| k.0);
 
-        for (key, vec_map) in keys.iter() {
+        for (key, vec_map, shard_idx) in keys.iter() {
             let key = **key;
+            let cursor = &cursors[*shard_idx];
             // Write all page versions
             for (lsn, pos) in vec_map.as_slice() {
                 cursor.read_blob_into_buf(*pos, &mut buf).await?;
@@ -385,3 +488,78 @@ impl InMemoryLayer {
         Ok(delta_layer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use utils::id::RegionId;
+
+    use super::*;
+    use crate::tenant::harness::{TenantHarness, TEST_IMG, TIMELINE_ID};
+    use crate::DEFAULT_PG_VERSION;
+
+    #[tokio::test]
+    async fn parallel_put_values() -> Result<()> {
+        let harness = TenantHarness::create("parallel_put_values")?;
+        let (tenant, ctx) = harness.load().await;
+        // For the timeline directory, where the layer files go.
+        tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        let layer = Arc::new(InMemoryLayer::create(
+            harness.conf,
+            TIMELINE_ID,
+            harness.tenant_id,
+            Lsn(0x10),
+            4,
+        )?);
+        let img = |key: Key, lsn: Lsn| TEST_IMG(&format!("{key} at {lsn}"));
+        let lsns = [Lsn(0x10), Lsn(0x20), Lsn(0x30)];
+        let keys: Vec<Key> = (0..100).map(Key::from_i128).collect();
+        let batch: HashMap<Key, Vec<(Lsn, Value)>> = keys
+            .iter()
+            .map(|key| {
+                let values = lsns
+                    .iter()
+                    .map(|lsn| (*lsn, Value::Image(img(*key, *lsn))))
+                    .collect();
+                (*key, values)
+            })
+            .collect();
+        layer.put_values(&batch).await?;
+
+        // Every key reads back its latest version, from whichever shard.
+        for key in &keys {
+            let mut state = ValueReconstructState {
+                records: Vec::new(),
+                img: None,
+            };
+            layer
+                .get_value_reconstruct_data(*key, Lsn(0x10)..Lsn(0x40), &mut state, &ctx)
+                .await?;
+            assert_eq!(state.img, Some((Lsn(0x30), img(*key, Lsn(0x30)))));
+        }
+
+        // The delta layer has all the versions, in key and LSN order.
+        layer.freeze(Lsn(0x40)).await;
+        let delta = layer.write_to_disk().await?;
+        let written: Vec<(Key, Lsn)> = delta
+            .load_val_refs(&ctx)
+            .await?
+            .into_iter()
+            .map(|(key, lsn, _)| (key, lsn))
+            .collect();
+        let expected: Vec<(Key, Lsn)> = keys
+            .iter()
+            .flat_map(|key| lsns.iter().map(|lsn| (*key, *lsn)))
+            .collect();
+        assert_eq!(written, expected);
+        Ok(())
+    }
+}
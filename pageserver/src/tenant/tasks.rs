@@ -6,7 +6,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::context::{DownloadBehavior, RequestContext};
-use crate::metrics::TENANT_TASK_EVENTS;
+use crate::metrics::{record_background_task_completion, TENANT_TASK_EVENTS};
 use crate::task_mgr;
 use crate::task_mgr::{TaskKind, BACKGROUND_RUNTIME};
 use crate::tenant::{Tenant, TenantState};
@@ -178,6 +178,7 @@ async fn gc_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
                     error!("Gc failed, retrying in {:?}: {e:?}", wait_duration);
                     wait_duration
                 } else {
+                    record_background_task_completion("gc");
                     period
                 }
             };
@@ -0,0 +1,176 @@
+//! Admission control of the work a tenant puts on the pageserver, so that one
+//! busy tenant cannot starve the others on a shared pageserver.
+//!
+//! The limits are part of the tenant config, and are passed in on every
+//! admission, so that config changes apply right away:
+//!
+//! - `max_concurrent_getpage_requests` caps the getpage requests of the tenant
+//!   served at a time, over all its page service connections. The requests
+//!   over the limit wait for their turn.
+//! - `wal_ingest_rate_limit` caps the bytes of WAL per second ingested by the
+//!   timelines of the tenant, with a token bucket that allows bursts of up to
+//!   one second worth of WAL.
+//!
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use metrics::{Counter, IntCounter};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use utils::id::TenantId;
+
+use crate::metrics::{TENANT_THROTTLED_GETPAGE_REQUESTS, TENANT_THROTTLED_WAL_INGEST_SECONDS};
+
+pub(crate) struct TenantThrottle {
+    /// The semaphore of the current getpage concurrency limit. The requests
+    /// admitted under a previous limit keep the permits of its semaphore.
+    getpage_requests: Mutex<Option<(NonZeroUsize, Arc<Semaphore>)>>,
+    wal_ingest: Mutex<TokenBucket>,
+
+    throttled_getpage_requests: IntCounter,
+    throttled_wal_ingest_seconds: Counter,
+}
+
+impl TenantThrottle {
+    pub(crate) fn new(tenant_id: &TenantId) -> Self {
+        let tenant_id = tenant_id.to_string();
+        TenantThrottle {
+            getpage_requests: Mutex::new(None),
+            wal_ingest: Mutex::new(TokenBucket::new(Instant::now())),
+            throttled_getpage_requests: TENANT_THROTTLED_GETPAGE_REQUESTS
+                .with_label_values(&[&tenant_id]),
+            throttled_wal_ingest_seconds: TENANT_THROTTLED_WAL_INGEST_SECONDS
+                .with_label_values(&[&tenant_id]),
+        }
+    }
+
+    /// Waits for a getpage request to be admitted under `limit` concurrent
+    /// requests, if any. The request is served while the returned permit is held.
+    pub(crate) async fn admit_getpage_request(
+        &self,
+        limit: Option<NonZeroUsize>,
+    ) -> Option<OwnedSemaphorePermit> {
+        let limit = limit?;
+        let semaphore = {
+            let mut current = self.getpage_requests.lock().unwrap();
+            match &*current {
+                Some((current_limit, semaphore)) if *current_limit == limit => {
+                    Arc::clone(semaphore)
+                }
+                _ => {
+                    let semaphore = Arc::new(Semaphore::new(limit.get()));
+                    *current = Some((limit, Arc::clone(&semaphore)));
+                    semaphore
+                }
+            }
+        };
+
+        if let Ok(permit) = Arc::clone(&semaphore).try_acquire_owned() {
+            return Some(permit);
+        }
+        self.throttled_getpage_requests.inc();
+        Some(
+            semaphore
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed"),
+        )
+    }
+
+    /// Takes `bytes` of WAL out of the budget of `rate_limit` bytes per second,
+    /// if any, and returns how long to wait before ingesting them.
+    pub(crate) fn wal_ingest_delay(&self, bytes: u64, rate_limit: Option<NonZeroU64>) -> Duration {
+        let Some(rate_limit) = rate_limit else {
+            return Duration::ZERO;
+        };
+        let delay = self
+            .wal_ingest
+            .lock()
+            .unwrap()
+            .take(Instant::now(), bytes, rate_limit);
+        if !delay.is_zero() {
+            self.throttled_wal_ingest_seconds
+                .inc_by(delay.as_secs_f64());
+        }
+        delay
+    }
+}
+
+/// Tokens refill at a rate per second, up to one second worth of them. Taking
+/// more tokens than there are puts the bucket in debt, which the taker waits
+/// out before going on.
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(now: Instant) -> Self {
+        TokenBucket {
+            // Full, whatever the rate turns out to be.
+            tokens: f64::INFINITY,
+            refilled_at: now,
+        }
+    }
+
+    fn take(&mut self, now: Instant, amount: u64, rate: NonZeroU64) -> Duration {
+        let rate = rate.get() as f64;
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        self.refilled_at = now;
+
+        self.tokens -= amount as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn token_bucket() {
+        let rate = NonZeroU64::new(1000).unwrap();
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(start);
+
+        // A burst of one second worth of tokens goes through right away.
+        assert_eq!(bucket.take(start, 1000, rate), Duration::ZERO);
+        // Then the takers wait for the tokens to refill, in turn.
+        assert_eq!(bucket.take(start, 500, rate), Duration::from_millis(500));
+        assert_eq!(bucket.take(start, 500, rate), Duration::from_secs(1));
+        // Out of debt after waiting.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.take(later, 0, rate), Duration::ZERO);
+        // The bucket holds no more than one second worth of tokens.
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(bucket.take(much_later, 1000, rate), Duration::ZERO);
+        assert_eq!(bucket.take(much_later, 1, rate), Duration::from_millis(1));
+    }
+
+    #[tokio::test]
+    async fn getpage_concurrency_limit() {
+        let throttle = TenantThrottle::new(&TenantId::generate());
+        assert!(throttle.admit_getpage_request(None).await.is_none());
+
+        let limit = NonZeroUsize::new(2);
+        let first = throttle.admit_getpage_request(limit).await;
+        let _second = throttle.admit_getpage_request(limit).await;
+        let third = throttle.admit_getpage_request(limit);
+        tokio::pin!(third);
+        assert!(third.as_mut().now_or_never().is_none());
+        drop(first);
+        assert!(third.await.is_some());
+
+        // A new limit applies to the requests that come after it.
+        let _fourth = throttle.admit_getpage_request(NonZeroUsize::new(1)).await;
+        let fifth = throttle.admit_getpage_request(NonZeroUsize::new(1));
+        tokio::pin!(fifth);
+        assert!(fifth.as_mut().now_or_never().is_none());
+    }
+}
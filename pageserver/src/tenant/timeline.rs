@@ -1,8 +1,15 @@
+mod archive;
+mod changed_pages;
 pub mod delete;
 mod eviction_task;
 pub mod layer_manager;
 mod logical_size;
+mod purge;
+mod read_heat;
+mod scrub;
+pub(crate) mod slow_getpage_log;
 pub mod span;
+mod truncate;
 pub mod uninit;
 mod walreceiver;
 
@@ -13,7 +20,8 @@ use futures::StreamExt;
 use itertools::Itertools;
 use pageserver_api::models::{
     DownloadRemoteLayersTaskInfo, DownloadRemoteLayersTaskSpawnRequest,
-    DownloadRemoteLayersTaskState, LayerMapInfo, LayerResidenceEventReason, LayerResidenceStatus,
+    DownloadRemoteLayersTaskState, LayerGcExplanation, LayerGcVerdict, LayerMapInfo,
+    LayerResidenceEventReason, LayerResidenceStatus, SlowGetPageRequest, TimelineLayerCounts,
     TimelineState,
 };
 use remote_storage::GenericRemoteStorage;
@@ -28,6 +36,7 @@ use utils::id::TenantTimelineId;
 use std::cmp::{max, min, Ordering};
 use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs;
+use std::num::NonZeroU64;
 use std::ops::{Deref, Range};
 use std::path::{Path, PathBuf};
 use std::pin::pin;
@@ -60,7 +69,7 @@ use crate::metrics::{
 };
 use crate::pgdatadir_mapping::LsnForTimestamp;
 use crate::pgdatadir_mapping::{is_rel_fsm_block_key, is_rel_vm_block_key};
-use crate::pgdatadir_mapping::{BlockNumber, CalculateLogicalSizeError};
+use crate::pgdatadir_mapping::{key_to_rel_block, BlockNumber, CalculateLogicalSizeError};
 use crate::tenant::config::{EvictionPolicy, TenantConfOpt};
 use pageserver_api::reltag::RelTag;
 
@@ -70,7 +79,7 @@ use utils::{
     completion,
     id::{RegionId, TenantId, TimelineId},
     lsn::{AtomicLsn, Lsn, RecordLsn},
-    seqwait::SeqWait,
+    seqwait::{SeqWait, SeqWaitError},
     simple_rcu::{Rcu, RcuReadGuard},
 };
 
@@ -79,15 +88,18 @@ use crate::repository::GcResult;
 use crate::repository::{Key, Value};
 use crate::task_mgr::TaskKind;
 use crate::walredo::WalRedoManager;
-use crate::METADATA_FILE_NAME;
 use crate::ZERO_PAGE;
 use crate::{is_temporary, task_mgr};
+use crate::{METADATA_FILE_NAME, WAL_RECEIVER_STATE_FILE_NAME};
 
 use self::delete::DeleteTimelineFlow;
 pub(super) use self::eviction_task::EvictionTaskTenantState;
 use self::eviction_task::EvictionTaskTimelineState;
 use self::layer_manager::LayerManager;
 use self::logical_size::LogicalSize;
+use self::purge::DroppedKeyRange;
+use self::read_heat::ReadHeat;
+use self::slow_getpage_log::SlowGetPageLog;
 use self::walreceiver::{WalReceiver, WalReceiverConf};
 
 use super::config::TenantConf;
@@ -96,6 +108,7 @@ use super::remote_timeline_client::RemoteTimelineClient;
 use super::storage_layer::{
     AsLayerDesc, DeltaLayer, ImageLayer, Layer, LayerAccessStatsReset, PersistentLayerDesc,
 };
+use super::throttle::TenantThrottle;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(super) enum FlushLoopState {
@@ -142,6 +155,8 @@ fn drop_wlock<T>(rlock: tokio::sync::RwLockWriteGuard<'_, T>) {
 pub struct Timeline {
     conf: &'static PageServerConf,
     tenant_conf: Arc<RwLock<TenantConfOpt>>,
+    /// Admission control of the tenant, shared by its timelines.
+    throttle: Arc<TenantThrottle>,
 
     myself: Weak<Self>,
 
@@ -150,6 +165,9 @@ pub struct Timeline {
 
     pub pg_version: u32,
 
+    /// Whether the cluster uses data checksums, see `get_data_checksums_enabled`.
+    pub(crate) data_checksums_enabled: tokio::sync::OnceCell<bool>,
+
     /// The tuple has two elements.
     /// 1. `LayerFileManager` keeps track of the various physical representations of the layer files (inmem, local, remote).
     /// 2. `LayerMap`, the acceleration data structure for `get_reconstruct_data`.
@@ -281,6 +299,20 @@ pub struct Timeline {
     /// Relation size cache
     pub rel_size_cache: RwLock<HashMap<RelTag, (Lsn, BlockNumber)>>,
 
+    /// Page requests of computes that took long, if enabled.
+    pub(crate) slow_getpage_log: Mutex<SlowGetPageLog>,
+
+    /// How often the key ranges are read through WAL redo by computes.
+    read_heat: ReadHeat,
+
+    /// Dropped relations and databases whose history the next compactions
+    /// reclaim, see `purge`.
+    dropped_key_ranges: Mutex<Vec<DroppedKeyRange>>,
+
+    /// Reads before this LSN are refused, like the ones before the GC cutoff,
+    /// as the history of dropped relations and databases before it is purged.
+    purge_floor_lsn: AtomicLsn,
+
     download_all_remote_layers_task_info: RwLock<Option<DownloadRemoteLayersTaskInfo>>,
 
     state: watch::Sender<TimelineState>,
@@ -341,6 +373,85 @@ pub struct GcInfo {
     pub pitr_cutoff: Lsn,
 }
 
+/// Decides whether GC removes a layer, with the cutoffs of a [`GcInfo`].
+///
+/// A layer is removed only if all of these hold:
+/// 1. it is older than the horizon cutoff;
+/// 2. it is older than the PITR cutoff;
+/// 3. it doesn't need to be retained for `retain_lsns`;
+/// 4. newer image layers, before `image_cutoff`, cover its whole key range.
+///
+/// Otherwise, the verdict is the first of these reasons to keep it.
+fn gc_verdict(
+    layers: &LayerMap,
+    l: &PersistentLayerDesc,
+    horizon_cutoff: Lsn,
+    pitr_cutoff: Lsn,
+    retain_lsns: &[Lsn],
+    image_cutoff: Lsn,
+) -> anyhow::Result<LayerGcVerdict> {
+    let lsn_range = l.get_lsn_range();
+
+    // 1. Is it newer than GC horizon cutoff point?
+    if lsn_range.end > horizon_cutoff {
+        return Ok(LayerGcVerdict::NewerThanHorizonCutoff);
+    }
+
+    // 2. It is newer than PiTR cutoff point?
+    if lsn_range.end > pitr_cutoff {
+        return Ok(LayerGcVerdict::NewerThanPitrCutoff);
+    }
+
+    // 3. Is it needed by a child branch?
+    //
+    // A child reads the pages of its ancestor as of its branch point, from
+    // the layers of the ancestor that start at or before it: the delta
+    // layer the branch point falls into, and all the older layers the page
+    // versions of that one are based on. So all the layers that start at or
+    // before the branch point are kept, whatever newer image layers there are.
+    //
+    // NOTE With that we would keep data that
+    // might be referenced by child branches forever.
+    // We can track this in child timeline GC and delete parent layers when
+    // they are no longer needed. This might be complicated with long inheritance chains.
+    //
+    // TODO Vec is not a great choice for `retain_lsns`
+    // start_lsn is inclusive
+    if let Some(&branch_lsn) = retain_lsns
+        .iter()
+        .filter(|&&retain_lsn| lsn_range.start <= retain_lsn)
+        .min()
+    {
+        return Ok(LayerGcVerdict::NeededByBranch { branch_lsn });
+    }
+
+    // 4. Is there a later on-disk layer for this relation?
+    //
+    // The end-LSN is exclusive, while disk_consistent_lsn is
+    // inclusive. For example, if disk_consistent_lsn is 100, it is
+    // OK for a delta layer to have end LSN 101, but if the end LSN
+    // is 102, then it might not have been fully flushed to disk
+    // before crash.
+    //
+    // For example, imagine that the following layers exist:
+    //
+    // 1000      - image (A)
+    // 1000-2000 - delta (B)
+    // 2000      - image (C)
+    // 2000-3000 - delta (D)
+    // 3000      - image (E)
+    //
+    // If GC horizon is at 2500, we can remove layers A and B, but
+    // we cannot remove C, even though it's older than 2500, because
+    // the delta layer 2000-3000 depends on it.
+    if !layers.image_layer_exists(&l.get_key_range(), &(lsn_range.end..image_cutoff))? {
+        return Ok(LayerGcVerdict::NotCoveredByImageLayers);
+    }
+
+    // We didn't find any reason to keep this file, so remove it.
+    Ok(LayerGcVerdict::Removable)
+}
+
 /// An error happened in a get() operation.
 #[derive(thiserror::Error)]
 pub enum PageReconstructError {
@@ -403,6 +514,19 @@ impl std::fmt::Display for PageReconstructError {
     }
 }
 
+/// An error happened in [`Timeline::wait_lsn_durable`].
+#[derive(Debug, thiserror::Error)]
+pub enum WaitLsnDurableError {
+    #[error("timed out waiting for WAL record at LSN {lsn} to arrive, last_record_lsn {last_record_lsn}")]
+    Timeout { lsn: Lsn, last_record_lsn: Lsn },
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
 #[derive(Clone, Copy)]
 pub enum LogicalSizeCalculationCause {
     Initial,
@@ -460,6 +584,14 @@ impl Timeline {
             ctx.task_kind()
         );
 
+        // Only the page requests of computes go to the slow getPage log.
+        let slow_getpage_threshold = if ctx.task_kind() == TaskKind::PageRequestHandler {
+            self.slow_getpage_log.lock().unwrap().threshold()
+        } else {
+            None
+        };
+        let started = Instant::now();
+
         // Check the page cache. We will get back the most recent page with lsn <= `lsn`.
         // The cached image can be returned directly if there is no WAL between the cached image
         // and requested LSN. The cached image can also be used to reduce the amount of WAL needed
@@ -487,12 +619,45 @@ impl Timeline {
         };
 
         let timer = crate::metrics::GET_RECONSTRUCT_DATA_TIME.start_timer();
-        self.get_reconstruct_data(key, lsn, &mut reconstruct_state, ctx)
+        let layers_visited = self
+            .get_reconstruct_data(key, lsn, &mut reconstruct_state, ctx)
             .await?;
         timer.stop_and_record();
 
-        RECONSTRUCT_TIME
-            .observe_closure_duration(|| self.reconstruct_value(key, lsn, reconstruct_state))
+        let redo_records = reconstruct_state.records.len();
+        let res = RECONSTRUCT_TIME
+            .observe_closure_duration(|| self.reconstruct_value(key, lsn, reconstruct_state));
+
+        // Only the page requests of computes heat up the key ranges: the reads of
+        // image layer creation would heat up the whole key space.
+        if ctx.task_kind() == TaskKind::PageRequestHandler && redo_records > 0 && res.is_ok() {
+            self.read_heat.record_read(key, redo_records);
+            self.metrics.read_heat_redo_reads.inc();
+            self.metrics
+                .read_heat_redo_records
+                .inc_by(redo_records as u64);
+        }
+
+        if let Some(threshold) = slow_getpage_threshold {
+            let latency = started.elapsed();
+            if latency >= threshold {
+                if let Ok((rel, blkno)) = key_to_rel_block(key) {
+                    self.slow_getpage_log
+                        .lock()
+                        .unwrap()
+                        .record(SlowGetPageRequest {
+                            rel,
+                            blkno,
+                            lsn,
+                            latency_micros: latency.as_micros() as u64,
+                            layers_visited,
+                            timestamp: SystemTime::now(),
+                        });
+                }
+            }
+        }
+
+        res
     }
 
     /// Get last or prev record separately. Same as get_last_record_rlsn().last/prev.
@@ -534,6 +699,48 @@ impl Timeline {
         size
     }
 
+    pub async fn layer_counts(&self) -> anyhow::Result<TimelineLayerCounts> {
+        let guard = self.layers.read().await;
+        let layer_map = guard.layer_map();
+        let mut counts = TimelineLayerCounts {
+            delta_layers: 0,
+            image_layers: 0,
+            evicted_layers: 0,
+            in_memory_layers: 0,
+            in_memory_size: 0,
+        };
+        for l in layer_map.iter_historic_layers() {
+            if l.is_delta() {
+                counts.delta_layers += 1;
+            } else {
+                counts.image_layers += 1;
+            }
+            if guard.get_from_desc(&l).is_remote_layer() {
+                counts.evicted_layers += 1;
+            }
+        }
+        for l in layer_map.open_layer.iter().chain(&layer_map.frozen_layers) {
+            counts.in_memory_layers += 1;
+            counts.in_memory_size += l.size().await?;
+        }
+        Ok(counts)
+    }
+
+    /// Updates the layer count metrics from the layer map.
+    async fn update_layer_count_metrics(&self) {
+        let guard = self.layers.read().await;
+        let (mut delta_layers, mut image_layers) = (0, 0);
+        for l in guard.layer_map().iter_historic_layers() {
+            if l.is_delta() {
+                delta_layers += 1;
+            } else {
+                image_layers += 1;
+            }
+        }
+        self.metrics.delta_layer_count_gauge.set(delta_layers);
+        self.metrics.image_layer_count_gauge.set(image_layers);
+    }
+
     pub fn resident_physical_size(&self) -> u64 {
         self.metrics.resident_physical_size_gauge.get()
     }
@@ -605,6 +812,72 @@ impl Timeline {
         }
     }
 
+    /// Waits for at most `timeout` until the WAL up to `lsn` is ingested, and
+    /// then until it is flushed to disk. Unlike [`Self::wait_lsn`], which is
+    /// for reads, this doesn't wait for the checkpoint distance or timeout to
+    /// flush the in-memory layer, it is frozen and flushed right away.
+    pub async fn wait_lsn_durable(
+        &self,
+        lsn: Lsn,
+        timeout: Duration,
+        cancel: &CancellationToken,
+    ) -> Result<(), WaitLsnDurableError> {
+        if !self.is_active() {
+            return Err(anyhow!("Cannot wait for Lsn on inactive timeline").into());
+        }
+
+        let wait = self.last_record_lsn.wait_for_timeout(
+            RecordLsn {
+                last: lsn,
+                prev: Lsn::INVALID,
+            },
+            timeout,
+        );
+        tokio::select! {
+            res = wait => match res {
+                Ok(()) => {}
+                Err(SeqWaitError::Timeout) => {
+                    return Err(WaitLsnDurableError::Timeout {
+                        lsn,
+                        last_record_lsn: self.get_last_record_lsn(),
+                    })
+                }
+                Err(SeqWaitError::Shutdown) => {
+                    return Err(anyhow!("timeline is shutting down").into())
+                }
+            },
+            _ = cancel.cancelled() => return Err(WaitLsnDurableError::Cancelled),
+        }
+
+        if self.get_disk_consistent_lsn() < lsn {
+            self.freeze_and_flush().await?;
+        }
+        Ok(())
+    }
+
+    /// How far the ingested WAL is behind the latest commit LSN known from the
+    /// safekeepers, in bytes. `None` if the WAL receiver is not running or
+    /// hasn't heard from any safekeeper yet.
+    pub fn wal_ingest_lag(&self) -> Option<u64> {
+        let commit_lsn = self
+            .walreceiver
+            .lock()
+            .unwrap()
+            .as_ref()?
+            .status()?
+            .latest_commit_lsn()?;
+        Some(commit_lsn.0.saturating_sub(self.get_last_record_lsn().0))
+    }
+
+    /// When the timeline was created, judging by the creation time of its local
+    /// directory. For a timeline downloaded from remote storage, that is when
+    /// it was downloaded. `None` if the filesystem doesn't record it.
+    pub fn created_at(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.conf.timeline_path(&self.tenant_id, &self.timeline_id))
+            .and_then(|metadata| metadata.created())
+            .ok()
+    }
+
     /// Check that it is valid to request operations with that lsn.
     pub fn check_lsn_is_in_scope(
         &self,
@@ -701,7 +974,10 @@ impl Timeline {
             }
 
             let rls = match res {
-                Ok(()) => return Ok(()),
+                Ok(()) => {
+                    self.update_layer_count_metrics().await;
+                    return Ok(());
+                }
                 Err(CompactionError::DownloadRequired(rls)) if !last_round => {
                     // this can be done at most one time before exiting, waiting
                     rls
@@ -826,12 +1102,17 @@ impl Timeline {
                         remote_client.schedule_layer_file_upload(&path, &layer_metadata)?;
                     }
                 }
+                self.read_heat.decay();
 
                 // 3. Compact
                 let timer = self.metrics.compact_time_histo.start_timer();
                 self.compact_level0(layer_removal_cs.clone(), target_file_size, ctx)
                     .await?;
                 timer.stop_and_record();
+
+                // 4. Rewrite or remove the layers of purged relations and databases
+                self.purge_dropped_layers(layer_removal_cs.clone(), ctx)
+                    .await?;
             }
             Err(err) => {
                 // no partitioning? This is normal, if the timeline was just created
@@ -1350,6 +1631,36 @@ impl Timeline {
             .unwrap_or(self.conf.default_tenant_conf.gc_feedback)
     }
 
+    pub(crate) fn get_physical_size_quota(&self) -> Option<u64> {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf
+            .physical_size_quota
+            .or(self.conf.default_tenant_conf.physical_size_quota)
+    }
+
+    pub(crate) fn get_wal_ingest_rate_limit(&self) -> Option<NonZeroU64> {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf
+            .wal_ingest_rate_limit
+            .or(self.conf.default_tenant_conf.wal_ingest_rate_limit)
+    }
+
+    fn get_hot_range_image_creation_threshold(&self) -> Option<NonZeroU64> {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf.hot_range_image_creation_threshold.or(self
+            .conf
+            .default_tenant_conf
+            .hot_range_image_creation_threshold)
+    }
+
+    pub(crate) fn get_max_regions(&self) -> u32 {
+        let tenant_conf = self.tenant_conf.read().unwrap();
+        tenant_conf
+            .max_regions
+            .unwrap_or(self.conf.default_tenant_conf.max_regions)
+            .get()
+    }
+
     pub(super) fn tenant_conf_updated(&self) {
         // NB: Most tenant conf options are read by background loops, so,
         // changes will automatically be picked up.
@@ -1377,6 +1688,7 @@ impl Timeline {
     pub(super) fn new(
         conf: &'static PageServerConf,
         tenant_conf: Arc<RwLock<TenantConfOpt>>,
+        throttle: Arc<TenantThrottle>,
         metadata: &TimelineMetadata,
         ancestor: Option<Arc<Timeline>>,
         timeline_id: TimelineId,
@@ -1407,10 +1719,12 @@ impl Timeline {
             let mut result = Timeline {
                 conf,
                 tenant_conf,
+                throttle,
                 myself: myself.clone(),
                 timeline_id,
                 tenant_id,
                 pg_version,
+                data_checksums_enabled: tokio::sync::OnceCell::new(),
                 layers: Arc::new(tokio::sync::RwLock::new(LayerManager::create())),
                 wanted_image_layers: Mutex::new(None),
 
@@ -1475,6 +1789,10 @@ impl Timeline {
 
                 last_received_wal: Mutex::new(None),
                 rel_size_cache: RwLock::new(HashMap::new()),
+                slow_getpage_log: Mutex::new(SlowGetPageLog::default()),
+                read_heat: ReadHeat::default(),
+                dropped_key_ranges: Mutex::new(Vec::new()),
+                purge_floor_lsn: AtomicLsn::new(metadata.purge_floor_lsn().0),
 
                 download_all_remote_layers_task_info: RwLock::new(None),
 
@@ -1575,6 +1893,12 @@ impl Timeline {
             .unwrap_or(self.conf.default_tenant_conf.max_lsn_wal_lag);
         drop(tenant_conf_guard);
 
+        // Timelines with a directory in the WAL archive ingest their WAL from there.
+        let wal_archive_dir = self
+            .conf
+            .timeline_wal_archive_path(&self.tenant_id, &self.timeline_id)
+            .filter(|path| path.is_dir());
+
         let mut guard = self.walreceiver.lock().unwrap();
         assert!(
             guard.is_none(),
@@ -1589,6 +1913,10 @@ impl Timeline {
                 auth_token: crate::config::SAFEKEEPER_AUTH_TOKEN.get().cloned(),
                 availability_zone: self.conf.availability_zone.clone(),
                 ingest_batch_size: self.conf.ingest_batch_size,
+                ingest_backpressure_lag: self.conf.ingest_backpressure_lag,
+                wal_archive_dir,
+                wal_compression: self.conf.wal_receiver_compression,
+                region: self.conf.wal_receiver_region,
             },
             broker_client,
             ctx,
@@ -1686,7 +2014,10 @@ impl Timeline {
 
                 total_physical_size += file_size;
                 loaded_layers.push(Arc::new(layer));
-            } else if fname == METADATA_FILE_NAME || fname.ends_with(".old") {
+            } else if fname == METADATA_FILE_NAME
+                || fname == WAL_RECEIVER_STATE_FILE_NAME
+                || fname.ends_with(".old")
+            {
                 // ignore these
             } else if remote_timeline_client::is_temp_download_file(&direntry_path) {
                 info!(
@@ -2324,13 +2655,15 @@ impl Timeline {
     ///
     /// This function takes the current timeline's locked LayerMap as an argument,
     /// so callers can avoid potential race conditions.
+    ///
+    /// Returns the number of layers visited.
     async fn get_reconstruct_data(
         &self,
         key: Key,
         request_lsn: Lsn,
         reconstruct_state: &mut ValueReconstructState,
         ctx: &RequestContext,
-    ) -> Result<(), PageReconstructError> {
+    ) -> Result<usize, PageReconstructError> {
         // Start from the current timeline.
         let mut timeline_owned;
         let mut timeline = self;
@@ -2361,12 +2694,12 @@ impl Timeline {
             // The function should have updated 'state'
             //info!("CALLED for {} at {}: {:?} with {} records, cached {}", key, cont_lsn, result, reconstruct_state.records.len(), cached_lsn);
             match result {
-                ValueReconstructResult::Complete => return Ok(()),
+                ValueReconstructResult::Complete => return Ok(traversal_path.len()),
                 ValueReconstructResult::Continue => {
                     // If we reached an earlier cached page image, we're done.
                     if cont_lsn == cached_lsn + 1 {
                         MATERIALIZED_PAGE_CACHE_HIT.inc_by(1);
-                        return Ok(());
+                        return Ok(traversal_path.len());
                     }
                     if prev_lsn <= cont_lsn {
                         // Didn't make any progress in last iteration. Error out to avoid
@@ -2632,8 +2965,13 @@ impl Timeline {
 
         // FIXME: It's pointless to check the cache for things that are not 8kB pages.
         // We should look at the key to determine if it's a cacheable object
-        let (lsn, read_guard) =
-            cache.lookup_materialized_page(self.tenant_id, self.timeline_id, key, lsn)?;
+        let (lsn, read_guard) = cache.lookup_materialized_page(
+            self.tenant_id,
+            self.timeline_id,
+            key,
+            lsn,
+            &self.metrics.page_cache,
+        )?;
         let img = Bytes::from(read_guard.to_vec());
         Some((lsn, img))
     }
@@ -2813,6 +3151,10 @@ impl Timeline {
         frozen_layer: Arc<InMemoryLayer>,
         ctx: &RequestContext,
     ) -> anyhow::Result<()> {
+        fail_point!("before-flush-layer", |_| bail!(
+            "failpoint before-flush-layer"
+        ));
+
         // As a special case, when we have just imported an image into the repository,
         // instead of writing out a L0 delta layer, we directly write out image layer
         // files instead. This is possible as long as *all* the data imported into the
@@ -2920,6 +3262,14 @@ impl Timeline {
             // Also update the in-memory copy
             self.disk_consistent_lsn.store(disk_consistent_lsn);
         }
+
+        // The layer is on disk and covered by the metadata file now.
+        fail_point!("after-flush-layer", |_| bail!(
+            "failpoint after-flush-layer"
+        ));
+
+        self.update_layer_count_metrics().await;
+        crate::metrics::record_background_task_completion("checkpoint");
         Ok(())
     }
 
@@ -2959,6 +3309,7 @@ impl Timeline {
             self.initdb_lsn,
             self.pg_version,
             self.region_id,
+            self.purge_floor_lsn.load(),
         );
 
         fail_point!("checkpoint-before-saving-metadata", |x| bail!(
@@ -3145,6 +3496,31 @@ impl Timeline {
         Ok(false)
     }
 
+    // Is it time to create a new image layer for the given partition, that has
+    // hot key ranges? It is as soon as there's any delta layer over the last
+    // image of the partition.
+    async fn time_for_hot_image_layer(
+        &self,
+        partition: &KeySpace,
+        lsn: Lsn,
+    ) -> anyhow::Result<bool> {
+        let guard = self.layers.read().await;
+        let layers = guard.layer_map();
+        for part_range in &partition.ranges {
+            for (img_range, last_img) in layers.image_coverage(part_range, lsn)? {
+                let img_lsn = if let Some(last_img) = last_img {
+                    last_img.get_lsn_range().end
+                } else {
+                    Lsn(0)
+                };
+                if img_lsn < lsn && layers.count_deltas(&img_range, &(img_lsn..lsn), Some(1))? > 0 {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
     async fn create_image_layers(
         &self,
         partitioning: &KeyPartitioning,
@@ -3166,10 +3542,37 @@ impl Timeline {
         // image layers  <100000000..100000099> and <200000000..200000199> are not completely covering it.
         let mut start = Key::MIN;
 
+        // The key ranges that computes read often through WAL redo get their
+        // image layers early, see `read_heat`.
+        let hot_ranges = if force {
+            KeySpace::default()
+        } else {
+            let (hot_ranges, count) = self
+                .get_hot_range_image_creation_threshold()
+                .map(|threshold| self.read_heat.hot_ranges(threshold.get()))
+                .unwrap_or_default();
+            self.metrics.read_heat_hot_ranges_gauge.set(count as u64);
+            hot_ranges
+        };
+
         for partition in partitioning.parts.iter() {
             let img_range = start..partition.ranges.last().unwrap().end;
             start = img_range.end;
-            if force || self.time_for_new_image_layer(partition, lsn).await? {
+            let create = if force || self.time_for_new_image_layer(partition, lsn).await? {
+                true
+            } else if hot_ranges.overlaps(&img_range)
+                && self.time_for_hot_image_layer(partition, lsn).await?
+            {
+                debug!(
+                    "creating image layer {}-{} early for hot key ranges",
+                    img_range.start, img_range.end
+                );
+                self.metrics.read_heat_image_layers.inc();
+                true
+            } else {
+                false
+            };
+            if create {
                 let mut image_layer_writer = ImageLayerWriter::new(
                     self.conf,
                     self.timeline_id,
@@ -3219,6 +3622,7 @@ impl Timeline {
                 }
                 let image_layer = image_layer_writer.finish()?;
                 image_layers.push(image_layer);
+                self.read_heat.cool_down(&img_range);
             }
         }
         // All layers that the GC wanted us to create have now been created.
@@ -3454,8 +3858,18 @@ impl Timeline {
             .map(|x| guard.get_from_desc(&x))
             .collect_vec();
         stats.level0_deltas_count = Some(level0_deltas.len());
-        // Only compact if enough layers have accumulated.
-        let threshold = self.get_compaction_threshold();
+        // Only compact if enough layers have accumulated, or if some may have
+        // page versions of purged relations.
+        let dropped = self.dropped_key_ranges();
+        let threshold = if dropped.iter().any(|d| {
+            level0_deltas
+                .iter()
+                .any(|l| l.layer_desc().lsn_range.start < d.lsn)
+        }) {
+            1
+        } else {
+            self.get_compaction_threshold()
+        };
         if level0_deltas.is_empty() || level0_deltas.len() < threshold {
             debug!(
                 level0_deltas = level0_deltas.len(),
@@ -3602,6 +4016,13 @@ impl Timeline {
         // particularly fast where the slice is made up of sorted sub-ranges.
         all_keys.sort_by_key(|(key, lsn, _size)| (*key, *lsn));
 
+        // Leave out the page versions of purged relations.
+        if !dropped.is_empty() {
+            all_value_refs
+                .retain(|(key, lsn, _value_ref)| !purge::is_dropped(&dropped, *key, *lsn));
+            all_keys.retain(|(key, lsn, _size)| !purge::is_dropped(&dropped, *key, *lsn));
+        }
+
         for (next_key, _next_lsn, _size) in all_keys.iter() {
             let next_key = *next_key;
             if let Some(prev_key) = prev {
@@ -4111,6 +4532,7 @@ impl Timeline {
                 pitr_cutoff,
                 retain_lsns,
                 new_gc_cutoff,
+                new_gc_cutoff,
             )
             .instrument(
                 info_span!("gc_timeline", timeline_id = %self.timeline_id, cutoff = %new_gc_cutoff),
@@ -4119,10 +4541,51 @@ impl Timeline {
 
         // only record successes
         timer.stop_and_record();
+        self.update_layer_count_metrics().await;
 
         Ok(res)
     }
 
+    /// What the next GC of the timeline would do with each of its layers,
+    /// with the cutoffs of the last [`Timeline::update_gc_info`]. Removes
+    /// nothing.
+    pub(crate) async fn explain_gc(&self) -> anyhow::Result<Vec<LayerGcExplanation>> {
+        let (horizon_cutoff, pitr_cutoff, retain_lsns) = {
+            let gc_info = self.gc_info.read().unwrap();
+            let horizon_cutoff = min(gc_info.horizon_cutoff, self.get_disk_consistent_lsn());
+            (
+                horizon_cutoff,
+                gc_info.pitr_cutoff,
+                gc_info.retain_lsns.clone(),
+            )
+        };
+        let image_cutoff = Lsn::min(horizon_cutoff, pitr_cutoff);
+
+        let guard = self.layers.read().await;
+        let layers = guard.layer_map();
+        let mut explanations = Vec::new();
+        for l in layers.iter_historic_layers() {
+            let verdict = gc_verdict(
+                layers,
+                &l,
+                horizon_cutoff,
+                pitr_cutoff,
+                &retain_lsns,
+                image_cutoff,
+            )?;
+            let lsn_range = l.get_lsn_range();
+            explanations.push(LayerGcExplanation {
+                layer_file_name: l.filename().file_name(),
+                lsn_start: lsn_range.start,
+                lsn_end: lsn_range.end,
+                verdict,
+            });
+        }
+        Ok(explanations)
+    }
+
+    /// Layers are only removed if newer image layers, before `image_cutoff`,
+    /// cover their whole key range.
     async fn gc_timeline(
         &self,
         layer_removal_cs: Arc<tokio::sync::OwnedMutexGuard<()>>,
@@ -4130,6 +4593,7 @@ impl Timeline {
         pitr_cutoff: Lsn,
         retain_lsns: Vec<Lsn>,
         new_gc_cutoff: Lsn,
+        image_cutoff: Lsn,
     ) -> anyhow::Result<GcResult> {
         let now = SystemTime::now();
         let mut result: GcResult = GcResult::default();
@@ -4177,106 +4641,71 @@ impl Timeline {
         let mut layers_to_remove = Vec::new();
         let mut wanted_image_layers = KeySpaceRandomAccum::default();
 
-        // Scan all layers in the timeline (remote or on-disk).
-        //
-        // Garbage collect the layer if all conditions are satisfied:
-        // 1. it is older than cutoff LSN;
-        // 2. it is older than PITR interval;
-        // 3. it doesn't need to be retained for 'retain_lsns';
-        // 4. newer on-disk image layers cover the layer's whole key range
+        // Scan all layers in the timeline (remote or on-disk), and garbage
+        // collect the ones that nothing needs anymore, see `gc_verdict`.
         //
         // TODO holding a write lock is too agressive and avoidable
         let mut guard = self.layers.write().await;
         let layers = guard.layer_map();
-        'outer: for l in layers.iter_historic_layers() {
+        for l in layers.iter_historic_layers() {
             result.layers_total += 1;
 
-            // 1. Is it newer than GC horizon cutoff point?
-            if l.get_lsn_range().end > horizon_cutoff {
-                debug!(
-                    "keeping {} because it's newer than horizon_cutoff {}",
-                    l.filename(),
-                    horizon_cutoff,
-                );
-                result.layers_needed_by_cutoff += 1;
-                continue 'outer;
-            }
-
-            // 2. It is newer than PiTR cutoff point?
-            if l.get_lsn_range().end > pitr_cutoff {
-                debug!(
-                    "keeping {} because it's newer than pitr_cutoff {}",
-                    l.filename(),
-                    pitr_cutoff,
-                );
-                result.layers_needed_by_pitr += 1;
-                continue 'outer;
-            }
-
-            // 3. Is it needed by a child branch?
-            // NOTE With that we would keep data that
-            // might be referenced by child branches forever.
-            // We can track this in child timeline GC and delete parent layers when
-            // they are no longer needed. This might be complicated with long inheritance chains.
-            //
-            // TODO Vec is not a great choice for `retain_lsns`
-            for retain_lsn in &retain_lsns {
-                // start_lsn is inclusive
-                if &l.get_lsn_range().start <= retain_lsn {
+            let verdict = gc_verdict(
+                layers,
+                &l,
+                horizon_cutoff,
+                pitr_cutoff,
+                &retain_lsns,
+                image_cutoff,
+            )?;
+            match verdict {
+                LayerGcVerdict::NewerThanHorizonCutoff => {
+                    debug!(
+                        "keeping {} because it's newer than horizon_cutoff {}",
+                        l.filename(),
+                        horizon_cutoff,
+                    );
+                    result.layers_needed_by_cutoff += 1;
+                }
+                LayerGcVerdict::NewerThanPitrCutoff => {
+                    debug!(
+                        "keeping {} because it's newer than pitr_cutoff {}",
+                        l.filename(),
+                        pitr_cutoff,
+                    );
+                    result.layers_needed_by_pitr += 1;
+                }
+                LayerGcVerdict::NeededByBranch { branch_lsn } => {
                     debug!(
                         "keeping {} because it's still might be referenced by child branch forked at {} is_dropped: xx is_incremental: {}",
                         l.filename(),
-                        retain_lsn,
+                        branch_lsn,
                         l.is_incremental(),
                     );
                     result.layers_needed_by_branches += 1;
-                    continue 'outer;
                 }
-            }
-
-            // 4. Is there a later on-disk layer for this relation?
-            //
-            // The end-LSN is exclusive, while disk_consistent_lsn is
-            // inclusive. For example, if disk_consistent_lsn is 100, it is
-            // OK for a delta layer to have end LSN 101, but if the end LSN
-            // is 102, then it might not have been fully flushed to disk
-            // before crash.
-            //
-            // For example, imagine that the following layers exist:
-            //
-            // 1000      - image (A)
-            // 1000-2000 - delta (B)
-            // 2000      - image (C)
-            // 2000-3000 - delta (D)
-            // 3000      - image (E)
-            //
-            // If GC horizon is at 2500, we can remove layers A and B, but
-            // we cannot remove C, even though it's older than 2500, because
-            // the delta layer 2000-3000 depends on it.
-            if !layers
-                .image_layer_exists(&l.get_key_range(), &(l.get_lsn_range().end..new_gc_cutoff))?
-            {
-                debug!("keeping {} because it is the latest layer", l.filename());
-                // Collect delta key ranges that need image layers to allow garbage
-                // collecting the layers.
-                // It is not so obvious whether we need to propagate information only about
-                // delta layers. Image layers can form "stairs" preventing old image from been deleted.
-                // But image layers are in any case less sparse than delta layers. Also we need some
-                // protection from replacing recent image layers with new one after each GC iteration.
-                if self.get_gc_feedback() && l.is_incremental() && !LayerMap::is_l0(&l) {
-                    wanted_image_layers.add_range(l.get_key_range());
+                LayerGcVerdict::NotCoveredByImageLayers => {
+                    debug!("keeping {} because it is the latest layer", l.filename());
+                    // Collect delta key ranges that need image layers to allow garbage
+                    // collecting the layers.
+                    // It is not so obvious whether we need to propagate information only about
+                    // delta layers. Image layers can form "stairs" preventing old image from been deleted.
+                    // But image layers are in any case less sparse than delta layers. Also we need some
+                    // protection from replacing recent image layers with new one after each GC iteration.
+                    if self.get_gc_feedback() && l.is_incremental() && !LayerMap::is_l0(&l) {
+                        wanted_image_layers.add_range(l.get_key_range());
+                    }
+                    result.layers_not_updated += 1;
+                }
+                LayerGcVerdict::Removable => {
+                    debug!(
+                        "garbage collecting {} is_dropped: xx is_incremental: {}",
+                        l.filename(),
+                        l.is_incremental(),
+                    );
+                    layers_to_remove.push(Arc::clone(&l));
                 }
-                result.layers_not_updated += 1;
-                continue 'outer;
             }
-
-            // We didn't find any reason to keep this file, so remove it.
-            debug!(
-                "garbage collecting {} is_dropped: xx is_incremental: {}",
-                l.filename(),
-                l.is_incremental(),
-            );
-            layers_to_remove.push(Arc::clone(&l));
         }
         self.wanted_image_layers
             .lock()
@@ -4377,7 +4806,14 @@ impl Timeline {
 
                 let img = match self
                     .walredo_mgr
-                    .request_redo(key, request_lsn, data.img, data.records, self.pg_version)
+                    .request_redo(
+                        key,
+                        request_lsn,
+                        data.img,
+                        data.records,
+                        self.pg_version,
+                        self.get_max_regions(),
+                    )
                     .context("Failed to reconstruct a page image:")
                 {
                     Ok(img) => img,
@@ -4900,15 +5336,21 @@ pub fn compare_arced_layers<L: ?Sized>(left: &Arc<L>, right: &Arc<L>) -> bool {
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
+    use std::time::Duration;
 
+    use tokio_util::sync::CancellationToken;
     use utils::{
         id::{RegionId, TimelineId},
-        lsn::Lsn,
+        lsn::{Lsn, RecordLsn},
     };
 
-    use crate::tenant::{harness::TenantHarness, storage_layer::PersistentLayer};
+    use crate::repository::{Key, Value};
+    use crate::tenant::{
+        harness::{TenantHarness, TEST_IMG},
+        storage_layer::PersistentLayer,
+    };
 
-    use super::{EvictionError, Timeline};
+    use super::{EvictionError, Timeline, WaitLsnDurableError};
 
     #[tokio::test]
     async fn two_layer_eviction_attempts_at_the_same_time() {
@@ -5050,6 +5492,54 @@ mod tests {
         // failure
     }
 
+    #[tokio::test]
+    async fn wait_lsn_durable_flushes() {
+        let harness = TenantHarness::create("wait_lsn_durable_flushes").unwrap();
+        let ctx = any_context();
+        let tenant = harness.try_load(&ctx, None).await.unwrap();
+        let timeline = tenant
+            .create_test_timeline(TimelineId::generate(), Lsn(0x10), 14, RegionId(0), &ctx)
+            .await
+            .unwrap();
+        let cancel = CancellationToken::new();
+
+        let key = Key::from_hex("112222222233333333444444445500000001").unwrap();
+        let writer = timeline.writer().await;
+        writer
+            .put(key, Lsn(0x20), &Value::Image(TEST_IMG("foo at 0x20")))
+            .await
+            .unwrap();
+        writer.finish_write(RecordLsn {
+            last: Lsn(0x20),
+            prev: Lsn::INVALID,
+        });
+        drop(writer);
+        assert!(timeline.get_disk_consistent_lsn() < Lsn(0x20));
+
+        timeline
+            .wait_lsn_durable(Lsn(0x20), Duration::from_secs(10), &cancel)
+            .await
+            .unwrap();
+        assert_eq!(timeline.get_disk_consistent_lsn(), Lsn(0x20));
+
+        let res = timeline
+            .wait_lsn_durable(Lsn(0x30), Duration::from_millis(10), &cancel)
+            .await;
+        assert!(
+            matches!(res, Err(WaitLsnDurableError::Timeout { .. })),
+            "{res:?}"
+        );
+
+        cancel.cancel();
+        let res = timeline
+            .wait_lsn_durable(Lsn(0x30), Duration::from_secs(10), &cancel)
+            .await;
+        assert!(
+            matches!(res, Err(WaitLsnDurableError::Cancelled)),
+            "{res:?}"
+        );
+    }
+
     fn any_context() -> crate::context::RequestContext {
         use crate::context::*;
         use crate::task_mgr::*;
@@ -0,0 +1,320 @@
+//! Export of a timeline into a tar archive of its metadata and layer files, and
+//! import of such an archive into a tenant, behind the timeline archive API.
+//!
+//! This copies a timeline between pageservers, e.g. between the machines of two
+//! developers, without replaying its WAL. The archive holds the `metadata` file
+//! followed by the layer files, under their names. Only the layers of the
+//! timeline itself are exported: a branch can only be imported into a tenant
+//! that already has its ancestor timeline.
+//!
+//! The ids of the tenant and timeline are not part of the archive, the layer
+//! files are rewritten to the ids the archive is imported under.
+//!
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context};
+use futures::StreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_tar::{Archive, Builder, EntryType, Header};
+use tracing::*;
+use utils::crashsafe;
+use utils::id::TimelineId;
+
+use super::uninit::cleanup_timeline_directory;
+use super::Timeline;
+use crate::context::RequestContext;
+use crate::tenant::metadata::{load_metadata, save_metadata, TimelineMetadata};
+use crate::tenant::remote_timeline_client::RemoteTimelineClient;
+use crate::tenant::storage_layer::{
+    AsLayerDesc, DeltaFileName, DeltaLayer, ImageFileName, ImageLayer, PersistentLayer,
+};
+use crate::tenant::Tenant;
+use crate::METADATA_FILE_NAME;
+
+impl Timeline {
+    /// Writes the timeline archive into `writer`, downloading the evicted layers
+    /// first.
+    ///
+    /// Compaction, GC and eviction of the timeline wait until the archive is
+    /// written, so that its layer files stay in place.
+    pub(crate) async fn export_archive(
+        &self,
+        writer: impl AsyncWrite + Unpin + Send,
+        _ctx: &RequestContext,
+    ) -> anyhow::Result<()> {
+        let _layer_removal_guard = self.layer_removal_cs.lock().await;
+
+        let layers: Vec<Arc<dyn PersistentLayer>> = {
+            let guard = self.layers.read().await;
+            guard
+                .layer_map()
+                .iter_historic_layers()
+                .map(|desc| guard.get_from_desc(&desc))
+                .collect()
+        };
+        for layer in layers {
+            if let Some(remote_layer) = layer.downcast_remote_layer() {
+                ensure!(
+                    self.remote_client.is_some(),
+                    "layer {remote_layer} is evicted, but remote storage is not configured"
+                );
+                self.download_remote_layer(remote_layer).await?;
+            }
+        }
+
+        // Layers are added to the layer map before the metadata is updated on
+        // flush, so the layers found after loading the metadata cover all of
+        // it. The ones above its disk_consistent_lsn are left out, the import
+        // would not load them.
+        let metadata = load_metadata(self.conf, &self.tenant_id, &self.timeline_id)
+            .context("load timeline metadata")?;
+        let disk_consistent_lsn = metadata.disk_consistent_lsn();
+        let layers: Vec<Arc<dyn PersistentLayer>> = {
+            let guard = self.layers.read().await;
+            guard
+                .layer_map()
+                .iter_historic_layers()
+                .filter(|desc| desc.lsn_range.end <= disk_consistent_lsn + 1)
+                .map(|desc| guard.get_from_desc(&desc))
+                .collect()
+        };
+
+        let mut builder = Builder::new(writer);
+        let metadata_bytes = metadata.to_bytes().context("serialize timeline metadata")?;
+        let mut header = Header::new_gnu();
+        header.set_size(metadata_bytes.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, METADATA_FILE_NAME, metadata_bytes.as_slice())
+            .await?;
+
+        let mut exported_size = 0;
+        for layer in &layers {
+            let file_name = layer.filename().file_name();
+            let path = layer
+                .local_path()
+                .with_context(|| format!("layer {file_name} got evicted during the export"))?;
+            builder
+                .append_path_with_name(&path, &file_name)
+                .await
+                .with_context(|| format!("add layer {file_name} to the archive"))?;
+            exported_size += layer.layer_desc().file_size();
+        }
+
+        let mut writer = builder.into_inner().await?;
+        writer.shutdown().await?;
+
+        info!(
+            "exported {} layers of {exported_size} bytes at {disk_consistent_lsn}",
+            layers.len()
+        );
+        Ok(())
+    }
+}
+
+impl Tenant {
+    /// Creates the timeline `timeline_id` from a timeline archive read from
+    /// `reader`, uploading its layers to the remote storage.
+    ///
+    /// The caller is responsible for activating the returned timeline.
+    pub(crate) async fn import_timeline_archive(
+        &self,
+        timeline_id: TimelineId,
+        reader: impl AsyncRead + Unpin + Send,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Arc<Timeline>> {
+        ensure!(
+            self.is_active(),
+            "Cannot import timelines into inactive tenant"
+        );
+
+        let uninit_mark = {
+            let timelines = self.timelines.lock().unwrap();
+            self.create_timeline_uninit_mark(timeline_id, &timelines)?
+        };
+
+        let metadata = match self
+            .unpack_timeline_archive(timeline_id, &uninit_mark.timeline_path, reader)
+            .await
+        {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                cleanup_timeline_directory(uninit_mark);
+                return Err(e.context("unpack timeline archive"));
+            }
+        };
+
+        let ancestor = match metadata.ancestor_timeline() {
+            Some(ancestor_timeline_id) => match self.get_timeline(ancestor_timeline_id, false) {
+                Ok(ancestor) => Some(ancestor),
+                Err(_) => {
+                    cleanup_timeline_directory(uninit_mark);
+                    bail!("ancestor timeline {ancestor_timeline_id} must be imported first");
+                }
+            },
+            None => None,
+        };
+
+        let remote_client = self.remote_storage.as_ref().map(|remote_storage| {
+            RemoteTimelineClient::new(
+                remote_storage.clone(),
+                self.conf,
+                self.tenant_id,
+                timeline_id,
+            )
+        });
+        if let Err(e) = self
+            .timeline_init_and_sync(
+                timeline_id,
+                remote_client,
+                None,
+                Some(metadata),
+                ancestor,
+                false,
+                None,
+                ctx,
+            )
+            .await
+        {
+            // Once in the timelines map, the timeline stays there, and the
+            // uninit mark gets it removed on restart.
+            if self.get_timeline(timeline_id, false).is_err() {
+                cleanup_timeline_directory(uninit_mark);
+            }
+            return Err(e);
+        }
+
+        uninit_mark
+            .remove_uninit_mark()
+            .context("remove uninit mark of imported timeline")?;
+        let timeline = self.get_timeline(timeline_id, false)?;
+        info!(
+            "imported timeline {timeline_id} at {}",
+            timeline.get_disk_consistent_lsn()
+        );
+        Ok(timeline)
+    }
+
+    /// Unpacks the timeline archive into the new timeline directory, returning
+    /// its metadata, which is also saved.
+    async fn unpack_timeline_archive(
+        &self,
+        timeline_id: TimelineId,
+        timeline_path: &Path,
+        reader: impl AsyncRead + Unpin + Send,
+    ) -> anyhow::Result<TimelineMetadata> {
+        crashsafe::create_dir(timeline_path).context("create timeline directory")?;
+
+        let mut metadata = None;
+        let mut entries = Archive::new(reader).entries()?;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.into_owned();
+            // Layer and metadata file names have no directories in them, so this
+            // also keeps the files within the timeline directory.
+            let file_name = entry_path.to_string_lossy().into_owned();
+            ensure!(
+                entry.header().entry_type() == EntryType::Regular,
+                "entry {file_name} of the archive is not a file"
+            );
+
+            if file_name == METADATA_FILE_NAME {
+                let mut metadata_bytes = Vec::new();
+                entry.read_to_end(&mut metadata_bytes).await?;
+                metadata = Some(
+                    TimelineMetadata::from_bytes(&metadata_bytes)
+                        .context("parse timeline metadata")?,
+                );
+                continue;
+            }
+
+            let is_image = if ImageFileName::parse_str(&file_name).is_some() {
+                true
+            } else if DeltaFileName::parse_str(&file_name).is_some() {
+                false
+            } else {
+                bail!("unexpected file {file_name} in the archive");
+            };
+
+            let path = timeline_path.join(&file_name);
+            let mut file = tokio::fs::File::create(&path).await?;
+            tokio::io::copy(&mut entry, &mut file)
+                .await
+                .with_context(|| format!("unpack layer {file_name}"))?;
+            drop(file);
+
+            if is_image {
+                ImageLayer::rewrite_summary_ids(&path, self.tenant_id, timeline_id)
+            } else {
+                DeltaLayer::rewrite_summary_ids(&path, self.tenant_id, timeline_id)
+            }
+            .with_context(|| format!("rewrite summary of layer {file_name}"))?;
+        }
+
+        let metadata = metadata.context("no metadata in the archive")?;
+        save_metadata(self.conf, &self.tenant_id, &timeline_id, &metadata, true)
+            .context("save timeline metadata")?;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::id::RegionId;
+    use utils::lsn::{Lsn, RecordLsn};
+
+    use crate::repository::{Key, Value};
+    use crate::tenant::harness::{TenantHarness, NEW_TIMELINE_ID, TEST_IMG, TIMELINE_ID};
+    use crate::DEFAULT_PG_VERSION;
+    use pageserver_api::models::TimelineState;
+
+    #[tokio::test]
+    async fn export_import_roundtrip() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("export_import_roundtrip")?
+            .load()
+            .await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        let key = Key::from_hex("112222222233333333444444445500000001")?;
+        let writer = tline.writer().await;
+        writer
+            .put(key, Lsn(0x10), &Value::Image(TEST_IMG("foo at 0x10")))
+            .await?;
+        writer.finish_write(RecordLsn {
+            last: Lsn(0x10),
+            prev: Lsn::INVALID,
+        });
+        drop(writer);
+        tline.freeze_and_flush().await?;
+
+        let mut archive = Vec::new();
+        tline.export_archive(&mut archive, &ctx).await?;
+
+        let imported = tenant
+            .import_timeline_archive(NEW_TIMELINE_ID, archive.as_slice(), &ctx)
+            .await?;
+        imported.set_state(TimelineState::Active);
+        assert_eq!(imported.get_disk_consistent_lsn(), Lsn(0x10));
+        assert_eq!(
+            imported.get(key, Lsn(0x10), &ctx).await?,
+            TEST_IMG("foo at 0x10")
+        );
+
+        // The archive cannot be imported under the id of an existing timeline.
+        assert!(tenant
+            .import_timeline_archive(TIMELINE_ID, archive.as_slice(), &ctx)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
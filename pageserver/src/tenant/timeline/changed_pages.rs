@@ -0,0 +1,321 @@
+//! Iteration over the relation blocks of a timeline that changed in an LSN
+//! range, behind the changed pages API, for incremental backup tools.
+//!
+//! The changes are read from the delta layers overlapping the LSN range, in
+//! key order, so the API pages through them with a cursor: the key of the last
+//! page returned. Each page comes either with its image at the end of the
+//! range, or with its changes in the range, oldest first.
+//!
+//! Only relation blocks are reported. Relation sizes, and the relations
+//! dropped or truncated in the range, are left to the tools to compare
+//! between the two LSNs.
+//!
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use pageserver_api::models::{ChangedPage, ChangedPagesFormat, ChangedPagesResponse, PageDelta};
+use tracing::*;
+use utils::lsn::Lsn;
+
+use super::Timeline;
+use crate::context::RequestContext;
+use crate::pgdatadir_mapping::{is_rel_block_key, key_to_rel_block};
+use crate::repository::{Key, Value};
+use crate::tenant::storage_layer::delta_layer::DeltaLayerInner;
+use crate::tenant::storage_layer::{range_overlaps, PersistentLayer, ValueRef};
+use crate::walrecord::NeonWalRecord;
+
+impl Timeline {
+    /// Returns the relation blocks changed in `(from_lsn, to_lsn]`, in key
+    /// order, starting after the key `after`, up to `limit` of them.
+    ///
+    /// The caller checks that the changes in the range are still there, i.e.
+    /// that `from_lsn` is not behind the GC cutoff nor the ancestor LSN. The
+    /// WAL up to `to_lsn` is flushed first if needed.
+    pub(crate) async fn changed_pages(
+        &self,
+        from_lsn: Lsn,
+        to_lsn: Lsn,
+        after: Option<Key>,
+        limit: usize,
+        format: ChangedPagesFormat,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<ChangedPagesResponse> {
+        ensure!(limit > 0, "limit must be > 0");
+        if self.get_disk_consistent_lsn() < to_lsn {
+            self.freeze_and_flush().await?;
+        }
+
+        let start_key = after.map(|key| key.next()).unwrap_or(Key::MIN);
+        let mut changes = self
+            .changed_keys(
+                start_key..Key::MAX,
+                from_lsn + 1..to_lsn + 1,
+                limit + 1,
+                ctx,
+            )
+            .await?;
+        // One more key than asked for tells if there are more.
+        let next_cursor = if changes.len() > limit {
+            changes.pop_last();
+            changes.last_key_value().map(|(key, _)| key.to_string())
+        } else {
+            None
+        };
+
+        let mut pages = Vec::with_capacity(changes.len());
+        for (key, changes) in changes {
+            let (rel, blkno) = key_to_rel_block(key)?;
+            let lsn = changes.last().map(|(lsn, _)| *lsn).expect("changed key");
+            let (image, deltas) = match format {
+                ChangedPagesFormat::Image => {
+                    let image = self.get(key, to_lsn, ctx).await?;
+                    (Some(hex::encode(image)), None)
+                }
+                ChangedPagesFormat::Delta => {
+                    let mut deltas = Vec::with_capacity(changes.len());
+                    for (lsn, value_ref) in changes {
+                        let value = value_ref.load().await?;
+                        deltas.push(self.page_delta(key, lsn, value, ctx).await?);
+                    }
+                    (None, Some(deltas))
+                }
+            };
+            pages.push(ChangedPage {
+                rel,
+                blkno,
+                lsn,
+                image,
+                deltas,
+            });
+        }
+
+        Ok(ChangedPagesResponse { pages, next_cursor })
+    }
+
+    /// Collects the changes of the first `max_keys` relation blocks in
+    /// `key_range` changed in `lsn_range`, from the delta layers.
+    async fn changed_keys(
+        &self,
+        key_range: Range<Key>,
+        lsn_range: Range<Lsn>,
+        max_keys: usize,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<BTreeMap<Key, Vec<(Lsn, ValueRef<Arc<DeltaLayerInner>>)>>> {
+        // Keep compaction and GC from removing the layers while they are read.
+        let _layer_removal_guard = self.layer_removal_cs.lock().await;
+
+        for layer in self.overlapping_delta_layers(&key_range, &lsn_range).await {
+            if let Some(remote_layer) = layer.downcast_remote_layer() {
+                ensure!(
+                    self.remote_client.is_some(),
+                    "layer {remote_layer} is evicted, but remote storage is not configured"
+                );
+                self.download_remote_layer(remote_layer).await?;
+            }
+        }
+
+        let mut changes: BTreeMap<Key, Vec<(Lsn, ValueRef<_>)>> = BTreeMap::new();
+        for layer in self.overlapping_delta_layers(&key_range, &lsn_range).await {
+            let delta = layer
+                .clone()
+                .downcast_delta_layer()
+                .with_context(|| format!("layer {layer} is not a delta layer"))?;
+
+            for (key, lsn, value_ref) in delta.load_val_refs(ctx).await? {
+                // Past the keys collected so far, once there are enough.
+                if changes.len() >= max_keys
+                    && changes
+                        .last_key_value()
+                        .map_or(false, |(last, _)| key > *last)
+                {
+                    break;
+                }
+                // The blocks, not the sizes, of the relations.
+                if !key_range.contains(&key)
+                    || !lsn_range.contains(&lsn)
+                    || !is_rel_block_key(key)
+                    || key.field6 == 0xffffffff
+                {
+                    continue;
+                }
+                changes.entry(key).or_default().push((lsn, value_ref));
+                if changes.len() > max_keys {
+                    changes.pop_last();
+                }
+            }
+        }
+
+        for changes in changes.values_mut() {
+            changes.sort_by_key(|(lsn, _)| *lsn);
+        }
+        debug!(
+            "found {} changed keys in {key_range:?} at {lsn_range:?}",
+            changes.len()
+        );
+        Ok(changes)
+    }
+
+    async fn overlapping_delta_layers(
+        &self,
+        key_range: &Range<Key>,
+        lsn_range: &Range<Lsn>,
+    ) -> Vec<Arc<dyn PersistentLayer>> {
+        let guard = self.layers.read().await;
+        guard
+            .layer_map()
+            .iter_historic_layers()
+            .filter(|desc| {
+                desc.is_incremental()
+                    && range_overlaps(&desc.key_range, key_range)
+                    && range_overlaps(&desc.lsn_range, lsn_range)
+            })
+            .map(|desc| guard.get_from_desc(&desc))
+            .collect()
+    }
+
+    /// A change of the page for the API: the page image and the Postgres WAL
+    /// records as they are, and the page image after the other records, which
+    /// are specific to Neon.
+    async fn page_delta(
+        &self,
+        key: Key,
+        lsn: Lsn,
+        value: Value,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<PageDelta> {
+        Ok(match value {
+            Value::Image(image) => PageDelta {
+                lsn,
+                will_init: true,
+                image: Some(hex::encode(image)),
+                wal_record: None,
+            },
+            Value::WalRecord(NeonWalRecord::Postgres { will_init, rec }) => PageDelta {
+                lsn,
+                will_init,
+                image: None,
+                wal_record: Some(hex::encode(rec)),
+            },
+            Value::WalRecord(_) => PageDelta {
+                lsn,
+                will_init: true,
+                image: Some(hex::encode(self.get(key, lsn, ctx).await?)),
+                wal_record: None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::id::RegionId;
+    use utils::lsn::RecordLsn;
+
+    use super::*;
+    use crate::tenant::harness::{TenantHarness, TEST_IMG, TIMELINE_ID};
+    use crate::DEFAULT_PG_VERSION;
+
+    fn rel_block_key(blkno: u32) -> Key {
+        Key {
+            field1: 0x00,
+            field2: 1663,
+            field3: 5,
+            field4: 16384,
+            field5: 0,
+            field6: blkno,
+        }
+    }
+
+    #[tokio::test]
+    async fn changed_pages_with_cursor() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("changed_pages_with_cursor")?
+            .load()
+            .await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        let writer = tline.writer().await;
+        for (lsn, blknos) in [(Lsn(0x10), 0..3), (Lsn(0x20), 1..3)] {
+            for blkno in blknos {
+                let img = TEST_IMG(&format!("block {blkno} at {lsn}"));
+                writer
+                    .put(rel_block_key(blkno), lsn, &Value::Image(img))
+                    .await?;
+            }
+            writer.finish_write(RecordLsn {
+                last: lsn,
+                prev: Lsn::INVALID,
+            });
+        }
+        drop(writer);
+
+        // One page at a time, only the blocks changed after 0x10.
+        let first = tline
+            .changed_pages(
+                Lsn(0x10),
+                Lsn(0x20),
+                None,
+                1,
+                ChangedPagesFormat::Delta,
+                &ctx,
+            )
+            .await?;
+        assert_eq!(first.pages.len(), 1);
+        assert_eq!(first.pages[0].blkno, 1);
+        let deltas = first.pages[0].deltas.as_ref().unwrap();
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].lsn, Lsn(0x20));
+        assert_eq!(
+            deltas[0].image,
+            Some(hex::encode(TEST_IMG(&format!("block 1 at {}", Lsn(0x20)))))
+        );
+        let after = Key::from_hex(first.next_cursor.as_ref().unwrap())?;
+        let second = tline
+            .changed_pages(
+                Lsn(0x10),
+                Lsn(0x20),
+                Some(after),
+                1,
+                ChangedPagesFormat::Delta,
+                &ctx,
+            )
+            .await?;
+        assert_eq!(second.pages.len(), 1);
+        assert_eq!(second.pages[0].blkno, 2);
+        assert!(second.next_cursor.is_none());
+
+        // All the blocks, with their images at the end of the range.
+        let all = tline
+            .changed_pages(
+                Lsn(0x08),
+                Lsn(0x20),
+                None,
+                10,
+                ChangedPagesFormat::Image,
+                &ctx,
+            )
+            .await?;
+        let blocks: Vec<_> = all
+            .pages
+            .iter()
+            .map(|page| (page.blkno, page.lsn))
+            .collect();
+        assert_eq!(blocks, [(0, Lsn(0x10)), (1, Lsn(0x20)), (2, Lsn(0x20))]);
+        assert_eq!(
+            all.pages[0].image,
+            Some(hex::encode(TEST_IMG(&format!("block 0 at {}", Lsn(0x10)))))
+        );
+        assert!(all.next_cursor.is_none());
+        Ok(())
+    }
+}
@@ -150,7 +150,13 @@ impl LayerManager {
                 lsn
             );
 
-            let new_layer = InMemoryLayer::create(conf, timeline_id, tenant_id, start_lsn)?;
+            let new_layer = InMemoryLayer::create(
+                conf,
+                timeline_id,
+                tenant_id,
+                start_lsn,
+                conf.ingest_parallelism,
+            )?;
             let layer = Arc::new(new_layer);
 
             self.layer_map.open_layer = Some(layer.clone());
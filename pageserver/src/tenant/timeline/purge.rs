@@ -0,0 +1,465 @@
+//! Purge of the history of dropped relations and databases, behind the purge
+//! API, for workloads that create and drop large tables.
+//!
+//! The page versions of a dropped relation stay in the layers until the GC
+//! horizon and PITR interval have passed the drop, like any other history.
+//! Once a relation or database is purged, the next compactions reclaim its
+//! page versions from before the purge LSN, where it no longer exists, right
+//! away:
+//! - L0 compaction leaves them out of the L1 layers it writes, and runs as
+//!   long as any L0 layer may have some, whatever `compaction_threshold`,
+//! - the image and L1 delta layers that have some are rewritten without them,
+//!   split around the purged key ranges, and the ones that only hold them are
+//!   removed.
+//!
+//! The purge LSN becomes the purge floor of the timeline, persisted in its
+//! metadata before any layer is touched: like before the GC cutoff, get_page
+//! requests, basebackups and branches at LSNs before it are refused. The
+//! purges pending in compaction are not persisted, one pending at restart is
+//! forgotten, and the API can be called again.
+//!
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use pageserver_api::models::{LayerResidenceEventReason, LayerResidenceStatus};
+use tracing::*;
+use utils::lsn::Lsn;
+
+use super::{drop_wlock, CompactionError, Timeline};
+use crate::context::RequestContext;
+use crate::repository::Key;
+use crate::tenant::layer_map::LayerMap;
+use crate::tenant::par_fsync;
+use crate::tenant::remote_timeline_client::index::LayerFileMetadata;
+use crate::tenant::storage_layer::{
+    AsLayerDesc, DeltaLayerWriter, ImageLayerWriter, PersistentLayer, PersistentLayerDesc,
+};
+
+/// The key range of a dropped relation or database, whose page versions
+/// before `lsn` are dead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DroppedKeyRange {
+    pub(crate) key_range: Range<Key>,
+    pub(crate) lsn: Lsn,
+}
+
+impl DroppedKeyRange {
+    fn contains(&self, key: Key, lsn: Lsn) -> bool {
+        self.key_range.contains(&key) && lsn < self.lsn
+    }
+
+    /// Does the layer only hold dead page versions?
+    fn covers(&self, layer: &PersistentLayerDesc) -> bool {
+        self.key_range.start <= layer.key_range.start
+            && layer.key_range.end <= self.key_range.end
+            && layer.lsn_range.end <= self.lsn
+    }
+
+    /// May the layer hold dead page versions?
+    fn overlaps(&self, layer: &PersistentLayerDesc) -> bool {
+        self.key_range.start < layer.key_range.end
+            && layer.key_range.start < self.key_range.end
+            && layer.lsn_range.start < self.lsn
+    }
+}
+
+/// Is the page version of `key` at `lsn` one of a dropped relation or database?
+pub(super) fn is_dropped(dropped: &[DroppedKeyRange], key: Key, lsn: Lsn) -> bool {
+    dropped.iter().any(|d| d.contains(key, lsn))
+}
+
+/// Splits `key_range` at the bounds of the `dropped` key ranges within it.
+fn split_key_range(key_range: &Range<Key>, dropped: &[&DroppedKeyRange]) -> Vec<Range<Key>> {
+    let mut bounds: Vec<Key> = dropped
+        .iter()
+        .flat_map(|d| [d.key_range.start, d.key_range.end])
+        .filter(|key| key_range.start < *key && *key < key_range.end)
+        .collect();
+    bounds.sort();
+    bounds.dedup();
+    let mut start = key_range.start;
+    let mut pieces = Vec::with_capacity(bounds.len() + 1);
+    for bound in bounds {
+        pieces.push(start..bound);
+        start = bound;
+    }
+    pieces.push(start..key_range.end);
+    pieces
+}
+
+impl Timeline {
+    /// The LSN before which reads are refused, see `purge`.
+    pub fn get_purge_floor_lsn(&self) -> Lsn {
+        self.purge_floor_lsn.load()
+    }
+
+    /// Check that the history at `lsn` is not purged.
+    pub fn check_lsn_above_purge_floor(&self, lsn: Lsn) -> anyhow::Result<()> {
+        let purge_floor_lsn = self.get_purge_floor_lsn();
+        ensure!(
+            lsn >= purge_floor_lsn,
+            "LSN {lsn} is earlier than the purge floor {purge_floor_lsn} of dropped relations",
+        );
+        Ok(())
+    }
+
+    /// Schedules the purge of the page versions of `key_range` before `lsn`,
+    /// for the next compactions, and moves the purge floor up to `lsn`.
+    ///
+    /// The caller checks that nothing in `key_range` exists at `lsn`, nor at
+    /// the branch points of the child timelines before it.
+    pub(crate) fn schedule_purge(&self, key_range: Range<Key>, lsn: Lsn) -> anyhow::Result<()> {
+        info!(
+            "scheduling purge of {}-{} before {lsn}",
+            key_range.start, key_range.end
+        );
+        // Persist the new floor before compaction removes anything.
+        if self.purge_floor_lsn.fetch_max(lsn) < lsn {
+            self.update_metadata_file(self.disk_consistent_lsn.load(), HashMap::new())?;
+        }
+        self.dropped_key_ranges
+            .lock()
+            .unwrap()
+            .push(DroppedKeyRange { key_range, lsn });
+        Ok(())
+    }
+
+    pub(super) fn dropped_key_ranges(&self) -> Vec<DroppedKeyRange> {
+        self.dropped_key_ranges.lock().unwrap().clone()
+    }
+
+    /// Rewrites the image and L1 delta layers that hold page versions of
+    /// purged relations and databases without them, removes the ones that
+    /// only hold such page versions, and forgets the purges that L0 compaction
+    /// is done with. Returns the number of layers rewritten or removed.
+    pub(super) async fn purge_dropped_layers(
+        &self,
+        layer_removal_cs: Arc<tokio::sync::OwnedMutexGuard<()>>,
+        ctx: &RequestContext,
+    ) -> Result<usize, CompactionError> {
+        let dropped = self.dropped_key_ranges();
+        if dropped.is_empty() {
+            return Ok(0);
+        }
+
+        // L0 layers are left to L0 compaction. `layer_removal_cs` keeps the
+        // others in the layer map until we are done.
+        let candidates: Vec<Arc<dyn PersistentLayer>> = {
+            let guard = self.layers.read().await;
+            guard
+                .layer_map()
+                .iter_historic_layers()
+                .filter(|l| !LayerMap::is_l0(l) && dropped.iter().any(|d| d.overlaps(l)))
+                .map(|l| guard.get_from_desc(&l))
+                .collect()
+        };
+        let remotes: Vec<_> = candidates
+            .iter()
+            .filter(|l| l.is_remote_layer())
+            .inspect(|l| info!("purge requires download of {l}"))
+            .filter_map(|l| Arc::clone(l).downcast_remote_layer())
+            .collect();
+        if !remotes.is_empty() {
+            return Err(CompactionError::DownloadRequired(remotes));
+        }
+
+        let mut rewritten = Vec::new();
+        let mut new_layers = Vec::new();
+        for layer in candidates {
+            let desc = layer.layer_desc().clone();
+            if dropped.iter().any(|d| d.covers(&desc)) {
+                rewritten.push(layer);
+                continue;
+            }
+            let written = self
+                .rewrite_without_dropped(&layer, &dropped, ctx)
+                .await
+                .with_context(|| format!("purge dropped page versions from {layer}"))?;
+            if let Some(written) = written {
+                rewritten.push(layer);
+                new_layers.extend(written);
+            }
+        }
+
+        // Sync the new layers to disk before adding them to the layer map,
+        // like compaction does.
+        if !new_layers.is_empty() {
+            let new_paths: Vec<_> = new_layers.iter().filter_map(|l| l.local_path()).collect();
+            par_fsync::par_fsync_async(&new_paths)
+                .await
+                .context("fsync of newly created layer files")?;
+            let timeline_path = self.conf.timeline_path(&self.tenant_id, &self.timeline_id);
+            par_fsync::par_fsync_async(&[timeline_path])
+                .await
+                .context("fsync of timeline dir")?;
+        }
+
+        // Before deleting any layers, we need to wait for their upload ops to finish.
+        if let Some(remote_client) = &self.remote_client {
+            remote_client
+                .wait_completion()
+                .await
+                .context("wait for layer upload ops to complete")?;
+        }
+
+        let mut guard = self.layers.write().await;
+
+        // Page versions before the purge LSN may still be in memory, or in L0
+        // layers yet to be compacted.
+        let disk_consistent_lsn = self.get_disk_consistent_lsn();
+        let l0_start = guard
+            .layer_map()
+            .get_level0_deltas()?
+            .iter()
+            .map(|l| l.lsn_range.start)
+            .min();
+        self.dropped_key_ranges.lock().unwrap().retain(|d| {
+            disk_consistent_lsn < d.lsn || l0_start.map_or(false, |start| start < d.lsn)
+        });
+
+        if rewritten.is_empty() {
+            return Ok(0);
+        }
+        for l in &new_layers {
+            let path = l.local_path().expect("new layers are resident");
+            let size = path
+                .metadata()
+                .with_context(|| format!("read file metadata for new layer {}", path.display()))?
+                .len();
+            if let Some(remote_client) = &self.remote_client {
+                remote_client
+                    .schedule_layer_file_upload(&l.filename(), &LayerFileMetadata::new(size))?;
+            }
+            self.metrics.resident_physical_size_gauge.add(size);
+            l.access_stats().record_residence_event(
+                &guard,
+                LayerResidenceStatus::Resident,
+                LayerResidenceEventReason::LayerCreate,
+            );
+        }
+        let layer_names_to_delete: Vec<_> = rewritten.iter().map(|l| l.filename()).collect();
+        let count = rewritten.len();
+        guard.finish_compact_l0(layer_removal_cs, rewritten, new_layers, &self.metrics)?;
+        drop_wlock(guard);
+        if let Some(remote_client) = &self.remote_client {
+            remote_client.schedule_layer_file_deletion(&layer_names_to_delete)?;
+        }
+
+        info!("purged dropped page versions from {count} layers");
+        Ok(count)
+    }
+
+    /// Writes the page versions of `layer` that are not dead into new layers,
+    /// split around the dropped key ranges so that their names differ from
+    /// the one of `layer`. Returns `None` if `layer` has no dead page versions,
+    /// or can't be split, in which case it's left alone.
+    async fn rewrite_without_dropped(
+        &self,
+        layer: &Arc<dyn PersistentLayer>,
+        dropped: &[DroppedKeyRange],
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Option<Vec<Arc<dyn PersistentLayer>>>> {
+        let desc = layer.layer_desc();
+        let dropped: Vec<&DroppedKeyRange> = dropped.iter().filter(|d| d.overlaps(desc)).collect();
+        let pieces = split_key_range(&desc.key_range, &dropped);
+        let is_dead = |key: Key, lsn: Lsn| dropped.iter().any(|d| d.contains(key, lsn));
+
+        let mut written: Vec<Arc<dyn PersistentLayer>> = Vec::new();
+        if let Some(delta) = Arc::clone(layer).downcast_delta_layer() {
+            let val_refs = delta.load_val_refs(ctx).await?;
+            if !val_refs.iter().any(|(key, lsn, _)| is_dead(*key, *lsn)) {
+                return Ok(None);
+            }
+            let (kept, dead): (Vec<_>, Vec<_>) = val_refs
+                .into_iter()
+                .partition(|(key, lsn, _)| !is_dead(*key, *lsn));
+            if pieces.len() == 1 && !kept.is_empty() {
+                warn!(
+                    "can't purge {} dead page versions of {layer} without renaming it",
+                    dead.len()
+                );
+                return Ok(None);
+            }
+            for piece in pieces {
+                let entries: Vec<_> = kept
+                    .iter()
+                    .filter(|(key, ..)| piece.contains(key))
+                    .collect();
+                if entries.is_empty() {
+                    continue;
+                }
+                let mut writer = DeltaLayerWriter::new(
+                    self.conf,
+                    self.timeline_id,
+                    self.tenant_id,
+                    piece.start,
+                    desc.lsn_range.clone(),
+                )?;
+                for (key, lsn, val_ref) in entries {
+                    writer.put_value(*key, *lsn, val_ref.load().await?)?;
+                }
+                written.push(Arc::new(writer.finish(piece.end)?));
+            }
+        } else if let Some(image) = Arc::clone(layer).downcast_image_layer() {
+            let lsn = desc.image_layer_lsn();
+            let images = image.load_images(ctx).await?;
+            if !images.iter().any(|(key, _)| is_dead(*key, lsn)) {
+                return Ok(None);
+            }
+            let (kept, dead): (Vec<_>, Vec<_>) =
+                images.into_iter().partition(|(key, _)| !is_dead(*key, lsn));
+            if pieces.len() == 1 && !kept.is_empty() {
+                warn!(
+                    "can't purge {} dead page versions of {layer} without renaming it",
+                    dead.len()
+                );
+                return Ok(None);
+            }
+            for piece in pieces {
+                let entries: Vec<_> = kept.iter().filter(|(key, _)| piece.contains(key)).collect();
+                if entries.is_empty() {
+                    continue;
+                }
+                let mut writer = ImageLayerWriter::new(
+                    self.conf,
+                    self.timeline_id,
+                    self.tenant_id,
+                    &piece,
+                    lsn,
+                    desc.is_incremental(),
+                )?;
+                for (key, img) in entries {
+                    writer.put_image(*key, img)?;
+                }
+                written.push(Arc::new(writer.finish()?));
+            }
+        } else {
+            return Ok(None);
+        }
+        Ok(Some(written))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use pageserver_api::reltag::RelTag;
+    use tokio_util::sync::CancellationToken;
+    use utils::id::RegionId;
+
+    use super::*;
+    use crate::pgdatadir_mapping::Version;
+    use crate::tenant::harness::{TenantHarness, TEST_IMG, TIMELINE_ID};
+    use crate::tenant::metadata::load_metadata;
+    use crate::DEFAULT_PG_VERSION;
+
+    const DROPPED: RelTag = RelTag {
+        spcnode: 0,
+        dbnode: 111,
+        relnode: 1000,
+        forknum: 0,
+    };
+    const KEPT: RelTag = RelTag {
+        spcnode: 0,
+        dbnode: 111,
+        relnode: 1001,
+        forknum: 0,
+    };
+
+    #[tokio::test]
+    async fn purge_dropped_relation() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("purge_dropped_relation")?
+            .load()
+            .await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        for lsn in [Lsn(0x10), Lsn(0x20)] {
+            let mut m = tline.begin_modification(lsn);
+            if lsn == Lsn(0x10) {
+                m.put_relmap_file(0, 111, Bytes::from(""), &ctx).await?;
+                m.put_rel_creation(DROPPED, 1, &ctx).await?;
+                m.put_rel_creation(KEPT, 1, &ctx).await?;
+            }
+            m.put_rel_page_image(DROPPED, 0, TEST_IMG(&format!("dropped at {lsn}")))?;
+            m.put_rel_page_image(KEPT, 0, TEST_IMG(&format!("kept at {lsn}")))?;
+            m.commit().await?;
+            tline.freeze_and_flush().await?;
+        }
+        // An image layer at 0x20, with both relations.
+        let partitioning = tline
+            .collect_keyspace(Lsn(0x20), &ctx)
+            .await?
+            .partition(tline.get_compaction_target_size());
+        tline
+            .create_image_layers(&partitioning, Lsn(0x20), true, &ctx)
+            .await?;
+
+        let mut m = tline.begin_modification(Lsn(0x30));
+        m.put_rel_drop(DROPPED, &ctx).await?;
+        m.commit().await?;
+        tline.freeze_and_flush().await?;
+
+        // The relation still exists at 0x20, so it can't be purged from there.
+        let relnode = Some(DROPPED.relnode);
+        assert!(tline
+            .dropped_key_range(0, 111, relnode, Lsn(0x20), &ctx)
+            .await?
+            .is_none());
+        let key_range = tline
+            .dropped_key_range(0, 111, relnode, Lsn(0x30), &ctx)
+            .await?
+            .expect("dropped at 0x30");
+        tline.schedule_purge(key_range, Lsn(0x30))?;
+
+        // The purge floor is persisted right away.
+        assert!(tline.check_lsn_above_purge_floor(Lsn(0x20)).is_err());
+        tline.check_lsn_above_purge_floor(Lsn(0x30))?;
+        let metadata = load_metadata(tline.conf, &tline.tenant_id, &tline.timeline_id)?;
+        assert_eq!(metadata.purge_floor_lsn(), Lsn(0x30));
+
+        tline.compact(&CancellationToken::new(), &ctx).await?;
+
+        // The image layer is rewritten without the dropped relation.
+        let dropped_key = Key {
+            field1: 0x00,
+            field2: DROPPED.spcnode,
+            field3: DROPPED.dbnode,
+            field4: DROPPED.relnode,
+            field5: DROPPED.forknum,
+            field6: 0,
+        };
+        let guard = tline.layers.read().await;
+        let images: Vec<_> = guard
+            .layer_map()
+            .iter_historic_layers()
+            .filter(|l| !l.is_delta())
+            .collect();
+        assert!(!images.is_empty());
+        assert!(images.iter().all(|l| !l.key_range.contains(&dropped_key)));
+        drop(guard);
+
+        assert!(tline
+            .get_rel_page_at_lsn(DROPPED, 0, Version::Lsn(Lsn(0x20)), false, &ctx)
+            .await
+            .is_err());
+        for lsn in [Lsn(0x10), Lsn(0x20)] {
+            let img = tline
+                .get_rel_page_at_lsn(KEPT, 0, Version::Lsn(lsn), false, &ctx)
+                .await?;
+            assert_eq!(img, TEST_IMG(&format!("kept at {lsn}")));
+        }
+        // L0 compaction is done with the purge.
+        assert!(tline.dropped_key_ranges().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Read heat of the key ranges of a timeline, to create image layers early
+//! for the ranges that are read often through long chains of WAL records.
+//!
+//! Compaction creates an image layer for a partition of the key space once
+//! `image_creation_threshold` delta layers have piled up over it. Until then,
+//! every read of a page in the partition replays the WAL records of the page
+//! since its last image. That's cheap for a range that is rarely read, but a
+//! range read in a loop pays the WAL redo over and over. So the reads that
+//! replay WAL records are counted per key range, and, with the tenant's
+//! `hot_range_image_creation_threshold` set, compaction creates an image layer
+//! for the partitions overlapping a range that had at least that many of them,
+//! as soon as they have any delta layer over their last image.
+//!
+//! The counts decay by half at each compaction, so that a range that stops
+//! being read cools down.
+//!
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use crate::keyspace::{KeySpace, KeySpaceRandomAccum};
+use crate::repository::Key;
+
+/// The heat is tracked for ranges of this many consecutive blocks of a
+/// relation, 8 MB of pages.
+const HEAT_RANGE_BLOCKS: u32 = 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct RangeHeat {
+    /// Reads that replayed WAL records.
+    reads: u64,
+    /// WAL records replayed by them.
+    records: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct ReadHeat {
+    /// By the first key of the range.
+    ranges: Mutex<HashMap<Key, RangeHeat>>,
+}
+
+fn heat_range_start(key: Key) -> Key {
+    Key {
+        field6: key.field6 & !(HEAT_RANGE_BLOCKS - 1),
+        ..key
+    }
+}
+
+fn heat_range(start: Key) -> Range<Key> {
+    start..start.add(HEAT_RANGE_BLOCKS)
+}
+
+impl ReadHeat {
+    /// Records a read of `key` that replayed `records` WAL records.
+    pub(crate) fn record_read(&self, key: Key, records: usize) {
+        if records == 0 {
+            return;
+        }
+        let mut ranges = self.ranges.lock().unwrap();
+        let heat = ranges.entry(heat_range_start(key)).or_default();
+        heat.reads += 1;
+        heat.records += records as u64;
+    }
+
+    /// The key ranges with at least `threshold` reads, and how many there are.
+    pub(crate) fn hot_ranges(&self, threshold: u64) -> (KeySpace, usize) {
+        let ranges = self.ranges.lock().unwrap();
+        let mut hot = KeySpaceRandomAccum::default();
+        let mut count = 0;
+        for (start, heat) in ranges.iter() {
+            if heat.reads >= threshold {
+                hot.add_range(heat_range(*start));
+                count += 1;
+            }
+        }
+        (hot.to_keyspace(), count)
+    }
+
+    /// Halves the heat of all ranges, forgetting the ones that cooled down.
+    pub(crate) fn decay(&self) {
+        self.ranges.lock().unwrap().retain(|_, heat| {
+            heat.reads /= 2;
+            heat.records /= 2;
+            heat.reads > 0
+        });
+    }
+
+    /// Forgets the heat of the ranges that overlap `range`, once an image
+    /// layer covers it.
+    pub(crate) fn cool_down(&self, range: &Range<Key>) {
+        self.ranges.lock().unwrap().retain(|start, _| {
+            let heat_range = heat_range(*start);
+            heat_range.end <= range.start || range.end <= heat_range.start
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_key(blkno: u32) -> Key {
+        Key {
+            field1: 0,
+            field2: 1663,
+            field3: 5,
+            field4: 16384,
+            field5: 0,
+            field6: blkno,
+        }
+    }
+
+    #[test]
+    fn hot_ranges() {
+        let heat = ReadHeat::default();
+        // Reads served from an image don't count.
+        heat.record_read(block_key(1), 0);
+        for _ in 0..4 {
+            heat.record_read(block_key(1), 3);
+            heat.record_read(block_key(HEAT_RANGE_BLOCKS - 1), 3);
+        }
+        heat.record_read(block_key(5 * HEAT_RANGE_BLOCKS), 10);
+
+        let (hot, count) = heat.hot_ranges(8);
+        assert_eq!(count, 1);
+        assert_eq!(hot.ranges, vec![block_key(0)..block_key(HEAT_RANGE_BLOCKS)]);
+        assert_eq!(heat.hot_ranges(1).1, 2);
+
+        // The heat halves at each decay, and cold ranges are forgotten.
+        heat.decay();
+        assert_eq!(heat.hot_ranges(8).1, 0);
+        assert_eq!(heat.hot_ranges(4).1, 1);
+        assert_eq!(heat.ranges.lock().unwrap().len(), 1);
+
+        heat.cool_down(&(block_key(10)..block_key(20)));
+        assert_eq!(heat.hot_ranges(1).1, 0);
+    }
+}
@@ -0,0 +1,303 @@
+//! Consistency check of the layer files of a timeline, behind the scrub API.
+//!
+//! A layer is checked by reading the whole file to verify the checksum of its
+//! content, which loading a layer doesn't do, and by loading it and reading its
+//! whole index: the summary must match the layer file name,
+//! and the keys and LSNs of the index must be within the ranges of the layer.
+//! Layer files written before the checksums only get the latter checks, their
+//! values are not read.
+//!
+use std::cmp::{max, min};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use pageserver_api::models::TimelineScrubReport;
+use utils::lsn::Lsn;
+
+use super::Timeline;
+use crate::context::{AccessStatsBehavior, RequestContext, RequestContextBuilder};
+use crate::repository::Key;
+use crate::tenant::ephemeral_file::is_ephemeral_file;
+use crate::tenant::storage_layer::{AsLayerDesc, PersistentLayer};
+use crate::{is_temporary, METADATA_FILE_NAME, WAL_RECEIVER_STATE_FILE_NAME};
+
+impl Timeline {
+    /// Checks the resident layer files of the timeline, that the layer map
+    /// covers the LSNs from the gc cutoff up to `disk_consistent_lsn` and the
+    /// keys of the timeline at `disk_consistent_lsn`, and looks for files in
+    /// the timeline directory that are not in the layer map.
+    pub(crate) async fn scrub(&self, ctx: &RequestContext) -> anyhow::Result<TimelineScrubReport> {
+        // Don't make the layers look recently used to the eviction.
+        let ctx = RequestContextBuilder::extend(ctx)
+            .access_stats_behavior(AccessStatsBehavior::Skip)
+            .build();
+
+        let layers: Vec<Arc<dyn PersistentLayer>> = {
+            let guard = self.layers.read().await;
+            guard
+                .layer_map()
+                .iter_historic_layers()
+                .map(|desc| guard.get_from_desc(&desc))
+                .collect()
+        };
+
+        let mut report = TimelineScrubReport {
+            timeline_id: self.timeline_id,
+            layers_checked: 0,
+            errors: Vec::new(),
+            orphaned_files: Vec::new(),
+        };
+
+        for layer in &layers {
+            // Evicted layers have no local path.
+            let Some(path) = layer.local_path() else {
+                continue;
+            };
+            let file_name = layer.filename().file_name();
+            let expected_size = layer.layer_desc().file_size();
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.len() != expected_size => {
+                    report.errors.push(format!(
+                        "{file_name}: file size is {}, expected {expected_size}",
+                        metadata.len()
+                    ));
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    report.errors.push(format!("{file_name}: {e}"));
+                    continue;
+                }
+            }
+
+            let verified = if let Some(delta) = Arc::clone(layer).downcast_delta_layer() {
+                delta.verify(&ctx).await
+            } else if let Some(image) = Arc::clone(layer).downcast_image_layer() {
+                image.verify(&ctx).await
+            } else {
+                continue;
+            };
+            if let Err(e) = verified {
+                report.errors.push(format!("{file_name}: {e:#}"));
+            }
+            report.layers_checked += 1;
+        }
+
+        // Below the gc cutoff, history is only kept where it is still needed,
+        // but above it every LSN must be covered by some delta layer.
+        let gc_cutoff = *self.get_latest_gc_cutoff_lsn();
+        let required = max(gc_cutoff, self.get_ancestor_lsn())..self.get_disk_consistent_lsn();
+        let delta_lsn_ranges: Vec<_> = layers
+            .iter()
+            .map(|layer| layer.layer_desc())
+            .filter(|desc| desc.is_delta())
+            .map(|desc| desc.get_lsn_range())
+            .collect();
+        for gap in uncovered_ranges(delta_lsn_ranges, required) {
+            report.errors.push(format!(
+                "no delta layer covers the LSNs {}..{}",
+                gap.start, gap.end
+            ));
+        }
+
+        // Every key of the timeline must be in the key range of some layer
+        // that a read at `disk_consistent_lsn` gets to.
+        let disk_consistent_lsn = self.get_disk_consistent_lsn();
+        match self.collect_keyspace(disk_consistent_lsn, &ctx).await {
+            Ok(keyspace) => {
+                let key_ranges = self.readable_key_ranges(disk_consistent_lsn).await;
+                for required in keyspace.ranges {
+                    for gap in uncovered_ranges(key_ranges.clone(), required) {
+                        report.errors.push(format!(
+                            "no layer covers the keys {}..{}",
+                            gap.start, gap.end
+                        ));
+                    }
+                }
+            }
+            Err(e) => report.errors.push(format!(
+                "failed to collect the keys at {disk_consistent_lsn}: {e:#}"
+            )),
+        }
+
+        let layer_file_names: HashSet<String> = layers
+            .iter()
+            .map(|layer| layer.filename().file_name())
+            .collect();
+        for entry in std::fs::read_dir(self.conf.timeline_path(&self.tenant_id, &self.timeline_id))?
+        {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if file_name == METADATA_FILE_NAME
+                || file_name == WAL_RECEIVER_STATE_FILE_NAME
+                || is_ephemeral_file(&file_name)
+                || is_temporary(&entry.path())
+                || layer_file_names.contains(&file_name)
+            {
+                continue;
+            }
+            report.orphaned_files.push(file_name);
+        }
+
+        Ok(report)
+    }
+
+    /// Key ranges of the layers that a read at `lsn` can get to: the layers
+    /// of this timeline starting at or below `lsn`, and those of the
+    /// ancestors below the branch points.
+    async fn readable_key_ranges(&self, lsn: Lsn) -> Vec<Range<Key>> {
+        let mut key_ranges = Vec::new();
+        let mut timeline = self;
+        let mut lsn = lsn;
+        loop {
+            let guard = timeline.layers.read().await;
+            key_ranges.extend(
+                guard
+                    .layer_map()
+                    .iter_historic_layers()
+                    .filter(|desc| desc.get_lsn_range().start <= lsn)
+                    .map(|desc| desc.get_key_range()),
+            );
+            drop(guard);
+
+            let Some(ancestor) = timeline.ancestor_timeline.as_ref() else {
+                break;
+            };
+            lsn = timeline.get_ancestor_lsn();
+            timeline = ancestor;
+        }
+        key_ranges
+    }
+}
+
+/// Finds the parts of the `required` range that none of the `ranges` cover.
+fn uncovered_ranges<T: Ord + Copy>(mut ranges: Vec<Range<T>>, required: Range<T>) -> Vec<Range<T>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut gaps = Vec::new();
+    let mut covered_until = required.start;
+    for range in ranges {
+        if covered_until >= required.end {
+            break;
+        }
+        if range.start > covered_until {
+            gaps.push(covered_until..min(range.start, required.end));
+        }
+        covered_until = max(covered_until, range.end);
+    }
+    if covered_until < required.end {
+        gaps.push(covered_until..required.end);
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::harness::{TenantHarness, TEST_IMG, TIMELINE_ID};
+    use crate::tenant::layer_map::LayerMap;
+    use crate::DEFAULT_PG_VERSION;
+    use bytes::Bytes;
+    use pageserver_api::reltag::RelTag;
+    use tokio_util::sync::CancellationToken;
+    use utils::id::RegionId;
+
+    #[test]
+    fn lsn_range_gaps() {
+        let ranges = vec![Lsn(10)..Lsn(20), Lsn(0)..Lsn(12), Lsn(25)..Lsn(40)];
+        assert_eq!(
+            uncovered_ranges(ranges.clone(), Lsn(5)..Lsn(30)),
+            vec![Lsn(20)..Lsn(25)]
+        );
+        assert_eq!(
+            uncovered_ranges(ranges.clone(), Lsn(5)..Lsn(50)),
+            vec![Lsn(20)..Lsn(25), Lsn(40)..Lsn(50)]
+        );
+        assert!(uncovered_ranges(ranges, Lsn(0)..Lsn(18)).is_empty());
+        assert_eq!(
+            uncovered_ranges(Vec::new(), Lsn(5)..Lsn(6)),
+            vec![Lsn(5)..Lsn(6)]
+        );
+    }
+
+    #[tokio::test]
+    async fn scrub_finds_keys_of_deleted_layer() -> anyhow::Result<()> {
+        const TESTREL: RelTag = RelTag {
+            spcnode: 0,
+            dbnode: 111,
+            relnode: 1000,
+            forknum: 0,
+        };
+        const NBLOCKS: u32 = 1024;
+
+        let mut harness = TenantHarness::create("scrub_finds_keys_of_deleted_layer")?;
+        // Compact the level 0 layers into several layers split by key, and
+        // don't create image layers that would cover the keys again.
+        harness.tenant_conf.checkpoint_distance = 16 * 1024;
+        harness.tenant_conf.compaction_threshold = 2;
+        harness.tenant_conf.image_creation_threshold = 1000;
+
+        let deleted_key_range = {
+            let (tenant, ctx) = harness.load().await;
+            let tline = tenant
+                .create_test_timeline(
+                    TIMELINE_ID,
+                    Lsn(0x08),
+                    DEFAULT_PG_VERSION,
+                    RegionId(0),
+                    &ctx,
+                )
+                .await?;
+
+            let mut m = tline.begin_modification(Lsn(0x10));
+            m.put_relmap_file(0, 111, Bytes::from(""), &ctx).await?;
+            m.put_rel_creation(TESTREL, NBLOCKS, &ctx).await?;
+            m.commit().await?;
+            for (i, first_blknum) in (0..NBLOCKS).step_by(256).enumerate() {
+                let mut m = tline.begin_modification(Lsn(0x20 + 0x10 * i as u64));
+                for blknum in first_blknum..first_blknum + 256 {
+                    m.put_rel_page_image(TESTREL, blknum, TEST_IMG(&format!("blk {blknum}")))?;
+                }
+                m.commit().await?;
+                tline.freeze_and_flush().await?;
+            }
+            tline.compact(&CancellationToken::new(), &ctx).await?;
+
+            let report = tline.scrub(&ctx).await?;
+            assert_eq!(report.errors, Vec::<String>::new());
+
+            // Delete a layer in the middle of the key space, which holds only
+            // blocks of the relation.
+            let guard = tline.layers.read().await;
+            let mut layers: Vec<_> = guard
+                .layer_map()
+                .iter_historic_layers()
+                .filter(|desc| !LayerMap::is_l0(desc))
+                .collect();
+            layers.sort_by_key(|desc| desc.key_range.start);
+            assert!(
+                layers.len() >= 3,
+                "compaction created {} layers",
+                layers.len()
+            );
+            let deleted = guard.get_from_desc(&layers[layers.len() / 2]);
+            std::fs::remove_file(deleted.local_path().unwrap())?;
+            deleted.layer_desc().key_range.clone()
+        };
+
+        // Without the file, the layer is not in the layer map after a restart.
+        let (tenant, ctx) = harness.load().await;
+        let tline = tenant.get_timeline(TIMELINE_ID, false)?;
+        let report = tline.scrub(&ctx).await?;
+        assert_eq!(
+            report.errors,
+            vec![format!(
+                "no layer covers the keys {}..{}",
+                deleted_key_range.start, deleted_key_range.end
+            )]
+        );
+
+        Ok(())
+    }
+}
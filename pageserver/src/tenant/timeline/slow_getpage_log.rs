@@ -0,0 +1,99 @@
+//! Opt-in log of the page requests of a timeline that took longer than a
+//! threshold, along with the number of layers visited for them.
+//!
+//! Meant for diagnosing read amplification on a live pageserver: enable it for
+//! a timeline through the management API, reproduce the slow reads, and fetch
+//! the log.
+//!
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use pageserver_api::models::{SlowGetPageLogInfo, SlowGetPageRequest};
+
+/// How many of the most recent slow requests are kept.
+const SLOW_GETPAGE_LOG_CAPACITY: usize = 256;
+
+#[derive(Default)]
+pub(crate) struct SlowGetPageLog {
+    /// Requests taking at least this long are logged. The log is disabled if
+    /// `None`.
+    threshold: Option<Duration>,
+    requests: VecDeque<SlowGetPageRequest>,
+}
+
+impl SlowGetPageLog {
+    pub(crate) fn threshold(&self) -> Option<Duration> {
+        self.threshold
+    }
+
+    /// Enables the log, or changes the threshold of the enabled log, keeping
+    /// the requests logged so far.
+    pub(crate) fn enable(&mut self, threshold: Duration) {
+        self.threshold = Some(threshold);
+    }
+
+    /// Disables the log and drops the requests logged so far.
+    pub(crate) fn disable(&mut self) {
+        self.threshold = None;
+        self.requests.clear();
+    }
+
+    pub(crate) fn record(&mut self, request: SlowGetPageRequest) {
+        if self.requests.len() >= SLOW_GETPAGE_LOG_CAPACITY {
+            self.requests.pop_front();
+        }
+        self.requests.push_back(request);
+    }
+
+    pub(crate) fn info(&self) -> SlowGetPageLogInfo {
+        SlowGetPageLogInfo {
+            threshold_micros: self.threshold.map(|t| t.as_micros() as u64),
+            requests: self.requests.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use pageserver_api::reltag::RelTag;
+    use utils::lsn::Lsn;
+
+    use super::*;
+
+    fn request(blkno: u32) -> SlowGetPageRequest {
+        SlowGetPageRequest {
+            rel: RelTag {
+                forknum: 0,
+                spcnode: 1663,
+                dbnode: 5,
+                relnode: 16384,
+            },
+            blkno,
+            lsn: Lsn(0x16B5A50),
+            latency_micros: 1000,
+            layers_visited: 3,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn keeps_most_recent_requests() {
+        let mut log = SlowGetPageLog::default();
+        log.enable(Duration::from_millis(1));
+        for blkno in 0..SLOW_GETPAGE_LOG_CAPACITY as u32 + 10 {
+            log.record(request(blkno));
+        }
+
+        let info = log.info();
+        assert_eq!(info.threshold_micros, Some(1000));
+        assert_eq!(info.requests.len(), SLOW_GETPAGE_LOG_CAPACITY);
+        assert_eq!(info.requests[0].blkno, 10);
+
+        log.disable();
+        let info = log.info();
+        assert_eq!(info.threshold_micros, None);
+        assert!(info.requests.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! Truncation of the history of a timeline before an LSN, behind the timeline
+//! truncate API, for users who want to reclaim space sooner than the GC
+//! horizon and PITR interval allow.
+//!
+//! Regular GC can only remove a layer once newer image layers cover its key
+//! range, which compaction creates at its own pace. Truncation creates them
+//! right away, at the truncation LSN, for the whole keyspace, and then moves
+//! the GC cutoff there, so that the history before it goes at once.
+//!
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::bail;
+use tracing::*;
+use utils::lsn::Lsn;
+
+use super::Timeline;
+use crate::context::RequestContext;
+use crate::repository::GcResult;
+
+impl Timeline {
+    /// Drops the history of the timeline before `lsn`, for good. Page versions
+    /// at `lsn` and after remain readable.
+    ///
+    /// The caller checks `lsn` against the branch points of the child
+    /// timelines, `retain_lsns`, whose history is kept, and holds
+    /// `Tenant::gc_cs` so that no new ones are created meanwhile.
+    pub(crate) async fn truncate_before(
+        &self,
+        lsn: Lsn,
+        retain_lsns: Vec<Lsn>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<GcResult> {
+        let latest_gc_cutoff = *self.get_latest_gc_cutoff_lsn();
+        if lsn <= latest_gc_cutoff {
+            info!("history before {lsn} is already gone, the GC cutoff is {latest_gc_cutoff}");
+            return Ok(GcResult::default());
+        }
+        if self.get_disk_consistent_lsn() < lsn {
+            self.freeze_and_flush().await?;
+        }
+
+        let layer_removal_cs = Arc::new(self.layer_removal_cs.clone().lock_owned().await);
+        if self.is_stopping() {
+            bail!("timeline is Stopping");
+        }
+
+        let keyspace = self.collect_keyspace(lsn, ctx).await?;
+        let partitioning = keyspace.partition(self.get_compaction_target_size());
+        let layers_to_upload = self
+            .create_image_layers(&partitioning, lsn, true, ctx)
+            .await?;
+        if let Some(remote_client) = &self.remote_client {
+            for (path, layer_metadata) in layers_to_upload {
+                remote_client.schedule_layer_file_upload(&path, &layer_metadata)?;
+            }
+        }
+
+        // The image layers are at `lsn` itself, unlike the ones regular GC
+        // relies on, which are older than its cutoff.
+        let result = self
+            .gc_timeline(layer_removal_cs, lsn, lsn, retain_lsns, lsn, lsn + 1)
+            .await?;
+        // GC only persists the new cutoff along with removing layers.
+        if result.layers_removed == 0 {
+            self.update_metadata_file(self.disk_consistent_lsn.load(), HashMap::new())?;
+        }
+        self.update_layer_count_metrics().await;
+
+        info!(
+            "truncated history before {lsn}, removing {} layers",
+            result.layers_removed
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use pageserver_api::reltag::RelTag;
+    use utils::id::RegionId;
+
+    use super::*;
+    use crate::pgdatadir_mapping::Version;
+    use crate::tenant::harness::{TenantHarness, TEST_IMG, TIMELINE_ID};
+    use crate::DEFAULT_PG_VERSION;
+
+    const TESTREL: RelTag = RelTag {
+        spcnode: 0,
+        dbnode: 111,
+        relnode: 1000,
+        forknum: 0,
+    };
+
+    fn test_img(lsn: Lsn) -> Bytes {
+        TEST_IMG(&format!("block 0 at {lsn}"))
+    }
+
+    #[tokio::test]
+    async fn truncate_before() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("truncate_before")?.load().await;
+        let tline = tenant
+            .create_test_timeline(
+                TIMELINE_ID,
+                Lsn(0x08),
+                DEFAULT_PG_VERSION,
+                RegionId(0),
+                &ctx,
+            )
+            .await?;
+
+        for lsn in [Lsn(0x10), Lsn(0x20), Lsn(0x30)] {
+            let mut m = tline.begin_modification(lsn);
+            if lsn == Lsn(0x10) {
+                m.put_relmap_file(0, 111, Bytes::from(""), &ctx).await?;
+                m.put_rel_creation(TESTREL, 1, &ctx).await?;
+            }
+            m.put_rel_page_image(TESTREL, 0, test_img(lsn))?;
+            m.commit().await?;
+            tline.freeze_and_flush().await?;
+        }
+
+        let result = tline.truncate_before(Lsn(0x20), Vec::new(), &ctx).await?;
+        assert!(result.layers_removed > 0);
+        assert_eq!(*tline.get_latest_gc_cutoff_lsn(), Lsn(0x20));
+        for lsn in [Lsn(0x20), Lsn(0x30)] {
+            let img = tline
+                .get_rel_page_at_lsn(TESTREL, 0, Version::Lsn(lsn), false, &ctx)
+                .await?;
+            assert_eq!(img, test_img(lsn));
+        }
+
+        // Truncating before an older LSN does nothing.
+        let result = tline.truncate_before(Lsn(0x10), Vec::new(), &ctx).await?;
+        assert_eq!(result.layers_removed, 0);
+        assert_eq!(*tline.get_latest_gc_cutoff_lsn(), Lsn(0x20));
+        Ok(())
+    }
+}
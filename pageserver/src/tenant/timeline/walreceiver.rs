@@ -21,6 +21,8 @@
 //! The current module contains high-level primitives used in the submodules; general synchronization, timeline acknowledgement and shutdown logic.
 
 mod connection_manager;
+mod saved_connection;
+mod wal_archive;
 mod walreceiver_connection;
 
 use crate::context::{DownloadBehavior, RequestContext};
@@ -33,6 +35,7 @@ use crate::tenant::timeline::walreceiver::connection_manager::{
 use std::future::Future;
 use std::num::NonZeroU64;
 use std::ops::ControlFlow;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use storage_broker::BrokerClientChannel;
@@ -41,7 +44,7 @@ use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 use tracing::*;
 
-use utils::id::TenantTimelineId;
+use utils::id::{RegionId, TenantTimelineId};
 
 use self::connection_manager::ConnectionManagerStatus;
 
@@ -58,6 +61,14 @@ pub struct WalReceiverConf {
     pub auth_token: Option<Arc<String>>,
     pub availability_zone: Option<String>,
     pub ingest_batch_size: u64,
+    pub ingest_backpressure_lag: u64,
+    /// If set, the WAL is ingested from the archived WAL segments in this
+    /// directory instead of being streamed from the safekeepers.
+    pub wal_archive_dir: Option<PathBuf>,
+    /// Whether to ask the safekeepers that support it for zstd-compressed WAL.
+    pub wal_compression: bool,
+    /// Region to ask the safekeepers to filter the WAL of other regions for.
+    pub region: Option<RegionId>,
 }
 
 pub struct WalReceiver {
@@ -88,11 +99,28 @@ impl WalReceiver {
             false,
             async move {
                 debug_assert_current_span_has_tenant_and_timeline_id();
+                if let Some(wal_archive_dir) = conf.wal_archive_dir.as_deref() {
+                    debug!("WAL receiver manager started, ingesting WAL archive");
+                    select! {
+                        _ = task_mgr::shutdown_watcher() => {
+                            trace!("WAL receiver shutdown requested, shutting down");
+                        },
+                        _ = wal_archive::ingest_wal_archive(
+                            &timeline,
+                            wal_archive_dir,
+                            conf.ingest_batch_size,
+                            &walreceiver_ctx,
+                        ) => {},
+                    }
+                    return Ok(());
+                }
+
                 debug!("WAL receiver manager started, connecting to broker");
                 let mut connection_manager_state = ConnectionManagerState::new(
                     timeline,
                     conf,
                 );
+                connection_manager_state.restore_saved_connection().await;
                 loop {
                     select! {
                         _ = task_mgr::shutdown_watcher() => {
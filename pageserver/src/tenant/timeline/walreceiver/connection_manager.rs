@@ -11,6 +11,7 @@
 
 use std::{collections::HashMap, num::NonZeroU64, ops::ControlFlow, sync::Arc, time::Duration};
 
+use super::saved_connection::SavedWalConnection;
 use super::{TaskStateUpdate, WalReceiverConf};
 use crate::context::{DownloadBehavior, RequestContext};
 use crate::metrics::{
@@ -83,6 +84,19 @@ pub(super) async fn connection_manager_loop_step(
         .timeline
         .subscribe_for_state_updates();
 
+    // Connect right away if there is a candidate already, e.g. the safekeeper
+    // restored from the last graceful shutdown, instead of waiting for the
+    // broker subscription and its updates.
+    if connection_manager_state.wal_connection.is_none() {
+        if let Some(new_candidate) = connection_manager_state.next_connection_candidate() {
+            info!("Connecting to known connection candidate: {new_candidate:?}");
+            connection_manager_state
+                .change_connection(new_candidate, ctx)
+                .await;
+            *manager_status.write().unwrap() = Some(connection_manager_state.manager_status());
+        }
+    }
+
     // Subscribe to the broker updates. Stream shares underlying TCP connection
     // with other streams on this client (other connection managers). When
     // object goes out of scope, stream finishes in drop() automatically.
@@ -269,6 +283,21 @@ pub struct ConnectionManagerStatus {
 }
 
 impl ConnectionManagerStatus {
+    /// The highest commit LSN known from the safekeepers, either through the
+    /// current connection or the broker.
+    pub fn latest_commit_lsn(&self) -> Option<Lsn> {
+        let connection_commit_lsn = self
+            .existing_connection
+            .as_ref()
+            .and_then(|connection| connection.commit_lsn);
+        let candidates_commit_lsn = self
+            .wal_stream_candidates
+            .values()
+            .map(|candidate| Lsn(candidate.timeline.commit_lsn))
+            .max();
+        connection_commit_lsn.max(candidates_commit_lsn)
+    }
+
     /// Generates a string, describing current connection status in a form, suitable for logging.
     pub fn to_human_readable_string(&self) -> String {
         let mut resulting_string = String::new();
@@ -400,6 +429,22 @@ impl ConnectionManagerState {
         let node_id = new_sk.safekeeper_id;
         let connect_timeout = self.conf.wal_connect_timeout;
         let ingest_batch_size = self.conf.ingest_batch_size;
+        let ingest_backpressure_lag = self.conf.ingest_backpressure_lag;
+        // Only ask for compression the safekeepers that told the broker they can do it,
+        // older ones would reject the option.
+        let wal_compression = self.conf.wal_compression
+            && self
+                .wal_stream_candidates
+                .get(&node_id)
+                .map_or(false, |candidate| candidate.timeline.zstd_wal_compression);
+        // The timelines of our own region need all their WAL.
+        let wal_filter_region = self.conf.region.filter(|region| {
+            *region != self.timeline.region_id
+                && self
+                    .wal_stream_candidates
+                    .get(&node_id)
+                    .map_or(false, |candidate| candidate.timeline.region_wal_filter)
+        });
         let timeline = Arc::clone(&self.timeline);
         let ctx = ctx.detached_child(
             TaskKind::WalReceiverConnectionHandler,
@@ -420,6 +465,9 @@ impl ConnectionManagerState {
                     ctx,
                     node_id,
                     ingest_batch_size,
+                    ingest_backpressure_lag,
+                    wal_compression,
+                    wal_filter_region,
                 )
                 .await;
 
@@ -819,10 +867,102 @@ impl ConnectionManagerState {
         }
     }
 
+    /// Registers the connection saved at the last graceful shutdown, if any,
+    /// as a connection candidate, to reconnect to that safekeeper without
+    /// waiting for the broker to announce it.
+    pub(super) async fn restore_saved_connection(&mut self) {
+        let path = self
+            .timeline
+            .conf
+            .wal_receiver_state_path(&self.id.tenant_id, &self.id.timeline_id);
+        let saved = match SavedWalConnection::take(&path).await {
+            Ok(Some(saved)) => saved,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("failed to restore saved WAL connection: {e:#}");
+                return;
+            }
+        };
+
+        let last_record_lsn = self.timeline.get_last_record_lsn();
+        if last_record_lsn != saved.last_record_lsn {
+            warn!(
+                "last record LSN {last_record_lsn} differs from {} saved with the WAL connection",
+                saved.last_record_lsn
+            );
+        }
+        info!(
+            "restoring WAL connection to safekeeper {} at commit LSN {}",
+            saved.safekeeper_id, saved.commit_lsn
+        );
+        self.wal_stream_candidates.insert(
+            saved.safekeeper_id,
+            BrokerSkTimeline {
+                timeline: SafekeeperTimelineInfo {
+                    safekeeper_id: saved.safekeeper_id.0,
+                    tenant_timeline_id: None,
+                    last_log_term: 0,
+                    flush_lsn: saved.commit_lsn.0,
+                    commit_lsn: saved.commit_lsn.max(last_record_lsn).0,
+                    backup_lsn: 0,
+                    remote_consistent_lsn: 0,
+                    peer_horizon_lsn: 0,
+                    local_start_lsn: 0,
+                    safekeeper_connstr: saved.safekeeper_connstr,
+                    http_connstr: String::new(),
+                    zstd_wal_compression: false,
+                    region_wal_filter: false,
+                    availability_zone: saved.availability_zone,
+                },
+                latest_update: Utc::now().naive_utc(),
+            },
+        );
+    }
+
+    /// The current connection to save, if it got established.
+    fn saved_connection(&self) -> Option<SavedWalConnection> {
+        let wal_connection = self.wal_connection.as_ref()?;
+        if !wal_connection.status.is_connected {
+            return None;
+        }
+        let candidate = self.wal_stream_candidates.get(&wal_connection.sk_id)?;
+        let last_record_lsn = self.timeline.get_last_record_lsn();
+        Some(SavedWalConnection {
+            safekeeper_id: wal_connection.sk_id,
+            safekeeper_connstr: candidate.timeline.safekeeper_connstr.clone(),
+            availability_zone: wal_connection.availability_zone.clone(),
+            commit_lsn: wal_connection
+                .status
+                .commit_lsn
+                .unwrap_or(Lsn(candidate.timeline.commit_lsn)),
+            last_record_lsn,
+        })
+    }
+
+    /// Stops the current connection, if any, and saves it in the timeline
+    /// directory, to reconnect to the same safekeeper at the next start.
     pub(super) async fn shutdown(mut self) {
+        let saved = self.saved_connection();
         if let Some(wal_connection) = self.wal_connection.take() {
             wal_connection.connection_task.shutdown().await;
         }
+        let Some(mut saved) = saved else {
+            return;
+        };
+        // Streaming has stopped, so this is where it resumes from.
+        saved.last_record_lsn = self.timeline.get_last_record_lsn();
+
+        let path = self
+            .timeline
+            .conf
+            .wal_receiver_state_path(&self.id.tenant_id, &self.id.timeline_id);
+        match saved.save(&path).await {
+            Ok(()) => info!(
+                "saved WAL connection to safekeeper {} at {}",
+                saved.safekeeper_id, saved.last_record_lsn
+            ),
+            Err(e) => warn!("failed to save WAL connection: {e:#}"),
+        }
     }
 
     fn manager_status(&self) -> ConnectionManagerStatus {
@@ -929,6 +1069,9 @@ mod tests {
                 peer_horizon_lsn: 0,
                 local_start_lsn: 0,
                 safekeeper_connstr: safekeeper_connstr.to_owned(),
+                http_connstr: String::new(),
+                zstd_wal_compression: false,
+                region_wal_filter: false,
                 availability_zone: None,
             },
             latest_update,
@@ -1361,6 +1504,10 @@ mod tests {
                 auth_token: None,
                 availability_zone: None,
                 ingest_batch_size: 1,
+                ingest_backpressure_lag: 0,
+                wal_archive_dir: None,
+                wal_compression: false,
+                region: None,
             },
             wal_connection: None,
             wal_stream_candidates: HashMap::new(),
@@ -1438,4 +1585,64 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn reconnect_to_saved_connection() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("reconnect_to_saved_connection")?;
+        let mut state = dummy_state(&harness).await;
+        let current_lsn = Lsn(100_000).align();
+        let now = Utc::now().naive_utc();
+
+        let connected_sk_id = NodeId(1);
+        let connection_status = WalConnectionStatus {
+            is_connected: true,
+            has_processed_wal: true,
+            latest_connection_update: now,
+            latest_wal_update: now,
+            commit_lsn: Some(current_lsn),
+            streaming_lsn: Some(current_lsn),
+            node: connected_sk_id,
+        };
+        state.wal_connection = Some(WalConnection {
+            started_at: now,
+            sk_id: connected_sk_id,
+            availability_zone: None,
+            status: connection_status,
+            connection_task: TaskHandle::spawn(move |sender, _| async move {
+                sender
+                    .send(TaskStateUpdate::Progress(connection_status))
+                    .ok();
+                Ok(())
+            }),
+            discovered_new_wal: None,
+        });
+        state.wal_stream_candidates = HashMap::from([(
+            connected_sk_id,
+            dummy_broker_sk_timeline(current_lsn.0, DUMMY_SAFEKEEPER_HOST, now),
+        )]);
+
+        let timeline = Arc::clone(&state.timeline);
+        let conf = state.conf.clone();
+        state.shutdown().await;
+
+        // After a restart, the saved safekeeper is a candidate before any broker update.
+        let mut state = ConnectionManagerState::new(Arc::clone(&timeline), conf.clone());
+        state.restore_saved_connection().await;
+        let candidate = state
+            .next_connection_candidate()
+            .expect("Expected the saved safekeeper to be a candidate, but got none");
+        assert_eq!(candidate.safekeeper_id, connected_sk_id);
+        assert_eq!(candidate.reason, ReconnectReason::NoExistingConnection);
+        assert_eq!(
+            candidate.wal_source_connconf.host(),
+            &Host::Domain(DUMMY_SAFEKEEPER_HOST.to_owned())
+        );
+
+        // The saved connection is used once.
+        let mut state = ConnectionManagerState::new(timeline, conf);
+        state.restore_saved_connection().await;
+        assert!(state.next_connection_candidate().is_none());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,100 @@
+//! The WAL streaming connection of a timeline, saved in the timeline directory
+//! when the WAL receiver is shut down gracefully, so that it reconnects to the
+//! same safekeeper right away on the next start, instead of waiting for the
+//! safekeepers to announce the timeline in the storage broker again.
+//!
+//! The saved connection is only a hint: it is removed when read, and the
+//! connection manager drops it like any other candidate if the broker does not
+//! confirm it in time.
+//!
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use utils::crashsafe::{fsync, path_with_suffix_extension};
+use utils::id::NodeId;
+use utils::lsn::Lsn;
+
+use crate::TEMP_FILE_SUFFIX;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SavedWalConnection {
+    /// The safekeeper the WAL was streamed from.
+    pub safekeeper_id: NodeId,
+    /// The address of the safekeeper, as published in the broker.
+    pub safekeeper_connstr: String,
+    pub availability_zone: Option<String>,
+    /// The commit LSN last reported by the safekeeper.
+    pub commit_lsn: Lsn,
+    /// The last record LSN of the timeline when the connection was closed,
+    /// where streaming resumes from.
+    pub last_record_lsn: Lsn,
+}
+
+impl SavedWalConnection {
+    /// Writes the connection to `path`, replacing the one saved before, if any.
+    pub(super) async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let bytes = serde_json::to_vec(self).context("serialize saved WAL connection")?;
+        let temp_path = path_with_suffix_extension(path, TEMP_FILE_SUFFIX);
+        let mut file = tokio::fs::File::create(&temp_path)
+            .await
+            .with_context(|| format!("create {}", temp_path.display()))?;
+        file.write_all(&bytes)
+            .await
+            .with_context(|| format!("write {}", temp_path.display()))?;
+        file.sync_all()
+            .await
+            .with_context(|| format!("fsync {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, path)
+            .await
+            .with_context(|| format!("rename {} to {}", temp_path.display(), path.display()))?;
+        fsync(
+            path.parent()
+                .expect("saved WAL connection has a parent dir"),
+        )
+        .context("fsync timeline directory")?;
+        Ok(())
+    }
+
+    /// Reads the connection saved at `path`, if any, and removes it.
+    pub(super) async fn take(path: &Path) -> anyhow::Result<Option<Self>> {
+        let bytes = match tokio::fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+        };
+        tokio::fs::remove_file(path)
+            .await
+            .with_context(|| format!("remove {}", path.display()))?;
+        let saved = serde_json::from_slice(&bytes)
+            .with_context(|| format!("parse saved WAL connection {}", path.display()))?;
+        Ok(Some(saved))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn save_and_take() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(crate::WAL_RECEIVER_STATE_FILE_NAME);
+        assert_eq!(SavedWalConnection::take(&path).await?, None);
+
+        let saved = SavedWalConnection {
+            safekeeper_id: NodeId(1),
+            safekeeper_connstr: "safekeeper-1:5454".to_owned(),
+            availability_zone: Some("az-1".to_owned()),
+            commit_lsn: Lsn(0x200),
+            last_record_lsn: Lsn(0x100),
+        };
+        saved.save(&path).await?;
+        assert_eq!(SavedWalConnection::take(&path).await?, Some(saved));
+        // Taken only once.
+        assert!(!path.exists());
+        assert_eq!(SavedWalConnection::take(&path).await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Ingestion of the WAL of a timeline from a directory of archived WAL segment
+//! files, such as the ones produced by `archive_command` or WAL-G, instead of
+//! streaming it from the safekeepers.
+//!
+//! This allows reconstructing a historical cluster inside the pageserver:
+//! import its base backup into a timeline, put the archived WAL segments into
+//! `<wal_archive_dir>/<tenant_id>/<timeline_id>/`, and the timeline catches up
+//! with them. Segments appearing in the directory later are picked up as well.
+//!
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context};
+use postgres_ffi::v14::xlog_utils::{normalize_lsn, IsXLogFileName, XLogFromFileName};
+use postgres_ffi::waldecoder::WalStreamDecoder;
+use postgres_ffi::XLogSegNo;
+use tracing::*;
+
+use crate::context::RequestContext;
+use crate::tenant::Timeline;
+use crate::walingest::WalIngest;
+use crate::walrecord::DecodedWALRecord;
+
+/// How often to look for the next WAL segment, once all segments in the
+/// archive directory are ingested, and how long to wait before starting over
+/// after an error.
+const WAL_ARCHIVE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Ingests the WAL segments in `archive_path` into the timeline, starting from
+/// its last record LSN, until cancelled.
+pub(super) async fn ingest_wal_archive(
+    timeline: &Timeline,
+    archive_path: &Path,
+    ingest_batch_size: u64,
+    ctx: &RequestContext,
+) {
+    loop {
+        if let Err(e) =
+            ingest_wal_archive_segments(timeline, archive_path, ingest_batch_size, ctx).await
+        {
+            error!(
+                "failed to ingest WAL from archive {}: {e:#}",
+                archive_path.display()
+            );
+        }
+        tokio::time::sleep(WAL_ARCHIVE_POLL_INTERVAL).await;
+    }
+}
+
+async fn ingest_wal_archive_segments(
+    timeline: &Timeline,
+    archive_path: &Path,
+    ingest_batch_size: u64,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let last_rec_lsn = timeline.get_last_record_lsn();
+    // Same as when streaming from a safekeeper: skip the padding after the last
+    // record and the page header at a page boundary.
+    let mut startpoint = last_rec_lsn;
+    startpoint += startpoint.calc_padding(8u32);
+    let wal_seg_size = timeline
+        .get_wal_seg_size(last_rec_lsn, ctx)
+        .await
+        .context("get WAL segment size")?;
+    startpoint = normalize_lsn(startpoint, wal_seg_size);
+
+    info!(
+        "last_record_lsn {last_rec_lsn} ingesting WAL archive {} from {startpoint}",
+        archive_path.display()
+    );
+
+    let mut waldecoder = WalStreamDecoder::new(startpoint, timeline.pg_version, wal_seg_size);
+    let mut walingest = WalIngest::new(timeline, startpoint, ctx).await?;
+
+    let mut segno = startpoint.segment_number(wal_seg_size);
+    let mut offset = startpoint.segment_offset(wal_seg_size);
+    let mut last_rec_lsn = last_rec_lsn;
+    loop {
+        let Some(segment_path) = find_segment(archive_path, segno, wal_seg_size).await? else {
+            trace!("WAL segment {segno} is not in the archive yet");
+            tokio::time::sleep(WAL_ARCHIVE_POLL_INTERVAL).await;
+            continue;
+        };
+
+        let segment = tokio::fs::read(&segment_path)
+            .await
+            .with_context(|| format!("read WAL segment {}", segment_path.display()))?;
+        ensure!(
+            segment.len() == wal_seg_size,
+            "WAL segment {} is {} bytes, expected {wal_seg_size}",
+            segment_path.display(),
+            segment.len()
+        );
+        let data = &segment[offset..];
+        waldecoder.feed_bytes(data);
+
+        let mut decoded = DecodedWALRecord::default();
+        let mut modification = timeline.begin_modification(last_rec_lsn);
+        let mut uncommitted_records = 0;
+        let mut num_records = 0;
+        while let Some((lsn, recdata)) = waldecoder.poll_decode()? {
+            if !lsn.is_aligned() {
+                return Err(anyhow!("LSN not aligned"));
+            }
+
+            walingest
+                .ingest_record(recdata, lsn, &mut modification, &mut decoded, ctx)
+                .await
+                .with_context(|| format!("could not ingest record at {lsn}"))?;
+
+            last_rec_lsn = lsn;
+
+            uncommitted_records += 1;
+            // Commit every ingest_batch_size records.
+            if uncommitted_records >= ingest_batch_size {
+                modification.commit().await?;
+                uncommitted_records = 0;
+            }
+
+            num_records += 1;
+        }
+
+        // Commit the remaining records.
+        if uncommitted_records > 0 {
+            modification.commit().await?;
+        }
+
+        timeline.metrics.wal_ingest_bytes.inc_by(data.len() as u64);
+        timeline.metrics.wal_ingest_records.inc_by(num_records);
+
+        timeline
+            .check_checkpoint_distance()
+            .await
+            .context("Failed to check checkpoint distance")?;
+
+        debug!(
+            "ingested {num_records} records of WAL segment {}, up to {last_rec_lsn}",
+            segment_path.display()
+        );
+
+        segno += 1;
+        offset = 0;
+    }
+}
+
+/// Finds the file of the WAL segment `segno` in the archive directory. If the
+/// segment is there on several timelines, the one of the latest timeline is
+/// taken, as after a promotion its WAL supersedes the WAL of the older one.
+async fn find_segment(
+    archive_path: &Path,
+    segno: XLogSegNo,
+    wal_seg_size: usize,
+) -> anyhow::Result<Option<PathBuf>> {
+    let mut file_names = Vec::new();
+    let mut entries = tokio::fs::read_dir(archive_path)
+        .await
+        .with_context(|| format!("read WAL archive directory {}", archive_path.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(file_name) = entry.file_name().to_str() {
+            file_names.push(file_name.to_owned());
+        }
+    }
+
+    Ok(
+        select_segment_file(file_names.iter().map(String::as_str), segno, wal_seg_size)
+            .map(|file_name| archive_path.join(file_name)),
+    )
+}
+
+fn select_segment_file<'a>(
+    file_names: impl Iterator<Item = &'a str>,
+    segno: XLogSegNo,
+    wal_seg_size: usize,
+) -> Option<&'a str> {
+    file_names
+        .filter(|file_name| IsXLogFileName(file_name))
+        .filter_map(|file_name| {
+            let (file_segno, tli) = XLogFromFileName(file_name, wal_seg_size);
+            (file_segno == segno).then_some((tli, file_name))
+        })
+        .max_by_key(|(tli, _)| *tli)
+        .map(|(_, file_name)| file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use postgres_ffi::{XLogFileName, WAL_SEGMENT_SIZE};
+
+    use super::*;
+
+    #[test]
+    fn selects_segment_of_latest_timeline() {
+        let file_names = [
+            XLogFileName(1, 3, WAL_SEGMENT_SIZE),
+            XLogFileName(1, 4, WAL_SEGMENT_SIZE),
+            XLogFileName(2, 4, WAL_SEGMENT_SIZE),
+            format!("{}.partial", XLogFileName(3, 4, WAL_SEGMENT_SIZE)),
+            "00000002.history".to_owned(),
+        ];
+        let names = || file_names.iter().map(String::as_str);
+
+        assert_eq!(
+            select_segment_file(names(), 3, WAL_SEGMENT_SIZE),
+            Some(XLogFileName(1, 3, WAL_SEGMENT_SIZE).as_str())
+        );
+        assert_eq!(
+            select_segment_file(names(), 4, WAL_SEGMENT_SIZE),
+            Some(XLogFileName(2, 4, WAL_SEGMENT_SIZE).as_str())
+        );
+        assert_eq!(select_segment_file(names(), 5, WAL_SEGMENT_SIZE), None);
+    }
+}
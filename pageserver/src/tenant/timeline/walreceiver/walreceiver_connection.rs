@@ -5,16 +5,16 @@ use std::{
     pin::pin,
     str::FromStr,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, Context};
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use chrono::{NaiveDateTime, Utc};
 use fail::fail_point;
 use futures::StreamExt;
 use postgres::{error::SqlState, SimpleQueryMessage, SimpleQueryRow};
-use postgres_ffi::WAL_SEGMENT_SIZE;
+use postgres_ffi::MAX_SEND_SIZE;
 use postgres_ffi::{v14::xlog_utils::normalize_lsn, waldecoder::WalDecodeError};
 use postgres_protocol::message::backend::ReplicationMessage;
 use postgres_types::PgLsn;
@@ -30,7 +30,10 @@ use crate::{
     task_mgr,
     task_mgr::TaskKind,
     task_mgr::WALRECEIVER_RUNTIME,
-    tenant::{debug_assert_current_span_has_tenant_and_timeline_id, Timeline, WalReceiverInfo},
+    tenant::{
+        debug_assert_current_span_has_tenant_and_timeline_id, mgr, Tenant, Timeline,
+        WalReceiverInfo,
+    },
     walingest::WalIngest,
     walrecord::DecodedWALRecord,
 };
@@ -38,7 +41,14 @@ use postgres_backend::is_expected_io_error;
 use postgres_connection::PgConnectionConfig;
 use postgres_ffi::waldecoder::WalStreamDecoder;
 use utils::pageserver_feedback::PageserverFeedback;
-use utils::{id::NodeId, lsn::Lsn};
+use utils::{
+    id::{NodeId, RegionId},
+    lsn::Lsn,
+};
+
+/// How often to check whether the tenant is within its physical size quota,
+/// both while ingesting WAL and, while ingestion is paused, to resume it.
+const PHYSICAL_SIZE_QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Status of the connection.
 #[derive(Debug, Clone, Copy)]
@@ -116,6 +126,9 @@ pub(super) async fn handle_walreceiver_connection(
     ctx: RequestContext,
     node: NodeId,
     ingest_batch_size: u64,
+    ingest_backpressure_lag: u64,
+    wal_compression: bool,
+    wal_filter_region: Option<RegionId>,
 ) -> Result<(), WalReceiverError> {
     debug_assert_current_span_has_tenant_and_timeline_id();
 
@@ -242,19 +255,40 @@ pub(super) async fn handle_walreceiver_connection(
     // but when the compute node first starts on the branch, we normalize the first REDO position to just after the page
     // header (see generate_pg_control()), so the WAL for the page header is never streamed from the compute node
     //  to the safekeepers.
-    startpoint = normalize_lsn(startpoint, WAL_SEGMENT_SIZE);
+    let wal_seg_size = timeline
+        .get_wal_seg_size(last_rec_lsn, &ctx)
+        .await
+        .context("get WAL segment size")?;
+    startpoint = normalize_lsn(startpoint, wal_seg_size);
 
     info!("last_record_lsn {last_rec_lsn} starting replication from {startpoint}, safekeeper is at {end_of_wal}...");
 
-    let query = format!("START_REPLICATION PHYSICAL {startpoint}");
+    let mut options = Vec::new();
+    if wal_compression {
+        options.push("compression='zstd'".to_owned());
+    }
+    if let Some(region) = wal_filter_region {
+        options.push(format!("region='{region}'"));
+    }
+    let query = if options.is_empty() {
+        format!("START_REPLICATION PHYSICAL {startpoint}")
+    } else {
+        format!(
+            "START_REPLICATION PHYSICAL {startpoint} ({})",
+            options.join(", ")
+        )
+    };
 
     let copy_stream = replication_client.copy_both_simple(&query).await?;
     let mut physical_stream = pin!(ReplicationStream::new(copy_stream));
 
-    let mut waldecoder = WalStreamDecoder::new(startpoint, timeline.pg_version);
+    let mut waldecoder = WalStreamDecoder::new(startpoint, timeline.pg_version, wal_seg_size);
 
     let mut walingest = WalIngest::new(timeline.as_ref(), startpoint, &ctx).await?;
 
+    let mut slowing_down = false;
+    let mut physical_size_quota = PhysicalSizeQuotaCheck::default();
+
     while let Some(replication_message) = {
         select! {
             _ = cancellation.cancelled() => {
@@ -266,6 +300,17 @@ pub(super) async fn handle_walreceiver_connection(
     } {
         let replication_message = replication_message?;
 
+        // With compression, the WAL of each XLogData message is a zstd frame of
+        // at most MAX_SEND_SIZE bytes.
+        let wal = match &replication_message {
+            ReplicationMessage::XLogData(xlog_data) if wal_compression => Bytes::from(
+                zstd::bulk::decompress(xlog_data.data(), MAX_SEND_SIZE)
+                    .context("decompress WAL received from safekeeper")?,
+            ),
+            ReplicationMessage::XLogData(xlog_data) => xlog_data.data().clone(),
+            _ => Bytes::new(),
+        };
+
         let now = Utc::now().naive_utc();
         let last_rec_lsn_before_msg = last_rec_lsn;
 
@@ -287,10 +332,9 @@ pub(super) async fn handle_walreceiver_connection(
 
                 connection_status.latest_connection_update = now;
                 connection_status.commit_lsn = Some(Lsn::from(xlog_data.wal_end()));
-                connection_status.streaming_lsn = Some(Lsn::from(
-                    xlog_data.wal_start() + xlog_data.data().len() as u64,
-                ));
-                if !xlog_data.data().is_empty() {
+                connection_status.streaming_lsn =
+                    Some(Lsn::from(xlog_data.wal_start() + wal.len() as u64));
+                if !wal.is_empty() {
                     connection_status.latest_wal_update = now;
                 }
             }
@@ -307,9 +351,23 @@ pub(super) async fn handle_walreceiver_connection(
 
         let status_update = match replication_message {
             ReplicationMessage::XLogData(xlog_data) => {
+                // Don't ingest more WAL while the tenant is over its quota. Not reading
+                // from the stream meanwhile pushes back on the safekeeper, and the LSNs we
+                // report stop advancing, which in turn throttles the compute.
+                if !wal.is_empty() && !physical_size_quota.wait(&timeline, &cancellation).await {
+                    debug!("walreceiver interrupted while over physical size quota");
+                    return Ok(());
+                }
+                // Same with the tenant's WAL ingest rate limit.
+                if !wait_for_wal_ingest_rate_limit(&timeline, wal.len() as u64, &cancellation).await
+                {
+                    debug!("walreceiver interrupted while over WAL ingest rate limit");
+                    return Ok(());
+                }
+
                 // Pass the WAL data to the decoder, and see if we can decode
                 // more records as a result.
-                let data = xlog_data.data();
+                let data = &wal;
                 let startlsn = Lsn::from(xlog_data.wal_start());
                 let endlsn = startlsn + data.len() as u64;
 
@@ -359,6 +417,8 @@ pub(super) async fn handle_walreceiver_connection(
                         .metrics
                         .wal_replication_msg_records
                         .observe(num_records as f64);
+                    timeline.metrics.wal_ingest_bytes.inc_by(data.len() as u64);
+                    timeline.metrics.wal_ingest_records.inc_by(num_records);
                 }
 
                 if !caught_up && endlsn >= end_of_wal {
@@ -434,12 +494,26 @@ pub(super) async fn handle_walreceiver_connection(
             let (timeline_logical_size, _) = timeline
                 .get_current_logical_size(&ctx)
                 .context("Status update creation failed to get current logical size")?;
+            // Ask the compute to slow down while flushing the in-memory layers
+            // falls too far behind the ingestion.
+            let ingest_lag = last_received_lsn.0.saturating_sub(disk_consistent_lsn.0);
+            let slow_down = ingest_backpressure_lag > 0 && ingest_lag > ingest_backpressure_lag;
+            if slow_down != slowing_down {
+                if slow_down {
+                    warn!("received WAL is {ingest_lag} bytes ahead of disk_consistent_lsn {disk_consistent_lsn}, asking the compute to slow down");
+                } else {
+                    info!("caught up with flushing received WAL, disk_consistent_lsn is {disk_consistent_lsn}, no longer asking the compute to slow down");
+                }
+                slowing_down = slow_down;
+            }
+
             let status_update = PageserverFeedback {
                 current_timeline_size: timeline_logical_size,
                 last_received_lsn,
                 disk_consistent_lsn,
                 remote_consistent_lsn,
                 replytime: ts,
+                slow_down,
             };
 
             debug!("neon_status_update {status_update:?}");
@@ -456,6 +530,83 @@ pub(super) async fn handle_walreceiver_connection(
     Ok(())
 }
 
+/// The checks of the tenant's `physical_size_quota` on a walreceiver
+/// connection. The tenant is looked up once for the connection, and while it
+/// is within its quota, its size is checked at most once per
+/// [`PHYSICAL_SIZE_QUOTA_CHECK_INTERVAL`], not for each message.
+#[derive(Default)]
+struct PhysicalSizeQuotaCheck {
+    tenant: Option<Arc<Tenant>>,
+    last_check: Option<Instant>,
+}
+
+impl PhysicalSizeQuotaCheck {
+    /// Waits until the timeline's tenant is within its `physical_size_quota`,
+    /// or has none. Returns `false` if cancelled meanwhile.
+    async fn wait(&mut self, timeline: &Timeline, cancellation: &CancellationToken) -> bool {
+        if let Some(last_check) = self.last_check {
+            if last_check.elapsed() < PHYSICAL_SIZE_QUOTA_CHECK_INTERVAL {
+                return true;
+            }
+        }
+
+        let mut paused_at = None;
+        while let Some(quota) = timeline.get_physical_size_quota() {
+            let tenant = match self.tenant.take() {
+                Some(tenant) => tenant,
+                None => match mgr::get_tenant(timeline.tenant_id, true).await {
+                    Ok(tenant) => tenant,
+                    // The tenant is shutting down, which ends this connection soon anyway.
+                    Err(_) => break,
+                },
+            };
+            let resident_size = tenant.resident_physical_size();
+            self.tenant = Some(tenant);
+            if resident_size <= quota {
+                break;
+            }
+            if paused_at.is_none() {
+                warn!("tenant has {resident_size} bytes of layer files resident, over its physical size quota of {quota} bytes, pausing WAL ingestion");
+                paused_at = Some(Instant::now());
+            }
+
+            select! {
+                _ = cancellation.cancelled() => return false,
+                _ = time::sleep(PHYSICAL_SIZE_QUOTA_CHECK_INTERVAL) => {}
+            }
+        }
+        self.last_check = Some(Instant::now());
+
+        if let Some(paused_at) = paused_at {
+            info!(
+                "tenant is within its physical size quota again, resuming WAL ingestion after {:?}",
+                paused_at.elapsed()
+            );
+        }
+        true
+    }
+}
+
+/// Waits until the timeline's tenant can ingest `bytes` more bytes of WAL under
+/// its `wal_ingest_rate_limit`, if any. Returns `false` if cancelled meanwhile.
+async fn wait_for_wal_ingest_rate_limit(
+    timeline: &Timeline,
+    bytes: u64,
+    cancellation: &CancellationToken,
+) -> bool {
+    let delay = timeline
+        .throttle
+        .wal_ingest_delay(bytes, timeline.get_wal_ingest_rate_limit());
+    if delay.is_zero() {
+        return true;
+    }
+    trace!("waiting {delay:?} for the WAL ingest rate limit");
+    select! {
+        _ = cancellation.cancelled() => false,
+        _ = time::sleep(delay) => true,
+    }
+}
+
 /// Data returned from the postgres `IDENTIFY_SYSTEM` command
 ///
 /// See the [postgres docs] for more details.
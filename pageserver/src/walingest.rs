@@ -21,10 +21,7 @@
 //! redo Postgres process, but some records it can handle directly with
 //! bespoken Rust code.
 
-use postgres_ffi::v14::nonrelfile_utils::clogpage_precedes;
-use postgres_ffi::v14::nonrelfile_utils::csnlogpage_precedes;
-use postgres_ffi::v14::nonrelfile_utils::slru_may_delete_segment;
-use postgres_ffi::{fsm_logical_to_physical, page_is_new, page_set_lsn};
+use postgres_ffi::{page_is_new, page_set_lsn};
 
 use anyhow::{Context, Result};
 use bytes::{Buf, Bytes, BytesMut};
@@ -37,14 +34,21 @@ use crate::tenant::Timeline;
 use crate::walrecord::*;
 use crate::ZERO_PAGE;
 use pageserver_api::reltag::{RelTag, SlruKind};
+use postgres_ffi::bkpimage::BkpImage;
+use postgres_ffi::free_space_map::fsm_truncate_location;
 use postgres_ffi::pg_constants;
 use postgres_ffi::relfile_utils::{FSM_FORKNUM, INIT_FORKNUM, MAIN_FORKNUM, VISIBILITYMAP_FORKNUM};
+use postgres_ffi::slru::{Clog, CommitTs, CsnLog, MultiXactMembers, MultiXactOffsets, Slru};
+use postgres_ffi::twophase::TwoPhaseState;
 use postgres_ffi::v14::nonrelfile_utils::mx_offset_to_member_segment;
 use postgres_ffi::v14::xlog_utils::*;
 use postgres_ffi::v14::CheckPoint;
+use postgres_ffi::visibility_map::{vm_block, vm_nblocks};
+use postgres_ffi::walrecord::relmap::{RelmapRecord, XlRelmapUpdate};
 use postgres_ffi::TransactionId;
 use postgres_ffi::XidCSN;
 use postgres_ffi::BLCKSZ;
+use postgres_ffi::{transaction_id_precedes, FullTransactionId, TimestampTz};
 use utils::lsn::Lsn;
 
 pub struct WalIngest {
@@ -70,6 +74,11 @@ impl WalIngest {
         })
     }
 
+    /// The next XID of the checkpoint, with its epoch.
+    fn next_xid(&self) -> FullTransactionId {
+        FullTransactionId(self.checkpoint.nextXid.value)
+    }
+
     ///
     /// Decode a PostgreSQL WAL record and store it in the repository, in the given timeline.
     ///
@@ -203,6 +212,7 @@ impl WalIngest {
                     modification,
                     &parsed_xact,
                     info == pg_constants::XLOG_XACT_COMMIT,
+                    decoded.origin_id,
                     ctx,
                 )
                 .await?;
@@ -215,6 +225,7 @@ impl WalIngest {
                     modification,
                     &parsed_xact,
                     info == pg_constants::XLOG_XACT_COMMIT_PREPARED,
+                    decoded.origin_id,
                     ctx,
                 )
                 .await?;
@@ -229,8 +240,20 @@ impl WalIngest {
                     .drop_twophase_file(parsed_xact.xid, ctx)
                     .await?;
             } else if info == pg_constants::XLOG_XACT_PREPARE {
+                // The main data of the record is the two-phase state data, that
+                // becomes the pg_twophase file of the transaction. Decode it to
+                // make sure that a garbled one doesn't end up in basebackups.
+                let state = TwoPhaseState::decode(&buf, pg_version)
+                    .with_context(|| format!("invalid XLOG_XACT_PREPARE record at {lsn}"))?;
+                trace!(
+                    "Put twophaseFile for xid {} gid {} with {} subxacts here at {}",
+                    state.header.xid,
+                    state.gid,
+                    state.subxacts.len(),
+                    lsn,
+                );
                 modification
-                    .put_twophase_file(decoded.xl_xid, Bytes::copy_from_slice(&buf[..]), ctx)
+                    .put_twophase_file(state.header.xid, buf.clone(), ctx)
                     .await?;
             }
         } else if decoded.xl_rmid == pg_constants::RM_MULTIXACT_ID {
@@ -271,9 +294,9 @@ impl WalIngest {
                     .await?;
             }
         } else if decoded.xl_rmid == pg_constants::RM_RELMAP_ID {
-            let xlrec = XlRelmapUpdate::decode(&mut buf);
-            self.ingest_relmap_page(modification, &xlrec, decoded, ctx)
-                .await?;
+            let info = decoded.xl_info & pg_constants::XLR_RMGR_INFO_MASK;
+            let RelmapRecord::Update(xlrec) = RelmapRecord::decode(info, &mut buf)?;
+            self.ingest_relmap_update(modification, &xlrec, ctx).await?;
         } else if decoded.xl_rmid == pg_constants::RM_XLOG_ID {
             let info = decoded.xl_info & pg_constants::XLR_RMGR_INFO_MASK;
             if info == pg_constants::XLOG_NEXTOID {
@@ -302,6 +325,23 @@ impl WalIngest {
                     self.checkpoint.oldestXid = xlog_checkpoint.oldestXid;
                     self.checkpoint_modified = true;
                 }
+                // Our nextXid follows the XIDs of the records, and only
+                // guesses the epoch from their wraparounds. The checkpoints
+                // carry the epoch of the compute, catch up if it's ahead.
+                let xlog_next_xid = FullTransactionId(xlog_checkpoint.nextXid.value);
+                if xlog_next_xid > self.next_xid() {
+                    trace!(
+                        "xlog_checkpoint.nextXid={}, checkpoint.nextXid={}",
+                        xlog_next_xid,
+                        self.next_xid()
+                    );
+                    self.checkpoint.nextXid = xlog_checkpoint.nextXid;
+                    self.checkpoint_modified = true;
+                }
+            } else if info == pg_constants::XLOG_PARAMETER_CHANGE {
+                let xlrec = XlParameterChange::decode(&mut buf);
+                self.ingest_parameter_change(modification, &xlrec, ctx)
+                    .await?;
             }
         } else if decoded.xl_rmid == pg_constants::RM_LOGICALMSG_ID {
             let info = decoded.xl_info & pg_constants::XLR_RMGR_INFO_MASK;
@@ -335,6 +375,26 @@ impl WalIngest {
             // We don't handle assignment and set records to avoid the
             // ambiguity of the commit order. Instead we rely on the
             // XLOG records to determine the LSN and thus commit order.
+        } else if decoded.xl_rmid == pg_constants::RM_COMMIT_TS_ID {
+            let info = decoded.xl_info & !pg_constants::XLR_INFO_MASK;
+            if info == pg_constants::COMMIT_TS_ZEROPAGE {
+                let pageno = buf.get_u32_le();
+                let (segno, rpageno) = CommitTs::segment_block(pageno);
+                self.put_slru_page_image(
+                    modification,
+                    SlruKind::CommitTs,
+                    segno,
+                    rpageno,
+                    ZERO_PAGE.clone(),
+                    ctx,
+                )
+                .await?;
+            } else {
+                assert!(info == pg_constants::COMMIT_TS_TRUNCATE);
+                let xlrec = XlCommitTsTruncate::decode(&mut buf);
+                self.ingest_commit_ts_truncate_record(modification, &xlrec, ctx)
+                    .await?;
+            }
         }
 
         // Iterate through all the blocks that the record modifies, and
@@ -385,20 +445,19 @@ impl WalIngest {
             && decoded.xl_rmid == pg_constants::RM_XLOG_ID
             && (decoded.xl_info == pg_constants::XLOG_FPI
                 || decoded.xl_info == pg_constants::XLOG_FPI_FOR_HINT)
-        // compression of WAL is not yet supported: fall back to storing the original WAL record
-            && !postgres_ffi::bkpimage_is_compressed(blk.bimg_info, modification.tline.pg_version)?
         {
-            // Extract page image from FPI record
+            // Extract page image from FPI record, decompressing it if the
+            // compute runs with wal_compression
             let img_len = blk.bimg_len as usize;
             let img_offs = blk.bimg_offset as usize;
-            let mut image = BytesMut::with_capacity(BLCKSZ as usize);
-            image.extend_from_slice(&decoded.record[img_offs..img_offs + img_len]);
-
-            if blk.hole_length != 0 {
-                let tail = image.split_off(blk.hole_offset as usize);
-                image.resize(image.len() + blk.hole_length as usize, 0u8);
-                image.unsplit(tail);
-            }
+            let bkpimage = BkpImage::new(
+                blk.bimg_info,
+                blk.hole_offset,
+                blk.hole_length,
+                decoded.record.slice(img_offs..img_offs + img_len),
+                modification.tline.pg_version,
+            )?;
+            let mut image = BytesMut::from(&bkpimage.restore()?[..]);
             //
             // Match the logic of XLogReadBufferForRedoExtended:
             // The page may be uninitialized. If so, we can't set the LSN because
@@ -705,18 +764,16 @@ impl WalIngest {
                 forknum: FSM_FORKNUM,
             };
 
-            let fsm_logical_page_no = rec.blkno / pg_constants::SLOTS_PER_FSM_PAGE;
-            let mut fsm_physical_page_no = fsm_logical_to_physical(fsm_logical_page_no);
-            if rec.blkno % pg_constants::SLOTS_PER_FSM_PAGE != 0 {
+            let (fsm_nblocks, fsm_tail) = fsm_truncate_location(rec.blkno);
+            if let Some((fsm_physical_page_no, _)) = fsm_tail {
                 // Tail of last remaining FSM page has to be zeroed.
                 // We are not precise here and instead of digging in FSM bitmap format just clear the whole page.
                 modification.put_rel_page_image(rel, fsm_physical_page_no, ZERO_PAGE.clone())?;
-                fsm_physical_page_no += 1;
             }
             let nblocks = get_relsize(modification, rel, ctx).await?;
-            if nblocks > fsm_physical_page_no {
+            if nblocks > fsm_nblocks {
                 // check if something to do: FSM is larger than truncate position
-                self.put_rel_truncation(modification, rel, fsm_physical_page_no, ctx)
+                self.put_rel_truncation(modification, rel, fsm_nblocks, ctx)
                     .await?;
             }
         }
@@ -728,17 +785,16 @@ impl WalIngest {
                 forknum: VISIBILITYMAP_FORKNUM,
             };
 
-            let mut vm_page_no = rec.blkno / pg_constants::VM_HEAPBLOCKS_PER_PAGE;
-            if rec.blkno % pg_constants::VM_HEAPBLOCKS_PER_PAGE != 0 {
+            let new_vm_nblocks = vm_nblocks(rec.blkno);
+            if new_vm_nblocks != vm_block(rec.blkno) {
                 // Tail of last remaining vm page has to be zeroed.
                 // We are not precise here and instead of digging in VM bitmap format just clear the whole page.
-                modification.put_rel_page_image(rel, vm_page_no, ZERO_PAGE.clone())?;
-                vm_page_no += 1;
+                modification.put_rel_page_image(rel, vm_block(rec.blkno), ZERO_PAGE.clone())?;
             }
             let nblocks = get_relsize(modification, rel, ctx).await?;
-            if nblocks > vm_page_no {
+            if nblocks > new_vm_nblocks {
                 // check if something to do: VM is larger than truncate position
-                self.put_rel_truncation(modification, rel, vm_page_no, ctx)
+                self.put_rel_truncation(modification, rel, new_vm_nblocks, ctx)
                     .await?;
             }
         }
@@ -752,16 +808,17 @@ impl WalIngest {
         modification: &mut DatadirModification<'_>,
         parsed: &XlXactParsedRecord,
         is_commit: bool,
+        origin_id: u16,
         ctx: &RequestContext,
     ) -> anyhow::Result<()> {
         // Record update of CLOG pages
-        let mut pageno = parsed.xid / pg_constants::CLOG_XACTS_PER_PAGE;
-        let mut segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-        let mut rpageno = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+        let (region, max_regions) = timeline_region(modification.tline)?;
+        let mut pageno = Clog::logical_page(parsed.xid);
+        let (mut segno, mut rpageno) = Clog::segment_block(pageno);
         let mut page_xids: Vec<TransactionId> = vec![parsed.xid];
 
         for subxact in &parsed.subxacts {
-            let subxact_pageno = subxact / pg_constants::CLOG_XACTS_PER_PAGE;
+            let subxact_pageno = Clog::logical_page(*subxact);
             if subxact_pageno != pageno {
                 // This subxact goes to different page. Write the record
                 // for all the XIDs on the previous page, and continue
@@ -782,8 +839,7 @@ impl WalIngest {
                 page_xids = Vec::new();
             }
             pageno = subxact_pageno;
-            segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-            rpageno = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+            (segno, rpageno) = Clog::segment_block(pageno);
             page_xids.push(*subxact);
         }
         modification.put_slru_wal_record(
@@ -801,19 +857,13 @@ impl WalIngest {
         )?;
 
         // Record update of CSN pages.
-        let region = modification.tline.region_id.0 as u32;
-        let mut csn_pageno = ((parsed.xid / pg_constants::CSN_LOG_XACTS_PER_PAGE)
-            * pg_constants::MAX_REGIONS)
-            + region;
-        let mut csn_segno = csn_pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-        let mut csn_rpageno = csn_pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+        let mut csn_pageno = CsnLog::page(parsed.xid, region, max_regions);
+        let (mut csn_segno, mut csn_rpageno) = CsnLog::segment_block(csn_pageno);
         let mut csn_page_xids: Vec<TransactionId> = vec![parsed.xid];
         let lsn: XidCSN = modification.get_lsn().0;
 
         for subxact in &parsed.subxacts {
-            let csn_subxact_pageno = ((subxact / pg_constants::CSN_LOG_XACTS_PER_PAGE)
-                * pg_constants::MAX_REGIONS)
-                + region;
+            let csn_subxact_pageno = CsnLog::page(*subxact, region, max_regions);
             if csn_subxact_pageno != csn_pageno {
                 // This subxact goes to different page. Write the record
                 // for all the XIDs on the previous page, and continue
@@ -838,8 +888,7 @@ impl WalIngest {
                 csn_page_xids = Vec::new();
             }
             csn_pageno = csn_subxact_pageno;
-            csn_segno = csn_pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-            csn_rpageno = csn_pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+            (csn_segno, csn_rpageno) = CsnLog::segment_block(csn_pageno);
             csn_page_xids.push(*subxact);
         }
         modification.put_slru_wal_record(
@@ -860,6 +909,12 @@ impl WalIngest {
             },
         )?;
 
+        // Record the commit timestamps, when track_commit_timestamp is on
+        if is_commit && self.checkpoint.oldestCommitTsXid != pg_constants::INVALID_TRANSACTION_ID {
+            self.ingest_commit_ts(modification, parsed, origin_id, ctx)
+                .await?;
+        }
+
         for xnode in &parsed.xnodes {
             for forknum in MAIN_FORKNUM..=INIT_FORKNUM {
                 let rel = RelTag {
@@ -904,40 +959,218 @@ impl WalIngest {
 
         // TODO Treat AdvanceOldestClogXid() or write a comment why we don't need it
 
-        let latest_page_number =
-            self.checkpoint.nextXid.value as u32 / pg_constants::CLOG_XACTS_PER_PAGE;
+        let latest_page_number = Clog::logical_page(self.next_xid().xid());
+        self.truncate_slru::<Clog>(
+            modification,
+            SlruKind::Clog,
+            xlrec.pageno,
+            latest_page_number,
+            ctx,
+        )
+        .await
+    }
 
-        // Now delete all segments containing pages between xlrec.pageno
-        // and latest_page_number.
+    /// Sets the commit timestamp of a committed transaction and of its
+    /// subtransactions, like TransactionTreeSetCommitTsData() does.
+    async fn ingest_commit_ts(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        parsed: &XlXactParsedRecord,
+        origin_id: u16,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        let timestamp = parsed.commit_time();
+        let mut pageno = CommitTs::logical_page(parsed.xid);
+        let mut page_xids: Vec<TransactionId> = vec![parsed.xid];
 
-        // First, make an important safety check:
-        // the current endpoint page must not be eligible for removal.
-        // See SimpleLruTruncate() in slru.c
-        if clogpage_precedes(latest_page_number, xlrec.pageno) {
-            info!("could not truncate directory pg_xact apparent wraparound");
-            return Ok(());
+        for subxact in &parsed.subxacts {
+            let subxact_pageno = CommitTs::logical_page(*subxact);
+            if subxact_pageno != pageno {
+                let xids = std::mem::take(&mut page_xids);
+                self.put_commit_ts_record(modification, pageno, xids, timestamp, origin_id, ctx)
+                    .await?;
+            }
+            pageno = subxact_pageno;
+            page_xids.push(*subxact);
         }
+        self.put_commit_ts_record(modification, pageno, page_xids, timestamp, origin_id, ctx)
+            .await?;
 
-        // Iterate via SLRU CLOG segments and drop segments that we're ready to truncate
-        //
-        // We cannot pass 'lsn' to the Timeline.list_nonrels(), or it
-        // will block waiting for the last valid LSN to advance up to
-        // it. So we use the previous record's LSN in the get calls
-        // instead.
-        for segno in modification
+        // The checkpoints store the newest transaction with a commit
+        // timestamp, beyond which postgres doesn't look for them.
+        let newest_xid = parsed.subxacts.iter().fold(parsed.xid, |newest, &xid| {
+            if transaction_id_precedes(newest, xid) {
+                xid
+            } else {
+                newest
+            }
+        });
+        if transaction_id_precedes(self.checkpoint.newestCommitTsXid, newest_xid) {
+            self.checkpoint.newestCommitTsXid = newest_xid;
+            self.checkpoint_modified = true;
+        }
+        Ok(())
+    }
+
+    async fn put_commit_ts_record(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        pageno: u32,
+        xids: Vec<TransactionId>,
+        timestamp: TimestampTz,
+        origin_id: u16,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        // The page zeroed by the compute when it starts tracking commit
+        // timestamps is not WAL-logged, see ActivateCommitTs(), and the compute
+        // may be ahead of our nextXid. The page of the newest timestamp is
+        // known to exist, other ones are created if they are missing.
+        if pageno != CommitTs::logical_page(self.checkpoint.newestCommitTsXid) {
+            self.ensure_commit_ts_page(modification, pageno, ctx)
+                .await?;
+        }
+
+        let (segno, rpageno) = CommitTs::segment_block(pageno);
+        modification.put_slru_wal_record(
+            SlruKind::CommitTs,
+            segno,
+            rpageno,
+            NeonWalRecord::CommitTsSetTimestamp {
+                xids,
+                timestamp,
+                origin_id,
+            },
+        )
+    }
+
+    async fn ensure_commit_ts_page(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        pageno: u32,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        let (segno, rpageno) = CommitTs::segment_block(pageno);
+        let kind = SlruKind::CommitTs;
+        let exists = modification
             .tline
-            .list_slru_segments(SlruKind::Clog, Version::Modified(modification), ctx)
+            .get_slru_segment_exists(kind, segno, Version::Modified(modification), ctx)
             .await?
+            && rpageno
+                < modification
+                    .tline
+                    .get_slru_segment_size(kind, segno, Version::Modified(modification), ctx)
+                    .await?;
+        if !exists {
+            self.put_slru_page_image(modification, kind, segno, rpageno, ZERO_PAGE.clone(), ctx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn ingest_commit_ts_truncate_record(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        xlrec: &XlCommitTsTruncate,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        info!(
+            "RM_COMMIT_TS_ID truncate pageno {} oldestXid {}",
+            xlrec.pageno, xlrec.oldest_xid
+        );
+
+        // See AdvanceOldestCommitTsXid()
+        if transaction_id_precedes(self.checkpoint.oldestCommitTsXid, xlrec.oldest_xid)
+            && self.checkpoint.oldestCommitTsXid != pg_constants::INVALID_TRANSACTION_ID
         {
-            let segpage = segno * pg_constants::SLRU_PAGES_PER_SEGMENT;
-            if slru_may_delete_segment(segpage, xlrec.pageno, clogpage_precedes) {
+            self.checkpoint.oldestCommitTsXid = xlrec.oldest_xid;
+            self.checkpoint_modified = true;
+        }
+
+        let latest_page_number = CommitTs::logical_page(self.next_xid().xid());
+        self.truncate_slru::<CommitTs>(
+            modification,
+            SlruKind::CommitTs,
+            xlrec.pageno,
+            latest_page_number,
+            ctx,
+        )
+        .await
+    }
+
+    /// Subroutine of ingest_record(), to handle an XLOG_PARAMETER_CHANGE
+    /// record. Starts or stops maintaining the commit timestamps when
+    /// track_commit_timestamp changes, like CommitTsParameterChange() does.
+    async fn ingest_parameter_change(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        xlrec: &XlParameterChange,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        let active = self.checkpoint.oldestCommitTsXid != pg_constants::INVALID_TRANSACTION_ID;
+        if xlrec.track_commit_timestamp && !active {
+            // See ActivateCommitTs()
+            let next_xid = self.next_xid().xid();
+            info!("start tracking commit timestamps at xid {}", next_xid);
+            self.checkpoint.oldestCommitTsXid = next_xid;
+            self.checkpoint.newestCommitTsXid = next_xid;
+            self.checkpoint_modified = true;
+
+            self.ensure_commit_ts_page(modification, CommitTs::logical_page(next_xid), ctx)
+                .await?;
+        } else if !xlrec.track_commit_timestamp && active {
+            // See DeactivateCommitTs(), which removes all the segments
+            info!("stop tracking commit timestamps");
+            self.checkpoint.oldestCommitTsXid = pg_constants::INVALID_TRANSACTION_ID;
+            self.checkpoint.newestCommitTsXid = pg_constants::INVALID_TRANSACTION_ID;
+            self.checkpoint_modified = true;
+
+            let segments = modification
+                .tline
+                .list_slru_segments(SlruKind::CommitTs, Version::Modified(modification), ctx)
+                .await?;
+            for segno in segments {
                 modification
-                    .drop_slru_segment(SlruKind::Clog, segno, ctx)
+                    .drop_slru_segment(SlruKind::CommitTs, segno, ctx)
                     .await?;
-                trace!("Drop CLOG segment {:>04X}", segno);
             }
         }
+        Ok(())
+    }
 
+    /// Drops the segments of an SLRU that only hold pages preceding
+    /// `cutoff_page`, like SimpleLruTruncate() in slru.c does.
+    async fn truncate_slru<S: Slru>(
+        &mut self,
+        modification: &mut DatadirModification<'_>,
+        kind: SlruKind,
+        cutoff_page: u32,
+        latest_page_number: u32,
+        ctx: &RequestContext,
+    ) -> Result<()> {
+        // We cannot pass 'lsn' to the Timeline.list_nonrels(), or it
+        // will block waiting for the last valid LSN to advance up to
+        // it. So we use the previous record's LSN in the get calls
+        // instead.
+        let segments = modification
+            .tline
+            .list_slru_segments(kind, Version::Modified(modification), ctx)
+            .await?;
+
+        // The current endpoint page must not be eligible for removal.
+        let max_regions = modification.tline.get_max_regions();
+        let Some(segments) =
+            S::segments_to_truncate(segments, cutoff_page, latest_page_number, max_regions)
+        else {
+            info!(
+                "could not truncate directory {} apparent wraparound",
+                S::DIR
+            );
+            return Ok(());
+        };
+        for segno in segments {
+            modification.drop_slru_segment(kind, segno, ctx).await?;
+            trace!("Drop {} segment {:>04X}", S::DIR, segno);
+        }
         Ok(())
     }
 
@@ -947,12 +1180,9 @@ impl WalIngest {
         xlrec: &XlMultiXactCreate,
     ) -> Result<()> {
         // Create WAL record for updating the multixact-offsets page
-        let region = modification.tline.region_id.0 as u32;
-        let pageno = ((xlrec.mid / pg_constants::MULTIXACT_OFFSETS_PER_PAGE as u32)
-            * pg_constants::MAX_REGIONS)
-            + region;
-        let segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-        let rpageno = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+        let (region, max_regions) = timeline_region(modification.tline)?;
+        let (segno, rpageno) =
+            MultiXactOffsets::segment_block(MultiXactOffsets::page(xlrec.mid, region, max_regions));
 
         modification.put_slru_wal_record(
             SlruKind::MultiXactOffsets,
@@ -969,11 +1199,11 @@ impl WalIngest {
         let mut members = xlrec.members.iter();
         let mut offset = xlrec.moff;
         loop {
-            let pageno = offset / pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32;
+            let pageno = MultiXactMembers::page(offset, region, max_regions);
 
             // How many members fit on this page?
-            let page_remain = pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32
-                - offset % pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32;
+            let page_remain =
+                MultiXactMembers::ENTRIES_PER_PAGE - MultiXactMembers::entry_in_page(offset);
 
             let mut this_page_members: Vec<MultiXactMember> = Vec::new();
             for _ in 0..page_remain {
@@ -989,10 +1219,11 @@ impl WalIngest {
             }
             let n_this_page = this_page_members.len();
 
+            let (segno, rpageno) = MultiXactMembers::segment_block(pageno);
             modification.put_slru_wal_record(
                 SlruKind::MultiXactMembers,
-                pageno / pg_constants::SLRU_PAGES_PER_SEGMENT,
-                pageno % pg_constants::SLRU_PAGES_PER_SEGMENT,
+                segno,
+                rpageno,
                 NeonWalRecord::MultixactMembersCreate {
                     moff: offset,
                     members: this_page_members,
@@ -1036,11 +1267,13 @@ impl WalIngest {
         self.checkpoint_modified = true;
 
         // PerformMembersTruncation
-        let region = modification.tline.region_id.0 as u32;
+        let (region, max_regions) = timeline_region(modification.tline)?;
         let maxsegment: i32 =
-            mx_offset_to_member_segment(pg_constants::MAX_MULTIXACT_OFFSET, region);
-        let startsegment: i32 = mx_offset_to_member_segment(xlrec.start_trunc_memb, region);
-        let endsegment: i32 = mx_offset_to_member_segment(xlrec.end_trunc_memb, region);
+            mx_offset_to_member_segment(pg_constants::MAX_MULTIXACT_OFFSET, region, max_regions);
+        let startsegment: i32 =
+            mx_offset_to_member_segment(xlrec.start_trunc_memb, region, max_regions);
+        let endsegment: i32 =
+            mx_offset_to_member_segment(xlrec.end_trunc_memb, region, max_regions);
         let mut segment: i32 = startsegment;
 
         // Delete all the segments except the last one. The last segment can still
@@ -1064,25 +1297,31 @@ impl WalIngest {
         Ok(())
     }
 
-    async fn ingest_relmap_page(
+    /// Stores the new map file of an XLOG_RELMAP_UPDATE record, like
+    /// relmap_redo() writes it. The shared map has database 0.
+    async fn ingest_relmap_update(
         &mut self,
         modification: &mut DatadirModification<'_>,
         xlrec: &XlRelmapUpdate,
-        decoded: &DecodedWALRecord,
         ctx: &RequestContext,
     ) -> Result<()> {
-        let mut buf = decoded.record.clone();
-        buf.advance(decoded.main_data_offset);
-        // skip xl_relmap_update
-        buf.advance(12);
+        // A broken map file would only be noticed by the compute, when it
+        // starts from a basebackup.
+        let map = xlrec.map_file().with_context(|| {
+            format!(
+                "invalid relation map update of database {} tablespace {}",
+                xlrec.dbid, xlrec.tsid
+            )
+        })?;
+        trace!(
+            "relmap update of database {} tablespace {}: {} mappings",
+            xlrec.dbid,
+            xlrec.tsid,
+            map.mappings.len()
+        );
 
         modification
-            .put_relmap_file(
-                xlrec.tsid,
-                xlrec.dbid,
-                Bytes::copy_from_slice(&buf[..]),
-                ctx,
-            )
+            .put_relmap_file(xlrec.tsid, xlrec.dbid, xlrec.data.clone(), ctx)
             .await
     }
 
@@ -1094,37 +1333,10 @@ impl WalIngest {
     ) -> Result<()> {
         info!("XLOG_CSN_TRUNCATE truncate pageno {} ", pageno);
 
-        let latest_page_number =
-            self.checkpoint.nextXid.value as u32 / pg_constants::CSN_LOG_XACTS_PER_PAGE;
-
-        // First, make an important safety check:
-        // the current endpoint page must not be eligible for removal.
-        // See SimpleLruTruncate() in slru.c
-        if csnlogpage_precedes(latest_page_number, pageno) {
-            info!("could not truncate directory pg_csn apparent wraparound");
-            return Ok(());
-        }
-
-        // Iterate via SLRU CsnLog segments and drop segments that we're ready to truncate
-        //
-        // We cannot pass 'lsn' to the Timeline.list_nonrels(), or it
-        // will block waiting for the last valid LSN to advance up to
-        // it. So we use the previous record's LSN in the get calls
-        // instead.
-        for segno in modification
-            .tline
-            .list_slru_segments(SlruKind::Csn, Version::Modified(modification), ctx)
-            .await?
-        {
-            let segpage = segno * pg_constants::SLRU_PAGES_PER_SEGMENT;
-            if slru_may_delete_segment(segpage, pageno, csnlogpage_precedes) {
-                modification
-                    .drop_slru_segment(SlruKind::Csn, segno, ctx)
-                    .await?;
-                trace!("Drop Csn segment {:>04X}", segno);
-            }
-        }
-        Ok(())
+        let (region, max_regions) = timeline_region(modification.tline)?;
+        let latest_page_number = CsnLog::page(self.next_xid().xid(), region, max_regions);
+        self.truncate_slru::<CsnLog>(modification, SlruKind::Csn, pageno, latest_page_number, ctx)
+            .await
     }
 
     async fn put_rel_creation(
@@ -1314,6 +1526,19 @@ async fn get_relsize(
     Ok(nblocks)
 }
 
+/// Region of `timeline` and the number of regions interleaved in the pages of
+/// the multi-region SLRUs of its tenant.
+fn timeline_region(timeline: &Timeline) -> Result<(u32, u32)> {
+    let region = u32::from(timeline.region_id.0);
+    let max_regions = timeline.get_max_regions();
+    anyhow::ensure!(
+        region < max_regions,
+        "region {region} of timeline {} is out of range for max_regions {max_regions}",
+        timeline.timeline_id
+    );
+    Ok((region, max_regions))
+}
+
 #[allow(clippy::bool_assert_comparison)]
 #[cfg(test)]
 mod tests {
@@ -1569,6 +1794,56 @@ mod tests {
         Ok(())
     }
 
+    // WAL ingestion keeps the relation size cache up to date, so that the
+    // size lookups of the latest version don't read the layers.
+    #[tokio::test]
+    async fn test_relsize_cache() -> Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_relsize_cache")?.load().await;
+        let tline = tenant
+            .create_test_timeline(TIMELINE_ID, Lsn(8), DEFAULT_PG_VERSION, RegionId(0), &ctx)
+            .await?;
+        let mut walingest = init_walingest_test(&tline, &ctx).await?;
+
+        let mut m = tline.begin_modification(Lsn(0x20));
+        walingest
+            .put_rel_page_image(&mut m, TESTREL_A, 0, TEST_IMG("foo blk 0 at 2"), &ctx)
+            .await?;
+        m.commit().await?;
+        let mut m = tline.begin_modification(Lsn(0x30));
+        walingest
+            .put_rel_page_image(&mut m, TESTREL_A, 4, TEST_IMG("foo blk 4 at 3"), &ctx)
+            .await?;
+        m.commit().await?;
+
+        assert_eq!(tline.get_cached_rel_size(&TESTREL_A, Lsn(0x30)), Some(5));
+        // The cache only knows the latest size.
+        assert_eq!(tline.get_cached_rel_size(&TESTREL_A, Lsn(0x20)), None);
+        assert_eq!(
+            tline
+                .get_rel_size(TESTREL_A, Version::Lsn(Lsn(0x20)), false, &ctx)
+                .await?,
+            1
+        );
+
+        assert_eq!(
+            tline.list_rel_sizes(Lsn(0x30), &ctx).await?,
+            vec![(TESTREL_A, 5, Some(Lsn(0x30)))]
+        );
+        assert_eq!(
+            tline.list_rel_sizes(Lsn(0x20), &ctx).await?,
+            vec![(TESTREL_A, 1, Some(Lsn(0x30)))]
+        );
+
+        // Dropping the relation drops its entry.
+        let mut m = tline.begin_modification(Lsn(0x40));
+        walingest.put_rel_drop(&mut m, TESTREL_A, &ctx).await?;
+        m.commit().await?;
+        assert_eq!(tline.get_cached_rel_size(&TESTREL_A, Lsn(0x40)), None);
+        assert!(tline.list_rel_sizes(Lsn(0x40), &ctx).await?.is_empty());
+
+        Ok(())
+    }
+
     // Test what happens if we dropped a relation
     // and then created it again within the same layer.
     #[tokio::test]
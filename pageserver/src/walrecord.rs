@@ -56,6 +56,13 @@ pub enum NeonWalRecord {
         xids: Vec<TransactionId>,
         region: u32,
     },
+    /// Set the commit timestamp and replication origin of transaction IDs on a
+    /// pg_commit_ts page
+    CommitTsSetTimestamp {
+        xids: Vec<TransactionId>,
+        timestamp: TimestampTz,
+        origin_id: u16,
+    },
 }
 
 impl NeonWalRecord {
@@ -115,7 +122,8 @@ pub struct DecodedWALRecord {
     pub xl_xid: TransactionId,
     pub xl_info: u8,
     pub xl_rmid: u8,
-    pub record: Bytes, // raw XLogRecord
+    pub origin_id: u16, // replication origin, InvalidRepOriginId (0) if none
+    pub record: Bytes,  // raw XLogRecord
 
     pub blocks: Vec<DecodedBkpBlock>,
     pub main_data_offset: usize,
@@ -129,24 +137,6 @@ pub struct RelFileNode {
     pub relnode: Oid, /* relation */
 }
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct XlRelmapUpdate {
-    pub dbid: Oid,   /* database ID, or 0 for shared map */
-    pub tsid: Oid,   /* database's tablespace, or pg_global */
-    pub nbytes: i32, /* size of relmap data */
-}
-
-impl XlRelmapUpdate {
-    pub fn decode(buf: &mut Bytes) -> XlRelmapUpdate {
-        XlRelmapUpdate {
-            dbid: buf.get_u32_le(),
-            tsid: buf.get_u32_le(),
-            nbytes: buf.get_i32_le(),
-        }
-    }
-}
-
 #[repr(C)]
 #[derive(Debug)]
 pub struct XlSmgrCreate {
@@ -340,6 +330,9 @@ pub struct XlXactParsedRecord {
     pub subxacts: Vec<TransactionId>,
 
     pub xnodes: Vec<RelFileNode>,
+
+    pub origin_lsn: u64,
+    pub origin_timestamp: TimestampTz,
 }
 
 impl XlXactParsedRecord {
@@ -416,6 +409,22 @@ impl XlXactParsedRecord {
         if xinfo & pg_constants::XACT_XINFO_HAS_TWOPHASE != 0 {
             xid = buf.get_u32_le();
             debug!("XLOG_XACT_COMMIT-XACT_XINFO_HAS_TWOPHASE xid {}", xid);
+
+            if xinfo & pg_constants::XACT_XINFO_HAS_GID != 0 {
+                // Skip the nul-terminated GID
+                let gid_len = buf
+                    .iter()
+                    .position(|&b| b == 0)
+                    .map_or(buf.len(), |n| n + 1);
+                buf.advance(gid_len);
+            }
+        }
+
+        let mut origin_lsn = 0;
+        let mut origin_timestamp = 0;
+        if xinfo & pg_constants::XACT_XINFO_HAS_ORIGIN != 0 {
+            origin_lsn = buf.get_u64_le();
+            origin_timestamp = buf.get_i64_le();
         }
 
         XlXactParsedRecord {
@@ -427,6 +436,18 @@ impl XlXactParsedRecord {
             ts_id,
             subxacts,
             xnodes,
+            origin_lsn,
+            origin_timestamp,
+        }
+    }
+
+    /// Time to record as the commit timestamp of the transaction. That's the
+    /// one of the origin for replicated transactions, like in xact_redo_commit.
+    pub fn commit_time(&self) -> TimestampTz {
+        if self.xinfo & pg_constants::XACT_XINFO_HAS_ORIGIN != 0 {
+            self.origin_timestamp
+        } else {
+            self.xact_time
         }
     }
 }
@@ -449,6 +470,52 @@ impl XlClogTruncate {
     }
 }
 
+#[repr(C)]
+#[derive(Debug)]
+pub struct XlCommitTsTruncate {
+    pub pageno: u32,
+    pub oldest_xid: TransactionId,
+}
+
+impl XlCommitTsTruncate {
+    pub fn decode(buf: &mut Bytes) -> XlCommitTsTruncate {
+        XlCommitTsTruncate {
+            pageno: buf.get_u32_le(),
+            oldest_xid: buf.get_u32_le(),
+        }
+    }
+}
+
+/// XLOG_PARAMETER_CHANGE record, logged when a setting that matters to
+/// standbys changes.
+#[repr(C)]
+#[derive(Debug)]
+pub struct XlParameterChange {
+    pub max_connections: i32,
+    pub max_worker_processes: i32,
+    pub max_wal_senders: i32,
+    pub max_prepared_xacts: i32,
+    pub max_locks_per_xact: i32,
+    pub wal_level: i32,
+    pub wal_log_hints: bool,
+    pub track_commit_timestamp: bool,
+}
+
+impl XlParameterChange {
+    pub fn decode(buf: &mut Bytes) -> XlParameterChange {
+        XlParameterChange {
+            max_connections: buf.get_i32_le(),
+            max_worker_processes: buf.get_i32_le(),
+            max_wal_senders: buf.get_i32_le(),
+            max_prepared_xacts: buf.get_i32_le(),
+            max_locks_per_xact: buf.get_i32_le(),
+            wal_level: buf.get_i32_le(),
+            wal_log_hints: buf.get_u8() != 0,
+            track_commit_timestamp: buf.get_u8() != 0,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct MultiXactMember {
@@ -551,6 +618,7 @@ pub fn decode_wal_record(
     let mut rnode_dbnode: u32 = 0;
     let mut rnode_relnode: u32 = 0;
     let mut got_rnode = false;
+    let mut origin_id = 0;
 
     let mut buf = record.clone();
 
@@ -598,7 +666,7 @@ pub fn decode_wal_record(
 
             pg_constants::XLR_BLOCK_ID_ORIGIN => {
                 // RepOriginId is uint16
-                buf.advance(2);
+                origin_id = buf.get_u16_le();
             }
 
             pg_constants::XLR_BLOCK_ID_TOPLEVEL_XID => {
@@ -796,6 +864,7 @@ pub fn decode_wal_record(
     decoded.xl_xid = xlogrec.xl_xid;
     decoded.xl_info = xlogrec.xl_info;
     decoded.xl_rmid = xlogrec.xl_rmid;
+    decoded.origin_id = origin_id;
     decoded.record = record;
     decoded.main_data_offset = main_data_offset;
 
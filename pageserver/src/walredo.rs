@@ -50,10 +50,12 @@ use crate::{config::PageServerConf, TEMP_FILE_SUFFIX};
 use pageserver_api::reltag::{RelTag, SlruKind};
 use postgres_ffi::pg_constants;
 use postgres_ffi::relfile_utils::VISIBILITYMAP_FORKNUM;
+use postgres_ffi::slru::{Clog, CommitTs, CsnLog, MultiXactMembers, MultiXactOffsets, Slru};
 use postgres_ffi::v14::nonrelfile_utils::{
     mx_offset_to_flags_bitshift, mx_offset_to_flags_offset, mx_offset_to_member_offset,
-    transaction_id_set_csn, transaction_id_set_status,
+    transaction_id_set_commit_ts, transaction_id_set_csn, transaction_id_set_status,
 };
+use postgres_ffi::visibility_map::{vm_block, vm_clear_flags};
 use postgres_ffi::BLCKSZ;
 
 ///
@@ -78,7 +80,8 @@ pub trait WalRedoManager: Send + Sync {
     ///
     /// The caller passes an old page image, and WAL records that should be
     /// applied over it. The return value is a new page image, after applying
-    /// the reords.
+    /// the reords. `max_regions` is the number of regions interleaved in the
+    /// multi-region SLRUs, see [`postgres_ffi::slru`].
     fn request_redo(
         &self,
         key: Key,
@@ -86,6 +89,7 @@ pub trait WalRedoManager: Send + Sync {
         base_img: Option<(Lsn, Bytes)>,
         records: Vec<(Lsn, NeonWalRecord)>,
         pg_version: u32,
+        max_regions: u32,
     ) -> Result<Bytes, WalRedoError>;
 }
 
@@ -134,6 +138,16 @@ fn can_apply_in_neon(rec: &NeonWalRecord) -> bool {
     }
 }
 
+/// Checks that a record of a multi-region SLRU is for one of the `max_regions`
+/// regions its pages are interleaved by.
+fn check_region(region: u32, max_regions: u32) -> Result<(), WalRedoError> {
+    if region >= max_regions {
+        error!("WAL record for region {region} is out of range for max_regions {max_regions}");
+        return Err(WalRedoError::InvalidRecord);
+    }
+    Ok(())
+}
+
 /// An error happened in WAL redo
 #[derive(Debug, thiserror::Error)]
 pub enum WalRedoError {
@@ -165,6 +179,7 @@ impl WalRedoManager for PostgresRedoManager {
         base_img: Option<(Lsn, Bytes)>,
         records: Vec<(Lsn, NeonWalRecord)>,
         pg_version: u32,
+        max_regions: u32,
     ) -> Result<Bytes, WalRedoError> {
         if records.is_empty() {
             error!("invalid WAL redo request with no records");
@@ -180,7 +195,7 @@ impl WalRedoManager for PostgresRedoManager {
 
             if rec_neon != batch_neon {
                 let result = if batch_neon {
-                    self.apply_batch_neon(key, lsn, img, &records[batch_start..i])
+                    self.apply_batch_neon(key, lsn, img, &records[batch_start..i], max_regions)
                 } else {
                     self.apply_batch_postgres(
                         key,
@@ -200,7 +215,7 @@ impl WalRedoManager for PostgresRedoManager {
         }
         // last batch
         if batch_neon {
-            self.apply_batch_neon(key, lsn, img, &records[batch_start..])
+            self.apply_batch_neon(key, lsn, img, &records[batch_start..], max_regions)
         } else {
             self.apply_batch_postgres(
                 key,
@@ -342,6 +357,7 @@ impl PostgresRedoManager {
         lsn: Lsn,
         base_img: Option<Bytes>,
         records: &[(Lsn, NeonWalRecord)],
+        max_regions: u32,
     ) -> Result<Bytes, WalRedoError> {
         let start_time = Instant::now();
 
@@ -357,7 +373,7 @@ impl PostgresRedoManager {
 
         // Apply all the WAL records in the batch
         for (record_lsn, record) in records.iter() {
-            self.apply_record_neon(key, &mut page, *record_lsn, record)?;
+            self.apply_record_neon(key, &mut page, *record_lsn, record, max_regions)?;
         }
         // Success!
         let end_time = Instant::now();
@@ -380,6 +396,7 @@ impl PostgresRedoManager {
         page: &mut BytesMut,
         _record_lsn: Lsn,
         record: &NeonWalRecord,
+        max_regions: u32,
     ) -> Result<(), WalRedoError> {
         match record {
             NeonWalRecord::Postgres {
@@ -402,31 +419,17 @@ impl PostgresRedoManager {
                     rel
                 );
                 if let Some(heap_blkno) = *new_heap_blkno {
-                    // Calculate the VM block and offset that corresponds to the heap block.
-                    let map_block = pg_constants::HEAPBLK_TO_MAPBLOCK(heap_blkno);
-                    let map_byte = pg_constants::HEAPBLK_TO_MAPBYTE(heap_blkno);
-                    let map_offset = pg_constants::HEAPBLK_TO_OFFSET(heap_blkno);
-
                     // Check that we're modifying the correct VM block.
-                    assert!(map_block == blknum);
-
-                    // equivalent to PageGetContents(page)
-                    let map = &mut page[pg_constants::MAXALIGN_SIZE_OF_PAGE_HEADER_DATA..];
+                    assert!(vm_block(heap_blkno) == blknum);
 
-                    map[map_byte as usize] &= !(flags << map_offset);
+                    vm_clear_flags(page, heap_blkno, *flags);
                 }
 
                 // Repeat for 'old_heap_blkno', if any
                 if let Some(heap_blkno) = *old_heap_blkno {
-                    let map_block = pg_constants::HEAPBLK_TO_MAPBLOCK(heap_blkno);
-                    let map_byte = pg_constants::HEAPBLK_TO_MAPBYTE(heap_blkno);
-                    let map_offset = pg_constants::HEAPBLK_TO_OFFSET(heap_blkno);
-
-                    assert!(map_block == blknum);
+                    assert!(vm_block(heap_blkno) == blknum);
 
-                    let map = &mut page[pg_constants::MAXALIGN_SIZE_OF_PAGE_HEADER_DATA..];
-
-                    map[map_byte as usize] &= !(flags << map_offset);
+                    vm_clear_flags(page, heap_blkno, *flags);
                 }
             }
             // Non-relational WAL records are handled here, with custom code that has the
@@ -441,9 +444,8 @@ impl PostgresRedoManager {
                     key
                 );
                 for &xid in xids {
-                    let pageno = xid / pg_constants::CLOG_XACTS_PER_PAGE;
-                    let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                    let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                    let (expected_segno, expected_blknum) =
+                        Clog::segment_block(Clog::logical_page(xid));
 
                     // Check that we're modifying the correct CLOG block.
                     assert!(
@@ -491,9 +493,8 @@ impl PostgresRedoManager {
                     key
                 );
                 for &xid in xids {
-                    let pageno = xid / pg_constants::CLOG_XACTS_PER_PAGE;
-                    let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                    let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                    let (expected_segno, expected_blknum) =
+                        Clog::segment_block(Clog::logical_page(xid));
 
                     // Check that we're modifying the correct CLOG block.
                     assert!(
@@ -513,6 +514,7 @@ impl PostgresRedoManager {
                 }
             }
             NeonWalRecord::MultixactOffsetCreate { mid, moff, region } => {
+                check_region(*region, max_regions)?;
                 let (slru_kind, segno, blknum) =
                     key_to_slru_block(key).or(Err(WalRedoError::InvalidRecord))?;
                 assert_eq!(
@@ -523,15 +525,12 @@ impl PostgresRedoManager {
                 );
                 // Compute the block and offset to modify.
                 // See RecordNewMultiXact in PostgreSQL sources.
-                let pageno = ((mid / pg_constants::MULTIXACT_OFFSETS_PER_PAGE as u32)
-                    * pg_constants::MAX_REGIONS)
-                    + *region;
-                let entryno = mid % pg_constants::MULTIXACT_OFFSETS_PER_PAGE as u32;
+                let pageno = MultiXactOffsets::page(*mid, *region, max_regions);
+                let entryno = MultiXactOffsets::entry_in_page(*mid);
                 let offset = (entryno * 4) as usize;
 
                 // Check that we're modifying the correct multixact-offsets block.
-                let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                let (expected_segno, expected_blknum) = MultiXactOffsets::segment_block(pageno);
                 assert!(
                     segno == expected_segno,
                     "MultiXactOffsetsCreate record for multi-xid {} with unexpected key {}",
@@ -552,6 +551,7 @@ impl PostgresRedoManager {
                 members,
                 region,
             } => {
+                check_region(*region, max_regions)?;
                 let (slru_kind, segno, blknum) =
                     key_to_slru_block(key).or(Err(WalRedoError::InvalidRecord))?;
                 assert_eq!(
@@ -565,16 +565,13 @@ impl PostgresRedoManager {
 
                     // Compute the block and offset to modify.
                     // See RecordNewMultiXact in PostgreSQL sources.
-                    let pageno = ((offset / pg_constants::MULTIXACT_MEMBERS_PER_PAGE as u32)
-                        * pg_constants::MAX_REGIONS)
-                        + *region;
+                    let pageno = MultiXactMembers::page(offset, *region, max_regions);
                     let memberoff = mx_offset_to_member_offset(offset);
                     let flagsoff = mx_offset_to_flags_offset(offset);
                     let bshift = mx_offset_to_flags_bitshift(offset);
 
                     // Check that we're modifying the correct multixact-members block.
-                    let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                    let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                    let (expected_segno, expected_blknum) = MultiXactMembers::segment_block(pageno);
                     assert!(
                         segno == expected_segno,
                         "MultiXactMembersCreate record for offset {} with unexpected key {}",
@@ -596,6 +593,7 @@ impl PostgresRedoManager {
                 }
             }
             NeonWalRecord::CsnLogSetCommitted { xids, region, lsn } => {
+                check_region(*region, max_regions)?;
                 let (slru_kind, segno, blknum) =
                     key_to_slru_block(key).or(Err(WalRedoError::InvalidRecord))?;
                 assert_eq!(
@@ -606,11 +604,8 @@ impl PostgresRedoManager {
                 );
 
                 for &xid in xids {
-                    let pageno = ((xid / pg_constants::CSN_LOG_XACTS_PER_PAGE)
-                        * pg_constants::MAX_REGIONS)
-                        + *region;
-                    let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                    let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                    let (expected_segno, expected_blknum) =
+                        CsnLog::segment_block(CsnLog::page(xid, *region, max_regions));
 
                     // Check that we're modifying the correct CsnLog block.
                     assert!(
@@ -629,6 +624,7 @@ impl PostgresRedoManager {
                 }
             }
             NeonWalRecord::CsnLogSetAborted { xids, region } => {
+                check_region(*region, max_regions)?;
                 let (slru_kind, segno, blknum) =
                     key_to_slru_block(key).or(Err(WalRedoError::InvalidRecord))?;
                 assert_eq!(
@@ -639,11 +635,8 @@ impl PostgresRedoManager {
                 );
 
                 for &xid in xids {
-                    let pageno = ((xid / pg_constants::CSN_LOG_XACTS_PER_PAGE)
-                        * pg_constants::MAX_REGIONS)
-                        + *region;
-                    let expected_segno = pageno / pg_constants::SLRU_PAGES_PER_SEGMENT;
-                    let expected_blknum = pageno % pg_constants::SLRU_PAGES_PER_SEGMENT;
+                    let (expected_segno, expected_blknum) =
+                        CsnLog::segment_block(CsnLog::page(xid, *region, max_regions));
 
                     // Check that we're modifying the correct CSN block.
                     assert!(
@@ -662,6 +655,41 @@ impl PostgresRedoManager {
                     transaction_id_set_csn(xid, pg_constants::AbortedXidCSN, page);
                 }
             }
+            NeonWalRecord::CommitTsSetTimestamp {
+                xids,
+                timestamp,
+                origin_id,
+            } => {
+                let (slru_kind, segno, blknum) =
+                    key_to_slru_block(key).or(Err(WalRedoError::InvalidRecord))?;
+                assert_eq!(
+                    slru_kind,
+                    SlruKind::CommitTs,
+                    "CommitTsSetTimestamp record with unexpected key {}",
+                    key
+                );
+
+                for &xid in xids {
+                    let (expected_segno, expected_blknum) =
+                        CommitTs::segment_block(CommitTs::logical_page(xid));
+
+                    // Check that we're modifying the correct commit timestamp block.
+                    assert!(
+                        segno == expected_segno,
+                        "CommitTsSetTimestamp record for XID {} with unexpected key {}",
+                        xid,
+                        key
+                    );
+                    assert!(
+                        blknum == expected_blknum,
+                        "CommitTsSetTimestamp record for XID {} with unexpected key {}",
+                        xid,
+                        key
+                    );
+
+                    transaction_id_set_commit_ts(xid, *timestamp, *origin_id, page);
+                }
+            }
         }
 
         Ok(())
@@ -1208,10 +1236,11 @@ fn build_get_page_msg(tag: BufferTag, buf: &mut Vec<u8>) {
 
 #[cfg(test)]
 mod tests {
-    use super::{PostgresRedoManager, WalRedoManager};
+    use super::{PostgresRedoManager, WalRedoError, WalRedoManager};
     use crate::repository::Key;
     use crate::{config::PageServerConf, walrecord::NeonWalRecord};
     use bytes::Bytes;
+    use postgres_ffi::pg_constants;
     use std::str::FromStr;
     use utils::{id::TenantId, lsn::Lsn};
 
@@ -1236,6 +1265,7 @@ mod tests {
                 None,
                 short_records(),
                 14,
+                pg_constants::MAX_REGIONS,
             )
             .unwrap();
 
@@ -1262,6 +1292,7 @@ mod tests {
                 None,
                 short_records(),
                 14,
+                pg_constants::MAX_REGIONS,
             )
             .unwrap();
 
@@ -1270,6 +1301,41 @@ mod tests {
         assert_eq!(page, crate::ZERO_PAGE);
     }
 
+    #[test]
+    fn neon_redo_rejects_region_out_of_range() {
+        let h = RedoHarness::new().unwrap();
+
+        // First block of the first CSN log segment.
+        let key = Key {
+            field1: 0x01,
+            field2: 0x03,
+            field3: 1,
+            field4: 0,
+            field5: 0,
+            field6: 0,
+        };
+        let base_img = Some((Lsn(0x10), crate::ZERO_PAGE.clone()));
+        let record = |region| {
+            vec![(
+                Lsn(0x20),
+                NeonWalRecord::CsnLogSetCommitted {
+                    xids: vec![pg_constants::FIRST_NORMAL_TRANSACTION_ID],
+                    region,
+                    lsn: 0x20,
+                },
+            )]
+        };
+
+        h.manager
+            .request_redo(key, Lsn(0x20), base_img.clone(), record(0), 14, 2)
+            .unwrap();
+        let err = h
+            .manager
+            .request_redo(key, Lsn(0x20), base_img, record(2), 14, 2)
+            .unwrap_err();
+        assert!(matches!(err, WalRedoError::InvalidRecord), "{err:?}");
+    }
+
     #[allow(clippy::octal_escapes)]
     fn short_records() -> Vec<(Lsn, NeonWalRecord)> {
         vec![
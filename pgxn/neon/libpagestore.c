@@ -53,10 +53,12 @@ WaitEventSet *pageserver_conn_wes = NULL;
 char	   *neon_timeline;
 char	   *neon_tenant;
 int32		max_cluster_size;
+bool		catalog_basebackup = false;
 char	   *page_server_connstring;
 char	   *neon_auth_token;
 
 int			readahead_buffer_size = 128;
+int			read_range_size = 1;
 int			flush_every_n_requests = 8;
 
 int			n_reconnect_attempts = 0;
@@ -472,6 +474,27 @@ pg_init_libpagestore(void)
 							PGC_USERSET,
 							0,	/* no flags required */
 							NULL, (GucIntAssignHook) &readahead_buffer_resize, NULL);
+	DefineCustomIntVariable("neon.read_range_size",
+							"number of consecutive blocks to read at once on a cache miss",
+							"On a miss, the blocks following the requested one are "
+							"read along in a single GetPages request, and kept in "
+							"the prefetch buffer for the next reads. 1 disables it.",
+							&read_range_size,
+							1, 1, NEON_MAX_GET_PAGES_BATCH,
+							PGC_USERSET,
+							0,	/* no flags required */
+							NULL, NULL, NULL);
+	DefineCustomBoolVariable("neon.catalog_basebackup",
+							 "Keep the system catalogs in local files",
+							 "Set if the basebackup included the system catalogs. "
+							 "Their pages are then read from local files, and "
+							 "their changes written to them, like in vanilla "
+							 "Postgres, instead of going through the page server.",
+							 &catalog_basebackup,
+							 false,
+							 PGC_POSTMASTER,
+							 0,	/* no flags required */
+							 NULL, NULL, NULL);
 
 	relsize_hash_init();
 
@@ -35,6 +35,7 @@ typedef enum
 	T_NeonDbSizeRequest,
 	T_NeonGetSlruPageRequest,
 	T_NeonGetLatestLsnRequest,
+	T_NeonGetPagesRequest,
 
 	/* pagestore -> pagestore_client */
 	T_NeonExistsResponse = 100,
@@ -44,6 +45,7 @@ typedef enum
 	T_NeonGetLatestLsnResponse,
 	T_NeonErrorResponse,
 	T_NeonDbSizeResponse,
+	T_NeonGetPagesResponse,
 }			NeonMessageTag;
 
 
@@ -110,6 +112,7 @@ typedef enum
 	NEON_MULTI_XACT_MEMBERS,
 	NEON_MULTI_XACT_OFFSETS,
 	NEON_CSNLOG,
+	NEON_COMMIT_TS,
 } NeonSlruKind;
 
 typedef struct
@@ -126,6 +129,21 @@ typedef struct
 	NeonRequest req;
 } NeonGetLatestLsnRequest;
 
+/*
+ * Request for 'nblocks' pages starting at 'blkno'. The pageserver cuts the
+ * range at the end of the relation, so the response may hold fewer pages.
+ */
+typedef struct
+{
+	NeonRequest req;
+	RelFileNode rnode;
+	ForkNumber	forknum;
+	BlockNumber blkno;
+	uint32		nblocks;
+}			NeonGetPagesRequest;
+
+#define NEON_MAX_GET_PAGES_BATCH 128
+
 /* supertype of all the Neon*Response structs below */
 typedef struct
 {
@@ -177,6 +195,14 @@ typedef struct
 	XLogRecPtr lsn;
 } NeonGetLatestLsnResponse;
 
+typedef struct
+{
+	NeonMessageTag tag;
+	XLogRecPtr	lsn;
+	uint32		n_pages;
+	char		pages[FLEXIBLE_ARRAY_MEMBER];	/* n_pages * BLCKSZ */
+}			NeonGetPagesResponse;
+
 typedef struct
 {
 	NeonMessageTag tag;
@@ -206,12 +232,14 @@ extern page_server_api * page_server;
 extern char *page_server_connstring;
 extern int flush_every_n_requests;
 extern int readahead_buffer_size;
+extern int read_range_size;
 extern bool seqscan_prefetch_enabled;
 extern int seqscan_prefetch_distance;
 extern char *neon_timeline;
 extern char *neon_tenant;
 extern bool wal_redo;
 extern int32 max_cluster_size;
+extern bool catalog_basebackup;
 
 extern const f_smgr *smgr_neon(BackendId backend, RelFileNode rnode);
 extern void smgr_init_neon(void);
@@ -90,6 +90,15 @@ static char *hexdump_page(char *page);
 
 #define IS_LOCAL_REL(reln) (reln->smgr_rnode.node.dbNode != 0 && reln->smgr_rnode.node.relNode > FirstNormalObjectId)
 
+/*
+ * With neon.catalog_basebackup, the basebackup includes the system catalogs,
+ * i.e. the relations with the relfilenodes initdb gave them, and the compute
+ * keeps them in local files: they are read from the local files, and their
+ * changes are written to both the local files and, through the WAL, the page
+ * server. The page server makes the same distinction, see basebackup.rs.
+ */
+#define IS_LOCAL_CATALOG(reln) (catalog_basebackup && reln->smgr_rnode.node.relNode < FirstNormalObjectId)
+
 const int	SmgrTrace = DEBUG5;
 
 page_server_api *page_server;
@@ -254,7 +263,10 @@ XLogRecPtr	prefetch_lsn = 0;
 
 static bool compact_prefetch_buffers(void);
 static void consume_prefetch_responses(void);
+static void prefetch_make_room(void);
 static uint64 prefetch_register_buffer(BufferTag tag, int region, bool *force_latest, XLogRecPtr *force_lsn);
+static bool prefetch_register_range(BufferTag tag, int region, XLogRecPtr request_lsn,
+									bool request_latest, BlockNumber nblocks);
 static bool prefetch_read(PrefetchRequest *slot);
 static void prefetch_do_request(PrefetchRequest *slot, bool *force_latest, XLogRecPtr *force_lsn);
 static bool prefetch_wait_for(uint64 ring_index);
@@ -704,6 +716,66 @@ prefetch_do_request(PrefetchRequest *slot, bool *force_latest, XLogRecPtr *force
 	Assert(!found);
 }
 
+/*
+ * prefetch_make_room() - make sure the slot at ring_unused is free
+ *
+ * NOTE: this function may indirectly update MyPState->pfs_hash; which
+ * invalidates any active pointers into the hash table.
+ */
+static void
+prefetch_make_room(void)
+{
+	PrefetchRequest *slot;
+
+	/*
+	 * If the prefetch queue is full, we need to make room by clearing the
+	 * oldest slot. If the oldest slot holds a buffer that was already
+	 * received, we can just throw it away; we fetched the page unnecessarily
+	 * in that case. If the oldest slot holds a request that we haven't
+	 * received a response for yet, we have to wait for the response to that
+	 * before we can continue. We might not have even flushed the request to
+	 * the pageserver yet, it might be just sitting in the output buffer. In
+	 * that case, we flush it and wait for the response. (We could decide not
+	 * to send it, but it's hard to abort when the request is already in the
+	 * output buffer, and 'not sending' a prefetch request kind of goes
+	 * against the principles of prefetching)
+	 */
+	if (MyPState->ring_last + readahead_buffer_size - 1 == MyPState->ring_unused)
+	{
+		uint64 cleanup_index = MyPState->ring_last;
+		slot = GetPrfSlot(cleanup_index);
+
+		Assert(slot->status != PRFS_UNUSED);
+
+		/*
+		 * If there is good reason to run compaction on the prefetch buffers,
+		 * try to do that.
+		 */
+		if (ReceiveBufferNeedsCompaction() && compact_prefetch_buffers())
+		{
+			Assert(slot->status == PRFS_UNUSED);
+		}
+		else
+		{
+			/* We have the slot for ring_last, so that must still be in progress */
+			switch (slot->status)
+			{
+				case PRFS_REQUESTED:
+					Assert(MyPState->ring_receive == cleanup_index);
+					prefetch_wait_for(cleanup_index);
+					prefetch_set_unused(cleanup_index);
+					break;
+				case PRFS_RECEIVED:
+				case PRFS_TAG_REMAINS:
+					prefetch_set_unused(cleanup_index);
+					break;
+				default:
+					pg_unreachable();
+			}
+		}
+	}
+}
+
 /*
  * prefetch_register_buffer() - register and prefetch buffer
  *
@@ -791,53 +863,7 @@ prefetch_register_buffer(BufferTag tag, int region, bool *force_latest, XLogRecP
 		}
 	}
 
-	/*
-	 * If the prefetch queue is full, we need to make room by clearing the
-	 * oldest slot. If the oldest slot holds a buffer that was already
-	 * received, we can just throw it away; we fetched the page unnecessarily
-	 * in that case. If the oldest slot holds a request that we haven't
-	 * received a response for yet, we have to wait for the response to that
-	 * before we can continue. We might not have even flushed the request to
-	 * the pageserver yet, it might be just sitting in the output buffer. In
-	 * that case, we flush it and wait for the response. (We could decide not
-	 * to send it, but it's hard to abort when the request is already in the
-	 * output buffer, and 'not sending' a prefetch request kind of goes
-	 * against the principles of prefetching)
-	 */
-	if (MyPState->ring_last + readahead_buffer_size - 1 == MyPState->ring_unused)
-	{
-		uint64 cleanup_index = MyPState->ring_last;
-		slot = GetPrfSlot(cleanup_index);
-
-		Assert(slot->status != PRFS_UNUSED);
-
-		/*
-		 * If there is good reason to run compaction on the prefetch buffers,
-		 * try to do that.
-		 */
-		if (ReceiveBufferNeedsCompaction() && compact_prefetch_buffers())
-		{
-			Assert(slot->status == PRFS_UNUSED);
-		}
-		else
-		{
-			/* We have the slot for ring_last, so that must still be in progress */
-			switch (slot->status)
-			{
-				case PRFS_REQUESTED:
-					Assert(MyPState->ring_receive == cleanup_index);
-					prefetch_wait_for(cleanup_index);
-					prefetch_set_unused(cleanup_index);
-					break;
-				case PRFS_RECEIVED:
-				case PRFS_TAG_REMAINS:
-					prefetch_set_unused(cleanup_index);
-					break;
-				default:
-					pg_unreachable();
-			}
-		}
-	}
+	prefetch_make_room();
 
 	/*
 	 * The next buffer pointed to by `ring_unused` is now definitely empty,
@@ -888,6 +914,136 @@ page_server_request(void const *req)
 
 }
 
+/*
+ * prefetch_register_range() -- read a range of blocks into the prefetch ring
+ *
+ * Reads up to 'nblocks' consecutive blocks of a relation, starting at the
+ * block of 'tag', in a single GetPages round trip to the page server. The
+ * pages are stored in the prefetch ring as received responses with
+ * 'request_lsn' as their effective request LSN, so neon_read() serves them
+ * like completed prefetches, and drops them if they turn out to be too old.
+ *
+ * The range stops at the first block that is already in the ring or in the
+ * local file cache, and at the end of the relation. Returns false if the
+ * block of 'tag' itself is past the end of the relation.
+ *
+ * NOTE: this function may indirectly update MyPState->pfs_hash; which
+ * invalidates any active pointers into the hash table.
+ */
+static bool
+prefetch_register_range(BufferTag tag, int region, XLogRecPtr request_lsn,
+						bool request_latest, BlockNumber nblocks)
+{
+	NeonResponse *resp;
+	NeonGetPagesResponse *pages_resp;
+	BlockNumber n;
+
+	/* all the pages must fit in the ring at the same time */
+	nblocks = Min(nblocks, Min(NEON_MAX_GET_PAGES_BATCH, readahead_buffer_size - 1));
+
+	for (n = 1; n < nblocks; n++)
+	{
+		PrefetchRequest req;
+
+		req.buftag = tag;
+		req.buftag.blockNum = tag.blockNum + n;
+
+		if (prfh_lookup(MyPState->prf_hash, &req) != NULL ||
+			lfc_cache_contains(tag.rnode, tag.forkNum, req.buftag.blockNum))
+			break;
+	}
+	nblocks = n;
+
+	{
+		NeonGetPagesRequest request = {
+			.req.tag = T_NeonGetPagesRequest,
+			.req.latest = request_latest,
+			.req.lsn = request_lsn,
+			.req.region = region,
+			.rnode = tag.rnode,
+			.forknum = tag.forkNum,
+			.blkno = tag.blockNum,
+			.nblocks = nblocks,
+		};
+
+		resp = page_server_request(&request);
+	}
+
+	switch (resp->tag)
+	{
+		case T_NeonGetPagesResponse:
+			break;
+
+		case T_NeonErrorResponse:
+			ereport(ERROR,
+					(errcode(ERRCODE_IO_ERROR),
+					 errmsg("could not read blocks %u-%u in rel %u/%u/%u.%u in region %d, from page server at lsn %X/%08X",
+							tag.blockNum,
+							tag.blockNum + nblocks - 1,
+							tag.rnode.spcNode,
+							tag.rnode.dbNode,
+							tag.rnode.relNode,
+							tag.forkNum,
+							region,
+							(uint32) (request_lsn >> 32), (uint32) request_lsn),
+					 errdetail("page server returned error: %s",
+							   ((NeonErrorResponse *) resp)->message)));
+			break;
+
+		default:
+			elog(ERROR, "unexpected response from page server with tag 0x%02x", resp->tag);
+	}
+
+	pages_resp = (NeonGetPagesResponse *) resp;
+
+	/*
+	 * page_server_request() consumed all the in-flight prefetches, so the new
+	 * slots can be appended as received responses.
+	 */
+	Assert(MyPState->ring_receive == MyPState->ring_unused);
+
+	for (n = 0; n < Min(pages_resp->n_pages, nblocks); n++)
+	{
+		NeonGetPageResponse *page_resp;
+		PrefetchRequest *slot;
+		uint64		ring_index;
+		bool		found;
+
+		prefetch_make_room();
+
+		page_resp = MemoryContextAllocZero(MyPState->bufctx, PS_GETPAGERESPONSE_SIZE);
+		page_resp->tag = T_NeonGetPageResponse;
+		page_resp->lsn = pages_resp->lsn;
+		memcpy(page_resp->page, pages_resp->pages + (Size) n * BLCKSZ, BLCKSZ);
+
+		ring_index = MyPState->ring_unused;
+		slot = &MyPState->prf_buffer[((ring_index) % readahead_buffer_size)];
+		Assert(slot->status == PRFS_UNUSED);
+
+		slot->buftag = tag;
+		slot->buftag.blockNum = tag.blockNum + n;
+		slot->region = region;
+		slot->my_ring_index = ring_index;
+		slot->effective_request_lsn = request_lsn;
+		slot->response = (NeonResponse *) page_resp;
+		slot->status = PRFS_RECEIVED;
+
+		/* update prefetch state */
+		MyPState->n_unused -= 1;
+		MyPState->n_responses_buffered += 1;
+		MyPState->ring_unused += 1;
+		MyPState->ring_flush = MyPState->ring_unused;
+		MyPState->ring_receive = MyPState->ring_unused;
+
+		prfh_insert(MyPState->prf_hash, slot, &found);
+		Assert(!found);
+	}
+
+	n = Min(pages_resp->n_pages, nblocks);
+	pfree(resp);
+	return n > 0;
+}
+
 StringInfoData
 nm_pack_request(NeonRequest * msg)
 {
@@ -976,6 +1132,22 @@ nm_pack_request(NeonRequest * msg)
 
 				break;
 			}
+		case T_NeonGetPagesRequest:
+			{
+				NeonGetPagesRequest *msg_req = (NeonGetPagesRequest *) msg;
+
+				pq_sendbyte(&s, msg_req->req.latest);
+				pq_sendint64(&s, msg_req->req.lsn);
+				pq_sendint8(&s, msg_req->req.region);
+				pq_sendint32(&s, msg_req->rnode.spcNode);
+				pq_sendint32(&s, msg_req->rnode.dbNode);
+				pq_sendint32(&s, msg_req->rnode.relNode);
+				pq_sendbyte(&s, msg_req->forknum);
+				pq_sendint32(&s, msg_req->blkno);
+				pq_sendint32(&s, msg_req->nblocks);
+
+				break;
+			}
 
 
 			/* pagestore -> pagestore_client. We never need to create these. */
@@ -986,6 +1158,7 @@ nm_pack_request(NeonRequest * msg)
 		case T_NeonGetLatestLsnResponse:
 		case T_NeonErrorResponse:
 		case T_NeonDbSizeResponse:
+		case T_NeonGetPagesResponse:
 		default:
 			elog(ERROR, "unexpected neon message tag 0x%02x", msg->tag);
 			break;
@@ -1088,6 +1261,26 @@ nm_unpack_response(StringInfo s)
 				break;
 			}
 
+		case T_NeonGetPagesResponse:
+			{
+				NeonGetPagesResponse *msg_resp;
+				XLogRecPtr	lsn = pq_getmsgint64(s);
+				uint32		n_pages = pq_getmsgint(s, 4);
+
+				if (n_pages > NEON_MAX_GET_PAGES_BATCH)
+					elog(ERROR, "too many pages in neon GetPages response: %u", n_pages);
+
+				msg_resp = palloc0(offsetof(NeonGetPagesResponse, pages) + (Size) n_pages * BLCKSZ);
+				msg_resp->tag = tag;
+				msg_resp->lsn = lsn;
+				msg_resp->n_pages = n_pages;
+				memcpy(msg_resp->pages, pq_getmsgbytes(s, n_pages * BLCKSZ), (Size) n_pages * BLCKSZ);
+				pq_getmsgend(s);
+
+				resp = (NeonResponse *) msg_resp;
+				break;
+			}
+
 		case T_NeonErrorResponse:
 			{
 				NeonErrorResponse *msg_resp;
@@ -1115,6 +1308,7 @@ nm_unpack_response(StringInfo s)
 		case T_NeonNblocksRequest:
 		case T_NeonGetPageRequest:
 		case T_NeonDbSizeRequest:
+		case T_NeonGetPagesRequest:
 		default:
 			elog(ERROR, "unexpected neon message tag 0x%02x", tag);
 			break;
@@ -1225,6 +1419,25 @@ nm_to_string(NeonMessage * msg)
 				break;
 			}
 
+		case T_NeonGetPagesRequest:
+			{
+				NeonGetPagesRequest *msg_req = (NeonGetPagesRequest *) msg;
+
+				appendStringInfoString(&s, "{\"type\": \"NeonGetPagesRequest\"");
+				appendStringInfo(&s, ", \"rnode\": \"%u/%u/%u\"",
+								 msg_req->rnode.spcNode,
+								 msg_req->rnode.dbNode,
+								 msg_req->rnode.relNode);
+				appendStringInfo(&s, ", \"forknum\": %d", msg_req->forknum);
+				appendStringInfo(&s, ", \"blkno\": %u", msg_req->blkno);
+				appendStringInfo(&s, ", \"nblocks\": %u", msg_req->nblocks);
+				appendStringInfo(&s, ", \"region\": %d", msg_req->req.region);
+				appendStringInfo(&s, ", \"lsn\": \"%X/%X\"", LSN_FORMAT_ARGS(msg_req->req.lsn));
+				appendStringInfo(&s, ", \"latest\": %d", msg_req->req.latest);
+				appendStringInfoChar(&s, '}');
+				break;
+			}
+
 			/* pagestore -> pagestore_client */
 		case T_NeonExistsResponse:
 			{
@@ -1304,6 +1517,16 @@ nm_to_string(NeonMessage * msg)
 
 				break;
 			}
+		case T_NeonGetPagesResponse:
+			{
+				NeonGetPagesResponse *msg_resp = (NeonGetPagesResponse *) msg;
+
+				appendStringInfoString(&s, "{\"type\": \"NeonGetPagesResponse\"");
+				appendStringInfo(&s, ", \"lsn\": \"%X/%X\"", LSN_FORMAT_ARGS(msg_resp->lsn));
+				appendStringInfo(&s, ", \"n_pages\": %u", msg_resp->n_pages);
+				appendStringInfoChar(&s, '}');
+				break;
+			}
 
 		default:
 			appendStringInfo(&s, "{\"type\": \"unknown 0x%02x\"", msg->tag);
@@ -1620,6 +1843,8 @@ neon_exists(SMgrRelation reln, ForkNumber forkNum)
 			break;
 
 		case RELPERSISTENCE_PERMANENT:
+			if (IS_LOCAL_CATALOG(reln))
+				return mdexists(reln, forkNum);
 			break;
 
 		case RELPERSISTENCE_TEMP:
@@ -1755,6 +1980,9 @@ neon_create(SMgrRelation reln, ForkNumber forkNum, bool isRedo)
 	else
 		set_cached_relsize(reln->smgr_region, reln->smgr_rnode.node, forkNum, 0);
 
+	if (IS_LOCAL_CATALOG(reln))
+		mdcreate(reln, forkNum, isRedo);
+
 #ifdef DEBUG_COMPARE_LOCAL
 	if (IS_LOCAL_REL(reln))
 		mdcreate(reln, forkNum, isRedo);
@@ -1872,7 +2100,10 @@ neon_extend(SMgrRelation reln, ForkNumber forkNum, BlockNumber blkno,
 		 forkNum, blkno,
 		 (uint32) (lsn >> 32), (uint32) lsn);
 
-	lfc_write(reln->smgr_rnode.node, forkNum, blkno, buffer);
+	if (IS_LOCAL_CATALOG(reln))
+		mdextend(reln, forkNum, blkno, buffer, skipFsync);
+	else
+		lfc_write(reln->smgr_rnode.node, forkNum, blkno, buffer);
 
 #ifdef DEBUG_COMPARE_LOCAL
 	if (IS_LOCAL_REL(reln))
@@ -1947,6 +2178,9 @@ neon_prefetch(SMgrRelation reln, ForkNumber forknum, BlockNumber blocknum)
 			elog(ERROR, "unknown relpersistence '%c'", reln->smgr_relpersistence);
 	}
 
+	if (IS_LOCAL_CATALOG(reln))
+		return mdprefetch(reln, forknum, blocknum);
+
 	if (lfc_cache_contains(reln->smgr_rnode.node, forknum, blocknum))
 		return false;
 
@@ -1986,6 +2220,11 @@ neon_writeback(SMgrRelation reln, ForkNumber forknum,
 			 * Remotexact
 			 * TODO(ctring): Write back temporary writes to remote relations?
 			 */
+			if (IS_LOCAL_CATALOG(reln))
+			{
+				mdwriteback(reln, forknum, blocknum, nblocks);
+				return;
+			}
 			break;
 
 		case RELPERSISTENCE_TEMP:
@@ -2090,9 +2329,27 @@ static void neon_read_at_lsn_multi_region(RelFileNode rnode, ForkNumber forkNum,
 		{
 			pgBufferUsage.prefetch.misses += 1;
 
-			ring_index = prefetch_register_buffer(buftag, region, &request_latest,
-												  &request_lsn);
-			slot = GetPrfSlot(ring_index);
+			/*
+			 * Read the following blocks along in a single round trip, if
+			 * configured. The block is past the end of the relation if the
+			 * range comes back empty, so fall back to a single GetPage then.
+			 */
+			if (read_range_size > 1 &&
+				prefetch_register_range(buftag, region, request_lsn,
+										request_latest, read_range_size))
+			{
+				entry = prfh_lookup(MyPState->prf_hash, (PrefetchRequest *) &buftag);
+				Assert(entry != NULL);
+				slot = entry->slot;
+				ring_index = slot->my_ring_index;
+				entry = NULL;
+			}
+			else
+			{
+				ring_index = prefetch_register_buffer(buftag, region, &request_latest,
+													  &request_lsn);
+				slot = GetPrfSlot(ring_index);
+			}
 		}
 		else
 		{
@@ -2183,6 +2440,11 @@ neon_read(SMgrRelation reln, ForkNumber forkNum, BlockNumber blkno,
 			elog(ERROR, "cannot call smgrread() on rel with unknown persistence");
 
 		case RELPERSISTENCE_PERMANENT:
+			if (IS_LOCAL_CATALOG(reln))
+			{
+				mdread(reln, forkNum, blkno, buffer);
+				return;
+			}
 			break;
 
 		case RELPERSISTENCE_TEMP:
@@ -2321,6 +2583,13 @@ neon_write(SMgrRelation reln, ForkNumber forknum, BlockNumber blocknum,
 	switch (reln->smgr_relpersistence)
 	{
 		case 0:
+			/*
+			 * Catalogs kept in local files are permanent, and must go through
+			 * the page server as well.
+			 */
+			if (IS_LOCAL_CATALOG(reln))
+				break;
+
 			/* This is a bit tricky. Check if the relation exists locally */
 			if (mdexists(reln, forknum))
 			{
@@ -2364,7 +2633,10 @@ neon_write(SMgrRelation reln, ForkNumber forknum, BlockNumber blocknum,
 		 forknum, blocknum,
 		 (uint32) (lsn >> 32), (uint32) lsn);
 
-	lfc_write(reln->smgr_rnode.node, forknum, blocknum, buffer);
+	if (IS_LOCAL_CATALOG(reln))
+		mdwrite(reln, forknum, blocknum, buffer, skipFsync);
+	else
+		lfc_write(reln->smgr_rnode.node, forknum, blocknum, buffer);
 
 #ifdef DEBUG_COMPARE_LOCAL
 	if (IS_LOCAL_REL(reln))
@@ -2390,6 +2662,8 @@ neon_nblocks(SMgrRelation reln, ForkNumber forknum)
 			break;
 
 		case RELPERSISTENCE_PERMANENT:
+			if (IS_LOCAL_CATALOG(reln))
+				return mdnblocks(reln, forknum);
 			break;
 
 		case RELPERSISTENCE_TEMP:
@@ -2577,6 +2851,9 @@ neon_truncate(SMgrRelation reln, ForkNumber forknum, BlockNumber nblocks)
 	 */
 	SetLastWrittenLSNForRelation(lsn, reln->smgr_rnode.node, forknum);
 
+	if (IS_LOCAL_CATALOG(reln))
+		mdtruncate(reln, forknum, nblocks);
+
 #ifdef DEBUG_COMPARE_LOCAL
 	if (IS_LOCAL_REL(reln))
 		mdtruncate(reln, forknum, nblocks);
@@ -2604,6 +2881,11 @@ neon_immedsync(SMgrRelation reln, ForkNumber forknum)
 			break;
 
 		case RELPERSISTENCE_PERMANENT:
+			if (IS_LOCAL_CATALOG(reln))
+			{
+				mdimmedsync(reln, forknum);
+				return;
+			}
 			break;
 
 		case RELPERSISTENCE_TEMP:
@@ -3004,6 +3286,8 @@ slru_kind_to_string(NeonSlruKind kind)
 			return "pg_multixact/offsets";
 		case NEON_CSNLOG:
 			return "pg_csn";
+		case NEON_COMMIT_TS:
+			return "pg_commit_ts";
 		default:
 			return "invalid";
 	}
@@ -3032,6 +3316,11 @@ slru_kind_from_string(const char* str, NeonSlruKind* kind)
 		*kind = NEON_CSNLOG;
 		return true;
 	}
+	else if (strcmp(str, "pg_commit_ts") == 0)
+	{
+		*kind = NEON_COMMIT_TS;
+		return true;
+	}
 	return false;
 }
 
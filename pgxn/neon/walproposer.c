@@ -74,15 +74,28 @@
 static bool syncSafekeepers = false;
 
 char	   *wal_acceptors_list;
+char	   *wal_acceptors_next_list;
 int			wal_acceptor_reconnect_timeout;
 int			wal_acceptor_connection_timeout;
+int			wal_acceptor_max_inflight_bytes;
 bool		am_wal_proposer;
 
 #define WAL_PROPOSER_SLOT_NAME "wal_proposer_slot"
 
 static int	n_safekeepers = 0;
-static int	quorum = 0;
 static Safekeeper safekeeper[MAX_SAFEKEEPERS];
+
+/*
+ * Safekeepers lists the proposer was started with. During a membership change
+ * both the current and the next set are configured, and every decision (term
+ * choice, election, commit) needs a majority of each of them, like the joint
+ * consensus of Raft. The lists are parsed once; the proposer restarts if they
+ * are changed.
+ */
+static char *current_sks_list;
+static char *next_sks_list;
+static int	n_current_sks = 0;
+static int	n_next_sks = 0;
 static XLogRecPtr availableLsn; /* WAL has been generated up to this point */
 static XLogRecPtr lastSentCommitLsn;	/* last commitLsn broadcast to*
 										 * safekeepers */
@@ -109,8 +122,10 @@ static XLogRecPtr propEpochStartLsn;	/* epoch start lsn of the proposer */
 static term_t donorEpoch;		/* Most advanced acceptor epoch */
 static int	donor;				/* Most advanced acceptor */
 static XLogRecPtr timelineStartLsn; /* timeline globally starts at this LSN */
-static int	n_votes = 0;
-static int	n_connected = 0;
+static bool greeted[MAX_SAFEKEEPERS];	/* safekeepers we got terms from */
+static bool voted[MAX_SAFEKEEPERS]; /* safekeepers which voted for us */
+static bool termChosen = false;
+static bool elected = false;
 static TimestampTz last_reconnect_attempt;
 
 static WalproposerShmemState * walprop_shared;
@@ -120,12 +135,16 @@ static void WalProposerRegister(void);
 static void WalProposerInit(XLogRecPtr flushRecPtr, uint64 systemId);
 static void WalProposerStart(void);
 static void WalProposerLoop(void);
+static void AddSafekeepers(char *list, bool next);
+static bool QuorumReached(bool *acked);
+static void ReloadConfig(void);
 static void InitEventSet(void);
 static void UpdateEventSet(Safekeeper *sk, uint32 events);
 static void HackyRemoveWalProposerEvent(Safekeeper *to_remove);
 static void ShutdownConnection(Safekeeper *sk);
 static void ResetConnection(Safekeeper *sk);
 static long TimeToReconnect(TimestampTz now);
+static XLogRecPtr InflightLimit(Safekeeper *sk);
 static void ReconnectSafekeepers(void);
 static void AdvancePollState(Safekeeper *sk, uint32 events);
 static void HandleConnectionEvent(Safekeeper *sk);
@@ -150,6 +169,7 @@ static bool SendAppendRequests(Safekeeper *sk);
 static bool RecvAppendResponses(Safekeeper *sk);
 static void CombineHotStanbyFeedbacks(HotStandbyFeedback * hs);
 static XLogRecPtr CalculateMinFlushLsn(void);
+static XLogRecPtr GetAcknowledgedBySetQuorumWALPosition(bool next);
 static XLogRecPtr GetAcknowledgedByQuorumWALPosition(void);
 static void HandleSafekeeperResponse(void);
 static bool AsyncRead(Safekeeper *sk, char **buf, int *buf_size);
@@ -247,11 +267,21 @@ nwp_register_gucs(void)
 							   NULL,	/* long_desc */
 							   &wal_acceptors_list, /* valueAddr */
 							   "",	/* bootValue */
-							   PGC_POSTMASTER,
+							   PGC_SIGHUP,
 							   GUC_LIST_INPUT,	/* extensions can't use*
 												 * GUC_LIST_QUOTE */
 							   NULL, NULL, NULL);
 
+	DefineCustomStringVariable(
+							   "neon.safekeepers_next",
+							   "List of Neon WAL acceptors (host:port) the timeline is being moved to",
+							   "While set, WAL is committed only when acknowledged by a majority of both neon.safekeepers and this list.",
+							   &wal_acceptors_next_list,
+							   "",
+							   PGC_SIGHUP,
+							   GUC_LIST_INPUT,
+							   NULL, NULL, NULL);
+
 	DefineCustomIntVariable(
 							"neon.safekeeper_reconnect_timeout",
 							"Walproposer reconnects to offline safekeepers once in this interval.",
@@ -271,6 +301,16 @@ nwp_register_gucs(void)
 							PGC_SIGHUP,
 							GUC_UNIT_MS,
 							NULL, NULL, NULL);
+
+	DefineCustomIntVariable(
+							"neon.safekeeper_max_inflight_bytes",
+							"Maximum amount of WAL sent to a safekeeper but not yet acknowledged by it.",
+							"Zero means no limit.",
+							&wal_acceptor_max_inflight_bytes,
+							0, 0, INT_MAX,
+							PGC_SIGHUP,
+							GUC_UNIT_BYTE,
+							NULL, NULL, NULL);
 }
 
 /* shmem handling */
@@ -392,6 +432,8 @@ WalProposerPoll(void)
 							  &event, 1, WAIT_EVENT_WAL_SENDER_MAIN);
 		sk = (Safekeeper *) event.user_data;
 
+		ReloadConfig();
+
 		/*
 		 * If the event contains something that one of our safekeeper states
 		 * was waiting for, we'll advance its state.
@@ -478,59 +520,21 @@ WalProposerRegister(void)
 static void
 WalProposerInit(XLogRecPtr flushRecPtr, uint64 systemId)
 {
-	char	   *host;
-	char	   *sep;
-	char	   *port;
-
 	load_file("libpqwalreceiver", false);
 	if (WalReceiverFunctions == NULL)
 		elog(ERROR, "libpqwalreceiver didn't initialize correctly");
 
-	for (host = wal_acceptors_list; host != NULL && *host != '\0'; host = sep)
-	{
-		port = strchr(host, ':');
-		if (port == NULL)
-		{
-			elog(FATAL, "port is not specified");
-		}
-		*port++ = '\0';
-		sep = strchr(port, ',');
-		if (sep != NULL)
-			*sep++ = '\0';
-		if (n_safekeepers + 1 >= MAX_SAFEKEEPERS)
-		{
-			elog(FATAL, "Too many safekeepers");
-		}
-		safekeeper[n_safekeepers].host = host;
-		safekeeper[n_safekeepers].port = port;
-		safekeeper[n_safekeepers].state = SS_OFFLINE;
-		safekeeper[n_safekeepers].conn = NULL;
-
-		{
-			Safekeeper *sk = &safekeeper[n_safekeepers];
-			int written = 0;
-
-			written = snprintf((char *) &sk->conninfo, MAXCONNINFO,
-							   "host=%s port=%s dbname=replication options='-c timeline_id=%s tenant_id=%s'",
-							   sk->host, sk->port, neon_timeline, neon_tenant);
-			if (written > MAXCONNINFO || written < 0)
-				elog(FATAL, "could not create connection string for safekeeper %s:%s", sk->host, sk->port);
-		}
-
-		initStringInfo(&safekeeper[n_safekeepers].outbuf);
-		safekeeper[n_safekeepers].xlogreader = XLogReaderAllocate(wal_segment_size, NULL, XL_ROUTINE(.segment_open = wal_segment_open,.segment_close = wal_segment_close), NULL);
-		if (safekeeper[n_safekeepers].xlogreader == NULL)
-			elog(FATAL, "Failed to allocate xlog reader");
-		safekeeper[n_safekeepers].flushWrite = false;
-		safekeeper[n_safekeepers].startStreamingAt = InvalidXLogRecPtr;
-		safekeeper[n_safekeepers].streamingAt = InvalidXLogRecPtr;
-		n_safekeepers += 1;
-	}
-	if (n_safekeepers < 1)
+	current_sks_list = pstrdup(wal_acceptors_list);
+	next_sks_list = pstrdup(wal_acceptors_next_list);
+	AddSafekeepers(pstrdup(current_sks_list), false);
+	AddSafekeepers(pstrdup(next_sks_list), true);
+	if (n_current_sks < 1)
 	{
 		elog(FATAL, "Safekeepers addresses are not specified");
 	}
-	quorum = n_safekeepers / 2 + 1;
+	if (n_next_sks > 0)
+		elog(LOG, "safekeepers membership is changing from '%s' to '%s'",
+			 current_sks_list, next_sks_list);
 
 	/* Fill the greeting package */
 	greetRequest.tag = 'g';
@@ -560,6 +564,129 @@ WalProposerInit(XLogRecPtr flushRecPtr, uint64 systemId)
 	InitEventSet();
 }
 
+/*
+ * Parse a comma separated host:port list of safekeepers, adding them to the
+ * current or the next set. A safekeeper in both sets has a single connection.
+ */
+static void
+AddSafekeepers(char *list, bool next)
+{
+	char	   *host;
+	char	   *sep;
+	char	   *port;
+
+	for (host = list; host != NULL && *host != '\0'; host = sep)
+	{
+		Safekeeper *sk = NULL;
+
+		port = strchr(host, ':');
+		if (port == NULL)
+		{
+			elog(FATAL, "port is not specified");
+		}
+		*port++ = '\0';
+		sep = strchr(port, ',');
+		if (sep != NULL)
+			*sep++ = '\0';
+
+		for (int i = 0; i < n_safekeepers; i++)
+		{
+			if (strcmp(safekeeper[i].host, host) == 0 &&
+				strcmp(safekeeper[i].port, port) == 0)
+				sk = &safekeeper[i];
+		}
+
+		if (sk == NULL)
+		{
+			int			written = 0;
+
+			if (n_safekeepers + 1 >= MAX_SAFEKEEPERS)
+			{
+				elog(FATAL, "Too many safekeepers");
+			}
+			sk = &safekeeper[n_safekeepers];
+			sk->host = host;
+			sk->port = port;
+			sk->state = SS_OFFLINE;
+			sk->conn = NULL;
+			sk->inCurrentSet = false;
+			sk->inNextSet = false;
+
+			written = snprintf((char *) &sk->conninfo, MAXCONNINFO,
+							   "host=%s port=%s dbname=replication options='-c timeline_id=%s tenant_id=%s'",
+							   sk->host, sk->port, neon_timeline, neon_tenant);
+			if (written > MAXCONNINFO || written < 0)
+				elog(FATAL, "could not create connection string for safekeeper %s:%s", sk->host, sk->port);
+
+			initStringInfo(&sk->outbuf);
+			sk->xlogreader = XLogReaderAllocate(wal_segment_size, NULL, XL_ROUTINE(.segment_open = wal_segment_open,.segment_close = wal_segment_close), NULL);
+			if (sk->xlogreader == NULL)
+				elog(FATAL, "Failed to allocate xlog reader");
+			sk->flushWrite = false;
+			sk->startStreamingAt = InvalidXLogRecPtr;
+			sk->streamingAt = InvalidXLogRecPtr;
+			n_safekeepers += 1;
+		}
+
+		if (next && !sk->inNextSet)
+		{
+			sk->inNextSet = true;
+			n_next_sks += 1;
+		}
+		else if (!next && !sk->inCurrentSet)
+		{
+			sk->inCurrentSet = true;
+			n_current_sks += 1;
+		}
+	}
+}
+
+/*
+ * Whether the safekeepers marked in acked make up a majority of the current
+ * set and, during a membership change, of the next set as well.
+ */
+static bool
+QuorumReached(bool *acked)
+{
+	int			n_current_acked = 0;
+	int			n_next_acked = 0;
+
+	for (int i = 0; i < n_safekeepers; i++)
+	{
+		if (!acked[i])
+			continue;
+		if (safekeeper[i].inCurrentSet)
+			n_current_acked++;
+		if (safekeeper[i].inNextSet)
+			n_next_acked++;
+	}
+	return n_current_acked >= n_current_sks / 2 + 1 &&
+		(n_next_sks == 0 || n_next_acked >= n_next_sks / 2 + 1);
+}
+
+/*
+ * Process a pending configuration reload. Safekeepers lists are parsed only
+ * once, so if the membership changed, exit and let the postmaster start a new
+ * proposer with it; the new proposer is elected by the new membership.
+ */
+static void
+ReloadConfig(void)
+{
+	if (!ConfigReloadPending)
+		return;
+
+	ConfigReloadPending = false;
+	ProcessConfigFile(PGC_SIGHUP);
+
+	if (strcmp(current_sks_list, wal_acceptors_list) != 0 ||
+		strcmp(next_sks_list, wal_acceptors_next_list) != 0)
+	{
+		elog(LOG, "safekeepers membership changed to '%s' (next '%s'), restarting walproposer",
+			 wal_acceptors_list, wal_acceptors_next_list);
+		proc_exit(1);
+	}
+}
+
 static void
 WalProposerStart(void)
 {
@@ -1040,22 +1167,18 @@ RecvAcceptorGreeting(Safekeeper *sk)
 	/* Protocol is all good, move to voting. */
 	sk->state = SS_VOTING;
 
-	/* 
-	 * Note: it would be better to track the counter on per safekeeper basis,
-	 * but at worst walproposer would restart with 'term rejected', so leave as
-	 * is for now.
-	 */
-	++n_connected;
-	if (n_connected <= quorum)
+	greeted[sk - safekeeper] = true;
+	if (!termChosen)
 	{
 		/* We're still collecting terms from the majority. */
 		propTerm = Max(sk->greetResponse.term, propTerm);
 
 		/* Quorum is acquried, prepare the vote request. */
-		if (n_connected == quorum)
+		if (QuorumReached(greeted))
 		{
+			termChosen = true;
 			propTerm++;
-			elog(LOG, "proposer connected to quorum (%d) safekeepers, propTerm=" INT64_FORMAT, quorum, propTerm);
+			elog(LOG, "proposer connected to quorum of safekeepers, propTerm=" INT64_FORMAT, propTerm);
 
 			voteRequest = (VoteRequest)
 			{
@@ -1079,7 +1202,7 @@ RecvAcceptorGreeting(Safekeeper *sk)
 	 *
 	 * If we do have quorum, we can start an election.
 	 */
-	if (n_connected < quorum)
+	if (!termChosen)
 	{
 		/*
 		 * SS_VOTING is an idle state; read-ready indicates the connection
@@ -1136,7 +1259,7 @@ RecvVoteResponse(Safekeeper *sk)
 	 * we are not elected yet and thus need the vote.
 	 */
 	if ((!sk->voteResponse.voteGiven) &&
-		(sk->voteResponse.term > propTerm || n_votes < quorum))
+		(sk->voteResponse.term > propTerm || !elected))
 	{
 		elog(FATAL, "WAL acceptor %s:%s with term " INT64_FORMAT " rejects our connection request with term " INT64_FORMAT "",
 			 sk->host, sk->port,
@@ -1145,18 +1268,19 @@ RecvVoteResponse(Safekeeper *sk)
 	Assert(sk->voteResponse.term == propTerm);
 
 	/* Handshake completed, do we have quorum? */
-	n_votes++;
-	if (n_votes < quorum)
-	{
-		sk->state = SS_IDLE;	/* can't do much yet, no quorum */
-	}
-	else if (n_votes > quorum)
+	voted[sk - safekeeper] = true;
+	if (elected)
 	{
 		/* recovery already performed, just start streaming */
 		SendProposerElected(sk);
 	}
+	else if (!QuorumReached(voted))
+	{
+		sk->state = SS_IDLE;	/* can't do much yet, no quorum */
+	}
 	else
 	{
+		elected = true;
 		sk->state = SS_IDLE;
 		UpdateEventSet(sk, WL_SOCKET_READABLE); /* Idle states wait for
 												 * read-ready */
@@ -1344,8 +1468,7 @@ DetermineEpochStartLsn(void)
 	propTermHistory.entries[propTermHistory.n_entries - 1].term = propTerm;
 	propTermHistory.entries[propTermHistory.n_entries - 1].lsn = propEpochStartLsn;
 
-	elog(LOG, "got votes from majority (%d) of nodes, term " UINT64_FORMAT ", epochStartLsn %X/%X, donor %s:%s, truncate_lsn %X/%X",
-		 quorum,
+	elog(LOG, "got votes from majority of nodes, term " UINT64_FORMAT ", epochStartLsn %X/%X, donor %s:%s, truncate_lsn %X/%X",
 		 propTerm,
 		 LSN_FORMAT_ARGS(propEpochStartLsn),
 		 safekeeper[donor].host, safekeeper[donor].port,
@@ -1697,7 +1820,8 @@ HandleActiveState(Safekeeper *sk, uint32 events)
 	 * after arrival. But it's good to have it here in case we change this
 	 * behavior in the future.
 	 */
-	if (sk->streamingAt != availableLsn || sk->flushWrite)
+	if ((sk->streamingAt != availableLsn && sk->streamingAt < InflightLimit(sk)) ||
+		sk->flushWrite)
 		newEvents |= WL_SOCKET_WRITEABLE;
 
 	UpdateEventSet(sk, newEvents);
@@ -1737,6 +1861,13 @@ SendAppendRequests(Safekeeper *sk)
 
 	while (sk->streamingAt != availableLsn || !sentAnything)
 	{
+		/*
+		 * Stop once too much WAL is unacknowledged; the message sent anyway
+		 * is empty and serves as a heartbeat.
+		 */
+		if (sentAnything && sk->streamingAt >= InflightLimit(sk))
+			break;
+
 		sentAnything = true;
 
 		endLsn = sk->streamingAt;
@@ -1748,6 +1879,12 @@ SendAppendRequests(Safekeeper *sk)
 			endLsn = availableLsn;
 		}
 
+		/* don't exceed the limit of unacknowledged WAL */
+		if (endLsn > InflightLimit(sk))
+		{
+			endLsn = Max(InflightLimit(sk), sk->streamingAt);
+		}
+
 		req = &sk->appendRequest;
 		PrepareAppendRequest(&sk->appendRequest, sk->streamingAt, endLsn);
 
@@ -1818,6 +1955,23 @@ SendAppendRequests(Safekeeper *sk)
 	return true;
 }
 
+/*
+ * Returns the LSN up to which WAL can be sent to the safekeeper without more
+ * than neon.safekeeper_max_inflight_bytes of it being unacknowledged.
+ */
+static XLogRecPtr
+InflightLimit(Safekeeper *sk)
+{
+	XLogRecPtr	ackedLsn;
+
+	if (wal_acceptor_max_inflight_bytes <= 0)
+		return PG_UINT64_MAX;
+
+	/* the acknowledged LSN may be left from the previous connection */
+	ackedLsn = Max(sk->appendResponse.flushLsn, sk->startStreamingAt);
+	return ackedLsn + wal_acceptor_max_inflight_bytes;
+}
+
 /*
  * Receive and process all available feedback.
  *
@@ -1942,6 +2096,14 @@ ParsePageserverFeedbackMessage(StringInfo reply_message, PageserverFeedback * rf
 				pfree(replyTimeStr);
 			}
 		}
+		else if (strcmp(key, "ps_slowdown") == 0)
+		{
+			pq_getmsgint(reply_message, sizeof(int32));
+			/* read value length */
+			rf->slow_down = pq_getmsgint64(reply_message) != 0;
+			elog(DEBUG2, "ParsePageserverFeedbackMessage: slow_down %d",
+				 rf->slow_down);
+		}
 		else
 		{
 			len = pq_getmsgint(reply_message, sizeof(int32));
@@ -2012,30 +2174,49 @@ CalculateMinFlushLsn(void)
 }
 
 /*
- * Calculate WAL position acknowledged by quorum
+ * Calculate WAL position acknowledged by quorum of the current or the next set
+ * of safekeepers.
  */
 static XLogRecPtr
-GetAcknowledgedByQuorumWALPosition(void)
+GetAcknowledgedBySetQuorumWALPosition(bool next)
 {
 	XLogRecPtr	responses[MAX_SAFEKEEPERS];
+	int			n_responses = 0;
 
 	/*
 	 * Sort acknowledged LSNs
 	 */
 	for (int i = 0; i < n_safekeepers; i++)
 	{
+		if (next ? !safekeeper[i].inNextSet : !safekeeper[i].inCurrentSet)
+			continue;
+
 		/*
 		 * Like in Raft, we aren't allowed to commit entries from previous
 		 * terms, so ignore reported LSN until it gets to epochStartLsn.
 		 */
-		responses[i] = safekeeper[i].appendResponse.flushLsn >= propEpochStartLsn ? safekeeper[i].appendResponse.flushLsn : 0;
+		responses[n_responses++] = safekeeper[i].appendResponse.flushLsn >= propEpochStartLsn ? safekeeper[i].appendResponse.flushLsn : 0;
 	}
-	qsort(responses, n_safekeepers, sizeof(XLogRecPtr), CompareLsn);
+	qsort(responses, n_responses, sizeof(XLogRecPtr), CompareLsn);
 
 	/*
 	 * Get the smallest LSN committed by quorum
 	 */
-	return responses[n_safekeepers - quorum];
+	return responses[n_responses - (n_responses / 2 + 1)];
+}
+
+/*
+ * Calculate WAL position acknowledged by quorum. During a membership change
+ * it must be acknowledged by quorum of both sets.
+ */
+static XLogRecPtr
+GetAcknowledgedByQuorumWALPosition(void)
+{
+	XLogRecPtr	lsn = GetAcknowledgedBySetQuorumWALPosition(false);
+
+	if (n_next_sks > 0)
+		lsn = Min(lsn, GetAcknowledgedBySetQuorumWALPosition(true));
+	return lsn;
 }
 
 /*
@@ -2086,8 +2267,20 @@ replication_feedback_get_lsns(XLogRecPtr *writeLsn, XLogRecPtr *flushLsn, XLogRe
 	SpinLockRelease(&walprop_shared->mutex);
 }
 
+static bool
+replication_feedback_slow_down(void)
+{
+	bool		slow_down;
+
+	SpinLockAcquire(&walprop_shared->mutex);
+	slow_down = walprop_shared->feedback.slow_down;
+	SpinLockRelease(&walprop_shared->mutex);
+	return slow_down;
+}
+
 /*
- * Get PageserverFeedback fields from the most advanced safekeeper
+ * Get PageserverFeedback fields from the most advanced safekeeper, except
+ * slow_down which is set if any connected safekeeper relays it.
  */
 static void
 GetLatestNeonFeedback(PageserverFeedback * rf)
@@ -2109,14 +2302,25 @@ GetLatestNeonFeedback(PageserverFeedback * rf)
 	rf->disk_consistent_lsn = safekeeper[latest_safekeeper].appendResponse.rf.disk_consistent_lsn;
 	rf->remote_consistent_lsn = safekeeper[latest_safekeeper].appendResponse.rf.remote_consistent_lsn;
 	rf->replytime = safekeeper[latest_safekeeper].appendResponse.rf.replytime;
+	/*
+	 * Only the safekeepers we stream to relay current feedback, the last one
+	 * of a disconnected safekeeper may be stale forever.
+	 */
+	rf->slow_down = false;
+	for (int i = 0; i < n_safekeepers; i++)
+	{
+		if (safekeeper[i].state == SS_ACTIVE)
+			rf->slow_down |= safekeeper[i].appendResponse.rf.slow_down;
+	}
 
 	elog(DEBUG2, "GetLatestNeonFeedback: currentClusterSize %lu,"
-		 " last_received_lsn %X/%X, disk_consistent_lsn %X/%X, remote_consistent_lsn %X/%X, replytime %lu",
+		 " last_received_lsn %X/%X, disk_consistent_lsn %X/%X, remote_consistent_lsn %X/%X, replytime %lu, slow_down %d",
 		 rf->currentClusterSize,
 		 LSN_FORMAT_ARGS(rf->last_received_lsn),
 		 LSN_FORMAT_ARGS(rf->disk_consistent_lsn),
 		 LSN_FORMAT_ARGS(rf->remote_consistent_lsn),
-		 rf->replytime);
+		 rf->replytime,
+		 rf->slow_down);
 
 	replication_feedback_set(rf);
 }
@@ -2214,21 +2418,19 @@ HandleSafekeeperResponse(void)
 	 */
 	if (syncSafekeepers)
 	{
-		int			n_synced;
+		bool		synced[MAX_SAFEKEEPERS];
 
-		n_synced = 0;
 		for (int i = 0; i < n_safekeepers; i++)
 		{
 			Safekeeper *sk = &safekeeper[i];
-			bool		synced = sk->appendResponse.commitLsn >= propEpochStartLsn;
+
+			synced[i] = sk->appendResponse.commitLsn >= propEpochStartLsn;
 
 			/* alive safekeeper which is not synced yet; wait for it */
-			if (sk->state != SS_OFFLINE && !synced)
+			if (sk->state != SS_OFFLINE && !synced[i])
 				return;
-			if (synced)
-				n_synced++;
 		}
-		if (n_synced >= quorum)
+		if (QuorumReached(synced))
 		{
 			/* All safekeepers synced! */
 			
@@ -2476,6 +2678,14 @@ AsyncFlush(Safekeeper *sk)
 static uint64
 backpressure_lag_impl(void)
 {
+	/*
+	 * The pageserver falls behind with ingesting the WAL it received. Its
+	 * ingestion lag is not comparable with ours, so just report some lag to
+	 * throttle until it catches up.
+	 */
+	if (replication_feedback_slow_down())
+		return 1;
+
 	if (max_replication_apply_lag > 0 || max_replication_flush_lag > 0 || max_replication_write_lag > 0)
 	{
 		XLogRecPtr	writePtr;
@@ -29,8 +29,10 @@
 #define WL_NO_EVENTS 0
 
 extern char *wal_acceptors_list;
+extern char *wal_acceptors_next_list;
 extern int	wal_acceptor_reconnect_timeout;
 extern int	wal_acceptor_connection_timeout;
+extern int	wal_acceptor_max_inflight_bytes;
 extern bool am_wal_proposer;
 
 struct WalProposerConn;			/* Defined in libpqwalproposer */
@@ -289,6 +291,8 @@ typedef struct PageserverFeedback
 	XLogRecPtr	disk_consistent_lsn;
 	XLogRecPtr	remote_consistent_lsn;
 	TimestampTz replytime;
+	/* pageserver asks to throttle WAL generation, its ingestion is behind */
+	bool		slow_down;
 }			PageserverFeedback;
 
 typedef struct WalproposerShmemState
@@ -335,6 +339,9 @@ typedef struct Safekeeper
 	char const *host;
 	char const *port;
 
+	bool		inCurrentSet;	/* member of neon.safekeepers */
+	bool		inNextSet;		/* member of neon.safekeepers_next */
+
 	/*
 	 * connection string for connecting/reconnecting.
 	 *
@@ -0,0 +1,144 @@
+/*-------------------------------------------------------------------------
+ *
+ * commit_stats.c
+ *	  Histograms of the latency of the phases of commits, shared by all
+ *	  backends
+ *
+ * contrib/remotexact/commit_stats.c
+ *
+ *-------------------------------------------------------------------------
+ */
+#include "postgres.h"
+
+#include "commit_stats.h"
+#include "port/atomics.h"
+#include "storage/ipc.h"
+#include "storage/lwlock.h"
+#include "storage/shmem.h"
+
+/* Upper bounds of the buckets, in microseconds, the last one being +Inf */
+static const int64 bucket_bounds[] = {
+	100, 500, 1000, 5000, 10000, 50000, 100000, 500000, 1000000, 5000000
+};
+
+#define NUM_BUCKETS (lengthof(bucket_bounds) + 1)
+
+typedef struct CommitPhaseStats
+{
+	pg_atomic_uint64 buckets[NUM_BUCKETS];
+	pg_atomic_uint64 count;
+	pg_atomic_uint64 sum_usecs;
+} CommitPhaseStats;
+
+static CommitPhaseStats *commit_stats = NULL;
+
+static const char *const phase_names[NUM_COMMIT_PHASES] = {
+	[COMMIT_PHASE_REMOTE_ACK] = "remote_ack",
+	[COMMIT_PHASE_LOCAL] = "local",
+	[COMMIT_PHASE_TOTAL] = "total",
+};
+
+static Size
+CommitStatsShmemSize(void)
+{
+	return sizeof(CommitPhaseStats) * NUM_COMMIT_PHASES;
+}
+
+void
+CommitStatsShmemRequest(void)
+{
+	RequestAddinShmemSpace(CommitStatsShmemSize());
+}
+
+void
+CommitStatsShmemInit(void)
+{
+	bool		found;
+
+	LWLockAcquire(AddinShmemInitLock, LW_EXCLUSIVE);
+	commit_stats = ShmemInitStruct("remotexact commit stats",
+								   CommitStatsShmemSize(),
+								   &found);
+	if (!found)
+	{
+		for (int phase = 0; phase < NUM_COMMIT_PHASES; phase++)
+		{
+			CommitPhaseStats *stats = &commit_stats[phase];
+
+			for (int i = 0; i < NUM_BUCKETS; i++)
+				pg_atomic_init_u64(&stats->buckets[i], 0);
+			pg_atomic_init_u64(&stats->count, 0);
+			pg_atomic_init_u64(&stats->sum_usecs, 0);
+		}
+	}
+	LWLockRelease(AddinShmemInitLock);
+}
+
+void
+CommitStatsRecord(CommitPhase phase, int64 usecs)
+{
+	CommitPhaseStats *stats;
+	int			bucket = 0;
+
+	if (commit_stats == NULL)
+		return;
+
+	if (usecs < 0)
+		usecs = 0;
+
+	while (bucket < lengthof(bucket_bounds) && usecs > bucket_bounds[bucket])
+		bucket++;
+
+	stats = &commit_stats[phase];
+	pg_atomic_fetch_add_u64(&stats->buckets[bucket], 1);
+	pg_atomic_fetch_add_u64(&stats->count, 1);
+	pg_atomic_fetch_add_u64(&stats->sum_usecs, usecs);
+}
+
+const char *
+CommitPhaseName(CommitPhase phase)
+{
+	return phase_names[phase];
+}
+
+int
+CommitStatsNumBuckets(void)
+{
+	return NUM_BUCKETS;
+}
+
+/*
+ * Upper bound of the bucket in microseconds, or -1 for the last, unbounded
+ * one.
+ */
+int64
+CommitStatsBucketBound(int bucket)
+{
+	if (bucket >= lengthof(bucket_bounds))
+		return -1;
+	return bucket_bounds[bucket];
+}
+
+/*
+ * Reads the histogram of the phase. The counts of the buckets aren't
+ * cumulative.
+ */
+void
+CommitStatsGet(CommitPhase phase, uint64 *buckets, uint64 *count, uint64 *sum_usecs)
+{
+	CommitPhaseStats *stats;
+
+	if (commit_stats == NULL)
+	{
+		memset(buckets, 0, sizeof(uint64) * NUM_BUCKETS);
+		*count = 0;
+		*sum_usecs = 0;
+		return;
+	}
+
+	stats = &commit_stats[phase];
+	for (int i = 0; i < NUM_BUCKETS; i++)
+		buckets[i] = pg_atomic_read_u64(&stats->buckets[i]);
+	*count = pg_atomic_read_u64(&stats->count);
+	*sum_usecs = pg_atomic_read_u64(&stats->sum_usecs);
+}
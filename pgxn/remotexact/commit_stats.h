@@ -0,0 +1,40 @@
+/*-------------------------------------------------------------------------
+ *
+ * commit_stats.h
+ *
+ * contrib/remotexact/commit_stats.h
+ *
+ *-------------------------------------------------------------------------
+ */
+#ifndef COMMIT_STATS_H
+#define COMMIT_STATS_H
+
+#include "postgres.h"
+
+/*
+ * Phases of a commit whose latency is recorded
+ */
+typedef enum CommitPhase
+{
+	/* Validation of the transaction by the xactserver and the remote regions */
+	COMMIT_PHASE_REMOTE_ACK,
+	/*
+	 * Local part of the commit: assignment of the CSN and flushing of the
+	 * commit record to the safekeepers
+	 */
+	COMMIT_PHASE_LOCAL,
+	/* The whole commit */
+	COMMIT_PHASE_TOTAL,
+	NUM_COMMIT_PHASES
+} CommitPhase;
+
+extern void CommitStatsShmemRequest(void);
+extern void CommitStatsShmemInit(void);
+extern void CommitStatsRecord(CommitPhase phase, int64 usecs);
+
+extern const char *CommitPhaseName(CommitPhase phase);
+extern int	CommitStatsNumBuckets(void);
+extern int64 CommitStatsBucketBound(int bucket);
+extern void CommitStatsGet(CommitPhase phase, uint64 *buckets, uint64 *count, uint64 *sum_usecs);
+
+#endif							/* COMMIT_STATS_H */
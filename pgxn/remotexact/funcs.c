@@ -2,6 +2,7 @@
 
 #include "access/remotexact.h"
 #include "apply.h"
+#include "commit_stats.h"
 #include "fmgr.h"
 #include "funcapi.h"
 #include "miscadmin.h"
@@ -13,6 +14,7 @@
 
 PG_FUNCTION_INFO_V1(validate_and_apply_xact);
 PG_FUNCTION_INFO_V1(lsn_snapshot);
+PG_FUNCTION_INFO_V1(commit_latency);
 
 static int relation_comparator(const void *p1, const void *p2)
 {
@@ -228,3 +230,80 @@ lsn_snapshot(PG_FUNCTION_ARGS)
 
 	return (Datum) 0;
 }
+
+/*
+ * Returns the histograms of the latency of the phases of commits, one row per
+ * bucket, with cumulative counts like in Prometheus.
+ */
+Datum
+commit_latency(PG_FUNCTION_ARGS)
+{
+	ReturnSetInfo *rsinfo = (ReturnSetInfo *) fcinfo->resultinfo;
+	MemoryContext oldcontext;
+	TupleDesc	tupdesc;
+	Tuplestorestate *tupstore;
+	AttInMetadata *attinmeta;
+	HeapTuple	tuple;
+	char	**values;
+	int			nbuckets = CommitStatsNumBuckets();
+	uint64	   *buckets;
+
+	/* check to see if caller supports us returning a tuplestore */
+	if (rsinfo == NULL || !IsA(rsinfo, ReturnSetInfo))
+		ereport(ERROR,
+				(errcode(ERRCODE_FEATURE_NOT_SUPPORTED),
+				 errmsg("set-valued function called in context that cannot accept a set")));
+	if (!(rsinfo->allowedModes & SFRM_Materialize))
+		ereport(ERROR,
+				(errcode(ERRCODE_SYNTAX_ERROR),
+				 errmsg("materialize mode required, but it is not allowed in this context")));
+
+	/* The tupdesc and tuplestore must be created in ecxt_per_query_memory */
+	oldcontext = MemoryContextSwitchTo(rsinfo->econtext->ecxt_per_query_memory);
+
+	if (get_call_result_type(fcinfo, NULL, &tupdesc) != TYPEFUNC_COMPOSITE)
+		elog(ERROR, "return type must be a row type");
+
+	tupstore = tuplestore_begin_heap(true, false, work_mem);
+	rsinfo->returnMode = SFRM_Materialize;
+	rsinfo->setResult = tupstore;
+	rsinfo->setDesc = tupdesc;
+
+	MemoryContextSwitchTo(oldcontext);
+
+	attinmeta = TupleDescGetAttInMetadata(tupdesc);
+
+	values = (char **) palloc(tupdesc->natts * sizeof(char *));
+	buckets = (uint64 *) palloc(nbuckets * sizeof(uint64));
+
+	for (int phase = 0; phase < NUM_COMMIT_PHASES; phase++)
+	{
+		uint64		count;
+		uint64		sum_usecs;
+		uint64		cumulative = 0;
+
+		CommitStatsGet(phase, buckets, &count, &sum_usecs);
+
+		for (int i = 0; i < nbuckets; i++)
+		{
+			int64		bound = CommitStatsBucketBound(i);
+
+			cumulative += buckets[i];
+
+			/* phase */
+			values[0] = pstrdup(CommitPhaseName(phase));
+			/* le, in seconds */
+			values[1] = bound < 0 ? pstrdup("Infinity") : psprintf("%f", bound / 1000000.0);
+			/* count */
+			values[2] = psprintf(UINT64_FORMAT, cumulative);
+			/* sum, in seconds */
+			values[3] = psprintf("%f", sum_usecs / 1000000.0);
+
+			/* build the tuple */
+			tuple = BuildTupleFromCStrings(attinmeta, values);
+			tuplestore_puttuple(tupstore, tuple);
+		}
+	}
+
+	return (Datum) 0;
+}
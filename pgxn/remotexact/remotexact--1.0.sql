@@ -11,4 +11,12 @@ LANGUAGE C STRICT;
 CREATE FUNCTION lsn_snapshot(OUT region smallint, OUT lsn pg_lsn)
 RETURNS SETOF record
 AS 'MODULE_PATHNAME', 'lsn_snapshot'
-LANGUAGE C STRICT;
\ No newline at end of file
+LANGUAGE C STRICT;
+
+-- Histograms of the latency of the phases of commits: remote_ack (validation
+-- by the remote regions), local (CSN assignment and flush to the safekeepers)
+-- and total. Counts are cumulative per bucket, sum is per phase.
+CREATE FUNCTION commit_latency(OUT phase text, OUT le float8, OUT count int8, OUT sum float8)
+RETURNS SETOF record
+AS 'MODULE_PATHNAME', 'commit_latency'
+LANGUAGE C STRICT;
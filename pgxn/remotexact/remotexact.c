@@ -3,12 +3,14 @@
 
 #include "access/xact.h"
 #include "access/remotexact.h"
+#include "commit_stats.h"
 #include "fmgr.h"
 #include "libpq-fe.h"
 #include "libpq/pqformat.h"
 #include "log.h"
 #include "replication/logicalproto.h"
 #include "rwset.h"
+#include "storage/ipc.h"
 #include "storage/latch.h"
 #include "storage/predicate.h"
 #include "storage/proc.h"
@@ -18,6 +20,7 @@
 #include "utils/memutils.h"
 #include "utils/rel.h"
 #include "utils/snapshot.h"
+#include "utils/timestamp.h"
 #include "utils/wait_event.h"
 #include "miscadmin.h"
 
@@ -80,6 +83,15 @@ static RWSetCollectionBuffer *rwset_collection_buffer = NULL;
 static MultiRegionXactState multi_region_xact_state = MULTI_REGION_XACT_NONE;
 static StringInfo multi_region_xact_resp = NULL;
 
+/* Timing of the commit of the current transaction, 0 if not committing */
+static TimestampTz commit_started_at = 0;
+static int64 commit_remote_ack_usecs = 0;
+
+#if PG_VERSION_NUM >= 150000
+static shmem_request_hook_type prev_shmem_request_hook = NULL;
+#endif
+static shmem_startup_hook_type prev_shmem_startup_hook = NULL;
+
 PGconn	   *xactserver_conn;
 bool		xactserver_connected = false;
 
@@ -112,6 +124,10 @@ static void xactserver_connect(void);
 static void xactserver_disconnect(void);
 static int call_PQgetCopyData(char **buffer);
 static void clean_up_xact_callback(XactEvent event, void *arg);
+#if PG_VERSION_NUM >= 150000
+static void rx_shmem_request(void);
+#endif
+static void rx_shmem_startup(void);
 
 static void
 init_rwset_collection_buffer(Oid dbid)
@@ -494,6 +510,7 @@ rx_commit_multi_region_xact(void)
 	StringInfoData		buf;
 	int		read_len = 0;
 	int 	num_read_rels = 0;
+	TimestampTz remote_ack_started_at;
 
 	/* Unset this early so that we don't miss it due to the returns */
 	multi_region_xact_state = MULTI_REGION_XACT_NONE;
@@ -571,6 +588,11 @@ rx_commit_multi_region_xact(void)
 	/* Update the number of the read relations sent */
 	*(int *)(buf.data + buf.cursor + sizeof(int)) = pg_hton32(num_read_rels);
 
+	/* The commit may start with the validation, before the pre-commit callback */
+	if (commit_started_at == 0)
+		commit_started_at = GetCurrentTimestamp();
+	remote_ack_started_at = GetCurrentTimestamp();
+
 	/* Send the buffer to the xact server */
 	pq_sendbytes(&buf, rwset_collection_buffer->writes.data, rwset_collection_buffer->writes.len);
 	if (PQputCopyData(xactserver_conn, buf.data, buf.len) <= 0 || PQflush(xactserver_conn))
@@ -596,6 +618,9 @@ rx_commit_multi_region_xact(void)
 		{
 			int rollbacked_by;
 
+			commit_remote_ack_usecs = GetCurrentTimestamp() - remote_ack_started_at;
+			CommitStatsRecord(COMMIT_PHASE_REMOTE_ACK, commit_remote_ack_usecs);
+
 			resp_buf.len = rc;
 			resp_buf.cursor = 0;
 
@@ -862,16 +887,35 @@ xactserver_disconnect(void)
 	}
 }
 
+/*
+ * Records the latency of the commit of the transaction, split into the
+ * validation by the remote regions and the rest, done locally.
+ */
+static void
+record_commit_latency(void)
+{
+	int64		total_usecs = GetCurrentTimestamp() - commit_started_at;
+
+	CommitStatsRecord(COMMIT_PHASE_LOCAL, total_usecs - commit_remote_ack_usecs);
+	CommitStatsRecord(COMMIT_PHASE_TOTAL, total_usecs);
+}
+
 static void
 clean_up_xact_callback(XactEvent event, void *arg)
 {
 	switch (event)
 	{
+		case XACT_EVENT_COMMIT:
+			if (commit_started_at != 0)
+				record_commit_latency();
+			/* FALLTHROUGH */
 		case XACT_EVENT_ABORT:
 		case XACT_EVENT_PARALLEL_ABORT:
-		case XACT_EVENT_COMMIT:
 		case XACT_EVENT_PARALLEL_COMMIT:
 		case XACT_EVENT_PREPARE:
+			commit_started_at = 0;
+			commit_remote_ack_usecs = 0;
+
 			/*
 			 * Unset the PROC_IS_REMOTEXACT statusFlag for MyProc once the remotexact
 			 * completes its execution. We don't need to lock the ProcArray because we
@@ -882,12 +926,36 @@ clean_up_xact_callback(XactEvent event, void *arg)
 			clean_up_rwset_collection_buffer();
 			break;
 		case XACT_EVENT_PRE_COMMIT:
+			/* Only the commits of transactions that wrote something are timed */
+			if (commit_started_at == 0 && GetTopTransactionIdIfAny() != InvalidTransactionId)
+				commit_started_at = GetCurrentTimestamp();
+			break;
 		case XACT_EVENT_PARALLEL_PRE_COMMIT:
 		case XACT_EVENT_PRE_PREPARE:
 			break;
 	}
 }
 
+#if PG_VERSION_NUM >= 150000
+static void
+rx_shmem_request(void)
+{
+	if (prev_shmem_request_hook)
+		prev_shmem_request_hook();
+
+	CommitStatsShmemRequest();
+}
+#endif
+
+static void
+rx_shmem_startup(void)
+{
+	if (prev_shmem_startup_hook)
+		prev_shmem_startup_hook();
+
+	CommitStatsShmemInit();
+}
+
 static const RemoteXactHook remote_xact_hook =
 {
 	.collect_tuple = rx_collect_tuple,
@@ -941,6 +1009,15 @@ _PG_init(void)
 							 0, /* no flags required */
 							 NULL, NULL, NULL);
 
+#if PG_VERSION_NUM >= 150000
+	prev_shmem_request_hook = shmem_request_hook;
+	shmem_request_hook = rx_shmem_request;
+#else
+	CommitStatsShmemRequest();
+#endif
+	prev_shmem_startup_hook = shmem_startup_hook;
+	shmem_startup_hook = rx_shmem_startup;
+
 	if (remotexact_connstring && remotexact_connstring[0])
 	{
 		MemoryContext old_context;
@@ -26,20 +26,22 @@ use utils::pid_file;
 use metrics::set_build_info_metric;
 use safekeeper::defaults::{
     DEFAULT_HEARTBEAT_TIMEOUT, DEFAULT_HTTP_LISTEN_ADDR, DEFAULT_MAX_OFFLOADER_LAG_BYTES,
-    DEFAULT_PG_LISTEN_ADDR,
+    DEFAULT_PG_LISTEN_ADDR, DEFAULT_WALPROPOSER_KEEPALIVE_INTERVAL,
 };
+use safekeeper::wal_filter::{parse_region_wal_filter, WalFilter};
 use safekeeper::wal_service;
 use safekeeper::GlobalTimelines;
 use safekeeper::SafeKeeperConf;
 use safekeeper::{broker, WAL_SERVICE_RUNTIME};
 use safekeeper::{control_file, BROKER_RUNTIME};
 use safekeeper::{http, WAL_REMOVER_RUNTIME};
+use safekeeper::{recovery, PEER_RECOVERY_RUNTIME};
 use safekeeper::{remove_wal, WAL_BACKUP_RUNTIME};
 use safekeeper::{wal_backup, HTTP_RUNTIME};
 use storage_broker::DEFAULT_ENDPOINT;
 use utils::auth::{JwtAuth, Scope};
 use utils::{
-    id::NodeId,
+    id::{NodeId, RegionId},
     logging::{self, LogFormat},
     project_git_version,
     sentry_init::init_sentry,
@@ -122,7 +124,53 @@ struct Args {
     /// WAL backup horizon.
     #[arg(long)]
     disable_wal_backup: bool,
-    /// Path to a .pem public key which is used to check JWT tokens.
+    /// Bytes of WAL to keep on disk before the LSN up to which the pageserver
+    /// has it in remote storage, the peers have it and, unless disabled, it is
+    /// backed up, e.g. for recovery of lagging readers. Removal is done by
+    /// whole segments.
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    wal_retention_margin: u64,
+    /// Bytes of WAL the timelines of a tenant may take on disk together. Over
+    /// it, the WAL from the computes of the tenant isn't accepted until WAL
+    /// removal frees some space. Not limited by default.
+    #[arg(long, verbatim_doc_comment)]
+    tenant_wal_quota: Option<u64>,
+    /// Bytes per second of WAL accepted from the compute of each timeline.
+    /// Beyond it, AppendRequests are delayed, so the compute sees the
+    /// backpressure of a contended disk or network. Not limited by default.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), verbatim_doc_comment)]
+    ingest_rate_limit: Option<u64>,
+    /// Bytes of WAL accepted at once over --ingest-rate-limit after a pause.
+    /// Defaults to one second worth of it.
+    #[arg(long, requires = "ingest_rate_limit", verbatim_doc_comment)]
+    ingest_burst: Option<u64>,
+    /// Disable pulling the WAL a timeline misses from the most advanced peer
+    /// safekeeper when no compute is streaming to it. Always disabled with
+    /// auth, as peers don't authenticate to each other.
+    #[arg(long, verbatim_doc_comment)]
+    disable_peer_recovery: bool,
+    /// Time to wait for more WAL from the compute before flushing the WAL it
+    /// sent, so that one fsync covers several AppendRequests. Increases commit
+    /// latency by at most this much for higher commit throughput. With 0, WAL
+    /// is flushed as soon as no more of it is readily available.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "0s", verbatim_doc_comment)]
+    group_commit_window: Duration,
+    /// Interval of the feedback sent to the walproposer while no other
+    /// messages are sent to it. Should be well below its
+    /// neon.safekeeper_connect_timeout, after which it gives up on the
+    /// safekeeper; with high RTT between regions, raise both.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = DEFAULT_WALPROPOSER_KEEPALIVE_INTERVAL, verbatim_doc_comment)]
+    walproposer_keepalive_interval: Duration,
+    /// Filter of the WAL streamed to the pageservers of a region, which ask
+    /// for it with the region option of START_REPLICATION, as
+    /// `<region id>:<spcnode>/<dbnode>,...`. Records modifying relations of
+    /// databases other than the listed ones are replaced by no-op records;
+    /// `*` for the spcnode covers a database in all tablespaces, and for the
+    /// dbnode all databases in a tablespace. Can be repeated.
+    #[arg(long, value_parser = parse_region_wal_filter, verbatim_doc_comment)]
+    region_wal_filter: Vec<(RegionId, Arc<dyn WalFilter>)>,
+    /// Path to a .pem public key which is used to check JWT tokens, or to a
+    /// directory of `<key id>.pem` public keys to accept tokens from any of.
     #[arg(long)]
     auth_validation_public_key_path: Option<PathBuf>,
     /// Format for logging, either 'plain' or 'json'.
@@ -154,7 +202,26 @@ async fn main() -> anyhow::Result<()> {
         logging::TracingErrorLayerEnablement::Disabled,
     )?;
     logging::replace_panic_hook_with_tracing_panic_hook().forget();
-    info!("version: {GIT_VERSION}");
+    info!(
+        "version: {GIT_VERSION} failpoints: {}",
+        fail::has_failpoints()
+    );
+
+    // Initialize up failpoints support
+    let _scenario = fail::FailScenario::setup();
+    // If any failpoints were set from FAILPOINTS environment variable,
+    // print them to the log for debugging purposes
+    let failpoints = fail::list();
+    if !failpoints.is_empty() {
+        info!(
+            "started with failpoints: {}",
+            failpoints
+                .iter()
+                .map(|(name, actions)| format!("{name}={actions}"))
+                .collect::<Vec<String>>()
+                .join(";")
+        )
+    }
 
     let args_workdir = &args.datadir;
     let workdir = args_workdir.canonicalize().with_context(|| {
@@ -198,6 +265,14 @@ async fn main() -> anyhow::Result<()> {
         remote_storage: args.remote_storage,
         max_offloader_lag_bytes: args.max_offloader_lag,
         wal_backup_enabled: !args.disable_wal_backup,
+        wal_retention_margin_bytes: args.wal_retention_margin,
+        tenant_wal_quota_bytes: args.tenant_wal_quota,
+        ingest_rate_limit_bytes_per_sec: args.ingest_rate_limit,
+        ingest_burst_bytes: args.ingest_burst,
+        peer_recovery_enabled: !args.disable_peer_recovery && auth.is_none(),
+        group_commit_window: args.group_commit_window,
+        walproposer_keepalive_interval: args.walproposer_keepalive_interval,
+        region_wal_filters: args.region_wal_filter.into_iter().collect(),
         backup_parallel_jobs: args.wal_backup_parallel_jobs,
         auth,
         current_thread_runtime: args.current_thread_runtime,
@@ -333,6 +408,18 @@ async fn start_safekeeper(conf: SafeKeeperConf) -> Result<()> {
         .map(|res| ("WAL remover".to_owned(), res));
     tasks_handles.push(Box::pin(wal_remover_handle));
 
+    if conf.peer_recovery_enabled {
+        let conf_ = conf.clone();
+        let recovery_handle = current_thread_rt
+            .as_ref()
+            .unwrap_or_else(|| PEER_RECOVERY_RUNTIME.handle())
+            .spawn(recovery::task_main(conf_))
+            .map(|res| ("peer recovery".to_owned(), res));
+        tasks_handles.push(Box::pin(recovery_handle));
+    } else {
+        info!("peer recovery is disabled");
+    }
+
     let conf_ = conf.clone();
     let wal_backup_handle = current_thread_rt
         .as_ref()
@@ -71,6 +71,7 @@ pub struct Config {
     pub no_sync: bool,
     pub max_offloader_lag_bytes: u64,
     pub wal_backup_enabled: bool,
+    pub peer_recovery_enabled: bool,
 }
 
 #[serde_as]
@@ -262,5 +263,6 @@ fn build_config(config: SafeKeeperConf) -> Config {
         no_sync: config.no_sync,
         max_offloader_lag_bytes: config.max_offloader_lag_bytes,
         wal_backup_enabled: config.wal_backup_enabled,
+        peer_recovery_enabled: config.peer_recovery_enabled,
     }
 }
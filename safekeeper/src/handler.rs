@@ -11,7 +11,9 @@ use crate::auth::check_permission;
 use crate::json_ctrl::{handle_json_ctrl, AppendLogicalMessage};
 
 use crate::metrics::{TrafficMetrics, PG_QUERIES_FINISHED, PG_QUERIES_RECEIVED};
+use crate::recovery::RECOVERY_APPNAME;
 use crate::safekeeper::Term;
+use crate::send_wal::WalCompression;
 use crate::timeline::TimelineError;
 use crate::wal_service::ConnectionId;
 use crate::{GlobalTimelines, SafeKeeperConf};
@@ -22,7 +24,7 @@ use pq_proto::{BeMessage, FeStartupPacket, RowDescriptor, INT4_OID, TEXT_OID};
 use regex::Regex;
 use utils::auth::{Claims, Scope};
 use utils::{
-    id::{TenantId, TenantTimelineId, TimelineId},
+    id::{RegionId, TenantId, TenantTimelineId, TimelineId},
     lsn::Lsn,
 };
 
@@ -45,10 +47,24 @@ pub struct SafekeeperPostgresHandler {
 /// Parsed Postgres command.
 enum SafekeeperPostgresCommand {
     StartWalPush,
-    StartReplication { start_lsn: Lsn, term: Option<Term> },
+    StartReplication {
+        start_lsn: Lsn,
+        slot_name: Option<String>,
+        term: Option<Term>,
+        compression: Option<WalCompression>,
+        region: Option<RegionId>,
+    },
+    CreateReplicationSlot {
+        slot_name: String,
+    },
+    DropReplicationSlot {
+        slot_name: String,
+    },
     IdentifySystem,
     TimelineStatus,
-    JSONCtrl { cmd: AppendLogicalMessage },
+    JSONCtrl {
+        cmd: AppendLogicalMessage,
+    },
 }
 
 fn parse_cmd(cmd: &str) -> anyhow::Result<SafekeeperPostgresCommand> {
@@ -56,21 +72,77 @@ fn parse_cmd(cmd: &str) -> anyhow::Result<SafekeeperPostgresCommand> {
         Ok(SafekeeperPostgresCommand::StartWalPush)
     } else if cmd.starts_with("START_REPLICATION") {
         let re = Regex::new(
-            // We follow postgres START_REPLICATION LOGICAL options to pass term.
-            r"START_REPLICATION(?: SLOT [^ ]+)?(?: PHYSICAL)? ([[:xdigit:]]+/[[:xdigit:]]+)(?: \(term='(\d+)'\))?",
+            // We follow postgres START_REPLICATION LOGICAL options to pass term,
+            // compression and region.
+            r"START_REPLICATION(?: SLOT ([^ ]+))?(?: PHYSICAL)? ([[:xdigit:]]+/[[:xdigit:]]+)(?: TIMELINE ([[:digit:]]+))?(?: \((.*)\))?",
         )
         .unwrap();
         let caps = re
             .captures(cmd)
             .context(format!("failed to parse START_REPLICATION command {}", cmd))?;
+        let slot_name = caps.get(1).map(|m| unquote_slot_name(m.as_str()));
         let start_lsn =
-            Lsn::from_str(&caps[1]).context("parse start LSN from START_REPLICATION command")?;
-        let term = if let Some(m) = caps.get(2) {
-            Some(m.as_str().parse::<u64>().context("invalid term")?)
-        } else {
-            None
-        };
-        Ok(SafekeeperPostgresCommand::StartReplication { start_lsn, term })
+            Lsn::from_str(&caps[2]).context("parse start LSN from START_REPLICATION command")?;
+        // Standbys ask for the timeline they follow; there is only one.
+        if let Some(timeline) = caps.get(3) {
+            let timeline = timeline
+                .as_str()
+                .parse::<u32>()
+                .context("invalid timeline")?;
+            if timeline != PG_TLI {
+                anyhow::bail!("requested timeline {timeline} is not in this server's history");
+            }
+        }
+        let mut term = None;
+        let mut compression = None;
+        let mut region = None;
+        for option in caps.get(4).iter().flat_map(|m| m.as_str().split(',')) {
+            let (name, value) = option
+                .trim()
+                .split_once('=')
+                .and_then(|(name, value)| {
+                    Some((name, value.strip_prefix('\'')?.strip_suffix('\'')?))
+                })
+                .with_context(|| format!("invalid START_REPLICATION option {option}"))?;
+            match name {
+                "term" => term = Some(value.parse::<u64>().context("invalid term")?),
+                "compression" => compression = Some(value.parse::<WalCompression>()?),
+                "region" => region = Some(value.parse::<RegionId>().context("invalid region")?),
+                _ => anyhow::bail!("unsupported START_REPLICATION option {name}"),
+            }
+        }
+        Ok(SafekeeperPostgresCommand::StartReplication {
+            start_lsn,
+            slot_name,
+            term,
+            compression,
+            region,
+        })
+    } else if cmd.starts_with("CREATE_REPLICATION_SLOT") {
+        // Slots always reserve WAL, so RESERVE_WAL and the other options
+        // following the slot kind are ignored.
+        let re =
+            Regex::new(r#"CREATE_REPLICATION_SLOT ("[^"]+"|[^ ]+)( TEMPORARY)? ([A-Z]+)"#).unwrap();
+        let caps = re
+            .captures(cmd)
+            .with_context(|| format!("failed to parse CREATE_REPLICATION_SLOT command {cmd}"))?;
+        if caps.get(2).is_some() {
+            anyhow::bail!("temporary replication slots are not supported");
+        }
+        if &caps[3] != "PHYSICAL" {
+            anyhow::bail!("only physical replication slots are supported");
+        }
+        Ok(SafekeeperPostgresCommand::CreateReplicationSlot {
+            slot_name: unquote_slot_name(&caps[1]),
+        })
+    } else if cmd.starts_with("DROP_REPLICATION_SLOT") {
+        let re = Regex::new(r#"DROP_REPLICATION_SLOT ("[^"]+"|[^ ;]+)"#).unwrap();
+        let caps = re
+            .captures(cmd)
+            .with_context(|| format!("failed to parse DROP_REPLICATION_SLOT command {cmd}"))?;
+        Ok(SafekeeperPostgresCommand::DropReplicationSlot {
+            slot_name: unquote_slot_name(&caps[1]),
+        })
     } else if cmd.starts_with("IDENTIFY_SYSTEM") {
         Ok(SafekeeperPostgresCommand::IdentifySystem)
     } else if cmd.starts_with("TIMELINE_STATUS") {
@@ -85,10 +157,16 @@ fn parse_cmd(cmd: &str) -> anyhow::Result<SafekeeperPostgresCommand> {
     }
 }
 
+fn unquote_slot_name(slot_name: &str) -> String {
+    slot_name.trim_matches('"').to_owned()
+}
+
 fn cmd_to_string(cmd: &SafekeeperPostgresCommand) -> &str {
     match cmd {
         SafekeeperPostgresCommand::StartWalPush => "START_WAL_PUSH",
         SafekeeperPostgresCommand::StartReplication { .. } => "START_REPLICATION",
+        SafekeeperPostgresCommand::CreateReplicationSlot { .. } => "CREATE_REPLICATION_SLOT",
+        SafekeeperPostgresCommand::DropReplicationSlot { .. } => "DROP_REPLICATION_SLOT",
         SafekeeperPostgresCommand::TimelineStatus => "TIMELINE_STATUS",
         SafekeeperPostgresCommand::IdentifySystem => "IDENTIFY_SYSTEM",
         SafekeeperPostgresCommand::JSONCtrl { .. } => "JSON_CTRL",
@@ -225,11 +303,23 @@ impl<IO: AsyncRead + AsyncWrite + Unpin + Send> postgres_backend::Handler<IO>
                     .instrument(info_span!("WAL receiver", ttid = %span_ttid))
                     .await
             }
-            SafekeeperPostgresCommand::StartReplication { start_lsn, term } => {
-                self.handle_start_replication(pgb, start_lsn, term)
+            SafekeeperPostgresCommand::StartReplication {
+                start_lsn,
+                slot_name,
+                term,
+                compression,
+                region,
+            } => {
+                self.handle_start_replication(pgb, start_lsn, slot_name, term, compression, region)
                     .instrument(info_span!("WAL sender", ttid = %span_ttid))
                     .await
             }
+            SafekeeperPostgresCommand::CreateReplicationSlot { slot_name } => {
+                self.handle_create_replication_slot(pgb, &slot_name).await
+            }
+            SafekeeperPostgresCommand::DropReplicationSlot { slot_name } => {
+                self.handle_drop_replication_slot(pgb, &slot_name).await
+            }
             SafekeeperPostgresCommand::IdentifySystem => self.handle_identify_system(pgb).await,
             SafekeeperPostgresCommand::TimelineStatus => self.handle_timeline_status(pgb).await,
             SafekeeperPostgresCommand::JSONCtrl { ref cmd } => {
@@ -368,10 +458,75 @@ impl SafekeeperPostgresHandler {
         Ok(())
     }
 
+    ///
+    /// Handle CREATE_REPLICATION_SLOT replication command
+    ///
+    async fn handle_create_replication_slot<IO: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+        slot_name: &str,
+    ) -> Result<(), QueryError> {
+        let tli = GlobalTimelines::get(self.ttid).map_err(|e| QueryError::Other(e.into()))?;
+        let restart_lsn = tli.create_replication_slot(slot_name).await?.to_string();
+
+        pgb.write_message_noflush(&BeMessage::RowDescription(&[
+            RowDescriptor {
+                name: b"slot_name",
+                typoid: TEXT_OID,
+                typlen: -1,
+                ..Default::default()
+            },
+            RowDescriptor {
+                name: b"consistent_point",
+                typoid: TEXT_OID,
+                typlen: -1,
+                ..Default::default()
+            },
+            RowDescriptor {
+                name: b"snapshot_name",
+                typoid: TEXT_OID,
+                typlen: -1,
+                ..Default::default()
+            },
+            RowDescriptor {
+                name: b"output_plugin",
+                typoid: TEXT_OID,
+                typlen: -1,
+                ..Default::default()
+            },
+        ]))?
+        .write_message_noflush(&BeMessage::DataRow(&[
+            Some(slot_name.as_bytes()),
+            Some(restart_lsn.as_bytes()),
+            None,
+            None,
+        ]))?
+        .write_message_noflush(&BeMessage::CommandComplete(b"CREATE_REPLICATION_SLOT"))?;
+        Ok(())
+    }
+
+    ///
+    /// Handle DROP_REPLICATION_SLOT replication command
+    ///
+    async fn handle_drop_replication_slot<IO: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+        slot_name: &str,
+    ) -> Result<(), QueryError> {
+        let tli = GlobalTimelines::get(self.ttid).map_err(|e| QueryError::Other(e.into()))?;
+        tli.drop_replication_slot(slot_name).await?;
+        pgb.write_message_noflush(&BeMessage::CommandComplete(b"DROP_REPLICATION_SLOT"))?;
+        Ok(())
+    }
+
     /// Returns true if current connection is a replication connection, originating
-    /// from a walproposer recovery function. This connection gets a special handling:
-    /// safekeeper must stream all local WAL till the flush_lsn, whether committed or not.
+    /// from a walproposer recovery function or from peer recovery of another
+    /// safekeeper. This connection gets a special handling: safekeeper must
+    /// stream all local WAL till the flush_lsn, whether committed or not.
     pub fn is_walproposer_recovery(&self) -> bool {
-        self.appname == Some("wal_proposer_recovery".to_string())
+        matches!(
+            self.appname.as_deref(),
+            Some("wal_proposer_recovery") | Some(RECOVERY_APPNAME)
+        )
     }
 }
@@ -1,11 +1,16 @@
+use bytes::Bytes;
+use futures::StreamExt;
 use hyper::{Body, Request, Response, StatusCode, Uri};
 
 use once_cell::sync::Lazy;
 use postgres_ffi::WAL_SEGMENT_SIZE;
-use safekeeper_api::models::SkTimelineInfo;
-use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
-use std::collections::{HashMap, HashSet};
+use safekeeper_api::models::{
+    AcceptorStateStatus, ConfigureFailpointsRequest, PeerStatus, SkTimelineInfo, TenantInfo,
+    TermSwitchApiEntry, TimelineAckedLsn, TimelinePgInfo, TimelineRemoveWalResponse,
+    TimelineStatus, TimelineWalHorizon,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -13,11 +18,13 @@ use storage_broker::proto::SafekeeperTimelineInfo;
 use storage_broker::proto::TenantTimelineId as ProtoTenantTimelineId;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::{error, info_span, Instrument};
 use utils::http::endpoint::request_span;
 
 use crate::safekeeper::ServerInfo;
-use crate::safekeeper::Term;
-use crate::{debug_dump, pull_timeline};
+use crate::timeline::Timeline;
+use crate::{debug_dump, pull_timeline, snapshot};
 
 use crate::timelines_global_map::TimelineDeleteForceResult;
 use crate::GlobalTimelines;
@@ -32,11 +39,12 @@ use utils::{
         RequestExt, RouterBuilder,
     },
     id::{NodeId, TenantId, TenantTimelineId, TimelineId},
-    lsn::Lsn,
 };
 
 use super::models::TimelineCreateRequest;
 
+const TIMELINE_SNAPSHOT_BUFFER_SIZE: usize = 1024 * 1024;
+
 #[derive(Debug, Serialize)]
 struct SafekeeperStatus {
     id: NodeId,
@@ -57,56 +65,42 @@ fn get_conf(request: &Request<Body>) -> &SafeKeeperConf {
         .as_ref()
 }
 
-/// Same as TermSwitchEntry, but serializes LSN using display serializer
-/// in Postgres format, i.e. 0/FFFFFFFF. Used only for the API response.
-#[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TermSwitchApiEntry {
-    pub term: Term,
-    #[serde_as(as = "DisplayFromStr")]
-    pub lsn: Lsn,
-}
-
-/// Augment AcceptorState with epoch for convenience
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AcceptorStateStatus {
-    pub term: Term,
-    pub epoch: Term,
-    pub term_history: Vec<TermSwitchApiEntry>,
-}
-
-/// Info about timeline on safekeeper ready for reporting.
-#[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TimelineStatus {
-    #[serde_as(as = "DisplayFromStr")]
-    pub tenant_id: TenantId,
-    #[serde_as(as = "DisplayFromStr")]
-    pub timeline_id: TimelineId,
-    pub acceptor_state: AcceptorStateStatus,
-    pub pg_info: ServerInfo,
-    #[serde_as(as = "DisplayFromStr")]
-    pub flush_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub timeline_start_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub local_start_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub commit_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub backup_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub peer_horizon_lsn: Lsn,
-    #[serde_as(as = "DisplayFromStr")]
-    pub remote_consistent_lsn: Lsn,
-}
-
 fn check_permission(request: &Request<Body>, tenant_id: Option<TenantId>) -> Result<(), ApiError> {
     check_permission_with(request, |claims| {
         crate::auth::check_permission(claims, tenant_id)
     })
 }
 
+/// List the tenants with timelines on the safekeeper, with the disk space
+/// taken by their WAL.
+async fn tenant_list_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    let conf = get_conf(&request);
+
+    let mut timelines: BTreeMap<TenantId, Vec<Arc<Timeline>>> = BTreeMap::new();
+    for tli in GlobalTimelines::get_all() {
+        timelines.entry(tli.ttid.tenant_id).or_default().push(tli);
+    }
+
+    let mut tenants = Vec::with_capacity(timelines.len());
+    for (tenant_id, tenant_timelines) in timelines {
+        let mut wal_disk_usage_bytes = 0;
+        for tli in tenant_timelines.iter() {
+            wal_disk_usage_bytes += tli.get_wal_disk_usage().await;
+        }
+        tenants.push(TenantInfo {
+            tenant_id,
+            timelines: tenant_timelines
+                .iter()
+                .map(|tli| tli.ttid.timeline_id)
+                .collect(),
+            wal_disk_usage_bytes,
+            wal_quota_bytes: conf.tenant_wal_quota_bytes,
+        });
+    }
+    json_response(StatusCode::OK, tenants)
+}
+
 /// Report info about timeline.
 async fn timeline_status_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
     let ttid = TenantTimelineId::new(
@@ -118,6 +112,22 @@ async fn timeline_status_handler(request: Request<Body>) -> Result<Response<Body
     let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
     let (inmem, state) = tli.get_state().await;
     let flush_lsn = tli.get_flush_lsn().await;
+    let last_removed_segno = tli.get_last_removed_segno().await;
+    let conf = get_conf(&request);
+    // Our own info comes back through the broker as well.
+    let peers = tli
+        .get_peers(conf)
+        .await
+        .into_iter()
+        .filter(|p| p.sk_id != conf.my_id)
+        .map(|p| PeerStatus {
+            sk_id: p.sk_id,
+            last_log_term: p.last_log_term,
+            flush_lsn: p.flush_lsn,
+            commit_lsn: p.commit_lsn,
+            local_start_lsn: p.local_start_lsn,
+        })
+        .collect();
 
     let epoch = state.acceptor_state.get_epoch(flush_lsn);
     let term_history = state
@@ -141,7 +151,11 @@ async fn timeline_status_handler(request: Request<Body>) -> Result<Response<Body
         tenant_id: ttid.tenant_id,
         timeline_id: ttid.timeline_id,
         acceptor_state: acc_state,
-        pg_info: state.server,
+        pg_info: TimelinePgInfo {
+            pg_version: state.server.pg_version,
+            system_id: state.server.system_id,
+            wal_seg_size: state.server.wal_seg_size,
+        },
         flush_lsn,
         timeline_start_lsn: state.timeline_start_lsn,
         local_start_lsn: state.local_start_lsn,
@@ -149,10 +163,90 @@ async fn timeline_status_handler(request: Request<Body>) -> Result<Response<Body
         backup_lsn: inmem.backup_lsn,
         peer_horizon_lsn: inmem.peer_horizon_lsn,
         remote_consistent_lsn: tli.get_walsenders().get_remote_consistent_lsn(),
+        last_removed_segno,
+        peers,
     };
     json_response(StatusCode::OK, status)
 }
 
+/// Report the persisted control file state of the timeline: the term, the
+/// term history and the LSNs, e.g. to debug stuck elections.
+async fn timeline_control_file_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let (_, state) = tli.get_state().await;
+    json_response(StatusCode::OK, state)
+}
+
+/// Remove the WAL segments of the timeline that nothing needs anymore now,
+/// without waiting for the next round of the WAL removal task.
+async fn timeline_remove_wal_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let conf = get_conf(&request);
+    let last_removed_segno = tli
+        .remove_old_wal(conf.wal_backup_enabled, conf.wal_retention_margin_bytes)
+        .await
+        .map_err(ApiError::InternalServerError)?;
+    json_response(
+        StatusCode::OK,
+        TimelineRemoveWalResponse { last_removed_segno },
+    )
+}
+
+/// Report the LSN before which the WAL of the timeline is removed, and the
+/// LSNs it is computed from.
+async fn timeline_wal_horizon_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let conf = get_conf(&request);
+    let (_, state) = tli.get_state().await;
+    let horizon = TimelineWalHorizon {
+        remote_consistent_lsn: state.remote_consistent_lsn,
+        peer_horizon_lsn: state.peer_horizon_lsn,
+        backup_lsn: conf.wal_backup_enabled.then_some(state.backup_lsn),
+        slots_restart_lsn: tli.get_walsenders().get_slots_restart_lsn(),
+        retention_margin: conf.wal_retention_margin_bytes,
+        horizon_lsn: tli
+            .get_horizon_lsn(conf.wal_backup_enabled, conf.wal_retention_margin_bytes)
+            .await,
+        last_removed_segno: tli.get_last_removed_segno().await,
+    };
+    json_response(StatusCode::OK, horizon)
+}
+
+/// Report how far the safekeeper has acknowledged the WAL of the timeline. A
+/// cheap subset of the timeline status, for tools polling the quorum.
+async fn timeline_acked_lsn_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let acked = TimelineAckedLsn {
+        flush_lsn: tli.get_flush_lsn().await,
+        commit_lsn: tli.get_state().await.0.commit_lsn,
+    };
+    json_response(StatusCode::OK, acked)
+}
+
 async fn timeline_create_handler(mut request: Request<Body>) -> Result<Response<Body>, ApiError> {
     let request_data: TimelineCreateRequest = json_request(&mut request).await?;
 
@@ -191,6 +285,81 @@ async fn timeline_pull_handler(mut request: Request<Body>) -> Result<Response<Bo
     json_response(StatusCode::OK, resp)
 }
 
+/// Stream a tar archive of the control file and WAL of the timeline, to seed
+/// another safekeeper with.
+async fn timeline_snapshot_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let conf = get_conf(&request).clone();
+
+    let (reader, writer) = tokio::io::duplex(TIMELINE_SNAPSHOT_BUFFER_SIZE);
+    let snapshot_task = tokio::spawn(
+        async move { snapshot::write_snapshot(&tli, &conf, writer).await }
+            .instrument(info_span!("timeline_snapshot", %ttid)),
+    );
+    // Fail the response body rather than end it if the snapshot fails midway,
+    // so that the client does not take a truncated archive for a complete one.
+    let snapshot_error = futures::stream::once(async move {
+        let result = match snapshot_task.await {
+            Ok(result) => result,
+            Err(e) => Err(anyhow::anyhow!("snapshot task failed: {e}")),
+        };
+        result.err().map(|e| {
+            error!(%ttid, "timeline snapshot failed: {e:#}");
+            Err::<Bytes, _>(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{e:#}"),
+            ))
+        })
+    })
+    .filter_map(std::future::ready);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/x-tar")
+        .body(Body::wrap_stream(
+            ReaderStream::new(reader).chain(snapshot_error),
+        ))
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
+/// Create a timeline from a snapshot of the snapshot API, sent as the request
+/// body.
+async fn timeline_restore_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    if GlobalTimelines::get(ttid).is_ok() {
+        return Err(ApiError::Conflict(format!(
+            "timeline {ttid} already exists"
+        )));
+    }
+    let conf = get_conf(&request).clone();
+    let archive = StreamReader::new(
+        request
+            .into_body()
+            .map(|chunk| chunk.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))),
+    );
+
+    let tli = snapshot::restore_snapshot(&conf, ttid, archive)
+        .instrument(info_span!("timeline_restore", %ttid))
+        .await
+        .map_err(ApiError::InternalServerError)?;
+    let acked = TimelineAckedLsn {
+        flush_lsn: tli.get_flush_lsn().await,
+        commit_lsn: tli.get_state().await.0.commit_lsn,
+    };
+    json_response(StatusCode::CREATED, acked)
+}
+
 /// Download a file from the timeline directory.
 // TODO: figure out a better way to copy files between safekeepers
 async fn timeline_files_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
@@ -262,6 +431,40 @@ async fn tenant_delete_force_handler(
     )
 }
 
+/// Configure failpoints, used only in tests.
+async fn failpoints_handler(mut request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    if !fail::has_failpoints() {
+        return Err(ApiError::BadRequest(anyhow::anyhow!(
+            "Cannot manage failpoints because safekeeper was compiled without failpoints support"
+        )));
+    }
+
+    let failpoints: ConfigureFailpointsRequest = json_request(&mut request).await?;
+    for fp in failpoints {
+        tracing::info!("cfg failpoint: {} {}", fp.name, fp.actions);
+
+        // We recognize one extra "action" that's not natively recognized
+        // by the failpoints crate: exit, to immediately kill the process
+        let cfg_result = if fp.actions == "exit" {
+            fail::cfg_callback(fp.name, || {
+                tracing::info!("Exit requested by failpoint");
+                std::process::exit(1);
+            })
+        } else {
+            fail::cfg(fp.name, &fp.actions)
+        };
+
+        if let Err(err_msg) = cfg_result {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "Failed to configure failpoints: {err_msg}"
+            )));
+        }
+    }
+
+    json_response(StatusCode::OK, ())
+}
+
 /// Used only in tests to hand craft required data.
 async fn record_safekeeper_info(mut request: Request<Body>) -> Result<Response<Body>, ApiError> {
     let ttid = TenantTimelineId::new(
@@ -282,6 +485,9 @@ async fn record_safekeeper_info(mut request: Request<Body>) -> Result<Response<B
         remote_consistent_lsn: sk_info.remote_consistent_lsn.0,
         peer_horizon_lsn: sk_info.peer_horizon_lsn.0,
         safekeeper_connstr: sk_info.safekeeper_connstr.unwrap_or_else(|| "".to_owned()),
+        http_connstr: sk_info.http_connstr.unwrap_or_else(|| "".to_owned()),
+        zstd_wal_compression: true,
+        region_wal_filter: true,
         backup_lsn: sk_info.backup_lsn.0,
         local_start_lsn: sk_info.local_start_lsn.0,
         availability_zone: None,
@@ -380,6 +586,7 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
         .data(Arc::new(conf))
         .data(auth)
         .get("/v1/status", |r| request_span(r, status_handler))
+        .get("/v1/tenant", |r| request_span(r, tenant_list_handler))
         // Will be used in the future instead of implicit timeline creation
         .post("/v1/tenant/timeline", |r| {
             request_span(r, timeline_create_handler)
@@ -387,6 +594,22 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
         .get("/v1/tenant/:tenant_id/timeline/:timeline_id", |r| {
             request_span(r, timeline_status_handler)
         })
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/acked_lsn",
+            |r| request_span(r, timeline_acked_lsn_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/control_file",
+            |r| request_span(r, timeline_control_file_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/wal_horizon",
+            |r| request_span(r, timeline_wal_horizon_handler),
+        )
+        .post(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/remove_wal",
+            |r| request_span(r, timeline_remove_wal_handler),
+        )
         .delete("/v1/tenant/:tenant_id/timeline/:timeline_id", |r| {
             request_span(r, timeline_delete_force_handler)
         })
@@ -400,7 +623,15 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
             "/v1/tenant/:tenant_id/timeline/:timeline_id/file/:filename",
             |r| request_span(r, timeline_files_handler),
         )
+        .get(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/snapshot",
+            |r| request_span(r, timeline_snapshot_handler),
+        )
+        .put("/v1/tenant/:tenant_id/timeline/:timeline_id/restore", |r| {
+            request_span(r, timeline_restore_handler)
+        })
         // for tests
+        .put("/v1/failpoints", |r| request_span(r, failpoints_handler))
         .post("/v1/record_safekeeper_info/:tenant_id/:timeline_id", |r| {
             request_span(r, record_safekeeper_info)
         })
@@ -410,6 +641,7 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::lsn::Lsn;
 
     #[test]
     fn test_term_switch_entry_api_serialize() {
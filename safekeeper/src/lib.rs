@@ -2,11 +2,12 @@ use once_cell::sync::Lazy;
 use remote_storage::RemoteStorageConfig;
 use tokio::runtime::Runtime;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::Duration;
 use storage_broker::Uri;
 
-use utils::id::{NodeId, TenantId, TenantTimelineId};
+use utils::id::{NodeId, RegionId, TenantId, TenantTimelineId};
 
 mod auth;
 pub mod broker;
@@ -19,11 +20,14 @@ pub mod json_ctrl;
 pub mod metrics;
 pub mod pull_timeline;
 pub mod receive_wal;
+pub mod recovery;
 pub mod remove_wal;
 pub mod safekeeper;
 pub mod send_wal;
+pub mod snapshot;
 pub mod timeline;
 pub mod wal_backup;
+pub mod wal_filter;
 pub mod wal_service;
 pub mod wal_storage;
 
@@ -31,6 +35,7 @@ mod timelines_global_map;
 use std::sync::Arc;
 pub use timelines_global_map::GlobalTimelines;
 use utils::auth::JwtAuth;
+use wal_filter::WalFilter;
 
 pub mod defaults {
     pub use safekeeper_api::{
@@ -39,6 +44,7 @@ pub mod defaults {
     };
 
     pub const DEFAULT_HEARTBEAT_TIMEOUT: &str = "5000ms";
+    pub const DEFAULT_WALPROPOSER_KEEPALIVE_INTERVAL: &str = "1000ms";
     pub const DEFAULT_MAX_OFFLOADER_LAG_BYTES: u64 = 128 * (1 << 20);
 }
 
@@ -65,6 +71,22 @@ pub struct SafeKeeperConf {
     pub max_offloader_lag_bytes: u64,
     pub backup_parallel_jobs: usize,
     pub wal_backup_enabled: bool,
+    /// Bytes of WAL kept before the horizon up to which nothing needs it.
+    pub wal_retention_margin_bytes: u64,
+    /// Disk space the WAL of all timelines of a tenant may take before
+    /// writing more of it is held off.
+    pub tenant_wal_quota_bytes: Option<u64>,
+    /// Bytes per second of WAL accepted from the compute of each timeline,
+    /// and how much of it may arrive at once over that rate.
+    pub ingest_rate_limit_bytes_per_sec: Option<u64>,
+    pub ingest_burst_bytes: Option<u64>,
+    pub peer_recovery_enabled: bool,
+    pub group_commit_window: Duration,
+    /// How often the walproposer is sent feedback when nothing else is sent to
+    /// it, so that it doesn't consider the safekeeper dead.
+    pub walproposer_keepalive_interval: Duration,
+    /// Filters of the WAL streamed to the regions asking for it.
+    pub region_wal_filters: HashMap<RegionId, Arc<dyn WalFilter>>,
     pub auth: Option<Arc<JwtAuth>>,
     pub current_thread_runtime: bool,
 }
@@ -98,6 +120,14 @@ impl SafeKeeperConf {
                 .expect("failed to parse default broker endpoint"),
             broker_keepalive_interval: Duration::from_secs(5),
             wal_backup_enabled: true,
+            wal_retention_margin_bytes: 0,
+            tenant_wal_quota_bytes: None,
+            ingest_rate_limit_bytes_per_sec: None,
+            ingest_burst_bytes: None,
+            peer_recovery_enabled: false,
+            group_commit_window: Duration::ZERO,
+            walproposer_keepalive_interval: Duration::from_secs(1),
+            region_wal_filters: HashMap::new(),
             backup_parallel_jobs: 1,
             auth: None,
             heartbeat_timeout: Duration::new(5, 0),
@@ -142,6 +172,14 @@ pub static WAL_REMOVER_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
         .expect("Failed to create broker runtime")
 });
 
+pub static PEER_RECOVERY_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .thread_name("peer recovery worker")
+        .enable_all()
+        .build()
+        .expect("Failed to create peer recovery runtime")
+});
+
 pub static WAL_BACKUP_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .thread_name("WAL backup worker")
@@ -5,13 +5,17 @@ use std::{
     time::{Instant, SystemTime},
 };
 
-use ::metrics::{register_histogram, GaugeVec, Histogram, IntGauge, DISK_WRITE_SECONDS_BUCKETS};
+use ::metrics::{
+    register_histogram, register_histogram_vec, GaugeVec, Histogram, HistogramVec, IntGauge,
+    DISK_WRITE_SECONDS_BUCKETS,
+};
 use anyhow::Result;
 use futures::Future;
 use metrics::{
     core::{AtomicU64, Collector, Desc, GenericCounter, GenericGaugeVec, Opts},
     proto::MetricFamily,
-    register_int_counter, register_int_counter_vec, Gauge, IntCounter, IntCounterVec, IntGaugeVec,
+    register_counter, register_int_counter, register_int_counter_vec, Counter, Gauge, IntCounter,
+    IntCounterVec, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -21,6 +25,7 @@ use utils::{id::TenantTimelineId, lsn::Lsn};
 
 use crate::{
     safekeeper::{SafeKeeperState, SafekeeperMemState},
+    timeline::wal_disk_usage,
     GlobalTimelines,
 };
 
@@ -58,6 +63,23 @@ pub static FLUSH_WAL_SECONDS: Lazy<Histogram> = Lazy::new(|| {
     )
     .expect("Failed to register safekeeper_flush_wal_seconds histogram")
 });
+pub static FLUSH_BATCH_SIZE: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "safekeeper_flush_batch_size",
+        "Number of AppendRequests flushed to disk together",
+        vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0]
+    )
+    .expect("Failed to register safekeeper_flush_batch_size histogram")
+});
+pub static COMMIT_LATENCY_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "safekeeper_commit_latency_seconds",
+        "Seconds from receiving WAL to flushing it (flush), from flushing it to learning that the quorum has it (quorum_ack) and both together (total)",
+        &["phase"],
+        vec![0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .expect("Failed to register safekeeper_commit_latency_seconds histogram vec")
+});
 pub static PERSIST_CONTROL_FILE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!(
         "safekeeper_persist_control_file_seconds",
@@ -74,6 +96,43 @@ pub static PG_IO_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .expect("Failed to register safekeeper_pg_io_bytes gauge")
 });
+pub static COMPRESSED_WAL_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "safekeeper_compressed_wal_bytes_total",
+        "Bytes of WAL streamed with compression, before and after compressing it",
+        &["stage"]
+    )
+    .expect("Failed to register safekeeper_compressed_wal_bytes_total counter")
+});
+pub static FILTERED_WAL_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "safekeeper_filtered_wal_bytes_total",
+        "Bytes of WAL records replaced by no-op records when streaming to a region"
+    )
+    .expect("Failed to register safekeeper_filtered_wal_bytes_total counter")
+});
+pub static WAL_VALIDATION_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "safekeeper_wal_validation_failures_total",
+        "Pieces of WAL rejected before being written, by the kind of the failed check",
+        &["kind"]
+    )
+    .expect("Failed to register safekeeper_wal_validation_failures_total counter")
+});
+pub static WAL_QUOTA_WAITS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "safekeeper_wal_quota_waits_total",
+        "Times accepting WAL was held off because the WAL of its tenant exceeded the quota"
+    )
+    .expect("Failed to register safekeeper_wal_quota_waits_total counter")
+});
+pub static INGEST_THROTTLED_SECONDS: Lazy<Counter> = Lazy::new(|| {
+    register_counter!(
+        "safekeeper_ingest_throttled_seconds_total",
+        "Seconds AppendRequests were delayed by the WAL ingest rate limit"
+    )
+    .expect("Failed to register safekeeper_ingest_throttled_seconds_total counter")
+});
 pub static BROKER_PUSHED_UPDATES: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
         "safekeeper_broker_pushed_updates_total",
@@ -112,6 +171,13 @@ pub static REMOVED_WAL_SEGMENTS: Lazy<IntCounter> = Lazy::new(|| {
     )
     .expect("Failed to register safekeeper_removed_wal_segments_total counter")
 });
+pub static PEER_RECOVERED_WAL_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "safekeeper_peer_recovered_wal_bytes_total",
+        "Number of bytes of WAL pulled from peer safekeepers"
+    )
+    .expect("Failed to register safekeeper_peer_recovered_wal_bytes_total counter")
+});
 pub static BACKED_UP_SEGMENTS: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
         "safekeeper_backed_up_segments_total",
@@ -318,6 +384,8 @@ pub struct FullTimelineInfo {
 
     pub flush_lsn: Lsn,
     pub remote_consistent_lsn: Lsn,
+    /// Highest commit_lsn the peers told us about through the broker.
+    pub peers_max_commit_lsn: Lsn,
 
     pub wal_storage: WalStorageMetrics,
 }
@@ -326,6 +394,7 @@ pub struct FullTimelineInfo {
 pub struct TimelineCollector {
     descs: Vec<Desc>,
     commit_lsn: GenericGaugeVec<AtomicU64>,
+    commit_lsn_lag: GenericGaugeVec<AtomicU64>,
     backup_lsn: GenericGaugeVec<AtomicU64>,
     flush_lsn: GenericGaugeVec<AtomicU64>,
     epoch_start_lsn: GenericGaugeVec<AtomicU64>,
@@ -365,6 +434,16 @@ impl TimelineCollector {
         .unwrap();
         descs.extend(commit_lsn.desc().into_iter().cloned());
 
+        let commit_lsn_lag = GenericGaugeVec::new(
+            Opts::new(
+                "safekeeper_commit_lsn_lag_bytes",
+                "How far commit_lsn is behind the highest one of the peers",
+            ),
+            &["tenant_id", "timeline_id"],
+        )
+        .unwrap();
+        descs.extend(commit_lsn_lag.desc().into_iter().cloned());
+
         let backup_lsn = GenericGaugeVec::new(
             Opts::new(
                 "safekeeper_backup_lsn",
@@ -529,6 +608,7 @@ impl TimelineCollector {
         TimelineCollector {
             descs,
             commit_lsn,
+            commit_lsn_lag,
             backup_lsn,
             flush_lsn,
             epoch_start_lsn,
@@ -560,6 +640,7 @@ impl Collector for TimelineCollector {
 
         // reset all metrics to clean up inactive timelines
         self.commit_lsn.reset();
+        self.commit_lsn_lag.reset();
         self.backup_lsn.reset();
         self.flush_lsn.reset();
         self.epoch_start_lsn.reset();
@@ -599,6 +680,11 @@ impl Collector for TimelineCollector {
             self.commit_lsn
                 .with_label_values(labels)
                 .set(tli.mem_state.commit_lsn.into());
+            self.commit_lsn_lag.with_label_values(labels).set(
+                tli.peers_max_commit_lsn
+                    .0
+                    .saturating_sub(tli.mem_state.commit_lsn.0),
+            );
             self.backup_lsn
                 .with_label_values(labels)
                 .set(tli.mem_state.backup_lsn.into());
@@ -649,21 +735,20 @@ impl Collector for TimelineCollector {
                     .set(unix_time.as_secs());
             }
 
-            if tli.last_removed_segno != 0 {
-                let segno_count = tli
-                    .flush_lsn
-                    .segment_number(tli.persisted_state.server.wal_seg_size as usize)
-                    - tli.last_removed_segno;
-                let disk_usage_bytes = segno_count * tli.persisted_state.server.wal_seg_size as u64;
-                self.disk_usage
-                    .with_label_values(labels)
-                    .set(disk_usage_bytes);
-            }
+            self.disk_usage
+                .with_label_values(labels)
+                .set(wal_disk_usage(
+                    tli.persisted_state.local_start_lsn,
+                    tli.last_removed_segno,
+                    tli.flush_lsn,
+                    tli.persisted_state.server.wal_seg_size as usize,
+                ));
         }
 
         // collect MetricFamilys.
         let mut mfs = Vec::new();
         mfs.extend(self.commit_lsn.collect());
+        mfs.extend(self.commit_lsn_lag.collect());
         mfs.extend(self.backup_lsn.collect());
         mfs.extend(self.flush_lsn.collect());
         mfs.extend(self.epoch_start_lsn.collect());
@@ -1,6 +1,11 @@
+use safekeeper_api::models::TimelineStatus;
 use serde::{Deserialize, Serialize};
 
+use std::path::Path;
+use std::sync::Arc;
+
 use anyhow::{bail, Context, Result};
+use tempfile::TempDir;
 use tokio::io::AsyncWriteExt;
 use tracing::info;
 use utils::id::{TenantId, TenantTimelineId, TimelineId};
@@ -9,9 +14,9 @@ use serde_with::{serde_as, DisplayFromStr};
 
 use crate::{
     control_file, debug_dump,
-    http::routes::TimelineStatus,
+    timeline::Timeline,
     wal_storage::{self, Storage},
-    GlobalTimelines,
+    GlobalTimelines, SafeKeeperConf,
 };
 
 /// Info about timeline on safekeeper ready for reporting.
@@ -58,7 +63,7 @@ pub async fn handle_request(request: Request) -> Result<Response> {
     let mut statuses = Vec::new();
     for (i, response) in responses.into_iter().enumerate() {
         let response = response.context(format!("Failed to get status from {}", http_hosts[i]))?;
-        let status: crate::http::routes::TimelineStatus = response.json().await?;
+        let status: TimelineStatus = response.json().await?;
         statuses.push((status, i));
     }
 
@@ -154,23 +159,7 @@ async fn pull_timeline(status: TimelineStatus, host: String) -> Result<Response>
         host
     );
 
-    // Creating temp directory for a new timeline. It needs to be
-    // located on the same filesystem as the rest of the timelines.
-
-    // conf.workdir is usually /storage/safekeeper/data
-    // will try to transform it into /storage/safekeeper/tmp
-    let temp_base = conf
-        .workdir
-        .parent()
-        .ok_or(anyhow::anyhow!("workdir has no parent"))?
-        .join("tmp");
-
-    tokio::fs::create_dir_all(&temp_base).await?;
-
-    let tli_dir = tempfile::Builder::new()
-        .suffix("_temptli")
-        .prefix(&format!("{}_{}_", ttid.tenant_id, ttid.timeline_id))
-        .tempdir_in(temp_base)?;
+    let tli_dir = create_temp_timeline_dir(conf, ttid).await?;
     let tli_dir_path = tli_dir.path().to_owned();
 
     // Note: some time happens between fetching list of files and fetching files themselves.
@@ -194,26 +183,79 @@ async fn pull_timeline(status: TimelineStatus, host: String) -> Result<Response>
 
     // TODO: fsync?
 
-    // Let's create timeline from temp directory and verify that it's correct
+    info!(
+        "Finished downloading timeline {}, commit_lsn={}",
+        ttid, status.commit_lsn
+    );
+    assert!(status.commit_lsn <= status.flush_lsn);
+
+    let tli = load_temp_timeline(conf, ttid, &tli_dir_path).await?;
+
+    info!(
+        "Loaded timeline {}, flush_lsn={}",
+        ttid,
+        tli.get_flush_lsn().await
+    );
+
+    Ok(Response {
+        safekeeper_host: host,
+    })
+}
+
+/// Create a temporary directory to put the files of a new timeline into before
+/// it is loaded. It is located on the same filesystem as the timelines, so that
+/// it can be moved into place.
+pub(crate) async fn create_temp_timeline_dir(
+    conf: &SafeKeeperConf,
+    ttid: TenantTimelineId,
+) -> Result<TempDir> {
+    // conf.workdir is usually /storage/safekeeper/data
+    // will try to transform it into /storage/safekeeper/tmp
+    let temp_base = conf
+        .workdir
+        .parent()
+        .ok_or(anyhow::anyhow!("workdir has no parent"))?
+        .join("tmp");
+
+    tokio::fs::create_dir_all(&temp_base).await?;
+
+    let tli_dir = tempfile::Builder::new()
+        .suffix("_temptli")
+        .prefix(&format!("{}_{}_", ttid.tenant_id, ttid.timeline_id))
+        .tempdir_in(temp_base)?;
+    Ok(tli_dir)
+}
 
+/// Verify that the timeline in the temporary directory `tli_dir_path` is
+/// correct, move it into place and load it.
+pub(crate) async fn load_temp_timeline(
+    conf: &SafeKeeperConf,
+    ttid: TenantTimelineId,
+    tli_dir_path: &Path,
+) -> Result<Arc<Timeline>> {
     let control_path = tli_dir_path.join("safekeeper.control");
 
     let control_store = control_file::FileStorage::load_control_file(control_path)?;
     if control_store.server.wal_seg_size == 0 {
         bail!("wal_seg_size is not set");
     }
+    if control_store.tenant_id != ttid.tenant_id || control_store.timeline_id != ttid.timeline_id {
+        bail!(
+            "control file is of timeline {}/{}, expected {}",
+            control_store.tenant_id,
+            control_store.timeline_id,
+            ttid
+        );
+    }
 
     let wal_store =
-        wal_storage::PhysicalStorage::new(&ttid, tli_dir_path.clone(), conf, &control_store)?;
-
-    let commit_lsn = status.commit_lsn;
-    let flush_lsn = wal_store.flush_lsn();
-
+        wal_storage::PhysicalStorage::new(&ttid, tli_dir_path.to_owned(), conf, &control_store)?;
     info!(
-        "Finished downloading timeline {}, commit_lsn={}, flush_lsn={}",
-        ttid, commit_lsn, flush_lsn
+        "Timeline {} has commit_lsn={}, flush_lsn={}",
+        ttid,
+        control_store.commit_lsn,
+        wal_store.flush_lsn()
     );
-    assert!(status.commit_lsn <= status.flush_lsn);
 
     // Move timeline dir to the correct location
     let timeline_path = conf.timeline_dir(&ttid);
@@ -227,15 +269,5 @@ async fn pull_timeline(status: TimelineStatus, host: String) -> Result<Response>
     tokio::fs::create_dir_all(conf.tenant_dir(&ttid.tenant_id)).await?;
     tokio::fs::rename(tli_dir_path, &timeline_path).await?;
 
-    let tli = GlobalTimelines::load_timeline(ttid).context("Failed to load timeline after copy")?;
-
-    info!(
-        "Loaded timeline {}, flush_lsn={}",
-        ttid,
-        tli.get_flush_lsn().await
-    );
-
-    Ok(Response {
-        safekeeper_host: host,
-    })
+    GlobalTimelines::load_timeline(ttid).context("Failed to load timeline after copy")
 }
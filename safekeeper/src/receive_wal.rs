@@ -3,6 +3,9 @@
 //! sends replies back.
 
 use crate::handler::SafekeeperPostgresHandler;
+use crate::metrics::{
+    COMMIT_LATENCY_SECONDS, FLUSH_BATCH_SIZE, INGEST_THROTTLED_SECONDS, WAL_QUOTA_WAITS,
+};
 use crate::safekeeper::AcceptorProposerMessage;
 use crate::safekeeper::ProposerAcceptorMessage;
 use crate::safekeeper::ServerInfo;
@@ -16,6 +19,7 @@ use postgres_backend::PostgresBackend;
 use postgres_backend::PostgresBackendReader;
 use postgres_backend::QueryError;
 use pq_proto::BeMessage;
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::AsyncRead;
@@ -26,6 +30,8 @@ use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
 use tokio::task;
 use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio::time::timeout_at;
 use tokio::time::Duration;
 use tokio::time::Instant;
 use tracing::*;
@@ -77,6 +83,9 @@ impl SafekeeperPostgresHandler {
             pgb_reader: &mut pgb_reader,
             peer_addr,
             acceptor_handle: &mut acceptor_handle,
+            group_commit_window: self.conf.group_commit_window,
+            tenant_wal_quota: self.conf.tenant_wal_quota_bytes,
+            keepalive_interval: self.conf.walproposer_keepalive_interval,
         };
         let res = tokio::select! {
             // todo: add read|write .context to these errors
@@ -122,6 +131,9 @@ struct NetworkReader<'a, IO> {
     // WalAcceptor is spawned when we learn server info from walproposer and
     // create timeline; handle is put here.
     acceptor_handle: &'a mut Option<JoinHandle<anyhow::Result<()>>>,
+    group_commit_window: Duration,
+    tenant_wal_quota: Option<u64>,
+    keepalive_interval: Duration,
 }
 
 impl<'a, IO: AsyncRead + AsyncWrite + Unpin> NetworkReader<'a, IO> {
@@ -158,6 +170,9 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> NetworkReader<'a, IO> {
             msg_rx,
             reply_tx,
             self.conn_id,
+            self.group_commit_window,
+            self.tenant_wal_quota,
+            self.keepalive_interval,
         ));
 
         // Forward all messages to WalAcceptor
@@ -209,15 +224,71 @@ async fn network_write<IO: AsyncRead + AsyncWrite + Unpin>(
     }
 }
 
-// Send keepalive messages to walproposer, to make sure it receives updates
-// even when it writes a steady stream of messages.
-const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+/// Measures how long the WAL from the walproposer takes to be flushed here and
+/// then reported committed by it, which needs the acks of the quorum; with
+/// safekeepers in several regions, those of the remote ones.
+#[derive(Default)]
+struct CommitLatencyTracker {
+    /// Flushed WAL not known to be committed yet, by batches: the end of the
+    /// batch, when its first AppendRequest was received and when it was
+    /// flushed.
+    pending: VecDeque<(Lsn, Instant, Instant)>,
+}
+
+impl CommitLatencyTracker {
+    /// Batches to keep track of at most, if the commit LSN doesn't advance.
+    const MAX_PENDING: usize = 1024;
+
+    fn on_flush(&mut self, end_lsn: Lsn, received_at: Instant) {
+        let now = Instant::now();
+        COMMIT_LATENCY_SECONDS
+            .with_label_values(&["flush"])
+            .observe((now - received_at).as_secs_f64());
+        if self
+            .pending
+            .back()
+            .map_or(true, |(lsn, _, _)| *lsn < end_lsn)
+        {
+            if self.pending.len() == Self::MAX_PENDING {
+                self.pending.pop_front();
+            }
+            self.pending.push_back((end_lsn, received_at, now));
+        }
+    }
+
+    fn on_commit(&mut self, commit_lsn: Lsn) {
+        let now = Instant::now();
+        while let Some(&(end_lsn, received_at, flushed_at)) = self.pending.front() {
+            if end_lsn > commit_lsn {
+                break;
+            }
+            COMMIT_LATENCY_SECONDS
+                .with_label_values(&["quorum_ack"])
+                .observe((now - flushed_at).as_secs_f64());
+            COMMIT_LATENCY_SECONDS
+                .with_label_values(&["total"])
+                .observe((now - received_at).as_secs_f64());
+            self.pending.pop_front();
+        }
+    }
+}
+
+/// How often to recheck the WAL disk usage of a tenant over its quota.
+const WAL_QUOTA_CHECK_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Takes messages from msg_rx, processes and pushes replies to reply_tx.
 struct WalAcceptor {
     tli: Arc<Timeline>,
     msg_rx: Receiver<ProposerAcceptorMessage>,
     reply_tx: Sender<AcceptorProposerMessage>,
+    /// How long to wait for more AppendRequests before flushing the WAL.
+    group_commit_window: Duration,
+    /// Disk space the WAL of the tenant may take before accepting more of it
+    /// is held off.
+    tenant_wal_quota: Option<u64>,
+    /// How often to send keepalives to the walproposer, to make sure it
+    /// receives updates even when it writes a steady stream of messages.
+    keepalive_interval: Duration,
 }
 
 impl WalAcceptor {
@@ -227,12 +298,18 @@ impl WalAcceptor {
         msg_rx: Receiver<ProposerAcceptorMessage>,
         reply_tx: Sender<AcceptorProposerMessage>,
         conn_id: ConnectionId,
+        group_commit_window: Duration,
+        tenant_wal_quota: Option<u64>,
+        keepalive_interval: Duration,
     ) -> JoinHandle<anyhow::Result<()>> {
         task::spawn(async move {
             let mut wa = WalAcceptor {
                 tli,
                 msg_rx,
                 reply_tx,
+                group_commit_window,
+                tenant_wal_quota,
+                keepalive_interval,
             };
 
             let span_ttid = wa.tli.ttid; // satisfy borrow checker
@@ -252,25 +329,49 @@ impl WalAcceptor {
         };
 
         // After this timestamp we will stop processing AppendRequests and send a response
-        // to the walproposer. walproposer sends at least one AppendRequest per second,
-        // we will send keepalives by replying to these requests once per second.
+        // to the walproposer. walproposer sends at least one AppendRequest per its reconnect timeout,
+        // we will send keepalives by replying to these requests once per keepalive_interval.
         let mut next_keepalive = Instant::now();
+        // Message received while collecting AppendRequests to flush together,
+        // to be processed next.
+        let mut pending_msg = None;
+        let mut commit_latency = CommitLatencyTracker::default();
 
         loop {
-            let opt_msg = self.msg_rx.recv().await;
-            if opt_msg.is_none() {
-                return Ok(()); // chan closed, streaming terminated
-            }
-            let mut next_msg = opt_msg.unwrap();
+            let mut next_msg = match pending_msg.take() {
+                Some(msg) => msg,
+                None => match self.msg_rx.recv().await {
+                    Some(msg) => msg,
+                    None => return Ok(()), // chan closed, streaming terminated
+                },
+            };
 
             let reply_msg = if matches!(next_msg, ProposerAcceptorMessage::AppendRequest(_)) {
-                // loop through AppendRequest's while it's readily available to
-                // write as many WAL as possible without fsyncing
-                //
-                // Note: this will need to be rewritten if we want to read non-AppendRequest messages here.
-                // Otherwise, we might end up in a situation where we read a message, but don't
-                // process it.
+                if let Some(quota) = self.tenant_wal_quota {
+                    if !self.wait_for_wal_quota(quota).await {
+                        return Ok(()); // chan closed, streaming terminated
+                    }
+                }
+
+                // loop through AppendRequest's while it's readily available, or
+                // arrives within the group commit window, to write as many WAL
+                // as possible without fsyncing
+                let batch_started_at = Instant::now();
+                let flush_deadline = batch_started_at + self.group_commit_window;
+                let mut batch_size = 0;
+                let mut batch_end_lsn = None;
                 while let ProposerAcceptorMessage::AppendRequest(append_request) = next_msg {
+                    commit_latency.on_commit(append_request.h.commit_lsn);
+                    let throttled = self
+                        .tli
+                        .throttle_ingest(append_request.wal_data.len() as u64)
+                        .await;
+                    if !throttled.is_zero() {
+                        INGEST_THROTTLED_SECONDS.inc_by(throttled.as_secs_f64());
+                    }
+                    if append_request.h.end_lsn > append_request.h.begin_lsn {
+                        batch_end_lsn = Some(append_request.h.end_lsn);
+                    }
                     let noflush_msg = ProposerAcceptorMessage::NoFlushAppendRequest(append_request);
 
                     if let Some(reply) = self.tli.process_msg(&noflush_msg).await? {
@@ -278,23 +379,45 @@ impl WalAcceptor {
                             return Ok(()); // chan closed, streaming terminated
                         }
                     }
+                    batch_size += 1;
 
                     // get out of this loop if keepalive time is reached
                     if Instant::now() >= next_keepalive {
                         break;
                     }
 
-                    match self.msg_rx.try_recv() {
-                        Ok(msg) => next_msg = msg,
-                        Err(TryRecvError::Empty) => break,
+                    let msg = match self.msg_rx.try_recv() {
+                        Ok(msg) => msg,
+                        Err(TryRecvError::Empty) => {
+                            let deadline = flush_deadline.min(next_keepalive);
+                            if Instant::now() >= deadline {
+                                break;
+                            }
+                            match timeout_at(deadline, self.msg_rx.recv()).await {
+                                Ok(Some(msg)) => msg,
+                                Ok(None) => return Ok(()), // chan closed, streaming terminated
+                                Err(_) => break,
+                            }
+                        }
                         Err(TryRecvError::Disconnected) => return Ok(()), // chan closed, streaming terminated
+                    };
+                    if !matches!(msg, ProposerAcceptorMessage::AppendRequest(_)) {
+                        pending_msg = Some(msg);
+                        break;
                     }
+                    next_msg = msg;
                 }
+                FLUSH_BATCH_SIZE.observe(batch_size as f64);
 
                 // flush all written WAL to the disk
-                self.tli
+                let reply = self
+                    .tli
                     .process_msg(&ProposerAcceptorMessage::FlushWAL)
-                    .await?
+                    .await?;
+                if let Some(end_lsn) = batch_end_lsn {
+                    commit_latency.on_flush(end_lsn, batch_started_at);
+                }
+                reply
             } else {
                 // process message other than AppendRequest
                 self.tli.process_msg(&next_msg).await?
@@ -305,9 +428,35 @@ impl WalAcceptor {
                     return Ok(()); // chan closed, streaming terminated
                 }
                 // reset keepalive time
-                next_keepalive = Instant::now() + KEEPALIVE_INTERVAL;
+                next_keepalive = Instant::now() + self.keepalive_interval;
+            }
+        }
+    }
+
+    /// Holds off accepting more WAL while the WAL of all timelines of the
+    /// tenant takes more than `quota` bytes on disk, until WAL removal frees
+    /// some. Returns false if the connection is closed meanwhile.
+    async fn wait_for_wal_quota(&self, quota: u64) -> bool {
+        let tenant_id = self.tli.ttid.tenant_id;
+        let mut usage = GlobalTimelines::get_tenant_wal_disk_usage(tenant_id).await;
+        if usage <= quota {
+            return true;
+        }
+        warn!("WAL of the tenant takes {usage} bytes, over the quota of {quota}, holding off accepting more");
+        WAL_QUOTA_WAITS.inc();
+        let started_at = Instant::now();
+        while usage > quota {
+            if self.reply_tx.is_closed() {
+                return false;
             }
+            sleep(WAL_QUOTA_CHECK_INTERVAL).await;
+            usage = GlobalTimelines::get_tenant_wal_disk_usage(tenant_id).await;
         }
+        info!(
+            "WAL of the tenant is back under the quota after {:?}",
+            started_at.elapsed()
+        );
+        true
     }
 }
 
@@ -0,0 +1,209 @@
+//! Peer recovery: a safekeeper which was down for a while pulls the WAL it
+//! misses from the most advanced of its peers, so that the timeline gets the
+//! durability of its quorum back without a compute.
+//!
+//! The recovering safekeeper follows the term of the donor: it adopts the term
+//! history of the donor with ProposerElected, starting at the highest point
+//! where its own WAL matches it, and appends the WAL streamed from the donor
+//! in that term, which the donor checks it is still in while streaming. A
+//! compute electing itself on either of them bumps the term and stops the
+//! recovery; nothing is pulled while a compute streams WAL to us.
+//!
+//! Peers are learned through the broker. The connections to them are not
+//! authenticated, so peer recovery is off when auth is enabled.
+
+use std::collections::HashMap;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use futures::StreamExt;
+use postgres_connection::{parse_host_port, PgConnectionConfig};
+use postgres_protocol::message::backend::ReplicationMessage;
+use safekeeper_api::models::TimelineStatus;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_postgres::replication::ReplicationStream;
+use tracing::*;
+use utils::{id::TenantTimelineId, lsn::Lsn};
+
+use crate::metrics::PEER_RECOVERED_WAL_BYTES;
+use crate::safekeeper::{
+    AcceptorProposerMessage, AppendRequest, AppendRequestHeader, ProposerAcceptorMessage,
+    ProposerElected, TermHistory, TermSwitchEntry,
+};
+use crate::timeline::{PeerInfo, Timeline};
+use crate::{GlobalTimelines, SafeKeeperConf};
+
+/// Application name of the replication connections of peer recovery. Like
+/// walproposer recovery, they get all the WAL of the donor, whether committed
+/// or not.
+pub const RECOVERY_APPNAME: &str = "safekeeper_recovery";
+
+pub async fn task_main(conf: SafeKeeperConf) -> anyhow::Result<()> {
+    let recovery_interval = Duration::from_millis(5000);
+    let mut recoveries: HashMap<TenantTimelineId, JoinHandle<()>> = HashMap::new();
+    loop {
+        recoveries.retain(|_, handle| !handle.is_finished());
+        for tli in GlobalTimelines::get_all() {
+            if recoveries.contains_key(&tli.ttid) {
+                continue;
+            }
+            let Some(donor) = tli.get_recovery_donor(&conf).await else {
+                continue;
+            };
+            let ttid = tli.ttid;
+            let span = info_span!("recovery", tenant = %ttid.tenant_id, timeline = %ttid.timeline_id, donor = %donor.sk_id);
+            let handle = tokio::spawn(
+                async move {
+                    info!(
+                        "recovering WAL from {} at {}, term {}",
+                        donor.sk_id, donor.flush_lsn, donor.last_log_term
+                    );
+                    match recover(&tli, &donor).await {
+                        Ok(end_lsn) => info!("recovered WAL up to {end_lsn}"),
+                        Err(e) => warn!("failed to recover WAL: {e:#}"),
+                    }
+                }
+                .instrument(span),
+            );
+            recoveries.insert(ttid, handle);
+        }
+        sleep(recovery_interval).await;
+    }
+}
+
+/// Pulls the WAL we miss from `donor`, returning our new end of WAL.
+async fn recover(tli: &Arc<Timeline>, donor: &PeerInfo) -> anyhow::Result<Lsn> {
+    let ttid = tli.ttid;
+
+    // The term history of the donor tells where our WAL diverges from its.
+    let status: TimelineStatus = reqwest::Client::new()
+        .get(format!(
+            "http://{}/v1/tenant/{}/timeline/{}",
+            donor.http_connstr, ttid.tenant_id, ttid.timeline_id
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("get the timeline status of the donor")?;
+    let donor_term = status.acceptor_state.term;
+    // Voting for a term whose proposer isn't elected yet would take our vote
+    // away from it.
+    if status.acceptor_state.epoch != donor_term {
+        bail!("donor is in term {donor_term}, but has no WAL of it yet, election is in progress");
+    }
+    let donor_th = TermHistory(
+        status
+            .acceptor_state
+            .term_history
+            .iter()
+            .map(|e| TermSwitchEntry {
+                term: e.term,
+                lsn: e.lsn,
+            })
+            .collect(),
+    );
+
+    let (inmem, state) = tli.get_state().await;
+    let flush_lsn = tli.get_flush_lsn().await;
+    if state.acceptor_state.term > donor_term {
+        bail!(
+            "our term {} is higher than term {donor_term} of the donor",
+            state.acceptor_state.term
+        );
+    }
+    let common_point = TermHistory::find_highest_common_point(
+        &donor_th,
+        &state.acceptor_state.term_history.up_to(flush_lsn),
+        flush_lsn,
+    )
+    .context("no WAL history in common with the donor")?;
+    if common_point.lsn < inmem.commit_lsn {
+        bail!(
+            "WAL of the donor diverges from ours at {}, below our commit_lsn {}",
+            common_point.lsn,
+            inmem.commit_lsn
+        );
+    }
+    if common_point.lsn >= status.flush_lsn {
+        return Ok(flush_lsn);
+    }
+    info!(
+        "WAL matches the donor's up to {}, pulling it up to {}",
+        common_point.lsn, status.flush_lsn
+    );
+
+    let epoch_start_lsn = donor_th.0.last().map_or(Lsn(0), |e| e.lsn);
+    tli.process_msg(&ProposerAcceptorMessage::Elected(ProposerElected {
+        term: donor_term,
+        start_streaming_at: common_point.lsn,
+        term_history: donor_th,
+        timeline_start_lsn: status.timeline_start_lsn,
+    }))
+    .await?;
+
+    let (host, port) = parse_host_port(&donor.pg_connstr).context("parse donor connstr")?;
+    let mut config = PgConnectionConfig::new_host_port(host, port.unwrap_or(5432))
+        .extend_options([
+            "-c".to_owned(),
+            format!("timeline_id={}", ttid.timeline_id),
+            format!("tenant_id={}", ttid.tenant_id),
+        ])
+        .to_tokio_postgres_config();
+    config.application_name(RECOVERY_APPNAME);
+    config.replication_mode(tokio_postgres::config::ReplicationMode::Physical);
+    let (client, connection) = config.connect(tokio_postgres::NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("connection to the donor closed: {e}");
+        }
+    });
+
+    // The donor makes sure it is still in the term while streaming.
+    let query = format!(
+        "START_REPLICATION PHYSICAL {} (term='{donor_term}')",
+        common_point.lsn
+    );
+    let copy_stream = client.copy_both_simple(&query).await?;
+    let mut stream = pin!(ReplicationStream::new(copy_stream));
+
+    let mut end_lsn = common_point.lsn;
+    while end_lsn < status.flush_lsn {
+        let msg = stream
+            .next()
+            .await
+            .context("donor ended streaming early")??;
+        let ReplicationMessage::XLogData(xlog_data) = msg else {
+            continue;
+        };
+        let begin_lsn = Lsn(xlog_data.wal_start());
+        let wal_data = xlog_data.data().clone();
+        let msg_end_lsn = begin_lsn + wal_data.len() as u64;
+        let append_request = ProposerAcceptorMessage::AppendRequest(AppendRequest {
+            h: AppendRequestHeader {
+                term: donor_term,
+                epoch_start_lsn,
+                begin_lsn,
+                end_lsn: msg_end_lsn,
+                commit_lsn: status.commit_lsn,
+                truncate_lsn: status.peer_horizon_lsn,
+                proposer_uuid: [0; 16],
+            },
+            wal_data,
+        });
+        if let Some(AcceptorProposerMessage::AppendResponse(resp)) =
+            tli.process_msg(&append_request).await?
+        {
+            if resp.term != donor_term {
+                bail!("term switched to {} during recovery", resp.term);
+            }
+        }
+        PEER_RECOVERED_WAL_BYTES.inc_by(msg_end_lsn.0 - begin_lsn.0);
+        end_lsn = msg_end_lsn;
+    }
+    Ok(end_lsn)
+}
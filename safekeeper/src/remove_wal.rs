@@ -12,7 +12,10 @@ pub async fn task_main(conf: SafeKeeperConf) -> anyhow::Result<()> {
     loop {
         let tlis = GlobalTimelines::get_all();
         for tli in &tlis {
-            if !tli.is_active().await {
+            // Timelines become inactive once the pageserver has caught up with
+            // them, which is when most of their WAL can go, so the horizon is
+            // persisted and WAL removed for all of them.
+            if tli.is_cancelled() {
                 continue;
             }
             let ttid = tli.ttid;
@@ -24,7 +27,7 @@ pub async fn task_main(conf: SafeKeeperConf) -> anyhow::Result<()> {
                 warn!("failed to persist control file: {e}");
             }
             if let Err(e) = tli
-                .remove_old_wal(conf.wal_backup_enabled)
+                .remove_old_wal(conf.wal_backup_enabled, conf.wal_retention_margin_bytes)
                 .instrument(info_span!("", tenant = %ttid.tenant_id, timeline = %ttid.timeline_id))
                 .await
             {
@@ -8,6 +8,7 @@ use postgres_ffi::{TimeLineID, XLogSegNo, MAX_SEND_SIZE};
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::Read;
 use std::time::Duration;
@@ -28,7 +29,7 @@ use utils::{
 };
 
 pub const SK_MAGIC: u32 = 0xcafeceefu32;
-pub const SK_FORMAT_VERSION: u32 = 7;
+pub const SK_FORMAT_VERSION: u32 = 8;
 const SK_PROTOCOL_VERSION: u32 = 2;
 pub const UNKNOWN_SERVER_VERSION: u32 = 0;
 
@@ -80,6 +81,33 @@ impl TermHistory {
         }
         TermHistory(res)
     }
+
+    /// Find the highest point where the WAL of a safekeeper, ending at
+    /// `sk_wal_end` with `sk_th` term history, matches the WAL described by
+    /// `prop_th`, i.e. where streaming the latter must start. None if the
+    /// histories have no term in common.
+    pub fn find_highest_common_point(
+        prop_th: &TermHistory,
+        sk_th: &TermHistory,
+        sk_wal_end: Lsn,
+    ) -> Option<TermSwitchEntry> {
+        let (prop_th, sk_th) = (&prop_th.0, &sk_th.0);
+        // The last entry which is the same in both histories.
+        let last_common_idx = prop_th
+            .iter()
+            .zip(sk_th.iter())
+            .take_while(|(p, s)| p.term == s.term && p.lsn == s.lsn)
+            .count()
+            .checked_sub(1)?;
+        // The common term ends where the next one starts, or at the end of WAL
+        // of the safekeeper if it is the last one there.
+        let prop_common_term_end = prop_th.get(last_common_idx + 1).map_or(Lsn::MAX, |e| e.lsn);
+        let sk_common_term_end = sk_th.get(last_common_idx + 1).map_or(sk_wal_end, |e| e.lsn);
+        Some(TermSwitchEntry {
+            term: prop_th[last_common_idx].term,
+            lsn: min(prop_common_term_end, sk_common_term_end),
+        })
+    }
 }
 
 /// Display only latest entries for Debug.
@@ -205,6 +233,9 @@ pub struct SafeKeeperState {
     // obviously can be stale. (Currently not saved at all, but let's provision
     // place to have less file version upgrades).
     pub peers: PersistedPeers,
+    /// Physical replication slots of standbys, with the LSN from which their
+    /// standby needs WAL. They are kept until explicitly dropped.
+    pub replication_slots: BTreeMap<String, Lsn>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,6 +278,7 @@ impl SafeKeeperState {
                     .map(|p| (*p, PersistedPeerInfo::new()))
                     .collect(),
             ),
+            replication_slots: BTreeMap::new(),
         }
     }
 
@@ -843,11 +875,22 @@ where
         self.state.persist(&state).await
     }
 
+    /// Persist the given replication slots, replacing the previous ones.
+    pub async fn persist_replication_slots(
+        &mut self,
+        replication_slots: BTreeMap<String, Lsn>,
+    ) -> Result<()> {
+        let mut state = self.state.clone();
+        state.replication_slots = replication_slots;
+        self.persist_control_file(state).await
+    }
+
     /// Persist control file if there is something to save and enough time
     /// passed after the last save.
     pub async fn maybe_persist_control_file(
         &mut self,
         inmem_remote_consistent_lsn: Lsn,
+        inmem_replication_slots: BTreeMap<String, Lsn>,
     ) -> Result<()> {
         const CF_SAVE_INTERVAL: Duration = Duration::from_secs(300);
         if self.state.last_persist_at().elapsed() < CF_SAVE_INTERVAL {
@@ -856,10 +899,12 @@ where
         let need_persist = self.inmem.commit_lsn > self.state.commit_lsn
             || self.inmem.backup_lsn > self.state.backup_lsn
             || self.inmem.peer_horizon_lsn > self.state.peer_horizon_lsn
-            || inmem_remote_consistent_lsn > self.state.remote_consistent_lsn;
+            || inmem_remote_consistent_lsn > self.state.remote_consistent_lsn
+            || inmem_replication_slots != self.state.replication_slots;
         if need_persist {
             let mut state = self.state.clone();
             state.remote_consistent_lsn = inmem_remote_consistent_lsn;
+            state.replication_slots = inmem_replication_slots;
             self.persist_control_file(state).await?;
             trace!("saved control file: {CF_SAVE_INTERVAL:?} passed");
         }
@@ -988,12 +1033,13 @@ where
         Ok(())
     }
 
-    /// Get oldest segno we still need to keep. We hold WAL till it is consumed
+    /// Get oldest LSN we still need to keep. We hold WAL till it is consumed
     /// by all of 1) pageserver (remote_consistent_lsn) 2) peers 3) s3
-    /// offloading.
+    /// offloading, and keep `retention_margin` bytes of WAL before that on
+    /// top of it.
     /// While it is safe to use inmem values for determining horizon,
     /// we use persistent to make possible normal states less surprising.
-    pub fn get_horizon_segno(&self, wal_backup_enabled: bool) -> XLogSegNo {
+    pub fn get_horizon_lsn(&self, wal_backup_enabled: bool, retention_margin: u64) -> Lsn {
         let mut horizon_lsn = min(
             self.state.remote_consistent_lsn,
             self.state.peer_horizon_lsn,
@@ -1001,7 +1047,13 @@ where
         if wal_backup_enabled {
             horizon_lsn = min(horizon_lsn, self.state.backup_lsn);
         }
-        horizon_lsn.segment_number(self.state.server.wal_seg_size as usize)
+        horizon_lsn.checked_sub(retention_margin).unwrap_or(Lsn(0))
+    }
+
+    /// Get oldest segno we still need to keep, see get_horizon_lsn.
+    pub fn get_horizon_segno(&self, wal_backup_enabled: bool, retention_margin: u64) -> XLogSegNo {
+        self.get_horizon_lsn(wal_backup_enabled, retention_margin)
+            .segment_number(self.state.server.wal_seg_size as usize)
     }
 }
 
@@ -1169,4 +1221,60 @@ mod tests {
         sk.wal_store.truncate_wal(Lsn(3)).await.unwrap(); // imitate the complete record at 3 %)
         assert_eq!(sk.get_epoch(), 1);
     }
+
+    #[test]
+    fn test_horizon_retention_margin() {
+        let seg_size = WAL_SEGMENT_SIZE as u64;
+        let mut state = test_sk_state();
+        state.remote_consistent_lsn = Lsn(5 * seg_size + 100);
+        state.peer_horizon_lsn = Lsn(6 * seg_size);
+        state.backup_lsn = Lsn(4 * seg_size);
+        let storage = InMemoryState {
+            persisted_state: state,
+        };
+        let wal_store = DummyWalStore { lsn: Lsn(0) };
+        let sk = SafeKeeper::new(storage, wal_store, NodeId(0)).unwrap();
+
+        assert_eq!(sk.get_horizon_lsn(true, 0), Lsn(4 * seg_size));
+        assert_eq!(sk.get_horizon_lsn(false, 0), Lsn(5 * seg_size + 100));
+        assert_eq!(sk.get_horizon_segno(false, 0), 5);
+        // The margin keeps the segment with the WAL just before the horizon.
+        assert_eq!(sk.get_horizon_segno(false, 200), 4);
+        assert_eq!(sk.get_horizon_lsn(false, 10 * seg_size), Lsn(0));
+    }
+
+    #[test]
+    fn test_find_highest_common_point() {
+        fn th(entries: &[(Term, u64)]) -> TermHistory {
+            TermHistory(
+                entries
+                    .iter()
+                    .map(|&(term, lsn)| TermSwitchEntry {
+                        term,
+                        lsn: Lsn(lsn),
+                    })
+                    .collect(),
+            )
+        }
+        let common_point = |prop: &[(Term, u64)], sk: &[(Term, u64)], sk_wal_end: u64| {
+            TermHistory::find_highest_common_point(&th(prop), &th(sk), Lsn(sk_wal_end))
+                .map(|e| (e.term, e.lsn.0))
+        };
+
+        // The safekeeper lags in the same term: stream from its end of WAL.
+        assert_eq!(common_point(&[(1, 10)], &[(1, 10)], 50), Some((1, 50)));
+        // The proposer moved on to the next term.
+        assert_eq!(
+            common_point(&[(1, 10), (3, 40)], &[(1, 10)], 50),
+            Some((1, 40))
+        );
+        // The safekeeper has WAL of a term the proposer doesn't know about.
+        assert_eq!(
+            common_point(&[(1, 10), (3, 40)], &[(1, 10), (2, 30)], 50),
+            Some((1, 30))
+        );
+        // No term in common.
+        assert_eq!(common_point(&[(2, 10)], &[(1, 10)], 50), None);
+        assert_eq!(common_point(&[(1, 10)], &[], 0), None);
+    }
 }
@@ -2,13 +2,15 @@
 //! with the "START_REPLICATION" message, and registry of walsenders.
 
 use crate::handler::SafekeeperPostgresHandler;
+use crate::metrics::COMPRESSED_WAL_BYTES;
 use crate::safekeeper::Term;
 use crate::timeline::Timeline;
+use crate::wal_filter::WalStreamFilter;
 use crate::wal_service::ConnectionId;
 use crate::wal_storage::WalReader;
 use crate::GlobalTimelines;
 use anyhow::Context as AnyhowContext;
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use parking_lot::Mutex;
 use postgres_backend::PostgresBackend;
 use postgres_backend::{CopyStreamHandlerEnd, PostgresBackendReader, QueryError};
@@ -18,13 +20,14 @@ use pq_proto::{BeMessage, WalSndKeepAlive, XLogDataBody};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use tokio::io::{AsyncRead, AsyncWrite};
-use utils::id::TenantTimelineId;
+use utils::id::{RegionId, TenantTimelineId};
 use utils::lsn::AtomicLsn;
 use utils::pageserver_feedback::PageserverFeedback;
 
 use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use std::str;
+use std::str::{self, FromStr};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::watch::Receiver;
@@ -58,6 +61,25 @@ impl HotStandbyFeedback {
             catalog_xmin: 0,
         }
     }
+
+    /// Parses the message body sent by the walreceiver of a standby, where
+    /// each xmin comes as a 32-bit xid followed by its epoch.
+    fn parse(mut buf: &[u8]) -> anyhow::Result<HotStandbyFeedback> {
+        anyhow::ensure!(buf.len() >= 24, "HotStandbyFeedback is too short");
+        let ts = buf.get_i64();
+        let mut get_full_xid = || {
+            let xid = buf.get_u32() as u64;
+            let epoch = buf.get_u32() as u64;
+            (epoch << 32) | xid
+        };
+        let xmin = get_full_xid();
+        let catalog_xmin = get_full_xid();
+        Ok(HotStandbyFeedback {
+            ts,
+            xmin,
+            catalog_xmin,
+        })
+    }
 }
 
 /// Standby status update
@@ -82,6 +104,30 @@ impl StandbyReply {
     }
 }
 
+/// zstd level of the WAL compression: a low one, to keep up with the WAL
+/// written by the compute.
+const ZSTD_WAL_COMPRESSION_LEVEL: i32 = 1;
+
+/// Compression of the WAL streamed to the receiver, requested with the
+/// `compression` option of START_REPLICATION. Each XLogData message then
+/// carries its WAL as a separate frame, so that the receiver can decompress it
+/// right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalCompression {
+    Zstd,
+}
+
+impl FromStr for WalCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zstd" => Ok(WalCompression::Zstd),
+            _ => anyhow::bail!("unsupported WAL compression {s}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct StandbyFeedback {
     reply: StandbyReply,
@@ -97,28 +143,43 @@ pub struct WalSenders {
 }
 
 impl WalSenders {
-    pub fn new(remote_consistent_lsn: Lsn) -> Arc<WalSenders> {
+    pub fn new(
+        remote_consistent_lsn: Lsn,
+        replication_slots: BTreeMap<String, Lsn>,
+    ) -> Arc<WalSenders> {
         Arc::new(WalSenders {
             remote_consistent_lsn: AtomicLsn::from(remote_consistent_lsn),
-            mutex: Mutex::new(WalSendersShared::new()),
+            mutex: Mutex::new(WalSendersShared::new(replication_slots)),
         })
     }
 
     /// Register new walsender. Returned guard provides access to the slot and
-    /// automatically deregisters in Drop.
+    /// automatically deregisters in Drop. Fails if the walsender streams
+    /// through a replication slot that doesn't exist or is already in use.
     fn register(
         self: &Arc<WalSenders>,
         ttid: TenantTimelineId,
         addr: SocketAddr,
         conn_id: ConnectionId,
         appname: Option<String>,
-    ) -> WalSenderGuard {
-        let slots = &mut self.mutex.lock().slots;
+        slot_name: Option<String>,
+    ) -> anyhow::Result<WalSenderGuard> {
+        let mut shared = self.mutex.lock();
+        if let Some(slot_name) = &slot_name {
+            if !shared.replication_slots.contains_key(slot_name) {
+                anyhow::bail!("replication slot \"{slot_name}\" does not exist");
+            }
+            if shared.is_replication_slot_active(slot_name) {
+                anyhow::bail!("replication slot \"{slot_name}\" is active");
+            }
+        }
+        let slots = &mut shared.slots;
         let walsender_state = WalSenderState {
             ttid,
             addr,
             conn_id,
             appname,
+            slot_name,
             feedback: ReplicationFeedback::Pageserver(PageserverFeedback::empty()),
         };
         // find empty slot or create new one
@@ -130,10 +191,10 @@ impl WalSenders {
             slots.push(Some(walsender_state));
             pos
         };
-        WalSenderGuard {
+        Ok(WalSenderGuard {
             id: pos,
             walsenders: self.clone(),
-        }
+        })
     }
 
     /// Get state of all walsenders.
@@ -163,7 +224,15 @@ impl WalSenders {
     /// Record standby reply.
     fn record_standby_reply(self: &Arc<WalSenders>, id: WalSenderId, reply: &StandbyReply) {
         let mut shared = self.mutex.lock();
-        let slot = shared.get_slot_mut(id);
+        let shared = &mut *shared;
+        let slot = shared.slots[id].as_mut().expect("walsender doesn't exist");
+        if let Some(restart_lsn) = slot
+            .slot_name
+            .as_ref()
+            .and_then(|slot_name| shared.replication_slots.get_mut(slot_name))
+        {
+            *restart_lsn = max(*restart_lsn, reply.flush_lsn);
+        }
         match &mut slot.feedback {
             ReplicationFeedback::Standby(sf) => sf.reply = *reply,
             ReplicationFeedback::Pageserver(_) => {
@@ -202,6 +271,39 @@ impl WalSenders {
         }
     }
 
+    /// Get the oldest WAL position needed by the standbys of the replication
+    /// slots, None if there are none.
+    pub fn get_slots_restart_lsn(self: &Arc<WalSenders>) -> Option<Lsn> {
+        self.mutex.lock().replication_slots.values().min().copied()
+    }
+
+    /// Get the replication slots with the WAL position up to which their
+    /// standby has flushed WAL, which is ahead of the persisted one.
+    pub fn get_replication_slots(self: &Arc<WalSenders>) -> BTreeMap<String, Lsn> {
+        self.mutex.lock().replication_slots.clone()
+    }
+
+    /// Add a replication slot created with the given restart LSN.
+    pub fn add_replication_slot(self: &Arc<WalSenders>, slot_name: String, restart_lsn: Lsn) {
+        self.mutex
+            .lock()
+            .replication_slots
+            .insert(slot_name, restart_lsn);
+    }
+
+    /// Remove a replication slot, failing if a standby streams through it.
+    /// Returns its restart LSN.
+    pub fn remove_replication_slot(self: &Arc<WalSenders>, slot_name: &str) -> anyhow::Result<Lsn> {
+        let mut shared = self.mutex.lock();
+        if shared.is_replication_slot_active(slot_name) {
+            anyhow::bail!("replication slot \"{slot_name}\" is active");
+        }
+        shared
+            .replication_slots
+            .remove(slot_name)
+            .with_context(|| format!("replication slot \"{slot_name}\" does not exist"))
+    }
+
     /// Get remote_consistent_lsn maximized across all walsenders and peers.
     pub fn get_remote_consistent_lsn(self: &Arc<WalSenders>) -> Lsn {
         self.remote_consistent_lsn.load()
@@ -228,17 +330,29 @@ struct WalSendersShared {
     // aggregated over all walsenders value
     agg_ps_feedback: PageserverFeedback,
     slots: Vec<Option<WalSenderState>>,
+    // replication slots of standbys, with the WAL position up to which their
+    // standby has flushed WAL
+    replication_slots: BTreeMap<String, Lsn>,
 }
 
 impl WalSendersShared {
-    fn new() -> Self {
+    fn new(replication_slots: BTreeMap<String, Lsn>) -> Self {
         WalSendersShared {
             agg_hs_feedback: HotStandbyFeedback::empty(),
             agg_ps_feedback: PageserverFeedback::empty(),
             slots: Vec::new(),
+            replication_slots,
         }
     }
 
+    /// Whether a walsender streams through the given replication slot.
+    fn is_replication_slot_active(&self, slot_name: &str) -> bool {
+        self.slots
+            .iter()
+            .flatten()
+            .any(|ws| ws.slot_name.as_deref() == Some(slot_name))
+    }
+
     /// Get content of provided id slot, it must exist.
     fn get_slot(&self, id: WalSenderId) -> &WalSenderState {
         self.slots[id].as_ref().expect("walsender doesn't exist")
@@ -283,7 +397,8 @@ impl WalSendersShared {
     /// Update aggregated pageserver feedback. LSNs (last_received,
     /// disk_consistent, remote_consistent) and reply timestamp are just
     /// maximized; timeline_size if taken from feedback with highest
-    /// last_received lsn. This is generally reasonable, but we might want to
+    /// last_received lsn; the compute is asked to slow down if any pageserver
+    /// asks to. This is generally reasonable, but we might want to
     /// implement other policies once multiple pageservers start to be actively
     /// used.
     fn update_ps_feedback(&mut self) {
@@ -304,6 +419,7 @@ impl WalSendersShared {
                         acc.remote_consistent_lsn =
                             max(feedback.remote_consistent_lsn, acc.remote_consistent_lsn);
                         acc.replytime = max(feedback.replytime, acc.replytime);
+                        acc.slow_down |= feedback.slow_down;
                         acc
                     }
                     ReplicationFeedback::Standby(_) => acc,
@@ -322,6 +438,9 @@ pub struct WalSenderState {
     conn_id: ConnectionId,
     // postgres application_name
     appname: Option<String>,
+    // replication slot the standby streams through, keeping the WAL it
+    // hasn't flushed yet from being removed
+    slot_name: Option<String>,
     feedback: ReplicationFeedback,
 }
 
@@ -360,10 +479,13 @@ impl SafekeeperPostgresHandler {
         &mut self,
         pgb: &mut PostgresBackend<IO>,
         start_pos: Lsn,
+        slot_name: Option<String>,
         term: Option<Term>,
+        compression: Option<WalCompression>,
+        region: Option<RegionId>,
     ) -> Result<(), QueryError> {
         if let Err(end) = self
-            .handle_start_replication_guts(pgb, start_pos, term)
+            .handle_start_replication_guts(pgb, start_pos, slot_name, term, compression, region)
             .await
         {
             // Log the result and probably send it to the client, closing the stream.
@@ -376,19 +498,27 @@ impl SafekeeperPostgresHandler {
         &mut self,
         pgb: &mut PostgresBackend<IO>,
         start_pos: Lsn,
+        slot_name: Option<String>,
         term: Option<Term>,
+        compression: Option<WalCompression>,
+        region: Option<RegionId>,
     ) -> Result<(), CopyStreamHandlerEnd> {
         let appname = self.appname.clone();
         let tli =
             GlobalTimelines::get(self.ttid).map_err(|e| CopyStreamHandlerEnd::Other(e.into()))?;
 
         // Use a guard object to remove our entry from the timeline when we are done.
-        let ws_guard = Arc::new(tli.get_walsenders().register(
-            self.ttid,
-            *pgb.get_peer_addr(),
-            self.conn_id,
-            self.appname.clone(),
-        ));
+        let ws_guard = Arc::new(
+            tli.get_walsenders()
+                .register(
+                    self.ttid,
+                    *pgb.get_peer_addr(),
+                    self.conn_id,
+                    self.appname.clone(),
+                    slot_name,
+                )
+                .map_err(CopyStreamHandlerEnd::Other)?,
+        );
 
         let commit_lsn_watch_rx = tli.get_commit_lsn_watch_rx();
 
@@ -419,8 +549,8 @@ impl SafekeeperPostgresHandler {
         }
 
         info!(
-            "starting streaming from {:?} till {:?}, available WAL ends at {}",
-            start_pos, stop_pos, end_pos
+            "starting streaming from {:?} till {:?}, available WAL ends at {}, compression {:?}",
+            start_pos, stop_pos, end_pos, compression
         );
 
         // switch to copy
@@ -435,6 +565,20 @@ impl SafekeeperPostgresHandler {
             self.conf.wal_backup_enabled,
         )?;
 
+        // Regions without a filter get all the WAL.
+        let wal_filter = match region {
+            Some(region) => self.conf.region_wal_filters.get(&region).map(|filter| {
+                info!("filtering WAL for region {region}");
+                WalStreamFilter::new(
+                    Arc::clone(filter),
+                    start_pos,
+                    persisted_state.server.pg_version / 10000,
+                    persisted_state.server.wal_seg_size as usize,
+                )
+            }),
+            None => None,
+        };
+
         // Split to concurrently receive and send data; replies are generally
         // not synchronized with sends, so this avoids deadlocks.
         let reader = pgb.split().context("START_REPLICATION split")?;
@@ -447,6 +591,8 @@ impl SafekeeperPostgresHandler {
             end_pos,
             stop_pos,
             term,
+            compression,
+            wal_filter,
             commit_lsn_watch_rx,
             ws_guard: ws_guard.clone(),
             wal_reader,
@@ -487,6 +633,9 @@ struct WalSender<'a, IO> {
     /// in. Streaming is stopped if local term changes to a different (higher)
     /// value.
     term: Option<Term>,
+    compression: Option<WalCompression>,
+    /// Filter of the WAL streamed to the pageserver of another region.
+    wal_filter: Option<WalStreamFilter>,
     commit_lsn_watch_rx: Receiver<Lsn>,
     ws_guard: Arc<WalSenderGuard>,
     wal_reader: WalReader,
@@ -542,14 +691,39 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> WalSender<'_, IO> {
                 send_size = self.wal_reader.read(send_buf).await?
             };
             let send_buf = &send_buf[..send_size];
+            // The filter holds back incomplete records, so what is streamed
+            // can start before and end before what was just read.
+            let filtered;
+            let (wal_start, wal) = match self.wal_filter.as_mut() {
+                Some(wal_filter) => {
+                    filtered = wal_filter.filter(send_buf)?;
+                    (filtered.0, &filtered.1[..])
+                }
+                None => (self.start_pos, send_buf),
+            };
+            let compressed;
+            let data = match self.compression {
+                Some(WalCompression::Zstd) => {
+                    compressed = zstd::bulk::compress(wal, ZSTD_WAL_COMPRESSION_LEVEL)
+                        .context("compress WAL")?;
+                    COMPRESSED_WAL_BYTES
+                        .with_label_values(&["uncompressed"])
+                        .inc_by(wal.len() as u64);
+                    COMPRESSED_WAL_BYTES
+                        .with_label_values(&["compressed"])
+                        .inc_by(compressed.len() as u64);
+                    &compressed[..]
+                }
+                None => wal,
+            };
 
             // and send it
             self.pgb
                 .write_message(&BeMessage::XLogData(XLogDataBody {
-                    wal_start: self.start_pos.0,
+                    wal_start: wal_start.0,
                     wal_end: self.end_pos.0,
                     timestamp: get_current_timestamp(),
-                    data: send_buf,
+                    data,
                 }))
                 .await?;
 
@@ -628,8 +802,8 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> ReplyReader<IO> {
         match msg.first().cloned() {
             Some(HOT_STANDBY_FEEDBACK_TAG_BYTE) => {
                 // Note: deserializing is on m[1..] because we skip the tag byte.
-                let hs_feedback = HotStandbyFeedback::des(&msg[1..])
-                    .context("failed to deserialize HotStandbyFeedback")?;
+                let hs_feedback = HotStandbyFeedback::parse(&msg[1..])
+                    .context("failed to parse HotStandbyFeedback")?;
                 self.ws_guard
                     .walsenders
                     .record_hs_feedback(self.ws_guard.id, &hs_feedback);
@@ -643,6 +817,9 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> ReplyReader<IO> {
             }
             Some(NEON_STATUS_UPDATE_TAG_BYTE) => {
                 // pageserver sends this.
+                fail::fail_point!("sk-pageserver-feedback", |_| {
+                    anyhow::bail!("failpoint sk-pageserver-feedback")
+                });
                 // Note: deserializing is on m[9..] because we skip the tag byte and len bytes.
                 let buf = Bytes::copy_from_slice(&msg[9..]);
                 let ps_feedback = PageserverFeedback::parse(buf);
@@ -717,6 +894,7 @@ mod tests {
             addr: mock_addr(),
             conn_id: 1,
             appname: None,
+            slot_name: None,
             feedback,
         };
         wss.slots.push(Some(walsender_state))
@@ -738,7 +916,7 @@ mod tests {
     // test that hs aggregation works as expected
     #[test]
     fn test_hs_feedback_no_valid() {
-        let mut wss = WalSendersShared::new();
+        let mut wss = WalSendersShared::new(BTreeMap::new());
         push_feedback(&mut wss, hs_feedback(1, INVALID_FULL_TRANSACTION_ID));
         wss.update_hs_feedback();
         assert_eq!(wss.agg_hs_feedback.xmin, INVALID_FULL_TRANSACTION_ID);
@@ -746,7 +924,7 @@ mod tests {
 
     #[test]
     fn test_hs_feedback() {
-        let mut wss = WalSendersShared::new();
+        let mut wss = WalSendersShared::new(BTreeMap::new());
         push_feedback(&mut wss, hs_feedback(1, INVALID_FULL_TRANSACTION_ID));
         push_feedback(&mut wss, hs_feedback(1, 42));
         push_feedback(&mut wss, hs_feedback(1, 64));
@@ -754,6 +932,58 @@ mod tests {
         assert_eq!(wss.agg_hs_feedback.xmin, 42);
     }
 
+    #[test]
+    fn test_hs_feedback_parse() {
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&42i64.to_be_bytes());
+        // xmin 100 of epoch 2, catalog_xmin 7 of epoch 1
+        for v in [100u32, 2, 7, 1] {
+            msg.extend_from_slice(&v.to_be_bytes());
+        }
+        let hs_feedback = HotStandbyFeedback::parse(&msg).unwrap();
+        assert_eq!(hs_feedback.ts, 42);
+        assert_eq!(hs_feedback.xmin, (2 << 32) | 100);
+        assert_eq!(hs_feedback.catalog_xmin, (1 << 32) | 7);
+        assert!(HotStandbyFeedback::parse(&msg[..20]).is_err());
+    }
+
+    #[test]
+    fn test_replication_slots() {
+        let walsenders = WalSenders::new(
+            Lsn::INVALID,
+            BTreeMap::from([("standby".to_owned(), Lsn(0x100))]),
+        );
+        let register = |slot_name: &str| {
+            walsenders.register(
+                TenantTimelineId::empty(),
+                mock_addr(),
+                1,
+                None,
+                Some(slot_name.to_owned()),
+            )
+        };
+        assert!(register("unknown").is_err());
+
+        let ws_guard = register("standby").unwrap();
+        assert!(register("standby").is_err());
+        assert!(walsenders.remove_replication_slot("standby").is_err());
+
+        let mut reply = StandbyReply::empty();
+        reply.flush_lsn = Lsn(0x200);
+        walsenders.record_standby_reply(ws_guard.id, &reply);
+        // The slot never goes back.
+        reply.flush_lsn = Lsn(0x180);
+        walsenders.record_standby_reply(ws_guard.id, &reply);
+        assert_eq!(walsenders.get_slots_restart_lsn(), Some(Lsn(0x200)));
+
+        drop(ws_guard);
+        assert_eq!(
+            walsenders.remove_replication_slot("standby").unwrap(),
+            Lsn(0x200)
+        );
+        assert_eq!(walsenders.get_slots_restart_lsn(), None);
+    }
+
     // form pageserver feedback with given last_record_lsn / tli size and the
     // rest set to dummy values.
     fn ps_feedback(current_timeline_size: u64, last_received_lsn: Lsn) -> ReplicationFeedback {
@@ -763,17 +993,33 @@ mod tests {
             disk_consistent_lsn: Lsn::INVALID,
             remote_consistent_lsn: Lsn::INVALID,
             replytime: *PG_EPOCH,
+            slow_down: false,
         })
     }
 
     // test that ps aggregation works as expected
     #[test]
     fn test_ps_feedback() {
-        let mut wss = WalSendersShared::new();
+        let mut wss = WalSendersShared::new(BTreeMap::new());
         push_feedback(&mut wss, ps_feedback(8, Lsn(42)));
         push_feedback(&mut wss, ps_feedback(4, Lsn(84)));
         wss.update_ps_feedback();
         assert_eq!(wss.agg_ps_feedback.current_timeline_size, 4);
         assert_eq!(wss.agg_ps_feedback.last_received_lsn, Lsn(84));
+        assert!(!wss.agg_ps_feedback.slow_down);
+    }
+
+    // test that a pageserver falling behind slows down the compute
+    #[test]
+    fn test_ps_feedback_slow_down() {
+        let mut wss = WalSendersShared::new(BTreeMap::new());
+        push_feedback(&mut wss, ps_feedback(8, Lsn(42)));
+        let ReplicationFeedback::Pageserver(mut slow) = ps_feedback(4, Lsn(84)) else {
+            unreachable!()
+        };
+        slow.slow_down = true;
+        push_feedback(&mut wss, ReplicationFeedback::Pageserver(slow));
+        wss.update_ps_feedback();
+        assert!(wss.agg_ps_feedback.slow_down);
     }
 }
@@ -0,0 +1,148 @@
+//! Snapshot of a timeline into a tar archive of its control file and WAL, and
+//! restore of such an archive, behind the timeline snapshot API.
+//!
+//! This seeds a safekeeper newly added to the timeline with the WAL its peers
+//! still keep, instead of having it recover the WAL from the beginning. The
+//! archive holds the control file followed by the WAL segments from the one of
+//! the WAL removal horizon up to the last one, under their names. The
+//! local_start_lsn in the archived control file is moved up to the start of
+//! the first segment, since the restored timeline has WAL only from there.
+//!
+use std::cmp::{max, min};
+use std::sync::Arc;
+
+use anyhow::{ensure, Context, Result};
+use futures::StreamExt;
+use postgres_ffi::v14::xlog_utils::{IsPartialXLogFileName, IsXLogFileName, XLogFromFileName};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_tar::{Archive, Builder, EntryType, Header};
+use tracing::*;
+use utils::id::TenantTimelineId;
+
+use crate::control_file::{FileStorage, CONTROL_FILE_NAME};
+use crate::pull_timeline::{create_temp_timeline_dir, load_temp_timeline};
+use crate::timeline::Timeline;
+use crate::SafeKeeperConf;
+
+/// Writes the snapshot of the timeline into `writer`.
+///
+/// The WAL keeps being appended meanwhile, the last segment is archived as far
+/// as it is written when it is reached. Removal of a segment before it is
+/// archived fails the snapshot, which can be retried.
+pub async fn write_snapshot(
+    tli: &Timeline,
+    conf: &SafeKeeperConf,
+    writer: impl AsyncWrite + Unpin + Send,
+) -> Result<()> {
+    let (_, mut state) = tli.get_state().await;
+    let wal_seg_size = state.server.wal_seg_size as usize;
+    ensure!(wal_seg_size != 0, "timeline {} has no WAL yet", tli.ttid);
+
+    // The end of the WAL is searched for from the persisted commit_lsn when the
+    // timeline is loaded, so the WAL from there on is always archived.
+    let horizon_lsn = tli
+        .get_horizon_lsn(conf.wal_backup_enabled, conf.wal_retention_margin_bytes)
+        .await;
+    let start_lsn = max(min(horizon_lsn, state.commit_lsn), state.local_start_lsn);
+    let start_segno = start_lsn.segment_number(wal_seg_size);
+    state.local_start_lsn = max(state.local_start_lsn, start_lsn.segment_lsn(wal_seg_size));
+
+    let mut segments = Vec::new();
+    let mut dir = tokio::fs::read_dir(&tli.timeline_dir).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !IsXLogFileName(&file_name) && !IsPartialXLogFileName(&file_name) {
+            continue;
+        }
+        let (segno, _) = XLogFromFileName(&file_name, wal_seg_size);
+        if segno >= start_segno {
+            segments.push(file_name);
+        }
+    }
+    segments.sort();
+
+    let mut builder = Builder::new(writer);
+    let control_bytes = FileStorage::ser_sk_state(&state).context("serialize control file")?;
+    let mut header = Header::new_gnu();
+    header.set_size(control_bytes.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, CONTROL_FILE_NAME, control_bytes.as_slice())
+        .await?;
+
+    let mut snapshot_size = 0;
+    for file_name in &segments {
+        let file = File::open(tli.timeline_dir.join(file_name))
+            .await
+            .with_context(|| format!("open WAL segment {file_name}"))?;
+        let size = file.metadata().await?.len();
+        let mut header = Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, file_name, file.take(size))
+            .await
+            .with_context(|| format!("add WAL segment {file_name} to the snapshot"))?;
+        snapshot_size += size;
+    }
+
+    let mut writer = builder.into_inner().await?;
+    writer.shutdown().await?;
+
+    info!(
+        "took snapshot of {} WAL segments of {snapshot_size} bytes from {}",
+        segments.len(),
+        state.local_start_lsn
+    );
+    Ok(())
+}
+
+/// Creates the timeline `ttid` from a snapshot read from `reader`.
+pub async fn restore_snapshot(
+    conf: &SafeKeeperConf,
+    ttid: TenantTimelineId,
+    reader: impl AsyncRead + Unpin + Send,
+) -> Result<Arc<Timeline>> {
+    let tli_dir = create_temp_timeline_dir(conf, ttid).await?;
+
+    let mut entries = Archive::new(reader).entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        // The control file and WAL segment names have no directories in them,
+        // so this also keeps the files within the timeline directory.
+        let file_name = entry_path.to_string_lossy().into_owned();
+        ensure!(
+            entry.header().entry_type() == EntryType::Regular,
+            "entry {file_name} of the snapshot is not a file"
+        );
+        ensure!(
+            file_name == CONTROL_FILE_NAME
+                || IsXLogFileName(&file_name)
+                || IsPartialXLogFileName(&file_name),
+            "unexpected file {file_name} in the snapshot"
+        );
+
+        let mut file = File::create(tli_dir.path().join(&file_name)).await?;
+        tokio::io::copy(&mut entry, &mut file)
+            .await
+            .with_context(|| format!("unpack {file_name}"))?;
+        if !conf.no_sync {
+            file.sync_all().await?;
+        }
+    }
+    if !conf.no_sync {
+        File::open(tli_dir.path()).await?.sync_all().await?;
+    }
+
+    let tli = load_temp_timeline(conf, ttid, tli_dir.path()).await?;
+    info!(
+        "restored timeline {} from snapshot, flush_lsn={}",
+        ttid,
+        tli.get_flush_lsn().await
+    );
+    Ok(tli)
+}
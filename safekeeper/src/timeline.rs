@@ -5,16 +5,18 @@ use anyhow::{anyhow, bail, Result};
 use postgres_ffi::XLogSegNo;
 use tokio::fs;
 
-use std::cmp::max;
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{Mutex, MutexGuard};
 use tokio::{
     sync::{mpsc::Sender, watch},
-    time::Instant,
+    time::{sleep, Duration, Instant},
 };
 use tracing::*;
 use utils::http::error::ApiError;
+use utils::rate_limit::TokenBucket;
 use utils::{
     id::{NodeId, TenantTimelineId},
     lsn::Lsn,
@@ -40,13 +42,16 @@ use crate::{debug_dump, wal_storage};
 pub struct PeerInfo {
     pub sk_id: NodeId,
     /// Term of the last entry.
-    _last_log_term: Term,
+    pub last_log_term: Term,
     /// LSN of the last record.
-    _flush_lsn: Lsn,
+    pub flush_lsn: Lsn,
     pub commit_lsn: Lsn,
     /// Since which LSN safekeeper has WAL. TODO: remove this once we fill new
     /// sk since backup_lsn.
     pub local_start_lsn: Lsn,
+    /// Connection strings of the WAL service and the HTTP API of the peer.
+    pub pg_connstr: String,
+    pub http_connstr: String,
     /// When info was received.
     ts: Instant,
 }
@@ -55,10 +60,12 @@ impl PeerInfo {
     fn from_sk_info(sk_info: &SafekeeperTimelineInfo, ts: Instant) -> PeerInfo {
         PeerInfo {
             sk_id: NodeId(sk_info.safekeeper_id),
-            _last_log_term: sk_info.last_log_term,
-            _flush_lsn: Lsn(sk_info.flush_lsn),
+            last_log_term: sk_info.last_log_term,
+            flush_lsn: Lsn(sk_info.flush_lsn),
             commit_lsn: Lsn(sk_info.commit_lsn),
             local_start_lsn: Lsn(sk_info.local_start_lsn),
+            pg_connstr: sk_info.safekeeper_connstr.clone(),
+            http_connstr: sk_info.http_connstr.clone(),
             ts,
         }
     }
@@ -241,6 +248,9 @@ impl SharedState {
                 .advertise_pg_addr
                 .to_owned()
                 .unwrap_or(conf.listen_pg_addr.clone()),
+            http_connstr: conf.listen_http_addr.clone(),
+            zstd_wal_compression: true,
+            region_wal_filter: true,
             backup_lsn: self.sk.inmem.backup_lsn.0,
             local_start_lsn: self.sk.state.local_start_lsn.0,
             availability_zone: conf.availability_zone.clone(),
@@ -305,6 +315,22 @@ pub struct Timeline {
 
     /// Directory where timeline state is stored.
     pub timeline_dir: PathBuf,
+
+    /// Limits the rate at which WAL is accepted from the compute, if set.
+    ingest_limit: Option<std::sync::Mutex<TokenBucket>>,
+
+    /// Serializes WAL removals, which the removal task and the HTTP API both
+    /// run, so that they don't remove the same segments at the same time.
+    remove_wal_lock: Mutex<()>,
+}
+
+/// Token bucket of the WAL ingest rate limit, shared by the connections of
+/// the timeline.
+fn ingest_limit(conf: &SafeKeeperConf) -> Option<std::sync::Mutex<TokenBucket>> {
+    conf.ingest_rate_limit_bytes_per_sec.map(|rate| {
+        let burst = conf.ingest_burst_bytes.unwrap_or(rate);
+        std::sync::Mutex::new(TokenBucket::new(rate, burst))
+    })
 }
 
 impl Timeline {
@@ -318,6 +344,7 @@ impl Timeline {
 
         let shared_state = SharedState::restore(&conf, &ttid)?;
         let rcl = shared_state.sk.state.remote_consistent_lsn;
+        let replication_slots = shared_state.sk.state.replication_slots.clone();
         let (commit_lsn_watch_tx, commit_lsn_watch_rx) =
             watch::channel(shared_state.sk.state.commit_lsn);
         let (cancellation_tx, cancellation_rx) = watch::channel(false);
@@ -328,10 +355,12 @@ impl Timeline {
             commit_lsn_watch_tx,
             commit_lsn_watch_rx,
             mutex: Mutex::new(shared_state),
-            walsenders: WalSenders::new(rcl),
+            walsenders: WalSenders::new(rcl, replication_slots),
             cancellation_rx,
             cancellation_tx,
             timeline_dir: conf.timeline_dir(&ttid),
+            ingest_limit: ingest_limit(&conf),
+            remove_wal_lock: Mutex::new(()),
         })
     }
 
@@ -354,10 +383,12 @@ impl Timeline {
             commit_lsn_watch_tx,
             commit_lsn_watch_rx,
             mutex: Mutex::new(SharedState::create_new(&conf, &ttid, state)?),
-            walsenders: WalSenders::new(Lsn(0)),
+            walsenders: WalSenders::new(Lsn(0), BTreeMap::new()),
             cancellation_rx,
             cancellation_tx,
             timeline_dir: conf.timeline_dir(&ttid),
+            ingest_limit: ingest_limit(&conf),
+            remove_wal_lock: Mutex::new(()),
         })
     }
 
@@ -527,6 +558,19 @@ impl Timeline {
         self.commit_lsn_watch_rx.clone()
     }
 
+    /// Waits until the ingest rate limit, if any, lets `bytes` of WAL from the
+    /// compute through. Returns how long it waited.
+    pub async fn throttle_ingest(&self, bytes: u64) -> Duration {
+        let delay = match &self.ingest_limit {
+            Some(bucket) => bucket.lock().unwrap().take(bytes),
+            None => return Duration::ZERO,
+        };
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+        delay
+    }
+
     /// Pass arrived message to the safekeeper.
     pub async fn process_msg(
         &self,
@@ -646,6 +690,30 @@ impl Timeline {
             .collect()
     }
 
+    /// Returns the peer to pull the WAL we miss from, if any: the most
+    /// advanced one, by term of the last entry and then flush_lsn. Nothing is
+    /// pulled while a compute streams WAL to us, not to get in its way.
+    pub async fn get_recovery_donor(&self, conf: &SafeKeeperConf) -> Option<PeerInfo> {
+        let shared_state = self.write_shared_state().await;
+        if shared_state.num_computes > 0 {
+            return None;
+        }
+        let me = (
+            shared_state.sk.get_epoch(),
+            shared_state.sk.wal_store.flush_lsn(),
+        );
+        let now = Instant::now();
+        shared_state
+            .peers_info
+            .0
+            .iter()
+            .filter(|p| now.duration_since(p.ts) <= conf.heartbeat_timeout)
+            .filter(|p| p.sk_id != conf.my_id && !p.http_connstr.is_empty())
+            .filter(|p| (p.last_log_term, p.flush_lsn) > me)
+            .max_by_key(|p| (p.last_log_term, p.flush_lsn))
+            .cloned()
+    }
+
     pub fn get_walsenders(&self) -> &Arc<WalSenders> {
         &self.walsenders
     }
@@ -655,19 +723,70 @@ impl Timeline {
         self.write_shared_state().await.sk.wal_store.flush_lsn()
     }
 
+    /// Returns the segment number up to which, excluded, WAL is removed.
+    pub async fn get_last_removed_segno(&self) -> XLogSegNo {
+        self.write_shared_state().await.last_removed_segno
+    }
+
+    /// Returns the estimated disk space taken by the WAL of the timeline.
+    pub async fn get_wal_disk_usage(&self) -> u64 {
+        let shared_state = self.write_shared_state().await;
+        wal_disk_usage(
+            shared_state.sk.state.local_start_lsn,
+            shared_state.last_removed_segno,
+            shared_state.sk.wal_store.flush_lsn(),
+            shared_state.get_wal_seg_size(),
+        )
+    }
+
+    /// Returns the LSN before which WAL is not needed anymore, see
+    /// `SafeKeeper::get_horizon_lsn`. The WAL not yet flushed by the standbys
+    /// streaming through replication slots is kept as well.
+    pub async fn get_horizon_lsn(&self, wal_backup_enabled: bool, retention_margin: u64) -> Lsn {
+        let shared_state = self.write_shared_state().await;
+        self.horizon_lsn(&shared_state, wal_backup_enabled, retention_margin)
+    }
+
+    fn horizon_lsn(
+        &self,
+        shared_state: &SharedState,
+        wal_backup_enabled: bool,
+        retention_margin: u64,
+    ) -> Lsn {
+        let horizon_lsn = shared_state
+            .sk
+            .get_horizon_lsn(wal_backup_enabled, retention_margin);
+        match self.walsenders.get_slots_restart_lsn() {
+            Some(restart_lsn) => min(
+                horizon_lsn,
+                restart_lsn.checked_sub(retention_margin).unwrap_or(Lsn(0)),
+            ),
+            None => horizon_lsn,
+        }
+    }
+
     /// Delete WAL segments from disk that are no longer needed. This is determined
-    /// based on pageserver's remote_consistent_lsn and local backup_lsn/peer_lsn.
-    pub async fn remove_old_wal(&self, wal_backup_enabled: bool) -> Result<()> {
+    /// based on pageserver's remote_consistent_lsn and local backup_lsn/peer_lsn,
+    /// keeping `retention_margin` bytes of WAL before that.
+    /// Returns the segment number up to which, excluded, WAL is removed.
+    pub async fn remove_old_wal(
+        &self,
+        wal_backup_enabled: bool,
+        retention_margin: u64,
+    ) -> Result<XLogSegNo> {
         if self.is_cancelled() {
             bail!(TimelineError::Cancelled(self.ttid));
         }
+        let _remove_wal_guard = self.remove_wal_lock.lock().await;
 
         let horizon_segno: XLogSegNo;
         let remover = {
             let shared_state = self.write_shared_state().await;
-            horizon_segno = shared_state.sk.get_horizon_segno(wal_backup_enabled);
+            horizon_segno = self
+                .horizon_lsn(&shared_state, wal_backup_enabled, retention_margin)
+                .segment_number(shared_state.get_wal_seg_size());
             if horizon_segno <= 1 || horizon_segno <= shared_state.last_removed_segno {
-                return Ok(()); // nothing to do
+                return Ok(shared_state.last_removed_segno); // nothing to do
             }
             let remover = shared_state.sk.wal_store.remove_up_to(horizon_segno - 1);
             // release the lock before removing
@@ -677,10 +796,10 @@ impl Timeline {
         // delete old WAL files
         remover.await?;
 
-        // update last_removed_segno
+        // update last_removed_segno, which never goes back
         let mut shared_state = self.write_shared_state().await;
-        shared_state.last_removed_segno = horizon_segno;
-        Ok(())
+        shared_state.last_removed_segno = max(shared_state.last_removed_segno, horizon_segno);
+        Ok(shared_state.last_removed_segno)
     }
 
     /// Persist control file if there is something to save and enough time
@@ -689,11 +808,53 @@ impl Timeline {
     /// safekeeper reconnections.
     pub async fn maybe_persist_control_file(&self) -> Result<()> {
         let remote_consistent_lsn = self.walsenders.get_remote_consistent_lsn();
-        self.write_shared_state()
+        let mut shared_state = self.write_shared_state().await;
+        // Slots are created and dropped under the lock.
+        let replication_slots = self.walsenders.get_replication_slots();
+        shared_state
+            .sk
+            .maybe_persist_control_file(remote_consistent_lsn, replication_slots)
             .await
+    }
+
+    /// Create a physical replication slot for a standby, keeping the WAL from
+    /// the current commit_lsn on until the standby has flushed it. Returns the
+    /// restart LSN of the slot.
+    pub async fn create_replication_slot(&self, slot_name: &str) -> Result<Lsn> {
+        validate_replication_slot_name(slot_name)?;
+        let mut shared_state = self.write_shared_state().await;
+        let mut replication_slots = self.walsenders.get_replication_slots();
+        if replication_slots.contains_key(slot_name) {
+            bail!("replication slot \"{slot_name}\" already exists");
+        }
+        let restart_lsn = shared_state.sk.inmem.commit_lsn;
+        replication_slots.insert(slot_name.to_owned(), restart_lsn);
+        shared_state
             .sk
-            .maybe_persist_control_file(remote_consistent_lsn)
+            .persist_replication_slots(replication_slots)
+            .await?;
+        self.walsenders
+            .add_replication_slot(slot_name.to_owned(), restart_lsn);
+        info!("created replication slot {slot_name} at {restart_lsn}");
+        Ok(restart_lsn)
+    }
+
+    /// Drop a replication slot, failing if a standby streams through it.
+    pub async fn drop_replication_slot(&self, slot_name: &str) -> Result<()> {
+        let mut shared_state = self.write_shared_state().await;
+        let restart_lsn = self.walsenders.remove_replication_slot(slot_name)?;
+        let replication_slots = self.walsenders.get_replication_slots();
+        if let Err(e) = shared_state
+            .sk
+            .persist_replication_slots(replication_slots)
             .await
+        {
+            self.walsenders
+                .add_replication_slot(slot_name.to_owned(), restart_lsn);
+            return Err(e);
+        }
+        info!("dropped replication slot {slot_name}");
+        Ok(())
     }
 
     /// Gather timeline data for metrics. If the timeline is not active, returns
@@ -718,6 +879,13 @@ impl Timeline {
                 persisted_state: state.sk.state.clone(),
                 flush_lsn: state.sk.wal_store.flush_lsn(),
                 remote_consistent_lsn: self.get_walsenders().get_remote_consistent_lsn(),
+                peers_max_commit_lsn: state
+                    .peers_info
+                    .0
+                    .iter()
+                    .map(|p| p.commit_lsn)
+                    .max()
+                    .unwrap_or(Lsn::INVALID),
                 wal_storage: state.sk.wal_store.get_metrics(),
             })
         } else {
@@ -751,6 +919,20 @@ impl Timeline {
 }
 
 /// Deletes directory and it's contents. Returns false if directory does not exist.
+/// Checks the name of a replication slot like Postgres does.
+fn validate_replication_slot_name(slot_name: &str) -> Result<()> {
+    if slot_name.is_empty() || slot_name.len() > 63 {
+        bail!("replication slot name \"{slot_name}\" is empty or too long");
+    }
+    if !slot_name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!("replication slot name \"{slot_name}\" contains invalid character");
+    }
+    Ok(())
+}
+
 async fn delete_dir(path: &PathBuf) -> Result<bool> {
     match fs::remove_dir_all(path).await {
         Ok(_) => Ok(true),
@@ -758,3 +940,23 @@ async fn delete_dir(path: &PathBuf) -> Result<bool> {
         Err(e) => Err(e.into()),
     }
 }
+
+/// Estimated disk space taken by the WAL segments of a timeline: the ones from
+/// the first segment not removed, or the one WAL starts at locally, up to the
+/// current one.
+pub fn wal_disk_usage(
+    local_start_lsn: Lsn,
+    last_removed_segno: XLogSegNo,
+    flush_lsn: Lsn,
+    wal_seg_size: usize,
+) -> u64 {
+    if flush_lsn == Lsn::INVALID {
+        return 0;
+    }
+    let first_segno = max(
+        last_removed_segno,
+        local_start_lsn.segment_number(wal_seg_size),
+    );
+    let segno_count = (flush_lsn.segment_number(wal_seg_size) + 1).saturating_sub(first_segno);
+    segno_count * wal_seg_size as u64
+}
@@ -289,6 +289,18 @@ impl GlobalTimelines {
             .collect()
     }
 
+    /// Returns the estimated disk space taken by the WAL of all timelines of
+    /// the tenant.
+    pub async fn get_tenant_wal_disk_usage(tenant_id: TenantId) -> u64 {
+        let mut usage = 0;
+        for tli in Self::get_all_for_tenant(tenant_id) {
+            if !tli.is_cancelled() {
+                usage += tli.get_wal_disk_usage().await;
+            }
+        }
+        usage
+    }
+
     /// Returns all timelines belonging to a given tenant. Used for deleting all timelines of a tenant,
     /// and that's why it can return cancelled timelines, to retry deleting them.
     fn get_all_for_tenant(tenant_id: TenantId) -> Vec<Arc<Timeline>> {
@@ -0,0 +1,594 @@
+//! Filtering of the WAL streamed to the pageserver of another region.
+//!
+//! In a multi-region deployment, the pageservers of every region ingest the
+//! timelines of all regions, although a region reads only some relations of
+//! the others. Safekeepers can be configured with a [`WalFilter`] per region,
+//! used when a receiver passes `region='<id>'` in START_REPLICATION. Records
+//! modifying user relations that the filter deems irrelevant to the region are
+//! then replaced by XLOG_NOOP records of the same size, with a zeroed payload.
+//! That keeps the LSNs, and so the WAL decoding and ingestion on the receiving
+//! side, intact, while the zeroes cost next to nothing on the wire with WAL
+//! compression.
+//!
+//! Filters pick whole databases or tablespaces, whose catalogs go along with
+//! their user relations: a relfilenode alone can't tell a catalog from a user
+//! relation, as rewriting a catalog gives it a new one in the normal range.
+//! A region reading no relation of a database doesn't need its catalogs
+//! either. Everything else is global and always streamed: records without
+//! block references (commits, checkpoints, ...), records of resource managers
+//! that don't modify relation pages, and records touching the shared catalogs
+//! of the global tablespace.
+//!
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use crc32c::crc32c_append;
+use postgres_ffi::v14::xlog_utils::{XLOG_RECORD_CRC_OFFS, XLOG_SIZE_OF_XLOG_LONG_PHD};
+use postgres_ffi::{pg_constants, TransactionId};
+use postgres_ffi::{XLOG_BLCKSZ, XLOG_SIZE_OF_XLOG_RECORD, XLOG_SIZE_OF_XLOG_SHORT_PHD};
+use utils::id::RegionId;
+use utils::lsn::Lsn;
+
+use crate::metrics::FILTERED_WAL_BYTES;
+
+/// Records longer than this are streamed as is as soon as they start coming
+/// in, instead of being held back until complete to be filtered.
+const MAX_FILTERED_RECORD_LEN: u32 = 1024 * 1024;
+
+/// Relation whose block a WAL record modifies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelFileNode {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    pub relnode: u32,
+}
+
+/// A record modifying relations of databases, as presented to a [`WalFilter`].
+#[derive(Debug)]
+pub struct WalRecordInfo<'a> {
+    pub rmid: u8,
+    pub info: u8,
+    pub xid: TransactionId,
+    /// Relations of the blocks the record modifies, never empty.
+    pub rels: &'a [RelFileNode],
+}
+
+/// Decides which records modifying relations of databases a region needs.
+pub trait WalFilter: fmt::Debug + Send + Sync {
+    fn is_relevant(&self, record: &WalRecordInfo) -> bool;
+}
+
+/// Keeps the records of a set of databases and tablespaces, given as a comma
+/// separated list of `<spcnode>/<dbnode>`, where either can be `*`, e.g.
+/// `*/16384,16400/*` for database 16384 wherever its relations are and all
+/// relations in tablespace 16400.
+#[derive(Debug, Default)]
+pub struct DatabaseWalFilter {
+    databases: HashSet<u32>,
+    tablespaces: HashSet<u32>,
+    /// Databases within a single tablespace, as (spcnode, dbnode).
+    tablespace_databases: HashSet<(u32, u32)>,
+}
+
+impl WalFilter for DatabaseWalFilter {
+    fn is_relevant(&self, record: &WalRecordInfo) -> bool {
+        record.rels.iter().any(|rel| {
+            self.databases.contains(&rel.dbnode)
+                || self.tablespaces.contains(&rel.spcnode)
+                || self
+                    .tablespace_databases
+                    .contains(&(rel.spcnode, rel.dbnode))
+        })
+    }
+}
+
+impl FromStr for DatabaseWalFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = DatabaseWalFilter::default();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (spcnode, dbnode) = entry
+                .split_once('/')
+                .with_context(|| format!("no '/' in '{entry}'"))?;
+            let parse_oid = |oid: &str, what: &str| -> anyhow::Result<Option<u32>> {
+                if oid == "*" {
+                    return Ok(None);
+                }
+                let oid = oid
+                    .parse()
+                    .with_context(|| format!("invalid {what} oid in '{entry}'"))?;
+                Ok(Some(oid))
+            };
+            let spcnode = parse_oid(spcnode, "tablespace")?;
+            let dbnode = parse_oid(dbnode, "database")?;
+            match (spcnode, dbnode) {
+                (None, None) => bail!("'{entry}' would keep everything"),
+                (None, Some(dbnode)) => filter.databases.insert(dbnode),
+                (Some(spcnode), None) => filter.tablespaces.insert(spcnode),
+                (Some(spcnode), Some(dbnode)) => {
+                    filter.tablespace_databases.insert((spcnode, dbnode))
+                }
+            };
+        }
+        Ok(filter)
+    }
+}
+
+/// Parses a `--region-wal-filter` value, `<region id>:<databases>` with the
+/// databases of [`DatabaseWalFilter`].
+pub fn parse_region_wal_filter(s: &str) -> anyhow::Result<(RegionId, Arc<dyn WalFilter>)> {
+    let (region, databases) = s
+        .split_once(':')
+        .with_context(|| format!("no ':' after the region id in '{s}'"))?;
+    let region = region
+        .parse()
+        .with_context(|| format!("invalid region id in '{s}'"))?;
+    let filter: DatabaseWalFilter = databases.parse()?;
+    Ok((region, Arc::new(filter)))
+}
+
+/// Applies a [`WalFilter`] to a stream of WAL.
+///
+/// The WAL of a record is held back until the whole record has been fed, so
+/// the filtered WAL returned by [`WalStreamFilter::filter`] can end before
+/// the WAL fed so far.
+pub struct WalStreamFilter {
+    filter: Arc<dyn WalFilter>,
+    pg_version: u32,
+    wal_seg_size: usize,
+    /// WAL fed, but not returned yet, starting at `pending_lsn`.
+    pending: Vec<u8>,
+    pending_lsn: Lsn,
+    /// Where the next record, or the page header before it, starts. Ahead of
+    /// the end of `pending` while skipping the padding after a record.
+    next_lsn: Lsn,
+}
+
+impl WalStreamFilter {
+    /// `start_lsn` must be at the start of a record, or of the page before it.
+    pub fn new(
+        filter: Arc<dyn WalFilter>,
+        start_lsn: Lsn,
+        pg_version: u32,
+        wal_seg_size: usize,
+    ) -> Self {
+        WalStreamFilter {
+            filter,
+            pg_version,
+            wal_seg_size,
+            pending: Vec::new(),
+            pending_lsn: start_lsn,
+            next_lsn: start_lsn,
+        }
+    }
+
+    /// Feeds the WAL following the one fed before, and returns the filtered
+    /// WAL that is ready to be streamed, with the LSN it starts at.
+    pub fn filter(&mut self, wal: &[u8]) -> anyhow::Result<(Lsn, Vec<u8>)> {
+        self.pending.extend_from_slice(wal);
+        let end_lsn = self.pending_lsn + self.pending.len() as u64;
+
+        let mut lsn = self.next_lsn;
+        while lsn < end_lsn {
+            if lsn.block_offset() == 0 {
+                lsn += self.page_header_size(lsn) as u64;
+                continue;
+            }
+            // The length of a record is always on the same page as its start.
+            let Some(tot_len) = self.pending_bytes(lsn, 4) else {
+                break;
+            };
+            let tot_len = u32::from_le_bytes(tot_len.try_into().unwrap());
+            if (tot_len as usize) < XLOG_SIZE_OF_XLOG_RECORD {
+                bail!("invalid record length {tot_len} at {lsn}");
+            }
+            let record_end = self.walk_record(lsn, tot_len as usize, |_, _| {});
+            if record_end > end_lsn {
+                if tot_len > MAX_FILTERED_RECORD_LEN {
+                    lsn = record_end.align();
+                    continue;
+                }
+                // Wait for the rest of the record.
+                break;
+            }
+
+            let mut record = Vec::with_capacity(tot_len as usize);
+            self.walk_record(lsn, tot_len as usize, |lsn, len| {
+                record.extend_from_slice(self.pending_bytes(lsn, len).unwrap())
+            });
+            let is_switch = record[17] == pg_constants::RM_XLOG_ID
+                && record[16] & pg_constants::XLR_RMGR_INFO_MASK == pg_constants::XLOG_SWITCH;
+            if let Some(noop) = self.filter_record(&record) {
+                let pending_lsn = self.pending_lsn;
+                let mut noop_off = 0;
+                let mut fragments = Vec::new();
+                self.walk_record(lsn, tot_len as usize, |lsn, len| fragments.push((lsn, len)));
+                for (frag_lsn, len) in fragments {
+                    let off = (frag_lsn.0 - pending_lsn.0) as usize;
+                    self.pending[off..off + len].copy_from_slice(&noop[noop_off..noop_off + len]);
+                    noop_off += len;
+                }
+                FILTERED_WAL_BYTES.inc_by(tot_len as u64);
+            }
+
+            lsn = if is_switch {
+                // The rest of the segment is padding.
+                Lsn(record_end.0 + record_end.calc_padding(self.wal_seg_size as u64))
+            } else {
+                record_end.align()
+            };
+        }
+
+        self.next_lsn = lsn;
+        let ready = (lsn.min(end_lsn).0 - self.pending_lsn.0) as usize;
+        let filtered: Vec<u8> = self.pending.drain(..ready).collect();
+        let start_lsn = self.pending_lsn;
+        self.pending_lsn += ready as u64;
+        Ok((start_lsn, filtered))
+    }
+
+    /// Returns the NOOP record to replace the given record with, if the region
+    /// doesn't need it.
+    fn filter_record(&self, record: &[u8]) -> Option<Vec<u8>> {
+        let xid = u32::from_le_bytes(record[4..8].try_into().unwrap());
+        let info = record[16];
+        let rmid = record[17];
+        let modifies_pages = match rmid {
+            pg_constants::RM_HEAP2_ID
+            | pg_constants::RM_HEAP_ID
+            | pg_constants::RM_BTREE_ID
+            | pg_constants::RM_HASH_ID
+            | pg_constants::RM_GIN_ID
+            | pg_constants::RM_GIST_ID
+            | pg_constants::RM_SEQ_ID
+            | pg_constants::RM_SPGIST_ID
+            | pg_constants::RM_BRIN_ID
+            | pg_constants::RM_GENERIC_ID => true,
+            pg_constants::RM_XLOG_ID => matches!(
+                info & pg_constants::XLR_RMGR_INFO_MASK,
+                pg_constants::XLOG_FPI | pg_constants::XLOG_FPI_FOR_HINT
+            ),
+            _ => false,
+        };
+        if !modifies_pages {
+            return None;
+        }
+        // Keep what we can't make sense of.
+        let rels = block_rels(record, self.pg_version)?;
+        if rels.is_empty()
+            || rels
+                .iter()
+                .any(|rel| rel.spcnode == pg_constants::GLOBALTABLESPACE_OID)
+        {
+            return None;
+        }
+        let record_info = WalRecordInfo {
+            rmid,
+            info,
+            xid,
+            rels: &rels,
+        };
+        if self.filter.is_relevant(&record_info) {
+            return None;
+        }
+        noop_record(record)
+    }
+
+    fn page_header_size(&self, page_lsn: Lsn) -> usize {
+        if page_lsn.segment_offset(self.wal_seg_size) == 0 {
+            XLOG_SIZE_OF_XLOG_LONG_PHD
+        } else {
+            XLOG_SIZE_OF_XLOG_SHORT_PHD
+        }
+    }
+
+    /// Calls `f` with the LSN and length of each fragment of the `len` bytes
+    /// long record starting at `lsn`, skipping the page headers in between.
+    /// Returns the end of the record.
+    fn walk_record(&self, mut lsn: Lsn, mut len: usize, mut f: impl FnMut(Lsn, usize)) -> Lsn {
+        while len > 0 {
+            if lsn.block_offset() == 0 {
+                lsn += self.page_header_size(lsn) as u64;
+            }
+            let fragment_len = len.min(XLOG_BLCKSZ - lsn.block_offset() as usize);
+            f(lsn, fragment_len);
+            lsn += fragment_len as u64;
+            len -= fragment_len;
+        }
+        lsn
+    }
+
+    fn pending_bytes(&self, lsn: Lsn, len: usize) -> Option<&[u8]> {
+        let off = lsn.0.checked_sub(self.pending_lsn.0)? as usize;
+        self.pending.get(off..off + len)
+    }
+}
+
+/// Returns the relations of the blocks a record references, or None if the
+/// block headers are malformed.
+fn block_rels(record: &[u8], pg_version: u32) -> Option<Vec<RelFileNode>> {
+    fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        let taken = buf.get(..len)?;
+        *buf = &buf[len..];
+        Some(taken)
+    }
+    fn take_u8(buf: &mut &[u8]) -> Option<u8> {
+        take(buf, 1).map(|b| b[0])
+    }
+    fn take_u16(buf: &mut &[u8]) -> Option<u16> {
+        take(buf, 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn take_u32(buf: &mut &[u8]) -> Option<u32> {
+        take(buf, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    let mut buf = record.get(XLOG_SIZE_OF_XLOG_RECORD..)?;
+    let mut rels = Vec::new();
+    let mut last_rel = None;
+    // Lengths of the block data and main data following the headers.
+    let mut datatotal = 0;
+    while buf.len() > datatotal {
+        match take_u8(&mut buf)? {
+            pg_constants::XLR_BLOCK_ID_DATA_SHORT | pg_constants::XLR_BLOCK_ID_DATA_LONG => {
+                // The main data comes last.
+                break;
+            }
+            pg_constants::XLR_BLOCK_ID_ORIGIN => {
+                take_u16(&mut buf)?;
+            }
+            pg_constants::XLR_BLOCK_ID_TOPLEVEL_XID => {
+                take_u32(&mut buf)?;
+            }
+            block_id if block_id <= pg_constants::XLR_MAX_BLOCK_ID => {
+                let fork_flags = take_u8(&mut buf)?;
+                datatotal += take_u16(&mut buf)? as usize;
+                if fork_flags & pg_constants::BKPBLOCK_HAS_IMAGE != 0 {
+                    let bimg_len = take_u16(&mut buf)?;
+                    let _hole_offset = take_u16(&mut buf)?;
+                    let bimg_info = take_u8(&mut buf)?;
+                    let compressed =
+                        postgres_ffi::bkpimage_is_compressed(bimg_info, pg_version).ok()?;
+                    if bimg_info & pg_constants::BKPIMAGE_HAS_HOLE != 0 && compressed {
+                        let _hole_length = take_u16(&mut buf)?;
+                    }
+                    datatotal += bimg_len as usize;
+                }
+                if fork_flags & pg_constants::BKPBLOCK_SAME_REL == 0 {
+                    last_rel = Some(RelFileNode {
+                        spcnode: take_u32(&mut buf)?,
+                        dbnode: take_u32(&mut buf)?,
+                        relnode: take_u32(&mut buf)?,
+                    });
+                }
+                let _blkno = take_u32(&mut buf)?;
+                rels.push(last_rel?);
+            }
+            _ => return None,
+        }
+    }
+    Some(rels)
+}
+
+/// Builds an XLOG_NOOP record of the same length and with the same xid and
+/// xl_prev as the given record, with all zeroes for payload.
+fn noop_record(record: &[u8]) -> Option<Vec<u8>> {
+    let payload_len = record.len() - XLOG_SIZE_OF_XLOG_RECORD;
+    let mut data = Vec::with_capacity(payload_len);
+    match payload_len {
+        0 => {}
+        // A main data header can't be shorter.
+        1 => return None,
+        2..=257 => data.extend([
+            pg_constants::XLR_BLOCK_ID_DATA_SHORT,
+            (payload_len - 2) as u8,
+        ]),
+        _ => {
+            data.push(pg_constants::XLR_BLOCK_ID_DATA_LONG);
+            data.extend_from_slice(&((payload_len - 5) as u32).to_le_bytes());
+        }
+    }
+    data.resize(payload_len, 0);
+
+    let mut header = record[..XLOG_SIZE_OF_XLOG_RECORD].to_vec();
+    header[16] = pg_constants::XLOG_NOOP;
+    header[17] = pg_constants::RM_XLOG_ID;
+    let crc = crc32c_append(0, &data);
+    let crc = crc32c_append(crc, &header[..XLOG_RECORD_CRC_OFFS]);
+    header[XLOG_RECORD_CRC_OFFS..XLOG_RECORD_CRC_OFFS + 4].copy_from_slice(&crc.to_le_bytes());
+
+    header.extend_from_slice(&data);
+    Some(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postgres_ffi::WAL_SEGMENT_SIZE;
+
+    const PG_VERSION: u32 = 15;
+
+    /// A heap record modifying a block of the given relation, with `data_len`
+    /// bytes of block data.
+    fn heap_record(xid: u32, rel: RelFileNode, data_len: u16) -> Vec<u8> {
+        let mut body = vec![0u8, pg_constants::BKPBLOCK_HAS_DATA];
+        body.extend_from_slice(&data_len.to_le_bytes());
+        for oid in [rel.spcnode, rel.dbnode, rel.relnode] {
+            body.extend_from_slice(&oid.to_le_bytes());
+        }
+        body.extend_from_slice(&7u32.to_le_bytes());
+        body.extend([pg_constants::XLR_BLOCK_ID_DATA_SHORT, 3]);
+        body.extend(std::iter::repeat(0xAB).take(data_len as usize + 3));
+
+        let mut record = Vec::new();
+        record.extend_from_slice(&((XLOG_SIZE_OF_XLOG_RECORD + body.len()) as u32).to_le_bytes());
+        record.extend_from_slice(&xid.to_le_bytes());
+        record.extend_from_slice(&0u64.to_le_bytes());
+        record.extend([0x00, pg_constants::RM_HEAP_ID, 0, 0]);
+        record.extend_from_slice(&0u32.to_le_bytes());
+        record.extend_from_slice(&body);
+        record
+    }
+
+    /// Lays out the records as WAL starting at the given LSN, with page
+    /// headers wherever a page starts.
+    fn layout(start_lsn: Lsn, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut wal = Vec::new();
+        let mut lsn = start_lsn;
+        let mut put = |wal: &mut Vec<u8>, lsn: &mut Lsn, bytes: &[u8]| {
+            for byte in bytes {
+                if lsn.block_offset() == 0 {
+                    let header_len = if lsn.segment_offset(WAL_SEGMENT_SIZE) == 0 {
+                        XLOG_SIZE_OF_XLOG_LONG_PHD
+                    } else {
+                        XLOG_SIZE_OF_XLOG_SHORT_PHD
+                    };
+                    wal.extend(std::iter::repeat(0xFF).take(header_len));
+                    *lsn += header_len as u64;
+                }
+                wal.push(*byte);
+                *lsn += 1;
+            }
+        };
+        for record in records {
+            put(&mut wal, &mut lsn, record);
+            let padding = vec![0; lsn.calc_padding(8u64) as usize];
+            put(&mut wal, &mut lsn, &padding);
+        }
+        wal
+    }
+
+    fn filter(databases: &str, start_lsn: Lsn) -> WalStreamFilter {
+        let filter: DatabaseWalFilter = databases.parse().unwrap();
+        WalStreamFilter::new(Arc::new(filter), start_lsn, PG_VERSION, WAL_SEGMENT_SIZE)
+    }
+
+    const KEPT: RelFileNode = RelFileNode {
+        spcnode: 1663,
+        dbnode: 5,
+        relnode: 16400,
+    };
+    const FILTERED: RelFileNode = RelFileNode {
+        spcnode: 1663,
+        dbnode: 6,
+        relnode: 16400,
+    };
+    /// pg_class of database 6 after a VACUUM FULL.
+    const FILTERED_CATALOG: RelFileNode = RelFileNode {
+        spcnode: 1663,
+        dbnode: 6,
+        relnode: 16700,
+    };
+    /// pg_database, shared by all databases.
+    const SHARED_CATALOG: RelFileNode = RelFileNode {
+        spcnode: pg_constants::GLOBALTABLESPACE_OID,
+        dbnode: 0,
+        relnode: 1262,
+    };
+
+    #[test]
+    fn replaces_irrelevant_records_by_noops() -> anyhow::Result<()> {
+        let start_lsn = Lsn(0x1000028);
+        let records = vec![
+            heap_record(10, KEPT, 100),
+            heap_record(11, FILTERED, 5000),
+            heap_record(12, SHARED_CATALOG, 100),
+            heap_record(13, FILTERED_CATALOG, 300),
+        ];
+        let wal = layout(start_lsn, &records);
+
+        let mut filter = filter("*/5", start_lsn);
+        let (lsn, filtered) = filter.filter(&wal)?;
+        assert_eq!(lsn, start_lsn);
+        assert_eq!(filtered.len(), wal.len());
+
+        let mut expected = records.clone();
+        expected[1] = noop_record(&records[1]).unwrap();
+        expected[3] = noop_record(&records[3]).unwrap();
+        assert_eq!(filtered, layout(start_lsn, &expected));
+        // The noops keep the xids.
+        assert_eq!(expected[1][4..8], 11u32.to_le_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn holds_back_incomplete_records() -> anyhow::Result<()> {
+        let start_lsn = Lsn(0x1001FC0);
+        let records = vec![
+            heap_record(10, FILTERED, 100),
+            heap_record(11, FILTERED, 200),
+        ];
+        let wal = layout(start_lsn, &records);
+        let mut expected_records = records.clone();
+        for record in expected_records.iter_mut() {
+            *record = noop_record(record).unwrap();
+        }
+        let expected = layout(start_lsn, &expected_records);
+
+        let mut filter = filter("*/5", start_lsn);
+        let mut filtered = Vec::new();
+        let mut next_lsn = start_lsn;
+        for chunk in wal.chunks(50) {
+            let (lsn, chunk) = filter.filter(chunk)?;
+            assert_eq!(lsn, next_lsn);
+            next_lsn += chunk.len() as u64;
+            filtered.extend(chunk);
+        }
+        assert_eq!(filtered, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn noop_record_keeps_the_length() {
+        for data_len in [0, 1, 200, 231, 232, 233, 234, 1000] {
+            let record = heap_record(10, FILTERED, data_len);
+            let noop = noop_record(&record).unwrap();
+            assert_eq!(noop.len(), record.len());
+            assert_eq!(noop[17], pg_constants::RM_XLOG_ID);
+            assert_eq!(block_rels(&noop, PG_VERSION), Some(Vec::new()));
+        }
+    }
+
+    #[test]
+    fn parse_region_filter() {
+        let (region, filter) = parse_region_wal_filter("1:*/5,1663/6,16400/*").unwrap();
+        assert_eq!(region, RegionId(1));
+        fn record(rels: &[RelFileNode]) -> WalRecordInfo {
+            WalRecordInfo {
+                rmid: pg_constants::RM_HEAP_ID,
+                info: 0,
+                xid: 0,
+                rels,
+            }
+        }
+        let other_tablespace = RelFileNode {
+            spcnode: 16400,
+            dbnode: 7,
+            ..FILTERED
+        };
+        let other_database = RelFileNode {
+            dbnode: 7,
+            ..FILTERED
+        };
+        let moved = RelFileNode {
+            spcnode: 16500,
+            ..FILTERED
+        };
+        assert!(filter.is_relevant(&record(&[KEPT])));
+        assert!(filter.is_relevant(&record(&[FILTERED])));
+        assert!(filter.is_relevant(&record(&[other_tablespace])));
+        assert!(!filter.is_relevant(&record(&[other_database])));
+        assert!(!filter.is_relevant(&record(&[moved])));
+        assert!(filter.is_relevant(&record(&[other_database, KEPT])));
+        assert!(parse_region_wal_filter("*/5").is_err());
+        assert!(parse_region_wal_filter("1:5").is_err());
+        assert!(parse_region_wal_filter("1:*/*").is_err());
+    }
+}
@@ -22,11 +22,13 @@ use tokio::io::{AsyncRead, AsyncWriteExt};
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tracing::*;
 
-use crate::metrics::{time_io_closure, WalStorageMetrics, REMOVED_WAL_SEGMENTS};
+use crate::metrics::{
+    time_io_closure, WalStorageMetrics, REMOVED_WAL_SEGMENTS, WAL_VALIDATION_FAILURES,
+};
 use crate::safekeeper::SafeKeeperState;
 use crate::wal_backup::read_object;
 use crate::SafeKeeperConf;
-use postgres_ffi::waldecoder::WalStreamDecoder;
+use postgres_ffi::waldecoder::{WalDecodeError, WalDecodeErrorKind, WalStreamDecoder};
 use postgres_ffi::XLogFileName;
 use postgres_ffi::XLOG_BLCKSZ;
 use pq_proto::SystemId;
@@ -173,7 +175,11 @@ impl PhysicalStorage {
             write_lsn,
             write_record_lsn: write_lsn,
             flush_record_lsn: flush_lsn,
-            decoder: WalStreamDecoder::new(write_lsn, state.server.pg_version / 10000),
+            decoder: WalStreamDecoder::new(
+                write_lsn,
+                state.server.pg_version / 10000,
+                wal_seg_size,
+            ),
             file: None,
             is_truncated_after_restart: false,
         })
@@ -315,11 +321,16 @@ impl Storage for PhysicalStorage {
         self.flush_record_lsn
     }
 
-    /// Write WAL to disk.
+    /// Write WAL to disk. The WAL is validated first: page headers, record
+    /// CRCs and continuity with the WAL written before. Invalid WAL is
+    /// rejected without writing any of it.
     async fn write_wal(&mut self, startpos: Lsn, buf: &[u8]) -> Result<()> {
         // Disallow any non-sequential writes, which can result in gaps or overwrites.
         // If we need to move the pointer, use truncate_wal() instead.
         if self.write_lsn > startpos {
+            WAL_VALIDATION_FAILURES
+                .with_label_values(&["continuity"])
+                .inc();
             bail!(
                 "write_wal rewrites WAL written before, write_lsn={}, startpos={}",
                 self.write_lsn,
@@ -327,6 +338,9 @@ impl Storage for PhysicalStorage {
             );
         }
         if self.write_lsn < startpos && self.write_lsn != Lsn(0) {
+            WAL_VALIDATION_FAILURES
+                .with_label_values(&["continuity"])
+                .inc();
             bail!(
                 "write_wal creates gap in written WAL, write_lsn={}, startpos={}",
                 self.write_lsn,
@@ -334,13 +348,8 @@ impl Storage for PhysicalStorage {
             );
         }
 
-        let write_seconds = time_io_closure(self.write_exact(startpos, buf)).await?;
-        // WAL is written, updating write metrics
-        self.metrics.observe_write_seconds(write_seconds);
-        self.metrics.observe_write_bytes(buf.len());
-
-        // figure out last record's end lsn for reporting (if we got the
-        // whole record)
+        // Decode the records completed by this piece of WAL before writing
+        // it, which also figures out last record's end lsn for reporting.
         if self.decoder.available() != startpos {
             info!(
                 "restart decoder from {} to {}",
@@ -348,18 +357,35 @@ impl Storage for PhysicalStorage {
                 startpos,
             );
             let pg_version = self.decoder.pg_version;
-            self.decoder = WalStreamDecoder::new(startpos, pg_version);
+            self.decoder = WalStreamDecoder::new(startpos, pg_version, self.wal_seg_size);
         }
         self.decoder.feed_bytes(buf);
+        let mut record_lsn = None;
         loop {
-            match self.decoder.poll_decode()? {
-                None => break, // no full record yet
-                Some((lsn, _rec)) => {
-                    self.write_record_lsn = lsn;
+            match self.decoder.poll_decode() {
+                Ok(None) => break, // no full record yet
+                Ok(Some((lsn, _rec))) => record_lsn = Some(lsn),
+                Err(e) => {
+                    WAL_VALIDATION_FAILURES
+                        .with_label_values(&[decode_error_kind(&e)])
+                        .inc();
+                    // Start over on the next write, which is at startpos again
+                    // or after truncation.
+                    let pg_version = self.decoder.pg_version;
+                    self.decoder = WalStreamDecoder::new(Lsn(0), pg_version, self.wal_seg_size);
+                    return Err(anyhow::Error::new(e).context("rejecting invalid WAL"));
                 }
             }
         }
 
+        let write_seconds = time_io_closure(self.write_exact(startpos, buf)).await?;
+        // WAL is written, updating write metrics
+        self.metrics.observe_write_seconds(write_seconds);
+        self.metrics.observe_write_bytes(buf.len());
+
+        if let Some(lsn) = record_lsn {
+            self.write_record_lsn = lsn;
+        }
         Ok(())
     }
 
@@ -372,6 +398,10 @@ impl Storage for PhysicalStorage {
         if let Some(mut unflushed_file) = self.file.take() {
             self.fdatasync_file(&mut unflushed_file).await?;
             self.file = Some(unflushed_file);
+            // The WAL is durable, but flush_lsn isn't advanced nor acked yet.
+            fail::fail_point!("sk-after-wal-fsync", |_| bail!(
+                "failpoint sk-after-wal-fsync"
+            ));
         } else {
             // We have unflushed data (write_lsn != flush_lsn), but no file.
             // This should only happen if last file was fully written and flushed,
@@ -484,7 +514,12 @@ async fn remove_segments_from_disk(
             }
             let (segno, _) = XLogFromFileName(fname_str, wal_seg_size);
             if remove_predicate(segno) {
-                remove_file(entry_path).await?;
+                match remove_file(entry_path).await {
+                    Ok(()) => {}
+                    // Already removed by a concurrent removal.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                }
                 n_removed += 1;
                 min_removed = min(min_removed, segno);
                 max_removed = max(max_removed, segno);
@@ -585,6 +620,7 @@ impl WalReader {
                     self.system_id,
                     self.pg_version,
                     self.timeline_start_lsn,
+                    self.wal_seg_size,
                 )?;
                 self.timeline_start_segment = Some(it);
             }
@@ -712,6 +748,15 @@ impl WalReader {
     }
 }
 
+/// Kind of the check WAL failed to decode on, for the validation metrics.
+fn decode_error_kind(e: &WalDecodeError) -> &'static str {
+    match e.kind {
+        WalDecodeErrorKind::PageHeader => "page_header",
+        WalDecodeErrorKind::Crc => "crc",
+        WalDecodeErrorKind::Record | WalDecodeErrorKind::UnknownVersion => "record",
+    }
+}
+
 /// Zero block for filling created WAL segments.
 const ZERO_BLOCK: &[u8] = &[0u8; XLOG_BLCKSZ];
 
@@ -737,3 +782,77 @@ fn wal_file_paths(
     let wal_file_partial_path = timeline_dir.join(wal_file_name + ".partial");
     Ok((wal_file_path, wal_file_partial_path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safekeeper::ServerInfo;
+    use postgres_ffi::v15::xlog_utils::XLOG_SIZE_OF_XLOG_LONG_PHD;
+    use postgres_ffi::wal_generator::{Record, WalGenerator};
+    use postgres_ffi::{WAL_SEGMENT_SIZE, XLOG_SIZE_OF_XLOG_RECORD};
+    use utils::id::TenantTimelineId;
+
+    const START_LSN: Lsn = Lsn(WAL_SEGMENT_SIZE as u64);
+
+    async fn create_storage() -> PhysicalStorage {
+        let conf = SafeKeeperConf {
+            workdir: tempfile::tempdir().unwrap().into_path(),
+            ..SafeKeeperConf::dummy()
+        };
+        let ttid = TenantTimelineId::generate();
+        let timeline_dir = conf.timeline_dir(&ttid);
+        fs::create_dir_all(&timeline_dir).await.unwrap();
+        let server_info = ServerInfo {
+            pg_version: 150000,
+            system_id: 0,
+            wal_seg_size: WAL_SEGMENT_SIZE as u32,
+        };
+        let state = SafeKeeperState::new(&ttid, server_info, vec![], Lsn(0), START_LSN);
+        PhysicalStorage::new(&ttid, timeline_dir, &conf, &state).unwrap()
+    }
+
+    fn generate_wal() -> Bytes {
+        let mut generator = WalGenerator::new(15, 0, WAL_SEGMENT_SIZE, START_LSN).unwrap();
+        generator.append_records(&[Record::logical_message("test", b"hello")])
+    }
+
+    async fn is_empty_dir(dir: &Path) -> bool {
+        fs::read_dir(dir)
+            .await
+            .unwrap()
+            .next_entry()
+            .await
+            .unwrap()
+            .is_none()
+    }
+
+    #[tokio::test]
+    async fn test_write_wal_rejects_invalid_wal() {
+        let mut storage = create_storage().await;
+
+        let mut bad_magic = generate_wal().to_vec();
+        bad_magic[0] ^= 0xff;
+        // The first byte after the record header, covered by the CRC.
+        let mut bad_crc = generate_wal().to_vec();
+        bad_crc[XLOG_SIZE_OF_XLOG_LONG_PHD + XLOG_SIZE_OF_XLOG_RECORD] ^= 0xff;
+
+        for (wal, kind) in [
+            (bad_magic, WalDecodeErrorKind::PageHeader),
+            (bad_crc, WalDecodeErrorKind::Crc),
+        ] {
+            let err = storage.write_wal(START_LSN, &wal).await.unwrap_err();
+            let decode_error = err.downcast_ref::<WalDecodeError>().unwrap();
+            assert_eq!(decode_error.kind, kind, "{err:#}");
+
+            // Nothing of it was written.
+            assert_eq!(storage.internal_state().0, Lsn(0));
+            assert!(is_empty_dir(&storage.timeline_dir).await);
+        }
+
+        // The valid WAL is written at the same position then.
+        let wal = generate_wal();
+        storage.write_wal(START_LSN, &wal).await.unwrap();
+        assert_eq!(storage.internal_state().0, START_LSN + wal.len() as u64);
+        assert!(!is_empty_dir(&storage.timeline_dir).await);
+    }
+}
@@ -132,6 +132,9 @@ async fn publish(client: Option<BrokerClientChannel>, n_keys: u64) {
                 remote_consistent_lsn: 4,
                 peer_horizon_lsn: 5,
                 safekeeper_connstr: "zenith-1-sk-1.local:7676".to_owned(),
+                http_connstr: "zenith-1-sk-1.local:7677".to_owned(),
+                zstd_wal_compression: false,
+                region_wal_filter: false,
                 local_start_lsn: 0,
                 availability_zone: None,
             };
@@ -526,6 +526,9 @@ mod tests {
             remote_consistent_lsn: 4,
             peer_horizon_lsn: 5,
             safekeeper_connstr: "neon-1-sk-1.local:7676".to_owned(),
+            http_connstr: "neon-1-sk-1.local:7677".to_owned(),
+            zstd_wal_compression: false,
+            region_wal_filter: false,
             local_start_lsn: 0,
             availability_zone: None,
         }
@@ -93,6 +93,10 @@ PAGESERVER_PER_TENANT_METRICS: Tuple[str, ...] = (
     "pageserver_written_persistent_bytes_total",
     "pageserver_evictions_total",
     "pageserver_evictions_with_low_residence_duration_total",
+    "pageserver_read_heat_redo_reads_total",
+    "pageserver_read_heat_redo_records_total",
+    "pageserver_read_heat_hot_ranges",
+    "pageserver_read_heat_image_layers_created_total",
     *PAGESERVER_PER_TENANT_REMOTE_TIMELINE_CLIENT_METRICS,
     # pageserver_broken_tenants_count is a leaked "metric" which is "cleared" on restart or reload
 )
@@ -417,6 +417,7 @@ class NeonEnvBuilder:
         remote_storage: Optional[RemoteStorage] = None,
         remote_storage_users: RemoteStorageUsers = RemoteStorageUsers.PAGESERVER,
         pageserver_config_override: Optional[str] = None,
+        num_pageservers: int = 1,
         num_safekeepers: int = 1,
         # Use non-standard SK ids to check for various parsing bugs
         safekeepers_id_start: int = 0,
@@ -439,9 +440,22 @@ class NeonEnvBuilder:
         self.run_id = run_id
         self.mock_s3_server: MockS3Server = mock_s3_server
         self.pageserver_config_override = pageserver_config_override
+        self.num_pageservers = num_pageservers
         self.num_safekeepers = num_safekeepers
         self.safekeepers_id_start = safekeepers_id_start
         self.safekeepers_enable_fsync = safekeepers_enable_fsync
+        self.safekeepers_group_commit_window: Optional[str] = None
+        self.safekeepers_region_wal_filters: List[str] = []
+        self.safekeepers_wal_retention_margin: Optional[int] = None
+        self.safekeepers_tenant_wal_quota: Optional[int] = None
+        # WAL ingest rate limit of the safekeepers, bytes per second, and its burst
+        self.safekeepers_ingest_rate_limit: Optional[int] = None
+        self.safekeepers_ingest_burst: Optional[int] = None
+        self.safekeepers_walproposer_keepalive_interval: Optional[str] = None
+        # Timing of the walproposers, e.g. {"election_timeout": "10s"}
+        self.walproposer_config: Dict[str, Any] = {}
+        # Groups of the safekeepers by their id, e.g. {1: "eu", 2: "eu", 3: "us"}
+        self.safekeepers_groups: Dict[int, str] = {}
         self.auth_enabled = auth_enabled
         self.default_branch_name = default_branch_name
         self.env: Optional[NeonEnv] = None
@@ -708,7 +722,8 @@ class NeonEnvBuilder:
             self.env.endpoints.stop_all()
             for sk in self.env.safekeepers:
                 sk.stop(immediate=True)
-            self.env.pageserver.stop(immediate=True)
+            for pageserver in self.env.pageservers:
+                pageserver.stop(immediate=True)
 
             cleanup_error = None
             try:
@@ -727,7 +742,8 @@ class NeonEnvBuilder:
             if cleanup_error is not None:
                 raise cleanup_error
 
-            self.env.pageserver.assert_no_errors()
+            for pageserver in self.env.pageservers:
+                pageserver.assert_no_errors()
 
 
 class NeonEnv:
@@ -800,29 +816,42 @@ class NeonEnv:
         """
         )
 
-        # Create config for pageserver
-        pageserver_port = PageserverPort(
-            pg=self.port_distributor.get_port(),
-            http=self.port_distributor.get_port(),
-        )
+        if config.walproposer_config:
+            toml += "[walproposer]\n"
+            toml += "".join(f"{k} = {json.dumps(v)}\n" for k, v in config.walproposer_config.items())
+
+        # Create config and a NeonPageserver object for each pageserver. A single
+        # pageserver is configured in the [pageserver] section, like in the configs
+        # written before multiple pageservers were supported.
         http_auth_type = "NeonJWT" if config.auth_enabled else "Trust"
         pg_auth_type = "NeonJWT" if config.auth_enabled else "Trust"
-
-        toml += textwrap.dedent(
-            f"""
-            [pageserver]
-            id=1
-            listen_pg_addr = 'localhost:{pageserver_port.pg}'
-            listen_http_addr = 'localhost:{pageserver_port.http}'
-            pg_auth_type = '{pg_auth_type}'
-            http_auth_type = '{http_auth_type}'
-        """
-        )
-
-        # Create a corresponding NeonPageserver object
-        self.pageserver = NeonPageserver(
-            self, port=pageserver_port, config_override=config.pageserver_config_override
-        )
+        pageserver_section = "[pageserver]" if config.num_pageservers == 1 else "[[pageservers]]"
+        self.pageservers: List[NeonPageserver] = []
+        for id in range(1, config.num_pageservers + 1):
+            pageserver_port = PageserverPort(
+                pg=self.port_distributor.get_port(),
+                http=self.port_distributor.get_port(),
+            )
+            toml += textwrap.dedent(
+                f"""
+                {pageserver_section}
+                id={id}
+                listen_pg_addr = 'localhost:{pageserver_port.pg}'
+                listen_http_addr = 'localhost:{pageserver_port.http}'
+                pg_auth_type = '{pg_auth_type}'
+                http_auth_type = '{http_auth_type}'
+            """
+            )
+            self.pageservers.append(
+                NeonPageserver(
+                    self,
+                    port=pageserver_port,
+                    config_override=config.pageserver_config_override,
+                    id=id,
+                )
+            )
+        # The first pageserver, the one the CLI commands use by default
+        self.pageserver = self.pageservers[0]
 
         # Create config and a Safekeeper object for each safekeeper
         for i in range(1, config.num_safekeepers + 1):
@@ -847,6 +876,54 @@ class NeonEnv:
                 auth_enabled = true
                 """
                 )
+            if config.safekeepers_group_commit_window is not None:
+                toml += textwrap.dedent(
+                    f"""
+                group_commit_window = "{config.safekeepers_group_commit_window}"
+                """
+                )
+            if config.safekeepers_region_wal_filters:
+                toml += textwrap.dedent(
+                    f"""
+                region_wal_filters = {config.safekeepers_region_wal_filters}
+                """
+                )
+            if config.safekeepers_wal_retention_margin is not None:
+                toml += textwrap.dedent(
+                    f"""
+                wal_retention_margin = {config.safekeepers_wal_retention_margin}
+                """
+                )
+            if config.safekeepers_tenant_wal_quota is not None:
+                toml += textwrap.dedent(
+                    f"""
+                tenant_wal_quota = {config.safekeepers_tenant_wal_quota}
+                """
+                )
+            if config.safekeepers_ingest_rate_limit is not None:
+                toml += textwrap.dedent(
+                    f"""
+                ingest_rate_limit = {config.safekeepers_ingest_rate_limit}
+                """
+                )
+            if config.safekeepers_ingest_burst is not None:
+                toml += textwrap.dedent(
+                    f"""
+                ingest_burst = {config.safekeepers_ingest_burst}
+                """
+                )
+            if config.safekeepers_walproposer_keepalive_interval is not None:
+                toml += textwrap.dedent(
+                    f"""
+                walproposer_keepalive_interval = "{config.safekeepers_walproposer_keepalive_interval}"
+                """
+                )
+            if id in config.safekeepers_groups:
+                toml += textwrap.dedent(
+                    f"""
+                group = "{config.safekeepers_groups[id]}"
+                """
+                )
             if (
                 bool(self.remote_storage_users & RemoteStorageUsers.SAFEKEEPER)
                 and self.remote_storage is not None
@@ -865,7 +942,8 @@ class NeonEnv:
     def start(self):
         # Start up broker, pageserver and all safekeepers
         self.broker.try_start()
-        self.pageserver.start()
+        for pageserver in self.pageservers:
+            pageserver.start()
 
         for safekeeper in self.safekeepers:
             safekeeper.start()
@@ -1290,8 +1368,11 @@ class NeonCli(AbstractNeonCli):
         self,
         overrides: Tuple[str, ...] = (),
         extra_env_vars: Optional[Dict[str, str]] = None,
+        id: Optional[int] = None,
     ) -> "subprocess.CompletedProcess[str]":
         start_args = ["pageserver", "start", *overrides]
+        if id is not None:
+            start_args.append(str(id))
         append_pageserver_param_overrides(
             params_to_update=start_args,
             remote_storage=self.env.remote_storage,
@@ -1305,31 +1386,122 @@ class NeonCli(AbstractNeonCli):
 
         return self.raw_cli(start_args, extra_env_vars=extra_env_vars)
 
-    def pageserver_stop(self, immediate=False) -> "subprocess.CompletedProcess[str]":
+    def pageserver_stop(
+        self, immediate=False, id: Optional[int] = None
+    ) -> "subprocess.CompletedProcess[str]":
         cmd = ["pageserver", "stop"]
+        if id is not None:
+            cmd.append(str(id))
         if immediate:
             cmd.extend(["-m", "immediate"])
 
         log.info(f"Stopping pageserver with {cmd}")
         return self.raw_cli(cmd)
 
-    def safekeeper_start(self, id: int) -> "subprocess.CompletedProcess[str]":
+    def safekeeper_start(
+        self, id: Optional[int] = None, group: Optional[str] = None
+    ) -> "subprocess.CompletedProcess[str]":
         s3_env_vars = None
         if self.env.remote_storage is not None and isinstance(self.env.remote_storage, S3Storage):
             s3_env_vars = self.env.remote_storage.access_env_vars()
 
-        return self.raw_cli(["safekeeper", "start", str(id)], extra_env_vars=s3_env_vars)
+        args = ["safekeeper", "start"]
+        if id is not None:
+            args.append(str(id))
+        if group is not None:
+            args.extend(["--group", group])
+        return self.raw_cli(args, extra_env_vars=s3_env_vars)
 
     def safekeeper_stop(
-        self, id: Optional[int] = None, immediate=False
+        self, id: Optional[int] = None, immediate=False, group: Optional[str] = None
     ) -> "subprocess.CompletedProcess[str]":
         args = ["safekeeper", "stop"]
         if id is not None:
             args.append(str(id))
+        if group is not None:
+            args.extend(["--group", group])
         if immediate:
             args.extend(["-m", "immediate"])
         return self.raw_cli(args)
 
+    def safekeeper_status(
+        self, id: int, tenant_id: TenantId, timeline_id: TimelineId
+    ) -> "subprocess.CompletedProcess[str]":
+        return self.raw_cli(
+            [
+                "safekeeper",
+                "status",
+                str(id),
+                "--tenant-id",
+                str(tenant_id),
+                "--timeline-id",
+                str(timeline_id),
+            ]
+        )
+
+    def safekeeper_add_to_timeline(
+        self, id: int, tenant_id: TenantId, timeline_id: TimelineId
+    ) -> "subprocess.CompletedProcess[str]":
+        return self.raw_cli(
+            [
+                "safekeeper",
+                "add-to-timeline",
+                str(id),
+                "--tenant-id",
+                str(tenant_id),
+                "--timeline-id",
+                str(timeline_id),
+            ]
+        )
+
+    def safekeeper_remove_from_timeline(
+        self, id: int, tenant_id: TenantId, timeline_id: TimelineId
+    ) -> "subprocess.CompletedProcess[str]":
+        return self.raw_cli(
+            [
+                "safekeeper",
+                "remove-from-timeline",
+                str(id),
+                "--tenant-id",
+                str(tenant_id),
+                "--timeline-id",
+                str(timeline_id),
+            ]
+        )
+
+    def safekeeper_sync(
+        self, tenant_id: TenantId, timeline_id: TimelineId, truncate: bool = False
+    ) -> "subprocess.CompletedProcess[str]":
+        args = [
+            "safekeeper",
+            "sync",
+            "--tenant-id",
+            str(tenant_id),
+            "--timeline-id",
+            str(timeline_id),
+        ]
+        if truncate:
+            args.append("--truncate")
+        return self.raw_cli(args)
+
+    def safekeeper_dump_control_file(
+        self, id: int, tenant_id: TenantId, timeline_id: TimelineId
+    ) -> Dict[str, Any]:
+        res = self.raw_cli(
+            [
+                "safekeeper",
+                "dump-control-file",
+                str(id),
+                "--tenant-id",
+                str(tenant_id),
+                "--timeline-id",
+                str(timeline_id),
+            ]
+        )
+        state = json.loads(res.stdout)
+        assert isinstance(state, dict)
+        return state
+
     def endpoint_create(
         self,
         branch_name: str,
@@ -1466,9 +1638,19 @@ class NeonPageserver(PgProtocol):
 
     TEMP_FILE_SUFFIX = "___temp"
 
-    def __init__(self, env: NeonEnv, port: PageserverPort, config_override: Optional[str] = None):
+    def __init__(
+        self,
+        env: NeonEnv,
+        port: PageserverPort,
+        config_override: Optional[str] = None,
+        id: int = 1,
+    ):
         super().__init__(host="localhost", port=port.pg, user="cloud_admin")
         self.env = env
+        self.id = id
+        # The first pageserver works in the repository directory, see
+        # LocalEnv::pageserver_data_dir
+        self.workdir = env.repo_dir if id == 1 else env.repo_dir / f"pageserver_{id}"
         self.running = False
         self.service_port = port
         self.config_override = config_override
@@ -1542,7 +1724,9 @@ class NeonPageserver(PgProtocol):
         """
         assert self.running is False
 
-        self.env.neon_cli.pageserver_start(overrides=overrides, extra_env_vars=extra_env_vars)
+        self.env.neon_cli.pageserver_start(
+            overrides=overrides, extra_env_vars=extra_env_vars, id=self.id
+        )
         self.running = True
         return self
 
@@ -1552,7 +1736,7 @@ class NeonPageserver(PgProtocol):
         Returns self.
         """
         if self.running:
-            self.env.neon_cli.pageserver_stop(immediate)
+            self.env.neon_cli.pageserver_stop(immediate, id=self.id)
             self.running = False
         return self
 
@@ -1579,7 +1763,7 @@ class NeonPageserver(PgProtocol):
         )
 
     def assert_no_errors(self):
-        logfile = open(os.path.join(self.env.repo_dir, "pageserver.log"), "r")
+        logfile = open(os.path.join(self.workdir, "pageserver.log"), "r")
         error_or_warn = re.compile(r"\s(ERROR|WARN)")
         errors = []
         while True:
@@ -1602,7 +1786,7 @@ class NeonPageserver(PgProtocol):
 
     def log_contains(self, pattern: str) -> Optional[str]:
         """Check that the pageserver log contains a line that matches the given regex"""
-        logfile = open(os.path.join(self.env.repo_dir, "pageserver.log"), "r")
+        logfile = open(os.path.join(self.workdir, "pageserver.log"), "r")
 
         contains_re = re.compile(pattern)
 
@@ -2578,6 +2762,9 @@ class SafekeeperTimelineStatus:
     backup_lsn: Lsn
     peer_horizon_lsn: Lsn
     remote_consistent_lsn: Lsn
+    last_removed_segno: int
+    # Ids of the other safekeepers of the timeline heard from recently.
+    peer_ids: List[int]
 
 
 @dataclass
@@ -2648,7 +2835,79 @@ class SafekeeperHttpClient(requests.Session):
             backup_lsn=Lsn(resj["backup_lsn"]),
             peer_horizon_lsn=Lsn(resj["peer_horizon_lsn"]),
             remote_consistent_lsn=Lsn(resj["remote_consistent_lsn"]),
+            last_removed_segno=resj["last_removed_segno"],
+            peer_ids=[peer["sk_id"] for peer in resj["peers"]],
+        )
+
+    def timeline_remove_wal(self, tenant_id: TenantId, timeline_id: TimelineId) -> int:
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/remove_wal"
+        )
+        res.raise_for_status()
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return int(res_json["last_removed_segno"])
+
+    def timeline_snapshot(self, tenant_id: TenantId, timeline_id: TimelineId) -> bytes:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/snapshot"
         )
+        res.raise_for_status()
+        return res.content
+
+    def timeline_restore(
+        self, tenant_id: TenantId, timeline_id: TimelineId, snapshot: bytes
+    ) -> Dict[str, Any]:
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/restore",
+            data=snapshot,
+        )
+        res.raise_for_status()
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_list(self) -> List[Dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant")
+        res.raise_for_status()
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def timeline_control_file(self, tenant_id: TenantId, timeline_id: TimelineId) -> Dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/control_file"
+        )
+        res.raise_for_status()
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_wal_horizon(self, tenant_id: TenantId, timeline_id: TimelineId) -> Dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/wal_horizon"
+        )
+        res.raise_for_status()
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def configure_failpoints(self, config_strings: Tuple[str, str] | List[Tuple[str, str]]):
+        if isinstance(config_strings, tuple):
+            pairs = [config_strings]
+        else:
+            pairs = config_strings
+
+        log.info(f"Requesting config failpoints: {repr(pairs)}")
+
+        res = self.put(
+            f"http://localhost:{self.port}/v1/failpoints",
+            json=[{"name": name, "actions": actions} for name, actions in pairs],
+        )
+        res.raise_for_status()
+        res_json = res.json()
+        assert res_json is None
+        return res_json
 
     def record_safekeeper_info(self, tenant_id: TenantId, timeline_id: TimelineId, body):
         res = self.post(
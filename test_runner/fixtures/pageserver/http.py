@@ -229,6 +229,27 @@ class PageserverHttpClient(requests.Session):
         assert isinstance(res_json, dict)
         return res_json
 
+    def page_cache_info(self) -> Dict[str, Any]:
+        res = self.get(f"http://localhost:{self.port}/v1/page_cache")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def page_cache_resize(self, size: int) -> Dict[str, Any]:
+        res = self.put(f"http://localhost:{self.port}/v1/page_cache", json={"size": size})
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_page_cache_stats(self, tenant_id: TenantId) -> Dict[str, Any]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/page_cache")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def tenant_config(self, tenant_id: TenantId) -> TenantConfig:
         res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/config")
         self.verbose_error(res)
@@ -391,6 +412,59 @@ class PageserverHttpClient(requests.Session):
         assert isinstance(res_json, dict)
         return res_json
 
+    def timeline_gc_explanation(
+        self, tenant_id: TenantId, timeline_id: TimelineId
+    ) -> dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/gc_explanation"
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_purge_dropped(
+        self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        spcnode: int,
+        dbnode: int,
+        relnode: Optional[int] = None,
+    ) -> Lsn:
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/purge_dropped",
+            json={"spcnode": spcnode, "dbnode": dbnode, "relnode": relnode},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return Lsn(res_json["lsn"])
+
+    def timeline_rel_sizes(
+        self, tenant_id: TenantId, timeline_id: TimelineId, lsn: Optional[Lsn] = None
+    ) -> dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/rel_sizes",
+            params={"lsn": str(lsn)} if lsn is not None else None,
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_truncate(
+        self, tenant_id: TenantId, timeline_id: TimelineId, lsn: Lsn
+    ) -> dict[str, Any]:
+        log.info(f"Requesting truncate: tenant {tenant_id}, timeline {timeline_id}, lsn {lsn}")
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/truncate",
+            params={"lsn": str(lsn)},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def timeline_compact(self, tenant_id: TenantId, timeline_id: TimelineId):
         self.is_testing_enabled_or_skip()
 
@@ -428,6 +502,67 @@ class PageserverHttpClient(requests.Session):
         res_json = res.json()
         assert res_json is None
 
+    def timeline_wait_lsn(
+        self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        lsn: Lsn,
+        timeout_ms: Optional[int] = None,
+    ):
+        params: Dict[str, Any] = {"lsn": str(lsn)}
+        if timeout_ms is not None:
+            params["timeout_ms"] = timeout_ms
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/wait_lsn",
+            params=params,
+        )
+        self.verbose_error(res)
+
+    def timeline_changed_pages(
+        self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        from_lsn: Lsn,
+        to_lsn: Lsn,
+        after: Optional[str] = None,
+        limit: Optional[int] = None,
+        format: Optional[str] = None,
+    ) -> Dict[str, Any]:
+        params: Dict[str, Any] = {"from_lsn": str(from_lsn), "to_lsn": str(to_lsn)}
+        if after is not None:
+            params["after"] = after
+        if limit is not None:
+            params["limit"] = limit
+        if format is not None:
+            params["format"] = format
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/changed_pages",
+            params=params,
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_export(self, tenant_id: TenantId, timeline_id: TimelineId) -> bytes:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/export"
+        )
+        self.verbose_error(res)
+        return res.content
+
+    def timeline_import(
+        self, tenant_id: TenantId, timeline_id: TimelineId, archive: bytes
+    ) -> Dict[Any, Any]:
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/import",
+            data=archive,
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def timeline_spawn_download_remote_layers(
         self,
         tenant_id: TenantId,
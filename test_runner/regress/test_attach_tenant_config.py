@@ -165,13 +165,18 @@ def test_fully_custom_config(positive_env: NeonEnv):
         "gc_feedback": True,
         "gc_horizon": 23 * (1024 * 1024),
         "gc_period": "2h 13m",
+        "hot_range_image_creation_threshold": 23,
         "image_creation_threshold": 7,
         "pitr_interval": "1m",
         "lagging_wal_timeout": "23m",
+        "max_concurrent_getpage_requests": 23,
         "max_lsn_wal_lag": 230000,
+        "max_regions": 23,
         "min_resident_size_override": 23,
+        "physical_size_quota": 23 * (1024 * 1024 * 1024),
         "trace_read_requests": True,
         "walreceiver_connect_timeout": "13m",
+        "wal_ingest_rate_limit": 23 * (1024 * 1024),
     }
 
     ps_http = env.pageserver.http_client()
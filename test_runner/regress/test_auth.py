@@ -63,6 +63,70 @@ def test_pageserver_auth(neon_env_builder: NeonEnvBuilder):
         tenant_http_client.tenant_create(TenantId.generate())
 
 
+def test_pageserver_http_api_scopes(neon_env_builder: NeonEnvBuilder):
+    """
+    Every route of the pageserver HTTP management API checks the scope of the token:
+    a tenant token only gives access to the routes of its own tenant, and the routes
+    of the whole pageserver need a pageserverapi token.
+    """
+    neon_env_builder.auth_enabled = True
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(
+        ".*Error processing HTTP request: Unauthorized: missing authorization header.*"
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    tenant_client = env.pageserver.http_client(env.auth_keys.generate_tenant_token(tenant_id))
+    other_tenant_client = env.pageserver.http_client(
+        env.auth_keys.generate_tenant_token(TenantId.generate())
+    )
+    pageserver_client = env.pageserver.http_client(env.auth_keys.generate_pageserver_token())
+    anonymous_client = env.pageserver.http_client()
+
+    tenant_routes = [
+        lambda client: client.tenant_status(tenant_id),
+        lambda client: client.tenant_config(tenant_id),
+        lambda client: client.tenant_size(tenant_id),
+        lambda client: client.timeline_list(tenant_id),
+        lambda client: client.timeline_detail(tenant_id, timeline_id),
+        lambda client: client.layer_map_info(tenant_id, timeline_id),
+        lambda client: client.timeline_checkpoint(tenant_id, timeline_id),
+    ]
+    for route in tenant_routes:
+        route(tenant_client)
+        route(pageserver_client)
+        with pytest.raises(PageserverApiException, match="Tenant id mismatch") as e:
+            route(other_tenant_client)
+        assert e.value.status_code == 403
+        with pytest.raises(PageserverApiException) as e:
+            route(anonymous_client)
+        assert e.value.status_code == 401
+
+    pageserver_routes = [
+        lambda client: client.tenant_list(),
+        lambda client: client.page_cache_info(),
+        lambda client: client.post_tracing_event("info", "test_pageserver_http_api_scopes"),
+    ]
+    for route in pageserver_routes:
+        route(pageserver_client)
+        with pytest.raises(
+            PageserverApiException, match="Attempt to access management api with tenant scope"
+        ) as e:
+            route(tenant_client)
+        assert e.value.status_code == 403
+        with pytest.raises(PageserverApiException) as e:
+            route(anonymous_client)
+        assert e.value.status_code == 401
+
+    # The status endpoint is open to everyone.
+    anonymous_client.check_status()
+
+    # A tenant can't break another tenant either.
+    with pytest.raises(PageserverApiException, match="Tenant id mismatch"):
+        other_tenant_client.tenant_break(tenant_id)
+
+
 def test_compute_auth_to_pageserver(neon_env_builder: NeonEnvBuilder):
     neon_env_builder.auth_enabled = True
     neon_env_builder.num_safekeepers = 3
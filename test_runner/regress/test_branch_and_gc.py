@@ -183,3 +183,73 @@ def test_branch_creation_before_gc(neon_simple_env: NeonEnv):
         pageserver_http_client.timeline_create(env.pg_version, tenant, new_timeline_id, b0, lsn)
 
     thread.join()
+
+
+# Test that GC keeps the layers a chain of branches reads through its ancestors,
+# and that the GC explanation of the pageserver says so, layer by layer.
+def test_gc_explanation_with_branches(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    pageserver_http_client = env.pageserver.http_client()
+
+    tenant, _ = env.neon_cli.create_tenant(
+        conf={
+            # disable background GC and compaction, so that the layers don't
+            # change between the explanation and the GC
+            "gc_period": "0s",
+            "compaction_period": "0s",
+            "checkpoint_distance": f"{1024 ** 2}",
+            "compaction_target_size": f"{1024 ** 3}",
+            "compaction_threshold": "2",
+            "image_creation_threshold": "1",
+            "gc_horizon": f"{1024 ** 2}",
+            "pitr_interval": "0 s",
+        }
+    )
+
+    main = env.neon_cli.create_timeline("main", tenant_id=tenant)
+    endpoint_main = env.endpoints.create_start("main", tenant_id=tenant)
+    main_cur = endpoint_main.connect().cursor()
+    main_cur.execute("CREATE TABLE foo(key serial primary key, t text default 'foooooooooooooooo')")
+    main_cur.execute("INSERT INTO foo SELECT FROM generate_series(1, 100000)")
+    lsn1 = Lsn(query_scalar(main_cur, "SELECT pg_current_wal_insert_lsn()"))
+
+    child = env.neon_cli.create_branch("child", "main", tenant_id=tenant, ancestor_start_lsn=lsn1)
+    endpoint_child = env.endpoints.create_start("child", tenant_id=tenant)
+    child_cur = endpoint_child.connect().cursor()
+    child_cur.execute("INSERT INTO foo SELECT FROM generate_series(1, 100000)")
+    lsn2 = Lsn(query_scalar(child_cur, "SELECT pg_current_wal_insert_lsn()"))
+    env.neon_cli.create_branch("grandchild", "child", tenant_id=tenant, ancestor_start_lsn=lsn2)
+
+    # Rewrite the table on main and on the child, so that newer image layers
+    # cover the layers at the branch points.
+    for cur in [main_cur, child_cur]:
+        cur.execute("UPDATE foo SET t = 'bar'")
+        cur.execute("VACUUM foo")
+    endpoint_main.stop()
+    endpoint_child.stop()
+    for timeline in [main, child]:
+        pageserver_http_client.timeline_checkpoint(tenant, timeline)
+        pageserver_http_client.timeline_compact(tenant, timeline)
+
+    explanation = pageserver_http_client.timeline_gc_explanation(tenant, main)
+    log.info(f"GC explanation of main: {explanation}")
+    assert [bp["lsn"] for bp in explanation["branch_points"]] == [str(lsn1)]
+    assert explanation["branch_points"][0]["timeline_id"] == str(child)
+    needed_by_branch = set()
+    for layer in explanation["layers"]:
+        verdict = layer["verdict"]
+        if verdict["kind"] == "needed_by_branch":
+            assert verdict["branch_lsn"] == str(lsn1)
+            needed_by_branch.add(layer["layer_file_name"])
+        if Lsn(layer["lsn_start"]) <= lsn1:
+            # The layer may be kept for other reasons first, but it is kept.
+            assert verdict["kind"] != "removable", layer
+    assert len(needed_by_branch) > 0
+
+    pageserver_http_client.timeline_gc(tenant, main, None)
+    layers = pageserver_http_client.timeline_gc_explanation(tenant, main)["layers"]
+    assert needed_by_branch <= {layer["layer_file_name"] for layer in layers}
+
+    # The chain of branches still reads the data at its branch points.
+    endpoint_grandchild = env.endpoints.create_start("grandchild", tenant_id=tenant)
+    assert endpoint_grandchild.safe_psql("SELECT count(*) FROM foo WHERE t <> 'bar'") == [(200000,)]
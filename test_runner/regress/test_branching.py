@@ -6,7 +6,8 @@ from typing import List
 import pytest
 from fixtures.log_helper import log
 from fixtures.neon_fixtures import Endpoint, NeonEnv, PgBin
-from fixtures.types import Lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.types import Lsn, TimelineId
 from fixtures.utils import query_scalar
 from performance.test_perf_pgbench import get_scales_matrix
 
@@ -128,3 +129,35 @@ def test_branching_unnormalized_start_lsn(neon_simple_env: NeonEnv, pg_bin: PgBi
     endpoint1 = env.endpoints.create_start("b1")
 
     pg_bin.run_capture(["pgbench", "-i", endpoint1.connstr()])
+
+
+# Test that a branch creation failing midway leaves nothing behind, neither in
+# memory nor on disk, and can be retried.
+def test_branching_failpoint(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.pageserver.is_testing_enabled_or_skip()
+    env.pageserver.allowed_errors.append(".*failpoint branch-timeline-before-finish.*")
+    pageserver_http = env.pageserver.http_client()
+
+    tenant_id = env.initial_tenant
+    ancestor_timeline_id = env.neon_cli.create_branch("test_branching_failpoint")
+    new_timeline_id = TimelineId.generate()
+
+    pageserver_http.configure_failpoints(("branch-timeline-before-finish", "return"))
+    with pytest.raises(PageserverApiException, match="failpoint branch-timeline-before-finish"):
+        pageserver_http.timeline_create(
+            env.pg_version, tenant_id, new_timeline_id, ancestor_timeline_id=ancestor_timeline_id
+        )
+    timelines = [TimelineId(t["timeline_id"]) for t in pageserver_http.timeline_list(tenant_id)]
+    assert new_timeline_id not in timelines
+
+    pageserver_http.configure_failpoints(("branch-timeline-before-finish", "off"))
+    pageserver_http.timeline_create(
+        env.pg_version, tenant_id, new_timeline_id, ancestor_timeline_id=ancestor_timeline_id
+    )
+
+    # The branch survives a restart.
+    env.pageserver.stop()
+    env.pageserver.start()
+    timelines = [TimelineId(t["timeline_id"]) for t in pageserver_http.timeline_list(tenant_id)]
+    assert new_timeline_id in timelines
@@ -0,0 +1,56 @@
+import tarfile
+from pathlib import Path
+
+from fixtures.neon_fixtures import NeonEnvBuilder, PgBin
+from fixtures.types import Lsn
+from fixtures.utils import query_scalar
+
+
+def test_catalog_basebackup(neon_env_builder: NeonEnvBuilder, pg_bin: PgBin, pg_distrib_dir: Path):
+    """
+    Check that a basebackup taken with --catalog includes the system catalogs
+    but not the user tables, and that a compute starts from it, reads the user
+    tables through getPage, and keeps its catalog changes across restarts.
+    """
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    with endpoint.cursor() as cur:
+        cur.execute("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 1000) g")
+        dboid = query_scalar(cur, "SELECT oid FROM pg_database WHERE datname = current_database()")
+        relfilenode = query_scalar(cur, "SELECT relfilenode FROM pg_class WHERE relname = 't'")
+        lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+
+    psql_env = {"LD_LIBRARY_PATH": str(pg_distrib_dir / "lib")}
+
+    def basebackup_files(flags: str):
+        query = f"basebackup {tenant_id} {timeline_id} {lsn} {flags}".strip()
+        cmd = ["psql", "--no-psqlrc", env.pageserver.connstr(), "-c", query]
+        result_basepath = pg_bin.run_capture(cmd, env=psql_env)
+        with tarfile.open(result_basepath + ".stdout") as tar:
+            return set(tar.getnames())
+
+    # pg_class is only in the catalog basebackup, the user table in neither.
+    pg_class = f"base/{dboid}/1259"
+    user_table = f"base/{dboid}/{relfilenode}"
+    files = basebackup_files("")
+    assert pg_class not in files
+    assert user_table not in files
+    files = basebackup_files("--catalog")
+    assert pg_class in files
+    assert user_table not in files
+
+    endpoint.stop()
+    endpoint.respec(catalog_basebackup=True)
+    endpoint.start()
+    with endpoint.cursor() as cur:
+        assert query_scalar(cur, "SHOW neon.catalog_basebackup") == "on"
+        assert query_scalar(cur, "SELECT sum(x) FROM t") == 500500
+        cur.execute("CREATE TABLE u AS SELECT g AS y FROM generate_series(1, 10) g")
+
+    # The catalog changes went to the pageserver as well.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM u") == [(10,)]
@@ -0,0 +1,57 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+from fixtures.utils import query_scalar
+
+
+def test_changed_pages(neon_env_builder: NeonEnvBuilder):
+    """
+    Page through the relation blocks changed between two LSNs, and check that
+    the blocks of the updated table are there, with their images or changes.
+    """
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    with endpoint.cursor() as cur:
+        cur.execute("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 10000) g")
+        relfilenode = query_scalar(cur, "SELECT relfilenode FROM pg_class WHERE relname = 't'")
+        num_blocks = query_scalar(cur, "SELECT pg_relation_size('t') / 8192")
+        from_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+        cur.execute("UPDATE t SET x = -x")
+        to_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+    wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, to_lsn)
+
+    pages = []
+    after = None
+    while True:
+        res = pageserver_http.timeline_changed_pages(
+            tenant_id, timeline_id, from_lsn, to_lsn, after=after, limit=10
+        )
+        assert len(res["pages"]) <= 10
+        pages.extend(res["pages"])
+        after = res["next_cursor"]
+        if after is None:
+            break
+
+    # The update rewrote all the blocks of the table, and added as many.
+    table_blocks = [page["blkno"] for page in pages if page["rel"]["relnode"] == relfilenode]
+    assert table_blocks == sorted(table_blocks)
+    assert len(table_blocks) >= num_blocks
+    assert all(len(page["image"]) == 2 * 8192 for page in pages)
+
+    res = pageserver_http.timeline_changed_pages(
+        tenant_id, timeline_id, from_lsn, to_lsn, limit=1, format="delta"
+    )
+    deltas = res["pages"][0]["deltas"]
+    assert len(deltas) > 0
+    assert all(from_lsn < Lsn(delta["lsn"]) <= to_lsn for delta in deltas)
+    assert all("image" in delta or "wal_record" in delta for delta in deltas)
+
+    with pytest.raises(PageserverApiException) as e:
+        pageserver_http.timeline_changed_pages(tenant_id, timeline_id, to_lsn, from_lsn)
+    assert e.value.status_code == 400
@@ -0,0 +1,59 @@
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv, fork_at_current_lsn
+
+
+#
+# Test that the commit timestamps survive branching, with track_commit_timestamp
+# on. The pageserver maintains pg_commit_ts from the commit records, and ships
+# it in the basebackup of the new branch.
+#
+def test_commit_ts(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.neon_cli.create_branch("test_commit_ts", "empty")
+    config_lines = ["track_commit_timestamp=on"]
+    endpoint = env.endpoints.create_start("test_commit_ts", config_lines=config_lines)
+
+    cur = endpoint.connect().cursor()
+    cur.execute("CREATE TABLE foo (i int)")
+
+    # Commit enough transactions to span several pages of pg_commit_ts, which
+    # hold 819 timestamps each, some of them with a subtransaction.
+    cur.execute(
+        """
+        DO $$
+        BEGIN
+            FOR i IN 1..2000 LOOP
+                INSERT INTO foo VALUES (i);
+                IF i % 10 = 0 THEN
+                    BEGIN
+                        INSERT INTO foo VALUES (-i);
+                    EXCEPTION WHEN others THEN
+                        NULL;
+                    END;
+                END IF;
+                COMMIT;
+            END LOOP;
+        END
+        $$
+        """
+    )
+
+    query = "SELECT i, pg_xact_commit_timestamp(xmin) FROM foo ORDER BY i"
+    cur.execute(query)
+    timestamps = cur.fetchall()
+    assert len(timestamps) == 2200
+    assert all(ts is not None for (_, ts) in timestamps)
+    log.info(f"commit timestamps from {timestamps[0][1]} to {timestamps[-1][1]}")
+
+    fork_at_current_lsn(env, endpoint, "test_commit_ts_new", "test_commit_ts")
+    endpoint_new = env.endpoints.create_start("test_commit_ts_new", config_lines=config_lines)
+
+    cur_new = endpoint_new.connect().cursor()
+    cur_new.execute(query)
+    assert cur_new.fetchall() == timestamps
+
+    # The new branch keeps tracking them
+    cur_new.execute("INSERT INTO foo VALUES (0)")
+    cur_new.execute("SELECT pg_xact_commit_timestamp(xmin) FROM foo WHERE i = 0")
+    row = cur_new.fetchone()
+    assert row is not None and row[0] is not None
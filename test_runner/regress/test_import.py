@@ -5,6 +5,7 @@ import shutil
 import tarfile
 from contextlib import closing
 from pathlib import Path
+from typing import List, Tuple
 
 import pytest
 from fixtures.log_helper import log
@@ -13,12 +14,15 @@ from fixtures.neon_fixtures import (
     NeonEnv,
     NeonEnvBuilder,
     PgBin,
+    VanillaPostgres,
+    wait_for_last_flush_lsn,
 )
 from fixtures.pageserver.utils import (
     timeline_delete_wait_completed,
     wait_for_last_record_lsn,
     wait_for_upload,
 )
+from fixtures.port_distributor import PortDistributor
 from fixtures.types import Lsn, TenantId, TimelineId
 from fixtures.utils import subprocess_capture
 
@@ -96,12 +100,11 @@ def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_build
             ".*InternalServerError.*Timeline .* not found.*",
             ".*InternalServerError.*Cannot delete timeline which has child timelines.*",
             ".*ignored .* unexpected bytes after the tar archive.*",
+            ".*Error processing HTTP request: InternalServerError\\(Failed to import basebackup.*",
+            ".*Error processing HTTP request: Bad request: .*tar EOF marker.*",
         ]
     )
 
-    # FIXME: we should clean up pageserver to not print this
-    env.pageserver.allowed_errors.append(".*exited with error: unexpected message type: CopyData.*")
-
     # FIXME: Is this expected?
     env.pageserver.allowed_errors.append(
         ".*init_tenant_mgr: marking .* as locally complete, while it doesnt exist in remote index.*"
@@ -162,6 +165,125 @@ def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_build
     assert endpoint.safe_psql("select count(*) from t") == [(300000,)]
 
 
+def test_import_wal_segsize(
+    test_output_dir: Path,
+    pg_bin: PgBin,
+    port_distributor: PortDistributor,
+    neon_env_builder: NeonEnvBuilder,
+):
+    """
+    Import a cluster initialized with 1MB WAL segments instead of the default
+    16MB, and check that the WAL of the endpoint on it is streamed through the
+    safekeepers and ingested by the pageserver across segment boundaries.
+    """
+    env = neon_env_builder.init_start()
+    endpoint, tenant, timeline = _import_vanilla_cluster(
+        env, test_output_dir, pg_bin, port_distributor, ["--wal-segsize=1"]
+    )
+    assert endpoint.safe_psql("show wal_segment_size") == [("1MB",)]
+    # Write a few segments worth of WAL
+    endpoint.safe_psql("insert into t select g from generate_series(10001,100000) g")
+    wait_for_last_flush_lsn(env, endpoint, tenant, timeline)
+
+    # Read it back from the pageserver
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("select count(*), sum(g) from t") == [(100000, 5000050000)]
+
+
+def test_import_data_checksums(
+    test_output_dir: Path,
+    pg_bin: PgBin,
+    port_distributor: PortDistributor,
+    neon_env_builder: NeonEnvBuilder,
+):
+    """
+    Import a cluster initialized with data checksums, and check that the pages
+    served to the endpoint on it, including the ones reconstructed by WAL redo,
+    pass the checksum verification of the compute.
+    """
+    env = neon_env_builder.init_start()
+    endpoint, tenant, timeline = _import_vanilla_cluster(
+        env, test_output_dir, pg_bin, port_distributor, ["--data-checksums"]
+    )
+    assert endpoint.safe_psql("show data_checksums") == [("on",)]
+    endpoint.safe_psql("update t set g = g + 1 where g % 2 = 0")
+    wait_for_last_flush_lsn(env, endpoint, tenant, timeline)
+
+    # Read the pages back from the pageserver, after WAL redo
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("select count(*), sum(g) from t") == [(10000, 50010000)]
+
+
+def _import_vanilla_cluster(
+    env: NeonEnv,
+    test_output_dir: Path,
+    pg_bin: PgBin,
+    port_distributor: PortDistributor,
+    initdb_args: List[str],
+) -> Tuple[Endpoint, TenantId, TimelineId]:
+    """
+    Initialize a vanilla cluster with `initdb_args`, with a table `t` of the
+    numbers 1 to 10000, import a base backup of it into a new timeline of the
+    initial tenant, and start an endpoint on the timeline.
+    """
+    pgdatadir = test_output_dir / "pgdata-vanilla"
+    pg_bin.run_capture(["initdb", "-D", str(pgdatadir), *initdb_args])
+    with VanillaPostgres(pgdatadir, pg_bin, port_distributor.get_port(), init=False) as vanilla_pg:
+        vanilla_pg.start()
+        vanilla_pg.safe_psql("create user cloud_admin with password 'postgres' superuser")
+        vanilla_pg.safe_psql("create table t as select g from generate_series(1,10000) g")
+        vanilla_pg.safe_psql("CHECKPOINT")
+
+        basebackup_dir = test_output_dir / "basebackup"
+        pg_bin.run(
+            [
+                "pg_basebackup",
+                "-F",
+                "tar",
+                "-d",
+                vanilla_pg.connstr(),
+                "-D",
+                str(basebackup_dir),
+            ]
+        )
+
+    with open(basebackup_dir / "backup_manifest") as f:
+        manifest = json.load(f)
+        start_lsn = manifest["WAL-Ranges"][0]["Start-LSN"]
+        end_lsn = manifest["WAL-Ranges"][0]["End-LSN"]
+
+    tenant = env.initial_tenant
+    timeline = TimelineId.generate()
+    endpoint_id = "ep-import_vanilla"
+    env.neon_cli.raw_cli(
+        [
+            "timeline",
+            "import",
+            "--tenant-id",
+            str(tenant),
+            "--timeline-id",
+            str(timeline),
+            "--node-name",
+            endpoint_id,
+            "--base-lsn",
+            start_lsn,
+            "--base-tarfile",
+            str(basebackup_dir / "base.tar"),
+            "--end-lsn",
+            end_lsn,
+            "--wal-tarfile",
+            str(basebackup_dir / "pg_wal.tar"),
+            "--pg-version",
+            env.pg_version,
+        ]
+    )
+
+    endpoint = env.endpoints.create_start(endpoint_id, tenant_id=tenant)
+    return endpoint, tenant, timeline
+
+
 def test_import_from_pageserver_small(pg_bin: PgBin, neon_env_builder: NeonEnvBuilder):
     neon_env_builder.enable_local_fs_remote_storage()
     env = neon_env_builder.init_start()
@@ -67,7 +67,7 @@ def helper_compare_tenant_list(pageserver_http_client: PageserverHttpClient, env
     tenants_api = sorted(map(lambda t: cast(str, t["id"]), tenants))
 
     res = env.neon_cli.list_tenants()
-    tenants_cli = sorted(map(lambda t: t.split()[0], res.stdout.splitlines()))
+    tenants_cli = sorted(map(lambda t: t.split()[0], res.stdout.splitlines()[1:]))
 
     assert tenants_api == tenants_cli
 
@@ -91,7 +91,7 @@ def test_cli_tenant_list(neon_simple_env: NeonEnv):
     helper_compare_tenant_list(pageserver_http_client, env)
 
     res = env.neon_cli.list_tenants()
-    tenants = sorted(map(lambda t: TenantId(t.split()[0]), res.stdout.splitlines()))
+    tenants = sorted(map(lambda t: TenantId(t.split()[0]), res.stdout.splitlines()[1:]))
 
     assert env.initial_tenant in tenants
     assert tenant1 in tenants
@@ -200,3 +200,28 @@ def test_binaries_version_parses(neon_binpath: Path):
     for bin in binaries:
         out = subprocess.check_output([neon_binpath / bin, "--version"]).decode("utf-8")
         parse_project_git_version_output(out)
+
+
+def test_cli_safekeeper_groups(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    neon_env_builder.safekeepers_groups = {1: "a", 2: "a", 3: "b"}
+    env = neon_env_builder.init_start()
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+
+    # Two of the three safekeepers are left, the endpoint keeps its quorum.
+    res = env.neon_cli.safekeeper_stop(group="b")
+    assert "WARNING" not in res.stderr
+    endpoint.safe_psql("INSERT INTO t VALUES (1, 'payload')")
+    env.neon_cli.safekeeper_start(group="b")
+
+    # One of the three safekeepers is left, the endpoint can't commit.
+    res = env.neon_cli.safekeeper_stop(group="a")
+    assert f"endpoint {endpoint.endpoint_id} is left with 1 of its safekeepers" in res.stderr
+    env.neon_cli.safekeeper_start(group="a")
+    endpoint.safe_psql("INSERT INTO t VALUES (2, 'payload')")
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 2
+
+    with pytest.raises(RuntimeError, match="safekeeper group 'c' is not defined"):
+        env.neon_cli.safekeeper_start(group="c")
@@ -0,0 +1,41 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+
+
+def test_page_cache_resize_and_stats(neon_env_builder: NeonEnvBuilder):
+    """
+    Resize the page cache through the management API, and check that the
+    lookups of the pages of a tenant show up in its page cache statistics.
+    """
+    neon_env_builder.pageserver_config_override = "page_cache_size=128;max_page_cache_size=1024"
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    info = pageserver_http.page_cache_info()
+    assert info["size"] == 128
+    assert info["max_size"] == 1024
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 100000) g")
+    # Read the table back from the pageserver.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(100000,)]
+
+    stats = pageserver_http.tenant_page_cache_stats(tenant_id)
+    assert stats["read_hits"] + stats["read_misses"] > 0
+
+    evictions = pageserver_http.page_cache_info()["stats"]["evictions"]
+    info = pageserver_http.page_cache_resize(16)
+    assert info["size"] == 16
+    assert info["stats"]["evictions"] >= evictions
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(100000,)]
+
+    assert pageserver_http.page_cache_resize(1024)["size"] == 1024
+    for size in [0, 1025]:
+        with pytest.raises(PageserverApiException) as e:
+            pageserver_http.page_cache_resize(size)
+        assert e.value.status_code == 400
+    assert pageserver_http.page_cache_info()["size"] == 1024
@@ -1,3 +1,4 @@
+import json
 import subprocess
 from pathlib import Path
 from typing import Optional
@@ -190,3 +191,36 @@ def test_pageserver_http_api_client_auth_enabled(neon_env_builder: NeonEnvBuilde
 
     with env.pageserver.http_client(auth_token=pageserver_token) as client:
         check_client(env.pg_version, client, env.initial_tenant)
+
+
+def test_pageserver_http_progress_frames(neon_simple_env: NeonEnv):
+    """
+    Create a timeline with progress=true, and check that the response is a
+    stream of progress frames that ends with the status and body of the
+    creation.
+    """
+    env = neon_simple_env
+    tenant_id = env.initial_tenant
+    client = env.pageserver.http_client()
+
+    def create_with_progress(timeline_id: TimelineId):
+        res = client.post(
+            f"http://localhost:{client.port}/v1/tenant/{tenant_id}/timeline",
+            params={"progress": "true"},
+            json={"new_timeline_id": str(timeline_id), "pg_version": int(env.pg_version)},
+        )
+        assert res.status_code == 200
+        assert res.headers["Content-Type"] == "application/x-ndjson"
+        frames = [json.loads(line) for line in res.text.splitlines()]
+        assert all(frame["type"] == "progress" for frame in frames[:-1])
+        assert frames[-1]["type"] == "done"
+        return frames[-1]
+
+    timeline_id = TimelineId.generate()
+    done = create_with_progress(timeline_id)
+    assert done["status"] == 201
+    assert done["body"]["timeline_id"] == str(timeline_id)
+    assert client.timeline_detail(tenant_id, timeline_id)["timeline_id"] == str(timeline_id)
+
+    # Errors come in the last frame too.
+    assert create_with_progress(timeline_id)["status"] == 409
@@ -1,8 +1,10 @@
+import json
 from contextlib import closing
 
 import pytest
 from fixtures.log_helper import log
-from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.types import Lsn
 
 
 # Test restarting page server, while safekeeper and compute node keep
@@ -160,3 +162,33 @@ def test_pageserver_chaos(neon_env_builder: NeonEnvBuilder):
         # Check that all the updates are visible
         num_updates = endpoint.safe_psql("SELECT sum(updates) FROM foo")[0][0]
         assert num_updates == i * 100000
+
+
+# Test that after a graceful restart, the page server reconnects to the
+# safekeeper it streamed the WAL from, without waiting for the broker.
+def test_pageserver_restart_reconnects_to_safekeeper(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE foo AS SELECT g AS x FROM generate_series(1, 1000) g")
+    last_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    env.pageserver.stop()
+    state_path = env.timeline_dir(tenant_id, timeline_id) / "wal_receiver_state"
+    with open(state_path) as f:
+        state = json.load(f)
+    log.info(f"saved WAL receiver state: {state}")
+    assert state["safekeeper_id"] in [sk.id for sk in env.safekeepers]
+    assert Lsn(state["last_record_lsn"]) >= last_lsn
+
+    # Without the broker, the WAL can only be streamed from the saved safekeeper.
+    env.broker.stop()
+    env.pageserver.start()
+    endpoint.safe_psql("INSERT INTO foo SELECT g FROM generate_series(1, 1000) g")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    assert not state_path.exists()
+
+    env.broker.try_start()
+    assert endpoint.safe_psql("SELECT count(*) FROM foo") == [(2000,)]
@@ -0,0 +1,31 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+
+
+def test_parallel_ingest(neon_env_builder: NeonEnvBuilder):
+    """
+    Bulk load a table with the WAL applied by several ingest workers, and check
+    that the pages read back from the pageserver have all the rows, before and
+    after the in-memory layers are flushed to disk.
+    """
+    neon_env_builder.pageserver_config_override = "ingest_parallelism=4;ingest_batch_size=1000"
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 200000) g")
+    endpoint.safe_psql("UPDATE t SET x = -x WHERE x % 3 = 0")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    expected_sum = sum(range(1, 200001)) - 2 * sum(range(3, 200001, 3))
+
+    def check_table():
+        # Read the table back from the pageserver.
+        endpoint.stop()
+        endpoint.start()
+        assert endpoint.safe_psql("SELECT count(*), sum(x) FROM t") == [(200000, expected_sum)]
+
+    check_table()
+    pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+    check_table()
@@ -0,0 +1,34 @@
+import time
+
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.utils import last_record_lsn, wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+def test_physical_size_quota(neon_env_builder: NeonEnvBuilder):
+    """
+    Set a physical size quota below the size of the tenant's layer files, and
+    check that WAL ingestion stalls, then that it resumes once the quota is
+    raised above it.
+    """
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*over its physical size quota.*")
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    # The layer files of initdb alone are larger than this.
+    pageserver_http.set_tenant_config(tenant_id, {"physical_size_quota": 1})
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 1000) g")
+    current_lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+
+    stalled_lsn = last_record_lsn(pageserver_http, tenant_id, timeline_id)
+    assert stalled_lsn < current_lsn
+    time.sleep(3)
+    assert last_record_lsn(pageserver_http, tenant_id, timeline_id) == stalled_lsn
+
+    pageserver_http.set_tenant_config(tenant_id, {"physical_size_quota": 1024 * 1024 * 1024})
+    wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, current_lsn)
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(1000,)]
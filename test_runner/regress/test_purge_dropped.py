@@ -0,0 +1,77 @@
+import pytest
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+def test_purge_dropped_relation(neon_env_builder: NeonEnvBuilder):
+    """
+    Drop a large table, purge it from the timeline, and check that the next
+    compaction reclaims its space, well within the PITR interval, while the
+    rest of the database stays readable.
+    """
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+
+    tenant_id, timeline_id = env.neon_cli.create_tenant(
+        conf={
+            # Compaction and GC only run when the test asks for them.
+            "compaction_period": "0s",
+            "gc_period": "0s",
+            "pitr_interval": "7 days",
+        }
+    )
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+
+    endpoint.safe_psql("CREATE TABLE kept AS SELECT g AS x FROM generate_series(1, 1000) g")
+    endpoint.safe_psql(
+        "CREATE TABLE dropped AS SELECT g AS x, repeat('x', 100) AS y FROM generate_series(1, 100000) g"
+    )
+    spcnode, dbnode, relnode, table_size = endpoint.safe_psql(
+        """
+        SELECT dattablespace, oid, pg_relation_filenode('dropped'), pg_relation_size('dropped')
+        FROM pg_database WHERE datname = current_database()
+        """
+    )[0]
+
+    def checkpoint():
+        lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+        wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, lsn)
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+        return lsn
+
+    def physical_size():
+        detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+        return detail["current_physical_size"]
+
+    lsn_before_drop = checkpoint()
+    # The table isn't dropped yet.
+    with pytest.raises(PageserverApiException, match="exists at the last record LSN"):
+        pageserver_http.timeline_purge_dropped(tenant_id, timeline_id, spcnode, dbnode, relnode)
+
+    endpoint.safe_psql("DROP TABLE dropped")
+    checkpoint()
+    size_before = physical_size()
+
+    purge_lsn = pageserver_http.timeline_purge_dropped(
+        tenant_id, timeline_id, spcnode, dbnode, relnode
+    )
+    log.info(f"purging relation {relnode} before {purge_lsn}")
+    pageserver_http.timeline_compact(tenant_id, timeline_id)
+    size_after = physical_size()
+    log.info(f"physical size before purge: {size_before}, after: {size_after}")
+    assert size_after < size_before - table_size // 2
+
+    # The history before the purge is gone for good, even after a restart.
+    env.pageserver.stop()
+    env.pageserver.start()
+    with pytest.raises(Exception, match="purge floor"):
+        env.neon_cli.create_branch(
+            "before_purge", "main", tenant_id=tenant_id, ancestor_start_lsn=lsn_before_drop
+        )
+
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM kept") == [(1000,)]
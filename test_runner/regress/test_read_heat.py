@@ -0,0 +1,60 @@
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+def test_hot_range_image_creation(neon_env_builder: NeonEnvBuilder):
+    """
+    Read a table through WAL redo over and over, and check that compaction
+    creates image layers for it early, well before image_creation_threshold
+    delta layers have piled up over it.
+    """
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+
+    tenant_id, timeline_id = env.neon_cli.create_tenant(
+        conf={
+            # Compaction only runs when the test asks for it.
+            "compaction_period": "0s",
+            "image_creation_threshold": "100",
+            "hot_range_image_creation_threshold": "3",
+        }
+    )
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 10000) g")
+    endpoint.safe_psql("UPDATE t SET x = x + 1")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, lsn)
+    pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    images_before = pageserver_http.layer_map_info(tenant_id, timeline_id).kind_count()["Image"]
+
+    # Restart the compute before each read, so that its pages come from the
+    # pageserver every time.
+    for _ in range(5):
+        endpoint.stop()
+        endpoint.start()
+        assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(50015000,)]
+
+    metric_filter = {"tenant_id": str(tenant_id), "timeline_id": str(timeline_id)}
+    redo_reads = pageserver_http.get_metric_value(
+        "pageserver_read_heat_redo_reads_total", metric_filter
+    )
+    assert redo_reads is not None and redo_reads > 0
+
+    pageserver_http.timeline_compact(tenant_id, timeline_id)
+
+    created = pageserver_http.get_metric_value(
+        "pageserver_read_heat_image_layers_created_total", metric_filter
+    )
+    assert created is not None and created > 0
+    images_after = pageserver_http.layer_map_info(tenant_id, timeline_id).kind_count()["Image"]
+    log.info(f"image layers before: {images_before}, after: {images_after}")
+    assert images_after > images_before
+
+    # The image layers serve the table now, without WAL redo.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(50015000,)]
@@ -0,0 +1,42 @@
+from fixtures.neon_fixtures import NeonEnv
+
+
+def test_read_range(neon_simple_env: NeonEnv):
+    """
+    Read a table with neon.read_range_size set, so that cache misses fetch the
+    following blocks along in GetPages requests, and check that the pages are
+    read correctly, also after they were modified in between.
+    """
+    env = neon_simple_env
+    timeline_id = env.neon_cli.create_branch("test_read_range", "empty")
+    endpoint = env.endpoints.create_start(
+        "test_read_range", config_lines=["neon.read_range_size=32"]
+    )
+    pageserver_http = env.pageserver.http_client()
+
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 100000) g")
+
+    def get_pages_requests() -> float:
+        metrics = pageserver_http.get_metrics()
+        samples = metrics.query_all(
+            "pageserver_smgr_query_seconds_count",
+            {
+                "smgr_query_type": "get_pages_at_lsn",
+                "tenant_id": str(env.initial_tenant),
+                "timeline_id": str(timeline_id),
+            },
+        )
+        return sum(sample.value for sample in samples)
+
+    # Restart the compute, so that the pages come from the pageserver.
+    endpoint.stop()
+    endpoint.start()
+    before = get_pages_requests()
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(5000050000,)]
+    assert get_pages_requests() > before
+
+    # Modify half of the rows, and read them again through the ranges.
+    endpoint.safe_psql("UPDATE t SET x = x + 1 WHERE x % 400 < 200")
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(5000100000,)]
@@ -1,8 +1,10 @@
 import time
 from contextlib import closing
 
+import pytest
 from fixtures.log_helper import log
-from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
 
 
 #
@@ -54,3 +56,46 @@ def test_pageserver_recovery(neon_env_builder: NeonEnvBuilder):
         with conn.cursor() as cur:
             cur.execute("select count(*) from foo")
             assert cur.fetchone() == (100000,)
+
+
+#
+# Test that a failed layer flush is retried, and that the pageserver recovers
+# from a crash right after a layer flush.
+#
+def test_pageserver_layer_flush_failpoints(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    env.pageserver.is_testing_enabled_or_skip()
+    env.pageserver.allowed_errors.extend(
+        [
+            ".*failpoint before-flush-layer.*",
+            ".*Could not flush frozen layer.*",
+        ]
+    )
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_pageserver_layer_flush_failpoints", "main")
+    endpoint = env.endpoints.create_start("test_pageserver_layer_flush_failpoints")
+    endpoint.safe_psql("CREATE TABLE foo(x bigint)")
+    endpoint.safe_psql("INSERT INTO foo VALUES (generate_series(1,100000))")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    pageserver_http = env.pageserver.http_client()
+    pageserver_http.configure_failpoints(("before-flush-layer", "return"))
+    with pytest.raises(PageserverApiException, match="Could not flush frozen layer"):
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    # The frozen layer is flushed on the next attempt.
+    pageserver_http.configure_failpoints(("before-flush-layer", "off"))
+    pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    endpoint.safe_psql("UPDATE foo SET x = x + 1")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    pageserver_http.configure_failpoints(("after-flush-layer", "exit"))
+    with pytest.raises(Exception):
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    log.info("Wait before server restart")
+    env.pageserver.stop()
+    env.pageserver.start()
+
+    assert endpoint.safe_psql("SELECT count(*), sum(x) FROM foo") == [(100000, 5000150000)]
@@ -0,0 +1,51 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn, TimelineId
+
+
+def test_timeline_rel_sizes(neon_simple_env: NeonEnv):
+    """
+    Dump the relation sizes of a timeline, and check them against the ones
+    of the compute, now and at an earlier LSN.
+    """
+    env = neon_simple_env
+    env.neon_cli.create_branch("test_timeline_rel_sizes", "empty")
+    endpoint = env.endpoints.create_start("test_timeline_rel_sizes")
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = TimelineId(endpoint.safe_psql("show neon.timeline_id")[0][0])
+
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 1000) g")
+    lsn_small = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 100000) g")
+    dbnode, relnode, nblocks = endpoint.safe_psql(
+        """
+        SELECT oid, pg_relation_filenode('t'), pg_relation_size('t') / 8192
+        FROM pg_database WHERE datname = current_database()
+        """
+    )[0]
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, lsn)
+
+    def main_fork_of_t(rel_sizes):
+        [rel] = [
+            rel
+            for rel in rel_sizes["relations"]
+            if rel["dbnode"] == dbnode and rel["relnode"] == relnode and rel["forknum"] == 0
+        ]
+        return rel
+
+    rel = main_fork_of_t(pageserver_http.timeline_rel_sizes(tenant_id, timeline_id))
+    assert rel["nblocks"] == nblocks
+    # WAL ingestion keeps the relation size cache up to date.
+    assert rel["cached_lsn"] is not None
+
+    rel_small = main_fork_of_t(
+        pageserver_http.timeline_rel_sizes(tenant_id, timeline_id, lsn=lsn_small)
+    )
+    assert 0 < rel_small["nblocks"] < nblocks
+
+    with pytest.raises(PageserverApiException, match="past the last record lsn"):
+        pageserver_http.timeline_rel_sizes(tenant_id, timeline_id, lsn=Lsn(lsn.lsn_int * 2))
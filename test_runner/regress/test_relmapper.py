@@ -0,0 +1,47 @@
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.utils import query_scalar
+
+
+#
+# Test that rewriting the mapped catalogs, which are not in pg_class but in
+# the pg_filenode.map files, is reflected in the basebackups: of the endpoint
+# after a restart, and of a new branch.
+#
+def test_relmapper(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.neon_cli.create_branch("test_relmapper", "empty")
+    endpoint = env.endpoints.create_start("test_relmapper")
+
+    def filenodes(cur):
+        cur.execute(
+            """
+            SELECT relname, pg_relation_filenode(oid) FROM pg_class
+            WHERE relname IN ('pg_class', 'pg_attribute', 'pg_database')
+            ORDER BY relname
+            """
+        )
+        return cur.fetchall()
+
+    with endpoint.cursor() as cur:
+        before = filenodes(cur)
+        # pg_database is a shared catalog, in global/pg_filenode.map
+        cur.execute("VACUUM FULL pg_class")
+        cur.execute("VACUUM FULL pg_attribute")
+        cur.execute("VACUUM FULL pg_database")
+        after = filenodes(cur)
+        assert all(b[1] != a[1] for b, a in zip(before, after))
+
+        cur.execute("CREATE TABLE foo AS SELECT g FROM generate_series(1, 100) g")
+        lsn = query_scalar(cur, "SELECT pg_current_wal_insert_lsn()")
+
+    endpoint.stop()
+    endpoint.start()
+    env.neon_cli.create_branch("test_relmapper2", "test_relmapper", ancestor_start_lsn=lsn)
+    endpoint2 = env.endpoints.create_start("test_relmapper2")
+
+    for ep in (endpoint, endpoint2):
+        with ep.cursor() as cur:
+            assert filenodes(cur) == after
+            assert query_scalar(cur, "SELECT count(*) FROM foo") == 100
+            datnames = query_scalar(cur, "SELECT array_agg(datname) FROM pg_database")
+            assert "postgres" in datnames
@@ -0,0 +1,41 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+
+
+def test_tenant_migrate_restarts_running_endpoint(neon_env_builder: NeonEnvBuilder):
+    """
+    Move a tenant with a running endpoint to another pageserver with
+    `neon_local tenant migrate`, and check that the endpoint is running again,
+    against the other pageserver, with the data written before the move.
+    """
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start()
+    source, destination = env.pageservers
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 10000) g")
+
+    env.neon_cli.raw_cli(
+        [
+            "tenant",
+            "migrate",
+            "--tenant-id",
+            str(env.initial_tenant),
+            "--pageserver-id",
+            str(source.id),
+            "--to",
+            str(destination.id),
+        ]
+    )
+
+    tenant_ids = [tenant["id"] for tenant in destination.http_client().tenant_list()]
+    assert str(env.initial_tenant) in tenant_ids
+    tenant_ids = [tenant["id"] for tenant in source.http_client().tenant_list()]
+    assert str(env.initial_tenant) not in tenant_ids
+
+    # The endpoint was restarted on the same port, against the destination.
+    connstring = endpoint.safe_psql("SHOW neon.pageserver_connstring")[0][0]
+    assert connstring.endswith(f":{destination.service_port.pg}")
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(50005000,)]
+
+    endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(10001, 20000) g")
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(20000,)]
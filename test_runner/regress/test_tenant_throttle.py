@@ -0,0 +1,51 @@
+from concurrent.futures import ThreadPoolExecutor
+
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+
+
+def test_tenant_throttle(neon_env_builder: NeonEnvBuilder):
+    """
+    Limit the concurrent getpage requests and the WAL ingest rate of a tenant
+    through its config, and check that its WAL ingestion waits for the limit,
+    and that its pages are served all the same.
+    """
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    pageserver_http.set_tenant_config(
+        tenant_id,
+        {"max_concurrent_getpage_requests": 1, "wal_ingest_rate_limit": 1024 * 1024},
+    )
+
+    endpoint = env.endpoints.create_start("main")
+    # A few MB of WAL, more than the burst of one second worth of it.
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 100000) g")
+    # Read the table back from the pageserver, over several connections at once.
+    endpoint.stop()
+    endpoint.start()
+
+    def count(_):
+        return endpoint.safe_psql("SELECT count(*) FROM t")
+
+    with ThreadPoolExecutor(max_workers=4) as executor:
+        assert list(executor.map(count, range(4))) == [[(100000,)]] * 4
+
+    metric_filter = {"tenant_id": str(tenant_id)}
+    wal_ingest_wait = pageserver_http.get_metric_value(
+        "pageserver_tenant_throttled_wal_ingest_seconds_total", metric_filter
+    )
+    assert wal_ingest_wait is not None and wal_ingest_wait > 0
+    assert (
+        pageserver_http.get_metric_value(
+            "pageserver_tenant_throttled_getpage_requests_total", metric_filter
+        )
+        is not None
+    )
+
+    for config in [{"max_concurrent_getpage_requests": 0}, {"wal_ingest_rate_limit": 0}]:
+        with pytest.raises(PageserverApiException) as e:
+            pageserver_http.set_tenant_config(tenant_id, config)
+        assert e.value.status_code == 400
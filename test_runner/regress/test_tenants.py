@@ -29,7 +29,7 @@ from prometheus_client.samples import Sample
 def test_tenant_creation_fails(neon_simple_env: NeonEnv):
     tenants_dir = Path(neon_simple_env.repo_dir) / "tenants"
     initial_tenants = sorted(
-        map(lambda t: t.split()[0], neon_simple_env.neon_cli.list_tenants().stdout.splitlines())
+        map(lambda t: t.split()[0], neon_simple_env.neon_cli.list_tenants().stdout.splitlines()[1:])
     )
     initial_tenant_dirs = [d for d in tenants_dir.iterdir()]
 
@@ -46,7 +46,7 @@ def test_tenant_creation_fails(neon_simple_env: NeonEnv):
         _ = neon_simple_env.neon_cli.create_tenant()
 
     new_tenants = sorted(
-        map(lambda t: t.split()[0], neon_simple_env.neon_cli.list_tenants().stdout.splitlines())
+        map(lambda t: t.split()[0], neon_simple_env.neon_cli.list_tenants().stdout.splitlines()[1:])
     )
     assert initial_tenants == new_tenants, "should not create new tenants"
 
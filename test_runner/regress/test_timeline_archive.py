@@ -0,0 +1,69 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn, wait_for_upload
+from fixtures.types import Lsn, TimelineId
+
+
+def test_timeline_export_import(neon_env_builder: NeonEnvBuilder, test_output_dir):
+    """
+    Export a timeline into an archive and import it into another tenant, under
+    another timeline id, and check that a compute sees the same data.
+    """
+    neon_env_builder.enable_local_fs_remote_storage()
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 100000) g")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    endpoint.stop()
+
+    wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, lsn)
+    pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    archive_path = test_output_dir / "timeline.tar"
+    env.neon_cli.raw_cli(
+        [
+            "timeline",
+            "export",
+            "--tenant-id",
+            str(tenant_id),
+            "--timeline-id",
+            str(timeline_id),
+            "--file",
+            str(archive_path),
+        ]
+    )
+
+    new_tenant_id, _ = env.neon_cli.create_tenant()
+    new_timeline_id = TimelineId.generate()
+    env.neon_cli.raw_cli(
+        [
+            "timeline",
+            "import-archive",
+            "--tenant-id",
+            str(new_tenant_id),
+            "--timeline-id",
+            str(new_timeline_id),
+            "--branch-name",
+            "imported",
+            "--file",
+            str(archive_path),
+        ]
+    )
+
+    detail = pageserver_http.timeline_detail(new_tenant_id, new_timeline_id)
+    assert Lsn(detail["last_record_lsn"]) >= lsn
+    wait_for_upload(
+        pageserver_http, new_tenant_id, new_timeline_id, Lsn(detail["last_record_lsn"])
+    )
+
+    endpoint = env.endpoints.create_start("imported", tenant_id=new_tenant_id)
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(100000,)]
+
+    # The timeline exists now.
+    with pytest.raises(PageserverApiException, match="already exists"):
+        pageserver_http.timeline_import(new_tenant_id, new_timeline_id, archive_path.read_bytes())
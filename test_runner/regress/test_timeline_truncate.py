@@ -0,0 +1,84 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import timeline_delete_wait_completed
+from fixtures.types import Lsn, TimelineId
+
+
+def test_timeline_truncate(neon_env_builder: NeonEnvBuilder):
+    """
+    Truncate the history of a timeline before an LSN, within the PITR interval,
+    and check that the data at and after the LSN remains, while branching
+    before it no longer works.
+    """
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.extend(
+        [
+            ".*invalid branch start lsn.*",
+            ".*invalid start lsn .* for ancestor timeline.*",
+            ".*is ahead of the last record LSN.*",
+            ".*is past the branch point.*",
+        ]
+    )
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    env.neon_cli.create_branch("test_timeline_truncate")
+    endpoint = env.endpoints.create_start("test_timeline_truncate")
+    timeline_id = TimelineId(endpoint.safe_psql("SHOW neon.timeline_id")[0][0])
+
+    endpoint.safe_psql("CREATE TABLE t (x int)")
+    endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 100000) g")
+    early_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    endpoint.safe_psql("UPDATE t SET x = x + 1")
+    truncate_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    endpoint.safe_psql("UPDATE t SET x = x + 1")
+    last_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    # Not ahead of the last record LSN.
+    with pytest.raises(PageserverApiException) as e:
+        pageserver_http.timeline_truncate(tenant_id, timeline_id, last_lsn + 0x1000000)
+    assert e.value.status_code == 400
+
+    # Not past the branch point of a child timeline.
+    child_timeline_id = env.neon_cli.create_branch(
+        "test_timeline_truncate_child", "test_timeline_truncate", ancestor_start_lsn=early_lsn
+    )
+    with pytest.raises(PageserverApiException) as e:
+        pageserver_http.timeline_truncate(tenant_id, timeline_id, truncate_lsn)
+    assert e.value.status_code == 400
+    timeline_delete_wait_completed(pageserver_http, tenant_id, child_timeline_id)
+
+    env.neon_cli.raw_cli(
+        [
+            "timeline",
+            "truncate",
+            "--tenant-id",
+            str(tenant_id),
+            "--timeline-id",
+            str(timeline_id),
+            "--lsn",
+            str(truncate_lsn),
+        ]
+    )
+    detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+    assert Lsn(detail["latest_gc_cutoff_lsn"]) == truncate_lsn
+
+    # The data after the LSN is all there.
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(sum(range(3, 100003)),)]
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT sum(x) FROM t") == [(sum(range(3, 100003)),)]
+
+    # Branching at the LSN still works, before it no longer does.
+    env.neon_cli.create_branch(
+        "test_timeline_truncate_at", "test_timeline_truncate", ancestor_start_lsn=truncate_lsn
+    )
+    endpoint_at = env.endpoints.create_start("test_timeline_truncate_at")
+    assert endpoint_at.safe_psql("SELECT sum(x) FROM t") == [(sum(range(2, 100002)),)]
+    with pytest.raises(Exception, match="invalid branch start lsn"):
+        env.neon_cli.create_branch(
+            "test_timeline_truncate_before",
+            "test_timeline_truncate",
+            ancestor_start_lsn=early_lsn,
+        )
@@ -84,3 +84,61 @@ def test_twophase(neon_simple_env: NeonEnv):
     # Only one committed insert is visible on the original branch
     cur.execute("SELECT * FROM foo")
     assert cur.fetchall() == [("three",)]
+
+
+#
+# Test that a prepared transaction with subtransactions is restored on a branch,
+# and that the CLOG of its subtransactions is updated when it's committed there.
+#
+def test_twophase_subxacts(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.neon_cli.create_branch("test_twophase_subxacts", "empty")
+    endpoint = env.endpoints.create_start(
+        "test_twophase_subxacts", config_lines=["max_prepared_transactions=5"]
+    )
+
+    conn = endpoint.connect()
+    cur = conn.cursor()
+
+    cur.execute("CREATE TABLE foo (i int)")
+    cur.execute("CREATE TABLE dropped (i int)")
+
+    # Prepare a transaction with subtransactions, that also drops a table
+    cur.execute("BEGIN")
+    cur.execute("INSERT INTO foo VALUES (1)")
+    for i in range(2, 6):
+        cur.execute(f"SAVEPOINT s{i}")
+        cur.execute(f"INSERT INTO foo VALUES ({i})")
+    cur.execute("DROP TABLE dropped")
+    cur.execute("PREPARE TRANSACTION 'with_subxacts'")
+
+    # Branch before the state data is checkpointed into a pg_twophase file, so
+    # that the file of the branch is built from the XLOG_XACT_PREPARE record
+    fork_at_current_lsn(env, endpoint, "test_twophase_subxacts_prepared", "test_twophase_subxacts")
+
+    endpoint2 = env.endpoints.create_start(
+        "test_twophase_subxacts_prepared",
+        config_lines=["max_prepared_transactions=5"],
+    )
+    twophase_files = os.listdir(endpoint2.pg_twophase_dir_path())
+    log.info(twophase_files)
+    assert len(twophase_files) == 1
+
+    cur2 = endpoint2.connect().cursor()
+    cur2.execute("SELECT gid FROM pg_prepared_xacts")
+    assert cur2.fetchall() == [("with_subxacts",)]
+    cur2.execute("COMMIT PREPARED 'with_subxacts'")
+
+    # Restart, to read the CLOG from the pageserver
+    endpoint2.stop()
+    endpoint2.start()
+    cur2 = endpoint2.connect().cursor()
+    cur2.execute("SELECT i FROM foo ORDER BY i")
+    assert cur2.fetchall() == [(i,) for i in range(1, 6)]
+    cur2.execute("SELECT count(*) FROM pg_class WHERE relname = 'dropped'")
+    assert cur2.fetchone() == (0,)
+
+    # The transaction is still prepared on the original branch
+    cur.execute("SELECT i FROM foo")
+    assert cur.fetchall() == []
+    cur.execute("ROLLBACK PREPARED 'with_subxacts'")
@@ -0,0 +1,36 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.types import Lsn
+
+
+def test_wait_lsn(neon_simple_env: NeonEnv):
+    """
+    Wait for the pageserver to ingest and flush the WAL of a timeline, through
+    the HTTP API and through libpq.
+    """
+    env = neon_simple_env
+    pageserver_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 10000) g")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+
+    pageserver_http.timeline_wait_lsn(tenant_id, timeline_id, lsn, timeout_ms=60000)
+    detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+    assert Lsn(detail["last_record_lsn"]) >= lsn
+    assert Lsn(detail["disk_consistent_lsn"]) >= lsn
+
+    # Nothing writes that far ahead.
+    future_lsn = Lsn(int(lsn) + 1024 * 1024 * 1024)
+    with pytest.raises(PageserverApiException, match="timed out") as exc:
+        pageserver_http.timeline_wait_lsn(tenant_id, timeline_id, future_lsn, timeout_ms=100)
+    assert exc.value.status_code == 408
+
+    endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 10000) g")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    env.pageserver.safe_psql(f"wait_lsn {tenant_id} {timeline_id} {lsn} 60000")
+    detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+    assert Lsn(detail["disk_consistent_lsn"]) >= lsn
@@ -14,9 +14,12 @@ from pathlib import Path
 from typing import Any, List, Optional
 
 import psycopg2
+import psycopg2.extras
 import pytest
+import requests
 from fixtures.broker import NeonBroker
 from fixtures.log_helper import log
+from fixtures.metrics import parse_metrics
 from fixtures.neon_fixtures import (
     Endpoint,
     NeonEnv,
@@ -27,6 +30,7 @@ from fixtures.neon_fixtures import (
     Safekeeper,
     SafekeeperHttpClient,
     SafekeeperPort,
+    wait_for_last_flush_lsn,
 )
 from fixtures.pageserver.utils import (
     timeline_delete_wait_completed,
@@ -41,7 +45,7 @@ from fixtures.remote_storage import (
     available_remote_storages,
 )
 from fixtures.types import Lsn, TenantId, TimelineId
-from fixtures.utils import get_dir_size, query_scalar, start_in_background
+from fixtures.utils import get_dir_size, query_scalar, start_in_background, wait_until
 
 
 def wait_lsn_force_checkpoint(
@@ -398,6 +402,180 @@ def test_wal_removal(neon_env_builder: NeonEnvBuilder, auth_enabled: bool):
     )
 
 
+# Test that safekeepers keep the configured margin of WAL before the point up
+# to which nothing needs it, and report that point.
+def test_wal_removal_retention_margin(neon_env_builder: NeonEnvBuilder):
+    seg_size = 16 * 1024 * 1024
+    neon_env_builder.num_safekeepers = 2
+    neon_env_builder.enable_local_fs_remote_storage()
+    neon_env_builder.safekeepers_wal_retention_margin = seg_size
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_wal_removal_retention_margin")
+    endpoint = env.endpoints.create_start("test_wal_removal_retention_margin")
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE t(key int primary key, value text)",
+            "INSERT INTO t SELECT generate_series(1,400000), 'payload'",
+        ]
+    )
+    tenant_id = TenantId(endpoint.safe_psql("show neon.tenant_id")[0][0])
+    timeline_id = TimelineId(endpoint.safe_psql("show neon.timeline_id")[0][0])
+    wait_lsn_force_checkpoint(tenant_id, timeline_id, endpoint, env.pageserver)
+
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+    # Pretend WAL is offloaded to s3.
+    http_cli.record_safekeeper_info(tenant_id, timeline_id, {"backup_lsn": "FFFFFFFF/FEFFFFFF"})
+
+    wait(
+        lambda: http_cli.timeline_remove_wal(tenant_id, timeline_id) > 1,
+        "first segment get removed",
+        wait_f=lambda: log.info(
+            f"waiting for segments removal, horizon: {http_cli.timeline_wal_horizon(tenant_id, timeline_id)}"
+        ),
+    )
+
+    horizon = http_cli.timeline_wal_horizon(tenant_id, timeline_id)
+    log.info(f"WAL horizon: {horizon}")
+    assert horizon["retention_margin"] == seg_size
+    needed_lsn = min(
+        int(Lsn(horizon["remote_consistent_lsn"])),
+        int(Lsn(horizon["peer_horizon_lsn"])),
+        int(Lsn(horizon["backup_lsn"])),
+    )
+    horizon_lsn = int(Lsn(horizon["horizon_lsn"]))
+    assert horizon_lsn == max(needed_lsn - seg_size, 0)
+
+    # The segment with the WAL at the horizon is kept.
+    horizon_segno = horizon_lsn // seg_size
+    assert 1 < horizon["last_removed_segno"] <= horizon_segno
+    horizon_segname = f"00000001{horizon_segno // 256:08X}{horizon_segno % 256:08X}"
+    horizon_segment = os.path.join(sk.data_dir(), str(tenant_id), str(timeline_id), horizon_segname)
+    assert os.path.exists(horizon_segment)
+
+
+# Test that the persisted election state of a timeline can be looked at, on a
+# running safekeeper as well as on a stopped one.
+def test_dump_control_file(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_dump_control_file")
+    endpoint = env.endpoints.create_start("test_dump_control_file")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    # Elect another walproposer, in a higher term.
+    endpoint.stop().start()
+    endpoint.safe_psql("INSERT INTO t VALUES (1, 'payload')")
+    endpoint.stop()
+
+    sk = env.safekeepers[0]
+    state = sk.http_client().timeline_control_file(tenant_id, timeline_id)
+    log.info(f"control file of safekeeper {sk.id}: {state}")
+    acceptor_state = state["acceptor_state"]
+    assert acceptor_state["term"] >= 2
+    assert len(acceptor_state["term_history"]) >= 2
+    assert acceptor_state["term_history"][-1]["term"] == acceptor_state["term"]
+
+    assert env.neon_cli.safekeeper_dump_control_file(sk.id, tenant_id, timeline_id) == state
+    sk.stop()
+    assert env.neon_cli.safekeeper_dump_control_file(sk.id, tenant_id, timeline_id) == state
+
+
+def test_tenant_list(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 1
+    env = neon_env_builder.init_start()
+
+    tenant_id, _ = env.neon_cli.create_tenant()
+    timeline_ids = [
+        env.neon_cli.create_branch(f"test_tenant_list_{i}", tenant_id=tenant_id) for i in range(2)
+    ]
+    for i in range(2):
+        endpoint = env.endpoints.create_start(f"test_tenant_list_{i}", tenant_id=tenant_id)
+        endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+        endpoint.stop()
+
+    tenants = env.safekeepers[0].http_client().tenant_list()
+    log.info(f"tenants on the safekeeper: {tenants}")
+    tenant = next(t for t in tenants if t["tenant_id"] == str(tenant_id))
+    assert sorted(tenant["timelines"]) == sorted(str(t) for t in timeline_ids)
+    # At least one WAL segment per timeline.
+    assert tenant["wal_disk_usage_bytes"] >= 2 * 16 * 1024 * 1024
+    assert tenant["wal_quota_bytes"] is None
+
+
+def test_tenant_wal_quota(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 1
+    quota = 2 * 16 * 1024 * 1024
+    neon_env_builder.safekeepers_tenant_wal_quota = quota
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_tenant_wal_quota")
+    endpoint = env.endpoints.create_start("test_tenant_wal_quota")
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+
+    with closing(endpoint.connect()) as conn:
+        with conn.cursor() as cur:
+            cur.execute("CREATE TABLE t(key int primary key, value text)")
+            # Writes more WAL than fits into the quota; the safekeeper holds
+            # off accepting it, so the statement doesn't get committed.
+            cur.execute("SET statement_timeout = '10s'")
+            try:
+                cur.execute("INSERT INTO t SELECT generate_series(1,500000), 'payload'")
+            except psycopg2.Error as e:
+                log.info(f"insert failed as expected: {e}")
+            flush_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+
+    metrics = parse_metrics(http_cli.get_metrics_str())
+    assert metrics.query_one("safekeeper_wal_quota_waits_total").value > 0
+    assert http_cli.timeline_status(tenant_id, timeline_id).flush_lsn < flush_lsn
+
+    tenant = next(t for t in http_cli.tenant_list() if t["tenant_id"] == str(tenant_id))
+    assert tenant["wal_quota_bytes"] == quota
+    # The batch that went over the quota is still accepted.
+    assert tenant["wal_disk_usage_bytes"] > quota
+
+    endpoint.stop()
+
+
+def test_ingest_rate_limit(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 1
+    rate = 1024 * 1024
+    burst = 256 * 1024
+    neon_env_builder.safekeepers_ingest_rate_limit = rate
+    neon_env_builder.safekeepers_ingest_burst = burst
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_ingest_rate_limit")
+    endpoint = env.endpoints.create_start("test_ingest_rate_limit")
+    http_cli = env.safekeepers[0].http_client()
+
+    with closing(endpoint.connect()) as conn:
+        with conn.cursor() as cur:
+            cur.execute("CREATE TABLE t(key int primary key, value text)")
+            start_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+            started_at = time.monotonic()
+            # Commits wait for the safekeeper, which accepts the WAL no faster
+            # than the rate limit.
+            cur.execute("INSERT INTO t SELECT generate_series(1,50000), 'payload'")
+            elapsed = time.monotonic() - started_at
+            end_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_flush_lsn()"))
+
+    wal_size = end_lsn - start_lsn
+    log.info(f"wrote {wal_size} bytes of WAL in {elapsed:.2f}s")
+    assert wal_size > 2 * burst
+    # Leave some slack for the burst and the WAL already written before.
+    assert elapsed >= 0.8 * (wal_size - burst) / rate
+
+    metrics = parse_metrics(http_cli.get_metrics_str())
+    assert metrics.query_one("safekeeper_ingest_throttled_seconds_total").value > 0
+
+    endpoint.stop()
+
+
 # Wait for something, defined as f() returning True, raising error if this
 # doesn't happen without timeout seconds, and calling wait_f while waiting.
 def wait(f, desc, timeout=30, wait_f=None):
@@ -869,6 +1047,204 @@ def test_timeline_status(neon_env_builder: NeonEnvBuilder, auth_enabled: bool):
     assert debug_dump_1["config"]["id"] == env.safekeepers[0].id
 
 
+# Test the peers in the timeline status, and WAL removal through the HTTP API
+# and neon_local.
+def test_timeline_status_peers_and_remove_wal(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    # to advance remote_consistent_lsn
+    neon_env_builder.enable_local_fs_remote_storage()
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_timeline_status_peers")
+    endpoint = env.endpoints.create_start("test_timeline_status_peers")
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE t(key int primary key, value text)",
+            "INSERT INTO t SELECT generate_series(1,200000), 'payload'",
+        ]
+    )
+    tenant_id = TenantId(endpoint.safe_psql("show neon.tenant_id")[0][0])
+    timeline_id = TimelineId(endpoint.safe_psql("show neon.timeline_id")[0][0])
+    wait_lsn_force_checkpoint(tenant_id, timeline_id, endpoint, env.pageserver)
+
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+    other_ids = {other.id for other in env.safekeepers[1:]}
+    wait(
+        lambda: set(http_cli.timeline_status(tenant_id, timeline_id).peer_ids) == other_ids,
+        "peers heard through the broker",
+    )
+
+    out = env.neon_cli.safekeeper_status(sk.id, tenant_id, timeline_id).stdout
+    log.info(f"neon_local safekeeper status: {out}")
+    assert f"commit_lsn: {http_cli.timeline_status(tenant_id, timeline_id).commit_lsn}" in out
+    for other_id in other_ids:
+        assert f"peer {other_id}:" in out
+
+    tt = {"tenant_id": str(tenant_id), "timeline_id": str(timeline_id)}
+    metrics = parse_metrics(http_cli.get_metrics_str())
+    assert metrics.query_one("safekeeper_disk_usage_bytes", filter=tt).value > 0
+    assert metrics.query_one("safekeeper_commit_lsn_lag_bytes", filter=tt).value >= 0
+
+    # Pretend WAL is offloaded to s3, then remove it without waiting for the
+    # WAL removal task.
+    http_cli.record_safekeeper_info(tenant_id, timeline_id, {"backup_lsn": "FFFFFFFF/FEFFFFFF"})
+    first_segment = os.path.join(
+        sk.data_dir(), str(tenant_id), str(timeline_id), "000000010000000000000001"
+    )
+    wait(
+        lambda: http_cli.timeline_remove_wal(tenant_id, timeline_id) > 1,
+        "first segment get removed",
+    )
+    assert not os.path.exists(first_segment)
+    status = http_cli.timeline_status(tenant_id, timeline_id)
+    assert status.last_removed_segno > 1
+
+
+# Test that a safekeeper which missed WAL while it was down pulls it from its
+# peers, without a compute.
+def test_peer_recovery(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_peer_recovery")
+    endpoint = env.endpoints.create_start("test_peer_recovery")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+
+    lagging = env.safekeepers[2]
+    lagging.stop()
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100000), 'payload'")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    endpoint.stop()
+    lagging.start()
+
+    http_cli = lagging.http_client()
+
+    def caught_up():
+        flush_lsn = http_cli.timeline_status(tenant_id, timeline_id).flush_lsn
+        log.info(f"lagging safekeeper has WAL up to {flush_lsn}, waiting for {lsn}")
+        return flush_lsn >= lsn
+
+    wait(caught_up, "lagging safekeeper pulls the WAL from its peers")
+    metrics = parse_metrics(http_cli.get_metrics_str())
+    assert metrics.query_one("safekeeper_peer_recovered_wal_bytes_total").value > 0
+
+    # The compute starts fine on the recovered quorum.
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(100000,)]
+
+
+# Test that with a group commit window, safekeepers flush several AppendRequests
+# at once.
+def test_group_commit_window(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.safekeepers_group_commit_window = "20ms"
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_group_commit_window")
+    endpoint = env.endpoints.create_start("test_group_commit_window")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100000), 'payload'")
+
+    for sk in env.safekeepers:
+        metrics = parse_metrics(sk.http_client().get_metrics_str())
+        flushes = metrics.query_one("safekeeper_flush_batch_size_count").value
+        flushed_requests = metrics.query_one("safekeeper_flush_batch_size_sum").value
+        log.info(f"safekeeper {sk.id} flushed {flushed_requests} AppendRequests in {flushes} flushes")
+        assert flushes > 0
+        assert flushed_requests > flushes
+
+
+def test_commit_latency_metrics(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_commit_latency_metrics")
+    endpoint = env.endpoints.create_start("test_commit_latency_metrics")
+    endpoint.safe_psql("CREATE EXTENSION remotexact")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    for i in range(100):
+        endpoint.safe_psql(f"INSERT INTO t VALUES ({i}, 'payload')")
+
+    for sk in env.safekeepers:
+        metrics = parse_metrics(sk.http_client().get_metrics_str())
+        for phase in ["flush", "quorum_ack", "total"]:
+            count = metrics.query_one(
+                "safekeeper_commit_latency_seconds_count", {"phase": phase}
+            ).value
+            log.info(f"safekeeper {sk.id} timed {count} commits in phase {phase}")
+            assert count > 0
+
+    rows = endpoint.safe_psql(
+        "SELECT phase, count, sum FROM commit_latency() WHERE le = 'Infinity'"
+    )
+    counts = {phase: (count, total) for phase, count, total in rows}
+    log.info(f"commit latency on the compute: {counts}")
+    assert counts["total"][0] >= 100
+    assert counts["local"][0] == counts["total"][0]
+    # No transaction touched other regions.
+    assert counts["remote_ack"][0] == 0
+
+
+def test_walproposer_timing(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.walproposer_config = {
+        "election_timeout": "30s",
+        "heartbeat_interval": "500ms",
+        "max_inflight_bytes": 64 * 1024,
+    }
+    neon_env_builder.safekeepers_walproposer_keepalive_interval = "200ms"
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_walproposer_timing")
+    endpoint = env.endpoints.create_start("test_walproposer_timing")
+    assert endpoint.safe_psql("SHOW neon.safekeeper_connect_timeout")[0][0] == "30s"
+    assert endpoint.safe_psql("SHOW neon.safekeeper_reconnect_timeout")[0][0] == "500ms"
+    assert endpoint.safe_psql("SHOW neon.safekeeper_max_inflight_bytes")[0][0] == "64kB"
+
+    # WAL still gets through with little of it in flight at a time.
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100000), 'payload'")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    for sk in env.safekeepers:
+        wait(
+            partial(is_flush_lsn_caught_up, sk, tenant_id, timeline_id, lsn),
+            f"safekeeper {sk.id} to catch up with {lsn}",
+        )
+
+
+def test_wal_compression(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that the pageserver asking for compressed WAL gets it from the
+    safekeepers and ingests it.
+    """
+    neon_env_builder.pageserver_config_override = "wal_receiver_compression=true"
+    env = neon_env_builder.init_start()
+
+    tenant_id, timeline_id = env.neon_cli.create_tenant()
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100000), 'payload'")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    uncompressed = 0
+    compressed = 0
+    for sk in env.safekeepers:
+        metrics = parse_metrics(sk.http_client().get_metrics_str())
+        for sample in metrics.query_all("safekeeper_compressed_wal_bytes_total"):
+            if sample.labels["stage"] == "uncompressed":
+                uncompressed += sample.value
+            else:
+                compressed += sample.value
+    log.info(f"safekeepers compressed {uncompressed} bytes of WAL to {compressed} bytes")
+    assert 0 < compressed < uncompressed
+
+    # The pages are reconstructed from the WAL the pageserver decompressed.
+    endpoint.stop_and_destroy()
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 100000
+
+
 class DummyConsumer(object):
     def __call__(self, msg):
         pass
@@ -912,6 +1288,335 @@ def test_start_replication_term(neon_env_builder: NeonEnvBuilder):
         assert "failed to acquire term 3" in str(excinfo.value)
 
 
+def test_region_wal_filter(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that the WAL streamed to a region leaves out the records modifying
+    databases its filter doesn't list, without changing the LSNs.
+    """
+    # Region 1 only needs the relations of template1.
+    neon_env_builder.safekeepers_region_wal_filters = ["1:*/1"]
+    env = neon_env_builder.init_start()
+
+    tenant_id, timeline_id = env.neon_cli.create_tenant()
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,10000), 'payload'")
+
+    sk = env.safekeepers[0]
+    tli_status = sk.http_client().timeline_status(tenant_id, timeline_id)
+
+    class WalCollector:
+        def __init__(self, start_lsn: Lsn, end_lsn: Lsn):
+            self.next_lsn = int(start_lsn)
+            self.end_lsn = int(end_lsn)
+
+        def __call__(self, msg):
+            # Filtered WAL still comes in contiguously.
+            assert msg.data_start == self.next_lsn
+            self.next_lsn += len(msg.payload)
+            if self.next_lsn >= self.end_lsn:
+                raise psycopg2.extras.StopReplication
+
+    conn_opts = {
+        "host": "127.0.0.1",
+        "options": f"-c timeline_id={timeline_id} tenant_id={tenant_id}",
+        "port": sk.port.pg,
+        "connection_factory": psycopg2.extras.PhysicalReplicationConnection,
+    }
+    sk_pg_conn = psycopg2.connect(**conn_opts)  # type: ignore
+    with sk_pg_conn.cursor() as cur:
+        cur.start_replication_expert(
+            f"START_REPLICATION {tli_status.timeline_start_lsn} (region='1')"
+        )
+        collector = WalCollector(tli_status.timeline_start_lsn, tli_status.commit_lsn)
+        cur.consume_stream(collector)
+
+    metrics = parse_metrics(sk.http_client().get_metrics_str())
+    filtered = metrics.query_one("safekeeper_filtered_wal_bytes_total").value
+    log.info(f"safekeeper filtered {filtered} bytes of WAL")
+    assert filtered > 0
+
+    # Other regions and the endpoint still get all of the WAL.
+    endpoint.stop_and_destroy()
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+
+
+def test_standby_replication_slot(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that a standby can stream WAL from a safekeeper like from a primary,
+    through a physical replication slot that keeps the WAL it still needs
+    until the slot is dropped.
+    """
+    neon_env_builder.num_safekeepers = 1
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_standby_replication_slot")
+    endpoint = env.endpoints.create_start("test_standby_replication_slot")
+
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+    assert http_cli.timeline_wal_horizon(tenant_id, timeline_id)["slots_restart_lsn"] is None
+
+    conn_opts = {
+        "host": "127.0.0.1",
+        "options": f"-c timeline_id={timeline_id} tenant_id={tenant_id}",
+        "port": sk.port.pg,
+        "connection_factory": psycopg2.extras.PhysicalReplicationConnection,
+    }
+
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            cur.create_replication_slot("dr_standby")
+            with pytest.raises(psycopg2.Error, match="already exists"):
+                cur.create_replication_slot("dr_standby")
+    horizon = http_cli.timeline_wal_horizon(tenant_id, timeline_id)
+    slot_start_lsn = Lsn(horizon["slots_restart_lsn"])
+
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,10000), 'payload'")
+    tli_status = http_cli.timeline_status(tenant_id, timeline_id)
+
+    # Only timeline 1 exists, and only the slots that were created.
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            with pytest.raises(psycopg2.Error, match="not in this server's history"):
+                cur.start_replication(start_lsn=str(slot_start_lsn), timeline=2)
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            with pytest.raises(psycopg2.Error, match="does not exist"):
+                cur.start_replication(slot_name="unknown", start_lsn=str(slot_start_lsn))
+
+    class Standby:
+        def __init__(self, cur):
+            self.cur = cur
+            self.flushed = slot_start_lsn
+
+        def __call__(self, msg):
+            # The slot holds the WAL the standby hasn't reported as flushed yet.
+            horizon = http_cli.timeline_wal_horizon(tenant_id, timeline_id)
+            slot_lsn = Lsn(horizon["slots_restart_lsn"])
+            assert slot_start_lsn <= slot_lsn <= self.flushed
+
+            self.flushed = Lsn(msg.data_start + len(msg.payload))
+            self.cur.send_feedback(flush_lsn=int(self.flushed), force=True)
+            if self.flushed >= tli_status.commit_lsn:
+                raise psycopg2.extras.StopReplication
+
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            cur.start_replication(
+                slot_name="dr_standby",
+                start_lsn=str(slot_start_lsn),
+                timeline=1,
+            )
+            cur.consume_stream(Standby(cur))
+
+    # The slot outlives its standby and the safekeeper restart.
+    sk.stop()
+    sk.start()
+    horizon = http_cli.timeline_wal_horizon(tenant_id, timeline_id)
+    assert Lsn(horizon["slots_restart_lsn"]) >= slot_start_lsn
+
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            cur.drop_replication_slot("dr_standby")
+            with pytest.raises(psycopg2.Error, match="does not exist"):
+                cur.drop_replication_slot("dr_standby")
+    assert http_cli.timeline_wal_horizon(tenant_id, timeline_id)["slots_restart_lsn"] is None
+
+
+def test_standby_reconnect_after_wal_removal(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that a standby streaming through a replication slot catches up after
+    being disconnected while the safekeeper removed the WAL nothing else
+    needed anymore.
+    """
+    seg_size = 16 * 1024 * 1024
+    neon_env_builder.num_safekeepers = 1
+    neon_env_builder.enable_local_fs_remote_storage()
+    env = neon_env_builder.init_start()
+
+    env.neon_cli.create_branch("test_standby_reconnect_after_wal_removal")
+    primary = env.endpoints.create_start("test_standby_reconnect_after_wal_removal")
+    tenant_id = TenantId(primary.safe_psql("show neon.tenant_id")[0][0])
+    timeline_id = TimelineId(primary.safe_psql("show neon.timeline_id")[0][0])
+    primary.safe_psql_many(
+        [
+            "CREATE TABLE t(key int primary key, value text)",
+            "INSERT INTO t SELECT generate_series(1,400000), 'payload'",
+        ]
+    )
+
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+    conn_opts = {
+        "host": "127.0.0.1",
+        "options": f"-c timeline_id={timeline_id} tenant_id={tenant_id}",
+        "port": sk.port.pg,
+        "connection_factory": psycopg2.extras.PhysicalReplicationConnection,
+    }
+    with closing(psycopg2.connect(**conn_opts)) as sk_pg_conn:  # type: ignore
+        with sk_pg_conn.cursor() as cur:
+            cur.create_replication_slot("dr_standby")
+
+    standby = env.endpoints.new_replica_start(
+        origin=primary,
+        endpoint_id="standby",
+        config_lines=["primary_slot_name = 'dr_standby'"],
+    )
+
+    def wait_standby_caught_up():
+        lsn = Lsn(query_scalar(primary.connect().cursor(), "SELECT pg_current_wal_flush_lsn()"))
+        wait(
+            lambda: Lsn(standby.safe_psql("SELECT pg_last_wal_replay_lsn()")[0][0]) >= lsn,
+            "standby to catch up",
+        )
+
+    primary.safe_psql("INSERT INTO t SELECT generate_series(400001,400100), 'payload'")
+    wait_standby_caught_up()
+
+    # Disconnect the standby.
+    standby.safe_psql_many(["ALTER SYSTEM SET primary_conninfo = ''", "SELECT pg_reload_conf()"])
+    wait(
+        lambda: standby.safe_psql("SELECT count(*) FROM pg_stat_wal_receiver")[0][0] == 0,
+        "standby to disconnect",
+    )
+    slot_lsn = Lsn(http_cli.timeline_wal_horizon(tenant_id, timeline_id)["slots_restart_lsn"])
+
+    # Write a few segments the pageserver and the WAL backup are done with.
+    primary.safe_psql("INSERT INTO t SELECT generate_series(400101,800000), 'payload'")
+    wait_lsn_force_checkpoint(tenant_id, timeline_id, primary, env.pageserver)
+    http_cli.record_safekeeper_info(tenant_id, timeline_id, {"backup_lsn": "FFFFFFFF/FEFFFFFF"})
+
+    # The WAL before the slot goes, the WAL the standby misses stays, also
+    # across a safekeeper restart.
+    wait(
+        lambda: http_cli.timeline_remove_wal(tenant_id, timeline_id) > 1,
+        "WAL before the slot to be removed",
+    )
+    sk.stop()
+    sk.start()
+    last_removed_segno = http_cli.timeline_remove_wal(tenant_id, timeline_id)
+    assert last_removed_segno <= int(slot_lsn) // seg_size
+    horizon = http_cli.timeline_wal_horizon(tenant_id, timeline_id)
+    assert Lsn(horizon["slots_restart_lsn"]) <= slot_lsn
+
+    # Reconnect the standby, which gets the WAL it missed through the slot.
+    standby.safe_psql_many(["ALTER SYSTEM RESET primary_conninfo", "SELECT pg_reload_conf()"])
+    wait_standby_caught_up()
+    assert standby.safe_psql("SELECT count(*) FROM t")[0][0] == 800000
+    wait(
+        lambda: Lsn(http_cli.timeline_wal_horizon(tenant_id, timeline_id)["slots_restart_lsn"])
+        > slot_lsn,
+        "slot to advance",
+    )
+
+
+def test_safekeeper_failpoints(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that a safekeeper killed right after it fsynced the WAL, but before it
+    acknowledged it, recovers and catches up with the others.
+    """
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+    # Safekeepers are built with the same features as the pageserver.
+    env.pageserver.is_testing_enabled_or_skip()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_safekeeper_failpoints")
+    endpoint = env.endpoints.create_start("test_safekeeper_failpoints")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+
+    sk = env.safekeepers[0]
+    http_cli = sk.http_client()
+
+    with pytest.raises(http_cli.HTTPError, match="Failed to configure failpoints"):
+        http_cli.configure_failpoints(("sk-after-wal-fsync", "bogus"))
+
+    http_cli.configure_failpoints(("sk-after-wal-fsync", "exit"))
+    # The two other safekeepers are still a quorum.
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,1000), 'payload'")
+
+    def sk_is_down() -> bool:
+        try:
+            http_cli.check_status()
+            return False
+        except requests.exceptions.ConnectionError:
+            return True
+
+    wait(sk_is_down, "safekeeper to exit on failpoint")
+    sk.stop()
+    sk.start()
+
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1001,2000), 'payload'")
+    lsn = Lsn(query_scalar(endpoint.connect().cursor(), "SELECT pg_current_wal_flush_lsn()"))
+    wait(
+        lambda: http_cli.timeline_status(tenant_id, timeline_id).flush_lsn >= lsn,
+        "restarted safekeeper to catch up",
+    )
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(2000,)]
+
+
+def test_safekeeper_sync(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that `neon_local safekeeper sync` finds the divergent tail a safekeeper
+    is left with when it got WAL that was never committed, and truncates it.
+    """
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_safekeeper_sync")
+    endpoint = env.endpoints.create_start("test_safekeeper_sync")
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+
+    res = env.neon_cli.safekeeper_sync(tenant_id, timeline_id)
+    assert "diverged" not in res.stdout
+
+    # Safekeeper 1 alone gets WAL that can't be committed.
+    env.safekeepers[1].stop()
+    env.safekeepers[2].stop()
+    with closing(endpoint.connect()) as conn:
+        with conn.cursor() as cur:
+            cur.execute("SET statement_timeout = '5s'")
+            try:
+                cur.execute("INSERT INTO t SELECT generate_series(1,1000), 'uncommitted'")
+            except psycopg2.extensions.QueryCanceledError:
+                pass
+    endpoint.stop()
+    env.safekeepers[0].stop()
+
+    # The others elect a new walproposer, which writes different WAL.
+    env.safekeepers[1].start()
+    env.safekeepers[2].start()
+    endpoint.start()
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100), 'committed'")
+    endpoint.stop()
+    env.safekeepers[0].start()
+
+    res = env.neon_cli.safekeeper_sync(tenant_id, timeline_id)
+    log.info(f"safekeeper sync:\n{res.stdout}")
+    assert "safekeeper 1: " in res.stdout
+    assert [line for line in res.stdout.splitlines() if "diverged at" in line] == [
+        line for line in res.stdout.splitlines() if line.startswith("safekeeper 1: ")
+    ]
+    assert "run with --truncate" in res.stdout
+
+    res = env.neon_cli.safekeeper_sync(tenant_id, timeline_id, truncate=True)
+    assert "safekeeper 1: truncated" in res.stdout
+    res = env.neon_cli.safekeeper_sync(tenant_id, timeline_id)
+    assert "diverged" not in res.stdout
+
+    # The truncated safekeeper can be a part of a quorum again.
+    env.safekeepers[2].stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(100,)]
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(101,200), 'committed'")
+    assert endpoint.safe_psql("SELECT count(*) FROM t") == [(200,)]
+
+
 # Test auth on WAL service (postgres protocol) ports.
 def test_sk_auth(neon_env_builder: NeonEnvBuilder):
     neon_env_builder.auth_enabled = True
@@ -1178,6 +1883,45 @@ def test_replace_safekeeper(neon_env_builder: NeonEnvBuilder):
     show_statuses(env.safekeepers, tenant_id, timeline_id)
 
 
+# Replace a failed safekeeper of a running endpoint, going through the joint
+# configuration of the old and the new set, without restarting Postgres.
+def test_change_safekeepers_of_running_endpoint(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 4
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_change_safekeepers")
+
+    endpoint = env.endpoints.create("test_change_safekeepers")
+    endpoint.active_safekeepers = [1, 2, 3]
+    endpoint.start()
+    endpoint.safe_psql("CREATE TABLE t(key int primary key, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,100000), 'payload'")
+    start_time = endpoint.safe_psql("SELECT pg_postmaster_start_time()")[0][0]
+
+    log.info("Stop sk1 (simulate failure) and replace it with sk4")
+    env.safekeepers[0].stop(immediate=True)
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(100001,200000), 'payload'")
+    env.neon_cli.safekeeper_add_to_timeline(4, tenant_id, timeline_id)
+    env.neon_cli.safekeeper_remove_from_timeline(1, tenant_id, timeline_id)
+    endpoint.active_safekeepers = [2, 3, 4]
+
+    sk4_status = env.safekeepers[3].http_client().timeline_status(tenant_id, timeline_id)
+    assert sk4_status.flush_lsn > Lsn(0)
+    sk_ports = [str(sk.port.pg_tenant_only) for sk in env.safekeepers[1:]]
+    neon_safekeepers = endpoint.safe_psql("SHOW neon.safekeepers")[0][0]
+    assert sorted(addr.split(":")[1] for addr in neon_safekeepers.split(",")) == sorted(sk_ports)
+
+    log.info("Stop sk2 to require quorum of sk3 and sk4")
+    env.safekeepers[1].stop(immediate=True)
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(200001,300000), 'payload'")
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 300000
+    assert endpoint.safe_psql("SELECT pg_postmaster_start_time()")[0][0] == start_time
+
+    log.info("Restart the endpoint, it keeps the new safekeepers")
+    endpoint.stop().start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 300000
+
+
 # We have `wal_keep_size=0`, so postgres should trim WAL once it's broadcasted
 # to all safekeepers. This test checks that compute WAL can fit into small number
 # of WAL segments.
@@ -1447,3 +2191,86 @@ def test_pull_timeline(neon_env_builder: NeonEnvBuilder):
 
     execute_payload(endpoint)
     show_statuses(env.safekeepers, tenant_id, timeline_id)
+
+
+def test_timeline_snapshot_restore(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 4
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_timeline_snapshot_restore")
+
+    env.safekeepers[3].stop()
+    endpoint = env.endpoints.create("test_timeline_snapshot_restore")
+    endpoint.active_safekeepers = [1, 2, 3]
+    endpoint.start()
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE t(key int, value text)",
+            "INSERT INTO t SELECT generate_series(1,100000), 'payload'",
+        ]
+    )
+    endpoint.stop_and_destroy()
+
+    donor = env.safekeepers[0].http_client()
+    donor_status = donor.timeline_status(tenant_id, timeline_id)
+    snapshot = donor.timeline_snapshot(tenant_id, timeline_id)
+    log.info(f"snapshot of {len(snapshot)} bytes at {donor_status.flush_lsn}")
+
+    log.info("Seed new safekeeper 4 from the snapshot of safekeeper 1")
+    env.safekeepers[3].start()
+    http_cli = env.safekeepers[3].http_client()
+    acked = http_cli.timeline_restore(tenant_id, timeline_id, snapshot)
+    assert Lsn(acked["flush_lsn"]) == donor_status.flush_lsn
+    status = http_cli.timeline_status(tenant_id, timeline_id)
+    assert status.flush_lsn == donor_status.flush_lsn
+    assert status.commit_lsn == donor_status.commit_lsn
+
+    # The timeline exists now.
+    with pytest.raises(requests.HTTPError, match="409"):
+        http_cli.timeline_restore(tenant_id, timeline_id, snapshot)
+
+    log.info("Use quorum of sk3 and sk4")
+    env.safekeepers[0].stop()
+    env.safekeepers[1].stop()
+    endpoint.create("test_timeline_snapshot_restore")
+    endpoint.active_safekeepers = [1, 3, 4]
+    endpoint.start()
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,1000), 'payload'")
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 101000
+    endpoint.stop()
+
+
+def test_endpoint_list_quorum_lsn(neon_env_builder: NeonEnvBuilder):
+    """
+    Check the quorum LSN, and how many safekeepers acknowledged the commit LSN,
+    that `neon_local endpoint list` reports for an endpoint: with all of its
+    safekeepers up, and with one of them stopped.
+    """
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g AS x FROM generate_series(1, 1000) g")
+    committed_lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+
+    def check_quorum_status(safekeepers: List[Safekeeper]):
+        output = env.neon_cli.raw_cli(["endpoint", "list", "--tenant-id", str(tenant_id)]).stdout
+        row = next(line for line in output.splitlines() if line.startswith("ep-"))
+        quorum_lsn, acked = row.split()[-2:]
+
+        # The highest LSN flushed by two of the three safekeepers.
+        statuses = sorted(
+            (sk.http_client().timeline_status(tenant_id, timeline_id) for sk in safekeepers),
+            key=lambda status: status.flush_lsn,
+            reverse=True,
+        )
+        assert Lsn(quorum_lsn) == statuses[1].flush_lsn
+        assert Lsn(quorum_lsn) >= committed_lsn
+        assert acked == f"({len(safekeepers)}/3)"
+
+    wait_until(20, 0.5, lambda: check_quorum_status(env.safekeepers))
+
+    env.safekeepers[0].stop()
+    wait_until(20, 0.5, lambda: check_quorum_status(env.safekeepers[1:]))
@@ -76,6 +76,7 @@ fn analyze_trace<R: std::io::Read>(mut reader: R) {
             PagestreamFeMessage::GetSlruPage(_) => {}
             PagestreamFeMessage::GetLatestLsn(_) => {}
             PagestreamFeMessage::DbSize(_) => {}
+            PagestreamFeMessage::GetPages(_) => {}
         };
     }
 